@@ -0,0 +1,93 @@
+//! Counts the allocations per frame when sending large messages over a unix socket.
+//!
+//! Run with `cargo bench --bench frame_alloc --features uds-transport`.
+//!
+//! Every message is serialized once on the sending side, and read into a buffer and
+//! deserialized on the receiving side, so each frame needs about three times the message
+//! size. Anything above that is overhead of the framing.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use futures::{SinkExt, StreamExt};
+use quic_rpc::transport::{
+    uds::{UdsConnector, UdsListener},
+    Connector, Listener,
+};
+
+/// Allocator that counts the allocations and the allocated bytes
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const MESSAGE_SIZE: usize = 1024 * 1024;
+const FRAMES: usize = 64;
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("frame_alloc.sock");
+    let listener = UdsListener::<Vec<u8>, ()>::bind(&path)?;
+    let connector = UdsConnector::<(), Vec<u8>>::new(path);
+    let (mut send, _recv) = connector.open().await?;
+    let (_send, mut recv) = listener.accept().await?;
+    // create the messages up front, so they are not counted
+    let messages = (0..FRAMES)
+        .map(|i| vec![i as u8; MESSAGE_SIZE])
+        .collect::<Vec<_>>();
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let bytes = BYTES.load(Ordering::Relaxed);
+    let sender = async {
+        for message in messages {
+            send.send(message).await?;
+        }
+        send.flush().await
+    };
+    let receiver = async {
+        for _ in 0..FRAMES {
+            let message = recv.next().await.expect("sender is alive")?;
+            assert_eq!(message.len(), MESSAGE_SIZE);
+        }
+        std::io::Result::Ok(())
+    };
+    let (sent, received) = futures::future::join(sender, receiver).await;
+    sent?;
+    received?;
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+    let bytes = BYTES.load(Ordering::Relaxed) - bytes;
+
+    println!("{FRAMES} frames of {MESSAGE_SIZE} bytes");
+    println!(
+        "allocations per frame: {:.1}",
+        allocations as f64 / FRAMES as f64
+    );
+    println!(
+        "allocated bytes per frame: {:.2} x message size",
+        bytes as f64 / (FRAMES * MESSAGE_SIZE) as f64
+    );
+    Ok(())
+}
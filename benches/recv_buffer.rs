@@ -0,0 +1,136 @@
+//! Measures the throughput of receiving a stream of messages of different sizes over a
+//! loopback quinn connection.
+//!
+//! Run with `cargo bench --bench recv_buffer --features quinn-transport`.
+//!
+//! The receive side of the quinn transport grows its read size with the frames it sees,
+//! so messages that are larger than a single small read, but not huge, take fewer reads.
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::Arc,
+    time::Instant,
+};
+
+use derive_more::{From, TryInto};
+use futures::StreamExt;
+use quic_rpc::{
+    message::{Msg, ServerStreaming, ServerStreamingMsg},
+    transport::quinn::{QuinnConnector, QuinnListener},
+    RpcClient, RpcServer, Service,
+};
+use quinn::{
+    crypto::rustls::{QuicClientConfig, QuicServerConfig},
+    rustls, ClientConfig, Endpoint, ServerConfig,
+};
+use serde::{Deserialize, Serialize};
+
+/// Send `count` chunks of `size` bytes
+#[derive(Debug, Serialize, Deserialize)]
+struct Download {
+    size: usize,
+    count: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Chunk(Vec<u8>);
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum DownloadRequest {
+    Download(Download),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum DownloadResponse {
+    Chunk(Chunk),
+}
+
+#[derive(Debug, Clone)]
+struct DownloadService;
+
+impl Service for DownloadService {
+    type Req = DownloadRequest;
+    type Res = DownloadResponse;
+}
+
+impl Msg<DownloadService> for Download {
+    type Pattern = ServerStreaming;
+}
+
+impl ServerStreamingMsg<DownloadService> for Download {
+    type Response = Chunk;
+}
+
+/// Bytes received for every message size
+const TOTAL: usize = 256 * 1024 * 1024;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let (server_config, server_cert) = configure_server()?;
+    let server = Endpoint::server(
+        server_config,
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)),
+    )?;
+    let addr = server.local_addr()?;
+    let mut client = Endpoint::client("0.0.0.0:0".parse()?)?;
+    client.set_default_client_config(configure_client(&server_cert)?);
+
+    let server = RpcServer::<DownloadService, _>::new(QuinnListener::new(server)?);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        let DownloadRequest::Download(req) = req;
+        chan.server_streaming(req, (), |_, Download { size, count }| {
+            futures::stream::repeat(Chunk(vec![7u8; size])).take(count)
+        })
+        .await
+    });
+    let connector =
+        QuinnConnector::<DownloadResponse, DownloadRequest>::new(client, addr, "localhost".into());
+    let client = RpcClient::<DownloadService, _>::new(connector);
+
+    for size in [1024, 16 * 1024, 48 * 1024, 1024 * 1024] {
+        let count = TOTAL / size;
+        let start = Instant::now();
+        let mut chunks = client.server_streaming(Download { size, count }).await?;
+        let mut received = 0;
+        while let Some(chunk) = chunks.next().await {
+            assert_eq!(chunk?.0.len(), size);
+            received += 1;
+        }
+        assert_eq!(received, count);
+        let elapsed = start.elapsed();
+        println!(
+            "{count} messages of {size} bytes in {elapsed:?}, {:.0} MiB/s",
+            TOTAL as f64 / elapsed.as_secs_f64() / (1024.0 * 1024.0)
+        );
+    }
+    Ok(())
+}
+
+fn configure_server() -> anyhow::Result<(ServerConfig, Vec<u8>)> {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
+    let cert_der = cert.cert.der();
+    let priv_key = rustls::pki_types::PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
+    let crypto = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])?
+    .with_no_client_auth()
+    .with_single_cert(vec![cert_der.clone()], priv_key.into())?;
+    let config = ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(crypto)?));
+    Ok((config, cert_der.to_vec()))
+}
+
+fn configure_client(server_cert: &[u8]) -> anyhow::Result<ClientConfig> {
+    let mut certs = rustls::RootCertStore::empty();
+    certs.add(rustls::pki_types::CertificateDer::from(
+        server_cert.to_vec(),
+    ))?;
+    let crypto = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])?
+    .with_root_certificates(certs)
+    .with_no_client_auth();
+    Ok(ClientConfig::new(Arc::new(QuicClientConfig::try_from(
+        crypto,
+    )?)))
+}
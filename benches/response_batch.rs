@@ -0,0 +1,81 @@
+//! Measures the throughput of a server streaming call with many tiny responses over a
+//! unix socket, with and without batching the responses.
+//!
+//! Run with `cargo bench --bench response_batch --features uds-transport`.
+use std::time::Instant;
+
+use derive_more::{From, TryInto};
+use futures::StreamExt;
+use quic_rpc::{
+    message::{Msg, ServerStreaming, ServerStreamingMsg},
+    transport::uds::{UdsConnector, UdsListener},
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Count(u64);
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum CountRequest {
+    Count(Count),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum CountResponse {
+    Item(u64),
+}
+
+#[derive(Debug, Clone)]
+struct CountService;
+
+impl Service for CountService {
+    type Req = CountRequest;
+    type Res = CountResponse;
+}
+
+impl Msg<CountService> for Count {
+    type Pattern = ServerStreaming;
+}
+
+impl ServerStreamingMsg<CountService> for Count {
+    type Response = u64;
+}
+
+const ITEMS: u64 = 1_000_000;
+
+async fn run(batch: usize) -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("response_batch.sock");
+    let listener = UdsListener::bind(&path)?;
+    let server = RpcServer::<CountService, _>::new(listener).with_response_batch(batch);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        let CountRequest::Count(req) = req;
+        chan.server_streaming(req, (), |_, Count(n)| futures::stream::iter(0..n))
+            .await
+    });
+    let client = RpcClient::<CountService, _>::new(UdsConnector::new(path));
+
+    let start = Instant::now();
+    let mut items = client.server_streaming(Count(ITEMS)).await?;
+    let mut received = 0;
+    while let Some(item) = items.next().await {
+        item?;
+        received += 1;
+    }
+    assert_eq!(received, ITEMS);
+    let elapsed = start.elapsed();
+    println!(
+        "batch {batch:>4}: {ITEMS} items in {elapsed:?}, {:.0} items/s",
+        ITEMS as f64 / elapsed.as_secs_f64()
+    );
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    for batch in [1, 16, 256] {
+        run(batch).await?;
+    }
+    Ok(())
+}
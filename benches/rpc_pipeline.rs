@@ -0,0 +1,149 @@
+//! Compares sequential rpc calls with calls pipelined on one substream, over flume and
+//! over a loopback quinn connection.
+//!
+//! Run with `cargo bench --bench rpc_pipeline --features flume-transport,quinn-transport`.
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use derive_more::{From, TryInto};
+use futures::StreamExt;
+use quic_rpc::{
+    message::RpcMsg,
+    transport::{
+        flume,
+        quinn::{QuinnConnector, QuinnListener},
+        Connector, Listener,
+    },
+    RpcClient, RpcServer, Service,
+};
+use quinn::{
+    crypto::rustls::{QuicClientConfig, QuicServerConfig},
+    rustls, ClientConfig, Endpoint, ServerConfig,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Echo(u64);
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum EchoRequest {
+    Echo(Echo),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum EchoResponse {
+    Echoed(u64),
+}
+
+#[derive(Debug, Clone)]
+struct EchoService;
+
+impl Service for EchoService {
+    type Req = EchoRequest;
+    type Res = EchoResponse;
+}
+
+impl RpcMsg<EchoService> for Echo {
+    type Response = u64;
+}
+
+const CALLS: u64 = 20_000;
+
+/// Serves pipelined echo calls on `listener`, which also accepts plain rpc calls
+fn serve<L: Listener<EchoService>>(listener: L) -> tokio_util::task::AbortOnDropHandle<()> {
+    RpcServer::<EchoService, _>::new(listener).spawn_accept_loop(|req, chan| async move {
+        let EchoRequest::Echo(req) = req;
+        chan.rpc_pipeline(req, (), |_, Echo(n)| async move { n })
+            .await
+    })
+}
+
+async fn run<C: Connector<EchoService>>(name: &str, connector: C) -> anyhow::Result<()> {
+    let client = RpcClient::<EchoService, _>::new(connector);
+    // connect before measuring
+    client.rpc(Echo(0)).await?;
+
+    let start = Instant::now();
+    for i in 0..CALLS {
+        assert_eq!(client.rpc(Echo(i)).await?, i);
+    }
+    let sequential = report(name, "sequential", start);
+
+    let start = Instant::now();
+    let mut responses = client.rpc_pipeline((0..CALLS).map(Echo)).await?;
+    let mut i = 0;
+    while let Some(res) = responses.next().await {
+        assert_eq!(res?, i);
+        i += 1;
+    }
+    assert_eq!(i, CALLS);
+    let pipelined = report(name, "pipelined", start);
+    println!(
+        "{name} speedup: {:.1}x",
+        sequential.as_secs_f64() / pipelined.as_secs_f64()
+    );
+    Ok(())
+}
+
+fn report(name: &str, mode: &str, start: Instant) -> Duration {
+    let elapsed = start.elapsed();
+    println!(
+        "{name} {mode}: {CALLS} calls in {elapsed:?}, {:.0} calls/s",
+        CALLS as f64 / elapsed.as_secs_f64()
+    );
+    elapsed
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel(1);
+    let _flume_server = serve(listener);
+    run("flume", connector).await?;
+
+    let (server_config, server_cert) = configure_server()?;
+    let server = Endpoint::server(
+        server_config,
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)),
+    )?;
+    let addr = server.local_addr()?;
+    let mut client = Endpoint::client("0.0.0.0:0".parse()?)?;
+    client.set_default_client_config(configure_client(&server_cert)?);
+    let _quinn_server = serve(QuinnListener::new(server)?);
+    let connector =
+        QuinnConnector::<EchoResponse, EchoRequest>::new(client, addr, "localhost".into());
+    run("quinn", connector).await?;
+    Ok(())
+}
+
+fn configure_server() -> anyhow::Result<(ServerConfig, Vec<u8>)> {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
+    let cert_der = cert.cert.der();
+    let priv_key = rustls::pki_types::PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
+    let crypto = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])?
+    .with_no_client_auth()
+    .with_single_cert(vec![cert_der.clone()], priv_key.into())?;
+    let config = ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(crypto)?));
+    Ok((config, cert_der.to_vec()))
+}
+
+fn configure_client(server_cert: &[u8]) -> anyhow::Result<ClientConfig> {
+    let mut certs = rustls::RootCertStore::empty();
+    certs.add(rustls::pki_types::CertificateDer::from(
+        server_cert.to_vec(),
+    ))?;
+    let crypto = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])?
+    .with_root_certificates(certs)
+    .with_no_client_auth();
+    Ok(ClientConfig::new(Arc::new(QuicClientConfig::try_from(
+        crypto,
+    )?)))
+}
@@ -0,0 +1,153 @@
+//! Measures the calls per second of tiny rpc calls over a loopback quinn connection,
+//! with and without reusing substreams.
+//!
+//! Run with `cargo bench --bench substream_pool --features quinn-transport`.
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use derive_more::{From, TryInto};
+use quic_rpc::{
+    message::RpcMsg,
+    transport::quinn::{QuinnConnector, QuinnListener, SubstreamPool},
+    RpcClient, RpcServer, Service,
+};
+use quinn::{
+    crypto::rustls::{QuicClientConfig, QuicServerConfig},
+    rustls, ClientConfig, Endpoint, ServerConfig,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Echo(u64);
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum EchoRequest {
+    Echo(Echo),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum EchoResponse {
+    Echoed(u64),
+}
+
+#[derive(Debug, Clone)]
+struct EchoService;
+
+impl Service for EchoService {
+    type Req = EchoRequest;
+    type Res = EchoResponse;
+}
+
+impl RpcMsg<EchoService> for Echo {
+    type Response = u64;
+}
+
+const CALLS: u64 = 20_000;
+const TASKS: u64 = 16;
+
+/// Returns the time of the sequential and the concurrent calls
+async fn run(
+    client: &Endpoint,
+    addr: SocketAddr,
+    pool: Option<SubstreamPool>,
+) -> anyhow::Result<(Duration, Duration)> {
+    let name = if pool.is_some() { "pooled" } else { "fresh" };
+    let mut connector =
+        QuinnConnector::<EchoResponse, EchoRequest>::new(client.clone(), addr, "localhost".into());
+    if let Some(pool) = pool {
+        connector = connector.with_substream_pool(pool);
+    }
+    let client = RpcClient::<EchoService, _>::new(connector);
+    // connect before measuring
+    client.rpc(Echo(0)).await?;
+
+    let start = Instant::now();
+    for i in 0..CALLS {
+        assert_eq!(client.rpc(Echo(i)).await?, i);
+    }
+    let sequential = report(name, "sequential", start);
+
+    let start = Instant::now();
+    let tasks = (0..TASKS).map(|task| {
+        let client = client.clone();
+        tokio::spawn(async move {
+            for i in (task..CALLS).step_by(TASKS as usize) {
+                assert_eq!(client.rpc(Echo(i)).await?, i);
+            }
+            anyhow::Ok(())
+        })
+    });
+    for task in futures::future::join_all(tasks).await {
+        task??;
+    }
+    let concurrent = report(name, "concurrent", start);
+    Ok((sequential, concurrent))
+}
+
+fn report(name: &str, mode: &str, start: Instant) -> Duration {
+    let elapsed = start.elapsed();
+    println!(
+        "{name} {mode}: {CALLS} calls in {elapsed:?}, {:.0} calls/s",
+        CALLS as f64 / elapsed.as_secs_f64()
+    );
+    elapsed
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let (server_config, server_cert) = configure_server()?;
+    let server = Endpoint::server(
+        server_config,
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 0)),
+    )?;
+    let addr = server.local_addr()?;
+    let mut client = Endpoint::client("0.0.0.0:0".parse()?)?;
+    client.set_default_client_config(configure_client(&server_cert)?);
+
+    let server = RpcServer::<EchoService, _>::new(QuinnListener::new(server)?);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        let EchoRequest::Echo(req) = req;
+        chan.rpc(req, (), |_, Echo(n)| async move { n }).await
+    });
+    let fresh = run(&client, addr, None).await?;
+    let pooled = run(&client, addr, Some(SubstreamPool::new(TASKS as usize))).await?;
+    println!(
+        "speedup of pooled: {:.2}x sequential, {:.2}x concurrent",
+        fresh.0.as_secs_f64() / pooled.0.as_secs_f64(),
+        fresh.1.as_secs_f64() / pooled.1.as_secs_f64(),
+    );
+    Ok(())
+}
+
+fn configure_server() -> anyhow::Result<(ServerConfig, Vec<u8>)> {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
+    let cert_der = cert.cert.der();
+    let priv_key = rustls::pki_types::PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
+    let crypto = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])?
+    .with_no_client_auth()
+    .with_single_cert(vec![cert_der.clone()], priv_key.into())?;
+    let config = ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(crypto)?));
+    Ok((config, cert_der.to_vec()))
+}
+
+fn configure_client(server_cert: &[u8]) -> anyhow::Result<ClientConfig> {
+    let mut certs = rustls::RootCertStore::empty();
+    certs.add(rustls::pki_types::CertificateDer::from(
+        server_cert.to_vec(),
+    ))?;
+    let crypto = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])?
+    .with_root_certificates(certs)
+    .with_no_client_auth();
+    Ok(ClientConfig::new(Arc::new(QuicClientConfig::try_from(
+        crypto,
+    )?)))
+}
@@ -0,0 +1,99 @@
+//! Send a fixed batch of updates with a bidi call, and receive the responses as a stream.
+use derive_more::{From, TryInto};
+use futures_lite::{Stream, StreamExt};
+use quic_rpc::{
+    message::{BidiStreaming, BidiStreamingMsg, Msg},
+    server::RpcServerError,
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+/// Score the documents that follow as updates against a query
+#[derive(Debug, Serialize, Deserialize)]
+struct Score {
+    query: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Document(String);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Scored {
+    index: usize,
+    hits: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum ScoreRequest {
+    Score(Score),
+    Document(Document),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum ScoreResponse {
+    Scored(Scored),
+}
+
+#[derive(Debug, Clone)]
+struct ScoreService;
+
+impl Service for ScoreService {
+    type Req = ScoreRequest;
+    type Res = ScoreResponse;
+}
+
+impl Msg<ScoreService> for Score {
+    type Pattern = BidiStreaming;
+}
+
+impl BidiStreamingMsg<ScoreService> for Score {
+    type Update = Document;
+    type Response = Scored;
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Scorer;
+
+impl Scorer {
+    fn score(
+        self,
+        req: Score,
+        documents: impl Stream<Item = Document> + Send + 'static,
+    ) -> impl Stream<Item = Scored> + Send + 'static {
+        documents
+            .enumerate()
+            .map(move |(index, Document(text))| Scored {
+                index,
+                hits: text.matches(req.query.as_str()).count(),
+            })
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::<ScoreService, _>::new(listener);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        match req {
+            ScoreRequest::Score(req) => chan.bidi_streaming(req, Scorer, Scorer::score).await,
+            ScoreRequest::Document(_) => Err(RpcServerError::UnexpectedStartMessage),
+        }
+    });
+
+    let client = RpcClient::<ScoreService, _>::new(connector);
+    let documents = [
+        "the quick brown fox",
+        "the lazy dog and the fox",
+        "nothing to see here",
+    ];
+    let query = Score {
+        query: "the".to_string(),
+    };
+    let updates = documents.iter().map(|text| Document(text.to_string()));
+    let mut scores = client.batch_server_streaming(query, updates).await?;
+    while let Some(Scored { index, hits }) = scores.next().await.transpose()? {
+        println!("{hits} hits in {:?}", documents[index]);
+    }
+    Ok(())
+}
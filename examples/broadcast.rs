@@ -0,0 +1,81 @@
+//! Relay one stream of events to several server streaming clients.
+use derive_more::{From, TryInto};
+use futures::StreamExt;
+use quic_rpc::{
+    broadcast::{Broadcaster, LagPolicy, Lagged},
+    message::{Msg, ServerStreaming, ServerStreamingMsg},
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Subscribe;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Event(u64);
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum EventRequest {
+    Subscribe(Subscribe),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum EventResponse {
+    Event(Result<Event, Lagged>),
+}
+
+#[derive(Debug, Clone)]
+struct EventService;
+
+impl Service for EventService {
+    type Req = EventRequest;
+    type Res = EventResponse;
+}
+
+impl Msg<EventService> for Subscribe {
+    type Pattern = ServerStreaming;
+}
+
+impl ServerStreamingMsg<EventService> for Subscribe {
+    type Response = Result<Event, Lagged>;
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let events = Broadcaster::new(16, LagPolicy::DropOldest);
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::<EventService, _>::new(listener);
+    let source = events.clone();
+    let _server_handle = server.spawn_accept_loop(move |req, chan| {
+        let source = source.clone();
+        async move {
+            match req {
+                EventRequest::Subscribe(req) => {
+                    chan.server_streaming(req, source, |source, _| source.subscribe())
+                        .await
+                }
+            }
+        }
+    });
+
+    // three clients subscribe to the same events
+    let client = RpcClient::<EventService, _>::new(connector);
+    let mut subscriptions = Vec::new();
+    for _ in 0..3 {
+        subscriptions.push(client.server_streaming(Subscribe).await?);
+    }
+    while events.receiver_count() < 3 {
+        tokio::task::yield_now().await;
+    }
+
+    // the producer runs only once
+    for i in 0..5 {
+        events.send(Event(i));
+    }
+    for (n, subscription) in subscriptions.into_iter().enumerate() {
+        let received = subscription.take(5).collect::<Vec<_>>().await;
+        println!("client {n}: {received:?}");
+    }
+    Ok(())
+}
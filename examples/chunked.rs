@@ -0,0 +1,100 @@
+//! Transfer a large buffer in chunks, and show the progress while it is received.
+use std::io::Write;
+
+use derive_more::{From, TryInto};
+use futures_lite::StreamExt;
+use quic_rpc::{
+    message::{Msg, ServerStreaming, ServerStreamingMsg},
+    pattern::chunked::{Chunk, Progress},
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+/// Request a buffer of the given size
+#[derive(Debug, Serialize, Deserialize)]
+struct Download(usize);
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum DownloadRequest {
+    Download(Download),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum DownloadResponse {
+    Chunk(Chunk),
+}
+
+#[derive(Debug, Clone)]
+struct DownloadService;
+
+impl Service for DownloadService {
+    type Req = DownloadRequest;
+    type Res = DownloadResponse;
+}
+
+impl Msg<DownloadService> for Download {
+    type Pattern = ServerStreaming;
+}
+
+impl ServerStreamingMsg<DownloadService> for Download {
+    type Response = Chunk;
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Store;
+
+impl Store {
+    async fn download(self, Download(size): Download) -> Vec<u8> {
+        (0..size).map(|i| i as u8).collect()
+    }
+}
+
+/// Draw a progress bar on the current line
+fn draw(progress: Progress) {
+    const WIDTH: usize = 40;
+    let filled = (progress.fraction() * WIDTH as f64) as usize;
+    print!(
+        "\r[{}{}] {}/{} bytes",
+        "#".repeat(filled),
+        " ".repeat(WIDTH - filled),
+        progress.transferred,
+        progress.size
+    );
+    std::io::stdout().flush().ok();
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::<DownloadService, _>::new(listener);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        match req {
+            DownloadRequest::Download(req) => {
+                chan.server_streaming_chunked(req, Store, 64 * 1024, Store::download)
+                    .await
+            }
+        }
+    });
+
+    let client = RpcClient::<DownloadService, _>::new(connector);
+    let size = 64 * 1024 * 1024;
+    let chunks = client.server_streaming_chunked(Download(size)).await?;
+    println!("downloading {} bytes", chunks.size());
+    let data = chunks.collect_with_progress(draw).await?;
+    println!();
+    assert_eq!(data.len(), size);
+    assert!(data.iter().enumerate().all(|(i, b)| *b == i as u8));
+
+    // dropping the stream cancels the transfer on the server
+    let mut chunks = client.server_streaming_chunked(Download(size)).await?;
+    while let Some(chunk) = chunks.next().await {
+        chunk?;
+        if chunks.progress().fraction() >= 0.25 {
+            break;
+        }
+    }
+    println!("cancelled after {} bytes", chunks.progress().transferred);
+    drop(chunks);
+    Ok(())
+}
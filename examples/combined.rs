@@ -0,0 +1,120 @@
+//! A single server that serves an in-process client via flume and a remote client via quinn.
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::Arc,
+};
+
+use derive_more::{From, TryInto};
+use quic_rpc::{
+    message::RpcMsg,
+    transport::{
+        combined::CombinedListener,
+        flume,
+        quinn::{QuinnConnector, QuinnListener},
+    },
+    RpcClient, RpcServer, Service,
+};
+use quinn::{
+    crypto::rustls::{QuicClientConfig, QuicServerConfig},
+    rustls, ClientConfig, Endpoint, ServerConfig,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Hello(String);
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum GreeterRequest {
+    Hello(Hello),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum GreeterResponse {
+    Hello(String),
+}
+
+#[derive(Debug, Clone)]
+struct GreeterService;
+
+impl Service for GreeterService {
+    type Req = GreeterRequest;
+    type Res = GreeterResponse;
+}
+
+impl RpcMsg<GreeterService> for Hello {
+    type Response = String;
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Greeter;
+
+impl Greeter {
+    async fn hello(self, req: Hello) -> String {
+        format!("Hello, {}!", req.0)
+    }
+}
+
+type Local = flume::FlumeListener<GreeterRequest, GreeterResponse>;
+type Remote = QuinnListener<GreeterRequest, GreeterResponse>;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12400));
+    let (server_config, server_cert) = configure_server()?;
+
+    // one listener for in-process and remote clients
+    let (local_listener, local_connector) = flume::channel(1);
+    let remote_listener = QuinnListener::new(Endpoint::server(server_config, addr)?)?;
+    let listener =
+        CombinedListener::<Local, Remote>::new(Some(local_listener), Some(remote_listener));
+    let server = RpcServer::<GreeterService, _>::new(listener);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        match req {
+            GreeterRequest::Hello(req) => chan.rpc(req, Greeter, Greeter::hello).await,
+        }
+    });
+
+    let local = RpcClient::<GreeterService, _>::new(local_connector);
+    let mut endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
+    endpoint.set_default_client_config(configure_client(&server_cert)?);
+    let remote_connector = QuinnConnector::new(endpoint, addr, "localhost".into());
+    let remote = RpcClient::<GreeterService, _>::new(remote_connector);
+
+    let (a, b) = tokio::join!(
+        local.rpc(Hello("local".into())),
+        remote.rpc(Hello("remote".into())),
+    );
+    println!("{}", a?);
+    println!("{}", b?);
+    Ok(())
+}
+
+fn configure_server() -> anyhow::Result<(ServerConfig, Vec<u8>)> {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
+    let cert_der = cert.cert.der();
+    let priv_key = rustls::pki_types::PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
+    let crypto = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])?
+    .with_no_client_auth()
+    .with_single_cert(vec![cert_der.clone()], priv_key.into())?;
+    let config = ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(crypto)?));
+    Ok((config, cert_der.to_vec()))
+}
+
+fn configure_client(server_cert: &[u8]) -> anyhow::Result<ClientConfig> {
+    let mut certs = rustls::RootCertStore::empty();
+    certs.add(rustls::pki_types::CertificateDer::from(
+        server_cert.to_vec(),
+    ))?;
+    let crypto = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])?
+    .with_root_certificates(certs)
+    .with_no_client_auth();
+    Ok(ClientConfig::new(Arc::new(QuicClientConfig::try_from(
+        crypto,
+    )?)))
+}
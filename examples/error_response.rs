@@ -0,0 +1,139 @@
+//! Answer failed handlers with a structured error, instead of closing the channel.
+//!
+//! An outdated client sends updates the server no longer understands. The server can not
+//! downcast them, and the hook of the server turns that into an error response.
+use derive_more::{From, TryInto};
+use futures_lite::StreamExt;
+use futures_util::SinkExt;
+use quic_rpc::{
+    message::{ClientStreaming, ClientStreamingMsg, Msg},
+    server::RpcServerError,
+    transport::{flume, ConnectionErrors},
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+/// Add up the amounts that follow as updates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Add;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Amount(u64);
+
+/// How older clients sent the amounts, no longer accepted by the server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LegacyAmount(String);
+
+#[derive(Debug, Serialize, Deserialize)]
+enum ErrorCode {
+    BadUpdate,
+}
+
+/// The error that clients see
+#[derive(Debug, Serialize, Deserialize)]
+struct ApiError {
+    code: ErrorCode,
+    message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum CounterRequest {
+    Add(Add),
+    Amount(Amount),
+    LegacyAmount(LegacyAmount),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum CounterResponse {
+    Add(Result<u64, ApiError>),
+}
+
+#[derive(Debug, Clone)]
+struct CounterService;
+
+impl Service for CounterService {
+    type Req = CounterRequest;
+    type Res = CounterResponse;
+}
+
+impl Msg<CounterService> for Add {
+    type Pattern = ClientStreaming;
+}
+
+impl ClientStreamingMsg<CounterService> for Add {
+    type Update = Amount;
+    type Response = Result<u64, ApiError>;
+}
+
+/// The same service, as an outdated client sees it
+#[derive(Debug, Clone)]
+struct LegacyCounterService;
+
+impl Service for LegacyCounterService {
+    type Req = CounterRequest;
+    type Res = CounterResponse;
+}
+
+impl Msg<LegacyCounterService> for Add {
+    type Pattern = ClientStreaming;
+}
+
+impl ClientStreamingMsg<LegacyCounterService> for Add {
+    type Update = LegacyAmount;
+    type Response = Result<u64, ApiError>;
+}
+
+/// Map errors of handlers to the error response of the request
+fn on_error<C: ConnectionErrors>(
+    req: &CounterRequest,
+) -> Option<impl FnOnce(&RpcServerError<C>) -> Option<CounterResponse>> {
+    match req {
+        CounterRequest::Add(_) => Some(|cause: &RpcServerError<C>| {
+            let code = match cause {
+                RpcServerError::UnexpectedUpdateMessage => ErrorCode::BadUpdate,
+                // the client is gone, or it is a transport error it sees anyway
+                _ => return None,
+            };
+            let err = ApiError {
+                code,
+                message: cause.to_string(),
+            };
+            Some(CounterResponse::Add(Err(err)))
+        }),
+        CounterRequest::Amount(_) | CounterRequest::LegacyAmount(_) => None,
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::<CounterService, _>::new(listener).with_error_response(on_error);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        match req {
+            CounterRequest::Add(req) => {
+                chan.client_streaming(req, (), |_, _, amounts| async move {
+                    Ok(amounts.fold(0, |sum, Amount(n)| sum + n).await)
+                })
+                .await
+            }
+            CounterRequest::Amount(_) | CounterRequest::LegacyAmount(_) => {
+                Err(RpcServerError::UnexpectedStartMessage)
+            }
+        }
+    });
+
+    let client = RpcClient::<CounterService, _>::new(connector.clone());
+    let (mut send, recv) = client.client_streaming(Add).await?;
+    for n in 1..=3 {
+        send.send(Amount(n)).await?;
+    }
+    drop(send);
+    println!("current client: {:?}", recv.await?);
+
+    let legacy = RpcClient::<LegacyCounterService, _>::new(connector);
+    let (mut send, recv) = legacy.client_streaming(Add).await?;
+    send.send(LegacyAmount("1".to_string())).await?;
+    drop(send);
+    println!("outdated client: {:?}", recv.await?);
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Send the responses of a server streaming request from several worker tasks.
+use std::time::Duration;
+
+use derive_more::{From, TryInto};
+use futures::StreamExt;
+use quic_rpc::{
+    message::{Msg, ServerStreaming, ServerStreamingMsg},
+    server::ResponseSender,
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+/// Search two sources for the given word
+#[derive(Debug, Serialize, Deserialize)]
+struct Search(String);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Hit {
+    source: String,
+    line: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum SearchRequest {
+    Search(Search),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum SearchResponse {
+    Hit(Hit),
+}
+
+#[derive(Debug, Clone)]
+struct SearchService;
+
+impl Service for SearchService {
+    type Req = SearchRequest;
+    type Res = SearchResponse;
+}
+
+impl Msg<SearchService> for Search {
+    type Pattern = ServerStreaming;
+}
+
+impl ServerStreamingMsg<SearchService> for Search {
+    type Response = Hit;
+}
+
+/// Search one source, pushing hits as they are found
+async fn worker(
+    source: &'static str,
+    lines: &'static [&'static str],
+    word: String,
+    hits: ResponseSender<Hit>,
+) {
+    for line in lines {
+        // pretend that reading a line takes a while
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        if !line.contains(&word) {
+            continue;
+        }
+        let hit = Hit {
+            source: source.to_string(),
+            line: line.to_string(),
+        };
+        if hits.send(hit).await.is_err() {
+            // the client is gone, no need to keep searching
+            return;
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Searcher;
+
+impl Searcher {
+    async fn search(self, Search(word): Search, hits: ResponseSender<Hit>) {
+        const FRUIT: &[&str] = &["apple pie", "banana bread", "apple juice"];
+        const TREES: &[&str] = &["apple tree", "oak", "pine", "crab apple"];
+        // each worker gets its own clone, the stream ends once both are done
+        tokio::spawn(worker("fruit", FRUIT, word.clone(), hits.clone()));
+        tokio::spawn(worker("trees", TREES, word, hits));
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::<SearchService, _>::new(listener);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        match req {
+            SearchRequest::Search(req) => {
+                chan.server_streaming_with_sender(req, Searcher, Searcher::search)
+                    .await
+            }
+        }
+    });
+
+    let client = RpcClient::<SearchService, _>::new(connector);
+    let mut hits = client.server_streaming(Search("apple".into())).await?;
+    let mut count = 0;
+    while let Some(hit) = hits.next().await {
+        let hit = hit?;
+        println!("{}: {}", hit.source, hit.line);
+        count += 1;
+    }
+    assert_eq!(count, 4);
+    Ok(())
+}
@@ -0,0 +1,88 @@
+//! Mount the rpc endpoint on a route of an existing http2 server, next to a normal route.
+//!
+//! With axum, the service can be mounted using `Router::new().route_service("/rpc", service)`.
+use std::{convert::Infallible, net::SocketAddr};
+
+use derive_more::{From, TryInto};
+use hyper::{
+    service::{make_service_fn, service_fn, Service},
+    Body, Request, Response, Server,
+};
+use quic_rpc::{
+    message::RpcMsg,
+    transport::hyper::{ChannelConfig, HyperConnector, HyperListener},
+    RpcClient, RpcServer, Service as RpcService,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Hello(String);
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum GreeterRequest {
+    Hello(Hello),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum GreeterResponse {
+    Hello(String),
+}
+
+#[derive(Debug, Clone)]
+struct GreeterService;
+
+impl RpcService for GreeterService {
+    type Req = GreeterRequest;
+    type Res = GreeterResponse;
+}
+
+impl RpcMsg<GreeterService> for Hello {
+    type Response = String;
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Greeter;
+
+impl Greeter {
+    async fn hello(self, req: Hello) -> String {
+        format!("Hello, {}!", req.0)
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let addr: SocketAddr = "127.0.0.1:3100".parse()?;
+
+    // the listener is fed by the service instead of binding a socket
+    let (listener, rpc_service) = HyperListener::service(ChannelConfig::default());
+    let server = RpcServer::<GreeterService, _>::new(listener);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        match req {
+            GreeterRequest::Hello(req) => chan.rpc(req, Greeter, Greeter::hello).await,
+        }
+    });
+
+    // the http server routes `/rpc` to the rpc service, and everything else to a normal handler
+    let make_service = make_service_fn(move |_| {
+        let rpc_service = rpc_service.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let mut rpc_service = rpc_service.clone();
+                async move {
+                    match req.uri().path() {
+                        "/rpc" => rpc_service.call(req).await,
+                        _ => Ok(Response::new(Body::from("not an rpc"))),
+                    }
+                }
+            }))
+        }
+    });
+    // the rpc client only speaks http2
+    let http_server = Server::bind(&addr).http2_only(true).serve(make_service);
+    tokio::spawn(http_server);
+
+    let connector = HyperConnector::new("http://127.0.0.1:3100/rpc".parse()?);
+    let client = RpcClient::<GreeterService, _>::new(connector);
+    println!("{}", client.rpc(Hello("hyper".into())).await?);
+    Ok(())
+}
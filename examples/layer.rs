@@ -0,0 +1,156 @@
+//! A [Layer] that rejects requests without a valid token, before any handler runs.
+use std::result;
+
+use derive_more::{Display, From, TryInto};
+use quic_rpc::{
+    message::RpcMsg,
+    server::{Layer, Rejection},
+    transport::RemoteInfo,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+/// Common header of all requests
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Header {
+    token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Get {
+    header: Header,
+    key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Put {
+    header: Header,
+    key: String,
+    value: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum StoreRequest {
+    Get(Get),
+    Put(Put),
+}
+
+impl StoreRequest {
+    fn header(&self) -> &Header {
+        match self {
+            StoreRequest::Get(req) => &req.header,
+            StoreRequest::Put(req) => &req.header,
+        }
+    }
+}
+
+#[derive(Debug, Display, Serialize, Deserialize)]
+struct Unauthorized;
+
+impl std::error::Error for Unauthorized {}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum StoreResponse {
+    Get(result::Result<Option<String>, Unauthorized>),
+    Put(result::Result<(), Unauthorized>),
+}
+
+#[derive(Debug, Clone)]
+struct StoreService;
+
+impl Service for StoreService {
+    type Req = StoreRequest;
+    type Res = StoreResponse;
+}
+
+impl RpcMsg<StoreService> for Get {
+    type Response = result::Result<Option<String>, Unauthorized>;
+}
+
+impl RpcMsg<StoreService> for Put {
+    type Response = result::Result<(), Unauthorized>;
+}
+
+/// Rejects all requests that do not carry the expected token
+struct TokenAuth {
+    token: String,
+}
+
+impl Layer<StoreService> for TokenAuth {
+    fn wrap(&self, req: &StoreRequest, _info: &RemoteInfo) -> Result<(), Rejection<StoreService>> {
+        if req.header().token == self.token {
+            return Ok(());
+        }
+        // respond with the error variant of the response for this request
+        Err(match req {
+            StoreRequest::Get(_) => Rejection::respond(StoreResponse::Get(Err(Unauthorized))),
+            StoreRequest::Put(_) => Rejection::respond(StoreResponse::Put(Err(Unauthorized))),
+        })
+    }
+}
+
+/// Logs every request that passed the previous layers
+struct Log;
+
+impl Layer<StoreService> for Log {
+    fn wrap(&self, req: &StoreRequest, info: &RemoteInfo) -> Result<(), Rejection<StoreService>> {
+        println!("request from {:?}: {req:?}", info.addr);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Store;
+
+impl Store {
+    async fn get(self, req: Get) -> result::Result<Option<String>, Unauthorized> {
+        Ok(Some(format!("value of {}", req.key)))
+    }
+
+    async fn put(self, _req: Put) -> result::Result<(), Unauthorized> {
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let (server, client) = quic_rpc::transport::flume::channel(1);
+    let server = RpcServer::<StoreService, _>::new(server)
+        .with_layer(TokenAuth {
+            token: "secret".to_string(),
+        })
+        .with_layer(Log);
+    let _handle = server.spawn_accept_loop(|req, chan| async move {
+        match req {
+            StoreRequest::Get(req) => chan.rpc(req, Store, Store::get).await,
+            StoreRequest::Put(req) => chan.rpc(req, Store, Store::put).await,
+        }
+    });
+    let client = RpcClient::<StoreService, _>::new(client);
+
+    let valid = Header {
+        token: "secret".to_string(),
+    };
+    let res = client
+        .rpc(Get {
+            header: valid.clone(),
+            key: "a".to_string(),
+        })
+        .await?;
+    println!("valid token: {res:?}");
+    assert!(res.is_ok());
+
+    let invalid = Header {
+        token: "guess".to_string(),
+    };
+    let res = client
+        .rpc(Put {
+            header: invalid,
+            key: "a".to_string(),
+            value: "b".to_string(),
+        })
+        .await?;
+    println!("invalid token: {res:?}");
+    assert!(res.is_err());
+    Ok(())
+}
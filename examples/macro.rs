@@ -1,6 +1,7 @@
 mod store_rpc {
     use std::fmt::Debug;
 
+    pub use quic_rpc::reflection::{Describe, ServiceDescriptor};
     use quic_rpc::rpc_service;
     use serde::{Deserialize, Serialize};
 
@@ -40,19 +41,21 @@ mod store_rpc {
         Response = StoreResponse;
         Service = StoreService;
         CreateDispatch = create_store_dispatch;
+        CreateClient = create_store_client;
 
         Rpc put = Put, _ -> PutResponse;
         Rpc get = Get, _ -> GetResponse;
         ClientStreaming put_file = PutFile, PutFileUpdate -> PutFileResponse;
         ServerStreaming get_file = GetFile, _ -> GetFileResponse;
         BidiStreaming convert_file = ConvertFile, ConvertFileUpdate -> ConvertFileResponse;
+        Rpc describe = Describe, _ -> ServiceDescriptor;
     }
 }
 
 use async_stream::stream;
 use futures_lite::{Stream, StreamExt};
 use futures_util::SinkExt;
-use quic_rpc::{client::RpcClient, server::run_server_loop, transport::flume};
+use quic_rpc::{client::RpcClient, reflection::Reflect, server::run_server_loop, transport::flume};
 use store_rpc::*;
 
 #[derive(Clone)]
@@ -97,10 +100,14 @@ impl Store {
             }
         }
     }
+
+    async fn describe(self, _describe: Describe) -> ServiceDescriptor {
+        StoreService::descriptor()
+    }
 }
 
 create_store_dispatch!(Store, dispatch_store_request);
-// create_store_client!(StoreClient);
+create_store_client!(StoreClient);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -109,28 +116,28 @@ async fn main() -> anyhow::Result<()> {
         let target = Store;
         run_server_loop(StoreService, server, target, dispatch_store_request).await
     });
-    let client = RpcClient::<StoreService, _>::new(client);
+    let client = StoreClient(RpcClient::<StoreService, _>::new(client));
 
     // a rpc call
     for i in 0..3 {
         println!("a rpc call [{i}]");
         let client = client.clone();
         tokio::task::spawn(async move {
-            let res = client.rpc(Get([0u8; 32])).await;
+            let res = client.get(Get([0u8; 32])).await;
             println!("rpc res [{i}]: {res:?}");
         });
     }
 
     // server streaming call
     println!("a server streaming call");
-    let mut s = client.server_streaming(GetFile([0u8; 32])).await?;
+    let mut s = client.get_file(GetFile([0u8; 32])).await?;
     while let Some(res) = s.next().await {
         println!("streaming res: {res:?}");
     }
 
     // client streaming call
     println!("a client streaming call");
-    let (mut send, recv) = client.client_streaming(PutFile).await?;
+    let (mut send, recv) = client.put_file(PutFile).await?;
     tokio::task::spawn(async move {
         for i in 0..3 {
             send.send(PutFileUpdate(vec![i])).await.unwrap();
@@ -141,7 +148,7 @@ async fn main() -> anyhow::Result<()> {
 
     // bidi streaming call
     println!("a bidi streaming call");
-    let (mut send, mut recv) = client.bidi(ConvertFile).await?;
+    let (mut send, mut recv) = client.convert_file(ConvertFile).await?;
     tokio::task::spawn(async move {
         for i in 0..3 {
             send.send(ConvertFileUpdate(vec![i])).await.unwrap();
@@ -151,6 +158,15 @@ async fn main() -> anyhow::Result<()> {
         println!("bidi res: {res:?}");
     }
 
+    // the messages the server supports
+    let descriptor = client.describe(Describe).await?;
+    for message in &descriptor.messages {
+        println!(
+            "{:?} {} -> {}",
+            message.pattern, message.name, message.response
+        );
+    }
+
     // dropping the client will cause the server to terminate
     drop(client);
     server_handle.await??;
@@ -0,0 +1,82 @@
+//! Pass a request id from the client to the handler and back, next to the messages.
+use derive_more::{From, TryInto};
+use quic_rpc::{
+    message::RpcMsg,
+    transport::{
+        flume,
+        metadata::{Frame, Metadata, MetadataConnector, MetadataListener},
+    },
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Greet(String);
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum GreetRequest {
+    Greet(Greet),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum GreetResponse {
+    Greeting(String),
+}
+
+#[derive(Debug, Clone)]
+struct GreetService;
+
+impl Service for GreetService {
+    type Req = GreetRequest;
+    type Res = GreetResponse;
+}
+
+impl RpcMsg<GreetService> for Greet {
+    type Response = String;
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Greeter;
+
+impl Greeter {
+    async fn greet(self, Greet(name): Greet) -> String {
+        format!("hello, {name}")
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel::<Frame<GreetRequest>, Frame<GreetResponse>>(1);
+    let server = RpcServer::<GreetService, _>::new(MetadataListener::new(listener));
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        // the metadata is known once the first request was read
+        let request_id = chan
+            .metadata()
+            .and_then(|m| m.get("request-id"))
+            .unwrap_or("none")
+            .to_owned();
+        println!("server: handling {req:?} for request {request_id}");
+        // echo the request id, so the client can match the response
+        chan.set_response_metadata(Metadata::new().with("request-id", request_id))?;
+        match req {
+            GreetRequest::Greet(req) => chan.rpc(req, Greeter, Greeter::greet).await?,
+        }
+        anyhow::Ok(())
+    });
+
+    let client = RpcClient::<GreetService, _>::new(MetadataConnector::new(connector));
+    for (i, name) in ["alice", "bob"].into_iter().enumerate() {
+        let request_id = format!("req-{i}");
+        // metadata is per call, the client itself sends none
+        let (call, response) =
+            client.with_response_metadata(Metadata::new().with("request-id", &request_id));
+        let greeting = call.rpc(Greet(name.into())).await?;
+        let response = response.get().unwrap_or_default();
+        println!(
+            "client: got {greeting:?} for request {:?}",
+            response.get("request-id")
+        );
+        assert_eq!(response.get("request-id"), Some(request_id.as_str()));
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! Mutual TLS with the quinn transport.
+//!
+//! A self-signed CA issues one certificate for the server and one for the client. The
+//! server only accepts clients with a certificate issued by the CA, and the handler
+//! looks at the certificate of the client.
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+use derive_more::{From, TryInto};
+use quic_rpc::{
+    message::RpcMsg,
+    transport::quinn::{QuinnConnector, QuinnListener},
+    RpcClient, RpcServer, Service,
+};
+use quinn::{
+    rustls::{
+        pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer},
+        RootCertStore,
+    },
+    Endpoint,
+};
+use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WhoAmI;
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Request {
+    WhoAmI(WhoAmI),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Response {
+    WhoAmI(String),
+}
+
+#[derive(Debug, Clone)]
+struct IdentityService;
+
+impl Service for IdentityService {
+    type Req = Request;
+    type Res = Response;
+}
+
+impl RpcMsg<IdentityService> for WhoAmI {
+    type Response = String;
+}
+
+/// A certificate and its key, in the form rustls wants them
+struct Issued {
+    cert: CertificateDer<'static>,
+    key: PrivateKeyDer<'static>,
+}
+
+fn issue(name: &str, ca: &Certificate, ca_key: &KeyPair) -> anyhow::Result<Issued> {
+    let key = KeyPair::generate()?;
+    let cert = CertificateParams::new(vec![name.into()])?.signed_by(&key, ca, ca_key)?;
+    Ok(Issued {
+        cert: cert.der().clone(),
+        key: PrivatePkcs8KeyDer::from(key.serialize_der()).into(),
+    })
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    // the CA that issues both certificates
+    let ca_key = KeyPair::generate()?;
+    let mut ca_params = CertificateParams::new(Vec::<String>::new())?;
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key)?;
+    let mut roots = RootCertStore::empty();
+    roots.add(ca.der().clone())?;
+
+    let server_cert = issue("localhost", &ca, &ca_key)?;
+    let client_cert = issue("client", &ca, &ca_key)?;
+
+    let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12401));
+    let endpoint = Endpoint::server(
+        quic_rpc::transport::quinn::server_config_with_client_auth(
+            vec![server_cert.cert],
+            server_cert.key,
+            roots.clone(),
+        )?,
+        addr,
+    )?;
+    let listener = QuinnListener::new(endpoint)?;
+    let server = RpcServer::<IdentityService, _>::new(listener);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        // the certificate was verified during the handshake
+        let certificates = chan
+            .remote_info()
+            .map(|info| info.certificates.len())
+            .unwrap_or_default();
+        match req {
+            Request::WhoAmI(req) => {
+                chan.rpc(req, (), move |_, WhoAmI| async move {
+                    format!("a client with {certificates} certificate(s)")
+                })
+                .await
+            }
+        }
+    });
+
+    let endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
+    let connector = QuinnConnector::with_client_cert(
+        endpoint,
+        addr,
+        "localhost".into(),
+        roots,
+        vec![client_cert.cert],
+        client_cert.key,
+    )?;
+    let client = RpcClient::<IdentityService, _>::new(connector);
+    println!("server says I am {}", client.rpc(WhoAmI).await?);
+    Ok(())
+}
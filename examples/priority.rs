@@ -0,0 +1,105 @@
+//! Keep cheap rpc calls responsive while the server is busy with long streams.
+use std::time::{Duration, Instant};
+
+use derive_more::{From, TryInto};
+use futures::StreamExt;
+use quic_rpc::{
+    message::{Msg, RpcMsg, ServerStreaming, ServerStreamingMsg},
+    server::Priority,
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+/// A cheap lookup
+#[derive(Debug, Serialize, Deserialize)]
+struct Get(u64);
+
+/// A long running stream of all values
+#[derive(Debug, Serialize, Deserialize)]
+struct Dump;
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum StoreRequest {
+    Get(Get),
+    Dump(Dump),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum StoreResponse {
+    Value(u64),
+}
+
+#[derive(Debug, Clone)]
+struct StoreService;
+
+impl Service for StoreService {
+    type Req = StoreRequest;
+    type Res = StoreResponse;
+}
+
+impl RpcMsg<StoreService> for Get {
+    type Response = u64;
+}
+
+impl Msg<StoreService> for Dump {
+    type Pattern = ServerStreaming;
+}
+
+impl ServerStreamingMsg<StoreService> for Dump {
+    type Response = u64;
+}
+
+#[derive(Debug, Clone)]
+struct Store;
+
+impl Store {
+    async fn get(self, Get(key): Get) -> u64 {
+        key * 2
+    }
+
+    fn dump(self, _req: Dump) -> impl futures::Stream<Item = u64> {
+        futures::stream::iter(0..20).then(|key| async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            key * 2
+        })
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel(1);
+    // at most 3 handlers at a time, one of which is reserved for lookups
+    let server = RpcServer::<StoreService, _>::new(listener)
+        .with_max_concurrent(3)
+        .with_priority(1, |req| match req {
+            StoreRequest::Get(_) => Priority::High,
+            StoreRequest::Dump(_) => Priority::Low,
+        });
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        match req {
+            StoreRequest::Get(req) => chan.rpc(req, Store, Store::get).await,
+            StoreRequest::Dump(req) => chan.server_streaming(req, Store, Store::dump).await,
+        }
+    });
+    let client = RpcClient::<StoreService, _>::new(connector);
+
+    // more dumps than the server handles at a time
+    let mut dumps = Vec::new();
+    for _ in 0..4 {
+        let dump = client.server_streaming(Dump).await?;
+        dumps.push(tokio::spawn(dump.count()));
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // a lookup does not wait for the dumps to finish
+    let start = Instant::now();
+    let value = client.rpc(Get(21)).await?;
+    println!("lookup took {:?}, result {value}", start.elapsed());
+    assert!(start.elapsed() < Duration::from_secs(1));
+
+    for dump in dumps {
+        println!("dump received {} values", dump.await?);
+    }
+    Ok(())
+}
@@ -6,7 +6,7 @@ use derive_more::{From, TryInto};
 use futures_lite::{Stream, StreamExt};
 use futures_util::SinkExt;
 use quic_rpc::{
-    server::RpcServerError,
+    server::{ChannelTypes, RpcChannel, RpcServerError},
     transport::{flume, Connector},
     *,
 };
@@ -161,34 +161,33 @@ impl Store {
     }
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    async fn server_future<C: Listener<StoreService>>(
-        server: RpcServer<StoreService, C>,
+impl Store {
+    async fn handle_rpc_request<C: ChannelTypes<StoreService>>(
+        self,
+        req: StoreRequest,
+        chan: RpcChannel<StoreService, C>,
     ) -> result::Result<(), RpcServerError<C>> {
-        let s = server;
-        let store = Store;
-        loop {
-            let (req, chan) = s.accept().await?.read_first().await?;
-            use StoreRequest::*;
-            let store = store.clone();
-            #[rustfmt::skip]
-            match req {
-                Put(msg) => chan.rpc(msg, store, Store::put).await,
-                Get(msg) => chan.rpc(msg, store, Store::get).await,
-                PutFile(msg) => chan.client_streaming(msg, store, Store::put_file).await,
-                GetFile(msg) => chan.server_streaming(msg, store, Store::get_file).await,
-                ConvertFile(msg) => chan.bidi_streaming(msg, store, Store::convert_file).await,
-                PutFileUpdate(_) => Err(RpcServerError::UnexpectedStartMessage)?,
-                ConvertFileUpdate(_) => Err(RpcServerError::UnexpectedStartMessage)?,
-            }?;
+        use StoreRequest::*;
+        match req {
+            Put(msg) => chan.rpc(msg, self, Store::put).await,
+            Get(msg) => chan.rpc(msg, self, Store::get).await,
+            PutFile(msg) => chan.client_streaming(msg, self, Store::put_file).await,
+            GetFile(msg) => chan.server_streaming(msg, self, Store::get_file).await,
+            ConvertFile(msg) => chan.bidi_streaming(msg, self, Store::convert_file).await,
+            PutFileUpdate(_) => Err(RpcServerError::UnexpectedStartMessage),
+            ConvertFileUpdate(_) => Err(RpcServerError::UnexpectedStartMessage),
         }
     }
+}
 
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
     let (server, client) = flume::channel(1);
     let client = RpcClient::<StoreService, _>::new(client);
     let server = RpcServer::<StoreService, _>::new(server);
-    let server_handle = tokio::task::spawn(server_future(server));
+    // every channel is handled in a separate task, so slow calls do not block the others
+    let server_handle =
+        server.spawn_accept_loop_with_shutdown(|req, chan| Store.handle_rpc_request(req, chan));
 
     // a rpc call
     println!("a rpc call");
@@ -225,9 +224,8 @@ async fn main() -> anyhow::Result<()> {
         println!("{res:?}");
     }
 
-    // dropping the client will cause the server to terminate
-    drop(client);
-    server_handle.await??;
+    // stop accepting and wait for the running calls to finish
+    server_handle.shutdown(None).await;
     Ok(())
 }
 
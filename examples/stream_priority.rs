@@ -0,0 +1,168 @@
+//! Keep a small control stream responsive while a large upload runs on the same
+//! quinn connection.
+//!
+//! The upload gets a lower priority than the control stream, so quinn sends the pings
+//! of the control stream first whenever both have data to send.
+use std::{
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use derive_more::{From, TryInto};
+use futures::{SinkExt, StreamExt};
+use quic_rpc::{
+    message::{BidiStreaming, BidiStreamingMsg, ClientStreaming, ClientStreamingMsg, Msg},
+    server::RpcServerError,
+    transport::quinn::{QuinnConnector, QuinnListener},
+    RpcClient, RpcServer, Service,
+};
+use quinn::{
+    crypto::rustls::{QuicClientConfig, QuicServerConfig},
+    rustls, ClientConfig, Endpoint, ServerConfig,
+};
+use serde::{Deserialize, Serialize};
+
+/// Start a large upload
+#[derive(Debug, Serialize, Deserialize)]
+struct Upload;
+
+/// A chunk of the upload
+#[derive(Debug, Serialize, Deserialize)]
+struct Chunk(Vec<u8>);
+
+/// Start the control stream
+#[derive(Debug, Serialize, Deserialize)]
+struct Control;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Ping(u64);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Pong(u64);
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Request {
+    Upload(Upload),
+    Chunk(Chunk),
+    Control(Control),
+    Ping(Ping),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum Response {
+    Uploaded(usize),
+    Pong(Pong),
+}
+
+#[derive(Debug, Clone)]
+struct TransferService;
+
+impl Service for TransferService {
+    type Req = Request;
+    type Res = Response;
+}
+
+impl Msg<TransferService> for Upload {
+    type Pattern = ClientStreaming;
+}
+
+impl ClientStreamingMsg<TransferService> for Upload {
+    type Update = Chunk;
+    type Response = usize;
+}
+
+impl Msg<TransferService> for Control {
+    type Pattern = BidiStreaming;
+}
+
+impl BidiStreamingMsg<TransferService> for Control {
+    type Update = Ping;
+    type Response = Pong;
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12402));
+    let (server_config, server_cert) = configure_server()?;
+    let listener = QuinnListener::new(Endpoint::server(server_config, addr)?)?;
+    let server = RpcServer::<TransferService, _>::new(listener);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        match req {
+            Request::Upload(req) => {
+                chan.client_streaming(req, (), |_, _, chunks| async move {
+                    chunks
+                        .fold(0, |n, Chunk(data)| async move { n + data.len() })
+                        .await
+                })
+                .await
+            }
+            Request::Control(req) => {
+                chan.bidi_streaming(req, (), |_, _, pings| pings.map(|Ping(n)| Pong(n)))
+                    .await
+            }
+            _ => Err(RpcServerError::UnexpectedStartMessage),
+        }
+    });
+
+    let mut endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
+    endpoint.set_default_client_config(configure_client(&server_cert)?);
+    let connector = QuinnConnector::new(endpoint, addr, "localhost".into());
+    let client = RpcClient::<TransferService, _>::new(connector);
+
+    // the upload only gets to send when the control stream has nothing to send
+    let (mut upload, uploaded) = client.client_streaming(Upload).await?;
+    upload.set_priority(-1)?;
+    let upload = tokio::spawn(async move {
+        for _ in 0..64 {
+            upload.send(Chunk(vec![0; 1024 * 1024])).await?;
+        }
+        upload.close().await?;
+        anyhow::Ok(uploaded.await?)
+    });
+
+    let (mut pings, mut pongs) = client.bidi(Control).await?;
+    pings.set_priority(1)?;
+    for i in 0..10 {
+        let start = Instant::now();
+        pings.send(Ping(i)).await?;
+        pongs.next().await.transpose()?;
+        println!("ping {i} during the upload took {:?}", start.elapsed());
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    pings.close().await?;
+
+    let size = upload.await??;
+    println!("uploaded {size} bytes");
+    Ok(())
+}
+
+fn configure_server() -> anyhow::Result<(ServerConfig, Vec<u8>)> {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
+    let cert_der = cert.cert.der();
+    let priv_key = rustls::pki_types::PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
+    let crypto = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])?
+    .with_no_client_auth()
+    .with_single_cert(vec![cert_der.clone()], priv_key.into())?;
+    let config = ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(crypto)?));
+    Ok((config, cert_der.to_vec()))
+}
+
+fn configure_client(server_cert: &[u8]) -> anyhow::Result<ClientConfig> {
+    let mut certs = rustls::RootCertStore::empty();
+    certs.add(rustls::pki_types::CertificateDer::from(
+        server_cert.to_vec(),
+    ))?;
+    let crypto = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_protocol_versions(&[&rustls::version::TLS13])?
+    .with_root_certificates(certs)
+    .with_no_client_auth();
+    Ok(ClientConfig::new(Arc::new(QuicClientConfig::try_from(
+        crypto,
+    )?)))
+}
@@ -0,0 +1,207 @@
+//! A blocking client, for callers without an async runtime
+//!
+//! [BlockingRpcClient] wraps an [RpcClient] together with a dedicated runtime, which
+//! runs on a background thread for as long as the client or any of its clones and
+//! streams are alive. Every call blocks the calling thread until it completes. This is
+//! meant for synchronous code like a CLI or an FFI boundary, similar to the blocking
+//! client of reqwest.
+//!
+//! # Thread safety
+//!
+//! The client is [Send] and [Sync] and cheap to clone. Calls from several threads run
+//! concurrently on the shared runtime, each call only blocks its own thread.
+//!
+//! # Async contexts
+//!
+//! The blocking calls must not be used from within an async runtime, they panic if the
+//! calling thread is inside one. Use [RpcClient] there instead. The runtime of the client
+//! only runs the transport, so handlers of a server in the same process need a runtime
+//! of their own.
+//!
+//! ```
+//! # use quic_rpc::{message::RpcMsg, RpcServer, Service};
+//! use quic_rpc::{blocking::BlockingRpcClient, transport::flume};
+//! # use serde::{Deserialize, Serialize};
+//! # #[derive(Debug, Serialize, Deserialize)]
+//! # struct Ping;
+//! # #[derive(Debug, Serialize, Deserialize)]
+//! # struct Pong;
+//! # #[derive(Debug, Clone)]
+//! # struct PingService;
+//! # #[derive(Debug, Serialize, Deserialize, derive_more::From, derive_more::TryInto)]
+//! # enum Request { Ping(Ping) }
+//! # #[derive(Debug, Serialize, Deserialize, derive_more::From, derive_more::TryInto)]
+//! # enum Response { Pong(Pong) }
+//! # impl Service for PingService { type Req = Request; type Res = Response; }
+//! # impl RpcMsg<PingService> for Ping { type Response = Pong; }
+//! let (listener, connector) = flume::channel(1);
+//! # let runtime = tokio::runtime::Runtime::new()?;
+//! # let _server = runtime.spawn(async move {
+//! #     let server = RpcServer::<PingService, _>::new(listener);
+//! #     let (Request::Ping(req), chan) = server.accept().await?.read_first().await?;
+//! #     chan.rpc(req, (), |_, _| async { Pong }).await
+//! # });
+//! let client = BlockingRpcClient::<PingService, _>::new(|| connector)?;
+//! let Pong = client.rpc(Ping)?;
+//! # anyhow::Ok(())
+//! ```
+use std::{fmt, future::Future, io, sync::Arc, thread};
+
+use futures_lite::StreamExt;
+use tokio::{runtime, sync::oneshot};
+
+use crate::{
+    client::{BoxStreamSync, BoxedConnector, CallError},
+    message::{RpcMsg, ServerStreamingMsg},
+    Connector, RpcClient, Service,
+};
+
+/// A client that blocks the calling thread, see the [module docs](self)
+pub struct BlockingRpcClient<S, C = BoxedConnector<S>> {
+    client: RpcClient<S, C>,
+    runtime: Runtime,
+}
+
+impl<S, C: Clone> Clone for BlockingRpcClient<S, C> {
+    fn clone(&self) -> Self {
+        Self {
+            client: self.client.clone(),
+            runtime: self.runtime.clone(),
+        }
+    }
+}
+
+impl<S: fmt::Debug, C: fmt::Debug> fmt::Debug for BlockingRpcClient<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockingRpcClient")
+            .field("client", &self.client)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: Service, C: Connector<S>> BlockingRpcClient<S, C> {
+    /// Start the runtime of the client, and create the connector with `connect`
+    ///
+    /// `connect` runs inside the runtime, so it can create transports that need one,
+    /// like a quinn endpoint. Fails if the runtime can not be started.
+    pub fn new(connect: impl FnOnce() -> C) -> io::Result<Self> {
+        let runtime = Runtime::start()?;
+        let connector = {
+            let _guard = runtime.handle.enter();
+            connect()
+        };
+        Ok(Self {
+            client: RpcClient::new(connector),
+            runtime,
+        })
+    }
+
+    /// The async client, which shares the connection of this client
+    pub fn client(&self) -> &RpcClient<S, C> {
+        &self.client
+    }
+
+    /// RPC call to the server, single request, single response
+    ///
+    /// See [RpcClient::rpc]. Panics if called from within an async runtime.
+    pub fn rpc<M>(&self, msg: M) -> Result<M::Response, CallError<C>>
+    where
+        M: RpcMsg<S>,
+    {
+        self.runtime.block_on(self.client.rpc(msg))
+    }
+
+    /// Server streaming call to the server, returns an iterator over the responses
+    ///
+    /// See [RpcClient::server_streaming]. Every step of the iterator blocks until the
+    /// next response arrives. Dropping the iterator cancels the call. Panics if called
+    /// from within an async runtime.
+    pub fn server_streaming<M>(
+        &self,
+        msg: M,
+    ) -> Result<BlockingStream<Result<M::Response, CallError<C>>>, CallError<C>>
+    where
+        M: ServerStreamingMsg<S>,
+    {
+        let stream = self.runtime.block_on(self.client.server_streaming(msg))?;
+        Ok(BlockingStream {
+            stream,
+            runtime: self.runtime.clone(),
+        })
+    }
+}
+
+/// The responses of [BlockingRpcClient::server_streaming]
+///
+/// Every call to [Iterator::next] blocks until the next response arrives, and panics
+/// if called from within an async runtime.
+pub struct BlockingStream<T> {
+    stream: BoxStreamSync<'static, T>,
+    runtime: Runtime,
+}
+
+impl<T> fmt::Debug for BlockingStream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockingStream").finish_non_exhaustive()
+    }
+}
+
+impl<T> Iterator for BlockingStream<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.runtime.block_on(self.stream.next())
+    }
+}
+
+/// A handle to a runtime that runs on a background thread until the last handle is
+/// dropped
+#[derive(Clone)]
+struct Runtime {
+    handle: runtime::Handle,
+    _thread: Arc<RuntimeThread>,
+}
+
+impl Runtime {
+    fn start() -> io::Result<Self> {
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let handle = runtime.handle().clone();
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let thread = thread::Builder::new()
+            .name("quic-rpc-blocking".into())
+            .spawn(move || {
+                // drives the io and timers of the transport, and its tasks
+                runtime.block_on(shutdown_rx).ok();
+            })?;
+        Ok(Self {
+            handle,
+            _thread: Arc::new(RuntimeThread {
+                shutdown: Some(shutdown_tx),
+                thread: Some(thread),
+            }),
+        })
+    }
+
+    /// Run `fut` to completion on the calling thread
+    fn block_on<F: Future>(&self, fut: F) -> F::Output {
+        self.handle.block_on(fut)
+    }
+}
+
+struct RuntimeThread {
+    shutdown: Option<oneshot::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Drop for RuntimeThread {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            shutdown.send(()).ok();
+        }
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
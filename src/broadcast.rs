@@ -0,0 +1,77 @@
+//! Fan-out of one stream of items to many server streaming handlers
+//!
+//! A [Broadcaster] wraps a [tokio::sync::broadcast] channel. The producer sends each
+//! item once, and every handler relays it to its client by returning
+//! [Broadcaster::subscribe] as its response stream.
+use futures_lite::Stream;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// What to do with a subscriber that falls behind by more than the capacity
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Drop the oldest items, and report the number of missed items to the subscriber
+    #[default]
+    DropOldest,
+    /// Report the number of missed items to the subscriber, then end its stream
+    Disconnect,
+}
+
+/// A subscriber missed this many items, see [LagPolicy]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lagged(pub u64);
+
+/// Shared source of items for many subscribers
+#[derive(Debug, Clone)]
+pub struct Broadcaster<T> {
+    sender: broadcast::Sender<T>,
+    policy: LagPolicy,
+}
+
+impl<T: Clone + Send + 'static> Broadcaster<T> {
+    /// Create a new broadcaster that buffers up to `capacity` items for each subscriber
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is 0.
+    pub fn new(capacity: usize, policy: LagPolicy) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender, policy }
+    }
+
+    /// Send an item to all current subscribers
+    ///
+    /// Returns the number of subscribers the item was sent to.
+    pub fn send(&self, item: T) -> usize {
+        self.sender.send(item).unwrap_or(0)
+    }
+
+    /// The number of current subscribers
+    pub fn receiver_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    /// Subscribe to all items sent from now on
+    ///
+    /// The stream yields [Lagged] when the subscriber falls behind, and ends when all
+    /// clones of the broadcaster are dropped. To relay it from a server streaming
+    /// handler, use `Result<T, Lagged>` as the response type.
+    pub fn subscribe(&self) -> impl Stream<Item = Result<T, Lagged>> + Send + 'static {
+        let policy = self.policy;
+        let receiver = self.sender.subscribe();
+        futures_lite::stream::unfold(Some(receiver), move |receiver| async move {
+            let mut receiver = receiver?;
+            match receiver.recv().await {
+                Ok(item) => Some((Ok(item), Some(receiver))),
+                Err(RecvError::Lagged(n)) => {
+                    let receiver = match policy {
+                        LagPolicy::DropOldest => Some(receiver),
+                        LagPolicy::Disconnect => None,
+                    };
+                    Some((Err(Lagged(n)), receiver))
+                }
+                Err(RecvError::Closed) => None,
+            }
+        })
+    }
+}
@@ -0,0 +1,388 @@
+//! A memory budget shared by the streaming responses of a server
+//!
+//! A [MemoryBudget] can be attached to a [RpcServer](crate::RpcServer) using
+//! [RpcServer::with_memory_budget](crate::RpcServer::with_memory_budget). Every response
+//! of a streaming pattern takes its serialized size from the budget before it is passed
+//! to the transport, and returns it once the transport accepted it with a flush. If the
+//! budget is exhausted, the handler is not polled for more responses until other
+//! responses are written, so slow clients can not make the server buffer an unbounded
+//! amount of responses across many channels.
+//!
+//! The rpc layer only sees typed messages, so the size of a response is its size in
+//! bincode with fixint encoding, the default [Codec](crate::transport::codec::Codec) of
+//! the transports that serialize messages. Other codecs produce roughly the same sizes.
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use event_listener::Event;
+use serde::{ser, Serialize};
+
+/// A limit on the bytes of responses that are buffered at the same time
+///
+/// Clones share the same budget, so a single budget can be attached to several servers.
+#[derive(Debug, Clone)]
+pub struct MemoryBudget(Arc<BudgetInner>);
+
+#[derive(Debug)]
+struct BudgetInner {
+    limit: usize,
+    used: AtomicUsize,
+    released: Event,
+}
+
+impl MemoryBudget {
+    /// Allow up to `limit` bytes of responses to be buffered at the same time.
+    ///
+    /// A single response that is larger than the limit is still sent, but only while
+    /// nothing else is buffered.
+    pub fn new(limit: usize) -> Self {
+        Self(Arc::new(BudgetInner {
+            limit,
+            used: AtomicUsize::new(0),
+            released: Event::new(),
+        }))
+    }
+
+    /// The maximum number of bytes buffered at the same time
+    pub fn limit(&self) -> usize {
+        self.0.limit
+    }
+
+    /// The number of bytes that are currently buffered
+    pub fn used(&self) -> usize {
+        self.0.used.load(Ordering::Relaxed)
+    }
+
+    /// Take the serialized size of `item` from the budget, waiting until enough of it
+    /// is available.
+    pub(crate) async fn acquire<T: Serialize>(&self, item: &T) -> BudgetPermit {
+        let bytes = serialized_size(item);
+        loop {
+            // register before checking, so a release in between is not missed
+            let released = self.0.released.listen();
+            let acquired = self
+                .0
+                .used
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |used| {
+                    (used == 0 || used + bytes <= self.0.limit).then_some(used + bytes)
+                });
+            if acquired.is_ok() {
+                return BudgetPermit {
+                    budget: self.clone(),
+                    bytes,
+                };
+            }
+            released.await;
+        }
+    }
+}
+
+/// Bytes taken from a [MemoryBudget], returned on drop
+#[derive(Debug)]
+pub(crate) struct BudgetPermit {
+    budget: MemoryBudget,
+    bytes: usize,
+}
+
+impl Drop for BudgetPermit {
+    fn drop(&mut self) {
+        self.budget.0.used.fetch_sub(self.bytes, Ordering::AcqRel);
+        self.budget.0.released.notify(usize::MAX);
+    }
+}
+
+/// The size of `item` in bincode with fixint encoding, without serializing it
+fn serialized_size<T: Serialize>(item: &T) -> usize {
+    let mut counter = SizeCounter(0);
+    // a failing Serialize impl fails in the codec as well, so the size does not matter
+    item.serialize(&mut counter).ok();
+    counter.0
+}
+
+/// A serializer that only counts bytes
+struct SizeCounter(usize);
+
+impl SizeCounter {
+    fn add(&mut self, bytes: usize) -> Result<(), fmt::Error> {
+        self.0 += bytes;
+        Ok(())
+    }
+}
+
+/// Length prefixes of sequences, maps, strings and byte arrays
+const LEN_SIZE: usize = 8;
+/// Variant indices of enums
+const VARIANT_SIZE: usize = 4;
+
+impl ser::Serializer for &mut SizeCounter {
+    type Ok = ();
+    type Error = fmt::Error;
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = Self;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, _v: bool) -> Result<(), fmt::Error> {
+        self.add(1)
+    }
+
+    fn serialize_i8(self, _v: i8) -> Result<(), fmt::Error> {
+        self.add(1)
+    }
+
+    fn serialize_i16(self, _v: i16) -> Result<(), fmt::Error> {
+        self.add(2)
+    }
+
+    fn serialize_i32(self, _v: i32) -> Result<(), fmt::Error> {
+        self.add(4)
+    }
+
+    fn serialize_i64(self, _v: i64) -> Result<(), fmt::Error> {
+        self.add(8)
+    }
+
+    fn serialize_i128(self, _v: i128) -> Result<(), fmt::Error> {
+        self.add(16)
+    }
+
+    fn serialize_u8(self, _v: u8) -> Result<(), fmt::Error> {
+        self.add(1)
+    }
+
+    fn serialize_u16(self, _v: u16) -> Result<(), fmt::Error> {
+        self.add(2)
+    }
+
+    fn serialize_u32(self, _v: u32) -> Result<(), fmt::Error> {
+        self.add(4)
+    }
+
+    fn serialize_u64(self, _v: u64) -> Result<(), fmt::Error> {
+        self.add(8)
+    }
+
+    fn serialize_u128(self, _v: u128) -> Result<(), fmt::Error> {
+        self.add(16)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<(), fmt::Error> {
+        self.add(4)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<(), fmt::Error> {
+        self.add(8)
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), fmt::Error> {
+        self.add(v.len_utf8())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), fmt::Error> {
+        self.add(LEN_SIZE + v.len())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), fmt::Error> {
+        self.add(LEN_SIZE + v.len())
+    }
+
+    fn serialize_none(self) -> Result<(), fmt::Error> {
+        self.add(1)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<(), fmt::Error> {
+        self.add(1)?;
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), fmt::Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), fmt::Error> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+    ) -> Result<(), fmt::Error> {
+        self.add(VARIANT_SIZE)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), fmt::Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), fmt::Error> {
+        self.add(VARIANT_SIZE)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self, fmt::Error> {
+        self.add(LEN_SIZE)?;
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self, fmt::Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self, fmt::Error> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, fmt::Error> {
+        self.add(VARIANT_SIZE)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self, fmt::Error> {
+        self.add(LEN_SIZE)?;
+        Ok(self)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, fmt::Error> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, fmt::Error> {
+        self.add(VARIANT_SIZE)?;
+        Ok(self)
+    }
+}
+
+impl ser::SerializeSeq for &mut SizeCounter {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), fmt::Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), fmt::Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut SizeCounter {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), fmt::Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), fmt::Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut SizeCounter {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), fmt::Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), fmt::Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for &mut SizeCounter {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), fmt::Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), fmt::Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeMap for &mut SizeCounter {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), fmt::Error> {
+        key.serialize(&mut **self)
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), fmt::Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), fmt::Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut SizeCounter {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), fmt::Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), fmt::Error> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut SizeCounter {
+    type Ok = ();
+    type Error = fmt::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), fmt::Error> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), fmt::Error> {
+        Ok(())
+    }
+}
@@ -2,19 +2,35 @@
 //!
 //! The main entry point is [RpcClient].
 use std::{
-    fmt::Debug,
+    collections::VecDeque,
+    convert::Infallible,
+    error,
+    fmt::{self, Debug},
+    future::Future,
     marker::PhantomData,
-    pin::Pin,
-    task::{Context, Poll},
+    pin::{pin, Pin},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
 };
 
-use futures_lite::Stream;
+use futures::task::AtomicWaker;
+use futures_lite::{Stream, StreamExt};
 use futures_sink::Sink;
+use futures_util::FutureExt;
 use pin_project::pin_project;
 
 use crate::{
-    transport::{boxed::BoxableConnector, mapped::MappedConnector, StreamTypes},
-    Connector, Service,
+    transport::{
+        boxed::BoxableConnector,
+        lifecycle::{LifecycleConnector, LifecycleEvent, LifecycleObserver},
+        mapped::MappedConnector,
+        ConnectionErrors, ConnectionStats, PingError, PriorityError, StreamTypes,
+    },
+    Connector, ErrorSource, Service,
 };
 
 /// Type alias for a boxed connection to a specific service
@@ -26,6 +42,57 @@ pub type BoxedConnector<S> =
 /// Sync version of `future::stream::BoxStream`.
 pub type BoxStreamSync<'a, T> = Pin<Box<dyn Stream<Item = T> + Send + Sync + 'a>>;
 
+/// Extension methods for streams of responses, such as the one returned by
+/// [RpcClient::server_streaming].
+///
+/// This is implemented for all streams of results, so a [CallError] can be handled in
+/// one place instead of in a loop over the items.
+pub trait ResponseStreamExt<T, E>: Stream<Item = Result<T, E>> + Sized {
+    /// Collect all items, including errors, until the stream ends.
+    fn collect_vec(self) -> impl Future<Output = Vec<Result<T, E>>> + Send
+    where
+        Self: Send,
+        T: Send,
+        E: Send,
+    {
+        self.collect()
+    }
+
+    /// Collect all items, stopping at the first error.
+    fn try_collect_vec(self) -> impl Future<Output = Result<Vec<T>, E>> + Send
+    where
+        Self: Send,
+        T: Send,
+        E: Send,
+    {
+        self.fold_ok(Vec::new(), |mut items, item| {
+            items.push(item);
+            items
+        })
+    }
+
+    /// Fold all items into `init` using `f`, stopping at the first error.
+    fn fold_ok<B, F>(self, init: B, mut f: F) -> impl Future<Output = Result<B, E>> + Send
+    where
+        Self: Send,
+        T: Send,
+        E: Send,
+        B: Send,
+        F: FnMut(B, T) -> B + Send,
+    {
+        async move {
+            let mut stream = pin!(self);
+            let mut acc = init;
+            while let Some(item) = stream.next().await {
+                acc = f(acc, item?);
+            }
+            Ok(acc)
+        }
+    }
+}
+
+impl<S, T, E> ResponseStreamExt<T, E> for S where S: Stream<Item = Result<T, E>> {}
+
 /// A client for a specific service
 ///
 /// This is a wrapper around a [`Connector`] that serves as the entry point
@@ -52,12 +119,151 @@ impl<S, C: Clone> Clone for RpcClient<S, C> {
 
 /// Sink that can be used to send updates to the server for the two interaction patterns
 /// that support it, [crate::message::ClientStreaming] and [crate::message::BidiStreaming].
+///
+/// By default, updates are handed to the transport directly, so [send](futures_util::SinkExt::send)
+/// waits until the transport accepts the update. A sink created with a capacity, e.g.
+/// using [RpcClient::bidi_with_capacity], additionally buffers up to that many updates
+/// that the transport has not accepted yet. In both cases the sink applies backpressure
+/// once the buffer is full, so a slow server slows down the client.
+///
+/// The sink implements [Sink], so the combinators of [SinkExt](futures_util::SinkExt)
+/// work with it, and a stream of updates can be forwarded into it using
+/// [StreamExt::forward](futures_util::StreamExt::forward). Closing the sink, which
+/// `forward` does at the end of the stream, is the same as [UpdateSink::close].
+///
+/// `A` is the reason with which the server can abort a client streaming request, see
+/// [RpcClient::client_streaming_abortable]. The sink then fails with
+/// [UpdateError::Aborted]. For the other requests, the server can not abort.
 #[pin_project]
 #[derive(Debug)]
-pub struct UpdateSink<C, T>(#[pin] pub C::SendSink, PhantomData<T>)
+pub struct UpdateSink<C, T, A = Infallible>(
+    #[pin] pub C::SendSink,
+    UpdateBuffer<C::Out>,
+    PhantomData<T>,
+    AbortWatch<A>,
+    Option<Arc<IdleWatch>>,
+)
 where
     C: StreamTypes;
 
+/// Updates that have not been accepted by the transport yet
+#[derive(Debug)]
+struct UpdateBuffer<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+    /// The inner sink returned ready on the last poll, so it can take an item
+    sink_ready: bool,
+}
+
+/// Finds out whether the server aborted the request of an [UpdateSink]
+pub(crate) trait PollAbort<A>: Send + Sync {
+    /// Ready with the reason once the server aborted, or with `None` once it is clear
+    /// that it will not abort, e.g. because the response arrived.
+    fn poll_abort(&self, cx: &mut Context<'_>) -> Poll<Option<A>>;
+}
+
+/// The activity on both halves of a call, see [RpcClient::bidi_with_idle_timeout]
+#[derive(Debug)]
+pub(crate) struct IdleWatch {
+    idle: Duration,
+    last: Mutex<Instant>,
+    expired: AtomicBool,
+    /// The [UpdateSink] waiting for the transport, woken once the call is idle
+    sink: AtomicWaker,
+}
+
+impl IdleWatch {
+    pub(crate) fn new(idle: Duration) -> Arc<Self> {
+        Arc::new(Self {
+            idle,
+            last: Mutex::new(Instant::now()),
+            expired: AtomicBool::new(false),
+            sink: AtomicWaker::new(),
+        })
+    }
+
+    pub(crate) fn idle(&self) -> Duration {
+        self.idle
+    }
+
+    /// Record activity on the call
+    pub(crate) fn touch(&self) {
+        *self.last.lock().unwrap_or_else(PoisonError::into_inner) = Instant::now();
+    }
+
+    /// The time left until the call is idle, or `None` once it is, which is final
+    pub(crate) fn remaining(&self) -> Option<Duration> {
+        if self.expired.load(Ordering::Acquire) {
+            return None;
+        }
+        let last = *self.last.lock().unwrap_or_else(PoisonError::into_inner);
+        let remaining = self
+            .idle
+            .checked_sub(last.elapsed())
+            .filter(|d| !d.is_zero());
+        if remaining.is_none() {
+            self.expired.store(true, Ordering::Release);
+            self.sink.wake();
+        }
+        remaining
+    }
+
+    /// Fail once the call is idle, and register the sink to be woken when it becomes idle
+    fn check<C: ConnectionErrors, A>(
+        watch: &Option<Arc<Self>>,
+        cx: &mut Context<'_>,
+    ) -> Result<(), UpdateError<C, A>> {
+        let Some(watch) = watch else {
+            return Ok(());
+        };
+        watch.sink.register(cx.waker());
+        match watch.remaining() {
+            Some(_) => Ok(()),
+            None => Err(UpdateError::Idle),
+        }
+    }
+}
+
+/// The [PollAbort] of an [UpdateSink], if the server can abort its request
+struct AbortWatch<A>(Option<Arc<dyn PollAbort<A>>>);
+
+impl<A> fmt::Debug for AbortWatch<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AbortWatch")
+            .field(&self.0.is_some())
+            .finish()
+    }
+}
+
+impl<A> AbortWatch<A> {
+    /// Turn an error of the transport into [UpdateError::Aborted] if the server aborted.
+    ///
+    /// The server aborts by sending the reason and closing the substream, so the error
+    /// can come before the reason was read. In that case this waits for the reason.
+    fn map_err<C: ConnectionErrors>(
+        &self,
+        cx: &mut Context<'_>,
+        res: Poll<Result<(), UpdateError<C, A>>>,
+    ) -> Poll<Result<(), UpdateError<C, A>>> {
+        match (res, &self.0) {
+            (Poll::Ready(Err(cause)), Some(abort)) => match abort.poll_abort(cx) {
+                Poll::Ready(Some(reason)) => Poll::Ready(Err(UpdateError::Aborted(reason))),
+                Poll::Ready(None) => Poll::Ready(Err(cause)),
+                Poll::Pending => Poll::Pending,
+            },
+            (res, _) => res,
+        }
+    }
+
+    /// Check whether the server aborted, and register for a wakeup when it does
+    fn check<C: ConnectionErrors>(&self, cx: &mut Context<'_>) -> Result<(), UpdateError<C, A>> {
+        match self.0.as_ref().map(|abort| abort.poll_abort(cx)) {
+            Some(Poll::Ready(Some(reason))) => Err(UpdateError::Aborted(reason)),
+            _ => Ok(()),
+        }
+    }
+}
+
 impl<C, T> UpdateSink<C, T>
 where
     C: StreamTypes,
@@ -65,35 +271,364 @@ where
 {
     /// Create a new update sink
     pub fn new(sink: C::SendSink) -> Self {
-        Self(sink, PhantomData)
+        Self::with_capacity(sink, 0)
+    }
+
+    /// Create a new update sink that buffers up to `capacity` updates
+    pub fn with_capacity(sink: C::SendSink, capacity: usize) -> Self {
+        Self::build(sink, capacity, AbortWatch(None))
     }
 }
 
-impl<C, T> Sink<T> for UpdateSink<C, T>
+impl<C, T, A> UpdateSink<C, T, A>
 where
     C: StreamTypes,
     T: Into<C::Out>,
 {
-    type Error = C::SendError;
+    fn build(sink: C::SendSink, capacity: usize, abort: AbortWatch<A>) -> Self {
+        let buffer = UpdateBuffer {
+            items: VecDeque::with_capacity(capacity),
+            capacity,
+            sink_ready: false,
+        };
+        Self(sink, buffer, PhantomData, abort, None)
+    }
+
+    /// Fail once the call is idle, see [RpcClient::bidi_with_idle_timeout]
+    pub(crate) fn with_idle_watch(mut self, watch: Arc<IdleWatch>) -> Self {
+        self.4 = Some(watch);
+        self
+    }
+
+    /// Create a new update sink whose request the server can abort
+    pub(crate) fn abortable(sink: C::SendSink, abort: Arc<dyn PollAbort<A>>) -> Self {
+        Self::build(sink, 0, AbortWatch(Some(abort)))
+    }
+
+    /// Set the priority of the updates sent on this sink.
+    ///
+    /// Updates of substreams with a higher priority are sent first when several
+    /// substreams of the same connection have data to send, e.g. so an interactive
+    /// stream is not slowed down by a bulk transfer. See
+    /// [StreamTypes::set_priority], only the quinn transport supports priorities.
+    pub fn set_priority(&self, priority: i32) -> Result<(), PriorityError> {
+        C::set_priority(&self.0, priority)
+    }
+
+    /// Signal to the server that no more updates will be sent.
+    ///
+    /// This flushes pending updates and finishes the sending half of the substream,
+    /// while responses can still be received. On the server side, the
+    /// [UpdateStream](crate::server::UpdateStream) ends instead of producing an error.
+    ///
+    /// This only returns once the transport is done with the updates, so they are not
+    /// lost if the sink and the connection are dropped right after. For the quinn based
+    /// transports this means that the server acknowledged all of them, for the others
+    /// that they were written to the underlying connection.
+    pub async fn close(&mut self) -> Result<(), UpdateError<C, A>> {
+        futures_util::SinkExt::close(self).await
+    }
+
+    /// Like [close](Self::close), but gives up after `timeout`.
+    ///
+    /// Returns [UpdateError::Timeout] if the transport is not done with the updates in
+    /// time, e.g. because the server stopped reading them. The server may or may not
+    /// have received some of them.
+    pub async fn close_with_timeout(&mut self, timeout: Duration) -> Result<(), UpdateError<C, A>> {
+        futures_lite::future::or(
+            self.close().map(Some),
+            glib::timeout_future(timeout).map(|_| None),
+        )
+        .await
+        .ok_or(UpdateError::Timeout)?
+    }
+
+    /// Try to send an update without waiting.
+    ///
+    /// Returns [TrySendError::Full] with the update if neither the transport nor the
+    /// buffer of this sink can take it right now. Buffered updates are passed on to the
+    /// transport on the next call to this method or to any of the async methods of the
+    /// sink, so call [flush](futures_util::SinkExt::flush) or [close](Self::close) once
+    /// done producing.
+    pub fn try_send(&mut self, item: T) -> Result<(), TrySendError<T, UpdateError<C, A>>> {
+        let mut cx = Context::from_waker(futures_util::task::noop_waker_ref());
+        match Pin::new(&mut *self).poll_ready(&mut cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(cause)) => return Err(TrySendError::Send(cause)),
+            Poll::Pending => return Err(TrySendError::Full(item)),
+        }
+        Pin::new(&mut *self)
+            .start_send(item)
+            .map_err(TrySendError::Send)?;
+        // get the update on its way, without waiting for it to arrive
+        match Pin::new(&mut *self).poll_flush(&mut cx) {
+            Poll::Ready(Err(cause)) => Err(TrySendError::Send(cause)),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<C, T, A> Sink<T> for UpdateSink<C, T, A>
+where
+    C: StreamTypes,
+    T: Into<C::Out>,
+{
+    type Error = UpdateError<C, A>;
 
     fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.project().0.poll_ready(cx)
+        let this = self.project();
+        let (mut sink, buffer, abort) = (this.0, this.1, this.3);
+        buffer.sink_ready = false;
+        abort.check::<C>(cx)?;
+        IdleWatch::check(this.4, cx)?;
+        // move buffered updates to the transport, as far as it accepts them
+        loop {
+            match sink.as_mut().poll_ready(cx).map_err(UpdateError::new) {
+                Poll::Ready(Ok(())) => match buffer.items.pop_front() {
+                    Some(item) => {
+                        let res = sink.as_mut().start_send(item).map_err(UpdateError::new);
+                        ready!(abort.map_err(cx, Poll::Ready(res)))?
+                    }
+                    None => {
+                        buffer.sink_ready = true;
+                        return Poll::Ready(Ok(()));
+                    }
+                },
+                Poll::Ready(Err(cause)) => return abort.map_err(cx, Poll::Ready(Err(cause))),
+                Poll::Pending if buffer.items.len() < buffer.capacity => {
+                    return Poll::Ready(Ok(()))
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
     }
 
     fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        let this = self.project();
+        if let Some(watch) = this.4 {
+            if watch.remaining().is_none() {
+                return Err(UpdateError::Idle);
+            }
+            watch.touch();
+        }
         let req = item.into();
-        self.project().0.start_send(req)
+        if std::mem::take(&mut this.1.sink_ready) {
+            this.0.start_send(req).map_err(UpdateError::new)
+        } else {
+            this.1.items.push_back(req);
+            Ok(())
+        }
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.project().0.poll_flush(cx)
+        let this = self.project();
+        let (mut sink, buffer, abort) = (this.0, this.1, this.3);
+        abort.check::<C>(cx)?;
+        while !buffer.items.is_empty() {
+            let res = ready!(sink.as_mut().poll_ready(cx)).map_err(UpdateError::new);
+            ready!(abort.map_err(cx, Poll::Ready(res)))?;
+            if let Some(item) = buffer.items.pop_front() {
+                let res = sink.as_mut().start_send(item).map_err(UpdateError::new);
+                ready!(abort.map_err(cx, Poll::Ready(res)))?;
+            }
+        }
+        let res = sink.poll_flush(cx).map_err(UpdateError::new);
+        abort.map_err(cx, res)
     }
 
-    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.project().0.poll_close(cx)
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        let this = self.project();
+        let res = this.0.poll_close(cx).map_err(UpdateError::new);
+        this.3.map_err(cx, res)
+    }
+}
+
+/// Client error of a call. All client call methods return a `Result` with this error
+/// type, for the call itself as well as for the items of response streams.
+///
+/// `A` is the application error of the [Fallible](crate::pattern::fallible::Fallible)
+/// and [TryServerStreaming](crate::pattern::try_server_streaming::TryServerStreaming)
+/// patterns. The other patterns have no application errors, so `App` can not occur.
+///
+/// The error types of the individual patterns can be converted to this using `From`. A
+/// receive error of a reset stream converts to [CallError::EarlyClose], as it does in the
+/// client methods.
+#[derive(Debug)]
+pub enum CallError<C: ConnectionErrors, A = Infallible> {
+    /// Unable to open a substream at all
+    Open(C::OpenError),
+    /// Unable to send the request to the server
+    Send(C::SendError),
+    /// Unable to receive a response from the server
+    Recv(C::RecvError),
+    /// Unexpected response from the server
+    Downcast,
+    /// The responses of the server violate the pattern of the call, e.g. a chunked
+    /// transfer with more data than announced, see [Chunks](crate::pattern::chunked::Chunks)
+    ProtocolViolation,
+    /// Server closed the stream before sending a response
+    ///
+    /// This is also returned when the handler on the server panicked or dropped the
+    /// channel without responding, for transports that report this as a reset, see
+    /// [ConnectionErrors::is_reset].
+    EarlyClose,
+    /// Server cancelled the call, e.g. because the handler ran longer than
+    /// [RpcServer::with_handler_timeout](crate::RpcServer::with_handler_timeout)
+    ///
+    /// Only transports that can cancel a channel report this, see
+    /// [StreamTypes::cancel](crate::transport::StreamTypes::cancel). With all others,
+    /// the call fails with [CallError::EarlyClose].
+    Cancelled,
+    /// The call, or the next item of a response stream, did not complete within the
+    /// given timeout
+    Timeout {
+        /// Whether the request may have reached the server when the timeout elapsed.
+        ///
+        /// This is `true` as soon as sending the request has started, even if the send did
+        /// not complete. If it is `false`, the server never saw the request, so it is safe
+        /// to retry. If it is `true`, the server may or may not have processed it.
+        sent: bool,
+    },
+    /// Neither side of the call had any activity for the idle timeout of
+    /// [RpcClient::bidi_with_idle_timeout]
+    Idle,
+    /// Application error sent by the server
+    App(A),
+}
+
+impl<C: ConnectionErrors, A: Debug> fmt::Display for CallError<C, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open(_) => write!(f, "failed to open a substream"),
+            Self::Send(_) => write!(f, "failed to send the request"),
+            Self::Recv(_) => write!(f, "failed to receive a response"),
+            Self::Downcast => write!(f, "unexpected response from the server"),
+            Self::ProtocolViolation => write!(f, "server violated the pattern of the call"),
+            Self::EarlyClose => write!(f, "server closed the stream before sending a response"),
+            Self::Cancelled => write!(f, "server cancelled the call"),
+            Self::Timeout { sent: false } => write!(f, "timed out before the request was sent"),
+            Self::Timeout { sent: true } => write!(f, "timed out after sending the request"),
+            Self::Idle => write!(f, "the call was idle for too long"),
+            Self::App(cause) => write!(f, "application error: {cause:?}"),
+        }
+    }
+}
+
+impl<C: ConnectionErrors, A: Debug> error::Error for CallError<C, A> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Open(cause) => Some(ErrorSource::new(cause)),
+            Self::Send(cause) => Some(ErrorSource::new(cause)),
+            Self::Recv(cause) => Some(ErrorSource::new(cause)),
+            _ => None,
+        }
+    }
+}
+
+impl<C: ConnectionErrors, A> CallError<C, A> {
+    /// Whether the call failed in the transport, so it is safe to retry if the request is idempotent
+    pub(crate) fn is_transport(&self) -> bool {
+        matches!(
+            self,
+            CallError::Open(_) | CallError::Send(_) | CallError::EarlyClose | CallError::Recv(_)
+        )
+    }
+
+    /// Error for a failed receive, where a reset of the stream counts as an early close,
+    /// unless the server cancelled it
+    pub(crate) fn recv(cause: C::RecvError) -> Self {
+        if C::is_cancelled(&cause) {
+            CallError::Cancelled
+        } else if C::is_reset(&cause) {
+            CallError::EarlyClose
+        } else {
+            CallError::Recv(cause)
+        }
+    }
+}
+
+/// A channel opened by the server, see [RpcClient::accept_push]
+#[derive(Debug)]
+pub struct PushChannel<S: Service, C: StreamTypes> {
+    /// Sink to send messages to the server.
+    pub send: C::SendSink,
+    /// Stream to receive the messages pushed by the server.
+    pub recv: C::RecvStream,
+    _p: PhantomData<S>,
+}
+
+/// Error when sending an update to the server
+///
+/// `A` is the reason with which the server can abort the request, see [UpdateSink].
+#[derive(Debug)]
+pub enum UpdateError<C: ConnectionErrors, A = Infallible> {
+    /// The server closed its receiving side, e.g. because it already sent the response
+    ServerClosed(C::SendError),
+    /// Unable to send the update
+    Send(C::SendError),
+    /// The updates were not delivered within the timeout of [UpdateSink::close_with_timeout]
+    Timeout,
+    /// Neither side of the call had any activity for the idle timeout of
+    /// [RpcClient::bidi_with_idle_timeout]
+    Idle,
+    /// The server aborted the request with this reason, see
+    /// [RpcClient::client_streaming_abortable]
+    Aborted(A),
+}
+
+impl<C: ConnectionErrors, A> UpdateError<C, A> {
+    fn new(cause: C::SendError) -> Self {
+        if C::is_remote_closed(&cause) {
+            Self::ServerClosed(cause)
+        } else {
+            Self::Send(cause)
+        }
     }
 }
 
+impl<C: ConnectionErrors, A: Debug> fmt::Display for UpdateError<C, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ServerClosed(_) => write!(f, "server stopped receiving updates"),
+            Self::Send(_) => write!(f, "failed to send an update"),
+            Self::Timeout => write!(f, "timed out delivering the updates"),
+            Self::Idle => write!(f, "the call was idle for too long"),
+            Self::Aborted(reason) => write!(f, "server aborted the request: {reason:?}"),
+        }
+    }
+}
+
+impl<C: ConnectionErrors, A: Debug> error::Error for UpdateError<C, A> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::ServerClosed(cause) => Some(ErrorSource::new(cause)),
+            Self::Send(cause) => Some(ErrorSource::new(cause)),
+            Self::Timeout | Self::Idle | Self::Aborted(_) => None,
+        }
+    }
+}
+
+/// Error returned by [UpdateSink::try_send]
+#[derive(Debug)]
+pub enum TrySendError<T, E> {
+    /// The sink is full, the update is returned
+    Full(T),
+    /// Sending failed
+    Send(E),
+}
+
+impl<T: Debug, E: Debug> fmt::Display for TrySendError<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Full(_) => write!(f, "update sink is full"),
+            Self::Send(cause) => write!(f, "failed to send an update: {cause:?}"),
+        }
+    }
+}
+
+impl<T: Debug, E: Debug> error::Error for TrySendError<T, E> {}
+
 impl<S, C> RpcClient<S, C>
 where
     S: Service,
@@ -123,6 +658,46 @@ where
         self.source
     }
 
+    /// Statistics of the connection to the server, if supported by the transport.
+    ///
+    /// See [Connector::stats](crate::transport::Connector::stats).
+    pub fn stats(&self) -> Option<ConnectionStats> {
+        self.source.stats()
+    }
+
+    /// Check that the server is reachable and measure the round trip time.
+    ///
+    /// This can be used as a readiness probe, or to establish the connection before a
+    /// latency sensitive call. It does not need a message in the service. The ping is
+    /// not bounded in time, so wrap it in a timeout if the server may be unreachable.
+    ///
+    /// See [Connector::ping](crate::transport::Connector::ping).
+    pub async fn ping(&self) -> Result<Duration, PingError> {
+        self.source.ping().await
+    }
+
+    /// Accept the channels that the server opens to this client.
+    ///
+    /// The server opens them using [Connection::open_push](crate::transport::Connection::open_push),
+    /// e.g. to push notifications without the client polling for them. On a push channel,
+    /// the server sends `S::Res` and the client sends `S::Req`, without a first request.
+    ///
+    /// Push channels never show up as responses to the calls of this client, and calls
+    /// can be made while waiting for pushes. The stream ends once no more channels can
+    /// be pushed. See [Connector::accept_push](crate::transport::Connector::accept_push)
+    /// for which transports support push.
+    pub fn accept_push(&self) -> impl Stream<Item = PushChannel<S, C>> + Send + 'static {
+        futures_lite::stream::unfold(self.source.clone(), |source| async move {
+            let (send, recv) = source.accept_push().await?;
+            let chan = PushChannel {
+                send,
+                recv,
+                _p: PhantomData,
+            };
+            Some((chan, source))
+        })
+    }
+
     /// Map this channel's service into an inner service.
     ///
     /// This method is available if the required bounds are upheld:
@@ -148,6 +723,44 @@ where
     {
         RpcClient::new(self.source.boxed())
     }
+
+    /// Call `on_event` when a channel is opened and when it ends, for debugging.
+    ///
+    /// This wraps the connector in a [LifecycleConnector], see the
+    /// [lifecycle](crate::transport::lifecycle) module for the events. Connections are
+    /// only reported by listeners. The connection of a quinn connector has events of its
+    /// own, see `QuinnConnector::events`.
+    pub fn with_lifecycle_observer(
+        self,
+        on_event: impl Fn(LifecycleEvent) + Send + Sync + 'static,
+    ) -> RpcClient<S, LifecycleConnector<C>> {
+        RpcClient::new(LifecycleConnector::new(
+            self.source,
+            LifecycleObserver::new(on_event),
+        ))
+    }
+}
+
+#[cfg(any(
+    feature = "quinn-transport",
+    feature = "iroh-net-transport",
+    all(feature = "uds-transport", unix),
+    feature = "tcp-transport"
+))]
+impl<S, C> RpcClient<S, C>
+where
+    S: Service,
+    C: Connector<S> + crate::transport::RawStreamTypes,
+{
+    /// Open a channel that sends and receives raw frames, bypassing the codec.
+    ///
+    /// Every frame sent must be a serialized `S::Req`, starting with the request that
+    /// opens the interaction, and every frame received is a serialized `S::Res`. See
+    /// [RawStreamTypes](crate::transport::RawStreamTypes) for the full contract.
+    pub async fn open_raw(&self) -> Result<(C::RawSendSink, C::RawRecvStream), C::OpenError> {
+        let (send, recv) = self.source.open().await?;
+        Ok(C::into_raw(send, recv))
+    }
 }
 
 impl<S, C> AsRef<C> for RpcClient<S, C>
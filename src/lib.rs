@@ -89,21 +89,55 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! # `no_std`
+//!
+//! Everything but the definition of a service needs the `std` feature, which is enabled
+//! by default and by all other features. With `default-features = false`, a crate that
+//! defines the messages of a service can be shared with `no_std` targets. It has access to:
+//!
+//! - [Service] and [RpcMessage]
+//! - [message::Msg] and [message::InteractionPattern]
+//! - the pattern markers and their message traits in [message], e.g.
+//!   [message::Rpc] and [message::RpcMsg]
+//!
+//! Clients, servers, transports and the error types of the patterns need std.
+#![cfg_attr(not(feature = "std"), no_std)]
 #![deny(missing_docs)]
 #![deny(rustdoc::broken_intra_doc_links)]
-use std::fmt::{Debug, Display};
+use core::fmt::Debug;
+#[cfg(feature = "std")]
+use std::fmt::Display;
 
 use serde::{de::DeserializeOwned, Serialize};
+#[cfg(feature = "blocking")]
+pub mod blocking;
+#[cfg(feature = "broadcast")]
+pub mod broadcast;
+#[cfg(feature = "std")]
+pub mod budget;
+#[cfg(feature = "std")]
 pub mod client;
 pub mod message;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "std")]
 pub mod server;
+#[cfg(feature = "std")]
 pub mod transport;
+#[cfg(feature = "std")]
 pub use client::RpcClient;
+#[cfg(feature = "std")]
 pub use server::RpcServer;
 #[cfg(feature = "macros")]
 mod macros;
 
+#[cfg(feature = "std")]
 pub mod pattern;
+#[cfg(feature = "std")]
+pub mod reflection;
+#[cfg(feature = "std")]
+mod sync;
 
 /// Requirements for a RPC message
 ///
@@ -129,11 +163,47 @@ impl<T> RpcMessage for T where
 ///
 /// Instead we require them to implement `Into<anyhow::Error>`, which is available
 /// both for any type that implements [std::error::Error] and anyhow itself.
+#[cfg(feature = "std")]
 pub trait RpcError: Debug + Display + Into<anyhow::Error> + Send + Sync + Unpin + 'static {}
 
+#[cfg(feature = "std")]
 impl<T> RpcError for T where T: Debug + Display + Into<anyhow::Error> + Send + Sync + Unpin + 'static
 {}
 
+/// An [RpcError] seen as a [std::error::Error], to return it from
+/// [std::error::Error::source] of the errors that wrap it.
+///
+/// An [RpcError] does not have to implement [std::error::Error], so it can be an
+/// `anyhow::Error`. Its own source is not available through this.
+#[cfg(feature = "std")]
+#[derive(ref_cast::RefCast)]
+#[repr(transparent)]
+pub(crate) struct ErrorSource<E>(E);
+
+#[cfg(feature = "std")]
+impl<E: RpcError> ErrorSource<E> {
+    pub(crate) fn new(error: &E) -> &(dyn std::error::Error + 'static) {
+        <Self as ref_cast::RefCast>::ref_cast(error)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: Debug> Debug for ErrorSource<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: Display> Display for ErrorSource<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E: Debug + Display> std::error::Error for ErrorSource<E> {}
+
 /// A service
 ///
 /// A service has request and response message types. These types have to be the
@@ -165,8 +235,10 @@ pub trait Service: Send + Sync + Debug + Clone + 'static {
 /// This is just a trait alias for a [`transport::Connector`] with the right types. It is used
 /// to make it easier to specify the bounds of a connector that matches a specific
 /// service.
+#[cfg(feature = "std")]
 pub trait Connector<S: Service>: transport::Connector<In = S::Res, Out = S::Req> {}
 
+#[cfg(feature = "std")]
 impl<T: transport::Connector<In = S::Res, Out = S::Req>, S: Service> Connector<S> for T {}
 
 /// A listener for a specific service
@@ -174,6 +246,8 @@ impl<T: transport::Connector<In = S::Res, Out = S::Req>, S: Service> Connector<S
 /// This is just a trait alias for a [`transport::Listener`] with the right types. It is used
 /// to make it easier to specify the bounds of a listener that matches a specific
 /// service.
+#[cfg(feature = "std")]
 pub trait Listener<S: Service>: transport::Listener<In = S::Req, Out = S::Res> {}
 
+#[cfg(feature = "std")]
 impl<T: transport::Listener<In = S::Req, Out = S::Res>, S: Service> Listener<S> for T {}
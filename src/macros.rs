@@ -40,6 +40,8 @@
 ///     // Optional, if not needed pass _ (underscore) as name.
 ///     CreateDispatch = create_my_dispatch;
 ///     // Name of the macro to create an RPC client.
+///     // Optional, can be omitted or passed as _ (underscore).
+///     CreateClient = create_my_client;
 ///
 ///     Rpc add = Add, _ -> Sum;
 ///     BidiStreaming multiply = Multiply, MultiplyUpdate -> MultiplyOutput
@@ -47,24 +49,28 @@
 /// ```
 ///
 /// This will generate a request enum `MyRequest`, a response enum `MyRespone`
-/// and a service declaration `MyService`.
+/// and a service declaration `MyService`. The service implements
+/// [Reflect](crate::reflection::Reflect), so it can describe its messages to clients
+/// if it also declares a [Describe](crate::reflection::Describe) message, see the
+/// [reflection](crate::reflection) module.
 ///
 /// It will also generate two macros to create an RPC client and a dispatch function.
 ///
 /// To use the client, invoke the macro with a name. The macro will generate a struct that
-/// takes a client channel and exposes typesafe methods for each RPC method.
+/// wraps a [RpcClient](crate::RpcClient) and exposes typesafe methods for each RPC method,
+/// named like the method in the declaration. Each method uses the interaction pattern of
+/// its message, so the pattern and the response type can not be mixed up.
 ///
 /// ```ignore
-/// create_store_client!(MyClient);
-/// let client = quic_rpc::quinn::Channel::new(client);
-/// let client = quic_rpc::client::RpcClient::<MyService, _>::new(client);
-/// let mut client = MyClient(client);
+/// create_my_client!(MyClient);
+/// let client = quic_rpc::client::RpcClient::<MyService, _>::new(connector);
+/// let client = MyClient(client);
 /// let sum = client.add(Add(3, 4)).await?;
 /// // Sum(7)
-/// let (send, mut recv) = client.multiply(Multiply(2));
-/// send(Update(3));
-/// let res = recv.next().await?;
-/// // Some(MultiplyOutput(6))
+/// let (mut send, mut recv) = client.multiply(Multiply(2)).await?;
+/// send.send(MultiplyUpdate(3)).await?;
+/// let res = recv.next().await;
+/// // Some(Ok(MultiplyOutput(6)))
 /// ```
 ///
 /// To use the dispatch function, invoke the macro with a struct that implements your RPC
@@ -140,7 +146,149 @@ macro_rules! rpc_service {
 
         $($m_pattern:ident $m_name:ident = $m_input:ident, $m_update:tt -> $m_output:ident);+$(;)?
     ) => {
+        $crate::rpc_service! {
+            Request = $request;
+            Response = $response;
+            Service = $service;
+            CreateDispatch = $create_dispatch;
+            CreateClient = _;
 
+            $($m_pattern $m_name = $m_input, $m_update -> $m_output);+
+        }
+    };
+    (
+        Request = $request:ident;
+        Response = $response:ident;
+        Service = $service:ident;
+        CreateDispatch = $create_dispatch:tt;
+        CreateClient = $create_client:tt;
+
+        $($m_pattern:ident $m_name:ident = $m_input:ident, $m_update:tt -> $m_output:ident);+$(;)?
+    ) => {
+
+        $crate::__service_types!(
+            $service,
+            $request,
+            $response,
+            [ $($m_pattern $m_input, $m_update -> $m_output);+ ]
+        );
+
+        $crate::__derive_create_dispatch!(
+            $service,
+            $request,
+            $create_dispatch,
+            [ $($m_pattern $m_name = $m_input, $m_update -> $m_output);+ ]
+        );
+
+        $crate::__derive_create_client!(
+            $service,
+            $create_client,
+            [ $($m_pattern $m_name = $m_input, $m_update -> $m_output);+ ]
+        );
+    };
+}
+
+/// Define a service and its request and response enums from a list of messages.
+///
+/// This is a shorter form of [rpc_service](crate::rpc_service), for services that
+/// dispatch requests by hand and use the [RpcClient](crate::RpcClient) directly. Each
+/// line declares a request type, its interaction pattern, the update type for the
+/// patterns with updates, and the response type:
+///
+/// ```
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Get;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Value;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct List;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Entry;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Upload;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Chunk;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Uploaded;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Replicate;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Edit;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Replicated;
+/// quic_rpc::define_service! {
+///     Service = StoreService;
+///     Request = StoreRequest;
+///     Response = StoreResponse;
+///
+///     Get: Rpc -> Value;
+///     List: ServerStreaming -> Entry;
+///     Upload: ClientStreaming<Chunk> -> Uploaded;
+///     Replicate: BidiStreaming<Edit> -> Replicated;
+/// }
+/// ```
+///
+/// This generates:
+///
+/// - the service struct, implementing [Service](crate::Service) and
+///   [Reflect](crate::reflection::Reflect)
+/// - the request enum, with a variant for every request and update type
+/// - the response enum, with a variant for every response type
+/// - `From` and `TryFrom` between the enums and their variants
+/// - the [Msg](crate::message::Msg) impl and the message trait of the pattern for every
+///   request type, e.g. [RpcMsg](crate::message::RpcMsg) for `Get`
+///
+/// The variants are named like their types, so every type can only occur once in each
+/// enum.
+#[macro_export]
+macro_rules! define_service {
+    (
+        Service = $service:ident;
+        Request = $request:ident;
+        Response = $response:ident;
+
+        $($messages:tt)+
+    ) => {
+        $crate::__define_service!(@ [$service $request $response] [] $($messages)+);
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __define_service {
+    // A message without updates
+    (
+        @ $names:tt [$($acc:tt)*]
+        $m_input:ident : $m_pattern:ident -> $m_output:ident $(; $($rest:tt)*)?
+    ) => {
+        $crate::__define_service!(
+            @ $names [$($acc)* $m_pattern $m_input, _ -> $m_output;] $($($rest)*)?
+        );
+    };
+    // A message with updates
+    (
+        @ $names:tt [$($acc:tt)*]
+        $m_input:ident : $m_pattern:ident < $m_update:ident > -> $m_output:ident $(; $($rest:tt)*)?
+    ) => {
+        $crate::__define_service!(
+            @ $names [$($acc)* $m_pattern $m_input, $m_update -> $m_output;] $($($rest)*)?
+        );
+    };
+    // All messages are collected
+    (
+        @ [$service:ident $request:ident $response:ident]
+        [$($m_pattern:ident $m_input:ident, $m_update:tt -> $m_output:ident;)+]
+    ) => {
+        $crate::__service_types!(
+            $service,
+            $request,
+            $response,
+            [ $($m_pattern $m_input, $m_update -> $m_output);+ ]
+        );
+    };
+}
+
+/// The types of a service, shared by [rpc_service] and [define_service]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __service_types {
+    (
+        $service:ident,
+        $request:ident,
+        $response:ident,
+        [ $($m_pattern:ident $m_input:ident, $m_update:tt -> $m_output:ident);+ ]
+    ) => {
         $crate::__request_enum! {
             $service,
             $request {
@@ -169,12 +317,171 @@ macro_rules! rpc_service {
             type Res = $response;
         }
 
-        $crate::__derive_create_dispatch!(
-            $service,
-            $request,
-            $create_dispatch,
-            [ $($m_pattern $m_name = $m_input, $m_update -> $m_output);+ ]
-        );
+        impl $crate::reflection::Reflect for $service {
+            fn descriptor() -> $crate::reflection::ServiceDescriptor {
+                $crate::reflection::ServiceDescriptor {
+                    name: stringify!($service).into(),
+                    messages: ::std::vec![$(
+                        $crate::reflection::MessageDescriptor {
+                            name: stringify!($m_input).into(),
+                            pattern: $crate::reflection::MessagePattern::$m_pattern,
+                            update: $crate::__update_name!($m_update),
+                            response: stringify!($m_output).into(),
+                        },
+                    )*],
+                }
+            }
+        }
+    };
+}
+
+/// Dispatch the first request of a channel to a handler for its variant.
+///
+/// Takes the channel, the request, the target that is passed to the handlers, and an
+/// arm for every variant of the request enum. An arm names the pattern method of
+/// [RpcChannel](crate::server::RpcChannel) that handles the variant, like `rpc` or
+/// `server_streaming`, together with the handler. Variants that can not start an
+/// interaction, like the updates of a streaming message, are marked as `unexpected` and
+/// fail with [RpcServerError::UnexpectedStartMessage](crate::server::RpcServerError).
+/// The arms become a `match` without a wildcard, so adding a message to the service
+/// without handling it is a compile error.
+///
+/// The macro is an expression that awaits the pattern method, so it has to be used in
+/// an async context. It evaluates to the result of the pattern method.
+///
+/// ```
+/// # use futures_lite::{Stream, StreamExt};
+/// # use quic_rpc::{server::{RpcChannel, RpcServerError}, Listener};
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Get;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Add;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Increment;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Value(u64);
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Total(u64);
+/// # quic_rpc::define_service! {
+/// #     Service = CounterService;
+/// #     Request = CounterRequest;
+/// #     Response = CounterResponse;
+/// #
+/// #     Get: Rpc -> Value;
+/// #     Add: ClientStreaming<Increment> -> Total;
+/// # }
+/// # #[derive(Clone)]
+/// # struct Counter;
+/// # impl Counter {
+/// #     async fn get(self, _req: Get) -> Value {
+/// #         Value(0)
+/// #     }
+/// #     async fn add(self, _req: Add, updates: impl Stream<Item = Increment>) -> Total {
+/// #         Total(updates.count().await as u64)
+/// #     }
+/// # }
+/// async fn dispatch<C: Listener<CounterService>>(
+///     req: CounterRequest,
+///     chan: RpcChannel<CounterService, C>,
+/// ) -> Result<(), RpcServerError<C>> {
+///     quic_rpc::handle_request!(chan, req, Counter, {
+///         CounterRequest::Get => rpc(Counter::get),
+///         CounterRequest::Add => client_streaming(Counter::add),
+///         CounterRequest::Increment => unexpected,
+///     })
+/// }
+/// ```
+///
+/// Leaving out a variant does not compile:
+///
+/// ```compile_fail
+/// # use futures_lite::{Stream, StreamExt};
+/// # use quic_rpc::{server::{RpcChannel, RpcServerError}, Listener};
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Get;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Add;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Increment;
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Value(u64);
+/// # #[derive(Debug, Serialize, Deserialize)] pub struct Total(u64);
+/// # quic_rpc::define_service! {
+/// #     Service = CounterService;
+/// #     Request = CounterRequest;
+/// #     Response = CounterResponse;
+/// #
+/// #     Get: Rpc -> Value;
+/// #     Add: ClientStreaming<Increment> -> Total;
+/// # }
+/// # #[derive(Clone)]
+/// # struct Counter;
+/// # impl Counter {
+/// #     async fn get(self, _req: Get) -> Value {
+/// #         Value(0)
+/// #     }
+/// #     async fn add(self, _req: Add, updates: impl Stream<Item = Increment>) -> Total {
+/// #         Total(updates.count().await as u64)
+/// #     }
+/// # }
+/// async fn dispatch<C: Listener<CounterService>>(
+///     req: CounterRequest,
+///     chan: RpcChannel<CounterService, C>,
+/// ) -> Result<(), RpcServerError<C>> {
+///     quic_rpc::handle_request!(chan, req, Counter, {
+///         CounterRequest::Get => rpc(Counter::get),
+///         CounterRequest::Add => client_streaming(Counter::add),
+///     })
+/// }
+/// ```
+///
+/// Several variants can share an arm, e.g. `Request::AddUpdate | Request::MulUpdate =>
+/// unexpected`.
+#[macro_export]
+macro_rules! handle_request {
+    ($chan:expr, $req:expr, $target:expr, { $($arms:tt)+ }) => {{
+        let chan = $chan;
+        let target = $target;
+        $crate::__handle_request!(@ [chan target $req] [] $($arms)+)
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __handle_request {
+    // Variants that can not start an interaction
+    (
+        @ $names:tt [$($acc:tt)*]
+        $($variant:path)|+ => unexpected $(, $($rest:tt)*)?
+    ) => {
+        $crate::__handle_request!(
+            @ $names
+            [$($acc)* $($variant(_))|+ => ::std::result::Result::Err(
+                $crate::server::RpcServerError::UnexpectedStartMessage
+            ),]
+            $($($rest)*)?
+        )
+    };
+    // Variants that are handled by a pattern method
+    (
+        @ [$chan:ident $target:ident $req:expr] [$($acc:tt)*]
+        $($variant:path)|+ => $method:ident($handler:expr) $(, $($rest:tt)*)?
+    ) => {
+        $crate::__handle_request!(
+            @ [$chan $target $req]
+            [$($acc)* $($variant(msg))|+ => $chan.$method(msg, $target, $handler).await,]
+            $($($rest)*)?
+        )
+    };
+    // All arms are collected
+    (@ [$chan:ident $target:ident $req:expr] [$($acc:tt)*]) => {
+        match $req {
+            $($acc)*
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __update_name {
+    (_) => {
+        None
+    };
+    ($m_update:ident) => {
+        Some(stringify!($m_update).into())
     };
 }
 
@@ -432,11 +739,14 @@ macro_rules! __derive_create_client{
         #[doc = concat!("Create an RPC client for ", stringify!($service), "\n\nSee the docs for [quic_rpc::rpc_service] for usage docs.")]
         #[macro_export]
         macro_rules! $create_client {
-            ($struct:ident) => {
+            ($client:ident) => {
+                #[doc = concat!("Typed client for ", stringify!($service))]
                 #[derive(::std::clone::Clone, ::std::fmt::Debug)]
-                pub struct $struct<C: $crate::Listener<$service>>(pub $crate::client::RpcClient<$service, C>);
+                pub struct $client<C: $crate::Connector<$service> = $crate::client::BoxedConnector<$service>>(
+                    pub $crate::client::RpcClient<$service, C>,
+                );
 
-                impl<C: $crate::Listener<$service>> $struct<C> {
+                impl<C: $crate::Connector<$service>> $client<C> {
                     $(
                         $crate::__rpc_method!($m_pattern, $service, $m_name, $m_input, $m_output, $m_update);
                     )*
@@ -450,57 +760,68 @@ macro_rules! __derive_create_client{
 #[macro_export]
 macro_rules! __rpc_method {
     (Rpc, $service:ident, $m_name:ident, $m_input:ident, $m_output:ident, _) => {
+        #[doc = concat!("Rpc call with a [", stringify!($m_input), "] request")]
         pub async fn $m_name(
-            &mut self,
+            &self,
             input: $m_input,
-        ) -> ::std::result::Result<$m_output, $crate::client::RpcClientError<C>> {
+        ) -> ::std::result::Result<$m_output, $crate::client::CallError<C>> {
             self.0.rpc(input).await
         }
     };
     (ClientStreaming, $service:ident, $m_name:ident, $m_input:ident, $m_output:ident, $m_update:ident) => {
+        #[doc = concat!("Client streaming call with a [", stringify!($m_input), "] request")]
         pub async fn $m_name(
-            &mut self,
+            &self,
             input: $m_input,
         ) -> ::std::result::Result<
             (
-                $crate::client::UpdateSink<$service, C, $m_input>,
-                ::futures::future::BoxFuture<
-                    'static,
-                    ::std::result::Result<$m_output, $crate::client::ClientStreamingItemError<C>>,
+                $crate::client::UpdateSink<C, $m_update>,
+                ::std::pin::Pin<
+                    ::std::boxed::Box<
+                        dyn ::std::future::Future<
+                                Output = ::std::result::Result<
+                                    $m_output,
+                                    $crate::client::CallError<C>,
+                                >,
+                            > + ::std::marker::Send
+                            + 'static,
+                    >,
                 >,
             ),
-            $crate::client::ClientStreamingError<C>,
+            $crate::client::CallError<C>,
         > {
             self.0.client_streaming(input).await
         }
     };
     (ServerStreaming, $service:ident, $m_name:ident, $m_input:ident, $m_output:ident, _) => {
+        #[doc = concat!("Server streaming call with a [", stringify!($m_input), "] request")]
         pub async fn $m_name(
-            &mut self,
+            &self,
             input: $m_input,
         ) -> ::std::result::Result<
-            ::futures::stream::BoxStream<
+            $crate::client::BoxStreamSync<
                 'static,
-                ::std::result::Result<$m_output, $crate::client::StreamingResponseItemError<C>>,
+                ::std::result::Result<$m_output, $crate::client::CallError<C>>,
             >,
-            $crate::client::StreamingResponseError<C>,
+            $crate::client::CallError<C>,
         > {
             self.0.server_streaming(input).await
         }
     };
     (BidiStreaming, $service:ident, $m_name:ident, $m_input:ident, $m_output:ident, $m_update:ident) => {
+        #[doc = concat!("Bidi streaming call with a [", stringify!($m_input), "] request")]
         pub async fn $m_name(
-            &mut self,
+            &self,
             input: $m_input,
         ) -> ::std::result::Result<
             (
-                $crate::client::UpdateSink<$service, C, $m_input>,
-                ::futures::stream::BoxStream<
+                $crate::client::UpdateSink<C, $m_update>,
+                $crate::client::BoxStreamSync<
                     'static,
-                    ::std::result::Result<$m_output, $crate::client::BidiItemError<C>>,
+                    ::std::result::Result<$m_output, $crate::client::CallError<C>>,
                 >,
             ),
-            $crate::client::BidiError<C>,
+            $crate::client::CallError<C>,
         > {
             self.0.bidi(input).await
         }
@@ -1,14 +1,15 @@
 //! Service definition
 //!
 //! Traits to define the behaviour of messages for services
-use std::fmt::Debug;
-
-pub use crate::pattern::{
-    bidi_streaming::{BidiStreaming, BidiStreamingMsg},
-    client_streaming::{ClientStreaming, ClientStreamingMsg},
-    rpc::{Rpc, RpcMsg},
-    server_streaming::{ServerStreaming, ServerStreamingMsg},
-};
+//!
+//! Everything in this module is available without the `std` feature, so a crate
+//! that only defines the messages of a service can be shared with `no_std` targets.
+//! The client and server side of each pattern is in the `pattern` module, which
+//! needs std.
+use core::{fmt::Debug, result};
+
+use serde::{Deserialize, Serialize};
+
 use crate::Service;
 
 /// Declares the interaction pattern for a message and a service.
@@ -29,3 +30,265 @@ pub trait Msg<S: Service>: Into<S::Req> + TryFrom<S::Req> + Send + 'static {
 ///
 /// You could define your own interaction patterns such as OneWay.
 pub trait InteractionPattern: Debug + Clone + Send + Sync + 'static {}
+
+/// Rpc interaction pattern
+///
+/// There is only one request and one response.
+#[derive(Debug, Clone, Copy)]
+pub struct Rpc;
+impl InteractionPattern for Rpc {}
+
+/// Defines the response type for a rpc message.
+///
+/// Since this is the most common interaction pattern, this also implements [Msg] for you
+/// automatically, with the interaction pattern set to [Rpc]. This is to reduce boilerplate
+/// when defining rpc messages.
+pub trait RpcMsg<S: Service>: Msg<S, Pattern = Rpc> {
+    /// The type for the response
+    ///
+    /// For requests that can produce errors, this can be set to [Result<T, E>](core::result::Result).
+    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
+}
+
+/// We can only do this for one trait, so we do it for RpcMsg since it is the most common
+impl<T: RpcMsg<S>, S: Service> Msg<S> for T {
+    type Pattern = Rpc;
+}
+
+/// Fallible rpc interaction pattern
+///
+/// There is only one request and one response, which is either a success value or
+/// an application error.
+#[derive(Debug, Clone, Copy)]
+pub struct Fallible;
+
+impl InteractionPattern for Fallible {}
+
+/// Same as RpcMsg, but with the application error type explicitly defined.
+///
+/// On the wire, the response is a [Result<Self::Response, Self::AppError>](core::result::Result)
+/// that is part of `S::Res`.
+pub trait FallibleMsg<S: Service>: Msg<S, Pattern = Fallible>
+where
+    result::Result<Self::Response, Self::AppError>: Into<S::Res> + TryFrom<S::Res>,
+{
+    /// The type for a successful response
+    type Response: Send + 'static;
+
+    /// The application error type
+    type AppError: Debug + Send + 'static;
+}
+
+/// Client streaming interaction pattern
+///
+/// After the initial request, the client can send updates, but there is only
+/// one response.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientStreaming;
+impl InteractionPattern for ClientStreaming {}
+
+/// Defines update type and response type for a client streaming message.
+pub trait ClientStreamingMsg<S: Service>: Msg<S, Pattern = ClientStreaming> {
+    /// The type for request updates
+    ///
+    /// For a request that does not support updates, this can be safely set to any type, including
+    /// the message type itself. Any update for such a request will result in an error.
+    type Update: Into<S::Req> + TryFrom<S::Req> + Send + 'static;
+
+    /// The type for the response
+    ///
+    /// For requests that can produce errors, this can be set to [Result<T, E>](core::result::Result).
+    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
+}
+
+/// A message from the server to the client of a client streaming request whose updates
+/// are acknowledged.
+///
+/// The server sends any number of [AckedResponse::Ack] while it reads the updates, and a
+/// single [AckedResponse::Response] at the end. For a message `M` to be used this way,
+/// `AckedResponse<M::Response>` has to be convertible to and from the response type of
+/// the service, just like `M::Response`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AckedResponse<R> {
+    /// The update with this sequence number was handled
+    ///
+    /// Updates are numbered from 0 in the order the client sent them.
+    Ack(u64),
+    /// The final response
+    Response(R),
+}
+
+/// Defines a reason with which the server can abort a client streaming request, while
+/// the client is still sending updates.
+///
+/// See `RpcClient::client_streaming_abortable` and
+/// `RpcChannel::client_streaming_abortable`.
+pub trait ClientStreamingAbortMsg<S: Service>: ClientStreamingMsg<S> {
+    /// The type for the abort reason
+    type Abort: Debug + Clone + Send + 'static;
+}
+
+/// A message from the server to the client of a client streaming request that the
+/// server can abort.
+///
+/// The server sends either the final [AbortableResponse::Response], or an
+/// [AbortableResponse::Aborted] after which it stops reading updates. For a message `M`
+/// to be used this way, `AbortableResponse<M::Response, M::Abort>` has to be convertible
+/// to and from the response type of the service, just like `M::Response`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AbortableResponse<R, A> {
+    /// The final response
+    Response(R),
+    /// The server aborted the request with this reason
+    Aborted(A),
+}
+
+/// Server streaming interaction pattern
+///
+/// After the initial request, the server can send a stream of responses.
+#[derive(Debug, Clone, Copy)]
+pub struct ServerStreaming;
+impl InteractionPattern for ServerStreaming {}
+
+/// Defines response type for a server streaming message.
+pub trait ServerStreamingMsg<S: Service>: Msg<S, Pattern = ServerStreaming> {
+    /// The type for the response
+    ///
+    /// For requests that can produce errors, this can be set to [Result<T, E>](core::result::Result).
+    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
+}
+
+/// Defines a header for a server streaming message, that is sent once before the
+/// responses.
+///
+/// This is for metadata like the total number of items, so that it does not have to be
+/// carried by every response. See `RpcClient::server_streaming_with_header` and
+/// `RpcChannel::server_streaming_with_header`.
+pub trait ServerStreamingHeaderMsg<S: Service>: ServerStreamingMsg<S> {
+    /// The type for the header
+    type Header: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
+}
+
+/// Tells the server where a resumable server streaming request continues.
+///
+/// The client sends this right after the request, see
+/// `RpcClient::server_streaming_resumable` and `RpcChannel::server_streaming_resumable`.
+/// For a message to be used this way, this has to be convertible to and from the
+/// request type of the service.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Resume {
+    /// The sequence number of the last response the client received, `None` for a new
+    /// request
+    pub last: Option<u64>,
+}
+
+impl Resume {
+    /// The sequence number of the first response to send
+    pub fn next_seq(&self) -> u64 {
+        self.last.map_or(0, |last| last + 1)
+    }
+}
+
+/// A message from the server to the client of a resumable server streaming request.
+///
+/// The server sends the responses as [ResumableResponse::Item], numbered from 0 in the
+/// order the handler produced them, and [ResumableResponse::End] once there are no more.
+/// For a message `M` to be used this way, `ResumableResponse<M::Response>` has to be
+/// convertible to and from the response type of the service, just like `M::Response`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResumableResponse<R> {
+    /// A response with its sequence number
+    Item {
+        /// The sequence number of the response
+        seq: u64,
+        /// The response
+        item: R,
+    },
+    /// The stream is complete
+    End,
+}
+
+/// Grants the server of a server streaming request credits to send more responses.
+///
+/// The client sends the initial credit window right after the request, and more credits
+/// as it consumes the responses, see `RpcClient::server_streaming_with_credits` and
+/// `RpcChannel::server_streaming_with_credits`. The server sends one response per
+/// credit, and waits once it has used them all. For a message to be used this way, this
+/// has to be convertible to and from the request type of the service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Credit(pub u64);
+
+/// A guard message to indicate that the stream has been created.
+///
+/// This is so we can dinstinguish between an error creating the stream and
+/// an error in the first item produced by the stream.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct StreamCreated;
+
+/// Fallible server streaming interaction pattern.
+#[derive(Debug, Clone, Copy)]
+pub struct TryServerStreaming;
+
+impl InteractionPattern for TryServerStreaming {}
+
+/// Same as ServerStreamingMsg, but with lazy stream creation and the error type explicitly defined.
+pub trait TryServerStreamingMsg<S: Service>: Msg<S, Pattern = TryServerStreaming>
+where
+    result::Result<Self::Item, Self::ItemError>: Into<S::Res> + TryFrom<S::Res>,
+    result::Result<StreamCreated, Self::CreateError>: Into<S::Res> + TryFrom<S::Res>,
+{
+    /// Error when creating the stream
+    type CreateError: Debug + Send + 'static;
+
+    /// Error for stream items
+    type ItemError: Debug + Send + 'static;
+
+    /// Successful response item
+    type Item: Send + 'static;
+}
+
+/// Bidirectional streaming interaction pattern
+///
+/// After the initial request, the client can send updates and the server can
+/// send responses.
+#[derive(Debug, Clone, Copy)]
+pub struct BidiStreaming;
+impl InteractionPattern for BidiStreaming {}
+
+/// Defines update type and response type for a bidi streaming message.
+pub trait BidiStreamingMsg<S: Service>: Msg<S, Pattern = BidiStreaming> {
+    /// The type for request updates
+    ///
+    /// For a request that does not support updates, set this to [NoUpdates]. The client
+    /// can then not send any update, see `RpcClient::bidi_without_updates`.
+    type Update: Into<S::Req> + TryFrom<S::Req> + Send + 'static;
+
+    /// The type for the response
+    ///
+    /// For requests that can produce errors, this can be set to [Result<T, E>](core::result::Result).
+    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
+}
+
+/// The update type of a bidi streaming message that does not support updates
+///
+/// This type has no values, so no update can be created, sent or received. A request
+/// enum needs a variant for it to provide the conversions, which is never sent:
+///
+/// ```
+/// use derive_more::{From, TryInto};
+/// use quic_rpc::message::NoUpdates;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Serialize, Deserialize)]
+/// struct Subscribe;
+///
+/// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+/// enum Request {
+///     Subscribe(Subscribe),
+///     NoUpdates(NoUpdates),
+/// }
+/// ```
+///
+/// An update that a client sends anyway fails to decode on the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NoUpdates {}
@@ -0,0 +1,167 @@
+//! Lightweight metrics for servers
+//!
+//! A [ServerMetrics] can be attached to a [RpcServer](crate::RpcServer) using
+//! [RpcServer::with_metrics](crate::RpcServer::with_metrics). All values are plain
+//! atomic counters, so they can be scraped into any metrics system.
+//!
+//! The rpc layer only sees typed messages, so it can not count bytes. For the transports
+//! that serialize messages, bytes can be counted by wrapping the codec in a
+//! [MeteredCodec](crate::transport::codec::MeteredCodec) that shares the
+//! [ByteCounters] of the metrics. Likewise, a listener that evicts idle connections can
+//! share the [ConnectionCounters] of the metrics, see
+//! `QuinnListener::with_idle_eviction`.
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// A monotonically increasing counter
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    /// The current value
+    pub fn load(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn inc(&self) {
+        self.add(1);
+    }
+
+    pub(crate) fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+/// A value that can go up and down
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+    /// The current value
+    pub fn load(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Increment the gauge, and decrement it again when the guard is dropped
+    fn guard(&self) -> GaugeGuard<'_> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        GaugeGuard(self)
+    }
+}
+
+/// Decrements a [Gauge] on drop, including when unwinding from a panic
+struct GaugeGuard<'a>(&'a Gauge);
+
+impl Drop for GaugeGuard<'_> {
+    fn drop(&mut self) {
+        self.0 .0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// The interaction patterns, for per pattern metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pattern {
+    /// [Rpc](crate::pattern::rpc::Rpc)
+    Rpc,
+    /// [ServerStreaming](crate::pattern::server_streaming::ServerStreaming)
+    ServerStreaming,
+    /// [ClientStreaming](crate::pattern::client_streaming::ClientStreaming)
+    ClientStreaming,
+    /// [BidiStreaming](crate::pattern::bidi_streaming::BidiStreaming)
+    BidiStreaming,
+    /// [TryServerStreaming](crate::pattern::try_server_streaming::TryServerStreaming)
+    TryServerStreaming,
+    /// [Fallible](crate::pattern::fallible::Fallible)
+    Fallible,
+}
+
+impl Pattern {
+    /// All patterns
+    pub const ALL: [Pattern; 6] = [
+        Pattern::Rpc,
+        Pattern::ServerStreaming,
+        Pattern::ClientStreaming,
+        Pattern::BidiStreaming,
+        Pattern::TryServerStreaming,
+        Pattern::Fallible,
+    ];
+
+    /// The name of the pattern, e.g. for use as a metrics label
+    pub fn name(self) -> &'static str {
+        match self {
+            Pattern::Rpc => "rpc",
+            Pattern::ServerStreaming => "server_streaming",
+            Pattern::ClientStreaming => "client_streaming",
+            Pattern::BidiStreaming => "bidi_streaming",
+            Pattern::TryServerStreaming => "try_server_streaming",
+            Pattern::Fallible => "rpc_fallible",
+        }
+    }
+}
+
+/// Metrics for a single interaction pattern
+#[derive(Debug, Default)]
+pub struct PatternMetrics {
+    /// Number of handled requests
+    pub requests: Counter,
+    /// Number of requests where handling failed, e.g. because sending the response failed
+    pub errors: Counter,
+}
+
+/// Bytes sent and received
+#[derive(Debug, Default)]
+pub struct ByteCounters {
+    /// Bytes of serialized messages sent
+    pub sent: Counter,
+    /// Bytes of serialized messages received
+    pub received: Counter,
+}
+
+/// Events of the connections of a listener
+#[derive(Debug, Default)]
+pub struct ConnectionCounters {
+    /// Number of connections that were closed because they were idle
+    pub evicted: Counter,
+}
+
+/// Metrics for a [RpcServer](crate::RpcServer)
+#[derive(Debug, Default)]
+pub struct ServerMetrics {
+    patterns: [PatternMetrics; 6],
+    /// Number of requests that are currently being handled
+    pub in_flight: Gauge,
+    /// Bytes sent and received, if counted by the transport
+    pub bytes: Arc<ByteCounters>,
+    /// Connection events, if counted by the transport
+    pub connections: Arc<ConnectionCounters>,
+}
+
+impl ServerMetrics {
+    /// Metrics for the given pattern
+    pub fn pattern(&self, pattern: Pattern) -> &PatternMetrics {
+        &self.patterns[pattern as usize]
+    }
+
+    /// Track the handling of a request with the given pattern
+    pub(crate) async fn track<T, E>(
+        metrics: Option<Arc<ServerMetrics>>,
+        pattern: Pattern,
+        fut: impl std::future::Future<Output = Result<T, E>>,
+    ) -> Result<T, E> {
+        let Some(metrics) = metrics else {
+            return fut.await;
+        };
+        let pattern = metrics.pattern(pattern);
+        pattern.requests.inc();
+        let res = {
+            let _guard = metrics.in_flight.guard();
+            fut.await
+        };
+        if res.is_err() {
+            pattern.errors.inc();
+        }
+        res
+    }
+}
@@ -1,45 +1,35 @@
 //! Bidirectional stream interaction pattern.
 
 use std::{
-    error,
-    fmt::{self, Debug},
+    error, fmt,
+    future::Future,
+    pin::Pin,
     result,
+    sync::{Arc, Mutex, PoisonError},
+    task::{Context, Poll},
+    time::Duration,
 };
 
-use futures_lite::{Stream, StreamExt};
+use futures_lite::{future::Boxed, Stream, StreamExt};
 use futures_util::{FutureExt, SinkExt};
 
+use super::server_streaming::Timer;
 use crate::{
-    client::{BoxStreamSync, UpdateSink},
-    message::{InteractionPattern, Msg},
-    server::{race2, RpcChannel, RpcServerError, UpdateStream},
+    client::{BoxStreamSync, CallError, IdleWatch, UpdateError, UpdateSink},
+    metrics::Pattern,
+    server::{
+        client_gone, instrument, race2, respond_to_error, send_all, send_responses, ResponseSender,
+        RpcChannel, RpcServerError, UpdateStream,
+    },
     transport::{ConnectionErrors, Connector, StreamTypes},
-    RpcClient, Service,
+    ErrorSource, RpcClient, Service,
 };
 
-/// Bidirectional streaming interaction pattern
-///
-/// After the initial request, the client can send updates and the server can
-/// send responses.
-#[derive(Debug, Clone, Copy)]
-pub struct BidiStreaming;
-impl InteractionPattern for BidiStreaming {}
-
-/// Defines update type and response type for a bidi streaming message.
-pub trait BidiStreamingMsg<S: Service>: Msg<S, Pattern = BidiStreaming> {
-    /// The type for request updates
-    ///
-    /// For a request that does not support updates, this can be safely set to any type, including
-    /// the message type itself. Any update for such a request will result in an error.
-    type Update: Into<S::Req> + TryFrom<S::Req> + Send + 'static;
+pub use crate::message::{BidiStreaming, BidiStreamingMsg, NoUpdates};
 
-    /// The type for the response
-    ///
-    /// For requests that can produce errors, this can be set to [Result<T, E>](std::result::Result).
-    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
-}
-
-/// Server error when accepting a bidi request
+/// Client error when opening a bidi request
+///
+/// The client methods return a [CallError], this can be converted to one.
 #[derive(Debug)]
 pub enum Error<C: ConnectionErrors> {
     /// Unable to open a substream at all
@@ -50,13 +40,34 @@ pub enum Error<C: ConnectionErrors> {
 
 impl<C: ConnectionErrors> fmt::Display for Error<C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        match self {
+            Self::Open(_) => write!(f, "failed to open a substream"),
+            Self::Send(_) => write!(f, "failed to send the request"),
+        }
+    }
+}
+
+impl<C: ConnectionErrors> error::Error for Error<C> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Open(cause) => Some(ErrorSource::new(cause)),
+            Self::Send(cause) => Some(ErrorSource::new(cause)),
+        }
     }
 }
 
-impl<C: ConnectionErrors> error::Error for Error<C> {}
+impl<C: ConnectionErrors, A> From<Error<C>> for CallError<C, A> {
+    fn from(e: Error<C>) -> Self {
+        match e {
+            Error::Open(cause) => CallError::Open(cause),
+            Error::Send(cause) => CallError::Send(cause),
+        }
+    }
+}
 
-/// Server error when receiving an item for a bidi request
+/// Client error when receiving a response of a bidi request
+///
+/// The client methods return a [CallError], this can be converted to one.
 #[derive(Debug)]
 pub enum ItemError<C: ConnectionErrors> {
     /// Unable to receive the response from the server
@@ -67,11 +78,30 @@ pub enum ItemError<C: ConnectionErrors> {
 
 impl<C: ConnectionErrors> fmt::Display for ItemError<C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        match self {
+            Self::RecvError(_) => write!(f, "failed to receive a response"),
+            Self::DowncastError => write!(f, "unexpected response from the server"),
+        }
     }
 }
 
-impl<C: ConnectionErrors> error::Error for ItemError<C> {}
+impl<C: ConnectionErrors> error::Error for ItemError<C> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::RecvError(cause) => Some(ErrorSource::new(cause)),
+            Self::DowncastError => None,
+        }
+    }
+}
+
+impl<C: ConnectionErrors, A> From<ItemError<C>> for CallError<C, A> {
+    fn from(e: ItemError<C>) -> Self {
+        match e {
+            ItemError::RecvError(cause) => CallError::recv(cause),
+            ItemError::DowncastError => CallError::Downcast,
+        }
+    }
+}
 
 impl<S, C> RpcClient<S, C>
 where
@@ -79,29 +109,352 @@ where
     C: Connector<In = S::Res, Out = S::Req>,
 {
     /// Bidi call to the server, request opens a stream, response is a stream
+    ///
+    /// Dropping the response stream cancels the handler on the server, on transports
+    /// that can tell. Dropping the update sink just ends the updates.
     pub async fn bidi<M>(
         &self,
         msg: M,
     ) -> result::Result<
         (
             UpdateSink<C, M::Update>,
-            BoxStreamSync<'static, result::Result<M::Response, ItemError<C>>>,
+            BoxStreamSync<'static, result::Result<M::Response, CallError<C>>>,
         ),
-        Error<C>,
+        CallError<C>,
+    >
+    where
+        M: BidiStreamingMsg<S>,
+    {
+        self.bidi_with_capacity(msg, 0).await
+    }
+
+    /// Bidi call to the server, request opens a stream, response is a stream.
+    ///
+    /// The returned [UpdateSink] buffers up to `capacity` updates that the transport has
+    /// not accepted yet, see [UpdateSink::with_capacity].
+    pub async fn bidi_with_capacity<M>(
+        &self,
+        msg: M,
+        capacity: usize,
+    ) -> result::Result<
+        (
+            UpdateSink<C, M::Update>,
+            BoxStreamSync<'static, result::Result<M::Response, CallError<C>>>,
+        ),
+        CallError<C>,
     >
     where
         M: BidiStreamingMsg<S>,
     {
         let msg = msg.into();
-        let (mut send, recv) = self.source.open().await.map_err(Error::Open)?;
-        send.send(msg).await.map_err(Error::<C>::Send)?;
-        let send = UpdateSink::new(send);
+        let (mut send, recv) = self.source.open().await.map_err(CallError::Open)?;
+        send.send(msg).await.map_err(CallError::<C>::Send)?;
+        let send = UpdateSink::with_capacity(send, capacity);
         let recv = Box::pin(recv.map(move |x| match x {
-            Ok(msg) => M::Response::try_from(msg).map_err(|_| ItemError::DowncastError),
-            Err(e) => Err(ItemError::RecvError(e)),
+            Ok(msg) => M::Response::try_from(msg).map_err(|_| CallError::Downcast),
+            Err(e) => Err(CallError::recv(e)),
         }));
         Ok((send, recv))
     }
+
+    /// Bidi call to the server, that ends once neither side had any activity for `idle`
+    ///
+    /// Sending an update and receiving a response are activity. Once there was none for
+    /// `idle`, the response stream yields [CallError::Idle] and then terminates, and the
+    /// update sink fails with [UpdateError::Idle](crate::client::UpdateError::Idle).
+    /// Unlike a timeout for the whole call, a call that stays active can run
+    /// indefinitely. The timer is driven by polling the response stream.
+    ///
+    /// The timer is a glib timer, so the response stream has to be polled on the thread
+    /// that owns the glib main context, e.g. in [glib::MainContext::block_on] or a task
+    /// of a [glib::MainLoop], otherwise it panics.
+    pub async fn bidi_with_idle_timeout<M>(
+        &self,
+        msg: M,
+        idle: Duration,
+    ) -> result::Result<
+        (
+            UpdateSink<C, M::Update>,
+            BoxStreamSync<'static, result::Result<M::Response, CallError<C>>>,
+        ),
+        CallError<C>,
+    >
+    where
+        M: BidiStreamingMsg<S>,
+    {
+        let (send, recv) = self.bidi(msg).await?;
+        let watch = IdleWatch::new(idle);
+        let send = send.with_idle_watch(watch.clone());
+        let recv = Box::pin(IdleTimeout::new(recv, watch));
+        Ok((send, recv))
+    }
+
+    /// Bidi call to the server for a message without updates, response is a stream
+    ///
+    /// This is for messages whose [BidiStreamingMsg::Update] is [NoUpdates], where only
+    /// the server sends. The returned [NoUpdateSink] can not send anything, so sending an
+    /// update is a compile error instead of an error on the server.
+    ///
+    /// ```
+    /// use derive_more::{From, TryInto};
+    /// use futures::StreamExt;
+    /// use quic_rpc::{
+    ///     message::{BidiStreaming, BidiStreamingMsg, Msg, NoUpdates},
+    ///     RpcClient, Service,
+    /// };
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Debug, Serialize, Deserialize)]
+    /// struct Subscribe;
+    ///
+    /// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    /// enum Request {
+    ///     Subscribe(Subscribe),
+    ///     NoUpdates(NoUpdates),
+    /// }
+    ///
+    /// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    /// enum Response {
+    ///     Event(u64),
+    /// }
+    ///
+    /// #[derive(Debug, Clone)]
+    /// struct Events;
+    ///
+    /// impl Service for Events {
+    ///     type Req = Request;
+    ///     type Res = Response;
+    /// }
+    ///
+    /// impl Msg<Events> for Subscribe {
+    ///     type Pattern = BidiStreaming;
+    /// }
+    ///
+    /// impl BidiStreamingMsg<Events> for Subscribe {
+    ///     type Update = NoUpdates;
+    ///     type Response = u64;
+    /// }
+    ///
+    /// async fn subscribe(client: RpcClient<Events>) -> anyhow::Result<()> {
+    ///     let (_send, mut events) = client.bidi_without_updates(Subscribe).await?;
+    ///     while let Some(event) = events.next().await {
+    ///         println!("event {}", event?);
+    ///     }
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn bidi_without_updates<M>(
+        &self,
+        msg: M,
+    ) -> result::Result<
+        (
+            NoUpdateSink<C>,
+            BoxStreamSync<'static, result::Result<M::Response, CallError<C>>>,
+        ),
+        CallError<C>,
+    >
+    where
+        M: BidiStreamingMsg<S, Update = NoUpdates>,
+    {
+        let (send, recv) = self.bidi(msg).await?;
+        Ok((NoUpdateSink(send.0), recv))
+    }
+
+    /// Bidi call to the server with a fixed set of updates, response is a stream
+    ///
+    /// Sends the request and all `updates`, then closes the update side, so the handler
+    /// on the server sees the end of the updates. The updates are sent while the
+    /// responses are received, so a server that responds to every update does not stall.
+    /// If sending fails, the stream yields [CallError::Send] and goes on with the
+    /// responses that still arrive.
+    ///
+    /// Dropping the response stream cancels the handler on the server, like for
+    /// [RpcClient::bidi].
+    pub async fn batch_server_streaming<M>(
+        &self,
+        msg: M,
+        updates: impl IntoIterator<Item = M::Update>,
+    ) -> result::Result<
+        BoxStreamSync<'static, result::Result<M::Response, CallError<C>>>,
+        CallError<C>,
+    >
+    where
+        M: BidiStreamingMsg<S>,
+    {
+        let updates: Vec<_> = updates.into_iter().collect();
+        let (mut send, recv) = self.bidi(msg).await?;
+        let send = async move {
+            for update in updates {
+                send.send(update).await?;
+            }
+            send.close().await
+        };
+        Ok(Box::pin(BatchUpdates {
+            inner: recv,
+            send: Some(Mutex::new(Box::pin(send))),
+        }))
+    }
+}
+
+/// The update side of [RpcClient::bidi_without_updates], which can not send anything
+///
+/// Dropping it ends the updates, which does not affect the responses. Sending an update
+/// does not compile:
+///
+/// ```compile_fail
+/// use derive_more::{From, TryInto};
+/// use futures::StreamExt;
+/// use quic_rpc::{
+///     message::{BidiStreaming, BidiStreamingMsg, Msg, NoUpdates},
+///     RpcClient, Service,
+/// };
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Serialize, Deserialize)]
+/// struct Subscribe;
+///
+/// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+/// enum Request {
+///     Subscribe(Subscribe),
+///     NoUpdates(NoUpdates),
+/// }
+///
+/// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+/// enum Response {
+///     Event(u64),
+/// }
+///
+/// #[derive(Debug, Clone)]
+/// struct Events;
+///
+/// impl Service for Events {
+///     type Req = Request;
+///     type Res = Response;
+/// }
+///
+/// impl Msg<Events> for Subscribe {
+///     type Pattern = BidiStreaming;
+/// }
+///
+/// impl BidiStreamingMsg<Events> for Subscribe {
+///     type Update = NoUpdates;
+///     type Response = u64;
+/// }
+///
+/// async fn subscribe(client: RpcClient<Events>) -> anyhow::Result<()> {
+///     use futures::SinkExt;
+///
+///     let (mut send, _events) = client.bidi_without_updates(Subscribe).await?;
+///     send.send(Subscribe).await?;
+///     Ok(())
+/// }
+/// ```
+pub struct NoUpdateSink<C: StreamTypes>(C::SendSink);
+
+impl<C: StreamTypes> fmt::Debug for NoUpdateSink<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NoUpdateSink").finish_non_exhaustive()
+    }
+}
+
+/// Sending the updates of [RpcClient::batch_server_streaming]
+///
+/// The future is only ever polled through `&mut`, the mutex just makes the stream `Sync`.
+type SendUpdates<C> = Mutex<Boxed<result::Result<(), UpdateError<C>>>>;
+
+/// Stream adapter that sends the updates of a call while its responses are received
+struct BatchUpdates<St, C: ConnectionErrors> {
+    inner: St,
+    /// `None` once all updates are sent. Dropping it drops the update sink.
+    send: Option<SendUpdates<C>>,
+}
+
+impl<St, T, C> Stream for BatchUpdates<St, C>
+where
+    St: Stream<Item = result::Result<T, CallError<C>>> + Unpin,
+    C: ConnectionErrors,
+{
+    type Item = St::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(send) = this.send.as_mut() {
+            let send = send.get_mut().unwrap_or_else(PoisonError::into_inner);
+            if let Poll::Ready(res) = send.as_mut().poll(cx) {
+                this.send = None;
+                match res {
+                    Err(UpdateError::Send(cause)) => {
+                        return Poll::Ready(Some(Err(CallError::Send(cause))))
+                    }
+                    // the server stopped reading the updates, its responses tell why
+                    Ok(()) | Err(_) => {}
+                }
+            }
+        }
+        this.inner.poll_next(cx)
+    }
+}
+
+/// Stream adapter that fails with [CallError::Idle] once a call had no activity for its
+/// idle timeout
+struct IdleTimeout<St> {
+    inner: St,
+    watch: Arc<IdleWatch>,
+    /// `None` once the stream has terminated. Dropping the timer cancels it.
+    timer: Option<Timer>,
+}
+
+impl<St> IdleTimeout<St> {
+    fn new(inner: St, watch: Arc<IdleWatch>) -> Self {
+        let timer = Mutex::new(glib::timeout_future(watch.idle()));
+        Self {
+            inner,
+            watch,
+            timer: Some(timer),
+        }
+    }
+
+    fn idle<T, C: ConnectionErrors>(&mut self) -> Poll<Option<result::Result<T, CallError<C>>>> {
+        self.timer = None;
+        Poll::Ready(Some(Err(CallError::Idle)))
+    }
+}
+
+impl<St, T, C> Stream for IdleTimeout<St>
+where
+    St: Stream<Item = result::Result<T, CallError<C>>> + Unpin,
+    C: ConnectionErrors,
+{
+    type Item = St::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.timer.is_none() {
+            return Poll::Ready(None);
+        }
+        if this.watch.remaining().is_none() {
+            return this.idle();
+        }
+        if let Poll::Ready(item) = this.inner.poll_next(cx) {
+            match item {
+                Some(_) => this.watch.touch(),
+                None => this.timer = None,
+            }
+            return Poll::Ready(item);
+        }
+        loop {
+            let timer = this.timer.as_mut().expect("checked above");
+            let timer = timer.get_mut().unwrap_or_else(PoisonError::into_inner);
+            if timer.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+            match this.watch.remaining() {
+                // updates were sent in the meantime
+                Some(remaining) => *timer = glib::timeout_future(remaining),
+                None => return this.idle(),
+            }
+        }
+    }
 }
 
 impl<C, S> RpcChannel<S, C>
@@ -111,7 +464,8 @@ where
 {
     /// handle the message M using the given function on the target object
     ///
-    /// If you want to support concurrent requests, you need to spawn this on a tokio task yourself.
+    /// To handle concurrent requests, run this from [RpcServer::accept_loop](crate::RpcServer::accept_loop),
+    /// which handles every channel in a separate task, or spawn it yourself.
     pub async fn bidi_streaming<M, F, Str, T>(
         self,
         req: M,
@@ -124,23 +478,80 @@ where
         Str: Stream<Item = M::Response> + Send + 'static,
         T: Send + 'static,
     {
-        let Self { mut send, recv, .. } = self;
+        let Self {
+            mut send,
+            recv,
+            metrics,
+            handler_timeouts,
+            response_batch,
+            memory_budget,
+            error_response,
+            ..
+        } = self;
         // downcast the updates
         let (updates, read_error) = UpdateStream::new(recv);
+        // the updates just end when the client drops the call, so also watch the responses
+        let gone = client_gone::<C>(&send);
         // get the response
         let responses = f(target, req, updates);
-        race2(read_error.map(Err), async move {
-            futures_lite::pin!(responses);
-            while let Some(response) = responses.next().await {
-                // turn into a S::Res so we can send it
-                let response = response.into();
-                // send it and return the error if any
-                send.send(response)
-                    .await
-                    .map_err(RpcServerError::SendError)?;
-            }
-            Ok(())
-        })
-        .await
+        let res = instrument(
+            Pattern::BidiStreaming,
+            metrics,
+            handler_timeouts,
+            race2(race2(gone, read_error.map(Err)), async {
+                send_all(&mut send, responses, response_batch, memory_budget.as_ref()).await
+            }),
+        )
+        .await;
+        respond_to_error(&mut send, error_response, res).await
+    }
+
+    /// handle the message M using the given function on the target object, pushing the
+    /// responses to a [ResponseSender]
+    ///
+    /// Same as [RpcChannel::bidi_streaming], but instead of returning a stream, the
+    /// function gets a [ResponseSender] that can be cloned and moved to other tasks. See
+    /// [RpcChannel::server_streaming_with_sender] for how the responses are sent.
+    pub async fn bidi_streaming_with_sender<M, F, Fut, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: BidiStreamingMsg<S>,
+        F: FnOnce(T, M, UpdateStream<C, M::Update>, ResponseSender<M::Response>) -> Fut
+            + Send
+            + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        T: Send + 'static,
+    {
+        let Self {
+            mut send,
+            recv,
+            metrics,
+            handler_timeouts,
+            response_batch,
+            memory_budget,
+            error_response,
+            ..
+        } = self;
+        // downcast the updates
+        let (updates, read_error) = UpdateStream::new(recv);
+        // the updates just end when the client drops the call, so also watch the responses
+        let gone = client_gone::<C>(&send);
+        let res = instrument(
+            Pattern::BidiStreaming,
+            metrics,
+            handler_timeouts,
+            race2(
+                race2(gone, read_error.map(Err)),
+                send_responses(&mut send, response_batch, memory_budget, move |sender| {
+                    f(target, req, updates, sender)
+                }),
+            ),
+        )
+        .await;
+        respond_to_error(&mut send, error_response, res).await
     }
 }
@@ -0,0 +1,193 @@
+//! Client streaming uploads with a checksum for every chunk.
+//!
+//! This is built on [abortable client streaming](crate::pattern::client_streaming#aborting).
+//! The client sends the data as [ChecksummedChunk] updates, which carry the CRC-32 of
+//! their bytes. [RpcChannel::client_streaming_checksummed] verifies every chunk before
+//! the handler sees it. On the first chunk whose checksum does not match, the handler is
+//! dropped and the upload is aborted with a [ChecksumMismatch] that contains the index
+//! of the chunk, counted from 0. The client sees it like any other abort, as
+//! [UpdateError::Aborted](crate::client::UpdateError::Aborted) from the update sink and
+//! [CallError::App](crate::client::CallError::App) from the response.
+//!
+//! This verifies the data end to end, e.g. when it passes proxies that terminate TLS.
+//! The checksum protects against corruption, not against tampering.
+//!
+//! The message has to be a [ClientStreamingAbortMsg] with [ChecksummedChunk] as its
+//! update and [ChecksumMismatch] as its abort reason. The client uses
+//! [RpcClient::client_streaming_abortable](crate::RpcClient::client_streaming_abortable).
+
+use std::{
+    error, fmt,
+    pin::{pin, Pin},
+    result,
+    task::{ready, Context, Poll},
+};
+
+use futures::channel::oneshot;
+use futures_lite::{Future, Stream};
+use futures_util::future::{self, Either};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    message::{AbortableResponse, ClientStreamingAbortMsg},
+    server::{RpcChannel, RpcServerError, UpdateStream},
+    transport::StreamTypes,
+    Service,
+};
+
+/// An update of a checksummed upload
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChecksummedChunk {
+    /// The bytes of the chunk
+    pub data: Vec<u8>,
+    /// The CRC-32 of `data`
+    pub crc32: u32,
+}
+
+impl ChecksummedChunk {
+    /// A chunk with the checksum of `data`
+    pub fn new(data: impl Into<Vec<u8>>) -> Self {
+        let data = data.into();
+        let crc32 = crc32(&data);
+        Self { data, crc32 }
+    }
+
+    /// Whether the checksum matches the data
+    pub fn verify(&self) -> bool {
+        crc32(&self.data) == self.crc32
+    }
+}
+
+/// The reason a checksummed upload was aborted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChecksumMismatch {
+    /// The index of the corrupted chunk, counted from 0
+    pub index: u64,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "checksum mismatch in chunk {}", self.index)
+    }
+}
+
+impl error::Error for ChecksumMismatch {}
+
+/// The data of the verified chunks of a checksummed upload, see
+/// [RpcChannel::client_streaming_checksummed]
+///
+/// After a chunk with a wrong checksum, the stream does not yield anything anymore and
+/// the handler is dropped.
+pub struct VerifiedChunks<C: StreamTypes> {
+    updates: UpdateStream<C, ChecksummedChunk>,
+    index: u64,
+    mismatch: Option<oneshot::Sender<ChecksumMismatch>>,
+}
+
+impl<C: StreamTypes> fmt::Debug for VerifiedChunks<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VerifiedChunks")
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C> Stream for VerifiedChunks<C>
+where
+    C: StreamTypes,
+    ChecksummedChunk: TryFrom<C::In>,
+{
+    type Item = Vec<u8>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.mismatch.is_none() {
+            // the upload is aborted, the handler is about to be dropped
+            return Poll::Pending;
+        }
+        let Some(chunk) = ready!(Pin::new(&mut this.updates).poll_next(cx)) else {
+            return Poll::Ready(None);
+        };
+        let index = this.index;
+        this.index += 1;
+        if chunk.verify() {
+            return Poll::Ready(Some(chunk.data));
+        }
+        if let Some(mismatch) = this.mismatch.take() {
+            mismatch.send(ChecksumMismatch { index }).ok();
+        }
+        Poll::Pending
+    }
+}
+
+impl<S, C> RpcChannel<S, C>
+where
+    S: Service,
+    C: StreamTypes<In = S::Req, Out = S::Res>,
+{
+    /// handle the message M using the given function on the target object, verifying
+    /// the checksum of every update
+    ///
+    /// Same as [RpcChannel::client_streaming], but the function gets the data of the
+    /// verified chunks. If a chunk is corrupted, the function is dropped and the client
+    /// gets a [ChecksumMismatch] with the index of the chunk, see the
+    /// [module docs](self).
+    pub async fn client_streaming_checksummed<M, F, Fut, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: ClientStreamingAbortMsg<S, Update = ChecksummedChunk, Abort = ChecksumMismatch>,
+        AbortableResponse<M::Response, ChecksumMismatch>: Into<S::Res>,
+        F: FnOnce(T, M, VerifiedChunks<C>) -> Fut + Send + 'static,
+        Fut: Future<Output = M::Response> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.client_streaming_abortable(req, target, move |target, req, updates| async move {
+            let (mismatch, mismatch_recv) = oneshot::channel();
+            let chunks = VerifiedChunks {
+                updates,
+                index: 0,
+                mismatch: Some(mismatch),
+            };
+            let handler = pin!(f(target, req, chunks));
+            match future::select(handler, mismatch_recv).await {
+                Either::Left((res, _)) => Ok(res),
+                Either::Right((Ok(mismatch), _)) => Err(mismatch),
+                // the function dropped the chunks without a mismatch
+                Either::Right((Err(_), handler)) => Ok(handler.await),
+            }
+        })
+        .await
+    }
+}
+
+/// The lookup table of the CRC-32 with the IEEE polynomial, in reversed bit order
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// The CRC-32 of `data`, as used by zlib and ethernet
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, &byte| {
+        CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
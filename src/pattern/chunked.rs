@@ -0,0 +1,225 @@
+//! Chunked transfer of large responses, with progress.
+//!
+//! This is built on [server streaming](crate::pattern::server_streaming). The handler
+//! of [RpcChannel::server_streaming_chunked] produces a single large buffer, which is
+//! sent as a [Chunk::Size] with its length, followed by [Chunk::Data] frames of at most
+//! `chunk_size` bytes. [RpcClient::server_streaming_chunked] returns a [Chunks] stream
+//! of the data frames, which knows the total size and the bytes received so far, so the
+//! client can report [Progress] while the transfer is running. Dropping the stream
+//! cancels the transfer, the server stops sending frames.
+//!
+//! The message has to be a [ServerStreamingMsg] with [Chunk] as its response. To
+//! transfer a large typed value, serialize it in the handler and deserialize the
+//! collected bytes on the client.
+
+use std::{
+    fmt, iter,
+    pin::Pin,
+    result,
+    task::{ready, Context, Poll},
+};
+
+use bytes::Bytes;
+use futures_lite::{stream, Future, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::{BoxStreamSync, CallError},
+    message::ServerStreamingMsg,
+    server::{RpcChannel, RpcServerError},
+    transport::{ConnectionErrors, StreamTypes},
+    RpcClient, Service,
+};
+
+/// A frame of a chunked transfer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Chunk {
+    /// The total size of the transfer in bytes, always the first frame
+    Size(u64),
+    /// The next bytes of the transfer
+    Data(Bytes),
+}
+
+/// The progress of a chunked transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// The number of bytes received so far
+    pub transferred: u64,
+    /// The total size of the transfer in bytes
+    pub size: u64,
+}
+
+impl Progress {
+    /// The received fraction of the transfer, between 0 and 1
+    pub fn fraction(&self) -> f64 {
+        if self.size == 0 {
+            1.0
+        } else {
+            self.transferred as f64 / self.size as f64
+        }
+    }
+
+    /// Whether all bytes have been received
+    pub fn is_done(&self) -> bool {
+        self.transferred >= self.size
+    }
+}
+
+/// The data frames of a chunked transfer, see [RpcClient::server_streaming_chunked]
+///
+/// The stream ends once all bytes announced by the server have been received. If the
+/// server closes the stream before, it yields [CallError::EarlyClose]. If the server
+/// sends more data than announced, or a second [Chunk::Size], it yields
+/// [CallError::ProtocolViolation]. Dropping the stream cancels the transfer.
+pub struct Chunks<C: ConnectionErrors> {
+    chunks: BoxStreamSync<'static, result::Result<Chunk, CallError<C>>>,
+    size: u64,
+    transferred: u64,
+    failed: bool,
+}
+
+impl<C: ConnectionErrors> fmt::Debug for Chunks<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Chunks")
+            .field("size", &self.size)
+            .field("transferred", &self.transferred)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: ConnectionErrors> Chunks<C> {
+    /// The total size of the transfer in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// The progress of the transfer
+    pub fn progress(&self) -> Progress {
+        Progress {
+            transferred: self.transferred,
+            size: self.size,
+        }
+    }
+
+    /// Receive all remaining frames into a single buffer
+    ///
+    /// `on_progress` is called once before receiving, and after every frame.
+    pub async fn collect_with_progress(
+        mut self,
+        mut on_progress: impl FnMut(Progress),
+    ) -> result::Result<Vec<u8>, CallError<C>> {
+        let mut data = Vec::new();
+        on_progress(self.progress());
+        while let Some(chunk) = self.next().await {
+            data.extend_from_slice(&chunk?);
+            on_progress(self.progress());
+        }
+        Ok(data)
+    }
+}
+
+impl<C: ConnectionErrors> Stream for Chunks<C> {
+    type Item = result::Result<Bytes, CallError<C>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.failed || this.transferred >= this.size {
+            return Poll::Ready(None);
+        }
+        let res = match ready!(this.chunks.poll_next(cx)) {
+            Some(Ok(Chunk::Data(data))) => {
+                let transferred = this.transferred + data.len() as u64;
+                if transferred > this.size {
+                    // more data than announced
+                    Err(CallError::ProtocolViolation)
+                } else {
+                    this.transferred = transferred;
+                    Ok(data)
+                }
+            }
+            Some(Ok(Chunk::Size(_))) => Err(CallError::ProtocolViolation),
+            Some(Err(cause)) => Err(cause),
+            None => Err(CallError::EarlyClose),
+        };
+        this.failed = res.is_err();
+        Poll::Ready(Some(res))
+    }
+}
+
+impl<S, C> RpcClient<S, C>
+where
+    C: crate::Connector<S>,
+    S: Service,
+{
+    /// Server streaming call to the server, that receives a large response in chunks
+    ///
+    /// Returns once the size of the transfer has been received. The handler on the server
+    /// has to use [RpcChannel::server_streaming_chunked].
+    pub async fn server_streaming_chunked<M>(
+        &self,
+        msg: M,
+    ) -> result::Result<Chunks<C>, CallError<C>>
+    where
+        M: ServerStreamingMsg<S, Response = Chunk>,
+    {
+        let mut chunks = self.server_streaming(msg).await?;
+        let size = match chunks.next().await {
+            Some(Ok(Chunk::Size(size))) => size,
+            Some(Ok(Chunk::Data(_))) => return Err(CallError::ProtocolViolation),
+            Some(Err(cause)) => return Err(cause),
+            None => return Err(CallError::EarlyClose),
+        };
+        Ok(Chunks {
+            chunks,
+            size,
+            transferred: 0,
+            failed: false,
+        })
+    }
+}
+
+impl<S, C> RpcChannel<S, C>
+where
+    S: Service,
+    C: StreamTypes<In = S::Req, Out = S::Res>,
+{
+    /// handle the message M using the given function on the target object, sending the
+    /// result in chunks of at most `chunk_size` bytes
+    ///
+    /// The frames are sent like the responses of [RpcChannel::server_streaming], so a
+    /// [MemoryBudget](crate::budget::MemoryBudget) of the server limits how many of them
+    /// are buffered. The handler is cancelled if the client drops the [Chunks] stream.
+    pub async fn server_streaming_chunked<M, F, Fut, T, D>(
+        self,
+        req: M,
+        target: T,
+        chunk_size: usize,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: ServerStreamingMsg<S, Response = Chunk>,
+        F: FnOnce(T, M) -> Fut + Send + 'static,
+        Fut: Future<Output = D> + Send + 'static,
+        D: Into<Bytes> + Send + 'static,
+        T: Send + 'static,
+    {
+        let chunk_size = chunk_size.max(1);
+        self.server_streaming(req, target, move |target, req| {
+            stream::once_future(f(target, req))
+                .flat_map(move |data| stream::iter(split(data, chunk_size)))
+        })
+        .await
+    }
+}
+
+/// Split `data` into a [Chunk::Size] and [Chunk::Data] frames of at most `chunk_size`
+/// bytes, which share the buffer of `data`
+fn split(data: impl Into<Bytes>, chunk_size: usize) -> impl Iterator<Item = Chunk> {
+    let data = data.into();
+    let size = data.len();
+    let frames = (0..size).step_by(chunk_size).map(move |start| {
+        let end = size.min(start + chunk_size);
+        Chunk::Data(data.slice(start..end))
+    });
+    iter::once(Chunk::Size(size as u64)).chain(frames)
+}
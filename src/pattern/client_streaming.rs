@@ -1,45 +1,65 @@
 //! Client streaming interaction pattern.
+//!
+//! # Acknowledged updates
+//!
+//! With [RpcClient::client_streaming_with_acks] and
+//! [RpcChannel::client_streaming_with_acks], the server can acknowledge updates while it
+//! reads them, e.g. to checkpoint a chunked upload so that it can be resumed after the
+//! last acknowledged chunk. This uses the same substream as a plain client streaming
+//! request: the request and the updates go from the client to the server, and the server
+//! answers with any number of [AckedResponse::Ack] followed by one
+//! [AckedResponse::Response]. Acks are sent in the order the handler made them, and
+//! always before the final response.
+//!
+//! # Aborting
+//!
+//! With [RpcClient::client_streaming_abortable] and
+//! [RpcChannel::client_streaming_abortable], the server can abort a request while the
+//! client is still sending updates, e.g. because a quota was exceeded. The message has to
+//! implement [ClientStreamingAbortMsg], which defines the type of the reason. The server
+//! answers with an [AbortableResponse], and when it is [AbortableResponse::Aborted] it
+//! stops reading updates. The client reads the reason while it sends updates, so the
+//! [UpdateSink] fails with [UpdateError::Aborted](crate::client::UpdateError::Aborted)
+//! instead of a plain send error, and the response is a [CallError::App] with the reason.
 
 use std::{
-    error,
-    fmt::{self, Debug},
+    collections::VecDeque,
+    convert::Infallible,
+    error, fmt,
+    marker::PhantomData,
+    pin::{pin, Pin},
     result,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll},
 };
 
-use futures_lite::{future::Boxed, Future, StreamExt};
-use futures_util::{FutureExt, SinkExt, TryFutureExt};
+use futures::{
+    channel::mpsc,
+    task::{waker_ref, ArcWake, AtomicWaker},
+};
+use futures_lite::{future::Boxed, Future, Stream, StreamExt};
+use futures_util::{
+    future::{self, Either},
+    FutureExt, SinkExt, TryFutureExt,
+};
 
 use crate::{
-    client::UpdateSink,
-    message::{InteractionPattern, Msg},
-    server::{race2, RpcChannel, RpcServerError, UpdateStream},
+    client::{BoxStreamSync, CallError, PollAbort, UpdateSink},
+    metrics::Pattern,
+    server::{
+        client_gone, instrument, race2, respond_to_error, RpcChannel, RpcServerError, UpdateStream,
+    },
     transport::{ConnectionErrors, StreamTypes},
-    Connector, RpcClient, Service,
+    Connector, ErrorSource, RpcClient, Service,
 };
 
-/// Client streaming interaction pattern
-///
-/// After the initial request, the client can send updates, but there is only
-/// one response.
-#[derive(Debug, Clone, Copy)]
-pub struct ClientStreaming;
-impl InteractionPattern for ClientStreaming {}
-
-/// Defines update type and response type for a client streaming message.
-pub trait ClientStreamingMsg<S: Service>: Msg<S, Pattern = ClientStreaming> {
-    /// The type for request updates
-    ///
-    /// For a request that does not support updates, this can be safely set to any type, including
-    /// the message type itself. Any update for such a request will result in an error.
-    type Update: Into<S::Req> + TryFrom<S::Req> + Send + 'static;
-
-    /// The type for the response
-    ///
-    /// For requests that can produce errors, this can be set to [Result<T, E>](std::result::Result).
-    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
-}
+pub use crate::message::{
+    AbortableResponse, AckedResponse, ClientStreaming, ClientStreamingAbortMsg, ClientStreamingMsg,
+};
 
-/// Server error when accepting a client streaming request
+/// Client error when opening a client streaming request
+///
+/// The client methods return a [CallError], this can be converted to one.
 #[derive(Debug)]
 pub enum Error<C: ConnectionErrors> {
     /// Unable to open a substream at all
@@ -50,13 +70,34 @@ pub enum Error<C: ConnectionErrors> {
 
 impl<C: ConnectionErrors> fmt::Display for Error<C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        match self {
+            Self::Open(_) => write!(f, "failed to open a substream"),
+            Self::Send(_) => write!(f, "failed to send the request"),
+        }
     }
 }
 
-impl<C: ConnectionErrors> error::Error for Error<C> {}
+impl<C: ConnectionErrors> error::Error for Error<C> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Open(cause) => Some(ErrorSource::new(cause)),
+            Self::Send(cause) => Some(ErrorSource::new(cause)),
+        }
+    }
+}
 
-/// Server error when receiving an item for a client streaming request
+impl<C: ConnectionErrors, A> From<Error<C>> for CallError<C, A> {
+    fn from(e: Error<C>) -> Self {
+        match e {
+            Error::Open(cause) => CallError::Open(cause),
+            Error::Send(cause) => CallError::Send(cause),
+        }
+    }
+}
+
+/// Client error when receiving the response of a client streaming request
+///
+/// The client methods return a [CallError], this can be converted to one.
 #[derive(Debug)]
 pub enum ItemError<C: ConnectionErrors> {
     /// Connection was closed before receiving the first message
@@ -69,11 +110,32 @@ pub enum ItemError<C: ConnectionErrors> {
 
 impl<C: ConnectionErrors> fmt::Display for ItemError<C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        match self {
+            Self::EarlyClose => write!(f, "server closed the stream before sending a response"),
+            Self::RecvError(_) => write!(f, "failed to receive the response"),
+            Self::DowncastError => write!(f, "unexpected response from the server"),
+        }
     }
 }
 
-impl<C: ConnectionErrors> error::Error for ItemError<C> {}
+impl<C: ConnectionErrors> error::Error for ItemError<C> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::RecvError(cause) => Some(ErrorSource::new(cause)),
+            _ => None,
+        }
+    }
+}
+
+impl<C: ConnectionErrors, A> From<ItemError<C>> for CallError<C, A> {
+    fn from(e: ItemError<C>) -> Self {
+        match e {
+            ItemError::EarlyClose => CallError::EarlyClose,
+            ItemError::RecvError(cause) => CallError::recv(cause),
+            ItemError::DowncastError => CallError::Downcast,
+        }
+    }
+}
 
 impl<S, C> RpcClient<S, C>
 where
@@ -81,34 +143,309 @@ where
     C: Connector<S>,
 {
     /// Call to the server that allows the client to stream, single response
+    ///
+    /// Dropping the response future cancels the handler on the server, on transports
+    /// that can tell. Dropping the update sink just ends the updates.
     pub async fn client_streaming<M>(
         &self,
         msg: M,
     ) -> result::Result<
         (
             UpdateSink<C, M::Update>,
-            Boxed<result::Result<M::Response, ItemError<C>>>,
+            Boxed<result::Result<M::Response, CallError<C>>>,
         ),
-        Error<C>,
+        CallError<C>,
+    >
+    where
+        M: ClientStreamingMsg<S>,
+    {
+        self.client_streaming_with_capacity(msg, 0).await
+    }
+
+    /// Call to the server that allows the client to stream, single response.
+    ///
+    /// The returned [UpdateSink] buffers up to `capacity` updates that the transport has
+    /// not accepted yet, see [UpdateSink::with_capacity].
+    pub async fn client_streaming_with_capacity<M>(
+        &self,
+        msg: M,
+        capacity: usize,
+    ) -> result::Result<
+        (
+            UpdateSink<C, M::Update>,
+            Boxed<result::Result<M::Response, CallError<C>>>,
+        ),
+        CallError<C>,
     >
     where
         M: ClientStreamingMsg<S>,
     {
         let msg = msg.into();
-        let (mut send, mut recv) = self.source.open().await.map_err(Error::Open)?;
-        send.send(msg).map_err(Error::Send).await?;
-        let send = UpdateSink::<C, M::Update>::new(send);
+        let (mut send, mut recv) = self.source.open().await.map_err(CallError::Open)?;
+        send.send(msg).map_err(CallError::Send).await?;
+        let send = UpdateSink::with_capacity(send, capacity);
         let recv = async move {
-            let item = recv.next().await.ok_or(ItemError::EarlyClose)?;
+            let item = recv.next().await.ok_or(CallError::EarlyClose)?;
 
             match item {
-                Ok(msg) => M::Response::try_from(msg).map_err(|_| ItemError::DowncastError),
-                Err(e) => Err(ItemError::RecvError(e)),
+                Ok(msg) => M::Response::try_from(msg).map_err(|_| CallError::Downcast),
+                Err(e) => Err(CallError::recv(e)),
             }
         }
         .boxed();
         Ok((send, recv))
     }
+
+    /// Call to the server that allows the client to stream, with acknowledged updates and
+    /// a single response.
+    ///
+    /// Besides the [UpdateSink] and the response, this returns a stream of the sequence
+    /// numbers of the updates the server acknowledged, see the
+    /// [module docs](self#acknowledged-updates). The ack stream ends when the response
+    /// arrives or the substream fails, the response future reports the error in that
+    /// case.
+    ///
+    /// Either the ack stream or the response future has to be polled while sending
+    /// updates. Otherwise unread acks can fill the substream, and the server stops
+    /// reading updates until they are read.
+    pub async fn client_streaming_with_acks<M>(
+        &self,
+        msg: M,
+    ) -> result::Result<
+        (
+            UpdateSink<C, M::Update>,
+            BoxStreamSync<'static, u64>,
+            Boxed<result::Result<M::Response, CallError<C>>>,
+        ),
+        CallError<C>,
+    >
+    where
+        M: ClientStreamingMsg<S>,
+        AckedResponse<M::Response>: Into<S::Res> + TryFrom<S::Res>,
+    {
+        let msg = msg.into();
+        let (mut send, recv) = self.source.open().await.map_err(CallError::Open)?;
+        send.send(msg).map_err(CallError::Send).await?;
+        let send = UpdateSink::new(send);
+        let demux = Arc::new(Demux::<C, AckedResponse<M::Response>, _, _>::new(recv));
+        let acks = AckStream(demux.clone());
+        let response = future::poll_fn(move |cx| demux.poll_response(cx)).boxed();
+        Ok((send, Box::pin(acks), response))
+    }
+
+    /// Call to the server that allows the client to stream, single response, where the
+    /// server can abort while the client is still sending updates.
+    ///
+    /// If the server aborts, the [UpdateSink] fails with
+    /// [UpdateError::Aborted](crate::client::UpdateError::Aborted) and the response future
+    /// with [CallError::App], both with the reason, see the
+    /// [module docs](self#aborting). The sink reads the responses of the substream itself
+    /// while sending, so it notices an abort even if the response future is not polled.
+    pub async fn client_streaming_abortable<M>(
+        &self,
+        msg: M,
+    ) -> result::Result<
+        (
+            UpdateSink<C, M::Update, M::Abort>,
+            Boxed<result::Result<M::Response, CallError<C, M::Abort>>>,
+        ),
+        CallError<C>,
+    >
+    where
+        M: ClientStreamingAbortMsg<S>,
+        AbortableResponse<M::Response, M::Abort>: Into<S::Res> + TryFrom<S::Res>,
+    {
+        let msg = msg.into();
+        let (mut send, recv) = self.source.open().await.map_err(CallError::Open)?;
+        send.send(msg).map_err(CallError::Send).await?;
+        let demux = Arc::new(Demux::<C, AbortableResponse<M::Response, M::Abort>, _, _>::new(recv));
+        let send = UpdateSink::<C, M::Update, M::Abort>::abortable(send, demux.clone());
+        let response = future::poll_fn(move |cx| demux.poll_response(cx)).boxed();
+        Ok((send, response))
+    }
+}
+
+/// Wakes both consumers of a substream that is read by two of them, the response future
+/// and either the ack stream or the update sink, since whichever is polled reads the
+/// substream for both
+#[derive(Debug, Default)]
+struct DemuxWakers {
+    other: AtomicWaker,
+    response: AtomicWaker,
+}
+
+impl ArcWake for DemuxWakers {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.other.wake();
+        arc_self.response.wake();
+    }
+}
+
+/// A message of a substream that is read by a [Demux]
+enum Demuxed<R, A> {
+    Ack(u64),
+    Response(R),
+    Aborted(A),
+}
+
+impl<R> From<AckedResponse<R>> for Demuxed<R, Infallible> {
+    fn from(msg: AckedResponse<R>) -> Self {
+        match msg {
+            AckedResponse::Ack(seq) => Self::Ack(seq),
+            AckedResponse::Response(res) => Self::Response(res),
+        }
+    }
+}
+
+impl<R, A> From<AbortableResponse<R, A>> for Demuxed<R, A> {
+    fn from(msg: AbortableResponse<R, A>) -> Self {
+        match msg {
+            AbortableResponse::Response(res) => Self::Response(res),
+            AbortableResponse::Aborted(reason) => Self::Aborted(reason),
+        }
+    }
+}
+
+struct DemuxState<C: StreamTypes, R, A> {
+    recv: C::RecvStream,
+    /// Acks that were received but not yet yielded by the ack stream
+    acks: VecDeque<u64>,
+    /// The reason, if the server aborted
+    aborted: Option<A>,
+    /// The response or the error that ended the substream, until the response future
+    /// takes it
+    end: Option<result::Result<R, CallError<C, A>>>,
+    /// Whether the substream ended, so `recv` must not be polled again
+    done: bool,
+}
+
+/// The substream of [RpcClient::client_streaming_with_acks] and
+/// [RpcClient::client_streaming_abortable], shared by the response future and either
+/// the ack stream or the update sink
+///
+/// `M` is the message the server sends on the substream, [AckedResponse] or
+/// [AbortableResponse].
+struct Demux<C: StreamTypes, M, R, A> {
+    state: Mutex<DemuxState<C, R, A>>,
+    wakers: Arc<DemuxWakers>,
+    _msg: PhantomData<fn() -> M>,
+}
+
+impl<C, M, R, A> Demux<C, M, R, A>
+where
+    C: StreamTypes,
+    M: TryFrom<C::In> + Into<Demuxed<R, A>>,
+    A: Clone,
+{
+    fn new(recv: C::RecvStream) -> Self {
+        Self {
+            state: Mutex::new(DemuxState {
+                recv,
+                acks: VecDeque::new(),
+                aborted: None,
+                end: None,
+                done: false,
+            }),
+            wakers: Arc::new(DemuxWakers::default()),
+            _msg: PhantomData,
+        }
+    }
+
+    /// Read everything that is available from the substream, with a waker for both
+    /// consumers
+    fn poll_recv(&self) -> MutexGuard<'_, DemuxState<C, R, A>> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let waker = waker_ref(&self.wakers);
+        let mut cx = Context::from_waker(&waker);
+        while !state.done {
+            let end = match Pin::new(&mut state.recv).poll_next(&mut cx) {
+                Poll::Pending => break,
+                Poll::Ready(Some(Ok(msg))) => match M::try_from(msg).map(Into::into) {
+                    Ok(Demuxed::Ack(seq)) => {
+                        state.acks.push_back(seq);
+                        continue;
+                    }
+                    Ok(Demuxed::Response(res)) => Ok(res),
+                    Ok(Demuxed::Aborted(reason)) => {
+                        state.aborted = Some(reason.clone());
+                        Err(CallError::App(reason))
+                    }
+                    Err(_) => Err(CallError::Downcast),
+                },
+                Poll::Ready(Some(Err(cause))) => Err(CallError::recv(cause)),
+                Poll::Ready(None) => Err(CallError::EarlyClose),
+            };
+            state.end = Some(end);
+            state.done = true;
+        }
+        state
+    }
+
+    /// Poll for the response, for the response future
+    fn poll_response(&self, cx: &mut Context<'_>) -> Poll<result::Result<R, CallError<C, A>>> {
+        self.wakers.response.register(cx.waker());
+        let mut state = self.poll_recv();
+        match state.end.take() {
+            Some(end) => Poll::Ready(end),
+            None if state.done => Poll::Ready(Err(CallError::EarlyClose)),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// The acks of [RpcClient::client_streaming_with_acks]
+struct AckStream<C: StreamTypes, R>(Arc<Demux<C, AckedResponse<R>, R, Infallible>>);
+
+impl<C, R> Stream for AckStream<C, R>
+where
+    C: StreamTypes,
+    AckedResponse<R>: TryFrom<C::In>,
+{
+    type Item = u64;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.wakers.other.register(cx.waker());
+        let mut state = self.0.poll_recv();
+        match state.acks.pop_front() {
+            Some(seq) => Poll::Ready(Some(seq)),
+            None if state.done => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<C, R, A> PollAbort<A> for Demux<C, AbortableResponse<R, A>, R, A>
+where
+    C: StreamTypes,
+    R: Send + 'static,
+    A: Clone + Send + 'static,
+    AbortableResponse<R, A>: TryFrom<C::In>,
+{
+    fn poll_abort(&self, cx: &mut Context<'_>) -> Poll<Option<A>> {
+        self.wakers.other.register(cx.waker());
+        let state = self.poll_recv();
+        match &state.aborted {
+            Some(reason) => Poll::Ready(Some(reason.clone())),
+            None if state.done => Poll::Ready(None),
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// Acknowledges updates from the handler of a client streaming request, see
+/// [RpcChannel::client_streaming_with_acks]
+#[derive(Debug, Clone)]
+pub struct Acks(mpsc::UnboundedSender<u64>);
+
+impl Acks {
+    /// Acknowledge the update with sequence number `seq`.
+    ///
+    /// Updates are numbered from 0 in the order the client sent them, so enumerating the
+    /// [UpdateStream] gives the numbers. Acks are sent in the order of the calls, and acks
+    /// made after the response was sent are ignored.
+    pub fn ack(&self, seq: u64) {
+        self.0.unbounded_send(seq).ok();
+    }
 }
 
 impl<S, C> RpcChannel<S, C>
@@ -118,7 +455,8 @@ where
 {
     /// handle the message M using the given function on the target object
     ///
-    /// If you want to support concurrent requests, you need to spawn this on a tokio task yourself.
+    /// To handle concurrent requests, run this from [RpcServer::accept_loop](crate::RpcServer::accept_loop),
+    /// which handles every channel in a separate task, or spawn it yourself.
     pub async fn client_streaming<M, F, Fut, T>(
         self,
         req: M,
@@ -131,16 +469,142 @@ where
         Fut: Future<Output = M::Response> + Send + 'static,
         T: Send + 'static,
     {
-        let Self { mut send, recv, .. } = self;
+        let Self {
+            mut send,
+            recv,
+            metrics,
+            handler_timeouts,
+            error_response,
+            ..
+        } = self;
+        let (updates, read_error) = UpdateStream::new(recv);
+        // the updates just end when the client drops the call, so also watch the responses
+        let gone = client_gone::<C>(&send);
+        let res = instrument(
+            Pattern::ClientStreaming,
+            metrics,
+            handler_timeouts,
+            race2(race2(gone, read_error.map(Err)), async {
+                // get the response
+                let res = f(target, req, updates).await;
+                // turn into a S::Res so we can send it
+                let res = res.into();
+                // send it and return the error if any
+                send.send(res).await.map_err(RpcServerError::SendError)
+            }),
+        )
+        .await;
+        respond_to_error(&mut send, error_response, res).await
+    }
+
+    /// handle the message M using the given function on the target object, with
+    /// acknowledged updates
+    ///
+    /// Same as [RpcChannel::client_streaming], but the function also gets an [Acks]
+    /// handle to acknowledge updates while it reads them, see the
+    /// [module docs](self#acknowledged-updates). The response is sent as an
+    /// [AckedResponse::Response] after all acks made before the function returned.
+    pub async fn client_streaming_with_acks<M, F, Fut, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: ClientStreamingMsg<S>,
+        AckedResponse<M::Response>: Into<S::Res>,
+        F: FnOnce(T, M, UpdateStream<C, M::Update>, Acks) -> Fut + Send + 'static,
+        Fut: Future<Output = M::Response> + Send + 'static,
+        T: Send + 'static,
+    {
+        let Self {
+            mut send,
+            recv,
+            metrics,
+            handler_timeouts,
+            error_response,
+            ..
+        } = self;
+        let (updates, read_error) = UpdateStream::new(recv);
+        // the updates just end when the client drops the call, so also watch the responses
+        let gone = client_gone::<C>(&send);
+        let (acks, mut ack_recv) = mpsc::unbounded();
+        let res = instrument(
+            Pattern::ClientStreaming,
+            metrics,
+            handler_timeouts,
+            race2(race2(gone, read_error.map(Err)), async {
+                let mut handler = pin!(f(target, req, updates, Acks(acks)));
+                // forward the acks while the handler runs
+                let res = loop {
+                    match future::select(handler.as_mut(), ack_recv.next()).await {
+                        Either::Left((res, _)) => break res,
+                        Either::Right((Some(seq), _)) => {
+                            let ack = AckedResponse::<M::Response>::Ack(seq).into();
+                            send.send(ack).await.map_err(RpcServerError::SendError)?;
+                        }
+                        // the handler dropped all its ack handles
+                        Either::Right((None, _)) => break handler.await,
+                    }
+                };
+                // acks made right before returning still go out before the response
+                while let Ok(Some(seq)) = ack_recv.try_next() {
+                    let ack = AckedResponse::<M::Response>::Ack(seq).into();
+                    send.feed(ack).await.map_err(RpcServerError::SendError)?;
+                }
+                let res = AckedResponse::Response(res).into();
+                send.send(res).await.map_err(RpcServerError::SendError)
+            }),
+        )
+        .await;
+        respond_to_error(&mut send, error_response, res).await
+    }
+
+    /// handle the message M using the given function on the target object, where the
+    /// function can abort
+    ///
+    /// Same as [RpcChannel::client_streaming], but the function returns either the
+    /// response or a reason to abort, see the [module docs](self#aborting). On abort, the
+    /// reason is sent to the client and the remaining updates are not read.
+    pub async fn client_streaming_abortable<M, F, Fut, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: ClientStreamingAbortMsg<S>,
+        AbortableResponse<M::Response, M::Abort>: Into<S::Res>,
+        F: FnOnce(T, M, UpdateStream<C, M::Update>) -> Fut + Send + 'static,
+        Fut: Future<Output = result::Result<M::Response, M::Abort>> + Send + 'static,
+        T: Send + 'static,
+    {
+        let Self {
+            mut send,
+            recv,
+            metrics,
+            handler_timeouts,
+            error_response,
+            ..
+        } = self;
         let (updates, read_error) = UpdateStream::new(recv);
-        race2(read_error.map(Err), async move {
-            // get the response
-            let res = f(target, req, updates).await;
-            // turn into a S::Res so we can send it
-            let res = res.into();
-            // send it and return the error if any
-            send.send(res).await.map_err(RpcServerError::SendError)
-        })
-        .await
+        // the updates just end when the client drops the call, so also watch the responses
+        let gone = client_gone::<C>(&send);
+        let res = instrument(
+            Pattern::ClientStreaming,
+            metrics,
+            handler_timeouts,
+            race2(race2(gone, read_error.map(Err)), async {
+                let res = match f(target, req, updates).await {
+                    Ok(res) => AbortableResponse::Response(res),
+                    Err(reason) => AbortableResponse::Aborted(reason),
+                };
+                send.send(res.into())
+                    .await
+                    .map_err(RpcServerError::SendError)
+            }),
+        )
+        .await;
+        respond_to_error(&mut send, error_response, res).await
     }
 }
@@ -0,0 +1,168 @@
+//! Fallible rpc interaction pattern.
+//!
+//! This is like [rpc](crate::pattern::rpc), but the application error type is
+//! explicitly defined, so it can be surfaced as an error on the client side.
+
+use std::{
+    error,
+    fmt::{self, Debug},
+    result,
+};
+
+use futures_lite::{Future, StreamExt};
+use futures_util::{FutureExt, SinkExt};
+
+use crate::{
+    client::CallError,
+    metrics::Pattern,
+    server::{instrument, race2, respond_to_error, RpcChannel, RpcServerError},
+    transport::{self, StreamTypes},
+    Connector, ErrorSource, RpcClient, Service,
+};
+
+pub use crate::message::{Fallible, FallibleMsg};
+
+/// Client error for a fallible rpc call
+///
+/// This combines network errors with application errors. Usually you don't
+/// care about the exact nature of the error, but if you want to handle
+/// application errors differently, you can match on this enum.
+///
+/// The client methods return a [CallError], this can be converted to one.
+#[derive(Debug)]
+pub enum Error<C: transport::Connector, E: Debug> {
+    /// Unable to open a substream at all
+    Open(C::OpenError),
+    /// Unable to send the request to the server
+    Send(C::SendError),
+    /// Server closed the stream before sending a response
+    EarlyClose,
+    /// Unable to receive the response from the server
+    Recv(C::RecvError),
+    /// Unexpected response from the server
+    Downcast,
+    /// Application error
+    Application(E),
+}
+
+impl<C: transport::Connector, E: Debug> fmt::Display for Error<C, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open(_) => write!(f, "failed to open a substream"),
+            Self::Send(_) => write!(f, "failed to send the request"),
+            Self::EarlyClose => write!(f, "server closed the stream before sending a response"),
+            Self::Recv(_) => write!(f, "failed to receive the response"),
+            Self::Downcast => write!(f, "unexpected response from the server"),
+            Self::Application(cause) => write!(f, "application error: {cause:?}"),
+        }
+    }
+}
+
+impl<C: transport::Connector, E: Debug> error::Error for Error<C, E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Open(cause) => Some(ErrorSource::new(cause)),
+            Self::Send(cause) => Some(ErrorSource::new(cause)),
+            Self::Recv(cause) => Some(ErrorSource::new(cause)),
+            _ => None,
+        }
+    }
+}
+
+impl<C: transport::Connector, E: Debug> From<Error<C, E>> for CallError<C, E> {
+    fn from(e: Error<C, E>) -> Self {
+        match e {
+            Error::Open(cause) => CallError::Open(cause),
+            Error::Send(cause) => CallError::Send(cause),
+            Error::EarlyClose => CallError::EarlyClose,
+            Error::Recv(cause) => CallError::recv(cause),
+            Error::Downcast => CallError::Downcast,
+            Error::Application(cause) => CallError::App(cause),
+        }
+    }
+}
+
+impl<S, C> RpcClient<S, C>
+where
+    S: Service,
+    C: Connector<S>,
+{
+    /// Fallible RPC call to the server, single request, single response
+    ///
+    /// An application error returned by the handler is surfaced as [CallError::App].
+    pub async fn rpc_fallible<M>(
+        &self,
+        msg: M,
+    ) -> result::Result<M::Response, CallError<C, M::AppError>>
+    where
+        M: FallibleMsg<S>,
+        result::Result<M::Response, M::AppError>: Into<S::Res> + TryFrom<S::Res>,
+    {
+        let msg = msg.into();
+        let (mut send, mut recv) = self.source.open().await.map_err(CallError::Open)?;
+        send.send(msg).await.map_err(CallError::Send)?;
+        let res = recv
+            .next()
+            .await
+            .ok_or(CallError::EarlyClose)?
+            .map_err(CallError::recv)?;
+        // keep send alive until we have the answer
+        drop(send);
+        let res = result::Result::<M::Response, M::AppError>::try_from(res)
+            .map_err(|_| CallError::Downcast)?;
+        res.map_err(CallError::App)
+    }
+}
+
+impl<S, C> RpcChannel<S, C>
+where
+    S: Service,
+    C: StreamTypes<In = S::Req, Out = S::Res>,
+{
+    /// handle the message of type `M` using the given fallible function on the target object
+    ///
+    /// To handle concurrent requests, run this from [RpcServer::accept_loop](crate::RpcServer::accept_loop),
+    /// which handles every channel in a separate task, or spawn it yourself.
+    pub async fn rpc_fallible<M, F, Fut, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: FallibleMsg<S>,
+        result::Result<M::Response, M::AppError>: Into<S::Res> + TryFrom<S::Res>,
+        F: FnOnce(T, M) -> Fut,
+        Fut: Future<Output = result::Result<M::Response, M::AppError>>,
+        T: Send + 'static,
+    {
+        let Self {
+            mut send,
+            mut recv,
+            metrics,
+            handler_timeouts,
+            error_response,
+            ..
+        } = self;
+        // cancel if we get an update, no matter what it is
+        let cancel = recv
+            .next()
+            .map(|_| RpcServerError::UnexpectedUpdateMessage::<C>);
+        // race the computation and the cancellation
+        let res = instrument(
+            Pattern::Fallible,
+            metrics,
+            handler_timeouts,
+            race2(cancel.map(Err), async {
+                // get the response, success or application error
+                let res = f(target, req).await;
+                // turn into a S::Res so we can send it
+                let res = res.into();
+                // send it and return the error if any
+                send.send(res).await.map_err(RpcServerError::SendError)
+            }),
+        )
+        .await;
+        respond_to_error(&mut send, error_response, res).await
+    }
+}
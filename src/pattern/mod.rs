@@ -5,7 +5,10 @@
 //!
 //! Each pattern defines different associated message types for the interaction.
 pub mod bidi_streaming;
+pub mod checksummed;
+pub mod chunked;
 pub mod client_streaming;
+pub mod fallible;
 pub mod rpc;
 pub mod server_streaming;
 pub mod try_server_streaming;
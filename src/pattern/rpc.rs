@@ -1,45 +1,43 @@
 //! RPC interaction pattern.
 
 use std::{
-    error,
-    fmt::{self, Debug},
+    collections::HashSet,
+    error, fmt,
+    iter::Peekable,
+    marker::PhantomData,
+    pin::Pin,
     result,
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+    vec,
 };
 
-use futures_lite::{Future, StreamExt};
-use futures_util::{FutureExt, SinkExt};
+use futures_lite::{Future, Stream, StreamExt};
+use futures_util::{
+    future::{self, Either},
+    stream::FuturesUnordered,
+    FutureExt, SinkExt,
+};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    message::{InteractionPattern, Msg},
-    server::{race2, RpcChannel, RpcServerError},
-    transport::{ConnectionErrors, StreamTypes},
-    Connector, RpcClient, Service,
+    client::{BoxStreamSync, CallError},
+    metrics::Pattern,
+    server::{instrument, race2, respond_to_error, RpcChannel, RpcServerError},
+    transport::{reconnecting::BackoffPolicy, ConnectionErrors, StreamTypes},
+    Connector, ErrorSource, RpcClient, Service,
 };
 
-/// Rpc interaction pattern
-///
-/// There is only one request and one response.
-#[derive(Debug, Clone, Copy)]
-pub struct Rpc;
-impl InteractionPattern for Rpc {}
+pub use crate::message::{Rpc, RpcMsg};
 
-/// Defines the response type for a rpc message.
+/// Policy for [RpcClient::rpc_with_retry]
 ///
-/// Since this is the most common interaction pattern, this also implements [Msg] for you
-/// automatically, with the interaction pattern set to [Rpc]. This is to reduce boilerplate
-/// when defining rpc messages.
-pub trait RpcMsg<S: Service>: Msg<S, Pattern = Rpc> {
-    /// The type for the response
-    ///
-    /// For requests that can produce errors, this can be set to [Result<T, E>](std::result::Result).
-    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
-}
+/// `max_attempts` limits the number of calls, including the first one.
+pub type RetryPolicy = BackoffPolicy;
 
-/// We can only do this for one trait, so we do it for RpcMsg since it is the most common
-impl<T: RpcMsg<S>, S: Service> Msg<S> for T {
-    type Pattern = Rpc;
-}
-/// Client error. All client DSL methods return a `Result` with this error type.
+/// Client error of the rpc pattern
+///
+/// The client methods return a [CallError], this can be converted to one.
 #[derive(Debug)]
 pub enum Error<C: ConnectionErrors> {
     /// Unable to open a substream at all
@@ -47,20 +45,178 @@ pub enum Error<C: ConnectionErrors> {
     /// Unable to send the request to the server
     Send(C::SendError),
     /// Server closed the stream before sending a response
+    ///
+    /// This is also returned when the handler on the server panicked or dropped the
+    /// channel without responding, for transports that report this as a reset, see
+    /// [ConnectionErrors::is_reset].
     EarlyClose,
     /// Unable to receive the response from the server
     RecvError(C::RecvError),
     /// Unexpected response from the server
     DowncastError,
+    /// The call did not complete within the given timeout
+    Timeout {
+        /// Whether the request may have reached the server when the timeout elapsed.
+        ///
+        /// This is `true` as soon as sending the request has started, even if the send did
+        /// not complete. If it is `false`, the server never saw the request, so it is safe
+        /// to retry. If it is `true`, the server may or may not have processed it.
+        sent: bool,
+    },
 }
 
 impl<C: ConnectionErrors> fmt::Display for Error<C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        match self {
+            Self::Open(_) => write!(f, "failed to open a substream"),
+            Self::Send(_) => write!(f, "failed to send the request"),
+            Self::EarlyClose => write!(f, "server closed the stream before sending a response"),
+            Self::RecvError(_) => write!(f, "failed to receive the response"),
+            Self::DowncastError => write!(f, "unexpected response from the server"),
+            Self::Timeout { sent: false } => write!(f, "timed out before the request was sent"),
+            Self::Timeout { sent: true } => write!(f, "timed out after sending the request"),
+        }
+    }
+}
+
+impl<C: ConnectionErrors> error::Error for Error<C> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Open(cause) => Some(ErrorSource::new(cause)),
+            Self::Send(cause) => Some(ErrorSource::new(cause)),
+            Self::RecvError(cause) => Some(ErrorSource::new(cause)),
+            _ => None,
+        }
     }
 }
 
-impl<C: ConnectionErrors> error::Error for Error<C> {}
+impl<C: ConnectionErrors, A> From<Error<C>> for CallError<C, A> {
+    fn from(e: Error<C>) -> Self {
+        match e {
+            Error::Open(cause) => CallError::Open(cause),
+            Error::Send(cause) => CallError::Send(cause),
+            Error::EarlyClose => CallError::EarlyClose,
+            Error::RecvError(cause) => CallError::recv(cause),
+            Error::DowncastError => CallError::Downcast,
+            Error::Timeout { sent } => CallError::Timeout { sent },
+        }
+    }
+}
+
+/// A key that identifies a request across retries
+///
+/// The server can use this to detect requests that it has already handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct IdempotencyKey(pub u128);
+
+impl IdempotencyKey {
+    /// A new random key, from the random source of the operating system
+    ///
+    /// # Panics
+    ///
+    /// If the operating system has no random source.
+    pub fn random() -> Self {
+        let mut bytes = [0u8; 16];
+        getrandom::getrandom(&mut bytes).expect("no random source");
+        Self(u128::from_le_bytes(bytes))
+    }
+}
+
+impl fmt::Display for IdempotencyKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:032x}", self.0)
+    }
+}
+
+/// A rpc request together with an [IdempotencyKey]
+///
+/// The key is created once in [Idempotent::new], so all clones share it. When used with
+/// [RpcClient::rpc_with_retry], every attempt carries the same key, and the handler on
+/// the server can read it from the request to deduplicate.
+///
+/// To use this, the service request enum needs a variant for `Idempotent<M>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Idempotent<M> {
+    /// The key of the request
+    pub key: IdempotencyKey,
+    /// The request
+    pub msg: M,
+}
+
+impl<M> Idempotent<M> {
+    /// Wrap a request with a new random key
+    pub fn new(msg: M) -> Self {
+        Self {
+            key: IdempotencyKey::random(),
+            msg,
+        }
+    }
+}
+
+impl<S, M> RpcMsg<S> for Idempotent<M>
+where
+    S: Service,
+    M: RpcMsg<S>,
+    Idempotent<M>: Into<S::Req> + TryFrom<S::Req> + Send + 'static,
+{
+    type Response = M::Response;
+}
+
+/// A request or response of [RpcClient::rpc_pipeline_unordered], tagged with the index
+/// of the request
+///
+/// The server sends the response to a request with the same index, so the client can
+/// match them up when the server completes the requests out of order, see
+/// [RpcChannel::rpc_pipeline_unordered].
+///
+/// To use this, the service request enum needs a variant for `Tagged<M>`, and the
+/// response enum one for `Tagged<M::Response>`, with the conversions of
+/// [crate::message::RpcMsg], e.g. derived with `derive_more::{From, TryInto}`:
+///
+/// ```
+/// # use derive_more::{From, TryInto};
+/// # use quic_rpc::{message::RpcMsg, pattern::rpc::Tagged, Service};
+/// # use serde::{Deserialize, Serialize};
+/// # #[derive(Debug, Clone)]
+/// # struct EchoService;
+/// # impl Service for EchoService {
+/// #     type Req = EchoRequest;
+/// #     type Res = EchoResponse;
+/// # }
+/// # #[derive(Debug, Serialize, Deserialize)]
+/// # struct Echo(u64);
+/// # impl RpcMsg<EchoService> for Echo {
+/// #     type Response = u64;
+/// # }
+/// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+/// enum EchoRequest {
+///     Echo(Echo),
+///     TaggedEcho(Tagged<Echo>),
+/// }
+///
+/// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+/// enum EchoResponse {
+///     Echoed(u64),
+///     TaggedEchoed(Tagged<u64>),
+/// }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tagged<T> {
+    /// The index of the request in the pipeline
+    pub index: u64,
+    /// The request or response
+    pub msg: T,
+}
+
+impl<S, M> RpcMsg<S> for Tagged<M>
+where
+    S: Service,
+    M: RpcMsg<S>,
+    Tagged<M>: Into<S::Req> + TryFrom<S::Req> + Send + 'static,
+    Tagged<M::Response>: Into<S::Res> + TryFrom<S::Res> + Send + 'static,
+{
+    type Response = Tagged<M::Response>;
+}
 
 impl<S, C> RpcClient<S, C>
 where
@@ -68,21 +224,367 @@ where
     C: Connector<S>,
 {
     /// RPC call to the server, single request, single response
-    pub async fn rpc<M>(&self, msg: M) -> result::Result<M::Response, Error<C>>
+    pub async fn rpc<M>(&self, msg: M) -> result::Result<M::Response, CallError<C>>
     where
         M: RpcMsg<S>,
     {
         let msg = msg.into();
-        let (mut send, mut recv) = self.source.open().await.map_err(Error::Open)?;
-        send.send(msg).await.map_err(Error::<C>::Send)?;
+        let (mut send, mut recv) = self.source.open_rpc().await.map_err(CallError::Open)?;
+        send.send(msg).await.map_err(CallError::<C>::Send)?;
         let res = recv
             .next()
             .await
-            .ok_or(Error::<C>::EarlyClose)?
-            .map_err(Error::<C>::RecvError)?;
+            .ok_or(CallError::<C>::EarlyClose)?
+            .map_err(CallError::<C>::recv)?;
         // keep send alive until we have the answer
         drop(send);
-        M::Response::try_from(res).map_err(|_| Error::DowncastError)
+        M::Response::try_from(res).map_err(|_| CallError::Downcast)
+    }
+
+    /// RPC call to the server, single request, single response, with a timeout
+    ///
+    /// The timeout covers the entire round trip: opening the substream, sending the
+    /// request and receiving the response. If it elapses, the substream is dropped and
+    /// [CallError::Timeout] is returned.
+    ///
+    /// The timeout is a glib timer, so the call has to be polled on the thread that owns
+    /// the glib main context, otherwise it panics.
+    ///
+    /// The time that is left after opening the substream is sent to the server ahead of
+    /// the request, see [StreamTypes::set_deadline], so the server can stop working on
+    /// the request once the client has given up, see
+    /// [RpcChannel::deadline](crate::server::RpcChannel::deadline). This needs a
+    /// transport that carries deadlines, on all others the server does not know about
+    /// the timeout.
+    pub async fn rpc_with_timeout<M>(
+        &self,
+        msg: M,
+        timeout: Duration,
+    ) -> result::Result<M::Response, CallError<C>>
+    where
+        M: RpcMsg<S>,
+    {
+        let res = self.rpc_with_timeout_impl(timeout, msg.into()).await?;
+        M::Response::try_from(res).map_err(|_| CallError::Downcast)
+    }
+
+    /// Open a substream, send a single request and receive a single response, unless
+    /// `timeout` elapses. The time that is left is sent ahead of the request.
+    async fn rpc_with_timeout_impl(
+        &self,
+        timeout: Duration,
+        msg: S::Req,
+    ) -> result::Result<S::Res, CallError<C>> {
+        let start = Instant::now();
+        let mut deadline = glib::timeout_future(timeout);
+        let (mut send, mut recv) =
+            futures_lite::future::or(self.source.open().map(Some), (&mut deadline).map(|_| None))
+                .await
+                .ok_or(CallError::Timeout { sent: false })?
+                .map_err(CallError::Open)?;
+        // transports without deadlines just don't tell the server
+        C::set_deadline(&send, timeout.saturating_sub(start.elapsed())).ok();
+        futures_lite::future::or(send.send(msg).map(Some), (&mut deadline).map(|_| None))
+            .await
+            // part of the request may already be on its way
+            .ok_or(CallError::Timeout { sent: true })?
+            .map_err(CallError::<C>::Send)?;
+        let res = futures_lite::future::or(recv.next().map(Some), (&mut deadline).map(|_| None))
+            .await
+            // on timeout, send and recv are dropped here, which closes the substream
+            .ok_or(CallError::Timeout { sent: true })?
+            .ok_or(CallError::<C>::EarlyClose)?
+            .map_err(CallError::<C>::recv)?;
+        // keep send alive until we have the answer
+        drop(send);
+        Ok(res)
+    }
+
+    /// RPC call to the server, retrying on transport errors
+    ///
+    /// Each attempt opens a fresh substream. Only failures to open the substream, to
+    /// send the request or to receive the response are retried. A response that was
+    /// received, including an application level error, is returned as is.
+    ///
+    /// Since the server may have handled the request before the transport failed, the
+    /// request should be idempotent. Wrap it in an [Idempotent] so the server can
+    /// deduplicate it.
+    ///
+    /// The delay between attempts is a glib timer, so with a non-zero delay the call has
+    /// to be polled on the thread that owns the glib main context, otherwise it panics.
+    pub async fn rpc_with_retry<M>(
+        &self,
+        msg: M,
+        policy: RetryPolicy,
+    ) -> result::Result<M::Response, CallError<C>>
+    where
+        M: RpcMsg<S> + Clone,
+    {
+        let mut delay = policy.initial_delay;
+        let mut attempt = 1;
+        loop {
+            let cause = match self.rpc(msg.clone()).await {
+                Err(cause) if cause.is_transport() => cause,
+                res => return res,
+            };
+            if attempt >= policy.max_attempts {
+                return Err(cause);
+            }
+            tracing::debug!(%cause, attempt, "rpc failed, retrying");
+            if !delay.is_zero() {
+                glib::timeout_future(delay).await;
+            }
+            delay = delay
+                .checked_mul(policy.multiplier)
+                .unwrap_or(policy.max_delay)
+                .min(policy.max_delay);
+            attempt += 1;
+        }
+    }
+
+    /// Pipeline a batch of RPC calls of the same type over a single substream
+    ///
+    /// All requests are sent on one substream, without waiting for the responses, and the
+    /// responses are returned in the order of the requests. This avoids opening a
+    /// substream per call.
+    ///
+    /// A response that can not be converted to `M::Response` yields
+    /// [CallError::Downcast] for that call only. A transport error yields the error and
+    /// ends the stream, so the remaining calls have no response.
+    ///
+    /// The handler on the server has to use [RpcChannel::rpc_pipeline] for `M`. A handler
+    /// using [RpcChannel::rpc] treats the second request as an unexpected update.
+    pub async fn rpc_pipeline<M, I>(
+        &self,
+        msgs: I,
+    ) -> result::Result<
+        BoxStreamSync<'static, result::Result<M::Response, CallError<C>>>,
+        CallError<C>,
+    >
+    where
+        M: RpcMsg<S>,
+        I: IntoIterator<Item = M>,
+        I::IntoIter: Send + Sync + 'static,
+    {
+        let mut msgs = msgs.into_iter().map(Into::into).peekable();
+        // without requests there is no need for a substream
+        let channel = match msgs.peek() {
+            Some(_) => Some(self.source.open().await.map_err(CallError::Open)?),
+            None => None,
+        };
+        Ok(Box::pin(Pipeline::<C, _, M::Response>::new(channel, msgs)))
+    }
+
+    /// Pipeline a batch of RPC calls of the same type over a single substream, receiving
+    /// the responses as they complete
+    ///
+    /// Like [RpcClient::rpc_pipeline], but every request is sent as a [Tagged] with its
+    /// index in `msgs`, so the server can complete the requests in any order. The stream
+    /// yields the index of every request together with its response, as the responses
+    /// arrive.
+    ///
+    /// Every request that was sent is yielded once. If the substream fails, or a response
+    /// can not be converted to `Tagged<M::Response>`, so it is not known which request it
+    /// belongs to, the first request without a response yields the error, the other ones
+    /// yield [CallError::EarlyClose], and the stream ends. If sending fails, the error is
+    /// yielded with the index of the first request that was not sent.
+    ///
+    /// The handler on the server has to use [RpcChannel::rpc_pipeline_unordered] for `M`.
+    /// The tagged requests and responses go through the service enums, which need
+    /// variants for `Tagged<M>` and `Tagged<M::Response>`, see [Tagged].
+    pub async fn rpc_pipeline_unordered<M, I>(
+        &self,
+        msgs: I,
+    ) -> result::Result<
+        BoxStreamSync<'static, (u64, result::Result<M::Response, CallError<C>>)>,
+        CallError<C>,
+    >
+    where
+        M: RpcMsg<S>,
+        Tagged<M>: Into<S::Req>,
+        Tagged<M::Response>: TryFrom<S::Res>,
+        I: IntoIterator<Item = M>,
+        I::IntoIter: Send + Sync + 'static,
+    {
+        let mut msgs = msgs
+            .into_iter()
+            .enumerate()
+            .map(|(index, msg)| {
+                Tagged {
+                    index: index as u64,
+                    msg,
+                }
+                .into()
+            })
+            .peekable();
+        let channel = match msgs.peek() {
+            Some(_) => Some(self.source.open().await.map_err(CallError::Open)?),
+            None => None,
+        };
+        Ok(Box::pin(UnorderedPipeline {
+            inner: Pipeline::<C, _, Tagged<M::Response>>::new(channel, msgs),
+            answered: HashSet::new(),
+            unanswered: None,
+        }))
+    }
+}
+
+/// The response stream of [RpcClient::rpc_pipeline]
+///
+/// Sends the requests while receiving the responses, so neither side blocks the other.
+struct Pipeline<C: StreamTypes, I: Iterator<Item = C::Out>, R> {
+    /// The send side, dropped once all requests are sent or sending failed
+    send: Option<C::SendSink>,
+    /// The receive side, dropped once the stream is done
+    recv: Option<C::RecvStream>,
+    /// The requests that are not sent yet, `None` once all are sent
+    msgs: Option<Peekable<I>>,
+    sent: usize,
+    received: usize,
+    /// Reported after the responses to the requests that were sent
+    send_error: Option<C::SendError>,
+    _p: PhantomData<fn() -> R>,
+}
+
+// the fields are never pinned
+impl<C: StreamTypes, I: Iterator<Item = C::Out>, R> Unpin for Pipeline<C, I, R> {}
+
+impl<C: StreamTypes, I: Iterator<Item = C::Out>, R> Pipeline<C, I, R> {
+    /// A pipeline of `msgs` on `channel`, which is done right away without a channel
+    fn new(channel: Option<(C::SendSink, C::RecvStream)>, msgs: Peekable<I>) -> Self {
+        let (send, recv) = channel.unzip();
+        Self {
+            send,
+            recv,
+            msgs: Some(msgs),
+            sent: 0,
+            received: 0,
+            send_error: None,
+            _p: PhantomData,
+        }
+    }
+
+    /// Send as many requests as possible, and close the send side after the last one
+    fn poll_send(&mut self, cx: &mut Context<'_>) {
+        let Some(send) = self.send.as_mut() else {
+            return;
+        };
+        let res = loop {
+            let Some(msgs) = self.msgs.as_mut() else {
+                match send.poll_close_unpin(cx) {
+                    Poll::Ready(res) => break res,
+                    Poll::Pending => return,
+                }
+            };
+            match send.poll_ready_unpin(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(cause)) => break Err(cause),
+                Poll::Pending => return,
+            }
+            match msgs.next() {
+                Some(msg) => {
+                    if let Err(cause) = send.start_send_unpin(msg) {
+                        break Err(cause);
+                    }
+                    self.sent += 1;
+                }
+                None => self.msgs = None,
+            }
+        };
+        self.send = None;
+        self.msgs = None;
+        self.send_error = res.err();
+    }
+}
+
+impl<C, I, R> Stream for Pipeline<C, I, R>
+where
+    C: StreamTypes,
+    I: Iterator<Item = C::Out>,
+    R: TryFrom<C::In>,
+{
+    type Item = result::Result<R, CallError<C>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.poll_send(cx);
+        let Some(recv) = this.recv.as_mut() else {
+            return Poll::Ready(None);
+        };
+        if this.received < this.sent {
+            let res = match recv.poll_next(cx) {
+                Poll::Ready(Some(Ok(res))) => {
+                    this.received += 1;
+                    return Poll::Ready(Some(R::try_from(res).map_err(|_| CallError::Downcast)));
+                }
+                Poll::Ready(Some(Err(cause))) => CallError::recv(cause),
+                Poll::Ready(None) => CallError::EarlyClose,
+                Poll::Pending => return Poll::Pending,
+            };
+            // the transport failed, so there will be no more responses
+            this.send = None;
+            this.recv = None;
+            return Poll::Ready(Some(Err(res)));
+        }
+        if this.send.is_some() {
+            // still sending, woken by the send side
+            return Poll::Pending;
+        }
+        this.recv = None;
+        Poll::Ready(
+            this.send_error
+                .take()
+                .map(|cause| Err(CallError::Send(cause))),
+        )
+    }
+}
+
+/// The response stream of [RpcClient::rpc_pipeline_unordered]
+struct UnorderedPipeline<C: StreamTypes, I: Iterator<Item = C::Out>, R> {
+    inner: Pipeline<C, I, Tagged<R>>,
+    /// The indices of the requests that got a response
+    answered: HashSet<u64>,
+    /// The requests that will not get a response, once the substream failed
+    unanswered: Option<vec::IntoIter<u64>>,
+}
+
+impl<C, I, R> Stream for UnorderedPipeline<C, I, R>
+where
+    C: StreamTypes,
+    I: Iterator<Item = C::Out>,
+    Tagged<R>: TryFrom<C::In>,
+{
+    type Item = (u64, result::Result<R, CallError<C>>);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(unanswered) = this.unanswered.as_mut() {
+            return Poll::Ready(
+                unanswered
+                    .next()
+                    .map(|index| (index, Err(CallError::EarlyClose))),
+            );
+        }
+        let cause = match ready!(this.inner.poll_next(cx)) {
+            Some(Ok(Tagged { index, msg })) => {
+                this.answered.insert(index);
+                return Poll::Ready(Some((index, Ok(msg))));
+            }
+            Some(Err(cause)) => cause,
+            None => return Poll::Ready(None),
+        };
+        // there will be no more responses that can be matched to a request
+        let sent = this.inner.sent as u64;
+        let mut unanswered = (0..sent)
+            .filter(|index| !this.answered.contains(index))
+            .collect::<Vec<_>>()
+            .into_iter();
+        let index = unanswered.next().unwrap_or(sent);
+        this.inner.send = None;
+        this.inner.recv = None;
+        this.inner.msgs = None;
+        this.unanswered = Some(unanswered);
+        Poll::Ready(Some((index, Err(cause))))
     }
 }
 
@@ -93,7 +595,8 @@ where
 {
     /// handle the message of type `M` using the given function on the target object
     ///
-    /// If you want to support concurrent requests, you need to spawn this on a tokio task yourself.
+    /// To handle concurrent requests, run this from [RpcServer::accept_loop](crate::RpcServer::accept_loop),
+    /// which handles every channel in a separate task, or spawn it yourself.
     pub async fn rpc<M, F, Fut, T>(
         self,
         req: M,
@@ -107,22 +610,146 @@ where
         T: Send + 'static,
     {
         let Self {
-            mut send, mut recv, ..
+            mut send,
+            mut recv,
+            metrics,
+            handler_timeouts,
+            error_response,
+            ..
         } = self;
         // cancel if we get an update, no matter what it is
         let cancel = recv
             .next()
             .map(|_| RpcServerError::UnexpectedUpdateMessage::<C>);
         // race the computation and the cancellation
-        race2(cancel.map(Err), async move {
-            // get the response
-            let res = f(target, req).await;
-            // turn into a S::Res so we can send it
-            let res = res.into();
-            // send it and return the error if any
-            send.send(res).await.map_err(RpcServerError::SendError)
+        let res = instrument(
+            Pattern::Rpc,
+            metrics,
+            handler_timeouts,
+            race2(cancel.map(Err), async {
+                // get the response
+                let res = f(target, req).await;
+                // turn into a S::Res so we can send it
+                let res = res.into();
+                // send it and return the error if any
+                send.send(res).await.map_err(RpcServerError::SendError)
+            }),
+        )
+        .await;
+        respond_to_error(&mut send, error_response, res).await
+    }
+
+    /// handle a pipeline of messages of type `M` using the given function on the target object
+    ///
+    /// This handles `req` like [RpcChannel::rpc], then every further request on the same
+    /// channel, in order, until the client closes its send side. This supports both
+    /// [RpcClient::rpc] and [RpcClient::rpc_pipeline]. Unlike [RpcChannel::rpc], a
+    /// running call is not cancelled by the client.
+    pub async fn rpc_pipeline<M, F, Fut, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: RpcMsg<S>,
+        F: Fn(T, M) -> Fut,
+        Fut: Future<Output = M::Response>,
+        T: Clone + Send + 'static,
+    {
+        let Self {
+            mut send,
+            mut recv,
+            metrics,
+            handler_timeouts,
+            error_response,
+            ..
+        } = self;
+        let res = instrument(Pattern::Rpc, metrics, handler_timeouts, async {
+            let mut req = req;
+            loop {
+                let res = f(target.clone(), req).await;
+                send.send(res.into())
+                    .await
+                    .map_err(RpcServerError::SendError)?;
+                req = match recv.next().await {
+                    Some(Ok(msg)) => {
+                        M::try_from(msg).map_err(|_| RpcServerError::UnexpectedUpdateMessage)?
+                    }
+                    Some(Err(cause)) => return Err(RpcServerError::RecvError(cause)),
+                    None => return Ok(()),
+                };
+            }
+        })
+        .await;
+        respond_to_error(&mut send, error_response, res).await
+    }
+
+    /// handle a pipeline of [Tagged] messages of type `M` using the given function on the
+    /// target object, completing them concurrently
+    ///
+    /// Like [RpcChannel::rpc_pipeline], but the calls run concurrently, and every response
+    /// is sent as soon as it is ready, tagged with the index of its request. This supports
+    /// [RpcClient::rpc_pipeline_unordered]. A running call is not cancelled by the client.
+    /// Like on the client, the service enums need variants for `Tagged<M>` and
+    /// `Tagged<M::Response>`.
+    pub async fn rpc_pipeline_unordered<M, F, Fut, T>(
+        self,
+        req: Tagged<M>,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: RpcMsg<S>,
+        Tagged<M>: TryFrom<S::Req>,
+        Tagged<M::Response>: Into<S::Res>,
+        F: Fn(T, M) -> Fut,
+        Fut: Future<Output = M::Response>,
+        T: Clone + Send + 'static,
+    {
+        let Self {
+            mut send,
+            mut recv,
+            metrics,
+            handler_timeouts,
+            error_response,
+            ..
+        } = self;
+        let res = instrument(Pattern::Rpc, metrics, handler_timeouts, async {
+            let call = |Tagged { index, msg }: Tagged<M>| {
+                f(target.clone(), msg).map(move |msg| Tagged { index, msg })
+            };
+            let mut running = FuturesUnordered::new();
+            running.push(call(req));
+            let mut requests_done = false;
+            loop {
+                let event = match (requests_done, running.is_empty()) {
+                    (true, true) => return Ok(()),
+                    (true, false) => Either::Right(running.next().await),
+                    (false, true) => Either::Left(recv.next().await),
+                    (false, false) => match future::select(recv.next(), running.next()).await {
+                        Either::Left((req, _)) => Either::Left(req),
+                        Either::Right((res, _)) => Either::Right(res),
+                    },
+                };
+                match event {
+                    Either::Left(Some(Ok(msg))) => {
+                        let msg = Tagged::<M>::try_from(msg)
+                            .map_err(|_| RpcServerError::UnexpectedUpdateMessage)?;
+                        running.push(call(msg));
+                    }
+                    Either::Left(Some(Err(cause))) => return Err(RpcServerError::RecvError(cause)),
+                    Either::Left(None) => requests_done = true,
+                    Either::Right(Some(res)) => send
+                        .send(res.into())
+                        .await
+                        .map_err(RpcServerError::SendError)?,
+                    Either::Right(None) => {}
+                }
+            }
         })
-        .await
+        .await;
+        respond_to_error(&mut send, error_response, res).await
     }
 
     /// A rpc call that also maps the error from the user type to the wire type
@@ -1,70 +1,208 @@
 //! Server streaming interaction pattern.
+//!
+//! # Resuming
+//!
+//! With [RpcClient::server_streaming_resumable] and
+//! [RpcChannel::server_streaming_resumable], a stream that is interrupted by the
+//! transport, e.g. because a mobile client lost its connection, continues where it
+//! stopped instead of starting over. The server numbers the responses from 0 and sends
+//! them as [ResumableResponse::Item], followed by [ResumableResponse::End] once the
+//! stream of the handler is done. Right after the request, the client sends a [Resume]
+//! with the sequence number of the last response it received, which is `None` for a new
+//! call. The handler gets the [Resume] and has to produce the responses from
+//! [Resume::next_seq] on, e.g. by skipping that many items of a deterministic source.
+//!
+//! When receiving fails in the transport, or the responses end without
+//! [ResumableResponse::End], the client sends the same request on a new substream,
+//! together with the sequence number of the last response it received. So no response
+//! is received twice or skipped. To resume after the connection itself is lost, use a
+//! connector that reconnects, like the
+//! [ReconnectingConnector](crate::transport::reconnecting::ReconnectingConnector).
+//!
+//! # Credits
+//!
+//! With [RpcClient::server_streaming_with_credits] and
+//! [RpcChannel::server_streaming_with_credits], the client controls how many responses
+//! the server may send ahead, no matter how much the transport buffers. Right after the
+//! request, the client grants the initial window of a [CreditWindow] as a [Credit]. The
+//! server sends one response per credit, and stops taking responses from the handler
+//! once it has used them all. Once only the refill threshold of the granted responses
+//! are still outstanding, because the application consumed the others, the client
+//! grants enough credits to fill the window again. So at most the initial window of
+//! responses is in flight, which bounds the memory of a call even on transports without
+//! flow control, like [flume](crate::transport::flume).
 
 use std::{
-    error,
-    fmt::{self, Debug},
+    error, fmt,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
     result,
+    sync::{Arc, Mutex, PoisonError},
+    task::{ready, Context, Poll},
+    time::Duration,
 };
 
-use futures_lite::{Stream, StreamExt};
-use futures_util::{FutureExt, SinkExt, TryFutureExt};
+use futures_lite::{future::Boxed, Stream, StreamExt};
+use futures_sink::Sink;
+use futures_util::{
+    future::{self, Either},
+    FutureExt, SinkExt, TryFutureExt,
+};
 
+use super::rpc::RetryPolicy;
 use crate::{
-    client::{BoxStreamSync, DeferDrop},
-    message::{InteractionPattern, Msg},
-    server::{race2, RpcChannel, RpcServerError},
+    client::{BoxStreamSync, CallError, DeferDrop},
+    metrics::Pattern,
+    server::{
+        instrument, race2, respond_to_error, send_all, send_responses, Cancelled, ResponseSender,
+        ResponsesClosed, RpcChannel, RpcServerError,
+    },
     transport::{ConnectionErrors, Connector, StreamTypes},
-    RpcClient, Service,
+    ErrorSource, RpcClient, Service,
 };
 
-/// Server streaming interaction pattern
-///
-/// After the initial request, the server can send a stream of responses.
-#[derive(Debug, Clone, Copy)]
-pub struct ServerStreaming;
-impl InteractionPattern for ServerStreaming {}
-
-/// Defines response type for a server streaming message.
-pub trait ServerStreamingMsg<S: Service>: Msg<S, Pattern = ServerStreaming> {
-    /// The type for the response
-    ///
-    /// For requests that can produce errors, this can be set to [Result<T, E>](std::result::Result).
-    type Response: Into<S::Res> + TryFrom<S::Res> + Send + 'static;
-}
+pub use crate::message::{
+    Credit, ResumableResponse, Resume, ServerStreaming, ServerStreamingHeaderMsg,
+    ServerStreamingMsg,
+};
 
-/// Server error when accepting a server streaming request
+/// Client error when opening a server streaming request
+///
+/// The client methods return a [CallError], this can be converted to one.
 #[derive(Debug)]
 pub enum Error<C: ConnectionErrors> {
     /// Unable to open a substream at all
     Open(C::OpenError),
     /// Unable to send the request to the server
     Send(C::SendError),
+    /// Unable to receive the header from the server
+    RecvError(C::RecvError),
+    /// Server closed the stream before sending the header
+    EarlyClose,
+    /// Unexpected header from the server
+    DowncastError,
 }
 
 impl<S: Connector> fmt::Display for Error<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        match self {
+            Self::Open(_) => write!(f, "failed to open a substream"),
+            Self::Send(_) => write!(f, "failed to send the request"),
+            Self::RecvError(_) => write!(f, "failed to receive the header"),
+            Self::EarlyClose => write!(f, "server closed the stream before sending the header"),
+            Self::DowncastError => write!(f, "unexpected header from the server"),
+        }
+    }
+}
+
+impl<S: Connector> error::Error for Error<S> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Open(cause) => Some(ErrorSource::new(cause)),
+            Self::Send(cause) => Some(ErrorSource::new(cause)),
+            Self::RecvError(cause) => Some(ErrorSource::new(cause)),
+            _ => None,
+        }
     }
 }
 
-impl<S: Connector> error::Error for Error<S> {}
+impl<C: ConnectionErrors, A> From<Error<C>> for CallError<C, A> {
+    fn from(e: Error<C>) -> Self {
+        match e {
+            Error::Open(cause) => CallError::Open(cause),
+            Error::Send(cause) => CallError::Send(cause),
+            Error::RecvError(cause) => CallError::recv(cause),
+            Error::EarlyClose => CallError::EarlyClose,
+            Error::DowncastError => CallError::Downcast,
+        }
+    }
+}
 
 /// Client error when handling responses from a server streaming request
+///
+/// The client methods return a [CallError], this can be converted to one.
 #[derive(Debug)]
 pub enum ItemError<S: ConnectionErrors> {
     /// Unable to receive the response from the server
     RecvError(S::RecvError),
     /// Unexpected response from the server
     DowncastError,
+    /// No item was received within the item timeout
+    Timeout,
 }
 
 impl<S: ConnectionErrors> fmt::Display for ItemError<S> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        match self {
+            Self::RecvError(_) => write!(f, "failed to receive a response"),
+            Self::DowncastError => write!(f, "unexpected response from the server"),
+            Self::Timeout => write!(f, "timed out waiting for the next response"),
+        }
+    }
+}
+
+impl<S: ConnectionErrors> error::Error for ItemError<S> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::RecvError(cause) => Some(ErrorSource::new(cause)),
+            _ => None,
+        }
+    }
+}
+
+impl<C: ConnectionErrors, A> From<ItemError<C>> for CallError<C, A> {
+    fn from(e: ItemError<C>) -> Self {
+        match e {
+            ItemError::RecvError(cause) => CallError::recv(cause),
+            ItemError::DowncastError => CallError::Downcast,
+            ItemError::Timeout => CallError::Timeout { sent: true },
+        }
+    }
+}
+
+/// Server error of [RpcChannel::server_streaming_generator]
+///
+/// This combines the errors serving the request with the error returned by the handler.
+pub enum GeneratorError<C: ConnectionErrors, E: fmt::Debug> {
+    /// Error serving the request
+    Server(RpcServerError<C>),
+    /// The handler returned an error
+    Handler(E),
+}
+
+impl<C: ConnectionErrors, E: fmt::Debug> fmt::Debug for GeneratorError<C, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Server(cause) => f.debug_tuple("Server").field(cause).finish(),
+            Self::Handler(cause) => f.debug_tuple("Handler").field(cause).finish(),
+        }
     }
 }
 
-impl<S: ConnectionErrors> error::Error for ItemError<S> {}
+impl<C: ConnectionErrors, E: fmt::Debug> fmt::Display for GeneratorError<C, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Server(cause) => fmt::Display::fmt(cause, f),
+            Self::Handler(cause) => write!(f, "handler error: {cause:?}"),
+        }
+    }
+}
+
+impl<C: ConnectionErrors, E: fmt::Debug> error::Error for GeneratorError<C, E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Server(cause) => cause.source(),
+            Self::Handler(_) => None,
+        }
+    }
+}
+
+impl<C: ConnectionErrors, E: fmt::Debug> From<RpcServerError<C>> for GeneratorError<C, E> {
+    fn from(e: RpcServerError<C>) -> Self {
+        Self::Server(e)
+    }
+}
 
 impl<S, C> RpcClient<S, C>
 where
@@ -75,20 +213,554 @@ where
     pub async fn server_streaming<M>(
         &self,
         msg: M,
-    ) -> result::Result<BoxStreamSync<'static, result::Result<M::Response, ItemError<C>>>, Error<C>>
+    ) -> result::Result<
+        BoxStreamSync<'static, result::Result<M::Response, CallError<C>>>,
+        CallError<C>,
+    >
     where
         M: ServerStreamingMsg<S>,
     {
         let msg = msg.into();
-        let (mut send, recv) = self.source.open().await.map_err(Error::Open)?;
-        send.send(msg).map_err(Error::<C>::Send).await?;
+        let (mut send, recv) = self.source.open().await.map_err(CallError::Open)?;
+        send.send(msg).map_err(CallError::<C>::Send).await?;
+        let recv = recv.map(move |x| match x {
+            Ok(msg) => M::Response::try_from(msg).map_err(|_| CallError::Downcast),
+            Err(e) => Err(CallError::recv(e)),
+        });
+        // keep send alive so the request on the server side does not get cancelled
+        let recv = Box::pin(DeferDrop(recv, send));
+        Ok(recv)
+    }
+
+    /// Bidi call to the server, request opens a stream, response is a stream, with a
+    /// handle to cancel the call
+    ///
+    /// Same as [RpcClient::server_streaming], but also returns a [StreamHandle] that
+    /// tells the server to stop sending responses. Dropping the handle does not cancel
+    /// the call.
+    pub async fn server_streaming_with_handle<M>(
+        &self,
+        msg: M,
+    ) -> result::Result<
+        (
+            StreamHandle<C>,
+            BoxStreamSync<'static, result::Result<M::Response, CallError<C>>>,
+        ),
+        CallError<C>,
+    >
+    where
+        M: ServerStreamingMsg<S>,
+    {
+        let msg = msg.into();
+        let (mut send, recv) = self.source.open().await.map_err(CallError::Open)?;
+        send.send(msg).map_err(CallError::<C>::Send).await?;
+        let recv = recv.map(move |x| match x {
+            Ok(msg) => M::Response::try_from(msg).map_err(|_| CallError::Downcast),
+            Err(e) => Err(CallError::recv(e)),
+        });
+        // shared, so that the send side stays open until both are dropped
+        let send = Arc::new(Mutex::new(Some(send)));
+        let handle = StreamHandle { send: send.clone() };
+        let recv = Box::pin(DeferDrop(recv, send));
+        Ok((handle, recv))
+    }
+
+    /// Server streaming call to the server, with a timeout for each item
+    ///
+    /// If no item arrives within `item_timeout` of the previous one, or of the request
+    /// for the first item, the stream yields [ItemError::Timeout] and then terminates.
+    /// The total duration of the call is not limited. Dropping the stream cancels the
+    /// timer.
+    ///
+    /// The timer is a glib timer, so the stream has to be polled on the thread that owns
+    /// the glib main context, otherwise it panics.
+    pub async fn server_streaming_with_item_timeout<M>(
+        &self,
+        msg: M,
+        item_timeout: Duration,
+    ) -> result::Result<
+        BoxStreamSync<'static, result::Result<M::Response, ItemError<C>>>,
+        CallError<C>,
+    >
+    where
+        M: ServerStreamingMsg<S>,
+    {
+        let msg = msg.into();
+        let (mut send, recv) = self.source.open().await.map_err(CallError::Open)?;
+        send.send(msg).map_err(CallError::<C>::Send).await?;
         let recv = recv.map(move |x| match x {
             Ok(msg) => M::Response::try_from(msg).map_err(|_| ItemError::DowncastError),
             Err(e) => Err(ItemError::RecvError(e)),
         });
         // keep send alive so the request on the server side does not get cancelled
+        let recv = DeferDrop(recv, send);
+        Ok(Box::pin(ItemTimeout::new(recv, item_timeout)))
+    }
+
+    /// Server streaming call to the server, where the server sends a header before the
+    /// responses
+    ///
+    /// Returns once the header has been received. Fails with [CallError::EarlyClose] if the
+    /// server closes the stream before sending it.
+    pub async fn server_streaming_with_header<M>(
+        &self,
+        msg: M,
+    ) -> result::Result<
+        (
+            M::Header,
+            BoxStreamSync<'static, result::Result<M::Response, CallError<C>>>,
+        ),
+        CallError<C>,
+    >
+    where
+        M: ServerStreamingHeaderMsg<S>,
+    {
+        let msg = msg.into();
+        let (mut send, mut recv) = self.source.open().await.map_err(CallError::Open)?;
+        send.send(msg).map_err(CallError::<C>::Send).await?;
+        let header = recv
+            .next()
+            .await
+            .ok_or(CallError::EarlyClose)?
+            .map_err(CallError::recv)?;
+        let header = M::Header::try_from(header).map_err(|_| CallError::Downcast)?;
+        let recv = recv.map(move |x| match x {
+            Ok(msg) => M::Response::try_from(msg).map_err(|_| CallError::Downcast),
+            Err(e) => Err(CallError::recv(e)),
+        });
+        // keep send alive so the request on the server side does not get cancelled
         let recv = Box::pin(DeferDrop(recv, send));
-        Ok(recv)
+        Ok((header, recv))
+    }
+
+    /// Server streaming call to the server, that resumes where it stopped if the
+    /// transport fails
+    ///
+    /// See [Resuming](self#resuming). The handler on the server has to use
+    /// [RpcChannel::server_streaming_resumable]. Only opening the call fails right
+    /// away, after that every interruption is retried according to `policy`, where
+    /// `max_attempts` limits the attempts to resume in a row without receiving a
+    /// response. Once they are used up, the stream yields the last error and ends.
+    pub async fn server_streaming_resumable<M>(
+        &self,
+        msg: M,
+        policy: RetryPolicy,
+    ) -> result::Result<
+        BoxStreamSync<'static, result::Result<M::Response, CallError<C>>>,
+        CallError<C>,
+    >
+    where
+        M: ServerStreamingMsg<S> + Clone + Sync,
+        ResumableResponse<M::Response>: TryFrom<S::Res>,
+        Resume: Into<S::Req>,
+    {
+        let (send, recv) =
+            open_resumable::<S, C, M>(self.source.clone(), msg.clone(), Resume::default()).await?;
+        Ok(Box::pin(Resumable {
+            source: self.source.clone(),
+            msg,
+            last: None,
+            attempt: 0,
+            delay: policy.initial_delay,
+            policy,
+            state: ResumeState::Streaming(send, recv),
+            _p: PhantomData,
+        }))
+    }
+
+    /// Server streaming call to the server, where the client grants the server credits
+    /// to send responses
+    ///
+    /// See [Credits](self#credits). The handler on the server has to use
+    /// [RpcChannel::server_streaming_with_credits]. The returned stream grants more
+    /// credits as it is polled, so a consumer that stops polling stops the server once
+    /// the window is used up. If the server sends more responses than it was granted,
+    /// the stream yields [CallError::ProtocolViolation] and ends.
+    pub async fn server_streaming_with_credits<M>(
+        &self,
+        msg: M,
+        window: CreditWindow,
+    ) -> result::Result<
+        BoxStreamSync<'static, result::Result<M::Response, CallError<C>>>,
+        CallError<C>,
+    >
+    where
+        M: ServerStreamingMsg<S>,
+        Credit: Into<S::Req>,
+    {
+        let (mut send, recv) = self.source.open().await.map_err(CallError::Open)?;
+        send.feed(msg.into()).await.map_err(CallError::Send)?;
+        send.send(Credit(window.initial).into())
+            .await
+            .map_err(CallError::Send)?;
+        Ok(Box::pin(Credited::<S, C, M::Response> {
+            send,
+            recv,
+            window,
+            outstanding: window.initial,
+            pending: 0,
+            flushing: false,
+            done: false,
+            _p: PhantomData,
+        }))
+    }
+}
+
+/// Open a substream and send the request of a resumable call
+async fn open_resumable<S, C, M>(
+    source: C,
+    msg: M,
+    resume: Resume,
+) -> result::Result<(C::SendSink, C::RecvStream), CallError<C>>
+where
+    S: Service,
+    C: crate::Connector<S>,
+    M: ServerStreamingMsg<S>,
+    Resume: Into<S::Req>,
+{
+    let (mut send, recv) = source.open().await.map_err(CallError::Open)?;
+    send.feed(msg.into()).await.map_err(CallError::Send)?;
+    send.send(resume.into()).await.map_err(CallError::Send)?;
+    Ok((send, recv))
+}
+
+/// Opening the substream to resume a call
+///
+/// The future is only ever polled through `&mut`, the mutex just makes the stream `Sync`.
+type Reopen<C> = Mutex<
+    Boxed<
+        result::Result<
+            (<C as StreamTypes>::SendSink, <C as StreamTypes>::RecvStream),
+            CallError<C>,
+        >,
+    >,
+>;
+
+enum ResumeState<C: StreamTypes> {
+    /// Receiving responses, the send side is kept so the server does not stop
+    Streaming(C::SendSink, C::RecvStream),
+    /// Waiting for the delay and opening a new substream
+    Resuming(Reopen<C>),
+    Done,
+}
+
+/// Response stream of [RpcClient::server_streaming_resumable]
+struct Resumable<S, C: StreamTypes, M> {
+    source: C,
+    msg: M,
+    /// Sequence number of the last response received
+    last: Option<u64>,
+    /// Attempts to resume since the last response
+    attempt: usize,
+    delay: Duration,
+    policy: RetryPolicy,
+    state: ResumeState<C>,
+    _p: PhantomData<S>,
+}
+
+// the fields are never pinned
+impl<S, C: StreamTypes, M> Unpin for Resumable<S, C, M> {}
+
+impl<S, C, M> Resumable<S, C, M>
+where
+    S: Service,
+    C: crate::Connector<S>,
+    M: ServerStreamingMsg<S> + Clone,
+    Resume: Into<S::Req>,
+{
+    /// Start resuming after a failure, or give up
+    fn resume(&mut self, cause: CallError<C>) -> Option<CallError<C>> {
+        self.attempt += 1;
+        if !cause.is_transport() || self.attempt >= self.policy.max_attempts {
+            self.state = ResumeState::Done;
+            return Some(cause);
+        }
+        tracing::debug!(%cause, attempt = self.attempt, last = ?self.last, "stream interrupted, resuming");
+        let delay = self.delay;
+        self.delay = delay
+            .checked_mul(self.policy.multiplier)
+            .unwrap_or(self.policy.max_delay)
+            .min(self.policy.max_delay);
+        let open = open_resumable::<S, C, M>(
+            self.source.clone(),
+            self.msg.clone(),
+            Resume { last: self.last },
+        );
+        let reopen = async move {
+            if !delay.is_zero() {
+                glib::timeout_future(delay).await;
+            }
+            open.await
+        };
+        self.state = ResumeState::Resuming(Mutex::new(Box::pin(reopen)));
+        None
+    }
+}
+
+impl<S, C, M> Stream for Resumable<S, C, M>
+where
+    S: Service,
+    C: crate::Connector<S>,
+    M: ServerStreamingMsg<S> + Clone,
+    ResumableResponse<M::Response>: TryFrom<S::Res>,
+    Resume: Into<S::Req>,
+{
+    type Item = result::Result<M::Response, CallError<C>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let cause = match &mut this.state {
+                ResumeState::Streaming(_, recv) => match ready!(recv.poll_next(cx)) {
+                    Some(Ok(msg)) => match ResumableResponse::<M::Response>::try_from(msg) {
+                        Ok(ResumableResponse::Item { seq, item }) => {
+                            let next = Resume { last: this.last }.next_seq();
+                            if seq < next {
+                                // already received before resuming
+                                continue;
+                            }
+                            if seq > next {
+                                // responses were skipped, so the server does not resume
+                                this.state = ResumeState::Done;
+                                return Poll::Ready(Some(Err(CallError::Downcast)));
+                            }
+                            this.last = Some(seq);
+                            this.attempt = 0;
+                            this.delay = this.policy.initial_delay;
+                            return Poll::Ready(Some(Ok(item)));
+                        }
+                        Ok(ResumableResponse::End) => {
+                            this.state = ResumeState::Done;
+                            return Poll::Ready(None);
+                        }
+                        Err(_) => {
+                            this.state = ResumeState::Done;
+                            return Poll::Ready(Some(Err(CallError::Downcast)));
+                        }
+                    },
+                    Some(Err(cause)) => CallError::recv(cause),
+                    // the server went away before the end of the stream
+                    None => CallError::EarlyClose,
+                },
+                ResumeState::Resuming(reopen) => {
+                    let reopen = reopen.get_mut().unwrap_or_else(PoisonError::into_inner);
+                    match ready!(reopen.as_mut().poll(cx)) {
+                        Ok((send, recv)) => {
+                            this.state = ResumeState::Streaming(send, recv);
+                            continue;
+                        }
+                        Err(cause) => cause,
+                    }
+                }
+                ResumeState::Done => return Poll::Ready(None),
+            };
+            if let Some(cause) = this.resume(cause) {
+                return Poll::Ready(Some(Err(cause)));
+            }
+        }
+    }
+}
+
+/// A handle to cancel a server streaming call
+///
+/// Returned by [RpcClient::server_streaming_with_handle].
+pub struct StreamHandle<C: StreamTypes> {
+    send: Arc<Mutex<Option<C::SendSink>>>,
+}
+
+impl<C: StreamTypes> fmt::Debug for StreamHandle<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamHandle").finish()
+    }
+}
+
+impl<C: StreamTypes> StreamHandle<C> {
+    /// Tell the server to stop sending responses.
+    ///
+    /// This closes the send side of the substream. A handler using
+    /// [RpcChannel::server_streaming_with_cancel] sees this as its [Cancelled] signal,
+    /// and a handler using [RpcChannel::server_streaming] stops. Responses that were
+    /// already sent can still be received, after which the response stream ends.
+    pub async fn cancel(self) -> result::Result<(), C::SendError> {
+        let send = self
+            .send
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        match send {
+            Some(mut send) => send.close().await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// The credits of [RpcClient::server_streaming_with_credits], see [Credits](self#credits)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CreditWindow {
+    initial: u64,
+    refill: u64,
+}
+
+impl CreditWindow {
+    /// Set the number of responses the server may send ahead of the application.
+    pub fn initial(mut self, value: u64) -> Self {
+        self.initial = value.max(1);
+        self.refill = self.refill.min(self.initial - 1);
+        self
+    }
+
+    /// Set the number of outstanding responses at which the client grants more credits.
+    ///
+    /// This is at most one less than the initial window. A lower value sends fewer
+    /// credits, a higher value keeps the server busy while the credits are on the way.
+    pub fn refill(mut self, value: u64) -> Self {
+        self.refill = value.min(self.initial - 1);
+        self
+    }
+}
+
+impl Default for CreditWindow {
+    fn default() -> Self {
+        Self {
+            initial: 32,
+            refill: 16,
+        }
+    }
+}
+
+/// Response stream of [RpcClient::server_streaming_with_credits]
+struct Credited<S, C: StreamTypes, R> {
+    send: C::SendSink,
+    recv: C::RecvStream,
+    window: CreditWindow,
+    /// Responses the server may send with the credits it was granted
+    outstanding: u64,
+    /// Credits to grant once the sink is ready
+    pending: u64,
+    /// A grant has to be flushed
+    flushing: bool,
+    done: bool,
+    _p: PhantomData<fn() -> (S, R)>,
+}
+
+// the fields are never pinned
+impl<S, C: StreamTypes, R> Unpin for Credited<S, C, R> {}
+
+impl<S, C, R> Credited<S, C, R>
+where
+    S: Service,
+    C: StreamTypes<In = S::Res, Out = S::Req>,
+    Credit: Into<S::Req>,
+{
+    /// Hand pending credits to the sink and flush them, without waiting for either
+    fn poll_grant(&mut self, cx: &mut Context<'_>) -> result::Result<(), C::SendError> {
+        if self.pending > 0 {
+            if let Poll::Ready(res) = Pin::new(&mut self.send).poll_ready(cx) {
+                res?;
+                Pin::new(&mut self.send).start_send(Credit(self.pending).into())?;
+                self.outstanding += self.pending;
+                self.pending = 0;
+                self.flushing = true;
+            }
+        }
+        if self.flushing {
+            if let Poll::Ready(res) = Pin::new(&mut self.send).poll_flush(cx) {
+                res?;
+                self.flushing = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S, C, R> Stream for Credited<S, C, R>
+where
+    S: Service,
+    C: StreamTypes<In = S::Res, Out = S::Req>,
+    Credit: Into<S::Req>,
+    R: TryFrom<S::Res>,
+{
+    type Item = result::Result<R, CallError<C>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        if let Err(cause) = this.poll_grant(cx) {
+            this.done = true;
+            return Poll::Ready(Some(Err(CallError::Send(cause))));
+        }
+        let res = match ready!(this.recv.poll_next(cx)) {
+            Some(Ok(_)) if this.outstanding == 0 => Err(CallError::ProtocolViolation),
+            Some(Ok(msg)) => {
+                this.outstanding -= 1;
+                if this.outstanding + this.pending <= this.window.refill {
+                    this.pending = this.window.initial - this.outstanding;
+                }
+                R::try_from(msg).map_err(|_| CallError::Downcast)
+            }
+            Some(Err(cause)) => Err(CallError::recv(cause)),
+            None => {
+                this.done = true;
+                return Poll::Ready(None);
+            }
+        };
+        this.done = res.is_err();
+        Poll::Ready(Some(res))
+    }
+}
+
+/// Timer for [ItemTimeout]
+///
+/// The timer is only ever polled through `&mut`, the mutex just makes the stream `Sync`.
+pub(super) type Timer = Mutex<Pin<Box<dyn Future<Output = ()> + Send>>>;
+
+/// Stream adapter that fails with [ItemError::Timeout] if there is a gap between items
+struct ItemTimeout<St> {
+    inner: St,
+    timeout: Duration,
+    /// `None` once the stream has terminated. Dropping the timer cancels it.
+    timer: Option<Timer>,
+}
+
+impl<St> ItemTimeout<St> {
+    fn new(inner: St, timeout: Duration) -> Self {
+        Self {
+            inner,
+            timeout,
+            timer: Some(Mutex::new(glib::timeout_future(timeout))),
+        }
+    }
+}
+
+impl<St, T, C> Stream for ItemTimeout<St>
+where
+    St: Stream<Item = result::Result<T, ItemError<C>>> + Unpin,
+    C: ConnectionErrors,
+{
+    type Item = St::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.timer.is_none() {
+            return Poll::Ready(None);
+        }
+        if let Poll::Ready(item) = this.inner.poll_next(cx) {
+            this.timer = match item {
+                // restart the timer for the next item
+                Some(_) => Some(Mutex::new(glib::timeout_future(this.timeout))),
+                None => None,
+            };
+            return Poll::Ready(item);
+        }
+        let timer = this.timer.as_mut().expect("checked above");
+        let timer = timer.get_mut().unwrap_or_else(PoisonError::into_inner);
+        if timer.as_mut().poll(cx).is_ready() {
+            this.timer = None;
+            return Poll::Ready(Some(Err(ItemError::Timeout)));
+        }
+        Poll::Pending
     }
 }
 
@@ -99,7 +771,8 @@ where
 {
     /// handle the message M using the given function on the target object
     ///
-    /// If you want to support concurrent requests, you need to spawn this on a tokio task yourself.
+    /// To handle concurrent requests, run this from [RpcServer::accept_loop](crate::RpcServer::accept_loop),
+    /// which handles every channel in a separate task, or spawn it yourself.
     pub async fn server_streaming<M, F, Str, T>(
         self,
         req: M,
@@ -113,27 +786,420 @@ where
         T: Send + 'static,
     {
         let Self {
-            mut send, mut recv, ..
+            mut send,
+            mut recv,
+            metrics,
+            handler_timeouts,
+            response_batch,
+            memory_budget,
+            error_response,
+            ..
         } = self;
-        // cancel if we get an update, no matter what it is
-        let cancel = recv
-            .next()
-            .map(|_| RpcServerError::UnexpectedUpdateMessage::<C>);
+        // stop if the client closes its side, cancel if we get an update, no matter what it is
+        let cancel = recv.next().map(|msg| match msg {
+            None => Ok(()),
+            Some(_) => Err(RpcServerError::UnexpectedUpdateMessage::<C>),
+        });
         // race the computation and the cancellation
-        race2(cancel.map(Err), async move {
-            // get the response
-            let responses = f(target, req);
-            futures_lite::pin!(responses);
-            while let Some(response) = responses.next().await {
-                // turn into a S::Res so we can send it
-                let response = response.into();
-                // send it and return the error if any
-                send.send(response)
+        let res = instrument(
+            Pattern::ServerStreaming,
+            metrics,
+            handler_timeouts,
+            race2(cancel, async {
+                // get the response
+                let responses = f(target, req);
+                send_all(&mut send, responses, response_batch, memory_budget.as_ref()).await
+            }),
+        )
+        .await;
+        respond_to_error(&mut send, error_response, res).await
+    }
+
+    /// handle the message M using the given function on the target object, with a
+    /// cancellation signal
+    ///
+    /// Same as [RpcChannel::server_streaming], but the function also gets a [Cancelled]
+    /// future that resolves once the client has closed its side of the stream, e.g. by
+    /// dropping the response stream or by calling [StreamHandle::cancel]. This allows to
+    /// stop producing items early, for example in a task that feeds the returned stream.
+    ///
+    /// If the client goes away, the returned stream is dropped and this returns `Ok(())`.
+    pub async fn server_streaming_with_cancel<M, F, Str, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: ServerStreamingMsg<S>,
+        F: FnOnce(T, M, Cancelled) -> Str + Send + 'static,
+        Str: Stream<Item = M::Response> + Send + 'static,
+        T: Send + 'static,
+    {
+        let Self {
+            mut send,
+            mut recv,
+            metrics,
+            handler_timeouts,
+            response_batch,
+            memory_budget,
+            error_response,
+            ..
+        } = self;
+        let (trigger, cancelled) = Cancelled::new();
+        // the client closing its side of the stream means it is no longer interested
+        let cancel = async move {
+            let res = match recv.next().await {
+                None => Ok(()),
+                Some(Err(cause)) => Err(RpcServerError::RecvError(cause)),
+                Some(Ok(_)) => Err(RpcServerError::UnexpectedUpdateMessage),
+            };
+            drop(trigger);
+            res
+        };
+        // race the computation and the cancellation
+        let res = instrument(
+            Pattern::ServerStreaming,
+            metrics,
+            handler_timeouts,
+            race2(cancel, async {
+                // get the response
+                let responses = f(target, req, cancelled);
+                send_all(&mut send, responses, response_batch, memory_budget.as_ref()).await
+            }),
+        )
+        .await;
+        respond_to_error(&mut send, error_response, res).await
+    }
+
+    /// handle the message M using the given function on the target object, pushing the
+    /// responses to a [ResponseSender]
+    ///
+    /// Same as [RpcChannel::server_streaming], but instead of returning a stream, the
+    /// function gets a [ResponseSender] that can be cloned and moved to other tasks. All
+    /// responses pushed to it are sent to the client, interleaved in the order in which
+    /// they are pushed. The call completes once the returned future has completed and all
+    /// clones of the sender are dropped.
+    ///
+    /// If sending to the client fails or the client goes away, the returned future is
+    /// dropped and the remaining clones of the sender fail to send.
+    pub async fn server_streaming_with_sender<M, F, Fut, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: ServerStreamingMsg<S>,
+        F: FnOnce(T, M, ResponseSender<M::Response>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+        T: Send + 'static,
+    {
+        let Self {
+            mut send,
+            mut recv,
+            metrics,
+            handler_timeouts,
+            response_batch,
+            memory_budget,
+            error_response,
+            ..
+        } = self;
+        // stop if the client closes its side, cancel if we get an update, no matter what it is
+        let cancel = recv.next().map(|msg| match msg {
+            None => Ok(()),
+            Some(_) => Err(RpcServerError::UnexpectedUpdateMessage::<C>),
+        });
+        let res = instrument(
+            Pattern::ServerStreaming,
+            metrics,
+            handler_timeouts,
+            race2(
+                cancel,
+                send_responses(&mut send, response_batch, memory_budget, move |sender| {
+                    f(target, req, sender)
+                }),
+            ),
+        )
+        .await;
+        respond_to_error(&mut send, error_response, res).await
+    }
+
+    /// handle the message M using the given async function on the target object, which
+    /// sends the responses one by one
+    ///
+    /// This allows to write the handler as a loop that awaits and sends responses, instead
+    /// of constructing a stream. The error type of the function only has to convert from
+    /// [ResponsesClosed], so it can also be the error type of the handler:
+    ///
+    /// ```
+    /// use derive_more::{From, TryInto};
+    /// use quic_rpc::{
+    ///     message::{Msg, ServerStreaming, ServerStreamingMsg},
+    ///     pattern::server_streaming::GeneratorError,
+    ///     server::RpcChannel,
+    ///     transport::StreamTypes,
+    ///     Service,
+    /// };
+    /// use serde::{Deserialize, Serialize};
+    ///
+    /// #[derive(Debug, Serialize, Deserialize)]
+    /// struct Count(u64);
+    ///
+    /// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    /// enum Request {
+    ///     Count(Count),
+    /// }
+    ///
+    /// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    /// enum Response {
+    ///     Item(u64),
+    /// }
+    ///
+    /// #[derive(Debug, Clone)]
+    /// struct Counter;
+    ///
+    /// impl Service for Counter {
+    ///     type Req = Request;
+    ///     type Res = Response;
+    /// }
+    ///
+    /// impl Msg<Counter> for Count {
+    ///     type Pattern = ServerStreaming;
+    /// }
+    ///
+    /// impl ServerStreamingMsg<Counter> for Count {
+    ///     type Response = u64;
+    /// }
+    ///
+    /// async fn count<C: StreamTypes<In = Request, Out = Response>>(
+    ///     chan: RpcChannel<Counter, C>,
+    ///     req: Count,
+    /// ) -> Result<(), GeneratorError<C, anyhow::Error>> {
+    ///     chan.server_streaming_generator(req, (), |_, Count(n), sender| async move {
+    ///         anyhow::ensure!(n <= 1000, "too many items");
+    ///         for i in 0..n {
+    ///             sender.send(i).await?;
+    ///         }
+    ///         Ok(())
+    ///     })
+    ///     .await
+    /// }
+    /// ```
+    ///
+    /// If sending to the client fails or the client goes away, the returned future is
+    /// dropped, and this method reports the error like [RpcChannel::server_streaming] as
+    /// [GeneratorError::Server]. An error returned by the function itself is reported as
+    /// [GeneratorError::Handler]. Apart from that it behaves like
+    /// [RpcChannel::server_streaming_with_sender].
+    pub async fn server_streaming_generator<M, F, Fut, T, E>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), GeneratorError<C, E>>
+    where
+        M: ServerStreamingMsg<S>,
+        F: FnOnce(T, M, ResponseSender<M::Response>) -> Fut + Send + 'static,
+        Fut: Future<Output = result::Result<(), E>> + Send + 'static,
+        E: From<ResponsesClosed> + fmt::Debug + Send + 'static,
+        T: Send + 'static,
+    {
+        let (error_tx, mut error_rx) = futures::channel::oneshot::channel();
+        self.server_streaming_with_sender(req, target, move |target, req, sender| async move {
+            if let Err(cause) = f(target, req, sender).await {
+                error_tx.send(cause).ok();
+            }
+        })
+        .await?;
+        match error_rx.try_recv() {
+            Ok(Some(cause)) => Err(GeneratorError::Handler(cause)),
+            _ => Ok(()),
+        }
+    }
+
+    /// handle the message M using the given function on the target object, sending a
+    /// header before the responses
+    ///
+    /// Same as [RpcChannel::server_streaming], but the function returns a future that
+    /// resolves to the header and the stream of responses. The header is sent as the
+    /// first message, see [RpcClient::server_streaming_with_header].
+    pub async fn server_streaming_with_header<M, F, Fut, Str, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: ServerStreamingHeaderMsg<S>,
+        F: FnOnce(T, M) -> Fut + Send + 'static,
+        Fut: Future<Output = (M::Header, Str)> + Send + 'static,
+        Str: Stream<Item = M::Response> + Send + 'static,
+        T: Send + 'static,
+    {
+        let Self {
+            mut send,
+            mut recv,
+            metrics,
+            handler_timeouts,
+            response_batch,
+            memory_budget,
+            error_response,
+            ..
+        } = self;
+        // stop if the client closes its side, cancel if we get an update, no matter what it is
+        let cancel = recv.next().map(|msg| match msg {
+            None => Ok(()),
+            Some(_) => Err(RpcServerError::UnexpectedUpdateMessage::<C>),
+        });
+        // race the computation and the cancellation
+        let res = instrument(
+            Pattern::ServerStreaming,
+            metrics,
+            handler_timeouts,
+            race2(cancel, async {
+                let (header, responses) = f(target, req).await;
+                send.send(header.into())
                     .await
                     .map_err(RpcServerError::SendError)?;
+                send_all(&mut send, responses, response_batch, memory_budget.as_ref()).await
+            }),
+        )
+        .await;
+        respond_to_error(&mut send, error_response, res).await
+    }
+
+    /// handle the message M using the given function on the target object, resuming
+    /// where a previous call for the same request stopped
+    ///
+    /// See [Resuming](self#resuming). The function gets the [Resume] sent by the client,
+    /// and the returned stream has to start with the response numbered
+    /// [Resume::next_seq]. The responses are numbered consecutively from there, and
+    /// [ResumableResponse::End] is sent after the last one. The client has to use
+    /// [RpcClient::server_streaming_resumable].
+    pub async fn server_streaming_resumable<M, F, Str, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: ServerStreamingMsg<S>,
+        ResumableResponse<M::Response>: Into<S::Res>,
+        Resume: TryFrom<S::Req>,
+        F: FnOnce(T, M, Resume) -> Str + Send + 'static,
+        Str: Stream<Item = M::Response> + Send + 'static,
+        T: Send + 'static,
+    {
+        let Self {
+            mut send,
+            mut recv,
+            metrics,
+            handler_timeouts,
+            response_batch,
+            memory_budget,
+            error_response,
+            ..
+        } = self;
+        let res = instrument(Pattern::ServerStreaming, metrics, handler_timeouts, async {
+            // the client tells where to continue right after the request
+            let resume = match recv.next().await {
+                None => return Err(RpcServerError::<C>::EarlyClose),
+                Some(Err(cause)) => return Err(RpcServerError::RecvError(cause)),
+                Some(Ok(msg)) => {
+                    Resume::try_from(msg).map_err(|_| RpcServerError::UnexpectedUpdateMessage)?
+                }
+            };
+            // stop if the client closes its side, cancel if we get an update, no matter what it is
+            let cancel = recv.next().map(|msg| match msg {
+                None => Ok(()),
+                Some(_) => Err(RpcServerError::UnexpectedUpdateMessage::<C>),
+            });
+            // race the computation and the cancellation
+            race2(cancel, async {
+                let mut seq = resume.next_seq();
+                let responses = f(target, req, resume).map(move |item| {
+                    let response = ResumableResponse::Item { seq, item };
+                    seq += 1;
+                    response
+                });
+                send_all::<C, _>(&mut send, responses, response_batch, memory_budget.as_ref())
+                    .await?;
+                send.send(ResumableResponse::<M::Response>::End.into())
+                    .await
+                    .map_err(RpcServerError::SendError)
+            })
+            .await
+        })
+        .await;
+        respond_to_error(&mut send, error_response, res).await
+    }
+
+    /// handle the message M using the given function on the target object, sending a
+    /// response only for every credit the client granted
+    ///
+    /// See [Credits](self#credits). The client has to use
+    /// [RpcClient::server_streaming_with_credits]. The stream of the handler is only
+    /// polled while there are credits left, so a handler that produces responses faster
+    /// than the client consumes them is paused. Closing the client side cancels the call
+    /// like in [RpcChannel::server_streaming].
+    pub async fn server_streaming_with_credits<M, F, Str, T>(
+        self,
+        req: M,
+        target: T,
+        f: F,
+    ) -> result::Result<(), RpcServerError<C>>
+    where
+        M: ServerStreamingMsg<S>,
+        Credit: TryFrom<S::Req>,
+        F: FnOnce(T, M) -> Str + Send + 'static,
+        Str: Stream<Item = M::Response> + Send + 'static,
+        T: Send + 'static,
+    {
+        let Self {
+            mut send,
+            mut recv,
+            metrics,
+            handler_timeouts,
+            error_response,
+            ..
+        } = self;
+        let res = instrument(Pattern::ServerStreaming, metrics, handler_timeouts, async {
+            let responses = f(target, req);
+            futures_lite::pin!(responses);
+            let mut credits = 0u64;
+            loop {
+                // only take a response from the handler if it can be sent
+                let event = if credits == 0 {
+                    Either::Left(recv.next().await)
+                } else {
+                    match future::select(recv.next(), responses.next()).await {
+                        Either::Left((update, _)) => Either::Left(update),
+                        Either::Right((response, _)) => Either::Right(response),
+                    }
+                };
+                match event {
+                    Either::Left(Some(Ok(msg))) => {
+                        let Credit(n) = Credit::try_from(msg)
+                            .map_err(|_| RpcServerError::UnexpectedUpdateMessage)?;
+                        credits = credits.saturating_add(n);
+                    }
+                    Either::Left(Some(Err(cause))) => {
+                        return Err(RpcServerError::<C>::RecvError(cause))
+                    }
+                    // the client closed its side, so it is no longer interested
+                    Either::Left(None) => return Ok(()),
+                    Either::Right(Some(response)) => {
+                        credits -= 1;
+                        send.send(response.into())
+                            .await
+                            .map_err(RpcServerError::SendError)?;
+                    }
+                    Either::Right(None) => return Ok(()),
+                }
             }
-            Ok(())
         })
-        .await
+        .await;
+        respond_to_error(&mut send, error_response, res).await
     }
 }
@@ -8,50 +8,24 @@ use std::{
 
 use futures_lite::{Future, Stream, StreamExt};
 use futures_util::{FutureExt, SinkExt, TryFutureExt};
-use serde::{Deserialize, Serialize};
 
 use crate::{
-    client::{BoxStreamSync, DeferDrop},
-    message::{InteractionPattern, Msg},
-    server::{race2, RpcChannel, RpcServerError},
+    client::{BoxStreamSync, CallError, DeferDrop},
+    metrics::Pattern,
+    server::{instrument, race2, respond_to_error, send_all, RpcChannel, RpcServerError},
     transport::{self, ConnectionErrors, StreamTypes},
-    Connector, RpcClient, Service,
+    Connector, ErrorSource, RpcClient, Service,
 };
 
-/// A guard message to indicate that the stream has been created.
-///
-/// This is so we can dinstinguish between an error creating the stream and
-/// an error in the first item produced by the stream.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub struct StreamCreated;
-
-/// Fallible server streaming interaction pattern.
-#[derive(Debug, Clone, Copy)]
-pub struct TryServerStreaming;
-
-impl InteractionPattern for TryServerStreaming {}
-
-/// Same as ServerStreamingMsg, but with lazy stream creation and the error type explicitly defined.
-pub trait TryServerStreamingMsg<S: Service>: Msg<S, Pattern = TryServerStreaming>
-where
-    result::Result<Self::Item, Self::ItemError>: Into<S::Res> + TryFrom<S::Res>,
-    result::Result<StreamCreated, Self::CreateError>: Into<S::Res> + TryFrom<S::Res>,
-{
-    /// Error when creating the stream
-    type CreateError: Debug + Send + 'static;
+pub use crate::message::{StreamCreated, TryServerStreaming, TryServerStreamingMsg};
 
-    /// Error for stream items
-    type ItemError: Debug + Send + 'static;
-
-    /// Successful response item
-    type Item: Send + 'static;
-}
-
-/// Server error when accepting a server streaming request
+/// Client error when opening a fallible server streaming request
 ///
 /// This combines network errors with application errors. Usually you don't
 /// care about the exact nature of the error, but if you want to handle
 /// application errors differently, you can match on this enum.
+///
+/// The client methods return a [CallError], this can be converted to one.
 #[derive(Debug)]
 pub enum Error<C: transport::Connector, E: Debug> {
     /// Unable to open a substream at all
@@ -70,15 +44,46 @@ pub enum Error<C: transport::Connector, E: Debug> {
 
 impl<S: transport::Connector, E: Debug> fmt::Display for Error<S, E> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        match self {
+            Self::Open(_) => write!(f, "failed to open a substream"),
+            Self::Send(_) => write!(f, "failed to send the request"),
+            Self::Recv(_) => write!(f, "failed to receive the stream creation result"),
+            Self::EarlyClose => write!(f, "server closed the stream before creating it"),
+            Self::Downcast => write!(f, "unexpected response from the server"),
+            Self::Application(cause) => write!(f, "application error: {cause:?}"),
+        }
+    }
+}
+
+impl<S: transport::Connector, E: Debug> error::Error for Error<S, E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Open(cause) => Some(ErrorSource::new(cause)),
+            Self::Send(cause) => Some(ErrorSource::new(cause)),
+            Self::Recv(cause) => Some(ErrorSource::new(cause)),
+            _ => None,
+        }
     }
 }
 
-impl<S: transport::Connector, E: Debug> error::Error for Error<S, E> {}
+impl<C: transport::Connector, E: Debug> From<Error<C, E>> for CallError<C, E> {
+    fn from(e: Error<C, E>) -> Self {
+        match e {
+            Error::Open(cause) => CallError::Open(cause),
+            Error::Send(cause) => CallError::Send(cause),
+            Error::Recv(cause) => CallError::recv(cause),
+            Error::EarlyClose => CallError::EarlyClose,
+            Error::Downcast => CallError::Downcast,
+            Error::Application(cause) => CallError::App(cause),
+        }
+    }
+}
 
 /// Client error when handling responses from a server streaming request.
 ///
 /// This combines network errors with application errors.
+///
+/// The client methods return a [CallError], this can be converted to one.
 #[derive(Debug)]
 pub enum ItemError<S: ConnectionErrors, E: Debug> {
     /// Unable to receive the response from the server
@@ -91,11 +96,32 @@ pub enum ItemError<S: ConnectionErrors, E: Debug> {
 
 impl<S: ConnectionErrors, E: Debug> fmt::Display for ItemError<S, E> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(self, f)
+        match self {
+            Self::Recv(_) => write!(f, "failed to receive a response"),
+            Self::Downcast => write!(f, "unexpected response from the server"),
+            Self::Application(cause) => write!(f, "application error: {cause:?}"),
+        }
     }
 }
 
-impl<S: ConnectionErrors, E: Debug> error::Error for ItemError<S, E> {}
+impl<S: ConnectionErrors, E: Debug> error::Error for ItemError<S, E> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Recv(cause) => Some(ErrorSource::new(cause)),
+            _ => None,
+        }
+    }
+}
+
+impl<C: ConnectionErrors, E: Debug> From<ItemError<C, E>> for CallError<C, E> {
+    fn from(e: ItemError<C, E>) -> Self {
+        match e {
+            ItemError::Recv(cause) => CallError::recv(cause),
+            ItemError::Downcast => CallError::Downcast,
+            ItemError::Application(cause) => CallError::App(cause),
+        }
+    }
+}
 
 impl<S, C> RpcChannel<S, C>
 where
@@ -104,7 +130,8 @@ where
 {
     /// handle the message M using the given function on the target object
     ///
-    /// If you want to support concurrent requests, you need to spawn this on a tokio task yourself.
+    /// To handle concurrent requests, run this from [RpcServer::accept_loop](crate::RpcServer::accept_loop),
+    /// which handles every channel in a separate task, or spawn it yourself.
     ///
     /// Compared to [RpcChannel::server_streaming], with this method the stream creation is via
     /// a function that returns a future that resolves to a stream.
@@ -124,47 +151,51 @@ where
         T: Send + 'static,
     {
         let Self {
-            mut send, mut recv, ..
+            mut send,
+            mut recv,
+            metrics,
+            handler_timeouts,
+            response_batch,
+            memory_budget,
+            error_response,
+            ..
         } = self;
         // cancel if we get an update, no matter what it is
         let cancel = recv
             .next()
             .map(|_| RpcServerError::UnexpectedUpdateMessage::<C>);
         // race the computation and the cancellation
-        race2(cancel.map(Err), async move {
-            // get the response
-            let responses = match f(target, req).await {
-                Ok(responses) => {
-                    // turn into a S::Res so we can send it
-                    let response = Ok(StreamCreated).into();
-                    // send it and return the error if any
-                    send.send(response)
-                        .await
-                        .map_err(RpcServerError::SendError)?;
-                    responses
-                }
-                Err(cause) => {
-                    // turn into a S::Res so we can send it
-                    let response = Err(cause).into();
-                    // send it and return the error if any
-                    send.send(response)
-                        .await
-                        .map_err(RpcServerError::SendError)?;
-                    return Ok(());
-                }
-            };
-            futures_lite::pin!(responses);
-            while let Some(response) = responses.next().await {
-                // turn into a S::Res so we can send it
-                let response = response.into();
-                // send it and return the error if any
-                send.send(response)
-                    .await
-                    .map_err(RpcServerError::SendError)?;
-            }
-            Ok(())
-        })
-        .await
+        let res = instrument(
+            Pattern::TryServerStreaming,
+            metrics,
+            handler_timeouts,
+            race2(cancel.map(Err), async {
+                // get the response
+                let responses = match f(target, req).await {
+                    Ok(responses) => {
+                        // turn into a S::Res so we can send it
+                        let response = Ok(StreamCreated).into();
+                        // send it and return the error if any
+                        send.send(response)
+                            .await
+                            .map_err(RpcServerError::SendError)?;
+                        responses
+                    }
+                    Err(cause) => {
+                        // turn into a S::Res so we can send it
+                        let response = Err(cause).into();
+                        // send it and return the error if any
+                        send.send(response)
+                            .await
+                            .map_err(RpcServerError::SendError)?;
+                        return Ok(());
+                    }
+                };
+                send_all(&mut send, responses, response_batch, memory_budget.as_ref()).await
+            }),
+        )
+        .await;
+        respond_to_error(&mut send, error_response, res).await
     }
 }
 
@@ -173,13 +204,18 @@ where
     C: Connector<S>,
     S: Service,
 {
-    /// Bidi call to the server, request opens a stream, response is a stream
+    /// Fallible server streaming call to the server, request opens a stream, response is
+    /// a stream of items that can fail individually
+    ///
+    /// Fails with [CallError::App] if the server could not create the stream. An item
+    /// that failed on the server is returned as [CallError::App] as well, so it can be
+    /// told apart from a transport error, which is returned as e.g. [CallError::Recv].
     pub async fn try_server_streaming<M>(
         &self,
         msg: M,
     ) -> result::Result<
-        BoxStreamSync<'static, Result<M::Item, ItemError<C, M::ItemError>>>,
-        Error<C, M::CreateError>,
+        BoxStreamSync<'static, Result<M::Item, CallError<C, M::ItemError>>>,
+        CallError<C, M::CreateError>,
     >
     where
         M: TryServerStreamingMsg<S>,
@@ -187,20 +223,20 @@ where
         Result<StreamCreated, M::CreateError>: Into<S::Res> + TryFrom<S::Res>,
     {
         let msg = msg.into();
-        let (mut send, mut recv) = self.source.open().await.map_err(Error::Open)?;
-        send.send(msg).map_err(Error::Send).await?;
+        let (mut send, mut recv) = self.source.open().await.map_err(CallError::Open)?;
+        send.send(msg).map_err(CallError::Send).await?;
         let Some(initial) = recv.next().await else {
-            return Err(Error::EarlyClose);
+            return Err(CallError::EarlyClose);
         };
-        let initial = initial.map_err(Error::Recv)?; // initial response
+        let initial = initial.map_err(CallError::recv)?; // initial response
         let initial = <std::result::Result<StreamCreated, M::CreateError>>::try_from(initial)
-            .map_err(|_| Error::Downcast)?;
-        let _ = initial.map_err(Error::Application)?;
+            .map_err(|_| CallError::Downcast)?;
+        let _ = initial.map_err(CallError::App)?;
         let recv = recv.map(move |x| {
-            let x = x.map_err(ItemError::Recv)?;
+            let x = x.map_err(CallError::recv)?;
             let x = <std::result::Result<M::Item, M::ItemError>>::try_from(x)
-                .map_err(|_| ItemError::Downcast)?;
-            let x = x.map_err(ItemError::Application)?;
+                .map_err(|_| CallError::Downcast)?;
+            let x = x.map_err(CallError::App)?;
             Ok(x)
         });
         // keep send alive so the request on the server side does not get cancelled
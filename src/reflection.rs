@@ -0,0 +1,137 @@
+//! Optional reflection, so a client can find out which messages a server supports
+//!
+//! A service opts in by implementing [Reflect], which describes its messages as a
+//! [ServiceDescriptor]. The [rpc_service](crate::rpc_service) macro implements it for
+//! the services it generates.
+//!
+//! The descriptor is retrieved with the [Describe] request, which is a plain [Rpc]
+//! message that has to be part of the service like any other message:
+//!
+//! - the request type of the service needs a [Describe] variant, and the response type
+//!   a [ServiceDescriptor] variant,
+//! - [Describe] is declared as a [RpcMsg] of the service with [ServiceDescriptor] as the
+//!   response, e.g. with `declare_rpc!` or as `Rpc describe = Describe, _ ->
+//!   ServiceDescriptor` in the [rpc_service](crate::rpc_service) macro,
+//! - the server handles it with [RpcChannel::describe].
+//!
+//! The client then calls [RpcClient::describe]. Since the request uses its own channel
+//! like every other request, it does not interfere with the other requests of the
+//! service.
+//!
+//! [Rpc]: crate::message::Rpc
+use std::result;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    client::CallError,
+    message::RpcMsg,
+    server::{RpcChannel, RpcServerError},
+    transport::StreamTypes,
+    Connector, RpcClient, Service,
+};
+
+/// The interaction pattern of a message, see [MessageDescriptor]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum MessagePattern {
+    /// [Rpc](crate::message::Rpc)
+    Rpc,
+    /// [ServerStreaming](crate::message::ServerStreaming)
+    ServerStreaming,
+    /// [ClientStreaming](crate::message::ClientStreaming)
+    ClientStreaming,
+    /// [BidiStreaming](crate::message::BidiStreaming)
+    BidiStreaming,
+    /// [TryServerStreaming](crate::message::TryServerStreaming)
+    TryServerStreaming,
+}
+
+/// Describes a single request message of a service
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MessageDescriptor {
+    /// The name of the request type
+    pub name: String,
+    /// The interaction pattern of the request
+    pub pattern: MessagePattern,
+    /// The name of the update type, for patterns with updates
+    pub update: Option<String>,
+    /// The name of the response type
+    pub response: String,
+}
+
+/// Describes the messages of a service, see [Reflect]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ServiceDescriptor {
+    /// The name of the service
+    pub name: String,
+    /// The request messages of the service, in the order of the request type
+    pub messages: Vec<MessageDescriptor>,
+}
+
+impl ServiceDescriptor {
+    /// A hash of the descriptor, to check whether client and server agree on the messages
+    /// without comparing the whole descriptor.
+    ///
+    /// The hash only depends on the descriptor, so it is the same on every platform and
+    /// for every build. It only covers the names and patterns though, not the fields of the
+    /// messages.
+    pub fn schema_hash(&self) -> u64 {
+        // 64 bit FNV-1a, with every string terminated so that names can not run together
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes.iter().chain([&0xff]) {
+                hash ^= *byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        write(self.name.as_bytes());
+        for message in &self.messages {
+            write(message.name.as_bytes());
+            write(&[message.pattern as u8]);
+            write(message.update.as_deref().unwrap_or_default().as_bytes());
+            write(message.response.as_bytes());
+        }
+        hash
+    }
+}
+
+/// A service that can describe its messages, see the [module docs](self)
+pub trait Reflect: Service {
+    /// The descriptor of this service
+    fn descriptor() -> ServiceDescriptor;
+}
+
+/// Request the [ServiceDescriptor] of a service, see the [module docs](self)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Describe;
+
+impl<S, C> RpcClient<S, C>
+where
+    S: Service,
+    C: Connector<S>,
+{
+    /// Get the descriptor of the service from the server.
+    ///
+    /// The server has to handle [Describe] requests, see the [module docs](self).
+    pub async fn describe(&self) -> result::Result<ServiceDescriptor, CallError<C>>
+    where
+        Describe: RpcMsg<S, Response = ServiceDescriptor>,
+    {
+        self.rpc(Describe).await
+    }
+}
+
+impl<S, C> RpcChannel<S, C>
+where
+    S: Reflect,
+    C: StreamTypes<In = S::Req, Out = S::Res>,
+{
+    /// Answer a [Describe] request with the descriptor of the service.
+    pub async fn describe(self, req: Describe) -> result::Result<(), RpcServerError<C>>
+    where
+        Describe: RpcMsg<S, Response = ServiceDescriptor>,
+    {
+        self.rpc(req, (), |_, _| async { S::descriptor() }).await
+    }
+}
@@ -2,29 +2,43 @@
 //!
 //! The main entry point is [RpcServer]
 use std::{
+    cmp::{self, Reverse},
+    collections::{BinaryHeap, HashMap, VecDeque},
     error,
     fmt::{self, Debug},
+    hash::Hash,
     marker::PhantomData,
+    panic::AssertUnwindSafe,
     pin::Pin,
     result,
-    sync::Arc,
-    task::{self, Poll},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{self, ready, Poll},
+    thread,
+    time::{Duration, Instant},
+};
+
+use futures::{
+    channel::oneshot,
+    future::{AbortHandle, BoxFuture, Shared},
+    stream::FuturesUnordered,
 };
-use futures::channel::oneshot;
 use futures_lite::{Future, Stream, StreamExt};
-use futures_util::{SinkExt, TryStreamExt};
-use futures_util::task::SpawnExt;
-use pin_project::pin_project;
-use tracing::{error, warn};
+use futures_util::{FutureExt, SinkExt};
+use tracing::{debug, error, warn};
 
 use crate::{
+    budget::MemoryBudget,
+    metrics::{Pattern, ServerMetrics},
+    sync::{OwnedSemaphorePermit, Semaphore, TryAcquireError},
     transport::{
         self,
         boxed::BoxableListener,
+        lifecycle::{LifecycleEvent, LifecycleListener, LifecycleObserver},
         mapped::{ErrorOrMapError, MappedRecvStream, MappedSendSink, MappedStreamTypes},
-        ConnectionErrors, StreamTypes,
+        metadata::Metadata,
+        ConnectionErrors, MetadataError, RemoteInfo, StreamTypes,
     },
-    Listener, RpcMessage, Service,
+    ErrorSource, Listener, RpcMessage, Service,
 };
 
 /// Stream types on the server side
@@ -53,25 +67,936 @@ pub type BoxedListener<S> =
 ///
 /// `S` is the service type.
 /// `C` is the channel type.
+/// `T` is the target that is passed to handlers, see [RpcServer::with_target].
 #[derive(Debug)]
-pub struct RpcServer<S, C = BoxedListener<S>> {
+pub struct RpcServer<S, C = BoxedListener<S>, T = ()> {
     /// The channel on which new requests arrive.
     ///
     /// Each new request is a receiver and channel pair on which messages for this request
     /// are received and responses sent.
     source: C,
+    /// Limit on the number of channels handled concurrently by the accept loop.
+    limit: Option<ConcurrencyLimit>,
+    /// Metrics shared by all channels accepted by this server.
+    metrics: Option<Arc<ServerMetrics>>,
+    /// Spawns the handler tasks of the accept loop.
+    spawner: Spawner,
+    /// Layers that check the first request of every channel, in order.
+    layers: Layers<S>,
+    /// Classifies channels for the accept loop, see [RpcServer::with_priority].
+    priority: Option<Priorities<S>>,
+    /// Limits the rate of new channels per peer, see [RpcServer::with_rate_limit].
+    rate_limit: Option<Arc<dyn Admit>>,
+    /// Limits the channels handled concurrently per peer, see
+    /// [RpcServer::with_max_concurrent_per_peer].
+    peer_limit: Option<Arc<dyn LimitPeer>>,
+    /// Maximum number of responses sent without a flush, see [RpcServer::with_response_batch].
+    response_batch: usize,
+    /// Bounds the responses buffered by all channels, see [RpcServer::with_memory_budget].
+    memory_budget: Option<MemoryBudget>,
+    /// Limits how long handlers may run, see [RpcServer::with_handler_timeout].
+    handler_timeouts: HandlerTimeouts,
+    /// Maps errors of handlers to responses, see [RpcServer::with_error_response].
+    error_hook: Option<ErrorHook<S, C>>,
+    /// Shared state that is passed to every handler, see [RpcServer::with_target].
+    target: T,
     _p: PhantomData<S>,
 }
 
-impl<S, C: Clone> Clone for RpcServer<S, C> {
-    fn clone(&self) -> Self {
-        Self {
-            source: self.source.clone(),
-            _p: PhantomData,
+/// The [Layer]s of an [RpcServer], in order
+struct Layers<S>(Arc<Vec<Arc<dyn Layer<S>>>>);
+
+impl<S> Clone for Layers<S> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<S> Default for Layers<S> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+impl<S> Debug for Layers<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Layers").field(&self.0.len()).finish()
+    }
+}
+
+impl<S, C: Clone, T: Clone> Clone for RpcServer<S, C, T> {
+    fn clone(&self) -> Self {
+        Self {
+            source: self.source.clone(),
+            limit: self.limit.clone(),
+            metrics: self.metrics.clone(),
+            spawner: self.spawner.clone(),
+            layers: self.layers.clone(),
+            priority: self.priority.clone(),
+            rate_limit: self.rate_limit.clone(),
+            peer_limit: self.peer_limit.clone(),
+            response_batch: self.response_batch,
+            memory_budget: self.memory_budget.clone(),
+            handler_timeouts: self.handler_timeouts,
+            error_hook: self.error_hook.clone(),
+            target: self.target.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+/// Checks the first request of every channel accepted by an [RpcServer], before it
+/// is dispatched to a handler.
+///
+/// This is the place for cross-cutting logic like authentication, rate limiting or
+/// logging. Layers are added using [RpcServer::with_layer] and run in the order in which
+/// they were added. The first layer that rejects the request stops the others.
+///
+/// Layers run in [Accepting::read_first], so they apply to the accept loop as well as to
+/// manually accepted channels.
+pub trait Layer<S: Service>: Send + Sync + 'static {
+    /// Check the first request of a channel.
+    ///
+    /// `info` is the [RemoteInfo] of the client. If the transport does not know it, this
+    /// is the default, which has no address and no certificates.
+    fn wrap(&self, req: &S::Req, info: &RemoteInfo) -> result::Result<(), Rejection<S>>;
+}
+
+/// A request rejected by a [Layer]
+///
+/// The channel is closed after the rejection. If there is a response, it is sent to
+/// the client first, so the client can tell a rejection from a failed connection. The
+/// response has to match the request, e.g. an error variant of the response type of
+/// the message, or the client sees it as an unexpected response.
+#[derive(Debug)]
+pub struct Rejection<S: Service> {
+    /// The response to send to the client before closing the channel, if any.
+    pub response: Option<S::Res>,
+}
+
+impl<S: Service> Rejection<S> {
+    /// Reject by closing the channel, without a response.
+    ///
+    /// The client sees this as an early close.
+    pub fn close() -> Self {
+        Self { response: None }
+    }
+
+    /// Reject by sending a response and closing the channel.
+    pub fn respond(response: impl Into<S::Res>) -> Self {
+        Self {
+            response: Some(response.into()),
+        }
+    }
+}
+
+/// Maps the error of a handler to a response, see [RpcServer::with_error_response].
+trait MapError<S: Service, C: ConnectionErrors>: Send + Sync + 'static {
+    /// Bind the hook to the first request of a channel.
+    ///
+    /// Returns `None` if errors of the channel are not answered.
+    fn bind(self: Arc<Self>, req: &S::Req) -> Option<BoundHook<S, C>>;
+}
+
+/// The function passed to [RpcServer::with_error_response]
+struct OnRequest<F, R>(F, PhantomData<fn() -> R>);
+
+impl<S, C, F, R> MapError<S, C> for OnRequest<F, R>
+where
+    S: Service,
+    C: ConnectionErrors,
+    F: Fn(&S::Req) -> Option<R> + Send + Sync + 'static,
+    R: FnOnce(&RpcServerError<C>) -> Option<S::Res> + Send + Sync + 'static,
+{
+    fn bind(self: Arc<Self>, req: &S::Req) -> Option<BoundHook<S, C>> {
+        let on_error = (self.0)(req)?;
+        Some(BoundHook(Box::new(move |cause| {
+            let response = on_error(&cause);
+            (cause, response)
+        })))
+    }
+}
+
+/// The hook of a transport, for a wrapper of it with the same errors, like a
+/// [LifecycleListener]
+struct WrappedHook<S, C>(ErrorHook<S, C>);
+
+impl<S, C, W> MapError<S, W> for WrappedHook<S, C>
+where
+    S: Service,
+    C: ConnectionErrors,
+    W: ConnectionErrors<
+        SendError = C::SendError,
+        RecvError = C::RecvError,
+        AcceptError = C::AcceptError,
+    >,
+{
+    fn bind(self: Arc<Self>, req: &S::Req) -> Option<BoundHook<S, W>> {
+        let BoundHook(on_error) = self.0 .0.clone().bind(req)?;
+        Some(BoundHook(Box::new(move |cause: RpcServerError<W>| {
+            let (cause, response) = on_error(cause.errors_into::<C>());
+            (cause.errors_into::<W>(), response)
+        })))
+    }
+}
+
+/// The hook of a transport, for the [BoxedListener] of it
+struct BoxedHook<S, C>(ErrorHook<S, C>);
+
+impl<S: Service, C: ConnectionErrors> MapError<S, BoxedListener<S>> for BoxedHook<S, C> {
+    fn bind(self: Arc<Self>, req: &S::Req) -> Option<BoundHook<S, BoxedListener<S>>> {
+        let BoundHook(on_error) = self.0 .0.clone().bind(req)?;
+        Some(BoundHook(Box::new(move |cause| {
+            match unbox_errors::<C, _>(cause) {
+                Ok(cause) => {
+                    let (cause, response) = on_error(cause);
+                    (box_errors(cause), response)
+                }
+                Err(cause) => (cause, None),
+            }
+        })))
+    }
+}
+
+/// The errors of a transport, from the errors of the boxed transport
+///
+/// Fails if an error did not come from `C`, which does not happen for the channels of
+/// a boxed `C`.
+fn unbox_errors<C, B>(
+    cause: RpcServerError<B>,
+) -> result::Result<RpcServerError<C>, RpcServerError<B>>
+where
+    C: ConnectionErrors,
+    B: ConnectionErrors<
+        SendError = anyhow::Error,
+        RecvError = anyhow::Error,
+        AcceptError = anyhow::Error,
+    >,
+{
+    Ok(match cause {
+        RpcServerError::SendError(x) => {
+            RpcServerError::SendError(x.downcast().map_err(RpcServerError::SendError)?)
+        }
+        RpcServerError::RecvError(x) => {
+            RpcServerError::RecvError(x.downcast().map_err(RpcServerError::RecvError)?)
+        }
+        RpcServerError::Accept(x) => {
+            RpcServerError::Accept(x.downcast().map_err(RpcServerError::Accept)?)
+        }
+        RpcServerError::EarlyClose => RpcServerError::EarlyClose,
+        RpcServerError::UnexpectedStartMessage => RpcServerError::UnexpectedStartMessage,
+        RpcServerError::UnexpectedUpdateMessage => RpcServerError::UnexpectedUpdateMessage,
+        RpcServerError::UnknownRequest => RpcServerError::UnknownRequest,
+        RpcServerError::Rejected => RpcServerError::Rejected,
+        RpcServerError::RateLimited => RpcServerError::RateLimited,
+        RpcServerError::TooManyUpdates => RpcServerError::TooManyUpdates,
+        RpcServerError::HandlerTimeout => RpcServerError::HandlerTimeout,
+    })
+}
+
+/// The errors of the boxed transport, like they are boxed by it
+fn box_errors<C, B>(cause: RpcServerError<C>) -> RpcServerError<B>
+where
+    C: ConnectionErrors,
+    B: ConnectionErrors<
+        SendError = anyhow::Error,
+        RecvError = anyhow::Error,
+        AcceptError = anyhow::Error,
+    >,
+{
+    match cause {
+        RpcServerError::SendError(x) => {
+            RpcServerError::SendError(transport::boxed::box_send_error::<C>(x))
+        }
+        RpcServerError::RecvError(x) => {
+            RpcServerError::RecvError(transport::boxed::box_recv_error::<C>(x))
+        }
+        cause => cause.errors_into(),
+    }
+}
+
+struct ErrorHook<S, C>(Arc<dyn MapError<S, C>>);
+
+impl<S, C> Clone for ErrorHook<S, C> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<S, C> Debug for ErrorHook<S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ErrorHook").finish_non_exhaustive()
+    }
+}
+
+/// The hook of [RpcServer::with_error_response], bound to the first request of a channel
+///
+/// Takes the error by value and hands it back, so that [WrappedHook] can convert it.
+#[allow(clippy::type_complexity)]
+struct BoundHook<S: Service, C: ConnectionErrors>(
+    Box<dyn FnOnce(RpcServerError<C>) -> (RpcServerError<C>, Option<S::Res>) + Send + Sync>,
+);
+
+/// Sends the response of the [RpcServer::with_error_response] hook on a channel
+type SendErrorResponse<C> = Box<
+    dyn for<'a> FnOnce(&'a mut <C as StreamTypes>::SendSink) -> BoxFuture<'a, ()> + Send + Sync,
+>;
+
+fn send_error_response<C, F>(f: F) -> SendErrorResponse<C>
+where
+    C: StreamTypes,
+    F: for<'a> FnOnce(&'a mut C::SendSink) -> BoxFuture<'a, ()> + Send + Sync + 'static,
+{
+    Box::new(f)
+}
+
+/// The hook of [RpcServer::with_error_response] for a channel
+///
+/// It sends the response itself, so that it keeps working for a channel converted with
+/// [RpcChannel::map], which has a different response type.
+#[allow(clippy::type_complexity)]
+pub(crate) struct ErrorResponse<C: StreamTypes>(
+    Box<
+        dyn FnOnce(RpcServerError<C>) -> (RpcServerError<C>, Option<SendErrorResponse<C>>)
+            + Send
+            + Sync,
+    >,
+);
+
+impl<C: StreamTypes> Debug for ErrorResponse<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ErrorResponse").finish_non_exhaustive()
+    }
+}
+
+impl<C: StreamTypes> ErrorResponse<C> {
+    fn new<S: Service>(BoundHook(on_error): BoundHook<S, C>) -> Self
+    where
+        C: ChannelTypes<S>,
+    {
+        Self(Box::new(move |cause| {
+            let (cause, response) = on_error(cause);
+            let send = response.map(|response| {
+                send_error_response::<C, _>(move |send| {
+                    async move {
+                        // best effort, the client may be gone, and the handler failed anyway
+                        send.send(response).await.ok();
+                    }
+                    .boxed()
+                })
+            });
+            (cause, send)
+        }))
+    }
+
+    /// The hook for the channel of an inner service, see [RpcChannel::map]
+    fn map<In, Out>(self) -> ErrorResponse<MappedStreamTypes<In, Out, C>>
+    where
+        In: RpcMessage + TryFrom<C::In>,
+        Out: RpcMessage,
+        C::Out: From<Out>,
+    {
+        let Self(on_error) = self;
+        ErrorResponse(Box::new(move |cause| {
+            let (cause, send) = match cause {
+                // the hook sees this like the pattern methods of the outer service would
+                RpcServerError::RecvError(ErrorOrMapError::Conversion) => {
+                    let (_, send) = on_error(RpcServerError::UnexpectedUpdateMessage);
+                    (cause, send)
+                }
+                cause => {
+                    let (cause, send) = on_error(cause.map_back());
+                    (map_errors(cause), send)
+                }
+            };
+            let send = send.map(|send| {
+                send_error_response::<MappedStreamTypes<In, Out, C>, _>(move |mapped| {
+                    send(mapped.get_mut())
+                })
+            });
+            (cause, send)
+        }))
+    }
+
+    /// The hook for the boxed channel, see [RpcChannel::boxed]
+    fn boxed<S: Service>(self) -> ErrorResponse<BoxedChannelTypes<S>>
+    where
+        C: ChannelTypes<S>,
+    {
+        let Self(on_error) = self;
+        ErrorResponse(Box::new(move |cause| match unbox_errors::<C, _>(cause) {
+            Ok(cause) => {
+                let (cause, send) = on_error(cause);
+                let send = send.map(|send| {
+                    send_error_response::<BoxedChannelTypes<S>, _>(move |boxed| {
+                        match boxed.downcast_mut::<C::SendSink>() {
+                            Some(inner) => send(inner),
+                            None => futures_lite::future::ready(()).boxed(),
+                        }
+                    })
+                });
+                (box_errors(cause), send)
+            }
+            Err(cause) => (cause, None),
+        }))
+    }
+}
+
+/// The errors of a mapped transport, from the errors of the transport
+fn map_errors<In, Out, C>(cause: RpcServerError<C>) -> RpcServerError<MappedStreamTypes<In, Out, C>>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: ConnectionErrors,
+{
+    match cause {
+        RpcServerError::RecvError(x) => RpcServerError::RecvError(ErrorOrMapError::Inner(x)),
+        RpcServerError::SendError(x) => RpcServerError::SendError(x),
+        RpcServerError::Accept(x) => RpcServerError::Accept(x),
+        RpcServerError::EarlyClose => RpcServerError::EarlyClose,
+        RpcServerError::UnexpectedStartMessage => RpcServerError::UnexpectedStartMessage,
+        RpcServerError::UnexpectedUpdateMessage => RpcServerError::UnexpectedUpdateMessage,
+        RpcServerError::UnknownRequest => RpcServerError::UnknownRequest,
+        RpcServerError::Rejected => RpcServerError::Rejected,
+        RpcServerError::RateLimited => RpcServerError::RateLimited,
+        RpcServerError::TooManyUpdates => RpcServerError::TooManyUpdates,
+        RpcServerError::HandlerTimeout => RpcServerError::HandlerTimeout,
+    }
+}
+
+/// The fewest buckets at which full ones are pruned, to forget peers that went away
+const MIN_RATE_LIMIT_PRUNE: usize = 64;
+
+/// A token bucket rate limit for new channels, see [RpcServer::with_rate_limit]
+///
+/// Every peer has a bucket that holds up to `burst` tokens and is refilled at a constant
+/// rate. Each new channel takes a token, and channels from a peer with an empty bucket
+/// are rejected.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    /// Tokens added per second
+    rate: f64,
+    burst: u32,
+}
+
+impl RateLimit {
+    /// Allow `requests` new channels per `period`, with a burst of the same size.
+    pub fn new(requests: u32, period: Duration) -> Self {
+        Self {
+            rate: requests as f64 / period.as_secs_f64(),
+            burst: requests,
+        }
+    }
+
+    /// Allow up to `burst` new channels at once from a peer that has been idle.
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst;
+        self
+    }
+
+    /// Add the tokens since the last update of `bucket`, and return the tokens
+    fn refill(&self, bucket: &mut Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated);
+        bucket.tokens = (bucket.tokens + elapsed.as_secs_f64() * self.rate).min(self.burst as f64);
+        bucket.updated = now;
+        bucket.tokens
+    }
+}
+
+/// The tokens of a single peer for a [RateLimit]
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Decides whether a new channel from a peer is handled
+trait Admit: Debug + Send + Sync + 'static {
+    fn admit(&self, info: &RemoteInfo) -> bool;
+}
+
+/// The buckets of the peers of a [RateLimiter]
+struct Buckets<K> {
+    buckets: HashMap<K, Bucket>,
+    /// The number of buckets at which full ones are pruned next
+    ///
+    /// This is twice the number that was left after the last pruning, so that pruning
+    /// takes constant time per channel on average.
+    prune_at: usize,
+}
+
+impl<K> Default for Buckets<K> {
+    fn default() -> Self {
+        Self {
+            buckets: HashMap::new(),
+            prune_at: MIN_RATE_LIMIT_PRUNE,
+        }
+    }
+}
+
+/// The buckets of a [RateLimit], keyed by a user supplied function
+struct RateLimiter<K, F> {
+    limit: RateLimit,
+    key: F,
+    buckets: Mutex<Buckets<K>>,
+}
+
+impl<K, F> Debug for RateLimiter<K, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("limit", &self.limit)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, F> Admit for RateLimiter<K, F>
+where
+    K: Hash + Eq + Send + 'static,
+    F: Fn(&RemoteInfo) -> Option<K> + Send + Sync + 'static,
+{
+    fn admit(&self, info: &RemoteInfo) -> bool {
+        let Some(key) = (self.key)(info) else {
+            return true;
+        };
+        let now = Instant::now();
+        let burst = self.limit.burst as f64;
+        let mut guard = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        let Buckets { buckets, prune_at } = &mut *guard;
+        if buckets.len() >= *prune_at {
+            // a full bucket is the same as no bucket
+            buckets.retain(|_, bucket| self.limit.refill(bucket, now) < burst);
+            *prune_at = (buckets.len() * 2).max(MIN_RATE_LIMIT_PRUNE);
+        }
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        if self.limit.refill(bucket, now) < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+/// Held while a channel of a peer is handled, see [LimitPeer]
+type PeerPermit = Box<dyn Send>;
+
+/// Limits the channels handled concurrently per peer
+trait LimitPeer: Debug + Send + Sync + 'static {
+    /// Wait until the peer can start another channel.
+    ///
+    /// Resolves to `None` if the peer is not limited. Fails right away if too many
+    /// channels of the peer are already waiting.
+    fn acquire(
+        &self,
+        info: &RemoteInfo,
+    ) -> Result<BoxFuture<'static, Option<PeerPermit>>, TryAcquireError>;
+}
+
+/// The running and waiting channels of a single peer of a [PeerLimiter]
+#[derive(Debug)]
+struct PeerState {
+    semaphore: Arc<Semaphore>,
+    channels: usize,
+}
+
+type PeerStates<K> = Arc<Mutex<HashMap<K, PeerState>>>;
+
+/// The semaphores of a per peer concurrency limit, keyed by a user supplied function
+struct PeerLimiter<K, F> {
+    limit: usize,
+    key: F,
+    /// Only peers with running or waiting channels have a state
+    peers: PeerStates<K>,
+}
+
+impl<K, F> Debug for PeerLimiter<K, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PeerLimiter")
+            .field("limit", &self.limit)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, F> LimitPeer for PeerLimiter<K, F>
+where
+    K: Hash + Eq + Clone + Send + 'static,
+    F: Fn(&RemoteInfo) -> Option<K> + Send + Sync + 'static,
+{
+    fn acquire(
+        &self,
+        info: &RemoteInfo,
+    ) -> Result<BoxFuture<'static, Option<PeerPermit>>, TryAcquireError> {
+        let Some(key) = (self.key)(info) else {
+            return Ok(futures_lite::future::ready(None).boxed());
+        };
+        let mut peers = self.peers.lock().unwrap_or_else(PoisonError::into_inner);
+        let peer = peers.entry(key.clone()).or_insert_with(|| PeerState {
+            semaphore: Arc::new(Semaphore::new(self.limit)),
+            channels: 0,
+        });
+        let max_channels = self.limit.saturating_mul(SCHEDULED_PER_PERMIT + 1);
+        if peer.channels >= max_channels {
+            return Err(TryAcquireError);
+        }
+        peer.channels += 1;
+        let semaphore = peer.semaphore.clone();
+        drop(peers);
+        // counts the channel from now on, even if it is dropped while waiting
+        let channel = PeerChannel {
+            key,
+            peers: self.peers.clone(),
+        };
+        Ok(async move {
+            let permit = semaphore.acquire_owned().await;
+            let permit: PeerPermit = Box::new(PeerLimitPermit {
+                _permit: permit,
+                _channel: channel,
+            });
+            Some(permit)
+        }
+        .boxed())
+    }
+}
+
+/// A running or waiting channel of a [PeerLimiter]. The last one of a peer removes its
+/// state.
+struct PeerChannel<K: Hash + Eq> {
+    key: K,
+    peers: PeerStates<K>,
+}
+
+impl<K: Hash + Eq> Drop for PeerChannel<K> {
+    fn drop(&mut self) {
+        let mut peers = self.peers.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(peer) = peers.get_mut(&self.key) {
+            peer.channels -= 1;
+            if peer.channels == 0 {
+                peers.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// A permit of a [PeerLimiter]
+struct PeerLimitPermit<K: Hash + Eq> {
+    // fields are dropped in order, so the permit is returned before the state is removed
+    _permit: OwnedSemaphorePermit,
+    _channel: PeerChannel<K>,
+}
+
+/// Spawns the tasks that handle the channels accepted by an [RpcServer].
+///
+/// The default is [DefaultSpawner]. A custom spawner allows running the handlers on a
+/// different runtime, or inside a structured concurrency scope. It is implemented for
+/// closures, e.g. `|task| { tokio::spawn(task); }`.
+///
+/// See [RpcServer::with_spawner].
+pub trait Spawn: Send + Sync + 'static {
+    /// Run `task` to completion in the background.
+    ///
+    /// The task already catches panics and reports its result, so the spawner does not
+    /// need to keep a handle to it.
+    fn spawn(&self, task: BoxFuture<'static, ()>);
+}
+
+impl<F: Fn(BoxFuture<'static, ()>) + Send + Sync + 'static> Spawn for F {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        self(task)
+    }
+}
+
+/// The spawner used by an [RpcServer] unless configured otherwise.
+///
+/// Spawns the handler tasks with [glib::spawn_future], like the accept loop itself.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultSpawner;
+
+impl Spawn for DefaultSpawner {
+    fn spawn(&self, task: BoxFuture<'static, ()>) {
+        // the task reports its result itself, so it can be detached
+        drop(glib::spawn_future(task));
+    }
+}
+
+/// The [Spawn] of an [RpcServer]
+#[derive(Clone)]
+struct Spawner(Arc<dyn Spawn>);
+
+impl Debug for Spawner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Spawner").finish_non_exhaustive()
+    }
+}
+
+/// A handler task spawned by the accept loop.
+///
+/// Resolves with the result of the handler once it is done, or with an error if it was
+/// aborted. Dropping this detaches the task.
+struct HandlerTask {
+    done: oneshot::Receiver<thread::Result<()>>,
+    abort: AbortHandle,
+}
+
+impl HandlerTask {
+    fn spawn(spawner: &dyn Spawn, task: impl Future<Output = ()> + Send + 'static) -> Self {
+        let (sender, done) = oneshot::channel();
+        let (task, abort) = futures::future::abortable(async move {
+            let res = AssertUnwindSafe(task).catch_unwind().await;
+            sender.send(res).ok();
+        });
+        spawner.spawn(Box::pin(task.map(|_| ())));
+        Self { done, abort }
+    }
+
+    fn abort(&self) {
+        self.abort.abort();
+    }
+}
+
+impl Future for HandlerTask {
+    type Output = Result<thread::Result<()>, oneshot::Canceled>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.done).poll(cx)
+    }
+}
+
+/// What the accept loop does with new channels when the concurrency limit is reached.
+///
+/// See [RpcServer::with_max_concurrent].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LimitPolicy {
+    /// Stop accepting until a running handler finishes.
+    ///
+    /// New connections queue up in the underlying transport.
+    #[default]
+    Wait,
+    /// Accept new channels and close them immediately.
+    Reject,
+}
+
+#[derive(Debug, Clone)]
+struct ConcurrencyLimit {
+    semaphore: Arc<Semaphore>,
+    /// The number of permits of the semaphore
+    max: usize,
+    policy: LimitPolicy,
+    /// Channels waiting for a permit, if the server uses [RpcServer::with_priority].
+    queue: Arc<Mutex<WaitQueue>>,
+}
+
+impl ConcurrencyLimit {
+    /// Get a permit according to the policy.
+    ///
+    /// Returns `None` if there is no free permit and the policy is [LimitPolicy::Reject].
+    async fn acquire(&self) -> Option<OwnedSemaphorePermit> {
+        match self.policy {
+            LimitPolicy::Wait => Some(self.semaphore.clone().acquire_owned().await),
+            LimitPolicy::Reject => self.semaphore.clone().try_acquire_owned().ok(),
+        }
+    }
+}
+
+/// Priority of a channel handled by the accept loop.
+///
+/// When the concurrency limit is reached, channels with a higher priority get the next
+/// free permit, and channels of the same priority are handled in the order in which they
+/// arrived. See [RpcServer::with_priority].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    /// Bulk work that can wait, e.g. long running streams.
+    Low,
+    /// The priority of all channels unless classified otherwise.
+    #[default]
+    Normal,
+    /// Latency sensitive work. Can use the permits reserved for high priority.
+    High,
+}
+
+/// Classifies the first request of a channel, see [RpcServer::with_priority].
+trait Classify<S: Service>: Send + Sync + 'static {
+    fn priority(&self, req: &S::Req) -> Priority;
+}
+
+impl<S: Service, F: Fn(&S::Req) -> Priority + Send + Sync + 'static> Classify<S> for F {
+    fn priority(&self, req: &S::Req) -> Priority {
+        self(req)
+    }
+}
+
+struct Priorities<S> {
+    classify: Arc<dyn Classify<S>>,
+    /// Number of permits that only [Priority::High] channels can use.
+    reserved: usize,
+}
+
+impl<S> Clone for Priorities<S> {
+    fn clone(&self) -> Self {
+        Self {
+            classify: self.classify.clone(),
+            reserved: self.reserved,
+        }
+    }
+}
+
+impl<S> Debug for Priorities<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Priorities")
+            .field("reserved", &self.reserved)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default)]
+struct WaitQueue {
+    waiters: BinaryHeap<Waiter>,
+    /// Sequence number of the next waiter, to keep the order within a priority.
+    next: u64,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    priority: Priority,
+    seq: u64,
+    sender: oneshot::Sender<OwnedSemaphorePermit>,
+}
+
+impl Waiter {
+    fn key(&self) -> (Priority, Reverse<u64>) {
+        (self.priority, Reverse(self.seq))
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// How many channels per permit of the [ConcurrencyLimit], or per slot of a peer, wait
+/// in the accept loop, see [RpcServer::with_priority] and
+/// [RpcServer::with_max_concurrent_per_peer]
+const SCHEDULED_PER_PERMIT: usize = 4;
+
+/// Hands out the permits of a [ConcurrencyLimit] by priority instead of in FIFO order.
+#[derive(Debug)]
+struct Scheduler {
+    limit: ConcurrencyLimit,
+    reserved: usize,
+}
+
+impl Scheduler {
+    /// Get a permit for a channel of the given priority, respecting the reservation.
+    fn try_acquire(&self, priority: Priority) -> Option<OwnedSemaphorePermit> {
+        let reserved = match priority {
+            Priority::High => 0,
+            _ => self.reserved,
+        };
+        if self.limit.semaphore.available_permits() <= reserved {
+            return None;
+        }
+        self.limit.semaphore.clone().try_acquire_owned().ok()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, WaitQueue> {
+        self.limit
+            .queue
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Get a permit, waiting behind all channels of the same or a higher priority.
+    ///
+    /// Returns `None` if there is no free permit and the policy is [LimitPolicy::Reject].
+    async fn acquire(self: &Arc<Self>, priority: Priority) -> Option<ScheduledPermit> {
+        let receiver = {
+            let mut queue = self.lock();
+            let ahead = queue
+                .waiters
+                .peek()
+                .is_some_and(|waiter| waiter.priority >= priority);
+            if !ahead {
+                if let Some(permit) = self.try_acquire(priority) {
+                    return Some(self.permit(permit));
+                }
+            }
+            if self.limit.policy == LimitPolicy::Reject {
+                return None;
+            }
+            let (sender, receiver) = oneshot::channel();
+            let seq = queue.next;
+            queue.next += 1;
+            queue.waiters.push(Waiter {
+                priority,
+                seq,
+                sender,
+            });
+            receiver
+        };
+        let permit = receiver
+            .await
+            .expect("waiters are only removed with a permit");
+        Some(self.permit(permit))
+    }
+
+    fn permit(self: &Arc<Self>, permit: OwnedSemaphorePermit) -> ScheduledPermit {
+        ScheduledPermit {
+            permit: Some(permit),
+            scheduler: self.clone(),
+        }
+    }
+
+    /// Hand out free permits to the waiting channels with the highest priority.
+    fn dispatch(&self) {
+        let mut queue = self.lock();
+        while let Some(waiter) = queue.waiters.peek() {
+            let Some(permit) = self.try_acquire(waiter.priority) else {
+                break;
+            };
+            let waiter = queue.waiters.pop().expect("peeked above");
+            // if the waiting task is gone, the permit is released and goes to the next one
+            waiter.sender.send(permit).ok();
         }
     }
 }
 
+/// A permit handed out by a [Scheduler]. Releasing it wakes the next waiting channel.
+struct ScheduledPermit {
+    permit: Option<OwnedSemaphorePermit>,
+    scheduler: Arc<Scheduler>,
+}
+
+impl Drop for ScheduledPermit {
+    fn drop(&mut self) {
+        drop(self.permit.take());
+        self.scheduler.dispatch();
+    }
+}
+
+/// The permits of a channel that waited in the accept loop, held by its handler task
+#[derive(Default)]
+struct WaitedPermits {
+    peer: Option<PeerPermit>,
+    global: Option<OwnedSemaphorePermit>,
+    scheduled: Option<ScheduledPermit>,
+}
+
 impl<S: Service, C: Listener<S>> RpcServer<S, C> {
     /// Create a new rpc server for a specific service for a [Service] given a compatible
     /// [Listener].
@@ -80,19 +1005,404 @@ impl<S: Service, C: Listener<S>> RpcServer<S, C> {
     pub fn new(source: C) -> Self {
         Self {
             source,
+            limit: None,
+            metrics: None,
+            spawner: Spawner(Arc::new(DefaultSpawner)),
+            layers: Default::default(),
+            priority: None,
+            rate_limit: None,
+            peer_limit: None,
+            response_batch: 1,
+            memory_budget: None,
+            handler_timeouts: HandlerTimeouts::default(),
+            error_hook: None,
+            target: (),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<S: Service, C: Listener<S>, T> RpcServer<S, C, T> {
+    /// Bind a target to this server, which is passed to every handler of
+    /// [RpcServer::accept_loop_with_target].
+    ///
+    /// The target is usually the shared state of the service, and is cloned for every
+    /// channel, so it is typically cheap to clone, e.g. an [Arc]. This is the same target
+    /// that is passed to [RpcChannel::rpc] and the other pattern methods, so handlers do
+    /// not have to capture and clone it themselves.
+    pub fn with_target<T2>(self, target: T2) -> RpcServer<S, C, T2> {
+        RpcServer {
+            source: self.source,
+            limit: self.limit,
+            metrics: self.metrics,
+            spawner: self.spawner,
+            layers: self.layers,
+            priority: self.priority,
+            rate_limit: self.rate_limit,
+            peer_limit: self.peer_limit,
+            response_batch: self.response_batch,
+            memory_budget: self.memory_budget,
+            handler_timeouts: self.handler_timeouts,
+            error_hook: self.error_hook,
+            target,
             _p: PhantomData,
         }
     }
 
+    /// The target of this server, see [RpcServer::with_target].
+    pub fn target(&self) -> &T {
+        &self.target
+    }
+
+    /// Limit the number of channels that are handled concurrently by the accept loop.
+    ///
+    /// Once `limit` handlers are running, new channels are handled according to the
+    /// [LimitPolicy], which defaults to [LimitPolicy::Wait]. A permit is released when
+    /// the handler completes, fails or panics.
+    ///
+    /// The limit is shared between clones of this server. A limit of 0 means that no
+    /// channels will ever be handled.
+    ///
+    /// # Panics
+    ///
+    /// If `limit` is not larger than the permits reserved by [RpcServer::with_priority].
+    pub fn with_max_concurrent(self, limit: usize) -> Self {
+        self.with_max_concurrent_policy(limit, LimitPolicy::default())
+    }
+
+    /// Limit the number of channels that are handled concurrently by the accept loop,
+    /// using the given [LimitPolicy] when the limit is reached.
+    ///
+    /// See [RpcServer::with_max_concurrent].
+    pub fn with_max_concurrent_policy(mut self, limit: usize, policy: LimitPolicy) -> Self {
+        self.limit = Some(ConcurrencyLimit {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            max: limit,
+            policy,
+            queue: Default::default(),
+        });
+        self.check_reserved();
+        self
+    }
+
+    /// The number of additional channels the accept loop can currently handle.
+    ///
+    /// Returns `None` if the server has no concurrency limit. A value of 0 means the
+    /// limit is saturated.
+    pub fn available_permits(&self) -> Option<usize> {
+        self.limit
+            .as_ref()
+            .map(|limit| limit.semaphore.available_permits())
+    }
+
+    /// Handle channels by priority when the concurrency limit is reached.
+    ///
+    /// `classify` is called with the first request of every channel that passed the
+    /// [Layer]s. Instead of in the order in which they arrive, channels get free permits
+    /// by [Priority], and `reserved` permits of the limit are only used by channels with
+    /// [Priority::High]. This way cheap calls are not starved by long running streams.
+    ///
+    /// The priority is only known once the first request has been read, so with
+    /// [LimitPolicy::Wait] the accept loop keeps accepting channels, and they wait for a
+    /// permit in the server instead of in the underlying transport. Up to four channels
+    /// per permit of the limit wait in the server, further ones wait in the transport. A
+    /// handler task is only spawned once its channel got a permit.
+    ///
+    /// This has no effect without a concurrency limit, see [RpcServer::with_max_concurrent].
+    ///
+    /// # Panics
+    ///
+    /// If `reserved` is not smaller than the concurrency limit, so only channels with
+    /// [Priority::High] would ever be handled.
+    pub fn with_priority(
+        mut self,
+        reserved: usize,
+        classify: impl Fn(&S::Req) -> Priority + Send + Sync + 'static,
+    ) -> Self {
+        self.priority = Some(Priorities {
+            classify: Arc::new(classify),
+            reserved,
+        });
+        self.check_reserved();
+        self
+    }
+
+    /// Check that the permits reserved by [RpcServer::with_priority] leave some for the
+    /// channels that do not have [Priority::High].
+    fn check_reserved(&self) {
+        if let (Some(limit), Some(priority)) = (&self.limit, &self.priority) {
+            assert!(
+                priority.reserved == 0 || priority.reserved < limit.max,
+                "reserved permits must be fewer than the concurrency limit, got {} of {}",
+                priority.reserved,
+                limit.max
+            );
+        }
+    }
+
+    /// Limit the rate of new channels per peer.
+    ///
+    /// `key` identifies the peer of a channel from its [RemoteInfo], e.g. by address or by
+    /// certificate, and every peer gets its own token bucket, see [RateLimit]. Channels
+    /// for which `key` returns `None` are not limited.
+    ///
+    /// A channel from a peer that exceeded its rate is closed after reading the first
+    /// request, before the [Layer]s run, and [Accepting::read_first] fails with
+    /// [RpcServerError::RateLimited]. To tell the client, answer the error with
+    /// [RpcServer::with_error_response], e.g. with an error variant of the response of
+    /// the request. Without a response, the client sees an early close. Raw channels
+    /// are limited as well, but never answered, see `Accepting::into_raw`.
+    ///
+    /// The buckets are shared between clones of this server.
+    pub fn with_rate_limit<K>(
+        mut self,
+        limit: RateLimit,
+        key: impl Fn(&RemoteInfo) -> Option<K> + Send + Sync + 'static,
+    ) -> Self
+    where
+        K: Hash + Eq + Send + 'static,
+    {
+        self.rate_limit = Some(Arc::new(RateLimiter {
+            limit,
+            key,
+            buckets: Default::default(),
+        }));
+        self
+    }
+
+    /// Limit the number of channels of a single peer that are handled concurrently by
+    /// the accept loop.
+    ///
+    /// `key` identifies the peer of a channel from its [RemoteInfo], e.g. by address for a
+    /// limit per connection or by identity for a limit per client. Channels for which
+    /// `key` returns `None` are not limited.
+    ///
+    /// A channel of a peer that is at its limit waits in the server until one of the
+    /// other channels of that peer is done. It only competes for the global limit of
+    /// [RpcServer::with_max_concurrent] once it got a slot of its peer, so a peer that
+    /// opens many channels can hold at most `limit` of the global permits, and the other
+    /// peers keep getting served. To make that possible, the accept loop keeps accepting
+    /// channels with [LimitPolicy::Wait], like with [RpcServer::with_priority]. Up to four
+    /// channels per slot of a peer wait in the server, further channels of that peer are
+    /// closed right away. The handler task of a channel is only spawned once it got its
+    /// slot.
+    ///
+    /// The limits are shared between clones of this server.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `limit` is zero.
+    pub fn with_max_concurrent_per_peer<K>(
+        mut self,
+        limit: usize,
+        key: impl Fn(&RemoteInfo) -> Option<K> + Send + Sync + 'static,
+    ) -> Self
+    where
+        K: Hash + Eq + Clone + Send + 'static,
+    {
+        assert!(limit > 0, "the per peer limit must be at least one");
+        self.peer_limit = Some(Arc::new(PeerLimiter {
+            limit,
+            key,
+            peers: Default::default(),
+        }));
+        self
+    }
+
+    /// Send up to `size` responses of a stream without flushing in between, for all
+    /// channels accepted by this server.
+    ///
+    /// See [RpcChannel::with_response_batch].
+    pub fn with_response_batch(mut self, size: usize) -> Self {
+        self.response_batch = size;
+        self
+    }
+
+    /// Bound the bytes of streaming responses buffered by all channels accepted by this
+    /// server.
+    ///
+    /// See [RpcChannel::with_memory_budget]. The budget can be shared with other servers,
+    /// and its usage can be read at any time using [MemoryBudget::used].
+    pub fn with_memory_budget(mut self, budget: MemoryBudget) -> Self {
+        self.memory_budget = Some(budget);
+        self
+    }
+
+    /// The memory budget of this server, if set using [RpcServer::with_memory_budget].
+    pub fn memory_budget(&self) -> Option<&MemoryBudget> {
+        self.memory_budget.as_ref()
+    }
+
+    /// Cancel handlers that run longer than `timeout`, no matter what the client does.
+    ///
+    /// The limit applies to the pattern methods like [RpcChannel::rpc], and covers the
+    /// whole interaction, so for a streaming pattern it is the time until the last
+    /// response is sent. On expiry, the handler is dropped, the pattern method returns
+    /// [RpcServerError::HandlerTimeout] and the channel is cancelled using
+    /// [StreamTypes::cancel]. The client sees
+    /// [CallError::Cancelled](crate::client::CallError::Cancelled) on transports that can
+    /// cancel a channel, and [CallError::EarlyClose](crate::client::CallError::EarlyClose)
+    /// on all others. If [RpcServer::with_error_response] answers the timeout with a
+    /// response, the client gets that response instead.
+    ///
+    /// The limit is a glib timer, so the handler has to be polled on the thread that owns
+    /// the glib main context, otherwise it panics. This is the case for the handlers of an
+    /// accept loop spawned with [RpcServer::spawn_accept_loop].
+    ///
+    /// Use [RpcServer::with_pattern_timeout] to use a different limit for a pattern, e.g.
+    /// for long lived subscriptions, and [RpcServer::with_deadline_cancel] to also cancel
+    /// handlers once the deadline of the client has passed.
+    pub fn with_handler_timeout(mut self, timeout: Duration) -> Self {
+        self.handler_timeouts.default = Some(timeout);
+        self
+    }
+
+    /// Use a different limit than the one of [RpcServer::with_handler_timeout] for a
+    /// single pattern.
+    ///
+    /// `None` lets handlers of the pattern run as long as they want. The limit of a
+    /// pattern takes precedence over the one for all patterns, no matter in which order
+    /// they are set.
+    pub fn with_pattern_timeout(mut self, pattern: Pattern, timeout: Option<Duration>) -> Self {
+        self.handler_timeouts.patterns[pattern as usize] = Some(timeout);
+        self
+    }
+
+    /// Cancel handlers once the deadline the client sent has passed, see
+    /// [RpcChannel::deadline].
+    ///
+    /// The client has given up on the call at that point, so there is no point in
+    /// finishing it. This works like [RpcServer::with_handler_timeout], with the time
+    /// until the deadline as the limit, and the earlier of the two wins. Channels
+    /// without a deadline are not affected.
+    pub fn with_deadline_cancel(mut self) -> Self {
+        self.handler_timeouts.cancel_at_deadline = true;
+        self
+    }
+
+    /// Record metrics for all requests handled by this server.
+    ///
+    /// The metrics are shared between clones of this server, and can be read at any time
+    /// using [RpcServer::metrics].
+    pub fn with_metrics(mut self, metrics: Arc<ServerMetrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Use a custom [Spawn] implementation for the handler tasks of the accept loop.
+    ///
+    /// Defaults to [DefaultSpawner]. This is needed to run the accept loop on a runtime
+    /// other than the default one.
+    pub fn with_spawner(mut self, spawner: impl Spawn) -> Self {
+        self.spawner = Spawner(Arc::new(spawner));
+        self
+    }
+
+    /// Add a [Layer] that checks the first request of every channel.
+    ///
+    /// Layers run in the order in which they are added.
+    pub fn with_layer(mut self, layer: impl Layer<S>) -> Self {
+        Arc::make_mut(&mut self.layers.0).push(Arc::new(layer));
+        self
+    }
+
+    /// Send a response to the client when a handler fails.
+    ///
+    /// `on_request` gets the first request of every channel, and returns the function
+    /// that maps an error of a pattern method like [RpcChannel::rpc] to a response, or
+    /// `None` if errors of the request are not answered. It takes what it needs from the
+    /// request, e.g. which message it is, since the request itself is moved to the
+    /// handler. If the returned function gives a response, that is sent to the client
+    /// before the channel is closed, e.g. an error variant of the response type of the
+    /// message, so the client gets a proper error instead of an early close. The handler
+    /// still returns the error. Like for a [Rejection], the response has to match the
+    /// request, or the client sees it as an unexpected response.
+    ///
+    /// Errors that a handler returns without handing the channel to a pattern method
+    /// can not be answered, the channel is already gone. Sending the response is best
+    /// effort, if the client is gone it is dropped.
+    ///
+    /// The function also answers channels rejected by [RpcServer::with_rate_limit], with
+    /// [RpcServerError::RateLimited], before any handler runs.
+    ///
+    /// This applies to the channels of [RpcServer::accept] as well as to the accept loop.
+    /// It is kept by [RpcServer::boxed], [RpcServer::with_lifecycle_observer],
+    /// [RpcChannel::boxed] and [RpcChannel::map], the function always gets the errors of
+    /// this transport. For a mapped channel, a request that does not convert to the inner
+    /// service is passed on as [RpcServerError::UnexpectedUpdateMessage].
+    pub fn with_error_response<F>(
+        mut self,
+        on_request: impl Fn(&S::Req) -> Option<F> + Send + Sync + 'static,
+    ) -> Self
+    where
+        F: FnOnce(&RpcServerError<C>) -> Option<S::Res> + Send + Sync + 'static,
+    {
+        self.error_hook = Some(ErrorHook(Arc::new(OnRequest(on_request, PhantomData))));
+        self
+    }
+
+    /// The metrics of this server, if enabled using [RpcServer::with_metrics].
+    pub fn metrics(&self) -> Option<&Arc<ServerMetrics>> {
+        self.metrics.as_ref()
+    }
+
     /// Box the transport for the service.
     ///
     /// The boxed transport is the default for the `C` type parameter, so by boxing we can avoid
     /// having to specify the type parameter.
-    pub fn boxed(self) -> RpcServer<S, BoxedListener<S>>
+    pub fn boxed(self) -> RpcServer<S, BoxedListener<S>, T>
     where
         C: BoxableListener<S::Req, S::Res>,
     {
-        RpcServer::new(self.source.boxed())
+        RpcServer {
+            source: self.source.boxed(),
+            limit: self.limit,
+            metrics: self.metrics,
+            spawner: self.spawner,
+            layers: self.layers,
+            priority: self.priority,
+            rate_limit: self.rate_limit,
+            peer_limit: self.peer_limit,
+            response_batch: self.response_batch,
+            memory_budget: self.memory_budget,
+            handler_timeouts: self.handler_timeouts,
+            error_hook: self
+                .error_hook
+                .map(|hook| ErrorHook(Arc::new(BoxedHook(hook)))),
+            target: self.target,
+            _p: PhantomData,
+        }
+    }
+
+    /// Call `on_event` when a channel is accepted and when it ends, and with the
+    /// listeners that support it when a connection is opened or closed, for debugging.
+    ///
+    /// This wraps the listener in a [LifecycleListener], see the
+    /// [lifecycle](transport::lifecycle) module for the events. The hook of
+    /// [RpcServer::with_error_response] is kept, the wrapper has the errors of the
+    /// listener.
+    pub fn with_lifecycle_observer(
+        self,
+        on_event: impl Fn(LifecycleEvent) + Send + Sync + 'static,
+    ) -> RpcServer<S, LifecycleListener<C>, T> {
+        RpcServer {
+            source: LifecycleListener::new(self.source, LifecycleObserver::new(on_event)),
+            limit: self.limit,
+            metrics: self.metrics,
+            spawner: self.spawner,
+            layers: self.layers,
+            priority: self.priority,
+            rate_limit: self.rate_limit,
+            peer_limit: self.peer_limit,
+            response_batch: self.response_batch,
+            memory_budget: self.memory_budget,
+            handler_timeouts: self.handler_timeouts,
+            error_hook: self
+                .error_hook
+                .map(|hook| ErrorHook(Arc::new(WrappedHook(hook)))),
+            target: self.target,
+            _p: PhantomData,
+        }
     }
 }
 
@@ -108,6 +1418,60 @@ impl<S: Service, C: Listener<S>> RpcServer<S, C> {
 ///
 /// `S` is the service type.
 /// `C` is the service endpoint from which the channel was created.
+///
+/// # Interaction patterns
+///
+/// Each message declares its interaction pattern via [Msg::Pattern](crate::message::Msg),
+/// and the methods to handle a request, such as [RpcChannel::rpc] or
+/// [RpcChannel::server_streaming], are only available for messages with the matching
+/// pattern. Handling a request with the wrong pattern is a compile error, so client
+/// and server can not disagree about the pattern as long as they share the service
+/// definition:
+///
+/// ```compile_fail
+/// use derive_more::{From, TryInto};
+/// use quic_rpc::{
+///     message::{Msg, ServerStreaming, ServerStreamingMsg},
+///     server::RpcChannel,
+///     Service,
+/// };
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Serialize, Deserialize)]
+/// struct Count(u64);
+///
+/// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+/// enum Request {
+///     Count(Count),
+/// }
+///
+/// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+/// enum Response {
+///     Count(u64),
+/// }
+///
+/// #[derive(Debug, Clone)]
+/// struct Counter;
+///
+/// impl Service for Counter {
+///     type Req = Request;
+///     type Res = Response;
+/// }
+///
+/// impl Msg<Counter> for Count {
+///     type Pattern = ServerStreaming;
+/// }
+///
+/// impl ServerStreamingMsg<Counter> for Count {
+///     type Response = u64;
+/// }
+///
+/// async fn handle(req: Count, chan: RpcChannel<Counter>) -> anyhow::Result<()> {
+///     // `Count` is a server streaming message, so it does not implement `RpcMsg`
+///     chan.rpc(req, (), |_, Count(n)| async move { n }).await?;
+///     Ok(())
+/// }
+/// ```
 #[derive(Debug)]
 pub struct RpcChannel<S: Service, C: ChannelTypes<S> = BoxedChannelTypes<S>> {
     /// Sink to send responses to the client.
@@ -115,6 +1479,21 @@ pub struct RpcChannel<S: Service, C: ChannelTypes<S> = BoxedChannelTypes<S>> {
     /// Stream to receive requests from the client.
     pub recv: C::RecvStream,
 
+    /// Metrics of the server that accepted this channel.
+    pub(crate) metrics: Option<Arc<ServerMetrics>>,
+    /// Limits how long handlers may run, see [RpcChannel::with_handler_timeout].
+    pub(crate) handler_timeouts: HandlerTimeouts,
+    /// Info about the client, if known by the transport.
+    pub(crate) remote_info: Option<Arc<RemoteInfo>>,
+    /// The connection to the client, if the transport supports push.
+    pub(crate) connection: Option<transport::Connection<S::Req, S::Res>>,
+    /// Maximum number of responses sent without a flush, see
+    /// [RpcChannel::with_response_batch].
+    pub(crate) response_batch: usize,
+    /// Bounds the buffered responses, see [RpcChannel::with_memory_budget].
+    pub(crate) memory_budget: Option<MemoryBudget>,
+    /// Response to a failed handler, see [RpcServer::with_error_response].
+    pub(crate) error_response: Option<ErrorResponse<C>>,
     pub(crate) _p: PhantomData<S>,
 }
 
@@ -128,20 +1507,131 @@ where
         Self {
             send,
             recv,
+            metrics: None,
+            handler_timeouts: HandlerTimeouts::default(),
+            remote_info: None,
+            connection: None,
+            response_batch: 1,
+            memory_budget: None,
+            error_response: None,
             _p: PhantomData,
         }
     }
 
+    /// Send up to `size` responses of a stream without flushing the sink in between.
+    ///
+    /// By default, every response of the streaming patterns is flushed on its own. With
+    /// a larger batch, responses that the handler has already produced are written
+    /// together, which increases the throughput for many small responses. The sink is
+    /// still flushed as soon as the handler has no response ready, so a slow handler does
+    /// not delay the responses it already produced. A size of 0 is the same as 1.
+    ///
+    /// See [RpcServer::with_response_batch] to set this for all channels of a server.
+    pub fn with_response_batch(mut self, size: usize) -> Self {
+        self.response_batch = size;
+        self
+    }
+
+    /// Take the size of every response of a stream from `budget` until it is written.
+    ///
+    /// A response takes its serialized size from the budget before it is passed to the
+    /// transport, and returns it once the sink is flushed. While the budget is exhausted,
+    /// no more responses are taken from the handler, so its stream is not polled and
+    /// [ResponseSender::send] waits. This bounds the memory used by responses across all
+    /// channels that share the budget. See [crate::budget] for how sizes are counted.
+    ///
+    /// See [RpcServer::with_memory_budget] to set this for all channels of a server.
+    pub fn with_memory_budget(mut self, budget: MemoryBudget) -> Self {
+        self.memory_budget = Some(budget);
+        self
+    }
+
+    /// Cancel the handler of this channel if it runs longer than `timeout`.
+    ///
+    /// This replaces the limits of the server, including those set using
+    /// [RpcServer::with_pattern_timeout]. See [RpcServer::with_handler_timeout] to set
+    /// this for all channels of a server.
+    pub fn with_handler_timeout(mut self, timeout: Duration) -> Self {
+        self.handler_timeouts = HandlerTimeouts {
+            default: Some(timeout),
+            patterns: Default::default(),
+            ..self.handler_timeouts
+        };
+        self
+    }
+
+    /// Info about the client that opened this channel, if the transport knows it.
+    ///
+    /// See [transport::Listener::remote_info]. This is `None` for channels created
+    /// using [RpcChannel::new].
+    pub fn remote_info(&self) -> Option<&RemoteInfo> {
+        self.remote_info.as_deref()
+    }
+
+    /// The connection to the client that opened this channel, to push channels to it.
+    ///
+    /// See [Accepting::connection]. This is `None` for channels created using
+    /// [RpcChannel::new], and for channels converted using [RpcChannel::map].
+    pub fn connection(&self) -> Option<&transport::Connection<S::Req, S::Res>> {
+        self.connection.as_ref()
+    }
+
+    /// Set the priority of the responses sent on this channel.
+    ///
+    /// Responses of channels with a higher priority are sent first when several channels
+    /// of the same connection have data to send. See [StreamTypes::set_priority], only
+    /// the quinn transport supports priorities.
+    pub fn set_priority(&self, priority: i32) -> Result<(), transport::PriorityError> {
+        C::set_priority(&self.send, priority)
+    }
+
+    /// The metadata the client sent ahead of its first request, if any.
+    ///
+    /// See [transport::metadata]. This is available once the first request was read,
+    /// and is `None` for transports that do not carry metadata.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        C::metadata(&self.recv)
+    }
+
+    /// The deadline the client sent ahead of its first request, if any.
+    ///
+    /// [RpcClient::rpc_with_timeout](crate::RpcClient::rpc_with_timeout) sends the time
+    /// that is left of its timeout, see [StreamTypes::set_deadline]. The deadline is that
+    /// long after the time was received, so the clocks of client and server do not have
+    /// to agree. This is available once the first request was read, and is `None` for
+    /// transports that do not carry deadlines, see [transport::metadata].
+    pub fn deadline(&self) -> Option<Instant> {
+        C::deadline(&self.recv)
+    }
+
+    /// Attach metadata that is sent to the client ahead of the first response.
+    ///
+    /// See [StreamTypes::set_metadata]. This has to be called before the first response
+    /// is sent, e.g. before passing the channel to a pattern method.
+    pub fn set_response_metadata(&self, metadata: Metadata) -> Result<(), MetadataError> {
+        C::set_metadata(&self.send, metadata)
+    }
+
     /// Convert this channel into a boxed channel.
     pub fn boxed(self) -> RpcChannel<S, BoxedChannelTypes<S>>
     where
         C::SendError: Into<anyhow::Error> + Send + Sync + 'static,
         C::RecvError: Into<anyhow::Error> + Send + Sync + 'static,
     {
-        let send =
-            transport::boxed::SendSink::boxed(Box::new(self.send.sink_map_err(|e| e.into())));
-        let recv = transport::boxed::RecvStream::boxed(Box::new(self.recv.map_err(|e| e.into())));
-        RpcChannel::new(send, recv)
+        let send = transport::boxed::box_send_sink::<C>(self.send);
+        let recv = transport::boxed::box_recv_stream::<C>(self.recv);
+        RpcChannel {
+            send,
+            recv,
+            metrics: self.metrics,
+            handler_timeouts: self.handler_timeouts,
+            remote_info: self.remote_info,
+            connection: self.connection,
+            response_batch: self.response_batch,
+            memory_budget: self.memory_budget,
+            error_response: self.error_response.map(ErrorResponse::boxed::<S>),
+            _p: PhantomData,
+        }
     }
 
     /// Map this channel's service into an inner service.
@@ -159,10 +1649,40 @@ where
         SNext::Req: TryFrom<S::Req>,
         S::Res: From<SNext::Res>,
     {
-        RpcChannel::new(
-            MappedSendSink::new(self.send),
-            MappedRecvStream::new(self.recv),
-        )
+        RpcChannel {
+            send: MappedSendSink::new(self.send),
+            recv: MappedRecvStream::new(self.recv),
+            metrics: self.metrics,
+            handler_timeouts: self.handler_timeouts,
+            remote_info: self.remote_info,
+            connection: None,
+            response_batch: self.response_batch,
+            memory_budget: self.memory_budget,
+            error_response: self.error_response.map(ErrorResponse::map),
+            _p: PhantomData,
+        }
+    }
+}
+
+#[cfg(any(
+    feature = "quinn-transport",
+    feature = "iroh-net-transport",
+    all(feature = "uds-transport", unix),
+    feature = "tcp-transport"
+))]
+impl<S, C> RpcChannel<S, C>
+where
+    S: Service,
+    C: ChannelTypes<S> + transport::RawStreamTypes,
+{
+    /// Convert this channel into raw halves, bypassing the codec.
+    ///
+    /// Every frame received is a serialized `S::Req` and every frame sent must be a
+    /// serialized `S::Res`. This allows a forwarding handler to splice frames between
+    /// this channel and a channel opened with [RpcClient::open_raw](crate::RpcClient::open_raw).
+    /// See [RawStreamTypes](transport::RawStreamTypes) for the full contract.
+    pub fn into_raw(self) -> (C::RawSendSink, C::RawRecvStream) {
+        C::into_raw(self.send, self.recv)
     }
 }
 
@@ -170,10 +1690,41 @@ where
 pub struct Accepting<S: Service, C: Listener<S>> {
     send: C::SendSink,
     recv: C::RecvStream,
+    metrics: Option<Arc<ServerMetrics>>,
+    handler_timeouts: HandlerTimeouts,
+    remote_info: Option<Arc<RemoteInfo>>,
+    connection: Option<transport::Connection<S::Req, S::Res>>,
+    layers: Layers<S>,
+    rate_limit: Option<Arc<dyn Admit>>,
+    response_batch: usize,
+    memory_budget: Option<MemoryBudget>,
+    error_hook: Option<ErrorHook<S, C>>,
     _p: PhantomData<S>,
 }
 
 impl<S: Service, C: Listener<S>> Accepting<S, C> {
+    /// Info about the client that opened this channel, if the transport knows it.
+    ///
+    /// This is available before reading the first request, e.g. to reject unknown
+    /// clients early.
+    pub fn remote_info(&self) -> Option<&RemoteInfo> {
+        self.remote_info.as_deref()
+    }
+
+    /// The connection to the client that opened this channel, if the transport
+    /// supports push, see [transport::Listener::connection].
+    ///
+    /// It can be kept after the channel is done, to open channels to the client with
+    /// [Connection::open_push](transport::Connection::open_push) at any time, e.g. to
+    /// notify it of events. On the push channel, the server sends `S::Res` and receives
+    /// `S::Req`, and the client accepts it with
+    /// [RpcClient::accept_push](crate::RpcClient::accept_push). Channels opened by the
+    /// client keep working as before, since the two sides never accept their own
+    /// channels.
+    pub fn connection(&self) -> Option<&transport::Connection<S::Req, S::Res>> {
+        self.connection.as_ref()
+    }
+
     /// Read the first message from the client.
     ///
     /// The return value is a tuple of `(request, channel)`.  Here `request` is the
@@ -183,8 +1734,24 @@ impl<S: Service, C: Listener<S>> Accepting<S, C> {
     ///
     /// Often sink and stream will wrap an an underlying byte stream. In this case you can
     /// call into_inner() on them to get it back to perform byte level reads and writes.
+    ///
+    /// The request is checked by the [Layer]s of the server. If one of them rejects it,
+    /// this returns [RpcServerError::Rejected].
     pub async fn read_first(self) -> result::Result<(S::Req, RpcChannel<S, C>), RpcServerError<C>> {
-        let Accepting { send, mut recv, .. } = self;
+        let Accepting {
+            mut send,
+            mut recv,
+            metrics,
+            mut handler_timeouts,
+            remote_info,
+            connection,
+            layers,
+            rate_limit,
+            response_batch,
+            memory_budget,
+            error_hook,
+            ..
+        } = self;
         // get the first message from the client. This will tell us what it wants to do.
         let request: S::Req = recv
             .next()
@@ -192,77 +1759,353 @@ impl<S: Service, C: Listener<S>> Accepting<S, C> {
             // no msg => early close
             .ok_or(RpcServerError::EarlyClose)?
             // recv error
-            .map_err(RpcServerError::RecvError)?;
-        Ok((request, RpcChannel::<S, C>::new(send, recv)))
+            .map_err(|cause| {
+                if C::is_unknown_message(&cause) {
+                    RpcServerError::UnknownRequest
+                } else {
+                    RpcServerError::RecvError(cause)
+                }
+            })?;
+        if handler_timeouts.cancel_at_deadline {
+            // the deadline frame comes before the first request
+            handler_timeouts.deadline = C::deadline(&recv);
+        }
+        let unknown = RemoteInfo::default();
+        let info = remote_info.as_deref().unwrap_or(&unknown);
+        let error_response = error_hook
+            .and_then(|hook| hook.0.bind(&request))
+            .map(ErrorResponse::new);
+        if let Some(rate_limit) = rate_limit {
+            if !rate_limit.admit(info) {
+                if let Some(ErrorResponse(on_error)) = error_response {
+                    if let (_, Some(respond)) = on_error(RpcServerError::RateLimited) {
+                        respond(&mut send).await;
+                    }
+                }
+                return Err(RpcServerError::RateLimited);
+            }
+        }
+        for layer in layers.0.iter() {
+            if let Err(rejection) = layer.wrap(&request, info) {
+                if let Some(response) = rejection.response {
+                    send.send(response)
+                        .await
+                        .map_err(RpcServerError::SendError)?;
+                }
+                return Err(RpcServerError::Rejected);
+            }
+        }
+        let chan = RpcChannel {
+            send,
+            recv,
+            metrics,
+            handler_timeouts,
+            remote_info,
+            connection,
+            response_batch,
+            memory_budget,
+            error_response,
+            _p: PhantomData,
+        };
+        Ok((request, chan))
     }
 }
 
-impl<S: Service, C: Listener<S>> RpcServer<S, C> {
+#[cfg(any(
+    feature = "quinn-transport",
+    feature = "iroh-net-transport",
+    all(feature = "uds-transport", unix),
+    feature = "tcp-transport"
+))]
+impl<S: Service, C: Listener<S> + transport::RawStreamTypes> Accepting<S, C> {
+    /// Convert the channel into raw halves without reading the first request.
+    ///
+    /// Unlike [RpcChannel::into_raw], this does not deserialize anything, so the first
+    /// frame received is the serialized request. The limit of
+    /// [RpcServer::with_rate_limit] is checked, and a channel from a peer that exceeded
+    /// its rate is closed with [RpcServerError::RateLimited]. Everything that needs the
+    /// request is skipped: the [Layer]s of the server are not run, and the function of
+    /// [RpcServer::with_error_response] does not answer the rate limit, so the client
+    /// sees an early close. A forwarding handler that needs them can use
+    /// [Accepting::read_first] and [RpcChannel::into_raw] instead, at the cost of
+    /// decoding the first request.
+    pub fn into_raw(self) -> result::Result<(C::RawSendSink, C::RawRecvStream), RpcServerError<C>> {
+        if let Some(rate_limit) = &self.rate_limit {
+            let unknown = RemoteInfo::default();
+            if !rate_limit.admit(self.remote_info.as_deref().unwrap_or(&unknown)) {
+                return Err(RpcServerError::RateLimited);
+            }
+        }
+        Ok(C::into_raw(self.send, self.recv))
+    }
+}
+
+impl<S: Service, C: Listener<S>, T> RpcServer<S, C, T> {
     /// Accepts a new channel from a client. The result is an [Accepting] object that
     /// can be used to read the first request.
     pub async fn accept(&self) -> result::Result<Accepting<S, C>, RpcServerError<C>> {
         let (send, recv) = self.source.accept().await.map_err(RpcServerError::Accept)?;
+        let remote_info = C::remote_info(&recv);
+        let connection = self.source.connection(&recv);
         Ok(Accepting {
             send,
             recv,
+            metrics: self.metrics.clone(),
+            handler_timeouts: self.handler_timeouts,
+            remote_info,
+            connection,
+            layers: self.layers.clone(),
+            rate_limit: self.rate_limit.clone(),
+            response_batch: self.response_batch,
+            memory_budget: self.memory_budget.clone(),
+            error_hook: self.error_hook.clone(),
             _p: PhantomData,
         })
     }
 
-    /// Get the underlying service endpoint
-    pub fn into_inner(self) -> C {
-        self.source
+    /// Get the underlying service endpoint
+    pub fn into_inner(self) -> C {
+        self.source
+    }
+
+    /// Run an accept loop for this server.
+    ///
+    /// Each request will be handled in a separate task, spawned with the [Spawn]
+    /// implementation of this server. This replaces a hand-written loop that accepts
+    /// channels and spawns a task for each of them:
+    ///
+    /// - the number of concurrent handlers can be limited with [RpcServer::with_max_concurrent],
+    ///   handed out by priority with [RpcServer::with_priority], and shared fairly between
+    ///   peers with [RpcServer::with_max_concurrent_per_peer],
+    /// - errors returned by `handler` are logged,
+    /// - a panicking handler only ends its own task and releases its permit.
+    ///
+    /// It is the caller's responsibility to poll the returned future to drive the server.
+    pub async fn accept_loop<Fun, Fut, E>(self, handler: Fun)
+    where
+        S: Service,
+        C: Listener<S>,
+        Fun: Fn(S::Req, RpcChannel<S, C>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<anyhow::Error> + 'static,
+    {
+        self.accept_loop_with_shutdown(handler, futures_lite::future::pending())
+            .await
+    }
+
+    /// Run an accept loop for this server until `shutdown` resolves.
+    ///
+    /// Each request will be handled in a separate task.
+    ///
+    /// Once `shutdown` resolves, no new channels are accepted, but handlers that are
+    /// already running are allowed to finish. If `shutdown` resolves to `Some(deadline)`,
+    /// handlers that are still running after `deadline` are aborted. The returned future
+    /// completes once all handlers have finished or have been aborted.
+    pub async fn accept_loop_with_shutdown<Fun, Fut, E, Sd>(self, handler: Fun, shutdown: Sd)
+    where
+        S: Service,
+        C: Listener<S>,
+        Fun: Fn(S::Req, RpcChannel<S, C>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<anyhow::Error> + 'static,
+        Sd: Future<Output = Option<Duration>>,
+    {
+        let handler = Arc::new(handler);
+        let scheduler = self.scheduler();
+        // with a per peer limit, the global permit is acquired once the peer has a slot
+        let deferred_limit = match (&scheduler, &self.peer_limit) {
+            (None, Some(_)) => self.limit.clone(),
+            _ => None,
+        };
+        let mut tasks = FuturesUnordered::new();
+        // with priorities or a per peer limit, channels wait here for their permits
+        let mut waiting = FuturesUnordered::new();
+        let max_waiting = self.limit.as_ref().map_or(0, |limit| {
+            limit.max.max(1).saturating_mul(SCHEDULED_PER_PERMIT)
+        });
+        let shutdown = shutdown.fuse();
+        futures_lite::pin!(shutdown);
+        let deadline = loop {
+            // stop accepting while as many channels wait for a permit as can run at a time,
+            // with a per peer limit the waiting channels are bounded per peer instead
+            let full =
+                scheduler.is_some() && self.peer_limit.is_none() && waiting.len() >= max_waiting;
+            let accept = async {
+                if full {
+                    futures_lite::future::pending::<()>().await;
+                }
+                // with priorities or a per peer limit, the permit is acquired later
+                let permit = match scheduler {
+                    Some(_) => None,
+                    None if self.peer_limit.is_some() => None,
+                    None => self.wait_for_permit().await,
+                };
+                (self.accept().await, permit)
+            }
+            .fuse();
+            futures_lite::pin!(accept);
+            futures_util::select! {
+                res = futures_util::StreamExt::select_next_some(&mut tasks) => log_task_result(res),
+                ready = futures_util::StreamExt::select_next_some(&mut waiting) => {
+                    let Some((req, chan, permits)) = ready else {
+                        continue;
+                    };
+                    let handler = handler.clone();
+                    tasks.push(HandlerTask::spawn(&*self.spawner.0, async move {
+                        // held until the handler is done, also released on panic or abort
+                        let _permits: WaitedPermits = permits;
+                        if let Err(cause) = handler(req, chan).await {
+                            warn!("Error handling RPC request: {:#}", cause.into());
+                        }
+                    }));
+                }
+                deadline = shutdown => break deadline,
+                (req, permit) = accept => {
+                    let req = match req {
+                        Ok(req) => req,
+                        Err(e) => {
+                            warn!("Error accepting RPC request: {:#}", anyhow::Error::from(e));
+                            continue;
+                        }
+                    };
+                    if scheduler.is_some() || self.peer_limit.is_some() {
+                        let peer_permit = match &self.peer_limit {
+                            Some(limit) => {
+                                let unknown = RemoteInfo::default();
+                                match limit.acquire(req.remote_info().unwrap_or(&unknown)) {
+                                    Ok(peer_permit) => Some(peer_permit),
+                                    Err(TryAcquireError) => {
+                                        warn!(
+                                            "Rejecting RPC request, too many channels of the peer waiting"
+                                        );
+                                        continue;
+                                    }
+                                }
+                            }
+                            None => None,
+                        };
+                        let scheduler = scheduler.clone();
+                        let deferred_limit = deferred_limit.clone();
+                        waiting.push(async move {
+                            let mut permits = WaitedPermits::default();
+                            // a peer at its limit waits here, without holding a global permit
+                            if let Some(peer_permit) = peer_permit {
+                                permits.peer = peer_permit.await;
+                            }
+                            if let Some(limit) = deferred_limit {
+                                let Some(permit) = limit.acquire().await else {
+                                    warn!("Rejecting RPC request, concurrency limit reached");
+                                    return None;
+                                };
+                                permits.global = Some(permit);
+                            }
+                            let (req, chan) = read_first_or_log(req).await?;
+                            if let Some((scheduler, classify)) = scheduler {
+                                let priority = classify.priority(&req);
+                                let Some(permit) = scheduler.acquire(priority).await else {
+                                    warn!("Rejecting RPC request, concurrency limit reached");
+                                    return None;
+                                };
+                                permits.scheduled = Some(permit);
+                            }
+                            Some((req, chan, permits))
+                        });
+                        continue;
+                    }
+                    let permit = match permit {
+                        Some(permit) => Some(permit),
+                        None => match self.try_acquire_permit() {
+                            Ok(permit) => permit,
+                            Err(_) => {
+                                warn!("Rejecting RPC request, concurrency limit reached");
+                                continue;
+                            }
+                        },
+                    };
+                    let handler = handler.clone();
+                    tasks.push(HandlerTask::spawn(&*self.spawner.0, async move {
+                        // held until the handler is done, also released on panic or abort
+                        let _permit = permit;
+                        let Some((req, chan)) = read_first_or_log(req).await else {
+                            return;
+                        };
+                        if let Err(cause) = handler(req, chan).await {
+                            warn!("Error handling RPC request: {:#}", cause.into());
+                        }
+                    }));
+                }
+            }
+        };
+        // stop accepting, but let the running handlers finish
+        drop(self);
+        let drain = async {
+            while let Some(res) = tasks.next().await {
+                log_task_result(res);
+            }
+        };
+        match deadline {
+            Some(deadline) => {
+                if glib::future_with_timeout(deadline, drain).await.is_err() {
+                    warn!(
+                        "Aborting {} RPC handlers still running after shutdown deadline",
+                        tasks.len()
+                    );
+                    for task in tasks.iter() {
+                        task.abort();
+                    }
+                }
+            }
+            None => drain.await,
+        }
+    }
+
+    /// The scheduler for the accept loop, if the server is limited and uses priorities.
+    fn scheduler(&self) -> Option<(Arc<Scheduler>, Arc<dyn Classify<S>>)> {
+        let limit = self.limit.clone()?;
+        let priority = self.priority.as_ref()?;
+        let scheduler = Scheduler {
+            limit,
+            reserved: priority.reserved,
+        };
+        Some((Arc::new(scheduler), priority.classify.clone()))
+    }
+
+    /// Wait for a permit if the server is limited with [LimitPolicy::Wait].
+    async fn wait_for_permit(&self) -> Option<OwnedSemaphorePermit> {
+        match &self.limit {
+            Some(limit) if limit.policy == LimitPolicy::Wait => limit.acquire().await,
+            _ => None,
+        }
+    }
+
+    /// Get a permit without waiting, if the server has a concurrency limit.
+    fn try_acquire_permit(&self) -> Result<Option<OwnedSemaphorePermit>, TryAcquireError> {
+        match &self.limit {
+            Some(limit) => limit.semaphore.clone().try_acquire_owned().map(Some),
+            None => Ok(None),
+        }
     }
 
-    /// Run an accept loop for this server.
-    ///
-    /// Each request will be handled in a separate task.
+    /// Spawn an accept loop that can be shut down gracefully.
     ///
-    /// It is the caller's responsibility to poll the returned future to drive the server.
-    pub async fn accept_loop<Fun, Fut, E>(self, handler: Fun)
+    /// See [RpcServer::accept_loop_with_shutdown] for the shutdown semantics. Dropping the
+    /// returned [ShutdownHandle] aborts the accept loop without draining.
+    pub fn spawn_accept_loop_with_shutdown<Fun, Fut, E>(self, handler: Fun) -> ShutdownHandle
     where
         S: Service,
         C: Listener<S>,
+        T: Send + Sync + 'static,
         Fun: Fn(S::Req, RpcChannel<S, C>) -> Fut + Send + Sync + 'static,
         Fut: Future<Output = Result<(), E>> + Send + 'static,
         E: Into<anyhow::Error> + 'static,
     {
-        let handler = Arc::new(handler);
-        let mut tasks = futures::stream::FuturesUnordered::new();
-        loop {
-            futures_util::select! {
-                Some(res) = tasks.join_next() => {
-                    if !tasks.is_empty() { // TODO: is this correct?
-                        if let Err(e) = res {
-                            if e.is_panic() {
-                                error!("Panic handling RPC request: {e}");
-                            }
-                        }
-                    }
-                }
-                req = self.accept() => {
-                    let req = match req {
-                        Ok(req) => req,
-                        Err(e) => {
-                            warn!("Error accepting RPC request: {e}");
-                            continue;
-                        }
-                    };
-                    let handler = handler.clone();
-                    tasks.spawn(async move {
-                        let (req, chan) = match req.read_first().await {
-                            Ok((req, chan)) => (req, chan),
-                            Err(e) => {
-                                warn!("Error reading first message: {e}");
-                                return;
-                            }
-                        };
-                        if let Err(cause) = handler(req, chan).await {
-                            warn!("Error handling RPC request: {}", cause.into());
-                        }
-                    });
-                }
-            }
+        let (trigger, shutdown) = oneshot::channel();
+        // if the handle is dropped, the task is aborted anyway
+        let shutdown = shutdown.map(|deadline| deadline.unwrap_or_default());
+        let task = glib::spawn_future(self.accept_loop_with_shutdown(handler, shutdown));
+        ShutdownHandle {
+            trigger: Some(trigger),
+            task: Some(task),
         }
     }
 
@@ -271,43 +2114,391 @@ impl<S: Service, C: Listener<S>> RpcServer<S, C> {
     where
         S: Service,
         C: Listener<S>,
+        T: Send + Sync + 'static,
         Fun: Fn(S::Req, RpcChannel<S, C>) -> Fut + Send + Sync + 'static,
         Fut: Future<Output = Result<(), E>> + Send + 'static,
         E: Into<anyhow::Error> + 'static,
     {
         AbortOnDropHandle::new(glib::spawn_future(self.accept_loop(handler)))
     }
+
+    /// Run an accept loop for this server, passing a clone of the target to every handler.
+    ///
+    /// This is the same as [RpcServer::accept_loop], except that `handler` also gets the
+    /// target that was bound with [RpcServer::with_target].
+    pub async fn accept_loop_with_target<Fun, Fut, E>(self, handler: Fun)
+    where
+        S: Service,
+        C: Listener<S>,
+        T: Clone + Send + Sync + 'static,
+        Fun: Fn(T, S::Req, RpcChannel<S, C>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<anyhow::Error> + 'static,
+    {
+        let target = self.target.clone();
+        self.accept_loop(move |req, chan| handler(target.clone(), req, chan))
+            .await
+    }
+
+    /// Spawn an accept loop that passes a clone of the target to every handler, and
+    /// return a handle to the task.
+    ///
+    /// See [RpcServer::accept_loop_with_target].
+    pub fn spawn_accept_loop_with_target<Fun, Fut, E>(self, handler: Fun) -> AbortOnDropHandle<()>
+    where
+        S: Service,
+        C: Listener<S>,
+        T: Clone + Send + Sync + 'static,
+        Fun: Fn(T, S::Req, RpcChannel<S, C>) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<anyhow::Error> + 'static,
+    {
+        AbortOnDropHandle::new(glib::spawn_future(self.accept_loop_with_target(handler)))
+    }
+}
+
+/// A handle to an accept loop spawned with [RpcServer::spawn_accept_loop_with_shutdown].
+#[must_use = "Dropping the handle aborts the accept loop immediately"]
+#[derive(Debug)]
+pub struct ShutdownHandle {
+    trigger: Option<oneshot::Sender<Option<Duration>>>,
+    task: Option<glib::JoinHandle<()>>,
+}
+
+impl ShutdownHandle {
+    /// Stop accepting new channels and wait for all running handlers to finish.
+    ///
+    /// If `deadline` is given, handlers that are still running after it has elapsed
+    /// are aborted.
+    pub async fn shutdown(mut self, deadline: Option<Duration>) {
+        if let Some(trigger) = self.trigger.take() {
+            trigger.send(deadline).ok();
+        }
+        if let Some(task) = self.task.take() {
+            if let Err(e) = task.await {
+                if e.is_panic() {
+                    error!("Panic in RPC accept loop: {e}");
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ShutdownHandle {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
 }
 
-impl<S: Service, C: Listener<S>> AsRef<C> for RpcServer<S, C> {
+/// Read the first request of a channel for the accept loop, logging why that failed
+async fn read_first_or_log<S: Service, C: Listener<S>>(
+    accepting: Accepting<S, C>,
+) -> Option<(S::Req, RpcChannel<S, C>)> {
+    match accepting.read_first().await {
+        Ok((req, chan)) => Some((req, chan)),
+        Err(RpcServerError::UnknownRequest) => {
+            debug!("Skipping unknown request");
+            None
+        }
+        Err(RpcServerError::Rejected) => {
+            debug!("Request rejected by a layer");
+            None
+        }
+        // a peer over its limit may send many of them, so this is no warning
+        Err(RpcServerError::RateLimited) => {
+            debug!("Request rate limited");
+            None
+        }
+        Err(e) => {
+            warn!("Error reading first message: {:#}", anyhow::Error::from(e));
+            None
+        }
+    }
+}
+
+fn log_task_result(res: Result<thread::Result<()>, oneshot::Canceled>) {
+    if let Ok(Err(payload)) = res {
+        // the payload of `panic!` is a `&str` or, if it has arguments, a `String`
+        let message = match payload.downcast_ref::<&str>() {
+            Some(message) => message,
+            None => payload
+                .downcast_ref::<String>()
+                .map_or("<non-string payload>", String::as_str),
+        };
+        error!("Panic handling RPC request: {message}");
+    }
+}
+
+impl<S: Service, C: Listener<S>, T> AsRef<C> for RpcServer<S, C, T> {
     fn as_ref(&self) -> &C {
         &self.source
     }
 }
 
+/// How the updates of an [UpdateStream] ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdatesEnd {
+    /// The client finished sending updates, or closed the connection cleanly
+    Closed,
+    /// The stream was reset, the connection was lost, or the client sent an unexpected message
+    Reset,
+}
+
+/// What an [UpdateStream] does when the client sends more updates than its limit, see
+/// [UpdateStream::with_update_limit]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateOverflow {
+    /// Stop reading updates until the handler takes some, so the client has to wait
+    Backpressure,
+    /// Terminate the call with [RpcServerError::TooManyUpdates]
+    Error,
+}
+
 /// A stream of updates
 ///
 /// If there is any error with receiving or with decoding the updates, the stream will stall and the error will
 /// cause a termination of the RPC call.
-#[pin_project]
-#[derive(Debug)]
-pub struct UpdateStream<C, T>(
-    #[pin] C::RecvStream,
-    Option<oneshot::Sender<RpcServerError<C>>>,
-    PhantomData<T>,
-)
+///
+/// When the client closes its sending half, e.g. using [UpdateSink::close](crate::client::UpdateSink::close),
+/// the stream ends. With [UpdateStream::drain_on_close], the stream also ends when the client
+/// closes the connection cleanly, so the handler can still produce its final responses.
+///
+/// By default, updates are only read when the handler polls the stream. With
+/// [UpdateStream::with_update_limit], updates are read ahead while the handler is busy,
+/// up to a limit.
+pub struct UpdateStream<C, T>
 where
-    C: StreamTypes;
+    C: StreamTypes,
+{
+    state: UpdateState<C>,
+    /// Hands the buffer to the reader once the stream reads ahead
+    reader: Option<oneshot::Sender<Arc<Mutex<UpdateBuffer<C>>>>>,
+    error: Option<oneshot::Sender<RpcServerError<C>>>,
+    _p: PhantomData<fn() -> T>,
+}
+
+/// Where an [UpdateStream] keeps its [UpdateBuffer]
+enum UpdateState<C: StreamTypes> {
+    /// Owned by the stream, which reads the updates when it is polled
+    Owned(UpdateBuffer<C>),
+    /// Shared with the reader that reads ahead, see [UpdateStream::with_update_limit]
+    Shared(Arc<Mutex<UpdateBuffer<C>>>),
+    /// Only while switching from owned to shared
+    Empty,
+}
+
+impl<C: StreamTypes> UpdateState<C> {
+    fn with<R>(&self, f: impl FnOnce(&UpdateBuffer<C>) -> R) -> R {
+        match self {
+            UpdateState::Owned(buffer) => f(buffer),
+            UpdateState::Shared(buffer) => {
+                f(&buffer.lock().unwrap_or_else(PoisonError::into_inner))
+            }
+            UpdateState::Empty => unreachable!("update buffer taken"),
+        }
+    }
+
+    fn with_mut<R>(&mut self, f: impl FnOnce(&mut UpdateBuffer<C>) -> R) -> R {
+        match self {
+            UpdateState::Owned(buffer) => f(buffer),
+            UpdateState::Shared(buffer) => {
+                f(&mut buffer.lock().unwrap_or_else(PoisonError::into_inner))
+            }
+            UpdateState::Empty => unreachable!("update buffer taken"),
+        }
+    }
+}
+
+/// The state of an [UpdateStream], shared with the reader that reads ahead
+struct UpdateBuffer<C: StreamTypes> {
+    recv: C::RecvStream,
+    /// Updates read ahead, that the handler did not take yet
+    queue: VecDeque<C::In>,
+    limit: Option<(usize, UpdateOverflow)>,
+    drain_on_close: bool,
+    end: Option<UpdatesEnd>,
+    /// Whether the stream was dropped, so no more updates are read
+    dropped: bool,
+    stream: Option<task::Waker>,
+    reader: Option<task::Waker>,
+}
+
+impl<C: StreamTypes> UpdateBuffer<C> {
+    /// Receive the next update, `None` if the updates ended cleanly
+    fn poll_recv(
+        &mut self,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<result::Result<C::In, C::RecvError>>> {
+        match Pin::new(&mut self.recv).poll_next(cx) {
+            Poll::Ready(Some(Ok(msg))) => Poll::Ready(Some(Ok(msg))),
+            Poll::Ready(Some(Err(cause))) => {
+                if self.drain_on_close && C::is_clean_close(&cause) {
+                    // the client went away cleanly, so this is just the end of the updates
+                    self.end = Some(UpdatesEnd::Closed);
+                    return Poll::Ready(None);
+                }
+                self.end = Some(UpdatesEnd::Reset);
+                Poll::Ready(Some(Err(cause)))
+            }
+            Poll::Ready(None) => {
+                self.end = Some(UpdatesEnd::Closed);
+                Poll::Ready(None)
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Read ahead until the limit is reached, returns an error that terminates the call
+    fn poll_read_ahead(&mut self, cx: &mut task::Context<'_>) -> Poll<RpcServerError<C>> {
+        loop {
+            let Some((max, overflow)) = self.limit else {
+                break;
+            };
+            if self.dropped || self.end.is_some() {
+                break;
+            }
+            if overflow == UpdateOverflow::Backpressure && self.queue.len() >= max {
+                break;
+            }
+            let res = ready!(self.poll_recv(cx));
+            if let Some(waker) = self.stream.take() {
+                waker.wake();
+            }
+            match res {
+                Some(Ok(msg)) => self.queue.push_back(msg),
+                Some(Err(cause)) => return Poll::Ready(RpcServerError::RecvError(cause)),
+                None => break,
+            }
+            if self.queue.len() > max {
+                self.end = Some(UpdatesEnd::Reset);
+                return Poll::Ready(RpcServerError::TooManyUpdates);
+            }
+        }
+        self.reader = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
 
 impl<C, T> UpdateStream<C, T>
 where
     C: StreamTypes,
     T: TryFrom<C::In>,
 {
-    pub(crate) fn new(recv: C::RecvStream) -> (Self, UnwrapToPending<RpcServerError<C>>) {
+    /// Create the stream, and a future that reads ahead and resolves with the error that
+    /// terminates the call
+    ///
+    /// The future only reads once the stream hands it the buffer in
+    /// [UpdateStream::with_update_limit], until then the stream owns the buffer.
+    pub(crate) fn new(
+        recv: C::RecvStream,
+    ) -> (Self, impl Future<Output = RpcServerError<C>> + Send) {
         let (error_send, error_recv) = oneshot::channel();
-        let error_recv = UnwrapToPending(error_recv);
-        (Self(recv, Some(error_send), PhantomData), error_recv)
+        let (reader_send, reader_recv) = oneshot::channel::<Arc<Mutex<UpdateBuffer<C>>>>();
+        let buffer = UpdateBuffer {
+            recv,
+            queue: VecDeque::new(),
+            limit: None,
+            drain_on_close: false,
+            end: None,
+            dropped: false,
+            stream: None,
+            reader: None,
+        };
+        let read_ahead = async move {
+            let buffer = UnwrapToPending(reader_recv).await;
+            futures::future::poll_fn(move |cx| {
+                let mut buffer = buffer.lock().unwrap_or_else(PoisonError::into_inner);
+                buffer.poll_read_ahead(cx)
+            })
+            .await
+        };
+        let updates = Self {
+            state: UpdateState::Owned(buffer),
+            reader: Some(reader_send),
+            error: Some(error_send),
+            _p: PhantomData,
+        };
+        let error = futures_lite::future::race(read_ahead, UnwrapToPending(error_recv));
+        (updates, error)
+    }
+
+    /// End the stream instead of terminating the call when the client closes cleanly.
+    ///
+    /// Whether a receive error is a clean close is decided by the transport, see
+    /// [ConnectionErrors::is_clean_close]. Any other receive error still terminates the call.
+    pub fn drain_on_close(mut self) -> Self {
+        self.state.with_mut(|buffer| buffer.drain_on_close = true);
+        self
+    }
+
+    /// Read updates ahead while the handler is busy, buffering at most `max` of them.
+    ///
+    /// At least one update is buffered, so a `max` of 0 is the same as 1.
+    /// If the client sends more updates than that before the handler takes them,
+    /// `overflow` decides whether the server stops reading, which makes the client wait
+    /// once the buffers of the transport are full as well, or terminates the call with
+    /// [RpcServerError::TooManyUpdates].
+    pub fn with_update_limit(mut self, max: usize, overflow: UpdateOverflow) -> Self {
+        let limit = Some((max.max(1), overflow));
+        self.state = match std::mem::replace(&mut self.state, UpdateState::Empty) {
+            UpdateState::Owned(mut buffer) => {
+                // from now on the reader reads the updates
+                buffer.limit = limit;
+                let buffer = Arc::new(Mutex::new(buffer));
+                if let Some(reader) = self.reader.take() {
+                    reader.send(buffer.clone()).ok();
+                }
+                UpdateState::Shared(buffer)
+            }
+            mut state => {
+                state.with_mut(|buffer| {
+                    buffer.limit = limit;
+                    if let Some(waker) = buffer.reader.take() {
+                        waker.wake();
+                    }
+                });
+                state
+            }
+        };
+        self
+    }
+
+    /// The number of updates that were read ahead, but not taken by the handler yet
+    pub fn buffered_len(&self) -> usize {
+        self.state.with(|buffer| buffer.queue.len())
+    }
+
+    /// How the updates ended, or `None` if more updates may arrive
+    pub fn end(&self) -> Option<UpdatesEnd> {
+        self.state.with(|buffer| match buffer.queue.is_empty() {
+            true => buffer.end,
+            false => None,
+        })
+    }
+}
+
+impl<C: StreamTypes, T> fmt::Debug for UpdateStream<C, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.state.with(|buffer| {
+            f.debug_struct("UpdateStream")
+                .field("buffered", &buffer.queue.len())
+                .field("limit", &buffer.limit)
+                .field("end", &buffer.end)
+                .finish_non_exhaustive()
+        })
+    }
+}
+
+impl<C: StreamTypes, T> Drop for UpdateStream<C, T> {
+    fn drop(&mut self) {
+        // only a shared buffer outlives the stream
+        if let UpdateState::Shared(buffer) = &self.state {
+            let mut buffer = buffer.lock().unwrap_or_else(PoisonError::into_inner);
+            buffer.dropped = true;
+            buffer.queue.clear();
+        }
     }
 }
 
@@ -319,33 +2510,54 @@ where
     type Item = T;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
-        let mut this = self.project();
-        match Pin::new(&mut this.0).poll_next(cx) {
-            Poll::Ready(Some(msg)) => match msg {
-                Ok(msg) => {
-                    let msg = T::try_from(msg).map_err(|_cause| ());
-                    match msg {
-                        Ok(msg) => Poll::Ready(Some(msg)),
-                        Err(_cause) => {
-                            // we were unable to downcast, so we need to send an error
-                            if let Some(tx) = this.1.take() {
-                                let _ = tx.send(RpcServerError::UnexpectedUpdateMessage);
-                            }
-                            Poll::Pending
+        let this = self.get_mut();
+        let error = &mut this.error;
+        this.state.with_mut(|buffer| {
+            if buffer.end == Some(UpdatesEnd::Reset) {
+                return Poll::Pending;
+            }
+            let full = matches!(buffer.limit, Some((max, _)) if buffer.queue.len() >= max);
+            let msg = if let Some(msg) = buffer.queue.pop_front() {
+                if full {
+                    // there is room again for the reader
+                    if let Some(waker) = buffer.reader.take() {
+                        waker.wake();
+                    }
+                }
+                msg
+            } else {
+                if buffer.end == Some(UpdatesEnd::Closed) {
+                    return Poll::Ready(None);
+                }
+                if buffer.limit.is_some() {
+                    // the reader receives the updates
+                    buffer.stream = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                match ready!(buffer.poll_recv(cx)) {
+                    Some(Ok(msg)) => msg,
+                    Some(Err(cause)) => {
+                        // we got a recv error, so return pending and send the error
+                        if let Some(tx) = error.take() {
+                            let _ = tx.send(RpcServerError::RecvError(cause));
                         }
+                        return Poll::Pending;
                     }
+                    None => return Poll::Ready(None),
                 }
-                Err(cause) => {
-                    // we got a recv error, so return pending and send the error
-                    if let Some(tx) = this.1.take() {
-                        let _ = tx.send(RpcServerError::RecvError(cause));
+            };
+            match T::try_from(msg) {
+                Ok(msg) => Poll::Ready(Some(msg)),
+                Err(_cause) => {
+                    // we were unable to downcast, so we need to send an error
+                    buffer.end = Some(UpdatesEnd::Reset);
+                    if let Some(tx) = error.take() {
+                        let _ = tx.send(RpcServerError::UnexpectedUpdateMessage);
                     }
                     Poll::Pending
                 }
-            },
-            Poll::Ready(None) => Poll::Ready(None),
-            Poll::Pending => Poll::Pending,
-        }
+            }
+        })
     }
 }
 
@@ -363,6 +2575,21 @@ pub enum RpcServerError<C: ConnectionErrors> {
     SendError(C::SendError),
     /// Got an unexpected update message, e.g. a request message or a non-matching update message
     UnexpectedUpdateMessage,
+    /// The first message is a request that is unknown to this server
+    ///
+    /// This is only reported by transports that can tell, see
+    /// [ConnectionErrors::is_unknown_message]. The channel is dropped, other requests
+    /// on the same connection are not affected.
+    UnknownRequest,
+    /// The first request was rejected by a [Layer]
+    Rejected,
+    /// The peer exceeded its rate of new channels, see [RpcServer::with_rate_limit]
+    RateLimited,
+    /// The client sent more updates than the handler buffers, see
+    /// [UpdateStream::with_update_limit]
+    TooManyUpdates,
+    /// The handler ran longer than allowed, see [RpcServer::with_handler_timeout]
+    HandlerTimeout,
 }
 
 impl<In: RpcMessage, Out: RpcMessage, C: ConnectionErrors>
@@ -374,6 +2601,11 @@ impl<In: RpcMessage, Out: RpcMessage, C: ConnectionErrors>
             RpcServerError::EarlyClose => RpcServerError::EarlyClose,
             RpcServerError::UnexpectedStartMessage => RpcServerError::UnexpectedStartMessage,
             RpcServerError::UnexpectedUpdateMessage => RpcServerError::UnexpectedUpdateMessage,
+            RpcServerError::UnknownRequest => RpcServerError::UnknownRequest,
+            RpcServerError::Rejected => RpcServerError::Rejected,
+            RpcServerError::RateLimited => RpcServerError::RateLimited,
+            RpcServerError::TooManyUpdates => RpcServerError::TooManyUpdates,
+            RpcServerError::HandlerTimeout => RpcServerError::HandlerTimeout,
             RpcServerError::SendError(x) => RpcServerError::SendError(x),
             RpcServerError::Accept(x) => RpcServerError::Accept(x),
             RpcServerError::RecvError(ErrorOrMapError::Inner(x)) => RpcServerError::RecvError(x),
@@ -397,6 +2629,11 @@ impl<C: ConnectionErrors> RpcServerError<C> {
             RpcServerError::EarlyClose => RpcServerError::EarlyClose,
             RpcServerError::UnexpectedStartMessage => RpcServerError::UnexpectedStartMessage,
             RpcServerError::UnexpectedUpdateMessage => RpcServerError::UnexpectedUpdateMessage,
+            RpcServerError::UnknownRequest => RpcServerError::UnknownRequest,
+            RpcServerError::Rejected => RpcServerError::Rejected,
+            RpcServerError::RateLimited => RpcServerError::RateLimited,
+            RpcServerError::TooManyUpdates => RpcServerError::TooManyUpdates,
+            RpcServerError::HandlerTimeout => RpcServerError::HandlerTimeout,
             RpcServerError::SendError(x) => RpcServerError::SendError(x.into()),
             RpcServerError::Accept(x) => RpcServerError::Accept(x.into()),
             RpcServerError::RecvError(x) => RpcServerError::RecvError(x.into()),
@@ -413,17 +2650,43 @@ impl<C: ConnectionErrors> fmt::Debug for RpcServerError<C> {
             Self::SendError(arg0) => f.debug_tuple("SendError").field(arg0).finish(),
             Self::UnexpectedStartMessage => f.debug_tuple("UnexpectedStartMessage").finish(),
             Self::UnexpectedUpdateMessage => f.debug_tuple("UnexpectedStartMessage").finish(),
+            Self::UnknownRequest => write!(f, "UnknownRequest"),
+            Self::Rejected => write!(f, "Rejected"),
+            Self::RateLimited => write!(f, "RateLimited"),
+            Self::TooManyUpdates => write!(f, "TooManyUpdates"),
+            Self::HandlerTimeout => write!(f, "HandlerTimeout"),
         }
     }
 }
 
 impl<C: ConnectionErrors> fmt::Display for RpcServerError<C> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        fmt::Debug::fmt(&self, f)
+        match self {
+            Self::Accept(_) => write!(f, "failed to accept a channel"),
+            Self::EarlyClose => write!(f, "channel closed before the first request"),
+            Self::RecvError(_) => write!(f, "failed to receive a message"),
+            Self::SendError(_) => write!(f, "failed to send a response"),
+            Self::UnexpectedStartMessage => write!(f, "unexpected first message"),
+            Self::UnexpectedUpdateMessage => write!(f, "unexpected update message"),
+            Self::UnknownRequest => write!(f, "unknown request"),
+            Self::Rejected => write!(f, "request rejected by a layer"),
+            Self::RateLimited => write!(f, "rate limit of the peer exceeded"),
+            Self::TooManyUpdates => write!(f, "too many buffered updates"),
+            Self::HandlerTimeout => write!(f, "handler timed out"),
+        }
     }
 }
 
-impl<C: ConnectionErrors> error::Error for RpcServerError<C> {}
+impl<C: ConnectionErrors> error::Error for RpcServerError<C> {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Self::Accept(cause) => Some(ErrorSource::new(cause)),
+            Self::RecvError(cause) => Some(ErrorSource::new(cause)),
+            Self::SendError(cause) => Some(ErrorSource::new(cause)),
+            _ => None,
+        }
+    }
+}
 
 /// Take an oneshot receiver and just return Pending the underlying future returns `Err(oneshot::Canceled)`
 pub(crate) struct UnwrapToPending<T>(oneshot::Receiver<T>);
@@ -440,6 +2703,334 @@ impl<T> Future for UnwrapToPending<T> {
     }
 }
 
+/// Resolves with `Ok(())` once the client stopped receiving on `send`
+///
+/// The updates of a call just end when the client drops it, so the handling of calls
+/// with updates races this, to not run to completion for nobody. See
+/// [StreamTypes::send_closed].
+pub(crate) fn client_gone<C: StreamTypes>(
+    send: &C::SendSink,
+) -> impl Future<Output = result::Result<(), RpcServerError<C>>> + Send + 'static {
+    // the future of the transport is `'static`, but its type still borrows `send`
+    let closed: BoxFuture<'static, ()> = Box::pin(C::send_closed(send));
+    closed.map(|()| {
+        debug!("client dropped the call, cancelling the handler");
+        Ok(())
+    })
+}
+
+/// A future that resolves once the client has gone away.
+///
+/// This is passed to handlers such as [RpcChannel::server_streaming_with_cancel], so
+/// they can stop producing responses early when nobody is listening anymore.
+#[derive(Debug, Clone)]
+pub struct Cancelled(Shared<oneshot::Receiver<()>>);
+
+impl Cancelled {
+    /// Create a new cancellation signal, which fires once the returned sender is dropped.
+    pub(crate) fn new() -> (oneshot::Sender<()>, Self) {
+        let (send, recv) = oneshot::channel();
+        (send, Self(recv.shared()))
+    }
+
+    /// Returns true if the client has already gone away.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.peek().is_some()
+    }
+}
+
+impl Future for Cancelled {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx).map(|_| ())
+    }
+}
+
+/// Number of responses a [ResponseSender] buffers before [ResponseSender::send] waits
+const RESPONSE_SENDER_CAPACITY: usize = 16;
+
+/// A cloneable sender for the responses of a streaming request.
+///
+/// This is passed to handlers such as [RpcChannel::server_streaming_with_sender], so
+/// responses can be produced by several tasks. Responses are sent in the order in which
+/// they are pushed. The stream of responses ends once all clones are dropped.
+#[derive(Debug)]
+pub struct ResponseSender<T> {
+    sender: futures::channel::mpsc::Sender<T>,
+    /// Notified once the responses are no longer received
+    closed: Arc<event_listener::Event>,
+}
+
+impl<T> Clone for ResponseSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            closed: self.closed.clone(),
+        }
+    }
+}
+
+impl<T> ResponseSender<T> {
+    fn new() -> (Self, ResponseReceiver<T>) {
+        let (sender, receiver) = futures::channel::mpsc::channel(RESPONSE_SENDER_CAPACITY);
+        let closed = Arc::new(event_listener::Event::new());
+        let receiver = ResponseReceiver {
+            receiver,
+            closed: closed.clone(),
+        };
+        (Self { sender, closed }, receiver)
+    }
+
+    /// Send a response, waiting if too many responses are not yet sent to the client.
+    ///
+    /// Fails once no more responses can be sent, because sending to the client failed or
+    /// the client has gone away.
+    pub async fn send(&self, response: T) -> result::Result<(), ResponsesClosed> {
+        self.sender
+            .clone()
+            .send(response)
+            .await
+            .map_err(|_| ResponsesClosed)
+    }
+
+    /// Returns true if no more responses can be sent.
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// Resolves once no more responses can be sent.
+    pub async fn closed(&self) {
+        loop {
+            // register before checking, so closing in between is not missed
+            let closed = self.closed.listen();
+            if self.is_closed() {
+                return;
+            }
+            closed.await;
+        }
+    }
+}
+
+/// The receiving side of a [ResponseSender], which wakes [ResponseSender::closed] on drop
+struct ResponseReceiver<T> {
+    receiver: futures::channel::mpsc::Receiver<T>,
+    closed: Arc<event_listener::Event>,
+}
+
+impl<T> Stream for ResponseReceiver<T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<T>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+impl<T> Drop for ResponseReceiver<T> {
+    fn drop(&mut self) {
+        self.receiver.close();
+        self.closed.notify(usize::MAX);
+    }
+}
+
+/// Error returned by [ResponseSender::send] once no more responses can be sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponsesClosed;
+
+impl fmt::Display for ResponsesClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl error::Error for ResponsesClosed {}
+
+/// Run `f` with a [ResponseSender], and send the responses pushed to it to the client.
+///
+/// Completes once `f` has completed and all clones of the sender are dropped, or with
+/// the first error sending a response. In that case `f` is dropped, and the clones held
+/// by other tasks fail to send from then on.
+pub(crate) async fn send_responses<C, T, Fut>(
+    send: &mut C::SendSink,
+    batch: usize,
+    budget: Option<MemoryBudget>,
+    f: impl FnOnce(ResponseSender<T>) -> Fut,
+) -> result::Result<(), RpcServerError<C>>
+where
+    C: StreamTypes,
+    T: Into<C::Out>,
+    Fut: Future<Output = ()>,
+{
+    let (sender, responses) = ResponseSender::new();
+    let produce = f(sender).map(Ok);
+    let forward = async move { send_all(send, responses, batch, budget.as_ref()).await };
+    futures::future::try_join(produce, forward).await?;
+    Ok(())
+}
+
+/// Send all responses of a stream, flushing after at most `batch` responses.
+///
+/// Responses that are ready are fed to the sink without a flush, so the transport can
+/// write them together. The sink is flushed as soon as no response is ready. With a
+/// `budget`, every response takes its size from it until the next flush.
+pub(crate) async fn send_all<C, T>(
+    send: &mut C::SendSink,
+    responses: impl Stream<Item = T>,
+    batch: usize,
+    budget: Option<&MemoryBudget>,
+) -> result::Result<(), RpcServerError<C>>
+where
+    C: StreamTypes,
+    T: Into<C::Out>,
+{
+    futures_lite::pin!(responses);
+    let batch = batch.max(1);
+    let mut permits = Vec::new();
+    let mut done = false;
+    while !done {
+        let Some(response) = responses.next().await else {
+            break;
+        };
+        let response = response.into();
+        if let Some(budget) = budget {
+            permits.push(budget.acquire(&response).await);
+        }
+        send.feed(response)
+            .await
+            .map_err(RpcServerError::SendError)?;
+        for _ in 1..batch {
+            // only take responses that are ready without waiting
+            let response = match futures_lite::future::poll_once(responses.next()).await {
+                Some(Some(response)) => response.into(),
+                Some(None) => {
+                    done = true;
+                    break;
+                }
+                None => break,
+            };
+            if let Some(budget) = budget {
+                // flush first instead of waiting for the budget while holding some of it
+                match futures_lite::future::poll_once(budget.acquire(&response)).await {
+                    Some(permit) => permits.push(permit),
+                    None => {
+                        send.flush().await.map_err(RpcServerError::SendError)?;
+                        permits.clear();
+                        permits.push(budget.acquire(&response).await);
+                    }
+                }
+            }
+            send.feed(response)
+                .await
+                .map_err(RpcServerError::SendError)?;
+        }
+        send.flush().await.map_err(RpcServerError::SendError)?;
+        // the transport took the responses, so they no longer count
+        permits.clear();
+    }
+    Ok(())
+}
+
+/// Run the handling of a single interaction.
+///
+/// Records the interaction in the server metrics, if enabled. With the `tracing-spans`
+/// feature, the handling also runs inside a span that records the name of the interaction
+/// pattern, the context of the remote if the listener is a
+/// [TracedListener](crate::transport::traced::TracedListener), and the error if handling
+/// failed.
+///
+/// Fails with [RpcServerError::HandlerTimeout] if the handling takes longer than the
+/// timeout of the pattern in `timeouts`.
+pub(crate) async fn instrument<T, C: ConnectionErrors>(
+    pattern: Pattern,
+    metrics: Option<Arc<ServerMetrics>>,
+    timeouts: HandlerTimeouts,
+    fut: impl Future<Output = result::Result<T, RpcServerError<C>>>,
+) -> result::Result<T, RpcServerError<C>> {
+    let fut = with_timeout(timeouts.get(pattern), fut);
+    let fut = ServerMetrics::track(metrics, pattern, fut);
+    #[cfg(feature = "tracing-spans")]
+    {
+        use tracing::{field, Instrument};
+        let span = tracing::info_span!(
+            "rpc",
+            pattern = pattern.name(),
+            remote = field::Empty,
+            error = field::Empty
+        );
+        let res = fut.instrument(span.clone()).await;
+        if let Err(cause) = &res {
+            span.record("error", field::debug(cause));
+        }
+        res
+    }
+    #[cfg(not(feature = "tracing-spans"))]
+    fut.await
+}
+
+/// Limits how long handlers may run, see [RpcServer::with_handler_timeout]
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct HandlerTimeouts {
+    /// The limit of patterns without a limit of their own
+    default: Option<Duration>,
+    /// The limits set using [RpcServer::with_pattern_timeout]
+    patterns: [Option<Option<Duration>>; Pattern::ALL.len()],
+    /// Whether to also limit handlers to the deadline of the client, see
+    /// [RpcServer::with_deadline_cancel]
+    cancel_at_deadline: bool,
+    /// The deadline of the client, set once the first request was read
+    deadline: Option<Instant>,
+}
+
+impl HandlerTimeouts {
+    fn get(&self, pattern: Pattern) -> Option<Duration> {
+        let timeout = self.patterns[pattern as usize].unwrap_or(self.default);
+        let Some(deadline) = self.deadline else {
+            return timeout;
+        };
+        let left = deadline.saturating_duration_since(Instant::now());
+        Some(timeout.map_or(left, |timeout| timeout.min(left)))
+    }
+}
+
+/// Fail with [RpcServerError::HandlerTimeout] if `fut` takes longer than `timeout`
+async fn with_timeout<T, C: ConnectionErrors>(
+    timeout: Option<Duration>,
+    fut: impl Future<Output = result::Result<T, RpcServerError<C>>>,
+) -> result::Result<T, RpcServerError<C>> {
+    let Some(timeout) = timeout else {
+        return fut.await;
+    };
+    match glib::future_with_timeout(timeout, fut).await {
+        Ok(res) => res,
+        Err(_) => {
+            debug!(?timeout, "handler timed out, cancelling it");
+            Err(RpcServerError::HandlerTimeout)
+        }
+    }
+}
+
+/// Send the response of the [RpcServer::with_error_response] hook if a handler failed.
+///
+/// Without a response, a channel whose handler timed out is cancelled.
+pub(crate) async fn respond_to_error<C: StreamTypes>(
+    send: &mut C::SendSink,
+    error_response: Option<ErrorResponse<C>>,
+    res: result::Result<(), RpcServerError<C>>,
+) -> result::Result<(), RpcServerError<C>> {
+    let (cause, response) = match (res, error_response) {
+        (Err(cause), Some(ErrorResponse(on_error))) => on_error(cause),
+        (Err(cause), None) => (cause, None),
+        (Ok(()), _) => return Ok(()),
+    };
+    match response {
+        Some(send_response) => send_response(send).await,
+        // so the client can tell a timeout from the handler just going away
+        None if matches!(cause, RpcServerError::HandlerTimeout) => C::cancel(send),
+        None => {}
+    }
+    Err(cause)
+}
+
 pub(crate) async fn race2<T, A: Future<Output = T>, B: Future<Output = T>>(mut f1: A, mut f2: B) -> T {
     futures_util::select! {
         x = f1 => x,
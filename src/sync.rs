@@ -0,0 +1,208 @@
+//! Synchronization primitives that do not depend on a particular runtime.
+//!
+//! The server only needs an executor that polls its futures, so it can not use the
+//! primitives of tokio without pulling in tokio for every user of the crate.
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll, Waker},
+};
+
+/// An async semaphore, with the subset of the api of the tokio semaphore that the crate uses
+///
+/// Waiters get permits in the order in which they started waiting: a released permit is
+/// handed directly to the first waiter, and [Semaphore::try_acquire_owned] fails while
+/// anyone is waiting, so new callers can not take a permit ahead of them. The semaphore is
+/// never closed.
+pub(crate) struct Semaphore(Mutex<State>);
+
+#[derive(Debug)]
+struct State {
+    /// Free permits, only ever non-zero while nobody waits
+    permits: usize,
+    waiters: VecDeque<Arc<Mutex<Waiter>>>,
+}
+
+/// A queued [Acquire]
+#[derive(Debug)]
+struct Waiter {
+    /// Whether a released permit was handed to this waiter
+    granted: bool,
+    waker: Option<Waker>,
+}
+
+impl fmt::Debug for Semaphore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state();
+        f.debug_struct("Semaphore")
+            .field("permits", &state.permits)
+            .field("waiters", &state.waiters.len())
+            .finish()
+    }
+}
+
+impl Semaphore {
+    /// A semaphore with `permits` free permits
+    pub(crate) fn new(permits: usize) -> Self {
+        Self(Mutex::new(State {
+            permits,
+            waiters: VecDeque::new(),
+        }))
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The number of free permits
+    pub(crate) fn available_permits(&self) -> usize {
+        self.state().permits
+    }
+
+    /// Take a permit if one is free and nobody is waiting for one
+    pub(crate) fn try_acquire_owned(
+        self: Arc<Self>,
+    ) -> Result<OwnedSemaphorePermit, TryAcquireError> {
+        let mut state = self.state();
+        if state.permits == 0 || !state.waiters.is_empty() {
+            return Err(TryAcquireError);
+        }
+        state.permits -= 1;
+        drop(state);
+        Ok(OwnedSemaphorePermit(self))
+    }
+
+    /// Take a permit, waiting behind everyone who waits already
+    pub(crate) async fn acquire_owned(self: Arc<Self>) -> OwnedSemaphorePermit {
+        Acquire {
+            semaphore: self,
+            waiter: None,
+        }
+        .await
+    }
+
+    /// Hand a permit to the first waiter, or make it free if nobody waits
+    fn release(&self) {
+        let mut state = self.state();
+        let Some(waiter) = state.waiters.pop_front() else {
+            state.permits += 1;
+            return;
+        };
+        let waker = {
+            let mut waiter = waiter.lock().unwrap_or_else(PoisonError::into_inner);
+            waiter.granted = true;
+            waiter.waker.take()
+        };
+        drop(state);
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// Future of [Semaphore::acquire_owned]
+struct Acquire {
+    semaphore: Arc<Semaphore>,
+    /// Our place in the queue, once we had to wait
+    waiter: Option<Arc<Mutex<Waiter>>>,
+}
+
+impl Future for Acquire {
+    type Output = OwnedSemaphorePermit;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let mut state = this.semaphore.state();
+        let Some(waiter) = &this.waiter else {
+            if state.permits > 0 && state.waiters.is_empty() {
+                state.permits -= 1;
+                drop(state);
+                return Poll::Ready(OwnedSemaphorePermit(this.semaphore.clone()));
+            }
+            let waiter = Arc::new(Mutex::new(Waiter {
+                granted: false,
+                waker: Some(cx.waker().clone()),
+            }));
+            state.waiters.push_back(waiter.clone());
+            this.waiter = Some(waiter);
+            return Poll::Pending;
+        };
+        let mut waiter = waiter.lock().unwrap_or_else(PoisonError::into_inner);
+        if !waiter.granted {
+            waiter.waker = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        drop(waiter);
+        drop(state);
+        this.waiter = None;
+        Poll::Ready(OwnedSemaphorePermit(this.semaphore.clone()))
+    }
+}
+
+impl Drop for Acquire {
+    fn drop(&mut self) {
+        let Some(waiter) = self.waiter.take() else {
+            return;
+        };
+        let mut state = self.semaphore.state();
+        let granted = waiter
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .granted;
+        if granted {
+            // a permit was handed to us after we were last polled, pass it on
+            drop(state);
+            self.semaphore.release();
+        } else {
+            state.waiters.retain(|queued| !Arc::ptr_eq(queued, &waiter));
+        }
+    }
+}
+
+/// A permit of a [Semaphore], returned on drop
+#[derive(Debug)]
+pub(crate) struct OwnedSemaphorePermit(Arc<Semaphore>);
+
+impl Drop for OwnedSemaphorePermit {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// Error of [Semaphore::try_acquire_owned] if there is no free permit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TryAcquireError;
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::future::poll_once;
+
+    use super::*;
+
+    /// a released permit goes to the first waiter, not to whoever asks next
+    #[tokio::test]
+    async fn permits_are_handed_to_waiters_in_order() {
+        let semaphore = Arc::new(Semaphore::new(1));
+        let permit = semaphore.clone().try_acquire_owned().unwrap();
+        let mut first = Box::pin(semaphore.clone().acquire_owned());
+        let mut second = Box::pin(semaphore.clone().acquire_owned());
+        assert!(poll_once(&mut first).await.is_none());
+        assert!(poll_once(&mut second).await.is_none());
+
+        drop(permit);
+        assert_eq!(
+            semaphore.clone().try_acquire_owned().err(),
+            Some(TryAcquireError)
+        );
+        assert!(poll_once(&mut second).await.is_none());
+        let permit = poll_once(&mut first).await.unwrap();
+
+        // a waiter that gives up passes its place on
+        drop(second);
+        drop(permit);
+        assert_eq!(semaphore.available_permits(), 1);
+    }
+}
@@ -0,0 +1,247 @@
+//! Application level authentication for transports without client certificates
+//!
+//! The [tcp](super::tcp) and [uds](super::uds) transports can run a handshake on every
+//! new connection, before any channel is opened on it. The client sends an [AuthToken]
+//! produced by its [ClientAuth], and the server checks it with its [ServerAuth]. If the
+//! token is accepted, the resulting [Identity] is attached to the connection and is
+//! available to the handlers as [RemoteInfo::identity](super::RemoteInfo::identity). If
+//! it is rejected, the connection is closed before the listener yields any channel of
+//! it, and opening the channel fails on the client with an error whose inner error is
+//! the [AuthError] of the server.
+//!
+//! Both sides have to agree on whether there is a handshake.
+//!
+//! # Wire format
+//!
+//! The client sends the token with a big endian u32 length prefix. The server answers
+//! with a single byte, 0 if the token was accepted and 1 if it was rejected. A rejection
+//! is followed by the reason, also with a big endian u32 length prefix.
+use std::{fmt, future::Future, io, sync::Arc, time::Duration};
+
+use futures_util::future::BoxFuture;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+pub use super::Identity;
+
+/// Maximum size of a token or a rejection reason
+const MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+const ACCEPTED: u8 = 0;
+const REJECTED: u8 = 1;
+
+/// The credentials a client presents to the server, see [ClientAuth]
+///
+/// What the bytes mean is up to the application, e.g. a bearer token or a signed
+/// challenge. The [Debug] output does not contain them.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AuthToken(pub Vec<u8>);
+
+impl fmt::Debug for AuthToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AuthToken").field(&"..").finish()
+    }
+}
+
+impl From<Vec<u8>> for AuthToken {
+    fn from(value: Vec<u8>) -> Self {
+        Self(value)
+    }
+}
+
+/// The reason why the server rejected a token, see [ServerAuth]
+///
+/// The reason is sent to the client, so it should not contain anything secret.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthError {
+    /// Why the token was rejected
+    pub reason: String,
+}
+
+impl AuthError {
+    /// Create a new error with the given reason
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self {
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// The [AuthError] of an io error returned when opening a channel, if the server
+/// rejected the token of the client
+pub fn auth_error(error: &io::Error) -> Option<&AuthError> {
+    error.get_ref()?.downcast_ref()
+}
+
+type AuthFn = dyn Fn() -> BoxFuture<'static, AuthToken> + Send + Sync;
+
+/// The client side of the handshake, producing a token for every new connection
+#[derive(Clone)]
+pub struct ClientAuth {
+    token: Arc<AuthFn>,
+    timeout: Duration,
+}
+
+impl ClientAuth {
+    /// Create the client side from a function that produces the token to send.
+    ///
+    /// The function is called for every new connection, so it can e.g. refresh an
+    /// expired token.
+    pub fn new<F, Fut>(auth: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = AuthToken> + Send + 'static,
+    {
+        Self {
+            token: Arc::new(move || Box::pin(auth())),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Fail opening a connection that did not complete the handshake within `timeout`.
+    ///
+    /// This includes the time to produce the token. The default is 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl fmt::Debug for ClientAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ClientAuth")
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+type VerifyFn = dyn Fn(AuthToken) -> BoxFuture<'static, Result<Identity, AuthError>> + Send + Sync;
+
+/// The server side of the handshake, checking the token of every new connection
+#[derive(Clone)]
+pub struct ServerAuth {
+    verify: Arc<VerifyFn>,
+    timeout: Duration,
+}
+
+impl ServerAuth {
+    /// Create the server side from a function that checks a token.
+    ///
+    /// The function returns the identity of the client if the token is accepted.
+    pub fn new<F, Fut>(verify: F) -> Self
+    where
+        F: Fn(AuthToken) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Identity, AuthError>> + Send + 'static,
+    {
+        Self {
+            verify: Arc::new(move |token| Box::pin(verify(token))),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    /// Close connections that did not complete the handshake within `timeout`.
+    ///
+    /// This includes the time to check the token. The default is 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl fmt::Debug for ServerAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServerAuth")
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Run the client side of the handshake on a new connection
+pub(crate) async fn client_handshake<T>(io: &mut T, auth: &ClientAuth) -> io::Result<()>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let handshake = async {
+        let token = (auth.token)().await;
+        write_message(io, &token.0).await?;
+        io.flush().await?;
+        match io.read_u8().await? {
+            ACCEPTED => Ok(()),
+            REJECTED => {
+                let reason = read_message(io).await?;
+                let reason = String::from_utf8_lossy(&reason).into_owned();
+                Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    AuthError { reason },
+                ))
+            }
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid handshake response {other}"),
+            )),
+        }
+    };
+    tokio::time::timeout(auth.timeout, handshake)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "handshake timed out"))?
+}
+
+/// Run the server side of the handshake on a new connection
+///
+/// A rejected token is answered with the reason, and returned as an error with
+/// [io::ErrorKind::PermissionDenied].
+pub(crate) async fn server_handshake<T>(io: &mut T, auth: &ServerAuth) -> io::Result<Identity>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let handshake = async {
+        let token = AuthToken(read_message(io).await?);
+        match (auth.verify)(token).await {
+            Ok(identity) => {
+                io.write_u8(ACCEPTED).await?;
+                io.flush().await?;
+                Ok(identity)
+            }
+            Err(cause) => {
+                io.write_u8(REJECTED).await?;
+                write_message(io, cause.reason.as_bytes()).await?;
+                io.flush().await?;
+                Err(io::Error::new(io::ErrorKind::PermissionDenied, cause))
+            }
+        }
+    };
+    tokio::time::timeout(auth.timeout, handshake)
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "handshake timed out"))?
+}
+
+async fn write_message<T: AsyncWrite + Unpin>(io: &mut T, message: &[u8]) -> io::Result<()> {
+    if message.len() > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "handshake message too large",
+        ));
+    }
+    io.write_u32(message.len() as u32).await?;
+    io.write_all(message).await
+}
+
+async fn read_message<T: AsyncRead + Unpin>(io: &mut T) -> io::Result<Vec<u8>> {
+    let size = io.read_u32().await? as usize;
+    if size > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "handshake message too large",
+        ));
+    }
+    let mut message = vec![0; size];
+    io.read_exact(&mut message).await?;
+    Ok(message)
+}
@@ -0,0 +1,86 @@
+//! Backtraces of transport errors
+//!
+//! With the `backtrace` feature, the errors that the [quinn](super::quinn) and
+//! [hyper](super::hyper) transports construct capture a [Backtrace], which is available
+//! through [ErrorBacktrace::backtrace] and printed as part of the `Debug` output of the
+//! error. Enabling the feature is the opt-in, so a backtrace is captured regardless of
+//! `RUST_BACKTRACE`.
+//!
+//! Without the feature, nothing is captured and there is no overhead.
+//!
+//! The backtrace is an [ErrorTrace] in the error types of the crate: the send and
+//! receive errors of the hyper transport, and the `FrameTooLarge` error of the framing
+//! of the stream based transports. Errors of the quinn library itself are passed on
+//! unchanged, so they have no backtrace, and downcasting the payload of an [io::Error]
+//! works the same with and without the feature.
+#[cfg(feature = "backtrace")]
+use std::sync::Arc;
+use std::{backtrace::Backtrace, fmt, io};
+
+/// Access to the backtrace captured when a transport error was constructed
+pub trait ErrorBacktrace {
+    /// The backtrace of the error, if one was captured
+    ///
+    /// This is always `None` without the `backtrace` feature.
+    fn backtrace(&self) -> Option<&Backtrace>;
+}
+
+/// The errors of the crate that are the payload of an [io::Error] have a backtrace
+impl ErrorBacktrace for io::Error {
+    fn backtrace(&self) -> Option<&Backtrace> {
+        if let Some(cause) = super::protocol::frame_too_large(self) {
+            return cause.backtrace.backtrace();
+        }
+        #[cfg(feature = "quinn-transport")]
+        if let Some(cause) = self.get_ref()?.downcast_ref::<super::quinn::ResetLocally>() {
+            return cause.backtrace.backtrace();
+        }
+        None
+    }
+}
+
+/// The backtrace of where an error was constructed, see the [module docs](self)
+///
+/// Without the `backtrace` feature this is empty. It is ignored when comparing errors.
+#[derive(Clone)]
+pub struct ErrorTrace {
+    #[cfg(feature = "backtrace")]
+    backtrace: Arc<Backtrace>,
+}
+
+impl ErrorTrace {
+    /// Capture the backtrace of the caller, if the `backtrace` feature is enabled
+    #[inline(always)]
+    pub fn capture() -> Self {
+        Self {
+            #[cfg(feature = "backtrace")]
+            backtrace: Arc::new(Backtrace::force_capture()),
+        }
+    }
+}
+
+impl ErrorBacktrace for ErrorTrace {
+    fn backtrace(&self) -> Option<&Backtrace> {
+        #[cfg(feature = "backtrace")]
+        return Some(&self.backtrace);
+        #[cfg(not(feature = "backtrace"))]
+        None
+    }
+}
+
+impl fmt::Debug for ErrorTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.backtrace() {
+            Some(backtrace) => fmt::Debug::fmt(backtrace, f),
+            None => f.write_str("<disabled>"),
+        }
+    }
+}
+
+impl PartialEq for ErrorTrace {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for ErrorTrace {}
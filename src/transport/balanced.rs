@@ -0,0 +1,250 @@
+//! Connector that spreads channels over several backends
+//!
+//! Each [`Connector::open`] picks one backend according to the [`Strategy`]. A channel
+//! is bound to the backend it was opened on, so all messages of a streaming call go
+//! to the same backend.
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::BuildHasher,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use futures_lite::Stream;
+use futures_sink::Sink;
+use pin_project::pin_project;
+
+use super::{ConnectionErrors, Connector, StreamTypes};
+
+/// How a [`BalancedConnector`] picks the backend for a new channel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Use the backends in turn
+    #[default]
+    RoundRobin,
+    /// Pick a random backend
+    Random,
+    /// Pick the backend with the fewest open channels
+    LeastInFlight,
+}
+
+struct Backend<C> {
+    connector: C,
+    in_flight: Arc<AtomicUsize>,
+}
+
+struct Inner<C> {
+    backends: Vec<Backend<C>>,
+    strategy: Strategy,
+    next: AtomicUsize,
+    random: RandomState,
+}
+
+/// A connector that balances channels over several backend connectors
+///
+/// If opening a channel on the chosen backend fails, the other backends are tried in
+/// turn. Only if all of them fail, the error of the last one is returned.
+pub struct BalancedConnector<C> {
+    inner: Arc<Inner<C>>,
+}
+
+impl<C: Connector> BalancedConnector<C> {
+    /// Create a new balanced connector
+    ///
+    /// # Panics
+    ///
+    /// If `backends` is empty.
+    pub fn new(backends: Vec<C>, strategy: Strategy) -> Self {
+        assert!(!backends.is_empty(), "at least one backend is required");
+        let backends = backends
+            .into_iter()
+            .map(|connector| Backend {
+                connector,
+                in_flight: Default::default(),
+            })
+            .collect();
+        Self {
+            inner: Arc::new(Inner {
+                backends,
+                strategy,
+                next: AtomicUsize::new(0),
+                random: RandomState::new(),
+            }),
+        }
+    }
+
+    /// The number of open channels for each backend
+    pub fn in_flight(&self) -> Vec<usize> {
+        self.inner
+            .backends
+            .iter()
+            .map(|backend| backend.in_flight.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    /// Index of the backend to try first
+    fn pick(&self) -> usize {
+        let inner = &self.inner;
+        let n = inner.backends.len();
+        let count = inner.next.fetch_add(1, Ordering::Relaxed);
+        match inner.strategy {
+            Strategy::RoundRobin => count % n,
+            Strategy::Random => (inner.random.hash_one(count) % n as u64) as usize,
+            // start at a rotating index, so ties are spread as well
+            Strategy::LeastInFlight => (0..n)
+                .map(|i| (count + i) % n)
+                .min_by_key(|&i| inner.backends[i].in_flight.load(Ordering::Relaxed))
+                .expect("at least one backend"),
+        }
+    }
+}
+
+impl<C> Clone for BalancedConnector<C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<C> fmt::Debug for BalancedConnector<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BalancedConnector")
+            .field("backends", &self.inner.backends.len())
+            .field("strategy", &self.inner.strategy)
+            .finish()
+    }
+}
+
+impl<C: ConnectionErrors> ConnectionErrors for BalancedConnector<C> {
+    type SendError = C::SendError;
+    type RecvError = C::RecvError;
+    type OpenError = C::OpenError;
+    type AcceptError = C::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        C::is_remote_closed(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        C::is_clean_close(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        C::is_unknown_message(error)
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        C::is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        C::is_cancelled(error)
+    }
+}
+
+impl<C: StreamTypes> StreamTypes for BalancedConnector<C> {
+    type In = C::In;
+    type Out = C::Out;
+    type SendSink = SendSink<C::SendSink>;
+    type RecvStream = RecvStream<C::RecvStream>;
+}
+
+impl<C: Connector> Connector for BalancedConnector<C> {
+    async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let backends = &self.inner.backends;
+        let start = self.pick();
+        let mut last_error = None;
+        for i in 0..backends.len() {
+            let index = (start + i) % backends.len();
+            let backend = &backends[index];
+            match backend.connector.open().await {
+                Ok((send, recv)) => {
+                    let guard = Arc::new(InFlight::new(backend.in_flight.clone()));
+                    let send = SendSink {
+                        inner: send,
+                        _guard: guard.clone(),
+                    };
+                    let recv = RecvStream {
+                        inner: recv,
+                        _guard: guard,
+                    };
+                    return Ok((send, recv));
+                }
+                Err(cause) => {
+                    tracing::debug!(%cause, index, "open failed, trying next backend");
+                    last_error = Some(cause);
+                }
+            }
+        }
+        Err(last_error.expect("at least one backend"))
+    }
+}
+
+/// Counts a channel as in flight until both halves are dropped
+#[derive(Debug)]
+struct InFlight(Arc<AtomicUsize>);
+
+impl InFlight {
+    fn new(count: Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self(count)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Send sink of a balanced channel
+#[derive(Debug)]
+#[pin_project]
+pub struct SendSink<S> {
+    #[pin]
+    inner: S,
+    _guard: Arc<InFlight>,
+}
+
+impl<S: Sink<T>, T> Sink<T> for SendSink<S> {
+    type Error = S::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        self.project().inner.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// Receive stream of a balanced channel
+#[derive(Debug)]
+#[pin_project]
+pub struct RecvStream<S> {
+    #[pin]
+    inner: S,
+    _guard: Arc<InFlight>,
+}
+
+impl<S: Stream> Stream for RecvStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().inner.poll_next(cx)
+    }
+}
@@ -1,10 +1,13 @@
 //! Boxed transport with concrete types
 
 use std::{
-    fmt::Debug,
+    any::Any,
+    fmt::{self, Debug, Display},
     future::Future,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use futures_lite::FutureExt;
@@ -12,14 +15,97 @@ use futures_sink::Sink;
 use futures_util::{future::BoxFuture, SinkExt, Stream, StreamExt, TryStreamExt};
 use pin_project::pin_project;
 
-use super::{ConnectionErrors, StreamTypes};
+use super::{
+    Connection, ConnectionErrors, ConnectionStats, Metadata, MetadataError, PingError,
+    PriorityError, RemoteInfo, StreamTypes,
+};
 use crate::RpcMessage;
 type BoxedFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + Sync + 'a>>;
 
+/// A boxed sink that can set the priority of the channel it sends on
+trait PrioritySink<T>: Sink<T, Error = anyhow::Error> + Send + Sync + 'static {
+    fn set_priority(&self, priority: i32) -> Result<(), PriorityError>;
+
+    fn set_metadata(&self, metadata: Metadata) -> Result<(), MetadataError>;
+
+    fn set_deadline(&self, timeout: Duration) -> Result<(), MetadataError>;
+
+    fn closed(&self) -> BoxFuture<'static, ()>;
+
+    fn cancel(self: Pin<&mut Self>);
+
+    fn inner_mut(self: Pin<&mut Self>) -> Option<&mut dyn Any>;
+}
+
+/// A sink and the function that sets its priority, see [SendSink::boxed_with_priority]
+#[pin_project]
+struct WithPriority<S> {
+    #[pin]
+    sink: S,
+    set_priority: fn(&S, i32) -> Result<(), PriorityError>,
+    set_metadata: fn(&S, Metadata) -> Result<(), MetadataError>,
+    set_deadline: fn(&S, Duration) -> Result<(), MetadataError>,
+    closed: fn(&S) -> BoxFuture<'static, ()>,
+    cancel: fn(Pin<&mut S>),
+    /// The send sink of the transport, if this boxes one
+    inner_mut: fn(Pin<&mut S>) -> Option<&mut dyn Any>,
+}
+
+impl<T, S: Sink<T, Error = anyhow::Error>> Sink<T> for WithPriority<S> {
+    type Error = anyhow::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().sink.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        self.project().sink.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().sink.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().sink.poll_close(cx)
+    }
+}
+
+impl<T, S> PrioritySink<T> for WithPriority<S>
+where
+    S: Sink<T, Error = anyhow::Error> + Send + Sync + 'static,
+{
+    fn set_priority(&self, priority: i32) -> Result<(), PriorityError> {
+        (self.set_priority)(&self.sink, priority)
+    }
+
+    fn set_metadata(&self, metadata: Metadata) -> Result<(), MetadataError> {
+        (self.set_metadata)(&self.sink, metadata)
+    }
+
+    fn set_deadline(&self, timeout: Duration) -> Result<(), MetadataError> {
+        (self.set_deadline)(&self.sink, timeout)
+    }
+
+    fn closed(&self) -> BoxFuture<'static, ()> {
+        (self.closed)(&self.sink)
+    }
+
+    fn cancel(self: Pin<&mut Self>) {
+        let this = self.project();
+        (this.cancel)(this.sink)
+    }
+
+    fn inner_mut(self: Pin<&mut Self>) -> Option<&mut dyn Any> {
+        let this = self.project();
+        (this.inner_mut)(this.sink)
+    }
+}
+
 enum SendSinkInner<T: RpcMessage> {
     #[cfg(feature = "flume-transport")]
-    Direct(::flume::r#async::SendSink<'static, T>),
-    Boxed(Pin<Box<dyn Sink<T, Error = anyhow::Error> + Send + Sync + 'static>>),
+    Direct(super::flume::SendSink<T>),
+    Boxed(Pin<Box<dyn PrioritySink<T>>>),
 }
 
 /// A sink that can be used to send messages to the remote end of a channel.
@@ -33,14 +119,89 @@ pub struct SendSink<T: RpcMessage>(SendSinkInner<T>);
 impl<T: RpcMessage> SendSink<T> {
     /// Create a new send sink from a boxed sink
     pub fn boxed(sink: impl Sink<T, Error = anyhow::Error> + Send + Sync + 'static) -> Self {
-        Self(SendSinkInner::Boxed(Box::pin(sink)))
+        Self::boxed_with_priority(sink, |_, _| Err(PriorityError::Unsupported))
+    }
+
+    /// Create a new send sink from a boxed sink, and a function that sets its priority
+    ///
+    /// The function is called by [SendSink::set_priority].
+    pub fn boxed_with_priority<S>(
+        sink: S,
+        set_priority: fn(&S, i32) -> Result<(), PriorityError>,
+    ) -> Self
+    where
+        S: Sink<T, Error = anyhow::Error> + Send + Sync + 'static,
+    {
+        Self(SendSinkInner::Boxed(Box::pin(WithPriority {
+            sink,
+            set_priority,
+            set_metadata: |_, _| Err(MetadataError::Unsupported),
+            set_deadline: |_, _| Err(MetadataError::Unsupported),
+            closed: |_| Box::pin(std::future::pending()),
+            cancel: |_| {},
+            inner_mut: |_| None,
+        })))
     }
 
     /// Create a new send sink from a direct flume send sink
     #[cfg(feature = "flume-transport")]
-    pub(crate) fn direct(sink: ::flume::r#async::SendSink<'static, T>) -> Self {
+    pub(crate) fn direct(sink: super::flume::SendSink<T>) -> Self {
         Self(SendSinkInner::Direct(sink))
     }
+
+    /// Set the priority of the channel, see [StreamTypes::set_priority]
+    pub fn set_priority(&self, priority: i32) -> Result<(), PriorityError> {
+        match &self.0 {
+            #[cfg(feature = "flume-transport")]
+            SendSinkInner::Direct(_) => Err(PriorityError::Unsupported),
+            SendSinkInner::Boxed(sink) => sink.set_priority(priority),
+        }
+    }
+
+    /// Attach metadata to the channel, see [StreamTypes::set_metadata]
+    pub fn set_metadata(&self, metadata: Metadata) -> Result<(), MetadataError> {
+        match &self.0 {
+            #[cfg(feature = "flume-transport")]
+            SendSinkInner::Direct(_) => Err(MetadataError::Unsupported),
+            SendSinkInner::Boxed(sink) => sink.set_metadata(metadata),
+        }
+    }
+
+    /// Send how long this side waits for the call, see [StreamTypes::set_deadline]
+    pub fn set_deadline(&self, timeout: Duration) -> Result<(), MetadataError> {
+        match &self.0 {
+            #[cfg(feature = "flume-transport")]
+            SendSinkInner::Direct(_) => Err(MetadataError::Unsupported),
+            SendSinkInner::Boxed(sink) => sink.set_deadline(timeout),
+        }
+    }
+
+    /// Resolves once the remote stopped receiving, see [StreamTypes::send_closed]
+    pub fn closed(&self) -> impl Future<Output = ()> + Send + 'static {
+        match &self.0 {
+            #[cfg(feature = "flume-transport")]
+            SendSinkInner::Direct(sink) => sink.closed().boxed(),
+            SendSinkInner::Boxed(sink) => sink.closed(),
+        }
+    }
+
+    /// Cancel the channel, see [StreamTypes::cancel]
+    pub fn cancel(&mut self) {
+        match &mut self.0 {
+            #[cfg(feature = "flume-transport")]
+            SendSinkInner::Direct(_) => {}
+            SendSinkInner::Boxed(sink) => sink.as_mut().cancel(),
+        }
+    }
+
+    /// The send sink of the transport that was boxed, if it is a `S`
+    pub(crate) fn downcast_mut<S: 'static>(&mut self) -> Option<&mut S> {
+        match &mut self.0 {
+            #[cfg(feature = "flume-transport")]
+            SendSinkInner::Direct(sink) => (sink as &mut dyn Any).downcast_mut(),
+            SendSinkInner::Boxed(sink) => sink.as_mut().inner_mut()?.downcast_mut(),
+        }
+    }
 }
 
 impl<T: RpcMessage> Sink<T> for SendSink<T> {
@@ -88,32 +249,295 @@ impl<T: RpcMessage> Sink<T> for SendSink<T> {
     }
 }
 
+/// Context of a boxed send error that was caused by the remote closing its receiving side
+#[derive(Debug)]
+struct RemoteClosed;
+
+impl Display for RemoteClosed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("remote closed the receiving side")
+    }
+}
+
+/// Convert a send error of a transport into a boxed send error
+///
+/// This keeps the information whether the remote closed its receiving side, see
+/// [ConnectionErrors::is_remote_closed].
+pub(crate) fn box_send_error<C: ConnectionErrors>(error: C::SendError) -> anyhow::Error {
+    let closed = C::is_remote_closed(&error);
+    let error = error.into();
+    if closed {
+        error.context(RemoteClosed)
+    } else {
+        error
+    }
+}
+
+/// Box the send side of a channel of a transport
+///
+/// The errors are converted using [box_send_error], and [StreamTypes::set_priority],
+/// [StreamTypes::set_metadata], [StreamTypes::set_deadline], [StreamTypes::send_closed]
+/// and [StreamTypes::cancel] of the transport are forwarded.
+pub(crate) fn box_send_sink<C: StreamTypes>(send: C::SendSink) -> SendSink<C::Out> {
+    let sink = send.sink_map_err(box_send_error::<C>);
+    SendSink(SendSinkInner::Boxed(Box::pin(WithPriority {
+        sink,
+        set_priority: |send, priority| C::set_priority(send.get_ref(), priority),
+        set_metadata: |send, metadata| C::set_metadata(send.get_ref(), metadata),
+        set_deadline: |send, timeout| C::set_deadline(send.get_ref(), timeout),
+        closed: |send| C::send_closed(send.get_ref()).boxed(),
+        cancel: |send| C::cancel(send.get_mut().get_mut()),
+        inner_mut: |send| Some(send.get_mut().get_mut()),
+    })))
+}
+
+fn is_remote_closed(error: &anyhow::Error) -> bool {
+    #[cfg(feature = "flume-transport")]
+    if let Some(error) = error.downcast_ref::<super::flume::SendError>() {
+        return matches!(error, super::flume::SendError::ReceiverDropped);
+    }
+    error.downcast_ref::<RemoteClosed>().is_some()
+}
+
+/// Context of a boxed receive error that was caused by the remote closing cleanly
+#[derive(Debug)]
+struct CleanClose;
+
+impl Display for CleanClose {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("remote closed the connection")
+    }
+}
+
+/// Convert a receive error of a transport into a boxed receive error
+///
+/// This keeps the information whether the remote closed cleanly, see
+/// [ConnectionErrors::is_clean_close], whether the message was unknown, see
+/// [ConnectionErrors::is_unknown_message], and whether the stream was reset or
+/// cancelled, see [ConnectionErrors::is_reset] and [ConnectionErrors::is_cancelled].
+pub(crate) fn box_recv_error<C: ConnectionErrors>(error: C::RecvError) -> anyhow::Error {
+    let clean = C::is_clean_close(&error);
+    let unknown = C::is_unknown_message(&error);
+    let reset = C::is_reset(&error);
+    let cancelled = C::is_cancelled(&error);
+    let error = error.into();
+    if clean {
+        error.context(CleanClose)
+    } else if unknown {
+        error.context(UnknownMessage)
+    } else if cancelled {
+        error.context(Cancelled)
+    } else if reset {
+        error.context(Reset)
+    } else {
+        error
+    }
+}
+
+fn is_clean_close(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<CleanClose>().is_some()
+}
+
+/// Context of a boxed receive error that was caused by an unknown message
+#[derive(Debug)]
+struct UnknownMessage;
+
+impl Display for UnknownMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("unknown message")
+    }
+}
+
+fn is_unknown_message(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<UnknownMessage>().is_some()
+}
+
+/// Context of a boxed receive error that was caused by the remote resetting the stream
+#[derive(Debug)]
+struct Reset;
+
+impl Display for Reset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("remote reset the stream")
+    }
+}
+
+fn is_reset(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<Reset>().is_some() || is_cancelled(error)
+}
+
+/// Context of a boxed receive error that was caused by the remote cancelling the stream
+#[derive(Debug)]
+struct Cancelled;
+
+impl Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("remote cancelled the stream")
+    }
+}
+
+fn is_cancelled(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<Cancelled>().is_some()
+}
+
+/// A boxed stream that can return the metadata and deadline of the channel it receives on
+trait MetadataStream<T>: Stream<Item = Result<T, anyhow::Error>> + Send + Sync + 'static {
+    fn metadata(&self) -> Option<&Metadata>;
+
+    fn deadline(&self) -> Option<Instant>;
+}
+
+/// A stream and the functions that return its metadata and deadline, see
+/// [box_recv_stream]
+#[pin_project]
+struct WithMetadata<S> {
+    #[pin]
+    stream: S,
+    metadata: fn(&S) -> Option<&Metadata>,
+    deadline: fn(&S) -> Option<Instant>,
+}
+
+impl<T, S> Stream for WithMetadata<S>
+where
+    S: Stream<Item = Result<T, anyhow::Error>>,
+{
+    type Item = Result<T, anyhow::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.poll_next(cx)
+    }
+}
+
+impl<T, S> MetadataStream<T> for WithMetadata<S>
+where
+    S: Stream<Item = Result<T, anyhow::Error>> + Send + Sync + 'static,
+{
+    fn metadata(&self) -> Option<&Metadata> {
+        (self.metadata)(&self.stream)
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        (self.deadline)(&self.stream)
+    }
+}
+
 enum RecvStreamInner<T: RpcMessage> {
     #[cfg(feature = "flume-transport")]
-    Direct(::flume::r#async::RecvStream<'static, T>),
-    Boxed(Pin<Box<dyn Stream<Item = Result<T, anyhow::Error>> + Send + Sync + 'static>>),
+    Direct(super::flume::RecvStream<T>),
+    Boxed(Pin<Box<dyn MetadataStream<T>>>),
 }
 
 /// A stream that can be used to receive messages from the remote end of a channel.
 ///
 /// For local channels, this is a thin wrapper around a flume receive stream.
 /// For network channels, this contains a boxed stream, since it is reasonable
+/// to assume that in that case the additional overhead of boxing is negligible.
+///
+/// The stream of an accepted channel also carries what [BoxedListener] returns for it:
+/// the info about the remote and the [Connection] to the client.
 #[pin_project]
-pub struct RecvStream<T: RpcMessage>(RecvStreamInner<T>);
+pub struct RecvStream<T: RpcMessage>(
+    RecvStreamInner<T>,
+    Option<Arc<RemoteInfo>>,
+    /// The [Connection] of the channel, whose `Out` type the stream does not know
+    Option<Box<dyn Any + Send + Sync>>,
+);
 
 impl<T: RpcMessage> RecvStream<T> {
     /// Create a new receive stream from a boxed stream
     pub fn boxed(
         stream: impl Stream<Item = Result<T, anyhow::Error>> + Send + Sync + 'static,
     ) -> Self {
-        Self(RecvStreamInner::Boxed(Box::pin(stream)))
+        Self::boxed_with_metadata(stream, |_| None)
+    }
+
+    /// Create a new receive stream from a boxed stream, and a function that returns its
+    /// metadata
+    ///
+    /// The function is called by [RecvStream::metadata].
+    pub fn boxed_with_metadata<S>(stream: S, metadata: fn(&S) -> Option<&Metadata>) -> Self
+    where
+        S: Stream<Item = Result<T, anyhow::Error>> + Send + Sync + 'static,
+    {
+        let stream = WithMetadata {
+            stream,
+            metadata,
+            deadline: |_| None,
+        };
+        Self(RecvStreamInner::Boxed(Box::pin(stream)), None, None)
     }
 
     /// Create a new receive stream from a direct flume receive stream
     #[cfg(feature = "flume-transport")]
-    pub(crate) fn direct(stream: ::flume::r#async::RecvStream<'static, T>) -> Self {
-        Self(RecvStreamInner::Direct(stream))
+    pub(crate) fn direct(stream: super::flume::RecvStream<T>) -> Self {
+        Self(RecvStreamInner::Direct(stream), None, None)
+    }
+
+    /// Attach the info about the remote, see [super::Listener::remote_info]
+    pub fn with_remote_info(mut self, remote_info: Option<Arc<RemoteInfo>>) -> Self {
+        self.1 = remote_info;
+        self
     }
+
+    /// Attach the connection to the client, see [super::Listener::connection]
+    pub fn with_connection<Out: RpcMessage>(
+        mut self,
+        connection: Option<Connection<T, Out>>,
+    ) -> Self {
+        self.2 = connection.map(|connection| Box::new(connection) as Box<dyn Any + Send + Sync>);
+        self
+    }
+
+    /// The metadata of the channel, see [StreamTypes::metadata]
+    pub fn metadata(&self) -> Option<&Metadata> {
+        match &self.0 {
+            #[cfg(feature = "flume-transport")]
+            RecvStreamInner::Direct(_) => None,
+            RecvStreamInner::Boxed(stream) => stream.metadata(),
+        }
+    }
+
+    /// The deadline of the call, see [StreamTypes::deadline]
+    pub fn deadline(&self) -> Option<Instant> {
+        match &self.0 {
+            #[cfg(feature = "flume-transport")]
+            RecvStreamInner::Direct(_) => None,
+            RecvStreamInner::Boxed(stream) => stream.deadline(),
+        }
+    }
+
+    /// The connection attached using [RecvStream::with_connection]
+    fn connection<Out: RpcMessage>(&self) -> Option<Connection<T, Out>> {
+        self.2.as_ref()?.downcast_ref().cloned()
+    }
+}
+
+/// Box the receive side of a channel of a transport
+///
+/// The errors are converted using [box_recv_error], and [StreamTypes::metadata] and
+/// [StreamTypes::deadline] of the transport are forwarded.
+pub(crate) fn box_recv_stream<C: StreamTypes>(recv: C::RecvStream) -> RecvStream<C::In> {
+    let stream = WithMetadata {
+        stream: recv.map_err(box_recv_error::<C>),
+        metadata: |recv| C::metadata(recv.get_ref()),
+        deadline: |recv| C::deadline(recv.get_ref()),
+    };
+    RecvStream(RecvStreamInner::Boxed(Box::pin(stream)), None, None)
+}
+
+/// Box both halves of a channel accepted by `listener`, along with what the listener
+/// knows about it
+fn box_accepted<L: super::Listener>(
+    listener: &L,
+    send: L::SendSink,
+    recv: L::RecvStream,
+) -> (SendSink<L::Out>, RecvStream<L::In>) {
+    let remote_info = L::remote_info(&recv);
+    let connection = listener.connection(&recv);
+    let recv = box_recv_stream::<L>(recv)
+        .with_remote_info(remote_info)
+        .with_connection(connection);
+    (box_send_sink::<L>(send), recv)
 }
 
 impl<T: RpcMessage> Stream for RecvStream<T> {
@@ -123,7 +547,8 @@ impl<T: RpcMessage> Stream for RecvStream<T> {
         match self.project().0 {
             #[cfg(feature = "flume-transport")]
             RecvStreamInner::Direct(stream) => match stream.poll_next_unpin(cx) {
-                Poll::Ready(Some(item)) => Poll::Ready(Some(Ok(item))),
+                Poll::Ready(Some(Ok(item))) => Poll::Ready(Some(Ok(item))),
+                Poll::Ready(Some(Err(cause))) => match cause {},
                 Poll::Ready(None) => Poll::Ready(None),
                 Poll::Pending => Poll::Pending,
             },
@@ -166,7 +591,7 @@ impl<'a, In: RpcMessage, Out: RpcMessage> Future for OpenFuture<'a, In, Out> {
             #[cfg(feature = "flume-transport")]
             OpenFutureInner::Direct(f) => f
                 .poll(cx)
-                .map_ok(|(send, recv)| (SendSink::direct(send.0), RecvStream::direct(recv.0)))
+                .map_ok(|(send, recv)| (SendSink::direct(send), RecvStream::direct(recv)))
                 .map_err(|e| e.into()),
             OpenFutureInner::Boxed(f) => f.poll(cx),
         }
@@ -207,7 +632,7 @@ impl<'a, In: RpcMessage, Out: RpcMessage> Future for AcceptFuture<'a, In, Out> {
             #[cfg(feature = "flume-transport")]
             AcceptFutureInner::Direct(f) => f
                 .poll(cx)
-                .map_ok(|(send, recv)| (SendSink::direct(send.0), RecvStream::direct(recv.0)))
+                .map_ok(|(send, recv)| (SendSink::direct(send), RecvStream::direct(recv)))
                 .map_err(|e| e.into()),
             AcceptFutureInner::Boxed(f) => f.poll(cx),
         }
@@ -221,6 +646,28 @@ pub trait BoxableConnector<In: RpcMessage, Out: RpcMessage>: Debug + Send + Sync
 
     /// Open a channel to the remote che
     fn open_boxed(&self) -> OpenFuture<In, Out>;
+
+    /// Open a channel for a single rpc call, see
+    /// [Connector::open_rpc](super::Connector::open_rpc)
+    fn open_rpc_boxed(&self) -> OpenFuture<In, Out> {
+        self.open_boxed()
+    }
+
+    /// Statistics of the current connection, see [Connector::stats](super::Connector::stats)
+    fn stats_boxed(&self) -> Option<ConnectionStats> {
+        None
+    }
+
+    /// Ping the remote, see [Connector::ping](super::Connector::ping)
+    fn ping_boxed(&self) -> BoxFuture<'_, Result<Duration, PingError>> {
+        Box::pin(async { Err(PingError::Unsupported) })
+    }
+
+    /// Accept a channel opened by the remote, see
+    /// [Connector::accept_push](super::Connector::accept_push)
+    fn accept_push_boxed(&self) -> BoxFuture<'_, Option<(SendSink<Out>, RecvStream<In>)>> {
+        Box::pin(async { None })
+    }
 }
 
 /// A boxed connector
@@ -245,6 +692,26 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for BoxedConnector<In, Out> {
     type Out = Out;
     type RecvStream = RecvStream<In>;
     type SendSink = SendSink<Out>;
+
+    fn set_priority(send: &Self::SendSink, priority: i32) -> Result<(), PriorityError> {
+        send.set_priority(priority)
+    }
+
+    fn deadline(recv: &Self::RecvStream) -> Option<Instant> {
+        recv.deadline()
+    }
+
+    fn set_deadline(send: &Self::SendSink, timeout: Duration) -> Result<(), MetadataError> {
+        send.set_deadline(timeout)
+    }
+
+    fn send_closed(send: &Self::SendSink) -> impl Future<Output = ()> + Send + 'static {
+        send.closed()
+    }
+
+    fn cancel(send: &mut Self::SendSink) {
+        send.cancel()
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for BoxedConnector<In, Out> {
@@ -252,12 +719,50 @@ impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for BoxedConnector<In, Ou
     type RecvError = anyhow::Error;
     type OpenError = anyhow::Error;
     type AcceptError = anyhow::Error;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        is_remote_closed(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        is_clean_close(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        is_unknown_message(error)
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        is_cancelled(error)
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> super::Connector for BoxedConnector<In, Out> {
     async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
         self.0.open_boxed().await
     }
+
+    async fn open_rpc(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        self.0.open_rpc_boxed().await
+    }
+
+    fn stats(&self) -> Option<ConnectionStats> {
+        self.0.stats_boxed()
+    }
+
+    fn ping(&self) -> impl Future<Output = Result<Duration, PingError>> + Send {
+        self.0.ping_boxed()
+    }
+
+    fn accept_push(
+        &self,
+    ) -> impl Future<Output = Option<(Self::SendSink, Self::RecvStream)>> + Send {
+        self.0.accept_push_boxed()
+    }
 }
 
 /// Stream types for boxed streams
@@ -279,6 +784,26 @@ impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for BoxedStreamTypes<In,
     type RecvError = anyhow::Error;
     type OpenError = anyhow::Error;
     type AcceptError = anyhow::Error;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        is_remote_closed(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        is_clean_close(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        is_unknown_message(error)
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        is_cancelled(error)
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> StreamTypes for BoxedStreamTypes<In, Out> {
@@ -286,6 +811,26 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for BoxedStreamTypes<In, Out>
     type Out = Out;
     type RecvStream = RecvStream<In>;
     type SendSink = SendSink<Out>;
+
+    fn set_priority(send: &Self::SendSink, priority: i32) -> Result<(), PriorityError> {
+        send.set_priority(priority)
+    }
+
+    fn deadline(recv: &Self::RecvStream) -> Option<Instant> {
+        recv.deadline()
+    }
+
+    fn set_deadline(send: &Self::SendSink, timeout: Duration) -> Result<(), MetadataError> {
+        send.set_deadline(timeout)
+    }
+
+    fn send_closed(send: &Self::SendSink) -> impl Future<Output = ()> + Send + 'static {
+        send.closed()
+    }
+
+    fn cancel(send: &mut Self::SendSink) {
+        send.cancel()
+    }
 }
 
 /// A boxable listener
@@ -322,6 +867,34 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for BoxedListener<In, Out> {
     type Out = Out;
     type RecvStream = RecvStream<In>;
     type SendSink = SendSink<Out>;
+
+    fn set_priority(send: &Self::SendSink, priority: i32) -> Result<(), PriorityError> {
+        send.set_priority(priority)
+    }
+
+    fn metadata(recv: &Self::RecvStream) -> Option<&Metadata> {
+        recv.metadata()
+    }
+
+    fn set_metadata(send: &Self::SendSink, metadata: Metadata) -> Result<(), MetadataError> {
+        send.set_metadata(metadata)
+    }
+
+    fn deadline(recv: &Self::RecvStream) -> Option<Instant> {
+        recv.deadline()
+    }
+
+    fn set_deadline(send: &Self::SendSink, timeout: Duration) -> Result<(), MetadataError> {
+        send.set_deadline(timeout)
+    }
+
+    fn send_closed(send: &Self::SendSink) -> impl Future<Output = ()> + Send + 'static {
+        send.closed()
+    }
+
+    fn cancel(send: &mut Self::SendSink) {
+        send.cancel()
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for BoxedListener<In, Out> {
@@ -329,6 +902,26 @@ impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for BoxedListener<In, Out
     type RecvError = anyhow::Error;
     type OpenError = anyhow::Error;
     type AcceptError = anyhow::Error;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        is_remote_closed(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        is_clean_close(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        is_unknown_message(error)
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        is_cancelled(error)
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> super::Listener for BoxedListener<In, Out> {
@@ -342,6 +935,14 @@ impl<In: RpcMessage, Out: RpcMessage> super::Listener for BoxedListener<In, Out>
     fn local_addr(&self) -> &[super::LocalAddr] {
         self.0.local_addr()
     }
+
+    fn remote_info(recv: &Self::RecvStream) -> Option<Arc<RemoteInfo>> {
+        recv.1.clone()
+    }
+
+    fn connection(&self, recv: &Self::RecvStream) -> Option<Connection<In, Out>> {
+        recv.connection()
+    }
 }
 impl<In: RpcMessage, Out: RpcMessage> BoxableConnector<In, Out> for BoxedConnector<In, Out> {
     fn clone_box(&self) -> Box<dyn BoxableConnector<In, Out>> {
@@ -351,6 +952,22 @@ impl<In: RpcMessage, Out: RpcMessage> BoxableConnector<In, Out> for BoxedConnect
     fn open_boxed(&self) -> OpenFuture<In, Out> {
         OpenFuture::boxed(crate::transport::Connector::open(self))
     }
+
+    fn open_rpc_boxed(&self) -> OpenFuture<In, Out> {
+        OpenFuture::boxed(crate::transport::Connector::open_rpc(self))
+    }
+
+    fn stats_boxed(&self) -> Option<ConnectionStats> {
+        super::Connector::stats(self)
+    }
+
+    fn ping_boxed(&self) -> BoxFuture<'_, Result<Duration, PingError>> {
+        Box::pin(super::Connector::ping(self))
+    }
+
+    fn accept_push_boxed(&self) -> BoxFuture<'_, Option<(SendSink<Out>, RecvStream<In>)>> {
+        Box::pin(super::Connector::accept_push(self))
+    }
 }
 
 #[cfg(feature = "quinn-transport")]
@@ -364,14 +981,34 @@ impl<In: RpcMessage, Out: RpcMessage> BoxableConnector<In, Out>
     fn open_boxed(&self) -> OpenFuture<In, Out> {
         let f = Box::pin(async move {
             let (send, recv) = super::Connector::open(self).await?;
-            // map the error types to anyhow
-            let send = send.sink_map_err(anyhow::Error::from);
-            let recv = recv.map_err(anyhow::Error::from);
-            // return the boxed streams
-            anyhow::Ok((SendSink::boxed(send), RecvStream::boxed(recv)))
+            // box the streams, mapping the error types to anyhow
+            anyhow::Ok((box_send_sink::<Self>(send), box_recv_stream::<Self>(recv)))
+        });
+        OpenFuture::boxed(f)
+    }
+
+    fn open_rpc_boxed(&self) -> OpenFuture<In, Out> {
+        let f = Box::pin(async move {
+            let (send, recv) = super::Connector::open_rpc(self).await?;
+            anyhow::Ok((box_send_sink::<Self>(send), box_recv_stream::<Self>(recv)))
         });
         OpenFuture::boxed(f)
     }
+
+    fn stats_boxed(&self) -> Option<ConnectionStats> {
+        super::Connector::stats(self)
+    }
+
+    fn ping_boxed(&self) -> BoxFuture<'_, Result<Duration, PingError>> {
+        Box::pin(super::Connector::ping(self))
+    }
+
+    fn accept_push_boxed(&self) -> BoxFuture<'_, Option<(SendSink<Out>, RecvStream<In>)>> {
+        Box::pin(async move {
+            let (send, recv) = super::Connector::accept_push(self).await?;
+            Some((box_send_sink::<Self>(send), box_recv_stream::<Self>(recv)))
+        })
+    }
 }
 
 #[cfg(feature = "quinn-transport")]
@@ -385,9 +1022,7 @@ impl<In: RpcMessage, Out: RpcMessage> BoxableListener<In, Out>
     fn accept_bi_boxed(&self) -> AcceptFuture<In, Out> {
         let f = async move {
             let (send, recv) = super::Listener::accept(self).await?;
-            let send = send.sink_map_err(anyhow::Error::from);
-            let recv = recv.map_err(anyhow::Error::from);
-            anyhow::Ok((SendSink::boxed(send), RecvStream::boxed(recv)))
+            anyhow::Ok(box_accepted(self, send, recv))
         };
         AcceptFuture::boxed(f)
     }
@@ -408,11 +1043,8 @@ impl<In: RpcMessage, Out: RpcMessage> BoxableConnector<In, Out>
     fn open_boxed(&self) -> OpenFuture<In, Out> {
         let f = Box::pin(async move {
             let (send, recv) = super::Connector::open(self).await?;
-            // map the error types to anyhow
-            let send = send.sink_map_err(anyhow::Error::from);
-            let recv = recv.map_err(anyhow::Error::from);
-            // return the boxed streams
-            anyhow::Ok((SendSink::boxed(send), RecvStream::boxed(recv)))
+            // box the streams, mapping the error types to anyhow
+            anyhow::Ok((box_send_sink::<Self>(send), box_recv_stream::<Self>(recv)))
         });
         OpenFuture::boxed(f)
     }
@@ -429,9 +1061,7 @@ impl<In: RpcMessage, Out: RpcMessage> BoxableListener<In, Out>
     fn accept_bi_boxed(&self) -> AcceptFuture<In, Out> {
         let f = async move {
             let (send, recv) = super::Listener::accept(self).await?;
-            let send = send.sink_map_err(anyhow::Error::from);
-            let recv = recv.map_err(anyhow::Error::from);
-            anyhow::Ok((SendSink::boxed(send), RecvStream::boxed(recv)))
+            anyhow::Ok(box_accepted(self, send, recv))
         };
         AcceptFuture::boxed(f)
     }
@@ -471,6 +1101,31 @@ impl<In: RpcMessage, Out: RpcMessage> BoxableListener<In, Out>
     }
 }
 
+/// A metadata listener can be boxed once its inner listener is boxed
+impl<In: RpcMessage, Out: RpcMessage> BoxableListener<In, Out>
+    for super::metadata::MetadataListener<
+        In,
+        Out,
+        BoxedListener<super::metadata::Frame<In>, super::metadata::Frame<Out>>,
+    >
+{
+    fn clone_box(&self) -> Box<dyn BoxableListener<In, Out>> {
+        Box::new(self.clone())
+    }
+
+    fn accept_bi_boxed(&self) -> AcceptFuture<In, Out> {
+        let f = async move {
+            let (send, recv) = super::Listener::accept(self).await?;
+            anyhow::Ok(box_accepted(self, send, recv))
+        };
+        AcceptFuture::boxed(f)
+    }
+
+    fn local_addr(&self) -> &[super::LocalAddr] {
+        super::Listener::local_addr(self)
+    }
+}
+
 impl<In, Out, C> BoxableConnector<In, Out> for super::mapped::MappedConnector<In, Out, C>
 where
     In: RpcMessage,
@@ -489,14 +1144,36 @@ where
     fn open_boxed(&self) -> OpenFuture<In, Out> {
         let f = Box::pin(async move {
             let (send, recv) = super::Connector::open(self).await.map_err(|e| e.into())?;
-            // map the error types to anyhow
-            let send = send.sink_map_err(|e| e.into());
-            let recv = recv.map_err(|e| e.into());
-            // return the boxed streams
-            anyhow::Ok((SendSink::boxed(send), RecvStream::boxed(recv)))
+            // box the streams, mapping the error types to anyhow
+            anyhow::Ok((box_send_sink::<Self>(send), box_recv_stream::<Self>(recv)))
+        });
+        OpenFuture::boxed(f)
+    }
+
+    fn open_rpc_boxed(&self) -> OpenFuture<In, Out> {
+        let f = Box::pin(async move {
+            let (send, recv) = super::Connector::open_rpc(self)
+                .await
+                .map_err(|e| e.into())?;
+            anyhow::Ok((box_send_sink::<Self>(send), box_recv_stream::<Self>(recv)))
         });
         OpenFuture::boxed(f)
     }
+
+    fn stats_boxed(&self) -> Option<ConnectionStats> {
+        super::Connector::stats(self)
+    }
+
+    fn ping_boxed(&self) -> BoxFuture<'_, Result<Duration, PingError>> {
+        Box::pin(super::Connector::ping(self))
+    }
+
+    fn accept_push_boxed(&self) -> BoxFuture<'_, Option<(SendSink<Out>, RecvStream<In>)>> {
+        Box::pin(async move {
+            let (send, recv) = super::Connector::accept_push(self).await?;
+            Some((box_send_sink::<Self>(send), box_recv_stream::<Self>(recv)))
+        })
+    }
 }
 
 #[cfg(test)]
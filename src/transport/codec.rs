@@ -0,0 +1,241 @@
+//! Serialization formats for the transports that send messages as bytes.
+//!
+//! The [quinn](super::quinn) and [hyper](super::hyper) transports use [BincodeCodec] by
+//! default. A different codec can be selected when constructing the transport. Both
+//! ends of a connection have to use the same codec.
+use std::{
+    fmt::{self, Debug, Display},
+    io,
+    sync::Arc,
+};
+
+use bincode::Options;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::metrics::ByteCounters;
+
+/// A serialization format for messages.
+///
+/// Errors are reported as [io::Error] with kind [io::ErrorKind::InvalidData], so they
+/// fit into the error types of the transports.
+pub trait Codec: Debug + Clone + Send + Sync + Unpin + 'static {
+    /// Serialize a message into bytes
+    fn encode<T: Serialize>(&self, item: &T) -> io::Result<Bytes>;
+
+    /// Deserialize a message from bytes
+    fn decode<T: DeserializeOwned>(&self, bytes: Bytes) -> io::Result<T>;
+}
+
+fn invalid_data(cause: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, cause)
+}
+
+/// Bincode with fixint encoding.
+///
+/// This is the default codec, and the wire format of older versions of this crate.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BincodeCodec;
+
+impl BincodeCodec {
+    fn options(&self) -> impl Options {
+        bincode::DefaultOptions::new().with_fixint_encoding()
+    }
+}
+
+impl Codec for BincodeCodec {
+    fn encode<T: Serialize>(&self, item: &T) -> io::Result<Bytes> {
+        let data = self.options().serialize(item).map_err(invalid_data)?;
+        Ok(data.into())
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: Bytes) -> io::Result<T> {
+        self.options().deserialize(&bytes).map_err(invalid_data)
+    }
+}
+
+/// [Postcard](https://docs.rs/postcard/), a compact binary format.
+#[cfg(feature = "postcard-codec")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PostcardCodec;
+
+#[cfg(feature = "postcard-codec")]
+impl Codec for PostcardCodec {
+    fn encode<T: Serialize>(&self, item: &T) -> io::Result<Bytes> {
+        let data = postcard::to_stdvec(item).map_err(invalid_data)?;
+        Ok(data.into())
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: Bytes) -> io::Result<T> {
+        postcard::from_bytes(&bytes).map_err(invalid_data)
+    }
+}
+
+/// JSON, for debugging and interop with clients not written in rust.
+#[cfg(feature = "json-codec")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonCodec;
+
+#[cfg(feature = "json-codec")]
+impl Codec for JsonCodec {
+    fn encode<T: Serialize>(&self, item: &T) -> io::Result<Bytes> {
+        let data = serde_json::to_vec(item).map_err(invalid_data)?;
+        Ok(data.into())
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: Bytes) -> io::Result<T> {
+        serde_json::from_slice(&bytes).map_err(invalid_data)
+    }
+}
+
+#[cfg(feature = "prost-codec")]
+pub mod prost;
+#[cfg(feature = "prost-codec")]
+pub use self::prost::ProstCodec;
+
+/// A codec that counts the bytes of all messages it encodes and decodes.
+///
+/// Use the [ByteCounters] of a [ServerMetrics](crate::metrics::ServerMetrics) to count
+/// the bytes of a server. On the client side, any [ByteCounters] can be used.
+#[derive(Debug, Clone)]
+pub struct MeteredCodec<C = BincodeCodec> {
+    inner: C,
+    bytes: Arc<ByteCounters>,
+}
+
+impl<C: Codec> MeteredCodec<C> {
+    /// Wrap a codec, counting bytes in the given counters
+    pub fn new(inner: C, bytes: Arc<ByteCounters>) -> Self {
+        Self { inner, bytes }
+    }
+}
+
+impl<C: Codec> Codec for MeteredCodec<C> {
+    fn encode<T: Serialize>(&self, item: &T) -> io::Result<Bytes> {
+        let data = self.inner.encode(item)?;
+        self.bytes.sent.add(data.len() as u64);
+        Ok(data)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: Bytes) -> io::Result<T> {
+        self.bytes.received.add(bytes.len() as u64);
+        self.inner.decode(bytes)
+    }
+}
+
+/// What a [VersionedCodec] does with a message it can not decode
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownVariantPolicy {
+    /// If the message is from a newer peer, report it as [UnknownMessage].
+    ///
+    /// A server skips unknown requests with
+    /// [RpcServerError::UnknownRequest](crate::server::RpcServerError::UnknownRequest),
+    /// and keeps serving all other requests on the connection.
+    Skip,
+    /// Report all decoding errors as such, like an unversioned codec.
+    #[default]
+    Error,
+}
+
+/// A message from a newer peer that could not be decoded
+///
+/// Reported as the inner error of an [io::Error] by a [VersionedCodec] with
+/// [UnknownVariantPolicy::Skip], see [unknown_message].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownMessage {
+    /// The version of the peer that sent the message
+    pub version: u16,
+}
+
+impl Display for UnknownMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown message from peer with version {}", self.version)
+    }
+}
+
+impl std::error::Error for UnknownMessage {}
+
+impl From<UnknownMessage> for io::Error {
+    fn from(value: UnknownMessage) -> Self {
+        invalid_data(value)
+    }
+}
+
+/// Get the [UnknownMessage] from an error returned by [VersionedCodec::decode], if any
+pub fn unknown_message(error: &io::Error) -> Option<&UnknownMessage> {
+    error.get_ref()?.downcast_ref()
+}
+
+/// A codec that tags each message with the version of the sender.
+///
+/// The message is encoded with the inner codec, prefixed by the version as a big endian
+/// `u16`. Both ends of a connection have to use a versioned codec, but they can have
+/// different versions.
+///
+/// This is meant for rolling upgrades where new variants are added to the request and
+/// response enums of a service. With bincode and postcard, enum variants are encoded by
+/// their index, so new variants must only be appended to `S::Req` and `S::Res`, and
+/// the version has to be increased whenever that happens. Fields must not be added to
+/// existing messages, since an older peer can not tell them apart from garbage.
+///
+/// If a message from a peer with a higher version can not be decoded, and the policy
+/// is [UnknownVariantPolicy::Skip], the error is reported as [UnknownMessage]. A server
+/// then drops just this request, so the client sees the channel closing early.
+/// Failures to decode a message from a peer with the same or a lower version are
+/// always reported as is, since they are not caused by a newer protocol.
+///
+/// An older client never receives an unknown response variant as long as the server
+/// only sends new response variants for new requests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VersionedCodec<C = BincodeCodec> {
+    inner: C,
+    version: u16,
+    policy: UnknownVariantPolicy,
+}
+
+impl<C: Codec> VersionedCodec<C> {
+    /// Wrap a codec, tagging messages with the given version
+    pub fn new(inner: C, version: u16) -> Self {
+        Self {
+            inner,
+            version,
+            policy: UnknownVariantPolicy::default(),
+        }
+    }
+
+    /// Set what to do with messages that can not be decoded.
+    ///
+    /// The default is [UnknownVariantPolicy::Error].
+    pub fn with_unknown_variant_policy(mut self, policy: UnknownVariantPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// The version of this side
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+}
+
+impl<C: Codec> Codec for VersionedCodec<C> {
+    fn encode<T: Serialize>(&self, item: &T) -> io::Result<Bytes> {
+        let data = self.inner.encode(item)?;
+        let mut buf = BytesMut::with_capacity(2 + data.len());
+        buf.put_u16(self.version);
+        buf.extend_from_slice(&data);
+        Ok(buf.freeze())
+    }
+
+    fn decode<T: DeserializeOwned>(&self, mut bytes: Bytes) -> io::Result<T> {
+        if bytes.len() < 2 {
+            return Err(invalid_data("message without version tag"));
+        }
+        let version = bytes.get_u16();
+        match self.inner.decode(bytes) {
+            Err(_) if version > self.version && self.policy == UnknownVariantPolicy::Skip => {
+                Err(UnknownMessage { version }.into())
+            }
+            res => res,
+        }
+    }
+}
@@ -0,0 +1,373 @@
+//! Protobuf messages with [prost](https://docs.rs/prost/)
+//!
+//! Protobuf is not a serde format, so [ProstCodec] can not encode arbitrary types like
+//! the other codecs. A prost message is made a serde type with
+//! [prost_message](crate::prost_message), which serializes it as its protobuf encoding,
+//! and [ProstCodec] puts exactly that encoding on the wire. The stream based transports
+//! already prefix every frame with its length, as 4 bytes in big endian, so a peer that
+//! is not written in rust reads that length and then parses the frame with its protobuf
+//! library, e.g. `parseFrom` in Java.
+//!
+//! # Services
+//!
+//! The request and response types of a service are enums with one variant per message.
+//! In protobuf, this is a message with a single `oneof`:
+//!
+//! ```protobuf
+//! message Request {
+//!   oneof req {
+//!     Add add = 1;
+//!   }
+//! }
+//! ```
+//!
+//! For this, prost generates a struct `Request` with an `Option<request::Req>` field.
+//! [prost_oneof](crate::prost_oneof) implements the conversions between the struct and
+//! the messages of the `oneof` that the [message traits](crate::message) need, so the
+//! generated type can be used as the request type of a service directly:
+//!
+//! ```
+//! use quic_rpc::{message::RpcMsg, prost_message, prost_oneof, Service};
+//!
+//! #[derive(Clone, PartialEq, prost::Message)]
+//! pub struct Add {
+//!     #[prost(uint64, tag = "1")]
+//!     pub a: u64,
+//!     #[prost(uint64, tag = "2")]
+//!     pub b: u64,
+//! }
+//!
+//! #[derive(Clone, PartialEq, prost::Message)]
+//! pub struct Sum {
+//!     #[prost(uint64, tag = "1")]
+//!     pub value: u64,
+//! }
+//!
+//! #[derive(Clone, PartialEq, prost::Message)]
+//! pub struct Request {
+//!     #[prost(oneof = "request::Req", tags = "1")]
+//!     pub req: Option<request::Req>,
+//! }
+//!
+//! pub mod request {
+//!     #[derive(Clone, PartialEq, prost::Oneof)]
+//!     pub enum Req {
+//!         #[prost(message, tag = "1")]
+//!         Add(super::Add),
+//!     }
+//! }
+//!
+//! #[derive(Clone, PartialEq, prost::Message)]
+//! pub struct Response {
+//!     #[prost(oneof = "response::Res", tags = "1")]
+//!     pub res: Option<response::Res>,
+//! }
+//!
+//! pub mod response {
+//!     #[derive(Clone, PartialEq, prost::Oneof)]
+//!     pub enum Res {
+//!         #[prost(message, tag = "1")]
+//!         Sum(super::Sum),
+//!     }
+//! }
+//!
+//! prost_message!(Request, Response);
+//! prost_oneof!(Request, request::Req, req { Add(Add) });
+//! prost_oneof!(Response, response::Res, res { Sum(Sum) });
+//!
+//! #[derive(Debug, Clone)]
+//! struct Calculator;
+//!
+//! impl Service for Calculator {
+//!     type Req = Request;
+//!     type Res = Response;
+//! }
+//!
+//! impl RpcMsg<Calculator> for Add {
+//!     type Response = Sum;
+//! }
+//! ```
+use std::{fmt, io, marker::PhantomData};
+
+use bytes::Bytes;
+use prost::Message;
+use serde::{
+    de::{self, DeserializeOwned, Visitor},
+    forward_to_deserialize_any, ser, Deserializer, Serialize, Serializer,
+};
+
+use super::{invalid_data, Codec};
+
+/// Protobuf messages, one per frame, see the [module docs](self)
+///
+/// Encoding a type that does not serialize as a single prost message fails with an
+/// error of kind [io::ErrorKind::InvalidData].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProstCodec;
+
+impl Codec for ProstCodec {
+    fn encode<T: Serialize>(&self, item: &T) -> io::Result<Bytes> {
+        let payload = item.serialize(PayloadSerializer).map_err(invalid_data)?;
+        Ok(payload.into())
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: Bytes) -> io::Result<T> {
+        T::deserialize(PayloadDeserializer(bytes)).map_err(invalid_data)
+    }
+}
+
+/// Serialize a prost message as its protobuf encoding
+///
+/// This is what [prost_message](crate::prost_message) uses. It can also be used for a
+/// field with `#[serde(with = "quic_rpc::transport::codec::prost")]`.
+pub fn serialize<M: Message, S: Serializer>(msg: &M, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(&msg.encode_to_vec())
+}
+
+/// Deserialize a prost message from its protobuf encoding, see [serialize]
+pub fn deserialize<'de, M, D>(deserializer: D) -> Result<M, D::Error>
+where
+    M: Message + Default,
+    D: Deserializer<'de>,
+{
+    deserializer.deserialize_bytes(MessageVisitor(PhantomData))
+}
+
+struct MessageVisitor<M>(PhantomData<M>);
+
+impl<'de, M: Message + Default> Visitor<'de> for MessageVisitor<M> {
+    type Value = M;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an encoded protobuf message")
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<M, E> {
+        M::decode(v).map_err(E::custom)
+    }
+
+    // formats without a byte array type, like json, encode bytes as a sequence
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<M, A::Error> {
+        let mut data = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element::<u8>()? {
+            data.push(byte);
+        }
+        self.visit_bytes(&data)
+    }
+}
+
+/// The error of [ProstCodec] for types that are not a prost message
+#[derive(Debug)]
+struct NotAMessage(String);
+
+impl fmt::Display for NotAMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for NotAMessage {}
+
+impl ser::Error for NotAMessage {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+impl de::Error for NotAMessage {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+fn not_a_message() -> NotAMessage {
+    NotAMessage("only prost messages can be encoded with ProstCodec".into())
+}
+
+/// A serializer that only accepts the bytes of a single message
+struct PayloadSerializer;
+
+type Rejected = ser::Impossible<Vec<u8>, NotAMessage>;
+
+/// Reject everything but bytes
+macro_rules! reject {
+    ($($method:ident($($arg:ty),*) -> $ok:ty;)*) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<$ok, NotAMessage> {
+                Err(not_a_message())
+            }
+        )*
+    };
+}
+
+impl Serializer for PayloadSerializer {
+    type Ok = Vec<u8>;
+    type Error = NotAMessage;
+    type SerializeSeq = Rejected;
+    type SerializeTuple = Rejected;
+    type SerializeTupleStruct = Rejected;
+    type SerializeTupleVariant = Rejected;
+    type SerializeMap = Rejected;
+    type SerializeStruct = Rejected;
+    type SerializeStructVariant = Rejected;
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Vec<u8>, NotAMessage> {
+        Ok(v.to_vec())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Vec<u8>, NotAMessage> {
+        value.serialize(self)
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _value: &T) -> Result<Vec<u8>, NotAMessage> {
+        Err(not_a_message())
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Vec<u8>, NotAMessage> {
+        Err(not_a_message())
+    }
+
+    reject! {
+        serialize_bool(bool) -> Vec<u8>;
+        serialize_i8(i8) -> Vec<u8>;
+        serialize_i16(i16) -> Vec<u8>;
+        serialize_i32(i32) -> Vec<u8>;
+        serialize_i64(i64) -> Vec<u8>;
+        serialize_u8(u8) -> Vec<u8>;
+        serialize_u16(u16) -> Vec<u8>;
+        serialize_u32(u32) -> Vec<u8>;
+        serialize_u64(u64) -> Vec<u8>;
+        serialize_f32(f32) -> Vec<u8>;
+        serialize_f64(f64) -> Vec<u8>;
+        serialize_char(char) -> Vec<u8>;
+        serialize_str(&str) -> Vec<u8>;
+        serialize_none() -> Vec<u8>;
+        serialize_unit() -> Vec<u8>;
+        serialize_unit_struct(&'static str) -> Vec<u8>;
+        serialize_unit_variant(&'static str, u32, &'static str) -> Vec<u8>;
+        serialize_seq(Option<usize>) -> Rejected;
+        serialize_tuple(usize) -> Rejected;
+        serialize_tuple_struct(&'static str, usize) -> Rejected;
+        serialize_tuple_variant(&'static str, u32, &'static str, usize) -> Rejected;
+        serialize_map(Option<usize>) -> Rejected;
+        serialize_struct(&'static str, usize) -> Rejected;
+        serialize_struct_variant(&'static str, u32, &'static str, usize) -> Rejected;
+    }
+}
+
+/// A deserializer that provides the bytes of a single message
+struct PayloadDeserializer(Bytes);
+
+impl<'de> Deserializer<'de> for PayloadDeserializer {
+    type Error = NotAMessage;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, NotAMessage> {
+        visitor.visit_bytes(&self.0)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, NotAMessage> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+/// Implement [Serialize] and [Deserialize](serde::Deserialize) for prost messages
+///
+/// The messages are serialized as their protobuf encoding, which [ProstCodec] puts on
+/// the wire unchanged. Other codecs write them as a byte array.
+///
+/// ```
+/// #[derive(Clone, PartialEq, prost::Message)]
+/// pub struct Ping {
+///     #[prost(uint32, tag = "1")]
+///     pub seq: u32,
+/// }
+///
+/// quic_rpc::prost_message!(Ping);
+/// ```
+#[macro_export]
+macro_rules! prost_message {
+    ($($message:ty),+ $(,)?) => {
+        $(
+            impl ::serde::Serialize for $message {
+                fn serialize<S: ::serde::Serializer>(
+                    &self,
+                    serializer: S,
+                ) -> ::std::result::Result<S::Ok, S::Error> {
+                    $crate::transport::codec::prost::serialize(self, serializer)
+                }
+            }
+
+            impl<'de> ::serde::Deserialize<'de> for $message {
+                fn deserialize<D: ::serde::Deserializer<'de>>(
+                    deserializer: D,
+                ) -> ::std::result::Result<Self, D::Error> {
+                    $crate::transport::codec::prost::deserialize(deserializer)
+                }
+            }
+        )+
+    };
+}
+
+/// Convert between a prost message with a single `oneof` field and the messages in it
+///
+/// Takes the message, the enum prost generates for the `oneof`, the name of the field
+/// and the variants with their messages. For every variant, this implements `From` the
+/// variant message for the message, and `TryFrom` the message for the variant message,
+/// which fails with the message itself if it holds another variant or none at all. See
+/// the [module docs](crate::transport::codec::prost) for an example.
+#[macro_export]
+macro_rules! prost_oneof {
+    (@variant $message:ty, [$($oneof:ident)::+], $field:ident, $variant:ident, $msg:ty) => {
+        impl ::std::convert::From<$msg> for $message {
+            fn from(msg: $msg) -> Self {
+                Self {
+                    $field: ::std::option::Option::Some($($oneof)::+::$variant(msg)),
+                }
+            }
+        }
+
+        impl ::std::convert::TryFrom<$message> for $msg {
+            type Error = $message;
+
+            fn try_from(mut message: $message) -> ::std::result::Result<Self, $message> {
+                match message.$field.take() {
+                    ::std::option::Option::Some($($oneof)::+::$variant(msg)) => {
+                        ::std::result::Result::Ok(msg)
+                    }
+                    other => {
+                        message.$field = other;
+                        ::std::result::Result::Err(message)
+                    }
+                }
+            }
+        }
+    };
+    (@variants $message:ty, $oneof:tt, $field:ident { $($variant:ident($msg:ty)),+ }) => {
+        $(
+            $crate::prost_oneof!(@variant $message, $oneof, $field, $variant, $msg);
+        )+
+    };
+    ($message:ty, $($oneof:ident)::+, $field:ident { $($variant:ident($msg:ty)),+ $(,)? }) => {
+        $crate::prost_oneof!(@variants $message, [$($oneof)::+], $field { $($variant($msg)),+ });
+    };
+}
@@ -3,14 +3,19 @@ use std::{
     error, fmt,
     fmt::Debug,
     pin::Pin,
+    sync::Arc,
     task::{Context, Poll},
+    time::Duration,
 };
 
 use futures_lite::Stream;
 use futures_sink::Sink;
 use pin_project::pin_project;
 
-use super::{ConnectionErrors, Connector, Listener, LocalAddr, StreamTypes};
+use super::{
+    ConnectionErrors, ConnectionStats, Connector, Listener, LocalAddr, PingError, RemoteInfo,
+    StreamTypes,
+};
 
 /// A connection that combines two other connections
 #[derive(Debug, Clone)]
@@ -201,6 +206,41 @@ impl<A: ConnectionErrors, B: ConnectionErrors> ConnectionErrors for CombinedConn
     type RecvError = self::RecvError<A, B>;
     type OpenError = self::OpenError<A, B>;
     type AcceptError = self::AcceptError<A, B>;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        match error {
+            self::SendError::A(error) => A::is_remote_closed(error),
+            self::SendError::B(error) => B::is_remote_closed(error),
+        }
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        match error {
+            self::RecvError::A(error) => A::is_clean_close(error),
+            self::RecvError::B(error) => B::is_clean_close(error),
+        }
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        match error {
+            self::RecvError::A(error) => A::is_unknown_message(error),
+            self::RecvError::B(error) => B::is_unknown_message(error),
+        }
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        match error {
+            self::RecvError::A(error) => A::is_reset(error),
+            self::RecvError::B(error) => B::is_reset(error),
+        }
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        match error {
+            self::RecvError::A(error) => A::is_cancelled(error),
+            self::RecvError::B(error) => B::is_cancelled(error),
+        }
+    }
 }
 
 impl<A: Connector, B: Connector<In = A::In, Out = A::Out>> StreamTypes for CombinedConnector<A, B> {
@@ -224,6 +264,22 @@ impl<A: Connector, B: Connector<In = A::In, Out = A::Out>> Connector for Combine
             Err(OpenError::NoChannel)
         }
     }
+
+    fn stats(&self) -> Option<ConnectionStats> {
+        match (&self.a, &self.b) {
+            (Some(a), _) => a.stats(),
+            (None, Some(b)) => b.stats(),
+            (None, None) => None,
+        }
+    }
+
+    async fn ping(&self) -> Result<Duration, PingError> {
+        match (&self.a, &self.b) {
+            (Some(a), _) => a.ping().await,
+            (None, Some(b)) => b.ping().await,
+            (None, None) => Err(PingError::Unsupported),
+        }
+    }
 }
 
 impl<A: ConnectionErrors, B: ConnectionErrors> ConnectionErrors for CombinedListener<A, B> {
@@ -231,6 +287,41 @@ impl<A: ConnectionErrors, B: ConnectionErrors> ConnectionErrors for CombinedList
     type RecvError = self::RecvError<A, B>;
     type OpenError = self::OpenError<A, B>;
     type AcceptError = self::AcceptError<A, B>;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        match error {
+            self::SendError::A(error) => A::is_remote_closed(error),
+            self::SendError::B(error) => B::is_remote_closed(error),
+        }
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        match error {
+            self::RecvError::A(error) => A::is_clean_close(error),
+            self::RecvError::B(error) => B::is_clean_close(error),
+        }
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        match error {
+            self::RecvError::A(error) => A::is_unknown_message(error),
+            self::RecvError::B(error) => B::is_unknown_message(error),
+        }
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        match error {
+            self::RecvError::A(error) => A::is_reset(error),
+            self::RecvError::B(error) => B::is_reset(error),
+        }
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        match error {
+            self::RecvError::A(error) => A::is_cancelled(error),
+            self::RecvError::B(error) => B::is_cancelled(error),
+        }
+    }
 }
 
 impl<A: Listener, B: Listener<In = A::In, Out = A::Out>> StreamTypes for CombinedListener<A, B> {
@@ -270,6 +361,13 @@ impl<A: Listener, B: Listener<In = A::In, Out = A::Out>> Listener for CombinedLi
     fn local_addr(&self) -> &[LocalAddr] {
         &self.local_addr
     }
+
+    fn remote_info(recv: &Self::RecvStream) -> Option<Arc<RemoteInfo>> {
+        match recv {
+            RecvStream::A(recv) => A::remote_info(recv),
+            RecvStream::B(recv) => B::remote_info(recv),
+        }
+    }
 }
 
 #[cfg(test)]
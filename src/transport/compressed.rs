@@ -0,0 +1,559 @@
+//! Transport wrapper that compresses each message with [zstd].
+//!
+//! Messages are serialized with bincode and sent as byte frames over an inner
+//! transport with `In = Out = Vec<u8>`. Each frame starts with a one byte marker
+//! that tells whether the rest of the frame is compressed. Frames smaller than
+//! [CompressionConfig::min_size] are sent uncompressed. Frames that would decompress to
+//! more than [CompressionConfig::max_size] are rejected without decompressing them
+//! further.
+//!
+//! Both sides of a connection need to use the compressed wrapper.
+//!
+//! [zstd]: https://docs.rs/zstd/
+use std::{
+    fmt::{self, Debug, Display},
+    io::{self, Read},
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_lite::{Future, Stream, StreamExt};
+use futures_sink::Sink;
+use futures_util::SinkExt;
+use pin_project::pin_project;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    ConnectionErrors, ConnectionStats, Connector, Listener, LocalAddr, PingError, RemoteInfo,
+    StreamTypes,
+};
+use crate::{RpcError, RpcMessage};
+
+/// Marker for a frame that is sent as is
+const UNCOMPRESSED: u8 = 0;
+/// Marker for a zstd compressed frame
+const ZSTD: u8 = 1;
+
+/// Configuration for [CompressedConnector] and [CompressedListener].
+#[derive(Debug, Clone, Copy)]
+pub struct CompressionConfig {
+    level: i32,
+    min_size: usize,
+    max_size: usize,
+}
+
+impl CompressionConfig {
+    /// Set the zstd compression level.
+    ///
+    /// 0 means the zstd default level.
+    pub fn level(mut self, value: i32) -> Self {
+        self.level = value;
+        self
+    }
+
+    /// Set the minimum size of a serialized message for it to be compressed.
+    ///
+    /// Smaller messages are sent uncompressed.
+    pub fn min_size(mut self, value: usize) -> Self {
+        self.min_size = value;
+        self
+    }
+
+    /// Set the maximum size of a received message after decompression.
+    ///
+    /// Larger messages fail with [CompressedRecvError::Decompress]. Defaults to 16 MiB.
+    pub fn max_size(mut self, value: usize) -> Self {
+        self.max_size = value;
+        self
+    }
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self {
+            level: 0,
+            min_size: 256,
+            max_size: 1024 * 1024 * 16,
+        }
+    }
+}
+
+/// Error when sending a message over a compressed transport
+#[derive(Debug)]
+pub enum CompressedSendError<E> {
+    /// Error from the inner transport
+    Inner(E),
+    /// Unable to serialize the message
+    Encode(bincode::Error),
+    /// Unable to compress the message
+    Compress(io::Error),
+}
+
+impl<E: Debug> Display for CompressedSendError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl<E: Debug> std::error::Error for CompressedSendError<E> {}
+
+/// Error when receiving a message over a compressed transport
+#[derive(Debug)]
+pub enum CompressedRecvError<E> {
+    /// Error from the inner transport
+    Inner(E),
+    /// The frame was empty or had an unknown marker byte
+    InvalidFrame,
+    /// Unable to decompress the frame
+    Decompress(io::Error),
+    /// Unable to deserialize the message
+    Decode(bincode::Error),
+}
+
+impl<E: Debug> Display for CompressedRecvError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl<E: Debug> std::error::Error for CompressedRecvError<E> {}
+
+fn encode<T: Serialize, E>(
+    item: &T,
+    config: &CompressionConfig,
+) -> Result<Vec<u8>, CompressedSendError<E>> {
+    let data = bincode::serialize(item).map_err(CompressedSendError::Encode)?;
+    if data.len() < config.min_size {
+        let mut frame = Vec::with_capacity(data.len() + 1);
+        frame.push(UNCOMPRESSED);
+        frame.extend_from_slice(&data);
+        return Ok(frame);
+    }
+    let mut frame = vec![ZSTD];
+    zstd::stream::copy_encode(&data[..], &mut frame, config.level)
+        .map_err(CompressedSendError::Compress)?;
+    Ok(frame)
+}
+
+fn decode<T: DeserializeOwned, E>(
+    frame: &[u8],
+    config: &CompressionConfig,
+) -> Result<T, CompressedRecvError<E>> {
+    match frame.split_first() {
+        Some((&UNCOMPRESSED, data)) => {
+            bincode::deserialize(data).map_err(CompressedRecvError::Decode)
+        }
+        Some((&ZSTD, data)) => {
+            let data =
+                decompress(data, config.max_size).map_err(CompressedRecvError::Decompress)?;
+            bincode::deserialize(&data).map_err(CompressedRecvError::Decode)
+        }
+        _ => Err(CompressedRecvError::InvalidFrame),
+    }
+}
+
+/// Decompress `data`, failing as soon as the output exceeds `limit`
+///
+/// Unlike [zstd::bulk::decompress], this only allocates as much as the output needs.
+fn decompress(data: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    let mut decoder = zstd::stream::read::Decoder::new(data)?.take(limit as u64 + 1);
+    let mut output = Vec::new();
+    decoder.read_to_end(&mut output)?;
+    if output.len() > limit {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("decompressed message exceeds the limit of {limit} bytes"),
+        ));
+    }
+    Ok(output)
+}
+
+/// A connector that compresses messages sent over an inner byte frame connector
+pub struct CompressedConnector<In, Out, C> {
+    inner: C,
+    config: CompressionConfig,
+    _p: PhantomData<(In, Out)>,
+}
+
+impl<In, Out, C> CompressedConnector<In, Out, C> {
+    /// Create a new compressed connector
+    pub fn new(inner: C, config: CompressionConfig) -> Self {
+        Self {
+            inner,
+            config,
+            _p: PhantomData,
+        }
+    }
+
+    /// Get the inner connector
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<In, Out, C: Clone> Clone for CompressedConnector<In, Out, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In, Out, C: Debug> Debug for CompressedConnector<In, Out, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressedConnector")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<In, Out, C> ConnectionErrors for CompressedConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: ConnectionErrors,
+{
+    type SendError = CompressedSendError<C::SendError>;
+    type RecvError = CompressedRecvError<C::RecvError>;
+    type OpenError = C::OpenError;
+    type AcceptError = C::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        matches!(error, CompressedSendError::Inner(e) if C::is_remote_closed(e))
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        matches!(error, CompressedRecvError::Inner(e) if C::is_clean_close(e))
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        matches!(error, CompressedRecvError::Inner(e) if C::is_unknown_message(e))
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        matches!(error, CompressedRecvError::Inner(e) if C::is_reset(e))
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        matches!(error, CompressedRecvError::Inner(e) if C::is_cancelled(e))
+    }
+}
+
+impl<In, Out, C> StreamTypes for CompressedConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: StreamTypes<In = Vec<u8>, Out = Vec<u8>>,
+{
+    type In = In;
+    type Out = Out;
+    type RecvStream = CompressedRecvStream<C::RecvStream, In>;
+    type SendSink = CompressedSendSink<C::SendSink, Out>;
+}
+
+impl<In, Out, C> Connector for CompressedConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: Connector<In = Vec<u8>, Out = Vec<u8>>,
+{
+    fn open(
+        &self,
+    ) -> impl Future<Output = Result<(Self::SendSink, Self::RecvStream), Self::OpenError>> + Send
+    {
+        let inner = self.inner.open();
+        let config = self.config;
+        async move {
+            let (send, recv) = inner.await?;
+            Ok((
+                CompressedSendSink::new(send, config),
+                CompressedRecvStream::new(recv, config),
+            ))
+        }
+    }
+
+    fn stats(&self) -> Option<ConnectionStats> {
+        self.inner.stats()
+    }
+
+    fn ping(&self) -> impl Future<Output = Result<Duration, PingError>> + Send {
+        self.inner.ping()
+    }
+}
+
+/// A listener that compresses messages sent over an inner byte frame listener
+pub struct CompressedListener<In, Out, L> {
+    inner: L,
+    config: CompressionConfig,
+    _p: PhantomData<(In, Out)>,
+}
+
+impl<In, Out, L> CompressedListener<In, Out, L> {
+    /// Create a new compressed listener
+    pub fn new(inner: L, config: CompressionConfig) -> Self {
+        Self {
+            inner,
+            config,
+            _p: PhantomData,
+        }
+    }
+
+    /// Get the inner listener
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+impl<In, Out, L: Clone> Clone for CompressedListener<In, Out, L> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            config: self.config,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In, Out, L: Debug> Debug for CompressedListener<In, Out, L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressedListener")
+            .field("inner", &self.inner)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<In, Out, L> ConnectionErrors for CompressedListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: ConnectionErrors,
+{
+    type SendError = CompressedSendError<L::SendError>;
+    type RecvError = CompressedRecvError<L::RecvError>;
+    type OpenError = L::OpenError;
+    type AcceptError = L::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        matches!(error, CompressedSendError::Inner(e) if L::is_remote_closed(e))
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        matches!(error, CompressedRecvError::Inner(e) if L::is_clean_close(e))
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        matches!(error, CompressedRecvError::Inner(e) if L::is_unknown_message(e))
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        matches!(error, CompressedRecvError::Inner(e) if L::is_reset(e))
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        matches!(error, CompressedRecvError::Inner(e) if L::is_cancelled(e))
+    }
+}
+
+impl<In, Out, L> StreamTypes for CompressedListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: StreamTypes<In = Vec<u8>, Out = Vec<u8>>,
+{
+    type In = In;
+    type Out = Out;
+    type RecvStream = CompressedRecvStream<L::RecvStream, In>;
+    type SendSink = CompressedSendSink<L::SendSink, Out>;
+}
+
+impl<In, Out, L> Listener for CompressedListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: Listener<In = Vec<u8>, Out = Vec<u8>>,
+{
+    fn accept(
+        &self,
+    ) -> impl Future<Output = Result<(Self::SendSink, Self::RecvStream), Self::AcceptError>> + Send
+    {
+        let inner = self.inner.accept();
+        let config = self.config;
+        async move {
+            let (send, recv) = inner.await?;
+            Ok((
+                CompressedSendSink::new(send, config),
+                CompressedRecvStream::new(recv, config),
+            ))
+        }
+    }
+
+    fn local_addr(&self) -> &[LocalAddr] {
+        self.inner.local_addr()
+    }
+
+    fn remote_info(recv: &Self::RecvStream) -> Option<Arc<RemoteInfo>> {
+        L::remote_info(&recv.inner)
+    }
+}
+
+/// A stream that decompresses and deserializes incoming frames
+#[pin_project]
+pub struct CompressedRecvStream<S, In> {
+    inner: S,
+    config: CompressionConfig,
+    _p: PhantomData<In>,
+}
+
+impl<S, In> CompressedRecvStream<S, In> {
+    /// Create a new compressed receive stream
+    pub fn new(inner: S, config: CompressionConfig) -> Self {
+        Self {
+            inner,
+            config,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<S, In> Debug for CompressedRecvStream<S, In> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressedRecvStream").finish()
+    }
+}
+
+impl<S, In, E> Stream for CompressedRecvStream<S, In>
+where
+    S: Stream<Item = Result<Vec<u8>, E>> + Unpin,
+    In: DeserializeOwned,
+    E: RpcError,
+{
+    type Item = Result<In, CompressedRecvError<E>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        match this.inner.poll_next(cx) {
+            Poll::Ready(Some(Ok(frame))) => Poll::Ready(Some(decode(&frame, this.config))),
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(CompressedRecvError::Inner(e)))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A sink that serializes and compresses outgoing messages
+#[pin_project]
+pub struct CompressedSendSink<S, Out> {
+    inner: S,
+    config: CompressionConfig,
+    _p: PhantomData<Out>,
+}
+
+impl<S, Out> CompressedSendSink<S, Out> {
+    /// Create a new compressed send sink
+    pub fn new(inner: S, config: CompressionConfig) -> Self {
+        Self {
+            inner,
+            config,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<S, Out> Debug for CompressedSendSink<S, Out> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressedSendSink")
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl<S, Out> Sink<Out> for CompressedSendSink<S, Out>
+where
+    S: Sink<Vec<u8>> + Unpin,
+    Out: Serialize,
+{
+    type Error = CompressedSendError<S::Error>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project()
+            .inner
+            .poll_ready_unpin(cx)
+            .map_err(CompressedSendError::Inner)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Out) -> Result<(), Self::Error> {
+        let this = self.project();
+        let frame = encode(&item, this.config)?;
+        this.inner
+            .start_send_unpin(frame)
+            .map_err(CompressedSendError::Inner)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project()
+            .inner
+            .poll_flush_unpin(cx)
+            .map_err(CompressedSendError::Inner)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project()
+            .inner
+            .poll_close_unpin(cx)
+            .map_err(CompressedSendError::Inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let config = CompressionConfig::default().min_size(16);
+        let small = "hello".to_string();
+        let large = "hello ".repeat(100);
+        let frame = encode::<_, ()>(&small, &config).unwrap();
+        assert_eq!(frame[0], UNCOMPRESSED);
+        assert_eq!(decode::<String, ()>(&frame, &config).unwrap(), small);
+        let frame = encode::<_, ()>(&large, &config).unwrap();
+        assert_eq!(frame[0], ZSTD);
+        assert!(frame.len() < large.len());
+        assert_eq!(decode::<String, ()>(&frame, &config).unwrap(), large);
+    }
+
+    #[test]
+    fn corrupt_frame() {
+        let config = CompressionConfig::default();
+        assert!(matches!(
+            decode::<String, ()>(&[], &config),
+            Err(CompressedRecvError::InvalidFrame)
+        ));
+        assert!(matches!(
+            decode::<String, ()>(&[ZSTD, 1, 2, 3], &config),
+            Err(CompressedRecvError::Decompress(_))
+        ));
+    }
+
+    #[test]
+    fn oversized_frame() {
+        // compresses to a few hundred bytes
+        let large = vec![0u8; 1024 * 1024];
+        let frame = encode::<_, ()>(&large, &CompressionConfig::default()).unwrap();
+        assert!(frame.len() < 1024);
+        let config = CompressionConfig::default().max_size(1024);
+        assert!(matches!(
+            decode::<Vec<u8>, ()>(&frame, &config),
+            Err(CompressedRecvError::Decompress(_))
+        ));
+        let config = CompressionConfig::default().max_size(2 * 1024 * 1024);
+        assert_eq!(decode::<Vec<u8>, ()>(&frame, &config).unwrap(), large);
+    }
+}
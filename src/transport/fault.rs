@@ -0,0 +1,481 @@
+//! Connector that injects faults, for testing how clients deal with a failing transport.
+//!
+//! [FaultInjector] wraps another connector and applies a [FaultPolicy] to every
+//! open, every sent frame and every received frame. The policy is a script of faults
+//! for each of these operations, which are applied in order:
+//!
+//! ```
+//! # use std::time::Duration;
+//! use quic_rpc::transport::fault::{FaultPolicy, OpenFault, Repeat, SendFault};
+//!
+//! // fail the next 2 opens, then delay every send by 50ms
+//! let policy = FaultPolicy::default()
+//!     .open(OpenFault::Fail, Repeat::Times(2))
+//!     .send(SendFault::Delay(Duration::from_millis(50)), Repeat::Forever);
+//! ```
+//!
+//! Operations for which the script is exhausted pass through unchanged. The policy is
+//! shared by all channels opened by the injector. Keep a clone of it to add faults while
+//! the injector is in use.
+//!
+//! Delays are glib timers, so a channel with a delay fault has to be used on the thread
+//! that owns the glib main context, otherwise it panics.
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug, Display},
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_lite::Stream;
+use futures_sink::Sink;
+
+use super::{ConnectionErrors, ConnectionStats, Connector, PingError, StreamTypes};
+use crate::RpcError;
+
+/// How often a fault in a [FaultPolicy] is applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    /// Apply the fault to the given number of operations
+    Times(usize),
+    /// Apply the fault to all following operations
+    Forever,
+}
+
+/// Fault when opening a channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpenFault {
+    /// Open the channel normally
+    Pass,
+    /// Fail with [OpenError::Injected], without opening an inner channel
+    Fail,
+    /// Wait before opening the channel
+    Delay(Duration),
+}
+
+/// Fault when sending a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SendFault {
+    /// Send the frame normally
+    Pass,
+    /// Silently discard the frame
+    Drop,
+    /// Wait before sending the frame. Flushing waits for the frame to be sent.
+    Delay(Duration),
+    /// Fail with [SendError::Reset], for this and all following frames
+    Reset,
+}
+
+/// Fault when receiving a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvFault {
+    /// Receive the frame normally
+    Pass,
+    /// Silently discard the frame
+    Drop,
+    /// Wait before yielding the frame
+    Delay(Duration),
+    /// Replace the frame with [RecvError::Corrupt], as if it could not be decoded
+    Corrupt,
+    /// Replace the frame with [RecvError::Reset] and end the stream
+    Reset,
+}
+
+/// Faults for one kind of operation, in the order they are applied
+#[derive(Debug)]
+struct Script<F>(VecDeque<(F, Repeat)>);
+
+impl<F> Default for Script<F> {
+    fn default() -> Self {
+        Self(VecDeque::new())
+    }
+}
+
+impl<F: Copy> Script<F> {
+    fn push(&mut self, fault: F, repeat: Repeat) {
+        if repeat != Repeat::Times(0) {
+            self.0.push_back((fault, repeat));
+        }
+    }
+
+    /// The fault for the next operation, if the script is not exhausted
+    fn next(&mut self) -> Option<F> {
+        let (fault, repeat) = self.0.front_mut()?;
+        let fault = *fault;
+        if let Repeat::Times(n) = repeat {
+            *n -= 1;
+            if *n == 0 {
+                self.0.pop_front();
+            }
+        }
+        Some(fault)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Scripts {
+    open: Script<OpenFault>,
+    send: Script<SendFault>,
+    recv: Script<RecvFault>,
+}
+
+/// Script of faults for a [FaultInjector]
+///
+/// Cloning the policy is cheap, and all clones share the same script.
+#[derive(Debug, Clone, Default)]
+pub struct FaultPolicy(Arc<Mutex<Scripts>>);
+
+impl FaultPolicy {
+    /// Append a fault for opening channels.
+    pub fn open(self, fault: OpenFault, repeat: Repeat) -> Self {
+        self.scripts().open.push(fault, repeat);
+        self
+    }
+
+    /// Append a fault for sending frames, on any channel.
+    pub fn send(self, fault: SendFault, repeat: Repeat) -> Self {
+        self.scripts().send.push(fault, repeat);
+        self
+    }
+
+    /// Append a fault for receiving frames, on any channel.
+    pub fn recv(self, fault: RecvFault, repeat: Repeat) -> Self {
+        self.scripts().recv.push(fault, repeat);
+        self
+    }
+
+    /// Remove all faults that have not been applied yet.
+    pub fn clear(&self) {
+        *self.scripts() = Scripts::default();
+    }
+
+    fn scripts(&self) -> MutexGuard<'_, Scripts> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn next_open(&self) -> OpenFault {
+        self.scripts().open.next().unwrap_or(OpenFault::Pass)
+    }
+
+    fn next_send(&self) -> SendFault {
+        self.scripts().send.next().unwrap_or(SendFault::Pass)
+    }
+
+    fn next_recv(&self) -> RecvFault {
+        self.scripts().recv.next().unwrap_or(RecvFault::Pass)
+    }
+}
+
+/// A connector that injects the faults of a [FaultPolicy] into an inner connector
+#[derive(Debug, Clone)]
+pub struct FaultInjector<C> {
+    inner: C,
+    policy: FaultPolicy,
+}
+
+impl<C: Connector> FaultInjector<C> {
+    /// Create a new fault injector with the given policy
+    pub fn new(inner: C, policy: FaultPolicy) -> Self {
+        Self { inner, policy }
+    }
+}
+
+impl<C: ConnectionErrors> ConnectionErrors for FaultInjector<C> {
+    type SendError = SendError<C::SendError>;
+    type RecvError = RecvError<C::RecvError>;
+    type OpenError = OpenError<C::OpenError>;
+    type AcceptError = C::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        match error {
+            SendError::Send(cause) => C::is_remote_closed(cause),
+            SendError::Reset => false,
+        }
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        match error {
+            RecvError::Recv(cause) => C::is_clean_close(cause),
+            RecvError::Corrupt | RecvError::Reset => false,
+        }
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        match error {
+            RecvError::Recv(cause) => C::is_unknown_message(cause),
+            RecvError::Corrupt | RecvError::Reset => false,
+        }
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        match error {
+            RecvError::Recv(cause) => C::is_reset(cause),
+            RecvError::Corrupt => false,
+            RecvError::Reset => true,
+        }
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        match error {
+            RecvError::Recv(cause) => C::is_cancelled(cause),
+            RecvError::Corrupt | RecvError::Reset => false,
+        }
+    }
+}
+
+impl<C: StreamTypes> StreamTypes for FaultInjector<C> {
+    type In = C::In;
+    type Out = C::Out;
+    type SendSink = SendSink<C>;
+    type RecvStream = RecvStream<C>;
+}
+
+impl<C: Connector> Connector for FaultInjector<C> {
+    async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        match self.policy.next_open() {
+            OpenFault::Pass => {}
+            OpenFault::Fail => return Err(OpenError::Injected),
+            OpenFault::Delay(delay) => glib::timeout_future(delay).await,
+        }
+        let (send, recv) = self.inner.open().await.map_err(OpenError::Open)?;
+        let send = SendSink {
+            inner: send,
+            policy: self.policy.clone(),
+            delayed: None,
+            reset: false,
+        };
+        let recv = RecvStream {
+            inner: recv,
+            policy: self.policy.clone(),
+            delayed: None,
+            reset: false,
+        };
+        Ok((send, recv))
+    }
+
+    fn stats(&self) -> Option<ConnectionStats> {
+        self.inner.stats()
+    }
+
+    fn ping(&self) -> impl Future<Output = Result<Duration, PingError>> + Send {
+        self.inner.ping()
+    }
+}
+
+/// Error when opening a channel of a [FaultInjector]
+#[derive(Debug)]
+pub enum OpenError<E> {
+    /// Opening the inner channel failed
+    Open(E),
+    /// The policy failed the open
+    Injected,
+}
+
+impl<E: Debug> Display for OpenError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl<E: RpcError> std::error::Error for OpenError<E> {}
+
+/// Error when sending on a channel of a [FaultInjector]
+#[derive(Debug)]
+pub enum SendError<E> {
+    /// Sending on the inner channel failed
+    Send(E),
+    /// The policy reset the send side of the channel
+    Reset,
+}
+
+impl<E: Debug> Display for SendError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl<E: RpcError> std::error::Error for SendError<E> {}
+
+/// Error when receiving on a channel of a [FaultInjector]
+#[derive(Debug)]
+pub enum RecvError<E> {
+    /// Receiving on the inner channel failed
+    Recv(E),
+    /// The policy corrupted a frame
+    Corrupt,
+    /// The policy reset the receive side of the channel
+    Reset,
+}
+
+impl<E: Debug> Display for RecvError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl<E: RpcError> std::error::Error for RecvError<E> {}
+
+/// Timer for delayed frames
+///
+/// The timer is only ever polled through `&mut`, the mutex just makes the channel `Sync`.
+type Timer = Mutex<Pin<Box<dyn Future<Output = ()> + Send>>>;
+
+fn poll_timer(timer: &mut Timer, cx: &mut Context<'_>) -> Poll<()> {
+    let timer = timer.get_mut().unwrap_or_else(PoisonError::into_inner);
+    timer.as_mut().poll(cx)
+}
+
+/// Send sink of a [FaultInjector] channel
+pub struct SendSink<C: StreamTypes> {
+    inner: C::SendSink,
+    policy: FaultPolicy,
+    /// A frame that is waiting to be sent, with the timer until its delay has elapsed
+    delayed: Option<(C::Out, Option<Timer>)>,
+    reset: bool,
+}
+
+impl<C: StreamTypes> fmt::Debug for SendSink<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendSink")
+            .field("delayed", &self.delayed.is_some())
+            .field("reset", &self.reset)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: StreamTypes> SendSink<C> {
+    /// Send the delayed frame, if any, once its delay has elapsed
+    fn poll_delayed(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), SendError<C::SendError>>> {
+        if self.reset {
+            return Poll::Ready(Err(SendError::Reset));
+        }
+        let Some((_, timer)) = self.delayed.as_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+        if let Some(elapsed) = timer.as_mut().map(|timer| poll_timer(timer, cx)) {
+            if elapsed.is_pending() {
+                return Poll::Pending;
+            }
+            *timer = None;
+        }
+        let mut inner = Pin::new(&mut self.inner);
+        if let Err(cause) = futures_lite::ready!(inner.as_mut().poll_ready(cx)) {
+            return Poll::Ready(Err(SendError::Send(cause)));
+        }
+        let (item, _) = self.delayed.take().expect("checked above");
+        Poll::Ready(inner.start_send(item).map_err(SendError::Send))
+    }
+}
+
+impl<C: StreamTypes> Sink<C::Out> for SendSink<C> {
+    type Error = SendError<C::SendError>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        futures_lite::ready!(this.poll_delayed(cx))?;
+        Pin::new(&mut this.inner)
+            .poll_ready(cx)
+            .map_err(SendError::Send)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: C::Out) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        if this.reset {
+            return Err(SendError::Reset);
+        }
+        match this.policy.next_send() {
+            SendFault::Pass => Pin::new(&mut this.inner)
+                .start_send(item)
+                .map_err(SendError::Send),
+            SendFault::Drop => Ok(()),
+            SendFault::Delay(delay) => {
+                this.delayed = Some((item, Some(Mutex::new(glib::timeout_future(delay)))));
+                Ok(())
+            }
+            SendFault::Reset => {
+                this.reset = true;
+                this.delayed = None;
+                Err(SendError::Reset)
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        futures_lite::ready!(this.poll_delayed(cx))?;
+        Pin::new(&mut this.inner)
+            .poll_flush(cx)
+            .map_err(SendError::Send)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        futures_lite::ready!(this.poll_delayed(cx))?;
+        Pin::new(&mut this.inner)
+            .poll_close(cx)
+            .map_err(SendError::Send)
+    }
+}
+
+/// Receive stream of a [FaultInjector] channel
+pub struct RecvStream<C: StreamTypes> {
+    inner: C::RecvStream,
+    policy: FaultPolicy,
+    /// A frame that is waiting for its delay to elapse
+    delayed: Option<(C::In, Timer)>,
+    reset: bool,
+}
+
+impl<C: StreamTypes> fmt::Debug for RecvStream<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecvStream")
+            .field("delayed", &self.delayed.is_some())
+            .field("reset", &self.reset)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: StreamTypes> Stream for RecvStream<C> {
+    type Item = Result<C::In, RecvError<C::RecvError>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.reset {
+            return Poll::Ready(None);
+        }
+        if let Some((_, timer)) = this.delayed.as_mut() {
+            if poll_timer(timer, cx).is_pending() {
+                return Poll::Pending;
+            }
+            let (item, _) = this.delayed.take().expect("checked above");
+            return Poll::Ready(Some(Ok(item)));
+        }
+        loop {
+            let item = match futures_lite::ready!(Pin::new(&mut this.inner).poll_next(cx)) {
+                Some(Ok(item)) => item,
+                Some(Err(cause)) => return Poll::Ready(Some(Err(RecvError::Recv(cause)))),
+                None => return Poll::Ready(None),
+            };
+            match this.policy.next_recv() {
+                RecvFault::Pass => return Poll::Ready(Some(Ok(item))),
+                RecvFault::Drop => continue,
+                RecvFault::Delay(delay) => {
+                    let mut timer = Mutex::new(glib::timeout_future(delay));
+                    if poll_timer(&mut timer, cx).is_ready() {
+                        return Poll::Ready(Some(Ok(item)));
+                    }
+                    this.delayed = Some((item, timer));
+                    return Poll::Pending;
+                }
+                RecvFault::Corrupt => return Poll::Ready(Some(Err(RecvError::Corrupt))),
+                RecvFault::Reset => {
+                    this.reset = true;
+                    return Poll::Ready(Some(Err(RecvError::Reset)));
+                }
+            }
+        }
+    }
+}
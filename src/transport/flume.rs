@@ -2,7 +2,14 @@
 //!
 //! [flume]: https://docs.rs/flume/
 use core::fmt;
-use std::{error, fmt::Display, marker::PhantomData, pin::Pin, result, task::Poll};
+use std::{
+    error,
+    fmt::Display,
+    marker::PhantomData,
+    pin::Pin,
+    result,
+    task::{ready, Poll},
+};
 
 use futures_lite::{Future, Stream};
 use futures_sink::Sink;
@@ -13,6 +20,8 @@ use crate::{
     RpcMessage,
 };
 
+const STREAM_CAPACITY: usize = 128;
+
 /// Error when receiving from a channel
 ///
 /// This type has zero inhabitants, so it is always safe to unwrap a result with this error type.
@@ -25,8 +34,43 @@ impl fmt::Display for RecvError {
     }
 }
 
+/// Both halves of a channel in one direction
+///
+/// The sink learns when the stream is dropped, see [SendSink::closed].
+fn pair<T: RpcMessage>(capacity: usize) -> (SendSink<T>, RecvStream<T>) {
+    let (send, recv) = flume::bounded(capacity);
+    // nothing is ever sent on this, it only disconnects when the stream is dropped
+    let (alive, closed) = flume::bounded(0);
+    (
+        SendSink(Some(send.into_sink()), closed),
+        RecvStream(recv.into_stream(), alive),
+    )
+}
+
 /// Sink for memory channels
-pub struct SendSink<T: RpcMessage>(pub(crate) flume::r#async::SendSink<'static, T>);
+pub struct SendSink<T: RpcMessage>(
+    pub(crate) Option<flume::r#async::SendSink<'static, T>>,
+    flume::Receiver<()>,
+);
+
+impl<T: RpcMessage> SendSink<T> {
+    /// Resolves once the receiving side is dropped, see [StreamTypes::send_closed]
+    pub(crate) fn closed(&self) -> impl Future<Output = ()> + Send + 'static {
+        let closed = self.1.clone();
+        async move {
+            closed.recv_async().await.ok();
+        }
+    }
+
+    fn inner(
+        self: Pin<&mut Self>,
+    ) -> Result<Pin<&mut flume::r#async::SendSink<'static, T>>, SendError> {
+        match &mut self.get_mut().0 {
+            Some(sink) => Ok(Pin::new(sink)),
+            None => Err(SendError::Closed),
+        }
+    }
+}
 
 impl<T: RpcMessage> fmt::Debug for SendSink<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -38,25 +82,25 @@ impl<T: RpcMessage> Sink<T> for SendSink<T> {
     type Error = self::SendError;
 
     fn poll_ready(
-        mut self: Pin<&mut Self>,
+        self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.0)
+        self.inner()?
             .poll_ready(cx)
             .map_err(|_| SendError::ReceiverDropped)
     }
 
-    fn start_send(mut self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
-        Pin::new(&mut self.0)
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        self.inner()?
             .start_send(item)
             .map_err(|_| SendError::ReceiverDropped)
     }
 
     fn poll_flush(
-        mut self: Pin<&mut Self>,
+        self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.0)
+        self.inner()?
             .poll_flush(cx)
             .map_err(|_| SendError::ReceiverDropped)
     }
@@ -65,14 +109,23 @@ impl<T: RpcMessage> Sink<T> for SendSink<T> {
         mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.0)
-            .poll_close(cx)
-            .map_err(|_| SendError::ReceiverDropped)
+        // drop the sender once everything is flushed, so the receiver sees the end of the stream
+        let Some(sink) = self.0.as_mut() else {
+            return Poll::Ready(Ok(()));
+        };
+        let res = ready!(Pin::new(sink).poll_close(cx));
+        self.0 = None;
+        Poll::Ready(res.map_err(|_| SendError::ReceiverDropped))
     }
 }
 
 /// Stream for memory channels
-pub struct RecvStream<T: RpcMessage>(pub(crate) flume::r#async::RecvStream<'static, T>);
+pub struct RecvStream<T: RpcMessage>(
+    pub(crate) flume::r#async::RecvStream<'static, T>,
+    /// Dropped with the stream, which tells the sending side, see [SendSink::closed]
+    #[allow(dead_code)]
+    flume::Sender<()>,
+);
 
 impl<T: RpcMessage> fmt::Debug for RecvStream<T> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -126,6 +179,10 @@ impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for FlumeListener<In, Out
     type RecvError = self::RecvError;
     type OpenError = self::OpenError;
     type AcceptError = self::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        matches!(error, SendError::ReceiverDropped)
+    }
 }
 
 type Socket<In, Out> = (self::SendSink<Out>, self::RecvStream<In>);
@@ -199,6 +256,10 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for FlumeListener<In, Out> {
     type Out = Out;
     type SendSink = SendSink<Out>;
     type RecvStream = RecvStream<In>;
+
+    fn send_closed(send: &Self::SendSink) -> impl Future<Output = ()> + Send + 'static {
+        send.closed()
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> Listener for FlumeListener<In, Out> {
@@ -220,6 +281,10 @@ impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for FlumeConnector<In, Ou
     type RecvError = self::RecvError;
     type OpenError = self::OpenError;
     type AcceptError = self::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        matches!(error, SendError::ReceiverDropped)
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> StreamTypes for FlumeConnector<In, Out> {
@@ -227,21 +292,19 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for FlumeConnector<In, Out> {
     type Out = Out;
     type SendSink = SendSink<Out>;
     type RecvStream = RecvStream<In>;
+
+    fn send_closed(send: &Self::SendSink) -> impl Future<Output = ()> + Send + 'static {
+        send.closed()
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> Connector for FlumeConnector<In, Out> {
     #[allow(refining_impl_trait)]
     fn open(&self) -> OpenFuture<In, Out> {
-        let (local_send, remote_recv) = flume::bounded::<Out>(128);
-        let (remote_send, local_recv) = flume::bounded::<In>(128);
-        let remote_chan = (
-            SendSink(remote_send.into_sink()),
-            RecvStream(remote_recv.into_stream()),
-        );
-        let local_chan = (
-            SendSink(local_send.into_sink()),
-            RecvStream(local_recv.into_stream()),
-        );
+        let (local_send, remote_recv) = pair::<Out>(self.stream_capacity);
+        let (remote_send, local_recv) = pair::<In>(self.stream_capacity);
+        let remote_chan = (remote_send, remote_recv);
+        let local_chan = (local_send, local_recv);
         OpenFuture::new(self.sink.clone().into_send_async(remote_chan), local_chan)
     }
 }
@@ -252,12 +315,27 @@ impl<In: RpcMessage, Out: RpcMessage> Connector for FlumeConnector<In, Out> {
 pub struct FlumeConnector<In: RpcMessage, Out: RpcMessage> {
     #[allow(clippy::type_complexity)]
     sink: flume::Sender<(SendSink<In>, RecvStream<Out>)>,
+    stream_capacity: usize,
+}
+
+impl<In: RpcMessage, Out: RpcMessage> FlumeConnector<In, Out> {
+    /// Set the number of messages that are buffered in each direction of a channel
+    /// opened by this connector, before the sender has to wait for the receiver.
+    ///
+    /// Defaults to 128. Together with the size of the messages, this bounds the memory
+    /// of a channel, e.g. when a server streaming handler produces items faster than
+    /// the client consumes them. A capacity of 0 hands over every message directly.
+    pub fn with_stream_capacity(mut self, stream_capacity: usize) -> Self {
+        self.stream_capacity = stream_capacity;
+        self
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> Clone for FlumeConnector<In, Out> {
     fn clone(&self) -> Self {
         Self {
             sink: self.sink.clone(),
+            stream_capacity: self.stream_capacity,
         }
     }
 }
@@ -266,6 +344,7 @@ impl<In: RpcMessage, Out: RpcMessage> fmt::Debug for FlumeConnector<In, Out> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("FlumeClientChannel")
             .field("sink", &self.sink)
+            .field("stream_capacity", &self.stream_capacity)
             .finish()
     }
 }
@@ -294,6 +373,8 @@ impl error::Error for AcceptError {}
 pub enum SendError {
     /// Receiver was dropped
     ReceiverDropped,
+    /// The sink was already closed
+    Closed,
 }
 
 impl Display for SendError {
@@ -336,10 +417,16 @@ impl std::error::Error for CreateChannelError {}
 
 /// Create a flume listener and a connected flume connector.
 ///
-/// `buffer` the size of the buffer for each channel. Keep this at a low value to get backpressure
+/// `buffer` is the number of channels that can be opened before the listener accepts
+/// them. Keep this at a low value to get backpressure. The messages of each channel are
+/// buffered separately, see [FlumeConnector::with_stream_capacity].
 pub fn channel<Req: RpcMessage, Res: RpcMessage>(
     buffer: usize,
 ) -> (FlumeListener<Req, Res>, FlumeConnector<Res, Req>) {
     let (sink, stream) = flume::bounded(buffer);
-    (FlumeListener { stream }, FlumeConnector { sink })
+    let connector = FlumeConnector {
+        sink,
+        stream_capacity: STREAM_CAPACITY,
+    };
+    (FlumeListener { stream }, connector)
 }
@@ -2,8 +2,16 @@
 //!
 //! [hyper]: https://crates.io/crates/hyper/
 use std::{
-    convert::Infallible, error, fmt, io, marker::PhantomData, net::SocketAddr, pin::Pin, result,
-    sync::Arc, task::Poll,
+    convert::Infallible,
+    error, fmt,
+    future::Future,
+    io,
+    marker::PhantomData,
+    net::SocketAddr,
+    pin::Pin,
+    result,
+    sync::Arc,
+    task::{Context, Poll},
 };
 
 use bytes::Bytes;
@@ -13,13 +21,17 @@ use futures_sink::Sink;
 use hyper::{
     client::{connect::Connect, HttpConnector, ResponseFuture},
     server::conn::{AddrIncoming, AddrStream},
-    service::{make_service_fn, service_fn},
+    service::{make_service_fn, service_fn, Service},
     Body, Client, Request, Response, Server, StatusCode, Uri,
 };
 use tracing::{debug, event, trace, Level};
 
 use crate::{
-    transport::{ConnectionErrors, Connector, Listener, LocalAddr, StreamTypes},
+    transport::{
+        backtrace::{ErrorBacktrace, ErrorTrace},
+        codec::{BincodeCodec, Codec},
+        ConnectionErrors, Connector, Listener, LocalAddr, StreamTypes,
+    },
     RpcMessage,
 };
 
@@ -30,15 +42,19 @@ struct HyperConnectionInner {
 }
 
 /// Hyper based connection to a server
-pub struct HyperConnector<In: RpcMessage, Out: RpcMessage> {
+///
+/// Messages are serialized using the codec `C`, which defaults to [BincodeCodec].
+pub struct HyperConnector<In: RpcMessage, Out: RpcMessage, C: Codec = BincodeCodec> {
     inner: Arc<HyperConnectionInner>,
+    codec: C,
     _p: PhantomData<(In, Out)>,
 }
 
-impl<In: RpcMessage, Out: RpcMessage> Clone for HyperConnector<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Clone for HyperConnector<In, Out, C> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            codec: self.codec.clone(),
             _p: PhantomData,
         }
     }
@@ -87,16 +103,31 @@ impl<In: RpcMessage, Out: RpcMessage> HyperConnector<In, Out> {
                 uri,
                 config,
             }),
+            codec: BincodeCodec,
             _p: PhantomData,
         }
     }
 }
 
-impl<In: RpcMessage, Out: RpcMessage> fmt::Debug for HyperConnector<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> HyperConnector<In, Out, C> {
+    /// Use a different [Codec] to serialize messages.
+    ///
+    /// The server needs to use the same codec, see [HyperListener::serve_with_codec].
+    pub fn with_codec<C2: Codec>(self, codec: C2) -> HyperConnector<In, Out, C2> {
+        HyperConnector {
+            inner: self.inner,
+            codec,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> fmt::Debug for HyperConnector<In, Out, C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ClientChannel")
             .field("uri", &self.inner.uri)
             .field("config", &self.inner.config)
+            .field("codec", &self.codec)
             .finish()
     }
 }
@@ -145,6 +176,11 @@ impl ChannelConfig {
     }
 
     /// Set the maximum payload size.
+    ///
+    /// This is the maximum size of a single serialized message, in both directions.
+    /// Sending a larger message fails with [SendError::FrameTooLarge] before anything is
+    /// written. Receiving a larger message fails with [RecvError::FrameTooLarge] as soon as
+    /// the length prefix is read, and only the offending stream is reset.
     pub fn max_payload_size(mut self, value: usize) -> result::Result<Self, ChannelConfigError> {
         if !(4096..1024 * 1024 * 16).contains(&value) {
             return Err(ChannelConfigError::InvalidMaxPayloadSize(value));
@@ -172,11 +208,13 @@ impl Default for ChannelConfig {
 /// Creating this spawns a tokio task which runs the server, once dropped this task is shut
 /// down: no new connections will be accepted and existing channels will stop.
 #[derive(Debug)]
-pub struct HyperListener<In: RpcMessage, Out: RpcMessage> {
+pub struct HyperListener<In: RpcMessage, Out: RpcMessage, C: Codec = BincodeCodec> {
     /// The channel.
     channel: Receiver<InternalChannel<In>>,
     /// The configuration.
     config: Arc<ChannelConfig>,
+    /// The codec used to serialize messages.
+    codec: C,
     /// The sender to stop the server.
     ///
     /// We never send anything over this really, simply dropping it makes the receiver
@@ -199,7 +237,31 @@ impl<In: RpcMessage, Out: RpcMessage> HyperListener<In, Out> {
 
     /// Creates a server listening on the [`SocketAddr`] with a custom configuration.
     pub fn serve_with_config(addr: &SocketAddr, config: ChannelConfig) -> hyper::Result<Self> {
+        Self::serve_with_codec(addr, config, BincodeCodec)
+    }
+
+    /// Creates a listener without a server, together with a [HyperService] that hands
+    /// requests to it.
+    ///
+    /// See [HyperListener::service_with_codec].
+    pub fn service(config: ChannelConfig) -> (Self, HyperService<In>) {
+        Self::service_with_codec(config, BincodeCodec)
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> HyperListener<In, Out, C> {
+    /// Creates a server listening on the [`SocketAddr`] with a custom configuration,
+    /// serializing messages with the given [Codec].
+    ///
+    /// The clients need to use the same codec, see [HyperConnector::with_codec].
+    pub fn serve_with_codec(
+        addr: &SocketAddr,
+        config: ChannelConfig,
+        codec: C,
+    ) -> hyper::Result<Self> {
         let (accept_tx, accept_rx) = flume::bounded(32);
+        let max_payload_size = config.max_payload_size;
+        let service_codec = codec.clone();
 
         // The hyper "MakeService" which is called for each connection that is made to the
         // server.  It creates another Service which handles a single request.
@@ -209,10 +271,16 @@ impl<In: RpcMessage, Out: RpcMessage> HyperListener<In, Out> {
 
             // Need a new accept_tx to move to the future on every call of this FnMut.
             let accept_tx = accept_tx.clone();
+            let codec = service_codec.clone();
             async move {
                 let one_req_service = service_fn(move |req: Request<Body>| {
                     // This closure is an FnMut as well, so clone accept_tx once more.
-                    Self::handle_one_http2_request(req, accept_tx.clone())
+                    handle_one_http2_request(
+                        req,
+                        accept_tx.clone(),
+                        codec.clone(),
+                        max_payload_size,
+                    )
                 });
                 Ok::<_, Infallible>(one_req_service)
             }
@@ -239,46 +307,150 @@ impl<In: RpcMessage, Out: RpcMessage> HyperListener<In, Out> {
         Ok(Self {
             channel: accept_rx,
             config: Arc::new(config),
+            codec,
             stop_tx,
             local_addr: [LocalAddr::Socket(local_addr)],
             _p: PhantomData,
         })
     }
 
-    /// Handles a single HTTP2 request.
+    /// Creates a listener without a server, together with a [HyperService] that hands
+    /// requests to it, serializing messages with the given [Codec].
     ///
-    /// This creates the channels to communicate the (optionally streaming) request and
-    /// response and sends them to the [`ServerChannel`].
-    async fn handle_one_http2_request(
-        req: Request<Body>,
-        accept_tx: Sender<InternalChannel<In>>,
-    ) -> Result<Response<Body>, String> {
-        let (req_tx, req_rx) = flume::bounded::<result::Result<In, RecvError>>(32);
-        let (res_tx, res_rx) = flume::bounded::<io::Result<Bytes>>(32);
-        accept_tx
-            .send_async((req_rx, res_tx))
-            .await
-            .map_err(|_e| "unable to send")?;
+    /// This allows mounting the rpc endpoint in an existing hyper or axum server, e.g.
+    /// next to other routes. With axum this is
+    /// `Router::new().route_service("/rpc", service)`. The clients then need to use the
+    /// full uri of the route, e.g. `http://localhost:3000/rpc`.
+    ///
+    /// The server has to accept http2, since [HyperConnector] only speaks http2. The http2
+    /// settings of the [ChannelConfig] are up to the server, only the maximum payload size
+    /// is applied by the service. Since no socket is bound, [Listener::local_addr] returns
+    /// [LocalAddr::Mem].
+    pub fn service_with_codec(config: ChannelConfig, codec: C) -> (Self, HyperService<In, C>) {
+        let (accept_tx, accept_rx) = flume::bounded(32);
+        // there is no server to stop, the service stops once the listener is dropped
+        let (stop_tx, _) = mpsc::channel::<()>(1);
+        let service = HyperService {
+            accept_tx,
+            codec: codec.clone(),
+            max_payload_size: config.max_payload_size,
+        };
+        let listener = Self {
+            channel: accept_rx,
+            config: Arc::new(config),
+            codec,
+            stop_tx,
+            local_addr: [LocalAddr::Mem],
+            _p: PhantomData,
+        };
+        (listener, service)
+    }
+}
 
-        spawn_recv_forwarder(req.into_body(), req_tx);
-        // Create a response with the response body channel as the response body
-        let response = Response::builder()
-            .status(StatusCode::OK)
-            .body(Body::wrap_stream(res_rx.into_stream()))
-            .map_err(|_| "unable to set body")?;
-        Ok(response)
+/// A service that hands http2 requests to a [HyperListener]
+///
+/// Created using [HyperListener::service]. This implements [hyper::service::Service], which
+/// is the same trait as `tower::Service`, so it can be used as a route in axum or wrapped
+/// in tower middleware.
+///
+/// Once the listener is dropped, requests are answered with `503 Service Unavailable`.
+pub struct HyperService<In: RpcMessage, C: Codec = BincodeCodec> {
+    accept_tx: Sender<InternalChannel<In>>,
+    codec: C,
+    max_payload_size: usize,
+}
+
+impl<In: RpcMessage, C: Codec> Clone for HyperService<In, C> {
+    fn clone(&self) -> Self {
+        Self {
+            accept_tx: self.accept_tx.clone(),
+            codec: self.codec.clone(),
+            max_payload_size: self.max_payload_size,
+        }
+    }
+}
+
+impl<In: RpcMessage, C: Codec> fmt::Debug for HyperService<In, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HyperService")
+            .field("codec", &self.codec)
+            .field("max_payload_size", &self.max_payload_size)
+            .finish()
     }
 }
 
-fn try_get_length_prefixed(buf: &[u8]) -> Option<&[u8]> {
+impl<In: RpcMessage, C: Codec> Service<Request<Body>> for HyperService<In, C> {
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = result::Result<Response<Body>, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<result::Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let fut = handle_one_http2_request(
+            req,
+            self.accept_tx.clone(),
+            self.codec.clone(),
+            self.max_payload_size,
+        );
+        Box::pin(async move {
+            Ok(fut.await.unwrap_or_else(|cause| {
+                debug!("{}", cause);
+                let mut response = Response::new(Body::empty());
+                *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                response
+            }))
+        })
+    }
+}
+
+/// Handles a single HTTP2 request.
+///
+/// This creates the channels to communicate the (optionally streaming) request and
+/// response and sends them to the [`ServerChannel`].
+async fn handle_one_http2_request<In: RpcMessage, C: Codec>(
+    req: Request<Body>,
+    accept_tx: Sender<InternalChannel<In>>,
+    codec: C,
+    max_payload_size: usize,
+) -> Result<Response<Body>, String> {
+    let (req_tx, req_rx) = flume::bounded::<result::Result<In, RecvError>>(32);
+    let (res_tx, res_rx) = flume::bounded::<io::Result<Bytes>>(32);
+    accept_tx
+        .send_async((req_rx, res_tx))
+        .await
+        .map_err(|_e| "unable to send")?;
+
+    spawn_recv_forwarder(req.into_body(), req_tx, codec, max_payload_size);
+    // Create a response with the response body channel as the response body
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .body(Body::wrap_stream(res_rx.into_stream()))
+        .map_err(|_| "unable to set body")?;
+    Ok(response)
+}
+
+/// Get the first length prefixed frame from the buffer, if it is complete.
+///
+/// Fails as soon as the length prefix is available if the frame would be larger than `limit`.
+fn try_get_length_prefixed(buf: &[u8], limit: usize) -> result::Result<Option<&[u8]>, RecvError> {
     if buf.len() < 4 {
-        return None;
+        return Ok(None);
     }
     let len = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) as usize;
+    if len > limit {
+        return Err(RecvError::FrameTooLarge {
+            size: len,
+            limit,
+            backtrace: ErrorTrace::capture(),
+        });
+    }
     if buf.len() < 4 + len {
-        return None;
+        return Ok(None);
     }
-    Some(&buf[4..4 + len])
+    Ok(Some(&buf[4..4 + len]))
 }
 
 /// Try forward all frames as deserialized messages from the buffer to the sender.
@@ -289,14 +461,30 @@ fn try_get_length_prefixed(buf: &[u8]) -> Option<&[u8]> {
 /// Deserialization errors don't cause an error, they will be sent.
 /// On error the number of consumed bytes is not returned. There is nothing to do but
 /// to stop the forwarder since there is nowhere to forward to anymore.
-async fn try_forward_all<In: RpcMessage>(
+///
+/// A frame that exceeds `limit` is sent as an error, and then stops the forwarder as
+/// well, since the rest of the stream can not be framed anymore.
+async fn try_forward_all<In: RpcMessage, C: Codec>(
     buffer: &[u8],
     req_tx: &Sender<Result<In, RecvError>>,
+    codec: &C,
+    limit: usize,
 ) -> result::Result<usize, ()> {
     let mut sent = 0;
-    while let Some(msg) = try_get_length_prefixed(&buffer[sent..]) {
+    loop {
+        let msg = match try_get_length_prefixed(&buffer[sent..], limit) {
+            Ok(Some(msg)) => msg,
+            Ok(None) => break,
+            Err(cause) => {
+                debug!("{}", cause);
+                req_tx.send_async(Err(cause)).await.ok();
+                return Err(());
+            }
+        };
         sent += msg.len() + 4;
-        let item = bincode::deserialize::<In>(msg).map_err(RecvError::DeserializeError);
+        let item = codec
+            .decode::<In>(Bytes::copy_from_slice(msg))
+            .map_err(|cause| RecvError::DeserializeError(cause, ErrorTrace::capture()));
         if let Err(_cause) = req_tx.send_async(item).await {
             // The receiver is gone, so we can't send any more data.
             //
@@ -316,15 +504,18 @@ async fn try_forward_all<In: RpcMessage>(
 /// This task will read chunks from the network, split them into length prefixed
 /// frames, deserialize those frames, and send the result to the flume channel.
 ///
-/// If there is a network error or the flume channel closes or the request
-/// stream is simply ended this task will terminate.
+/// If there is a network error, the flume channel closes, a frame is larger than
+/// `limit` or the request stream is simply ended this task will terminate. Dropping
+/// the body resets just this http2 stream.
 ///
 /// So it is fine to ignore the returned [`JoinHandle`].
 ///
 /// The HTTP2 request comes from *req* and the data is sent to `req_tx`.
-fn spawn_recv_forwarder<In: RpcMessage>(
+fn spawn_recv_forwarder<In: RpcMessage, C: Codec>(
     req: Body,
     req_tx: Sender<result::Result<In, RecvError>>,
+    codec: C,
+    limit: usize,
 ) -> JoinHandle<result::Result<(), ()>> {
     tokio::spawn(async move {
         let mut stream = req;
@@ -336,7 +527,7 @@ fn spawn_recv_forwarder<In: RpcMessage>(
                     event!(Level::TRACE, "Server got {} bytes", chunk.len());
                     if buf.is_empty() {
                         // try to forward directly from buffer
-                        let sent = try_forward_all(chunk, &req_tx).await?;
+                        let sent = try_forward_all(chunk, &req_tx, &codec, limit).await?;
                         // add just the rest, if any
                         buf.extend_from_slice(&chunk[sent..]);
                     } else {
@@ -352,7 +543,7 @@ fn spawn_recv_forwarder<In: RpcMessage>(
                     break;
                 }
             };
-            let sent = try_forward_all(&buf, &req_tx).await?;
+            let sent = try_forward_all(&buf, &req_tx, &codec, limit).await?;
             // remove the forwarded bytes.
             // Frequently this will be the entire buffer, so no memcpy but just set the size to 0
             buf.drain(..sent);
@@ -364,13 +555,14 @@ fn spawn_recv_forwarder<In: RpcMessage>(
 // This does not want or need RpcMessage to be clone but still want to clone the
 // ServerChannel and it's containing channels itself.  The derive macro can't cope with this
 // so this needs to be written by hand.
-impl<In: RpcMessage, Out: RpcMessage> Clone for HyperListener<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Clone for HyperListener<In, Out, C> {
     fn clone(&self) -> Self {
         Self {
             channel: self.channel.clone(),
             stop_tx: self.stop_tx.clone(),
             local_addr: self.local_addr.clone(),
             config: self.config.clone(),
+            codec: self.codec.clone(),
             _p: PhantomData,
         }
     }
@@ -416,30 +608,40 @@ impl<Res: RpcMessage> Stream for RecvStream<Res> {
 }
 
 /// Send sink for hyper channels
-pub struct SendSink<Out: RpcMessage> {
+pub struct SendSink<Out: RpcMessage, C: Codec = BincodeCodec> {
     sink: flume::r#async::SendSink<'static, io::Result<Bytes>>,
     config: Arc<ChannelConfig>,
+    codec: C,
     _p: PhantomData<Out>,
 }
 
-impl<Out: RpcMessage> SendSink<Out> {
-    fn new(sender: flume::Sender<io::Result<Bytes>>, config: Arc<ChannelConfig>) -> Self {
+impl<Out: RpcMessage, C: Codec> SendSink<Out, C> {
+    fn new(sender: flume::Sender<io::Result<Bytes>>, config: Arc<ChannelConfig>, codec: C) -> Self {
         Self {
             sink: sender.into_sink(),
             config,
+            codec,
             _p: PhantomData,
         }
     }
     fn serialize(&self, item: Out) -> Result<Bytes, SendError> {
-        let mut data = Vec::with_capacity(1024);
-        data.extend_from_slice(&[0u8; 4]);
-        bincode::serialize_into(&mut data, &item).map_err(SendError::SerializeError)?;
-        let len = data.len() - 4;
-        if len > self.config.max_payload_size {
-            return Err(SendError::SizeError(len));
+        let payload = self
+            .codec
+            .encode(&item)
+            .map_err(|cause| SendError::SerializeError(cause, ErrorTrace::capture()))?;
+        let len = payload.len();
+        let limit = self.config.max_payload_size;
+        if len > limit {
+            return Err(SendError::FrameTooLarge {
+                size: len,
+                limit,
+                backtrace: ErrorTrace::capture(),
+            });
         }
         let len: u32 = len.try_into().expect("max_payload_size fits into u32");
-        data[0..4].copy_from_slice(&len.to_be_bytes());
+        let mut data = Vec::with_capacity(4 + payload.len());
+        data.extend_from_slice(&len.to_be_bytes());
+        data.extend_from_slice(&payload);
         Ok(data.into())
     }
 
@@ -452,7 +654,7 @@ impl<Out: RpcMessage> SendSink<Out> {
     }
 }
 
-impl<Out: RpcMessage> Sink<Out> for SendSink<Out> {
+impl<Out: RpcMessage, C: Codec> Sink<Out> for SendSink<Out, C> {
     type Error = SendError;
 
     fn poll_ready(
@@ -461,7 +663,7 @@ impl<Out: RpcMessage> Sink<Out> for SendSink<Out> {
     ) -> Poll<Result<(), Self::Error>> {
         Pin::new(&mut self.sink)
             .poll_ready(cx)
-            .map_err(|_| SendError::ReceiverDropped)
+            .map_err(|_| SendError::ReceiverDropped(ErrorTrace::capture()))
     }
 
     fn start_send(mut self: Pin<&mut Self>, item: Out) -> Result<(), Self::Error> {
@@ -476,7 +678,7 @@ impl<Out: RpcMessage> Sink<Out> for SendSink<Out> {
         // attempt sending
         Pin::new(&mut self.sink)
             .start_send(send)
-            .map_err(|_| SendError::ReceiverDropped)?;
+            .map_err(|_| SendError::ReceiverDropped(ErrorTrace::capture()))?;
         res
     }
 
@@ -486,7 +688,7 @@ impl<Out: RpcMessage> Sink<Out> for SendSink<Out> {
     ) -> Poll<Result<(), Self::Error>> {
         Pin::new(&mut self.sink)
             .poll_flush(cx)
-            .map_err(|_| SendError::ReceiverDropped)
+            .map_err(|_| SendError::ReceiverDropped(ErrorTrace::capture()))
     }
 
     fn poll_close(
@@ -495,45 +697,122 @@ impl<Out: RpcMessage> Sink<Out> for SendSink<Out> {
     ) -> Poll<Result<(), Self::Error>> {
         Pin::new(&mut self.sink)
             .poll_close(cx)
-            .map_err(|_| SendError::ReceiverDropped)
+            .map_err(|_| SendError::ReceiverDropped(ErrorTrace::capture()))
     }
 }
 
 /// Send error for hyper channels.
+///
+/// Every variant has an [ErrorTrace] of where it was constructed, see
+/// [ErrorBacktrace].
 #[derive(Debug)]
 pub enum SendError {
-    /// Error when bincode serializing the message.
-    SerializeError(bincode::Error),
-    /// The message is too large to be sent.
-    SizeError(usize),
+    /// Error when serializing the message.
+    SerializeError(io::Error, ErrorTrace),
+    /// The message is larger than the maximum payload size.
+    FrameTooLarge {
+        /// Size of the serialized message
+        size: usize,
+        /// Maximum payload size
+        limit: usize,
+        /// Where the error was constructed
+        backtrace: ErrorTrace,
+    },
     /// The connection has been closed.
-    ReceiverDropped,
+    ReceiverDropped(ErrorTrace),
 }
 
 impl fmt::Display for SendError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(&self, f)
+        match self {
+            SendError::SerializeError(cause, _) => write!(f, "serialize error: {cause}"),
+            SendError::FrameTooLarge { size, limit, .. } => write!(
+                f,
+                "message of {size} bytes exceeds the limit of {limit} bytes"
+            ),
+            SendError::ReceiverDropped(_) => f.write_str("receiver dropped"),
+        }
     }
 }
 
-impl error::Error for SendError {}
+impl error::Error for SendError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            SendError::SerializeError(cause, _) => Some(cause),
+            _ => None,
+        }
+    }
+}
+
+impl ErrorBacktrace for SendError {
+    fn backtrace(&self) -> Option<&std::backtrace::Backtrace> {
+        match self {
+            SendError::SerializeError(_, trace)
+            | SendError::FrameTooLarge {
+                backtrace: trace, ..
+            }
+            | SendError::ReceiverDropped(trace) => trace.backtrace(),
+        }
+    }
+}
 
 /// Receive error for hyper channels.
+///
+/// Every variant has an [ErrorTrace] of where it was constructed, see
+/// [ErrorBacktrace].
 #[derive(Debug)]
 pub enum RecvError {
-    /// Error when bincode deserializing the message.
-    DeserializeError(bincode::Error),
+    /// Error when deserializing the message.
+    DeserializeError(io::Error, ErrorTrace),
     /// Hyper network error.
-    NetworkError(hyper::Error),
+    NetworkError(hyper::Error, ErrorTrace),
+    /// The length prefix of a received message exceeds the maximum payload size.
+    ///
+    /// The stream is reset, other streams on the same connection are not affected.
+    FrameTooLarge {
+        /// Size of the message according to its length prefix
+        size: usize,
+        /// Maximum payload size
+        limit: usize,
+        /// Where the error was constructed
+        backtrace: ErrorTrace,
+    },
 }
 
 impl fmt::Display for RecvError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        fmt::Debug::fmt(&self, f)
+        match self {
+            RecvError::DeserializeError(cause, _) => write!(f, "deserialize error: {cause}"),
+            RecvError::NetworkError(cause, _) => write!(f, "network error: {cause}"),
+            RecvError::FrameTooLarge { size, limit, .. } => write!(
+                f,
+                "message of {size} bytes exceeds the limit of {limit} bytes"
+            ),
+        }
+    }
+}
+
+impl error::Error for RecvError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RecvError::DeserializeError(cause, _) => Some(cause),
+            RecvError::NetworkError(cause, _) => Some(cause),
+            RecvError::FrameTooLarge { .. } => None,
+        }
     }
 }
 
-impl error::Error for RecvError {}
+impl ErrorBacktrace for RecvError {
+    fn backtrace(&self) -> Option<&std::backtrace::Backtrace> {
+        match self {
+            RecvError::DeserializeError(_, trace)
+            | RecvError::NetworkError(_, trace)
+            | RecvError::FrameTooLarge {
+                backtrace: trace, ..
+            } => trace.backtrace(),
+        }
+    }
+}
 
 /// OpenError for hyper channels.
 #[derive(Debug)]
@@ -573,7 +852,7 @@ impl fmt::Display for AcceptError {
 
 impl error::Error for AcceptError {}
 
-impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for HyperConnector<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> ConnectionErrors for HyperConnector<In, Out, C> {
     type SendError = self::SendError;
 
     type RecvError = self::RecvError;
@@ -581,16 +860,23 @@ impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for HyperConnector<In, Ou
     type OpenError = OpenError;
 
     type AcceptError = AcceptError;
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        matches!(
+            error,
+            RecvError::DeserializeError(e, _) if super::codec::unknown_message(e).is_some()
+        )
+    }
 }
 
-impl<In: RpcMessage, Out: RpcMessage> StreamTypes for HyperConnector<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> StreamTypes for HyperConnector<In, Out, C> {
     type In = In;
     type Out = Out;
     type RecvStream = self::RecvStream<In>;
-    type SendSink = self::SendSink<Out>;
+    type SendSink = self::SendSink<Out, C>;
 }
 
-impl<In: RpcMessage, Out: RpcMessage> Connector for HyperConnector<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Connector for HyperConnector<In, Out, C> {
     async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
         let (out_tx, out_rx) = flume::bounded::<io::Result<Bytes>>(32);
         let req: Request<Body> = Request::post(&self.inner.uri)
@@ -603,29 +889,41 @@ impl<In: RpcMessage, Out: RpcMessage> Connector for HyperConnector<In, Out> {
             .await
             .map_err(OpenError::Hyper)?;
         let (in_tx, in_rx) = flume::bounded::<result::Result<In, RecvError>>(32);
-        spawn_recv_forwarder(res.into_body(), in_tx);
-
-        let out_tx = self::SendSink::new(out_tx, self.inner.config.clone());
+        spawn_recv_forwarder(
+            res.into_body(),
+            in_tx,
+            self.codec.clone(),
+            self.inner.config.max_payload_size,
+        );
+
+        let out_tx = self::SendSink::new(out_tx, self.inner.config.clone(), self.codec.clone());
         let in_rx = self::RecvStream::new(in_rx);
         Ok((out_tx, in_rx))
     }
 }
 
-impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for HyperListener<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> ConnectionErrors for HyperListener<In, Out, C> {
     type SendError = self::SendError;
     type RecvError = self::RecvError;
     type OpenError = AcceptError;
     type AcceptError = AcceptError;
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        matches!(
+            error,
+            RecvError::DeserializeError(e, _) if super::codec::unknown_message(e).is_some()
+        )
+    }
 }
 
-impl<In: RpcMessage, Out: RpcMessage> StreamTypes for HyperListener<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> StreamTypes for HyperListener<In, Out, C> {
     type In = In;
     type Out = Out;
     type RecvStream = self::RecvStream<In>;
-    type SendSink = self::SendSink<Out>;
+    type SendSink = self::SendSink<Out, C>;
 }
 
-impl<In: RpcMessage, Out: RpcMessage> Listener for HyperListener<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Listener for HyperListener<In, Out, C> {
     fn local_addr(&self) -> &[LocalAddr] {
         &self.local_addr
     }
@@ -637,7 +935,7 @@ impl<In: RpcMessage, Out: RpcMessage> Listener for HyperListener<In, Out> {
             .await
             .map_err(|_| AcceptError::RemoteDropped)?;
         Ok((
-            SendSink::new(send, self.config.clone()),
+            SendSink::new(send, self.config.clone(), self.codec.clone()),
             RecvStream::new(recv),
         ))
     }
@@ -11,6 +11,7 @@ use std::{
     pin::{pin, Pin},
     sync::Arc,
     task::{Context, Poll},
+    time::SystemTime,
 };
 
 use flume::TryRecvError;
@@ -23,15 +24,18 @@ use serde::{de::DeserializeOwned, Serialize};
 use tracing::{debug_span, Instrument};
 
 use super::{
-    util::{FramedBincodeRead, FramedBincodeWrite},
-    StreamTypes,
+    codec::BincodeCodec,
+    lifecycle::{ConnectionObserver, LifecycleEvent, LifecycleObserver},
+    protocol::MAX_FRAME_LENGTH,
+    util::{self, FramedCodecRead, FramedCodecWrite},
+    RawStreamTypes, StreamTypes,
 };
 use crate::{
     transport::{ConnectionErrors, Connector, Listener, LocalAddr},
     RpcMessage,
 };
 
-const MAX_FRAME_LENGTH: usize = 1024 * 1024 * 16;
+pub use super::util::{RawRecvStream, RawSendSink, CANCEL_CODE};
 
 #[derive(Debug)]
 struct ListenerInner {
@@ -39,6 +43,8 @@ struct ListenerInner {
     task: Option<tokio::task::JoinHandle<()>>,
     local_addr: Vec<LocalAddr>,
     receiver: flume::Receiver<SocketInner>,
+    /// Reports connections, see [Listener::observe_connections]
+    lifecycle: Arc<ConnectionObserver>,
 }
 
 impl Drop for ListenerInner {
@@ -87,7 +93,17 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
     /// handles RPC requests from a connection
     ///
     /// to cleanly shut down the handler, drop the receiver side of the sender.
-    async fn connection_handler(connection: quinn::Connection, sender: flume::Sender<SocketInner>) {
+    async fn connection_handler(
+        connection: quinn::Connection,
+        sender: flume::Sender<SocketInner>,
+        lifecycle: Arc<ConnectionObserver>,
+    ) {
+        let id = connection.stable_id() as u64;
+        lifecycle.emit(|| LifecycleEvent::ConnectionOpened {
+            time: SystemTime::now(),
+            connection: id,
+            remote: connection.remote_address(),
+        });
         loop {
             tracing::debug!("Awaiting incoming bidi substream on existing connection...");
             let bidi_stream = match connection.accept_bi().await {
@@ -107,12 +123,24 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
                 break;
             }
         }
+        lifecycle.emit(|| LifecycleEvent::ConnectionClosed {
+            time: SystemTime::now(),
+            connection: id,
+            remote: connection.remote_address(),
+            error: match connection.close_reason() {
+                None
+                | Some(quinn::ConnectionError::ApplicationClosed(_))
+                | Some(quinn::ConnectionError::LocallyClosed) => None,
+                Some(cause) => Some(cause.to_string()),
+            },
+        });
     }
 
     async fn endpoint_handler(
         endpoint: iroh_net::Endpoint,
         sender: flume::Sender<SocketInner>,
         allowed_node_ids: BTreeSet<NodeId>,
+        lifecycle: Arc<ConnectionObserver>,
     ) {
         loop {
             tracing::debug!("Waiting for incoming connection...");
@@ -160,7 +188,11 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
             );
 
             tracing::debug!("Spawning connection handler...");
-            tokio::spawn(Self::connection_handler(connection, sender.clone()));
+            tokio::spawn(Self::connection_handler(
+                connection,
+                sender.clone(),
+                lifecycle.clone(),
+            ));
         }
     }
 
@@ -193,10 +225,12 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
 
         let (ipv4_socket_addr, maybe_ipv6_socket_addr) = endpoint.bound_sockets();
         let (sender, receiver) = flume::bounded(16);
+        let lifecycle = Arc::new(ConnectionObserver::default());
         let task = tokio::spawn(Self::endpoint_handler(
             endpoint.clone(),
             sender,
             allowed_node_ids,
+            lifecycle.clone(),
         ));
 
         Ok(Self {
@@ -207,6 +241,7 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
                     .chain(maybe_ipv6_socket_addr.map(LocalAddr::Socket))
                     .collect(),
                 receiver,
+                lifecycle,
             }),
             _p: PhantomData,
         })
@@ -221,10 +256,16 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
         local_addr: SocketAddr,
     ) -> Self {
         let (sender, receiver) = flume::bounded(16);
+        let lifecycle = Arc::new(ConnectionObserver::default());
+        let observer = lifecycle.clone();
         let task = tokio::spawn(async move {
             // just grab all connections and spawn a handler for each one
             while let Ok(connection) = incoming.recv_async().await {
-                tokio::spawn(Self::connection_handler(connection, sender.clone()));
+                tokio::spawn(Self::connection_handler(
+                    connection,
+                    sender.clone(),
+                    observer.clone(),
+                ));
             }
         });
         Self {
@@ -233,6 +274,7 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
                 task: Some(task),
                 local_addr: vec![LocalAddr::Socket(local_addr)],
                 receiver,
+                lifecycle,
             }),
             _p: PhantomData,
         }
@@ -252,6 +294,7 @@ impl<In: RpcMessage, Out: RpcMessage> IrohNetListener<In, Out> {
                 task: None,
                 local_addr: vec![LocalAddr::Socket(local_addr)],
                 receiver,
+                lifecycle: Default::default(),
             }),
             _p: PhantomData,
         }
@@ -272,6 +315,22 @@ impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for IrohNetListener<In, O
     type RecvError = io::Error;
     type OpenError = quinn::ConnectionError;
     type AcceptError = quinn::ConnectionError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        util::is_stopped(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        util::is_clean_close(error)
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        util::is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        util::is_cancelled(error)
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> StreamTypes for IrohNetListener<In, Out> {
@@ -279,6 +338,26 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for IrohNetListener<In, Out> {
     type Out = Out;
     type SendSink = SendSink<Out>;
     type RecvStream = RecvStream<In>;
+
+    fn send_closed(send: &Self::SendSink) -> impl Future<Output = ()> + Send + 'static {
+        util::send_closed(&send.0)
+    }
+
+    fn cancel(send: &mut Self::SendSink) {
+        send.cancel()
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage> RawStreamTypes for IrohNetListener<In, Out> {
+    type RawRecvStream = self::RawRecvStream<quinn::RecvStream>;
+    type RawSendSink = self::RawSendSink<quinn::SendStream>;
+
+    fn into_raw(
+        send: Self::SendSink,
+        recv: Self::RecvStream,
+    ) -> (Self::RawSendSink, Self::RawRecvStream) {
+        (send.take_framed().into_raw(), recv.0.into_raw())
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> Listener for IrohNetListener<In, Out> {
@@ -296,6 +375,13 @@ impl<In: RpcMessage, Out: RpcMessage> Listener for IrohNetListener<In, Out> {
     fn local_addr(&self) -> &[LocalAddr] {
         &self.inner.local_addr
     }
+
+    /// The id of a connection is its [quinn::Connection::stable_id]. The observer applies
+    /// to all clones of the listener, including connections that are already open. A
+    /// listener made with [IrohNetListener::handle_substreams] never reports connections.
+    fn observe_connections(&self, observer: LifecycleObserver) {
+        self.inner.lifecycle.set(observer);
+    }
 }
 
 type SocketInner = (quinn::SendStream, quinn::RecvStream);
@@ -630,6 +716,22 @@ impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for IrohNetConnector<In,
     type RecvError = io::Error;
     type OpenError = anyhow::Error;
     type AcceptError = anyhow::Error;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        util::is_stopped(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        util::is_clean_close(error)
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        util::is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        util::is_cancelled(error)
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> StreamTypes for IrohNetConnector<In, Out> {
@@ -637,6 +739,26 @@ impl<In: RpcMessage, Out: RpcMessage> StreamTypes for IrohNetConnector<In, Out>
     type Out = Out;
     type SendSink = SendSink<Out>;
     type RecvStream = RecvStream<In>;
+
+    fn send_closed(send: &Self::SendSink) -> impl Future<Output = ()> + Send + 'static {
+        util::send_closed(&send.0)
+    }
+
+    fn cancel(send: &mut Self::SendSink) {
+        send.cancel()
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage> RawStreamTypes for IrohNetConnector<In, Out> {
+    type RawRecvStream = self::RawRecvStream<quinn::RecvStream>;
+    type RawSendSink = self::RawSendSink<quinn::SendStream>;
+
+    fn into_raw(
+        send: Self::SendSink,
+        recv: Self::RecvStream,
+    ) -> (Self::RawSendSink, Self::RawRecvStream) {
+        (send.take_framed().into_raw(), recv.0.into_raw())
+    }
 }
 
 impl<In: RpcMessage, Out: RpcMessage> Connector for IrohNetConnector<In, Out> {
@@ -659,10 +781,16 @@ impl<In: RpcMessage, Out: RpcMessage> Connector for IrohNetConnector<In, Out> {
 
 /// A sink that wraps a quinn SendStream with length delimiting and bincode
 ///
+/// Closing the sink finishes the stream, and only completes once the remote has
+/// acknowledged all data.
+///
 /// If you want to send bytes directly, use [SendSink::into_inner] to get the
 /// underlying [quinn::SendStream].
-#[pin_project]
-pub struct SendSink<Out>(#[pin] FramedBincodeWrite<quinn::SendStream, Out>);
+pub struct SendSink<Out>(
+    /// Only empty once taken when the sink is consumed
+    util::SharedSend<FramedCodecWrite<quinn::SendStream, Out, BincodeCodec>>,
+    util::Acknowledged,
+);
 
 impl<Out> fmt::Debug for SendSink<Out> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -672,8 +800,8 @@ impl<Out> fmt::Debug for SendSink<Out> {
 
 impl<Out: Serialize> SendSink<Out> {
     fn new(inner: quinn::SendStream) -> Self {
-        let inner = FramedBincodeWrite::new(inner, MAX_FRAME_LENGTH);
-        Self(inner)
+        let inner = FramedCodecWrite::new(inner, BincodeCodec, MAX_FRAME_LENGTH);
+        Self(util::SharedSend::new(inner), Default::default())
     }
 }
 
@@ -681,27 +809,47 @@ impl<Out> SendSink<Out> {
     /// Get the underlying [quinn::SendStream], which implements
     /// [tokio::io::AsyncWrite] and can be used to send bytes directly.
     pub fn into_inner(self) -> quinn::SendStream {
-        self.0.into_inner()
+        self.take_framed().into_inner()
+    }
+
+    fn take_framed(self) -> FramedCodecWrite<quinn::SendStream, Out, BincodeCodec> {
+        self.0.take().expect("only taken when consumed")
+    }
+
+    /// Reset the stream with [CANCEL_CODE], see [StreamTypes::cancel]
+    fn cancel(&mut self) {
+        self.0.with(|framed| {
+            Pin::new(framed)
+                .get_pin_mut()
+                .get_mut()
+                .reset(CANCEL_CODE)
+                .ok()
+        });
     }
 }
 
-impl<Out: Serialize> Sink<Out> for SendSink<Out> {
+impl<Out: Serialize + Unpin> Sink<Out> for SendSink<Out> {
     type Error = io::Error;
 
     fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_ready(cx)
+        self.0.with(|framed| Pin::new(framed).poll_ready(cx))
     }
 
     fn start_send(self: Pin<&mut Self>, item: Out) -> Result<(), Self::Error> {
-        Pin::new(&mut self.project().0).start_send(item)
+        self.0.with(|framed| Pin::new(framed).start_send(item))
     }
 
     fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_flush(cx)
+        self.0.with(|framed| Pin::new(framed).poll_flush(cx))
     }
 
     fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_close(cx)
+        let this = self.get_mut();
+        let acknowledged = &mut this.1;
+        this.0.with(|framed| {
+            std::task::ready!(Pin::new(&mut *framed).poll_close(cx))?;
+            acknowledged.poll(Pin::new(framed).get_pin_mut().get_mut(), cx)
+        })
     }
 }
 
@@ -710,7 +858,7 @@ impl<Out: Serialize> Sink<Out> for SendSink<Out> {
 /// If you want to receive bytes directly, use [RecvStream::into_inner] to get
 /// the underlying [quinn::RecvStream].
 #[pin_project]
-pub struct RecvStream<In>(#[pin] FramedBincodeRead<quinn::RecvStream, In>);
+pub struct RecvStream<In>(#[pin] FramedCodecRead<quinn::RecvStream, In, BincodeCodec>);
 
 impl<In> fmt::Debug for RecvStream<In> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -720,7 +868,7 @@ impl<In> fmt::Debug for RecvStream<In> {
 
 impl<In: DeserializeOwned> RecvStream<In> {
     fn new(inner: quinn::RecvStream) -> Self {
-        let inner = FramedBincodeRead::new(inner, MAX_FRAME_LENGTH);
+        let inner = FramedCodecRead::new(inner, BincodeCodec, MAX_FRAME_LENGTH);
         Self(inner)
     }
 }
@@ -0,0 +1,540 @@
+//! Lifecycle events of connections and streams, for debugging.
+//!
+//! [LifecycleListener] and [LifecycleConnector] wrap another transport and call a
+//! [LifecycleObserver] with a [LifecycleEvent] whenever a stream is opened, closed or
+//! reset. They are usually added with
+//! [RpcServer::with_lifecycle_observer](crate::RpcServer::with_lifecycle_observer) and
+//! [RpcClient::with_lifecycle_observer](crate::RpcClient::with_lifecycle_observer).
+//!
+//! A stream ends when both of its halves are dropped. It is reported as reset if
+//! sending or receiving failed before, with the first error, and as closed otherwise.
+//! Every stream gets an id that is unique within the process, so the events of a stream
+//! can be correlated, and events of different streams can be correlated by the address
+//! of the remote.
+//!
+//! Connections are reported by the listeners that multiplex streams over them, see
+//! [Listener::observe_connections]: the quinn, iroh-net and tcp listeners. Every uds or
+//! websocket connection carries a single stream, the hyper listener leaves its
+//! connections to hyper, and the in-memory transports have no connections, so they
+//! report streams only.
+//!
+//! The events are meant for debugging, they are lower level than the
+//! [metrics](crate::metrics) and more structured than logs. A transport that is not
+//! wrapped does not check for an observer, so there is no overhead without one.
+use std::{
+    fmt,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+
+use futures_lite::{Stream, StreamExt};
+use futures_sink::Sink;
+
+use super::{
+    metadata::Metadata, ConnectionErrors, ConnectionStats, Connector, Listener, LocalAddr,
+    MetadataError, PingError, PriorityError, RemoteInfo, StreamTypes,
+};
+
+/// An event in the life of a connection or a stream, see the [module docs](self)
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LifecycleEvent {
+    /// A connection was established
+    ConnectionOpened {
+        /// When the event happened
+        time: SystemTime,
+        /// The id of the connection, unique within the listener
+        connection: u64,
+        /// The address of the remote
+        remote: SocketAddr,
+    },
+    /// A connection was closed, or the listener stopped handling it
+    ConnectionClosed {
+        /// When the event happened
+        time: SystemTime,
+        /// The id of the connection, as in [LifecycleEvent::ConnectionOpened]
+        connection: u64,
+        /// The address of the remote
+        remote: SocketAddr,
+        /// Why the connection was closed, if it was closed by an error
+        error: Option<String>,
+    },
+    /// A stream was opened by this side or accepted from the remote
+    StreamOpened {
+        /// When the event happened
+        time: SystemTime,
+        /// The id of the stream, unique within the process
+        stream: u64,
+        /// The address of the remote, if the transport knows it
+        remote: Option<SocketAddr>,
+    },
+    /// A stream ended without an error
+    StreamClosed {
+        /// When the event happened
+        time: SystemTime,
+        /// The id of the stream, as in [LifecycleEvent::StreamOpened]
+        stream: u64,
+    },
+    /// A stream ended after sending or receiving failed
+    StreamReset {
+        /// When the event happened
+        time: SystemTime,
+        /// The id of the stream, as in [LifecycleEvent::StreamOpened]
+        stream: u64,
+        /// The first error of the stream
+        error: String,
+    },
+}
+
+impl LifecycleEvent {
+    /// When the event happened
+    pub fn time(&self) -> SystemTime {
+        match self {
+            Self::ConnectionOpened { time, .. }
+            | Self::ConnectionClosed { time, .. }
+            | Self::StreamOpened { time, .. }
+            | Self::StreamClosed { time, .. }
+            | Self::StreamReset { time, .. } => *time,
+        }
+    }
+}
+
+/// A callback for [LifecycleEvent]s
+///
+/// The callback runs on the task that caused the event, so it should return quickly,
+/// e.g. by logging the event or sending it to a channel.
+#[derive(Clone)]
+pub struct LifecycleObserver(Arc<dyn Fn(LifecycleEvent) + Send + Sync>);
+
+impl fmt::Debug for LifecycleObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("LifecycleObserver").finish()
+    }
+}
+
+impl LifecycleObserver {
+    /// Create an observer from a callback
+    pub fn new(on_event: impl Fn(LifecycleEvent) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(on_event))
+    }
+
+    fn emit(&self, event: LifecycleEvent) {
+        (self.0)(event)
+    }
+
+    /// Report a new stream, the returned state reports its end
+    fn open_stream(&self, remote: Option<SocketAddr>) -> Arc<StreamState> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        self.emit(LifecycleEvent::StreamOpened {
+            time: SystemTime::now(),
+            stream: id,
+            remote,
+        });
+        Arc::new(StreamState {
+            observer: self.clone(),
+            id,
+            error: Mutex::new(None),
+        })
+    }
+}
+
+/// The observer of the connections of a transport, which can be set at any time
+#[cfg(any(
+    feature = "quinn-transport",
+    feature = "iroh-net-transport",
+    feature = "tcp-transport"
+))]
+#[derive(Debug, Default)]
+pub(crate) struct ConnectionObserver(Mutex<Option<LifecycleObserver>>);
+
+#[cfg(any(
+    feature = "quinn-transport",
+    feature = "iroh-net-transport",
+    feature = "tcp-transport"
+))]
+impl ConnectionObserver {
+    pub(crate) fn set(&self, observer: LifecycleObserver) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some(observer);
+    }
+
+    /// Report the event created by `event`, which is only called if there is an observer
+    pub(crate) fn emit(&self, event: impl FnOnce() -> LifecycleEvent) {
+        let observer = self
+            .0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        if let Some(observer) = observer {
+            observer.emit(event());
+        }
+    }
+}
+
+/// The state of a stream, shared by its halves, that reports the end of the stream when
+/// both are dropped
+struct StreamState {
+    observer: LifecycleObserver,
+    id: u64,
+    /// The first error of the stream
+    error: Mutex<Option<String>>,
+}
+
+impl StreamState {
+    fn fail(&self, cause: &impl fmt::Display) {
+        let mut error = self.error.lock().unwrap_or_else(PoisonError::into_inner);
+        if error.is_none() {
+            *error = Some(cause.to_string());
+        }
+    }
+}
+
+impl Drop for StreamState {
+    fn drop(&mut self) {
+        let time = SystemTime::now();
+        let error = self.error.get_mut().unwrap_or_else(PoisonError::into_inner);
+        let event = match error.take() {
+            Some(error) => LifecycleEvent::StreamReset {
+                time,
+                stream: self.id,
+                error,
+            },
+            None => LifecycleEvent::StreamClosed {
+                time,
+                stream: self.id,
+            },
+        };
+        self.observer.emit(event);
+    }
+}
+
+/// A connector that reports the lifecycle of its streams, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct LifecycleConnector<C> {
+    inner: C,
+    observer: LifecycleObserver,
+}
+
+impl<C: Connector> LifecycleConnector<C> {
+    /// Report the streams of `inner` to `observer`
+    pub fn new(inner: C, observer: LifecycleObserver) -> Self {
+        Self { inner, observer }
+    }
+
+    /// The wrapped connector
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<C: ConnectionErrors> ConnectionErrors for LifecycleConnector<C> {
+    type SendError = C::SendError;
+    type RecvError = C::RecvError;
+    type OpenError = C::OpenError;
+    type AcceptError = C::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        C::is_remote_closed(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        C::is_clean_close(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        C::is_unknown_message(error)
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        C::is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        C::is_cancelled(error)
+    }
+}
+
+impl<C: StreamTypes> StreamTypes for LifecycleConnector<C> {
+    type In = C::In;
+    type Out = C::Out;
+    type SendSink = SendSink<C>;
+    type RecvStream = RecvStream<C>;
+
+    fn set_priority(send: &Self::SendSink, priority: i32) -> Result<(), PriorityError> {
+        C::set_priority(&send.inner, priority)
+    }
+
+    fn metadata(recv: &Self::RecvStream) -> Option<&Metadata> {
+        C::metadata(&recv.inner)
+    }
+
+    fn set_metadata(send: &Self::SendSink, metadata: Metadata) -> Result<(), MetadataError> {
+        C::set_metadata(&send.inner, metadata)
+    }
+
+    fn deadline(recv: &Self::RecvStream) -> Option<Instant> {
+        C::deadline(&recv.inner)
+    }
+
+    fn set_deadline(send: &Self::SendSink, timeout: Duration) -> Result<(), MetadataError> {
+        C::set_deadline(&send.inner, timeout)
+    }
+
+    fn send_closed(send: &Self::SendSink) -> impl Future<Output = ()> + Send + 'static {
+        C::send_closed(&send.inner)
+    }
+
+    fn cancel(send: &mut Self::SendSink) {
+        C::cancel(&mut send.inner)
+    }
+}
+
+impl<C: Connector> Connector for LifecycleConnector<C> {
+    async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let (send, recv) = self.inner.open().await?;
+        Ok(observe(&self.observer, send, recv, None))
+    }
+
+    async fn open_rpc(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let (send, recv) = self.inner.open_rpc().await?;
+        Ok(observe(&self.observer, send, recv, None))
+    }
+
+    fn stats(&self) -> Option<ConnectionStats> {
+        self.inner.stats()
+    }
+
+    fn ping(&self) -> impl Future<Output = Result<Duration, PingError>> + Send {
+        self.inner.ping()
+    }
+
+    async fn accept_push(&self) -> Option<(Self::SendSink, Self::RecvStream)> {
+        let (send, recv) = self.inner.accept_push().await?;
+        Some(observe(&self.observer, send, recv, None))
+    }
+}
+
+/// A listener that reports the lifecycle of its streams and connections, see the
+/// [module docs](self)
+#[derive(Debug, Clone)]
+pub struct LifecycleListener<L> {
+    inner: L,
+    observer: LifecycleObserver,
+}
+
+impl<L: Listener> LifecycleListener<L> {
+    /// Report the streams of `inner` to `observer`, and its connections if it supports
+    /// [Listener::observe_connections]
+    pub fn new(inner: L, observer: LifecycleObserver) -> Self {
+        inner.observe_connections(observer.clone());
+        Self { inner, observer }
+    }
+
+    /// The wrapped listener
+    ///
+    /// The listener keeps reporting its connections to the observer.
+    pub fn into_inner(self) -> L {
+        self.inner
+    }
+}
+
+impl<L: ConnectionErrors> ConnectionErrors for LifecycleListener<L> {
+    type SendError = L::SendError;
+    type RecvError = L::RecvError;
+    type OpenError = L::OpenError;
+    type AcceptError = L::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        L::is_remote_closed(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        L::is_clean_close(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        L::is_unknown_message(error)
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        L::is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        L::is_cancelled(error)
+    }
+}
+
+impl<L: StreamTypes> StreamTypes for LifecycleListener<L> {
+    type In = L::In;
+    type Out = L::Out;
+    type SendSink = SendSink<L>;
+    type RecvStream = RecvStream<L>;
+
+    fn set_priority(send: &Self::SendSink, priority: i32) -> Result<(), PriorityError> {
+        L::set_priority(&send.inner, priority)
+    }
+
+    fn metadata(recv: &Self::RecvStream) -> Option<&Metadata> {
+        L::metadata(&recv.inner)
+    }
+
+    fn set_metadata(send: &Self::SendSink, metadata: Metadata) -> Result<(), MetadataError> {
+        L::set_metadata(&send.inner, metadata)
+    }
+
+    fn deadline(recv: &Self::RecvStream) -> Option<Instant> {
+        L::deadline(&recv.inner)
+    }
+
+    fn set_deadline(send: &Self::SendSink, timeout: Duration) -> Result<(), MetadataError> {
+        L::set_deadline(&send.inner, timeout)
+    }
+
+    fn send_closed(send: &Self::SendSink) -> impl Future<Output = ()> + Send + 'static {
+        L::send_closed(&send.inner)
+    }
+
+    fn cancel(send: &mut Self::SendSink) {
+        L::cancel(&mut send.inner)
+    }
+}
+
+impl<L: Listener> Listener for LifecycleListener<L> {
+    async fn accept(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::AcceptError> {
+        let (send, recv) = self.inner.accept().await?;
+        let remote = L::remote_info(&recv).and_then(|info| info.addr);
+        Ok(observe(&self.observer, send, recv, remote))
+    }
+
+    fn local_addr(&self) -> &[LocalAddr] {
+        self.inner.local_addr()
+    }
+
+    fn remote_info(recv: &Self::RecvStream) -> Option<Arc<RemoteInfo>> {
+        L::remote_info(&recv.inner)
+    }
+
+    fn connection(&self, recv: &Self::RecvStream) -> Option<super::Connection<L::In, L::Out>> {
+        self.inner.connection(&recv.inner)
+    }
+
+    fn observe_connections(&self, observer: LifecycleObserver) {
+        self.inner.observe_connections(observer)
+    }
+}
+
+/// Wrap the halves of a new stream, and report it
+fn observe<C: StreamTypes>(
+    observer: &LifecycleObserver,
+    send: C::SendSink,
+    recv: C::RecvStream,
+    remote: Option<SocketAddr>,
+) -> (SendSink<C>, RecvStream<C>) {
+    let state = observer.open_stream(remote);
+    let send = SendSink {
+        inner: send,
+        state: state.clone(),
+    };
+    let recv = RecvStream { inner: recv, state };
+    (send, recv)
+}
+
+/// Send sink of a [LifecycleConnector] or [LifecycleListener] stream
+pub struct SendSink<C: StreamTypes> {
+    inner: C::SendSink,
+    state: Arc<StreamState>,
+}
+
+impl<C: StreamTypes> fmt::Debug for SendSink<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendSink")
+            .field("stream", &self.state.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: StreamTypes> SendSink<C> {
+    /// The id of the stream, as in [LifecycleEvent::StreamOpened]
+    pub fn stream_id(&self) -> u64 {
+        self.state.id
+    }
+
+    fn check<T>(&self, res: Result<T, C::SendError>) -> Result<T, C::SendError> {
+        if let Err(cause) = &res {
+            self.state.fail(cause);
+        }
+        res
+    }
+}
+
+impl<C: StreamTypes> Sink<C::Out> for SendSink<C> {
+    type Error = C::SendError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        let res = futures_lite::ready!(Pin::new(&mut this.inner).poll_ready(cx));
+        Poll::Ready(this.check(res))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: C::Out) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        let res = Pin::new(&mut this.inner).start_send(item);
+        this.check(res)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        let res = futures_lite::ready!(Pin::new(&mut this.inner).poll_flush(cx));
+        Poll::Ready(this.check(res))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        let res = futures_lite::ready!(Pin::new(&mut this.inner).poll_close(cx));
+        Poll::Ready(this.check(res))
+    }
+}
+
+/// Receive stream of a [LifecycleConnector] or [LifecycleListener] stream
+pub struct RecvStream<C: StreamTypes> {
+    inner: C::RecvStream,
+    state: Arc<StreamState>,
+}
+
+impl<C: StreamTypes> fmt::Debug for RecvStream<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecvStream")
+            .field("stream", &self.state.id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C: StreamTypes> RecvStream<C> {
+    /// The id of the stream, as in [LifecycleEvent::StreamOpened]
+    pub fn stream_id(&self) -> u64 {
+        self.state.id
+    }
+}
+
+impl<C: StreamTypes> Stream for RecvStream<C> {
+    type Item = Result<C::In, C::RecvError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let item = futures_lite::ready!(this.inner.poll_next(cx));
+        if let Some(Err(cause)) = &item {
+            if !C::is_clean_close(cause) {
+                this.state.fail(cause);
+            }
+        }
+        Poll::Ready(item)
+    }
+}
@@ -1,18 +1,30 @@
 //! Transport with mapped input and output types.
 use std::{
     fmt::{Debug, Display},
+    future::Future,
     marker::PhantomData,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use futures_lite::{Stream, StreamExt};
 use futures_util::SinkExt;
 use pin_project::pin_project;
 
-use super::{ConnectionErrors, Connector, StreamTypes};
+use super::{
+    metadata::Metadata, ConnectionErrors, ConnectionStats, Connector, MetadataError, PingError,
+    PriorityError, StreamTypes,
+};
 use crate::{RpcError, RpcMessage};
 
 /// A connection that maps input and output types
+///
+/// This allows using a connector for a broad service to talk to a narrower service
+/// whose messages are a subset, see [Connector::map].
+///
+/// Outgoing messages are converted using [From], which can not fail. Incoming messages
+/// are converted using [TryFrom]. A message that can not be converted is reported as
+/// [ErrorOrMapError::Conversion] by the receive stream.
 #[derive(Debug)]
 pub struct MappedConnector<In, Out, C> {
     inner: C,
@@ -56,6 +68,26 @@ where
     type SendError = C::SendError;
     type OpenError = C::OpenError;
     type AcceptError = C::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        C::is_remote_closed(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        matches!(error, ErrorOrMapError::Inner(e) if C::is_clean_close(e))
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        matches!(error, ErrorOrMapError::Inner(e) if C::is_unknown_message(e))
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        matches!(error, ErrorOrMapError::Inner(e) if C::is_reset(e))
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        matches!(error, ErrorOrMapError::Inner(e) if C::is_cancelled(e))
+    }
 }
 
 impl<In, Out, C> StreamTypes for MappedConnector<In, Out, C>
@@ -70,6 +102,34 @@ where
     type Out = Out;
     type RecvStream = MappedRecvStream<C::RecvStream, In>;
     type SendSink = MappedSendSink<C::SendSink, Out, C::Out>;
+
+    fn set_priority(send: &Self::SendSink, priority: i32) -> Result<(), PriorityError> {
+        C::set_priority(&send.inner, priority)
+    }
+
+    fn metadata(recv: &Self::RecvStream) -> Option<&Metadata> {
+        C::metadata(&recv.inner)
+    }
+
+    fn set_metadata(send: &Self::SendSink, metadata: Metadata) -> Result<(), MetadataError> {
+        C::set_metadata(&send.inner, metadata)
+    }
+
+    fn deadline(recv: &Self::RecvStream) -> Option<Instant> {
+        C::deadline(&recv.inner)
+    }
+
+    fn set_deadline(send: &Self::SendSink, timeout: Duration) -> Result<(), MetadataError> {
+        C::set_deadline(&send.inner, timeout)
+    }
+
+    fn send_closed(send: &Self::SendSink) -> impl Future<Output = ()> + Send + 'static {
+        C::send_closed(&send.inner)
+    }
+
+    fn cancel(send: &mut Self::SendSink) {
+        C::cancel(&mut send.inner)
+    }
 }
 
 impl<In, Out, C> Connector for MappedConnector<In, Out, C>
@@ -90,6 +150,35 @@ where
             Ok((MappedSendSink::new(send), MappedRecvStream::new(recv)))
         }
     }
+
+    fn open_rpc(
+        &self,
+    ) -> impl std::future::Future<Output = Result<(Self::SendSink, Self::RecvStream), Self::OpenError>>
+           + Send {
+        let inner = self.inner.open_rpc();
+        async move {
+            let (send, recv) = inner.await?;
+            Ok((MappedSendSink::new(send), MappedRecvStream::new(recv)))
+        }
+    }
+
+    fn stats(&self) -> Option<ConnectionStats> {
+        self.inner.stats()
+    }
+
+    fn ping(&self) -> impl Future<Output = Result<Duration, PingError>> + Send {
+        self.inner.ping()
+    }
+
+    fn accept_push(
+        &self,
+    ) -> impl Future<Output = Option<(Self::SendSink, Self::RecvStream)>> + Send {
+        let inner = self.inner.accept_push();
+        async move {
+            let (send, recv) = inner.await?;
+            Some((MappedSendSink::new(send), MappedRecvStream::new(recv)))
+        }
+    }
 }
 
 /// A combinator that maps a stream of incoming messages to a different type
@@ -168,6 +257,11 @@ impl<S, Out, Out0> MappedSendSink<S, Out, Out0> {
             _p: std::marker::PhantomData,
         }
     }
+
+    /// The sink of the outer message type
+    pub(crate) fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
 }
 
 impl<S, Out, Out0> futures_sink::Sink<Out> for MappedSendSink<S, Out, Out0>
@@ -228,6 +322,26 @@ where
     type SendError = C::SendError;
     type OpenError = C::OpenError;
     type AcceptError = C::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        C::is_remote_closed(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        matches!(error, ErrorOrMapError::Inner(e) if C::is_clean_close(e))
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        matches!(error, ErrorOrMapError::Inner(e) if C::is_unknown_message(e))
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        matches!(error, ErrorOrMapError::Inner(e) if C::is_reset(e))
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        matches!(error, ErrorOrMapError::Inner(e) if C::is_cancelled(e))
+    }
 }
 
 impl<In, Out, C> StreamTypes for MappedStreamTypes<In, Out, C>
@@ -242,6 +356,34 @@ where
     type Out = Out;
     type RecvStream = MappedRecvStream<C::RecvStream, In>;
     type SendSink = MappedSendSink<C::SendSink, Out, C::Out>;
+
+    fn set_priority(send: &Self::SendSink, priority: i32) -> Result<(), PriorityError> {
+        C::set_priority(&send.inner, priority)
+    }
+
+    fn metadata(recv: &Self::RecvStream) -> Option<&Metadata> {
+        C::metadata(&recv.inner)
+    }
+
+    fn set_metadata(send: &Self::SendSink, metadata: Metadata) -> Result<(), MetadataError> {
+        C::set_metadata(&send.inner, metadata)
+    }
+
+    fn deadline(recv: &Self::RecvStream) -> Option<Instant> {
+        C::deadline(&recv.inner)
+    }
+
+    fn set_deadline(send: &Self::SendSink, timeout: Duration) -> Result<(), MetadataError> {
+        C::set_deadline(&send.inner, timeout)
+    }
+
+    fn send_closed(send: &Self::SendSink) -> impl Future<Output = ()> + Send + 'static {
+        C::send_closed(&send.inner)
+    }
+
+    fn cancel(send: &mut Self::SendSink) {
+        C::cancel(&mut send.inner)
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,730 @@
+//! Transport that sends key/value metadata along with each channel.
+//!
+//! Metadata is what the application wants to tell the other side about a call besides
+//! the messages, e.g. an auth token, a request id or a tenant. It is sent as an extra
+//! [Frame] ahead of the first message of a channel, in both directions, and stripped
+//! by the receive side of the other end, so the rpc layer never sees it.
+//!
+//! On the client, [MetadataConnector::with_metadata] creates a connector whose channels
+//! carry the given metadata, so a single call is e.g.
+//! `client.with_metadata(metadata).rpc(request)`. To also get the metadata the server
+//! sends back, [MetadataConnector::with_response_metadata] additionally returns a
+//! [ResponseMetadata] that belongs to the next call of the returned connector, so
+//! concurrent calls on the same client each get their own. [RpcClient] has the same
+//! methods for a client with a metadata connector.
+//!
+//! On the server, [RpcChannel::metadata](crate::server::RpcChannel::metadata) returns
+//! the metadata of the client once the first request was read, and
+//! [RpcChannel::set_response_metadata](crate::server::RpcChannel::set_response_metadata)
+//! attaches metadata that is sent ahead of the first response.
+//!
+//! The wrappers also carry the deadline of a call:
+//! [RpcClient::rpc_with_timeout](crate::RpcClient::rpc_with_timeout) sends the time that
+//! is left of its timeout as a [Frame::Deadline], and
+//! [RpcChannel::deadline](crate::server::RpcChannel::deadline) returns the resulting
+//! deadline on the server.
+//!
+//! Both sides have to use the metadata wrappers, since the inner transport carries
+//! [Frame]s instead of the plain messages.
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Debug},
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures_lite::{Future, Stream};
+use futures_sink::Sink;
+use pin_project::pin_project;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ConnectionErrors, ConnectionStats, Connector, Listener, LocalAddr, MetadataError, PingError,
+    RemoteInfo, StreamTypes,
+};
+use crate::{RpcClient, RpcMessage};
+
+/// Key/value metadata of a call, sent ahead of the first message of a channel
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata(BTreeMap<String, String>);
+
+impl Metadata {
+    /// Create empty metadata
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the value of a key, returning the previous value
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        self.0.insert(key.into(), value.into())
+    }
+
+    /// Set the value of a key
+    pub fn with(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.insert(key, value);
+        self
+    }
+
+    /// The value of a key
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// Remove a key, returning its value
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.0.remove(key)
+    }
+
+    /// Iterate over the keys and values, ordered by key
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// The number of keys
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there are no keys
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for Metadata {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect(),
+        )
+    }
+}
+
+/// A message on the inner transport of a channel with metadata
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Frame<T> {
+    /// Metadata, sent before the first message
+    Metadata(Metadata),
+    /// How long the sender waits for the call, sent before the first message
+    Deadline(Duration),
+    /// A message
+    Msg(T),
+}
+
+/// The longest deadline a receive stream records, see [RecvStream::deadline]
+///
+/// Longer timeouts are cut to this, so a huge timeout sent by the remote does not
+/// overflow the [Instant] of the deadline.
+pub const MAX_DEADLINE_TIMEOUT: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The metadata the server sends on a single call, see
+/// [MetadataConnector::with_response_metadata]
+#[derive(Debug, Clone, Default)]
+pub struct ResponseMetadata(Arc<Mutex<Option<Metadata>>>);
+
+impl ResponseMetadata {
+    /// The metadata the server sent on the call.
+    ///
+    /// This is `None` until the first response of the call is received, and stays
+    /// `None` if the server sent no metadata.
+    pub fn get(&self) -> Option<Metadata> {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn set(&self, metadata: Metadata) {
+        *self.0.lock().unwrap() = Some(metadata);
+    }
+}
+
+/// A connector that sends metadata ahead of the first request of each channel
+pub struct MetadataConnector<In, Out, C> {
+    inner: C,
+    metadata: Metadata,
+    /// Taken by the next channel, which records the metadata of the server in it
+    response: Arc<Mutex<Option<ResponseMetadata>>>,
+    _p: PhantomData<(In, Out)>,
+}
+
+impl<In, Out, C> MetadataConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: Connector<In = Frame<In>, Out = Frame<Out>>,
+{
+    /// Create a new metadata connector, which sends no metadata
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            metadata: Metadata::default(),
+            response: Default::default(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In, Out, C: Clone> MetadataConnector<In, Out, C> {
+    /// A connector on the same inner connector, whose channels carry the given metadata.
+    pub fn with_metadata(&self, metadata: Metadata) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            metadata,
+            response: Default::default(),
+            _p: PhantomData,
+        }
+    }
+
+    /// A connector whose channels carry the given metadata, like
+    /// [MetadataConnector::with_metadata], and where the server's metadata of its next
+    /// call ends up.
+    ///
+    /// Only the first channel opened by the returned connector or one of its clones
+    /// records into the [ResponseMetadata], so calls that run concurrently on other
+    /// connectors, or later on this one, do not overwrite it.
+    pub fn with_response_metadata(&self, metadata: Metadata) -> (Self, ResponseMetadata) {
+        let response = ResponseMetadata::default();
+        let connector = Self {
+            inner: self.inner.clone(),
+            metadata,
+            response: Arc::new(Mutex::new(Some(response.clone()))),
+            _p: PhantomData,
+        };
+        (connector, response)
+    }
+
+    /// Get the inner connector
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+impl<In, Out, C: Clone> Clone for MetadataConnector<In, Out, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            metadata: self.metadata.clone(),
+            response: self.response.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In, Out, C: Debug> Debug for MetadataConnector<In, Out, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetadataConnector")
+            .field("inner", &self.inner)
+            .field("metadata", &self.metadata)
+            .finish()
+    }
+}
+
+impl<In, Out, C> ConnectionErrors for MetadataConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: ConnectionErrors,
+{
+    type SendError = C::SendError;
+    type RecvError = C::RecvError;
+    type OpenError = C::OpenError;
+    type AcceptError = C::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        C::is_remote_closed(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        C::is_clean_close(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        C::is_unknown_message(error)
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        C::is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        C::is_cancelled(error)
+    }
+}
+
+impl<In, Out, C> StreamTypes for MetadataConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: StreamTypes<In = Frame<In>, Out = Frame<Out>>,
+{
+    type In = In;
+    type Out = Out;
+    type SendSink = SendSink<C::SendSink>;
+    type RecvStream = RecvStream<C::RecvStream>;
+
+    fn metadata(recv: &Self::RecvStream) -> Option<&Metadata> {
+        recv.metadata()
+    }
+
+    fn set_metadata(send: &Self::SendSink, metadata: Metadata) -> Result<(), MetadataError> {
+        send.set_metadata(metadata)
+    }
+
+    fn deadline(recv: &Self::RecvStream) -> Option<Instant> {
+        recv.deadline()
+    }
+
+    fn set_deadline(send: &Self::SendSink, timeout: Duration) -> Result<(), MetadataError> {
+        send.set_deadline(timeout)
+    }
+}
+
+impl<In, Out, C> Connector for MetadataConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: Connector<In = Frame<In>, Out = Frame<Out>>,
+{
+    async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let (send, recv) = self.inner.open().await?;
+        let metadata = Some(self.metadata.clone()).filter(|m| !m.is_empty());
+        let response = self.response.lock().unwrap().take();
+        Ok((
+            SendSink::new(send, metadata),
+            RecvStream::new(recv, response),
+        ))
+    }
+
+    fn stats(&self) -> Option<ConnectionStats> {
+        self.inner.stats()
+    }
+
+    fn ping(&self) -> impl Future<Output = Result<Duration, PingError>> + Send {
+        self.inner.ping()
+    }
+}
+
+impl<S, In, Out, C> RpcClient<S, MetadataConnector<In, Out, C>>
+where
+    C: Clone,
+{
+    /// A client whose calls carry the given metadata, see
+    /// [MetadataConnector::with_metadata]
+    pub fn with_metadata(&self, metadata: Metadata) -> Self {
+        RpcClient {
+            source: self.source.with_metadata(metadata),
+            _p: PhantomData,
+        }
+    }
+
+    /// A client whose calls carry the given metadata, and where the server's metadata of
+    /// its next call ends up, see [MetadataConnector::with_response_metadata]
+    pub fn with_response_metadata(&self, metadata: Metadata) -> (Self, ResponseMetadata) {
+        let (source, response) = self.source.with_response_metadata(metadata);
+        let client = RpcClient {
+            source,
+            _p: PhantomData,
+        };
+        (client, response)
+    }
+}
+
+/// A listener that strips the metadata sent by a [MetadataConnector]
+///
+/// To use it with the default [BoxedListener](super::boxed::BoxedListener) of a server,
+/// box the inner listener first, e.g. `MetadataListener::new(listener.boxed()).boxed()`.
+#[derive(Debug)]
+pub struct MetadataListener<In, Out, L> {
+    inner: L,
+    _p: PhantomData<(In, Out)>,
+}
+
+impl<In, Out, L> MetadataListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: Listener<In = Frame<In>, Out = Frame<Out>>,
+{
+    /// Create a new metadata listener
+    pub fn new(inner: L) -> Self {
+        Self {
+            inner,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In, Out, L: Clone> Clone for MetadataListener<In, Out, L> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In, Out, L> ConnectionErrors for MetadataListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: ConnectionErrors,
+{
+    type SendError = L::SendError;
+    type RecvError = L::RecvError;
+    type OpenError = L::OpenError;
+    type AcceptError = L::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        L::is_remote_closed(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        L::is_clean_close(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        L::is_unknown_message(error)
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        L::is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        L::is_cancelled(error)
+    }
+}
+
+impl<In, Out, L> StreamTypes for MetadataListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: StreamTypes<In = Frame<In>, Out = Frame<Out>>,
+{
+    type In = In;
+    type Out = Out;
+    type SendSink = SendSink<L::SendSink>;
+    type RecvStream = RecvStream<L::RecvStream>;
+
+    fn metadata(recv: &Self::RecvStream) -> Option<&Metadata> {
+        recv.metadata()
+    }
+
+    fn set_metadata(send: &Self::SendSink, metadata: Metadata) -> Result<(), MetadataError> {
+        send.set_metadata(metadata)
+    }
+
+    fn deadline(recv: &Self::RecvStream) -> Option<Instant> {
+        recv.deadline()
+    }
+
+    fn set_deadline(send: &Self::SendSink, timeout: Duration) -> Result<(), MetadataError> {
+        send.set_deadline(timeout)
+    }
+}
+
+impl<In, Out, L> Listener for MetadataListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: Listener<In = Frame<In>, Out = Frame<Out>>,
+{
+    async fn accept(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::AcceptError> {
+        let (send, recv) = self.inner.accept().await?;
+        Ok((SendSink::new(send, None), RecvStream::new(recv, None)))
+    }
+
+    fn local_addr(&self) -> &[LocalAddr] {
+        self.inner.local_addr()
+    }
+
+    fn remote_info(recv: &Self::RecvStream) -> Option<Arc<RemoteInfo>> {
+        L::remote_info(&recv.inner)
+    }
+}
+
+/// Whether the metadata and deadline of a send sink have gone out yet
+#[derive(Debug)]
+enum Header {
+    /// Nothing was sent yet, and this metadata and deadline are sent first
+    Pending {
+        metadata: Option<Metadata>,
+        deadline: Option<Duration>,
+    },
+    /// The first frame was sent
+    Sent,
+}
+
+/// Send sink of a channel with metadata, wrapping each message in a [Frame::Msg]
+///
+/// The metadata and deadline, if any, are sent as a [Frame::Metadata] and a
+/// [Frame::Deadline] when the sink is first made ready, flushed or closed.
+#[derive(Debug)]
+#[pin_project]
+pub struct SendSink<S> {
+    #[pin]
+    inner: S,
+    header: Mutex<Header>,
+}
+
+impl<S> SendSink<S> {
+    fn new(inner: S, metadata: Option<Metadata>) -> Self {
+        Self {
+            inner,
+            header: Mutex::new(Header::Pending {
+                metadata,
+                deadline: None,
+            }),
+        }
+    }
+
+    /// Set the metadata that is sent ahead of the first message.
+    ///
+    /// This replaces metadata that was set before, and fails once the first message
+    /// was sent.
+    pub fn set_metadata(&self, metadata: Metadata) -> Result<(), MetadataError> {
+        let mut header = self.header.lock().unwrap();
+        match &mut *header {
+            Header::Pending {
+                metadata: pending, ..
+            } => {
+                *pending = Some(metadata);
+                Ok(())
+            }
+            Header::Sent => Err(MetadataError::AlreadySent),
+        }
+    }
+
+    /// Set how long this side waits for the call, sent ahead of the first message.
+    ///
+    /// This replaces a timeout that was set before, and fails once the first message
+    /// was sent.
+    pub fn set_deadline(&self, timeout: Duration) -> Result<(), MetadataError> {
+        let mut header = self.header.lock().unwrap();
+        match &mut *header {
+            Header::Pending { deadline, .. } => {
+                *deadline = Some(timeout);
+                Ok(())
+            }
+            Header::Sent => Err(MetadataError::AlreadySent),
+        }
+    }
+
+    /// Send the pending metadata and deadline, if they were not sent yet
+    fn poll_header<T>(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>>
+    where
+        S: Sink<Frame<T>>,
+    {
+        let mut this = self.project();
+        let header = this.header.get_mut().unwrap();
+        if let Header::Pending { metadata, deadline } = header {
+            if metadata.is_some() {
+                ready!(this.inner.as_mut().poll_ready(cx))?;
+            }
+            if let Some(metadata) = metadata.take() {
+                this.inner.as_mut().start_send(Frame::Metadata(metadata))?;
+            }
+            if deadline.is_some() {
+                ready!(this.inner.as_mut().poll_ready(cx))?;
+            }
+            if let Some(timeout) = deadline.take() {
+                this.inner.as_mut().start_send(Frame::Deadline(timeout))?;
+            }
+            *header = Header::Sent;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S, T> Sink<T> for SendSink<S>
+where
+    S: Sink<Frame<T>>,
+{
+    type Error = S::Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_header(cx))?;
+        self.project().inner.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        self.project().inner.start_send(Frame::Msg(item))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_header(cx))?;
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        ready!(self.as_mut().poll_header(cx))?;
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// Receive stream of a channel with metadata, stripping [Frame::Metadata] and
+/// [Frame::Deadline]
+#[derive(Debug)]
+#[pin_project]
+pub struct RecvStream<S> {
+    #[pin]
+    inner: S,
+    metadata: Option<Metadata>,
+    deadline: Option<Instant>,
+    /// Where the metadata of the server goes, for the channel of a call that asked for it
+    response: Option<ResponseMetadata>,
+}
+
+impl<S> RecvStream<S> {
+    fn new(inner: S, response: Option<ResponseMetadata>) -> Self {
+        Self {
+            inner,
+            metadata: None,
+            deadline: None,
+            response,
+        }
+    }
+
+    /// The metadata sent by the remote, if it has been received yet
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+
+    /// The deadline of the call sent by the remote, if it has been received yet.
+    ///
+    /// The remote sends how long it waits, and the deadline is that long after the
+    /// [Frame::Deadline] was received, at most [MAX_DEADLINE_TIMEOUT]. It is relative so
+    /// the clocks of both sides do not have to agree, and it is slightly later than the
+    /// deadline of the remote, by the time the frame was in transit.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+}
+
+impl<S, T, E> Stream for RecvStream<S>
+where
+    S: Stream<Item = Result<Frame<T>, E>>,
+{
+    type Item = Result<T, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match this.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(Frame::Metadata(metadata)))) => {
+                    if let Some(response) = this.response.as_ref() {
+                        response.set(metadata.clone());
+                    }
+                    *this.metadata = Some(metadata);
+                }
+                Poll::Ready(Some(Ok(Frame::Deadline(timeout)))) => {
+                    *this.deadline = Some(Instant::now() + timeout.min(MAX_DEADLINE_TIMEOUT));
+                }
+                Poll::Ready(Some(Ok(Frame::Msg(msg)))) => return Poll::Ready(Some(Ok(msg))),
+                Poll::Ready(Some(Err(cause))) => return Poll::Ready(Some(Err(cause))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "flume-transport")]
+mod tests {
+    use futures_lite::StreamExt;
+    use futures_util::SinkExt;
+    use testresult::TestResult;
+
+    use super::*;
+    use crate::{transport::flume, RpcServer};
+
+    #[tokio::test]
+    async fn metadata_is_stripped() -> TestResult<()> {
+        let (listener, connector) = flume::channel::<Frame<u64>, Frame<u64>>(1);
+        let listener = MetadataListener::<u64, u64, _>::new(listener);
+        let (connector, response) = MetadataConnector::<u64, u64, _>::new(connector)
+            .with_response_metadata(Metadata::new().with("request-id", "42"));
+        let client = async {
+            let (mut send, mut recv) = connector.open().await?;
+            send.send(1).await?;
+            assert_eq!(recv.next().await.transpose()?, Some(2));
+            assert_eq!(recv.metadata().and_then(|m| m.get("status")), Some("ok"));
+            TestResult::Ok(())
+        };
+        let server = async {
+            let (mut send, mut recv) = listener.accept().await?;
+            assert_eq!(recv.next().await.transpose()?, Some(1));
+            assert_eq!(
+                recv.metadata().and_then(|m| m.get("request-id")),
+                Some("42")
+            );
+            send.set_metadata(Metadata::new().with("status", "ok"))?;
+            send.send(2).await?;
+            assert_eq!(
+                send.set_metadata(Metadata::new()),
+                Err(MetadataError::AlreadySent)
+            );
+            TestResult::Ok(())
+        };
+        let (client, server) = tokio::join!(client, server);
+        client?;
+        server?;
+        let response = response.get().unwrap_or_default();
+        assert_eq!(response.get("status"), Some("ok"));
+        Ok(())
+    }
+
+    /// concurrent calls on the same client each get the metadata of their own call, also
+    /// through a boxed listener
+    #[tokio::test]
+    async fn response_metadata_per_call() -> TestResult<()> {
+        #[derive(Debug, Clone)]
+        struct EchoService;
+
+        impl crate::Service for EchoService {
+            type Req = u64;
+            type Res = u64;
+        }
+
+        impl crate::message::RpcMsg<EchoService> for u64 {
+            type Response = u64;
+        }
+
+        let (listener, connector) = flume::channel::<Frame<u64>, Frame<u64>>(2);
+        let listener = MetadataListener::new(listener.boxed()).boxed();
+        let server = RpcServer::<EchoService>::new(listener);
+        let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+            let id = chan.metadata().and_then(|m| m.get("request-id"));
+            let id = id.expect("the boxed listener forwards the metadata");
+            let response = Metadata::new()
+                .with("request-id", id)
+                .with("request", req.to_string());
+            chan.set_response_metadata(response)?;
+            chan.rpc(req, (), |_, req| async move { req }).await?;
+            anyhow::Ok(())
+        });
+        let client = RpcClient::<EchoService, _>::new(MetadataConnector::new(connector));
+        let (a, response_a) =
+            client.with_response_metadata(Metadata::new().with("request-id", "a"));
+        let (b, response_b) =
+            client.with_response_metadata(Metadata::new().with("request-id", "b"));
+        let (res_a, res_b) = tokio::join!(a.rpc(1), b.rpc(2));
+        assert_eq!((res_a?, res_b?), (1, 2));
+        let response_a = response_a.get().unwrap_or_default();
+        assert_eq!(response_a.get("request-id"), Some("a"));
+        let response_b = response_b.get().unwrap_or_default();
+        assert_eq!(response_b.get("request-id"), Some("b"));
+
+        // the response metadata only belongs to the first call of a client
+        let (c, response_c) =
+            client.with_response_metadata(Metadata::new().with("request-id", "c"));
+        c.rpc(3).await?;
+        c.rpc(4).await?;
+        let response_c = response_c.get().unwrap_or_default();
+        assert_eq!(response_c.get("request"), Some("3"));
+        Ok(())
+    }
+}
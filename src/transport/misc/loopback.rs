@@ -0,0 +1,130 @@
+//! In-memory client and server pair for tests
+use crate::{
+    transport::flume::{self, FlumeConnector, FlumeListener},
+    RpcClient, RpcServer, Service,
+};
+
+/// Connector of a [loopback] pair
+pub type LoopbackConnector<S> = FlumeConnector<<S as Service>::Res, <S as Service>::Req>;
+
+/// Listener of a [loopback] pair
+pub type LoopbackListener<S> = FlumeListener<<S as Service>::Req, <S as Service>::Res>;
+
+/// Create a connected client and server for the service `S`
+///
+/// Both sides share in-memory [flume] channels, so this is a convenient way to test
+/// handlers for all interaction patterns without any networking.
+///
+/// # Rpc
+/// ```
+/// # async fn example() -> anyhow::Result<()> {
+/// use derive_more::{From, TryInto};
+/// use quic_rpc::{message::RpcMsg, transport::misc::loopback, Service};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Serialize, Deserialize)]
+/// struct Sqr(u64);
+///
+/// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+/// enum Request {
+///     Sqr(Sqr),
+/// }
+///
+/// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+/// enum Response {
+///     Sqr(u64),
+/// }
+///
+/// #[derive(Debug, Clone)]
+/// struct MathService;
+///
+/// impl Service for MathService {
+///     type Req = Request;
+///     type Res = Response;
+/// }
+///
+/// impl RpcMsg<MathService> for Sqr {
+///     type Response = u64;
+/// }
+///
+/// let (client, server) = loopback::<MathService>();
+/// let _handle = server.spawn_accept_loop(|req, chan| async move {
+///     match req {
+///         Request::Sqr(req) => chan.rpc(req, (), |_, Sqr(x)| async move { x * x }).await,
+///     }
+/// });
+/// assert_eq!(client.rpc(Sqr(3)).await?, 9);
+/// # Ok(())
+/// # }
+/// # glib::MainContext::default().block_on(example()).unwrap();
+/// ```
+///
+/// # Bidi streaming
+/// ```
+/// # async fn example() -> anyhow::Result<()> {
+/// use derive_more::{From, TryInto};
+/// use futures::{SinkExt, StreamExt};
+/// use quic_rpc::{
+///     message::{BidiStreaming, BidiStreamingMsg, Msg},
+///     server::RpcServerError,
+///     transport::misc::loopback,
+///     Service,
+/// };
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Debug, Serialize, Deserialize)]
+/// struct Echo;
+///
+/// #[derive(Debug, Serialize, Deserialize)]
+/// struct Line(String);
+///
+/// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+/// enum Request {
+///     Echo(Echo),
+///     Line(Line),
+/// }
+///
+/// #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+/// enum Response {
+///     Line(Line),
+/// }
+///
+/// #[derive(Debug, Clone)]
+/// struct EchoService;
+///
+/// impl Service for EchoService {
+///     type Req = Request;
+///     type Res = Response;
+/// }
+///
+/// impl Msg<EchoService> for Echo {
+///     type Pattern = BidiStreaming;
+/// }
+///
+/// impl BidiStreamingMsg<EchoService> for Echo {
+///     type Update = Line;
+///     type Response = Line;
+/// }
+///
+/// let (client, server) = loopback::<EchoService>();
+/// let _handle = server.spawn_accept_loop(|req, chan| async move {
+///     match req {
+///         Request::Echo(req) => chan.bidi_streaming(req, (), |_, _, updates| updates).await,
+///         Request::Line(_) => Err(RpcServerError::UnexpectedStartMessage),
+///     }
+/// });
+/// let (mut send, mut recv) = client.bidi(Echo).await?;
+/// send.send(Line("hello".into())).await?;
+/// let Line(line) = recv.next().await.expect("response")?;
+/// assert_eq!(line, "hello");
+/// # Ok(())
+/// # }
+/// # glib::MainContext::default().block_on(example()).unwrap();
+/// ```
+pub fn loopback<S: Service>() -> (
+    RpcClient<S, LoopbackConnector<S>>,
+    RpcServer<S, LoopbackListener<S>>,
+) {
+    let (listener, connector) = flume::channel(1);
+    (RpcClient::new(connector), RpcServer::new(listener))
+}
@@ -10,6 +10,11 @@ use crate::{
     RpcMessage,
 };
 
+#[cfg(feature = "flume-transport")]
+mod loopback;
+#[cfg(feature = "flume-transport")]
+pub use loopback::{loopback, LoopbackConnector, LoopbackListener};
+
 /// A dummy listener that does nothing
 ///
 /// This can be useful as a default if you want to configure
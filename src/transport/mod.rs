@@ -17,35 +17,86 @@
 //! types are defined by implementing the [`StreamTypes`] trait.
 //!
 //! Errors for both sides are defined by implementing the [`ConnectionErrors`] trait.
+//!
+//! Some transports also allow the server to open channels to a client, see
+//! [`Connection::open_push`] and [`Connector::accept_push`]. Such push channels are
+//! kept apart from the channels opened by the client: a listener only accepts
+//! channels opened by clients, and a connector only accepts channels opened by the
+//! server, so the normal request flow is not affected by them.
 use std::{
     fmt::{self, Debug, Display},
     net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 use boxed::{BoxableConnector, BoxableListener, BoxedConnector, BoxedListener};
+use bytes::Bytes;
 use futures_lite::{Future, Stream};
 use futures_sink::Sink;
+use futures_util::future::BoxFuture;
 use mapped::MappedConnector;
+use metadata::Metadata;
 
 use crate::{RpcError, RpcMessage};
 
+#[cfg(any(all(feature = "uds-transport", unix), feature = "tcp-transport"))]
+pub mod auth;
+pub mod backtrace;
+pub mod balanced;
 pub mod boxed;
+pub mod codec;
 pub mod combined;
+#[cfg(feature = "zstd-transport")]
+pub mod compressed;
+#[cfg(feature = "encrypted-transport")]
+pub mod encrypted;
+pub mod fault;
 #[cfg(feature = "flume-transport")]
 pub mod flume;
 #[cfg(feature = "hyper-transport")]
 pub mod hyper;
 #[cfg(feature = "iroh-net-transport")]
 pub mod iroh_net;
+pub mod lifecycle;
 pub mod mapped;
+pub mod metadata;
 pub mod misc;
+#[cfg(any(
+    feature = "quinn-transport",
+    feature = "hyper-transport",
+    feature = "iroh-net-transport",
+    feature = "ws-transport",
+    feature = "uds-transport",
+    feature = "tcp-transport"
+))]
+pub mod multiplex;
+pub mod protocol;
 #[cfg(feature = "quinn-transport")]
 pub mod quinn;
+pub mod reconnecting;
+#[cfg(feature = "tcp-transport")]
+pub mod tcp;
+#[cfg(any(
+    feature = "quinn-transport",
+    feature = "iroh-net-transport",
+    all(feature = "uds-transport", unix),
+    feature = "tcp-transport"
+))]
+pub mod testing;
+#[cfg(feature = "tracing-spans")]
+pub mod traced;
+#[cfg(all(feature = "uds-transport", unix))]
+pub mod uds;
+#[cfg(feature = "ws-transport")]
+pub mod ws;
 
 #[cfg(any(
     feature = "quinn-transport",
-    feature = "hyper-transport",
-    feature = "iroh-net-transport"
+    feature = "iroh-net-transport",
+    all(feature = "uds-transport", unix),
+    feature = "tcp-transport"
 ))]
 mod util;
 
@@ -59,6 +110,51 @@ pub trait ConnectionErrors: Debug + Clone + Send + Sync + 'static {
     type OpenError: RpcError;
     /// Error when accepting a channel
     type AcceptError: RpcError;
+
+    /// Whether a send error means that the remote closed its receiving side.
+    ///
+    /// This is the case e.g. when a server already responded to a client streaming
+    /// request and stopped reading updates. Transports that can not tell return `false`.
+    fn is_remote_closed(_error: &Self::SendError) -> bool {
+        false
+    }
+
+    /// Whether a receive error means that the remote closed the connection cleanly.
+    ///
+    /// This is the case e.g. when a client closes its connection while a bidi request
+    /// is in progress, as opposed to resetting the stream or losing the connection.
+    /// Transports that can not tell return `false`.
+    fn is_clean_close(_error: &Self::RecvError) -> bool {
+        false
+    }
+
+    /// Whether a receive error means that the remote sent a message that is unknown
+    /// to this side, e.g. a new request variant from a newer client.
+    ///
+    /// Such an error only affects the single message. See
+    /// [VersionedCodec](codec::VersionedCodec) for a codec that reports them.
+    /// Transports that can not tell return `false`.
+    fn is_unknown_message(_error: &Self::RecvError) -> bool {
+        false
+    }
+
+    /// Whether a receive error means that the remote reset the stream instead of
+    /// finishing it.
+    ///
+    /// Transports that can not tell return `false`. The tcp transport is one of them,
+    /// since yamux reports a reset substream as the end of the stream.
+    fn is_reset(_error: &Self::RecvError) -> bool {
+        false
+    }
+
+    /// Whether a receive error means that the remote cancelled the stream using
+    /// [StreamTypes::cancel], e.g. because the handler on the other side timed out.
+    ///
+    /// A cancelled stream is also reset, see [ConnectionErrors::is_reset]. Transports
+    /// that can not tell return `false`.
+    fn is_cancelled(_error: &Self::RecvError) -> bool {
+        false
+    }
 }
 
 /// Types that are common to both [`Connector`] and [`Listener`].
@@ -77,6 +173,156 @@ pub trait StreamTypes: ConnectionErrors {
         + 'static;
     /// Send side of a bidirectional typed channel
     type SendSink: Sink<Self::Out, Error = Self::SendError> + Send + Sync + Unpin + 'static;
+
+    /// Set the priority of the send side of a channel.
+    ///
+    /// When several channels of the same connection have data to send, the data of
+    /// channels with a higher priority is sent first, so e.g. a small interactive channel
+    /// is not slowed down by a large transfer. The default priority is 0. This only
+    /// affects the order within one connection, and only the sending side of this end.
+    ///
+    /// Only the quinn transport supports priorities, all others return
+    /// [PriorityError::Unsupported]. Of the wrappers, only the boxed, mapped and
+    /// lifecycle ones forward it.
+    fn set_priority(_send: &Self::SendSink, _priority: i32) -> Result<(), PriorityError> {
+        Err(PriorityError::Unsupported)
+    }
+
+    /// The metadata the remote sent ahead of the first message of a channel.
+    ///
+    /// Only the [metadata] wrappers carry metadata, all other transports return `None`.
+    /// Of the other wrappers, only the boxed, mapped and lifecycle ones forward it.
+    fn metadata(_recv: &Self::RecvStream) -> Option<&Metadata> {
+        None
+    }
+
+    /// Attach metadata that is sent ahead of the first message on the send side of a
+    /// channel.
+    ///
+    /// Only the [metadata] wrappers carry metadata, all other transports return
+    /// [MetadataError::Unsupported]. Of the other wrappers, only the boxed, mapped and
+    /// lifecycle ones forward it.
+    fn set_metadata(_send: &Self::SendSink, _metadata: Metadata) -> Result<(), MetadataError> {
+        Err(MetadataError::Unsupported)
+    }
+
+    /// The deadline of the call the remote sent ahead of the first message of a channel.
+    ///
+    /// Only the [metadata] wrappers carry deadlines, all other transports return `None`.
+    /// Of the other wrappers, only the boxed, mapped and lifecycle ones forward it.
+    fn deadline(_recv: &Self::RecvStream) -> Option<Instant> {
+        None
+    }
+
+    /// Send how long this side waits for the call ahead of the first message on the send
+    /// side of a channel.
+    ///
+    /// The time is sent instead of a point in time, so the clocks of both sides do not
+    /// have to agree. Only the [metadata] wrappers carry deadlines, all other transports
+    /// return [MetadataError::Unsupported]. Of the other wrappers, only the boxed, mapped
+    /// and lifecycle ones forward it.
+    fn set_deadline(_send: &Self::SendSink, _timeout: Duration) -> Result<(), MetadataError> {
+        Err(MetadataError::Unsupported)
+    }
+
+    /// Resolves once the remote stopped receiving on the send side of a channel.
+    ///
+    /// This is the case when the client drops a call, including its response side,
+    /// before the call is complete. The server uses this to cancel the handlers of such
+    /// calls, e.g. of a client streaming call whose updates just end when the client
+    /// goes away. Only the flume, quinn and iroh-net transports can tell, all others
+    /// return a future that never resolves. Of the wrappers, only the boxed, mapped and
+    /// lifecycle ones forward it.
+    fn send_closed(_send: &Self::SendSink) -> impl Future<Output = ()> + Send + 'static {
+        futures_lite::future::pending()
+    }
+
+    /// Cancel the send side of a channel, so the remote does not mistake it for a
+    /// channel that just ended.
+    ///
+    /// The server does this when a handler runs longer than
+    /// [RpcServer::with_handler_timeout](crate::RpcServer::with_handler_timeout). The
+    /// remote then gets a receive error for which [ConnectionErrors::is_cancelled] is
+    /// `true`. Sending on the channel fails afterwards. Only the quinn and iroh-net
+    /// transports can cancel a channel, all others do nothing, so the channel is just
+    /// closed once it is dropped. Of the wrappers, only the boxed, mapped and lifecycle
+    /// ones forward it.
+    fn cancel(_send: &mut Self::SendSink) {}
+}
+
+/// Error when setting the priority of a channel, see [StreamTypes::set_priority]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PriorityError {
+    /// The transport does not support priorities
+    Unsupported,
+    /// The channel was already closed
+    Closed,
+}
+
+impl fmt::Display for PriorityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for PriorityError {}
+
+/// Error when attaching metadata to a channel, see [StreamTypes::set_metadata]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MetadataError {
+    /// The transport does not carry metadata
+    Unsupported,
+    /// The first message was already sent, so the metadata can no longer go ahead of it
+    AlreadySent,
+}
+
+impl fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for MetadataError {}
+
+/// [StreamTypes] whose channels can send and receive raw frames, bypassing the codec.
+///
+/// A raw frame is the serialized form of a single message, as produced by the
+/// [Codec](codec::Codec) of the transport. Converting a channel keeps the framing and
+/// the multiplexing of the transport, so e.g. a proxy can forward messages between two
+/// connections without deserializing and serializing them again.
+///
+/// # Contract
+///
+/// The remote still expects typed messages, so every frame sent must be a message of
+/// the right type, serialized with the same codec as the remote uses. This includes
+/// the first frame on a channel opened by a client, which must be a request.
+///
+/// Converting consumes the typed halves, so raw and typed frames can not be mixed on
+/// the same channel. Frames that were already received but not read yet, or sent but
+/// not flushed yet, are kept and keep their order.
+#[cfg(any(
+    feature = "quinn-transport",
+    feature = "iroh-net-transport",
+    all(feature = "uds-transport", unix),
+    feature = "tcp-transport"
+))]
+pub trait RawStreamTypes: StreamTypes {
+    /// Receive side of a channel, yielding raw frames
+    type RawRecvStream: Stream<Item = Result<bytes::Bytes, Self::RecvError>>
+        + Send
+        + Sync
+        + Unpin
+        + 'static;
+    /// Send side of a channel, taking raw frames
+    type RawSendSink: Sink<bytes::Bytes, Error = Self::SendError> + Send + Sync + Unpin + 'static;
+
+    /// Convert both halves of a typed channel into raw halves.
+    fn into_raw(
+        send: Self::SendSink,
+        recv: Self::RecvStream,
+    ) -> (Self::RawSendSink, Self::RawRecvStream);
 }
 
 /// A connection to a specific remote machine
@@ -88,6 +334,20 @@ pub trait Connector: StreamTypes {
         &self,
     ) -> impl Future<Output = Result<(Self::SendSink, Self::RecvStream), Self::OpenError>> + Send;
 
+    /// Open a channel for a single rpc call, see [RpcClient::rpc](crate::RpcClient::rpc)
+    ///
+    /// A transport may carry the call on a substream that carried an earlier rpc call,
+    /// see [QuinnConnector::with_substream_pool](quinn::QuinnConnector::with_substream_pool).
+    /// By default, this is the same as [Connector::open]. Of the wrappers, only
+    /// [BoxedConnector], [MappedConnector] and
+    /// [LifecycleConnector](lifecycle::LifecycleConnector) forward it.
+    fn open_rpc(
+        &self,
+    ) -> impl Future<Output = Result<(Self::SendSink, Self::RecvStream), Self::OpenError>> + Send
+    {
+        self.open()
+    }
+
     /// Map the input and output types of this connection
     fn map<In1, Out1>(self) -> MappedConnector<In1, Out1, Self>
     where
@@ -97,6 +357,39 @@ pub trait Connector: StreamTypes {
         MappedConnector::new(self)
     }
 
+    /// Statistics of the current connection to the remote.
+    ///
+    /// Returns `None` if there is no connection right now, or if the transport does not
+    /// support statistics. Only the quinn transport supports them.
+    fn stats(&self) -> Option<ConnectionStats> {
+        None
+    }
+
+    /// Check that the remote is reachable, and measure the round trip time.
+    ///
+    /// This sends a ping below the rpc layer, so it needs no message in the service and
+    /// is never seen by the handlers on the server. If there is no connection yet, one
+    /// is established first, which is not included in the returned round trip time.
+    /// Only the quinn transport supports pings, all others return
+    /// [PingError::Unsupported].
+    fn ping(&self) -> impl Future<Output = Result<Duration, PingError>> + Send {
+        async { Err(PingError::Unsupported) }
+    }
+
+    /// Accept the next channel that the server opened using [Connection::open_push].
+    ///
+    /// Channels opened by the server are never returned by [Connector::open], and
+    /// channels opened by this side are never returned here. Returns `None` once no
+    /// more channels can be pushed, e.g. because the connection was closed for good.
+    /// Only the quinn transport supports push, all others return `None` right away. Of
+    /// the wrappers, only [BoxedConnector], [MappedConnector] and
+    /// [LifecycleConnector](lifecycle::LifecycleConnector) forward it.
+    fn accept_push(
+        &self,
+    ) -> impl Future<Output = Option<(Self::SendSink, Self::RecvStream)>> + Send {
+        async { None }
+    }
+
     /// Box the connection
     fn boxed(self) -> BoxedConnector<Self::In, Self::Out>
     where
@@ -106,6 +399,42 @@ pub trait Connector: StreamTypes {
     }
 }
 
+/// Statistics of a connection, see [Connector::stats]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ConnectionStats {
+    /// Smoothed round trip time
+    pub rtt: Duration,
+    /// Congestion window in bytes
+    pub cwnd: u64,
+    /// Number of packets that were lost
+    pub lost_packets: u64,
+    /// Bytes sent over the network, including protocol overhead
+    pub sent_bytes: u64,
+    /// Bytes received over the network, including protocol overhead
+    pub recv_bytes: u64,
+}
+
+/// Error when pinging the remote, see [Connector::ping]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum PingError {
+    /// The transport does not support pings
+    Unsupported,
+    /// The connection was closed before the ping was answered
+    Closed,
+    /// The ping was not answered in time
+    Timeout,
+}
+
+impl fmt::Display for PingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for PingError {}
+
 /// A listener that listens for connections
 ///
 /// A listener can be used to accept bidirectional typed channels from any of the
@@ -120,6 +449,29 @@ pub trait Listener: StreamTypes {
     /// The local addresses this endpoint is bound to.
     fn local_addr(&self) -> &[LocalAddr];
 
+    /// Information about the remote end of a channel returned by [Listener::accept].
+    ///
+    /// The default returns `None`, for transports that know nothing about the remote.
+    fn remote_info(_recv: &Self::RecvStream) -> Option<Arc<RemoteInfo>> {
+        None
+    }
+
+    /// The connection of a channel returned by [Listener::accept], which can be used
+    /// to open channels to the client, see [Connection::open_push].
+    ///
+    /// The default returns `None`, for transports that can not open channels to the
+    /// client. Only the quinn transport supports push.
+    fn connection(&self, _recv: &Self::RecvStream) -> Option<Connection<Self::In, Self::Out>> {
+        None
+    }
+
+    /// Report the connections of this listener to `observer`, see the [lifecycle]
+    /// module.
+    ///
+    /// The default does nothing, for transports that do not know about connections.
+    /// The quinn, iroh-net and tcp transports report connections.
+    fn observe_connections(&self, _observer: lifecycle::LifecycleObserver) {}
+
     /// Box the listener
     fn boxed(self) -> BoxedListener<Self::In, Self::Out>
     where
@@ -141,6 +493,8 @@ pub enum LocalAddr {
     Socket(SocketAddr),
     /// An in-memory address.
     Mem,
+    /// A unix domain socket path.
+    Path(PathBuf),
 }
 
 impl Display for LocalAddr {
@@ -148,6 +502,174 @@ impl Display for LocalAddr {
         match self {
             LocalAddr::Socket(sockaddr) => write!(f, "{sockaddr}"),
             LocalAddr::Mem => write!(f, "mem"),
+            LocalAddr::Path(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// Information about the remote end of an accepted channel.
+///
+/// Returned by [Listener::remote_info]. All channels on the same connection share
+/// the same info.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RemoteInfo {
+    /// The socket address of the remote, if known.
+    pub addr: Option<SocketAddr>,
+    /// The DER encoded certificate chain presented by the remote, leaf first.
+    ///
+    /// This is empty unless the listener is configured to require client certificates.
+    pub certificates: Vec<Vec<u8>>,
+    /// The identity established by the handshake of the connection, if any.
+    ///
+    /// This is `None` unless the listener is configured to authenticate clients, see
+    /// the `auth` module.
+    pub identity: Option<Identity>,
+}
+
+/// The identity of a client, as established by an application level handshake
+///
+/// What it contains is up to the application, e.g. a user name. See [RemoteInfo::identity].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Identity(pub String);
+
+/// Both halves of a channel opened using [Connection::open_push]
+type PushHalves<In, Out> = (boxed::SendSink<Out>, boxed::RecvStream<In>);
+
+/// A function that opens a channel on a connection, see [Connection::new]
+type OpenPush<In, Out> =
+    dyn Fn() -> BoxFuture<'static, anyhow::Result<PushHalves<In, Out>>> + Send + Sync;
+
+/// The connection to a client that a channel was accepted on.
+///
+/// Returned by [Listener::connection]. This allows the server to open channels to
+/// the client, e.g. to push notifications without the client polling for them. The
+/// client accepts them with [Connector::accept_push].
+///
+/// A push channel has the same types as the channels opened by the client: the server
+/// sends `Out` and receives `In`. There is no first request, both sides can send as
+/// soon as the channel is open, and the service decides what the messages mean.
+///
+/// Some transports also support unreliable datagrams on the connection, see
+/// [Connection::send_datagram].
+pub struct Connection<In: RpcMessage, Out: RpcMessage> {
+    open_push: Arc<OpenPush<In, Out>>,
+    datagrams: Option<Arc<dyn DatagramSocket>>,
+}
+
+impl<In: RpcMessage, Out: RpcMessage> Connection<In, Out> {
+    /// Create a connection from a function that opens a channel to the client
+    pub fn new<F, Fut>(open_push: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<PushHalves<In, Out>>> + Send + 'static,
+    {
+        Self {
+            open_push: Arc::new(move || Box::pin(open_push())),
+            datagrams: None,
         }
     }
+
+    /// Send and receive datagrams using `socket`
+    pub fn with_datagrams(mut self, socket: impl DatagramSocket) -> Self {
+        self.datagrams = Some(Arc::new(socket));
+        self
+    }
+
+    /// Open a channel to the client.
+    ///
+    /// The client only learns about the channel once the first message is sent on it,
+    /// so the server should send first. If the client does not accept pushed channels,
+    /// they are buffered by the transport until its limits are reached, after which this
+    /// waits. Fails if the connection is closed.
+    pub async fn open_push(&self) -> anyhow::Result<PushHalves<In, Out>> {
+        (self.open_push)().await
+    }
+
+    /// Send an unreliable datagram to the client.
+    ///
+    /// Datagrams are not part of any channel. They may be lost, duplicated or reordered,
+    /// and are never retransmitted, which suits data that is stale by the time it could
+    /// be resent, like telemetry. The bytes are sent as they are; to send a message, use
+    /// the [Codec](codec::Codec) of the transport to encode it first. A datagram must fit
+    /// into a single packet, see [Connection::max_datagram_size]. Only the quinn
+    /// transport supports datagrams, all others return [DatagramError::Unsupported].
+    pub fn send_datagram(&self, data: impl Into<Bytes>) -> Result<(), DatagramError> {
+        match &self.datagrams {
+            Some(socket) => socket.send_datagram(data.into()),
+            None => Err(DatagramError::Unsupported),
+        }
+    }
+
+    /// The largest datagram that can be sent to the client right now.
+    ///
+    /// This depends on the path to the client, so it can change over the lifetime of
+    /// the connection. Returns `None` if datagrams are not supported.
+    pub fn max_datagram_size(&self) -> Option<usize> {
+        self.datagrams.as_ref()?.max_datagram_size()
+    }
+
+    /// The datagrams sent by the client.
+    ///
+    /// Each datagram is returned by only one of the streams, if there are several. The
+    /// stream ends when the connection is closed, or right away if datagrams are not
+    /// supported.
+    pub fn datagrams(&self) -> impl Stream<Item = Bytes> + Send + 'static {
+        futures_lite::stream::unfold(self.datagrams.clone(), |socket| async move {
+            let data = socket.as_ref()?.read_datagram().await?;
+            Some((data, socket))
+        })
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage> Clone for Connection<In, Out> {
+    fn clone(&self) -> Self {
+        Self {
+            open_push: self.open_push.clone(),
+            datagrams: self.datagrams.clone(),
+        }
+    }
+}
+
+/// Unreliable datagrams on a connection, see [Connection::with_datagrams]
+pub trait DatagramSocket: Debug + Send + Sync + 'static {
+    /// Send a datagram, without waiting for it to be sent
+    fn send_datagram(&self, data: Bytes) -> Result<(), DatagramError>;
+
+    /// The largest datagram that can be sent right now, `None` if datagrams are not
+    /// supported
+    fn max_datagram_size(&self) -> Option<usize>;
+
+    /// Receive the next datagram, `None` once the connection is closed
+    fn read_datagram(&self) -> BoxFuture<'static, Option<Bytes>>;
+}
+
+/// Error when sending a datagram, see [Connection::send_datagram]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DatagramError {
+    /// The transport does not support datagrams, or they are disabled on either side
+    Unsupported,
+    /// The datagram does not fit into a single packet on the current path
+    TooLarge {
+        /// The size of the datagram
+        size: usize,
+        /// The largest datagram that can be sent right now
+        max: usize,
+    },
+    /// There is no connection to the remote
+    Closed,
+}
+
+impl fmt::Display for DatagramError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for DatagramError {}
+
+impl<In: RpcMessage, Out: RpcMessage> Debug for Connection<In, Out> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection").finish_non_exhaustive()
+    }
 }
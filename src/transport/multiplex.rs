@@ -0,0 +1,699 @@
+//! Transport wrapper that serves several services over one connection.
+//!
+//! Messages are serialized with a [Codec] and sent as byte frames over an inner
+//! transport with `In = Out = Vec<u8>`. A [MultiplexListener] wraps the inner listener,
+//! and [MultiplexListener::route] returns a [RoutedListener] per service, each of which
+//! can be used for its own [RpcServer](crate::RpcServer). On the client side,
+//! [MultiplexConnector::route] returns a [RoutedConnector] per service, and all of
+//! them open their channels on the same inner connector.
+//!
+//! Both sides of a connection need to use the multiplex wrapper.
+//!
+//! # Tag framing
+//!
+//! Every service that is routed has a [ServiceTag], a 16 bit number that has to be the
+//! same on both sides. The first frame of every channel starts with the tag of the
+//! service as two big endian bytes, followed by the first message of the channel. All
+//! other frames are just the serialized messages. This adds no extra frame or round
+//! trip when opening a channel, but it means that the listener only learns which
+//! service a channel is for once the client sent its first message, which is the case
+//! for all interaction patterns.
+//!
+//! # Collision handling
+//!
+//! Routing two services with the same tag on the same listener fails with a
+//! [RouteError] that names the service already using the tag. The tag is free again
+//! once all clones of the [RoutedListener] for it are dropped.
+//!
+//! A channel whose tag has no route, e.g. because the client knows a service that the
+//! server does not, is dropped by the listener, which the client sees as the channel
+//! being closed early. The same happens for a channel whose first frame is shorter
+//! than a tag.
+//!
+//! There is no background task. Whichever routed listener is waiting in
+//! [Listener::accept] accepts channels from the inner listener and hands them to the
+//! listener of their service, so channels are only accepted while at least one of the
+//! routed listeners is accepting. Up to 16 channels are kept for a service whose
+//! listener is not accepting at the moment, further channels for it are dropped.
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Display},
+    io,
+    marker::PhantomData,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures::{channel::mpsc, lock::Mutex as AsyncMutex, stream::FuturesUnordered};
+use futures_lite::{future, Future, Stream, StreamExt};
+use futures_sink::Sink;
+use futures_util::{future::BoxFuture, SinkExt};
+use tracing::debug;
+
+use super::{
+    codec::{BincodeCodec, Codec},
+    ConnectionErrors, ConnectionStats, Connector, Listener, LocalAddr, PingError, RemoteInfo,
+    StreamTypes,
+};
+use crate::{RpcError, Service};
+
+/// A [Service] that can be routed over a multiplexed connection.
+///
+/// The tag is sent on the wire and has to be unique among the services routed on the
+/// same listener, see the [module docs](self).
+pub trait ServiceTag: Service {
+    /// The tag that selects this service on a multiplexed connection
+    const TAG: u16;
+}
+
+/// Size of the tag at the start of the first frame of a channel
+const TAG_SIZE: usize = 2;
+
+/// Number of channels that are kept for a route while its listener is not accepting
+const ROUTE_BUFFER: usize = 16;
+
+/// Error when routing a service on a [MultiplexListener] whose tag is already in use
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteError {
+    /// The tag that is in use
+    pub tag: u16,
+    /// The type name of the service that uses the tag
+    pub existing: &'static str,
+}
+
+impl Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for RouteError {}
+
+/// Error when sending a message over a multiplexed transport
+#[derive(Debug)]
+pub enum MultiplexSendError<E> {
+    /// Error from the inner transport
+    Inner(E),
+    /// Unable to serialize the message
+    Encode(io::Error),
+}
+
+impl<E: Debug> Display for MultiplexSendError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl<E: Debug> std::error::Error for MultiplexSendError<E> {}
+
+/// Error when receiving a message over a multiplexed transport
+#[derive(Debug)]
+pub enum MultiplexRecvError<E> {
+    /// Error from the inner transport
+    Inner(E),
+    /// Unable to deserialize the message
+    Decode(io::Error),
+}
+
+impl<E: Debug> Display for MultiplexRecvError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl<E: Debug> std::error::Error for MultiplexRecvError<E> {}
+
+/// Both halves of a channel of the inner transport
+type Channel<L> = (<L as StreamTypes>::SendSink, <L as StreamTypes>::RecvStream);
+
+/// A channel of the inner transport whose tag was read, with the rest of its first frame
+type Tagged<L> = (u16, Channel<L>, Vec<u8>);
+
+/// The routes of a listener, by tag
+type Routes<L> = HashMap<u16, (&'static str, mpsc::Sender<(Channel<L>, Vec<u8>)>)>;
+
+/// Split the tag off the first frame of a channel
+fn split_tag(mut frame: Vec<u8>) -> Option<(u16, Vec<u8>)> {
+    if frame.len() < TAG_SIZE {
+        return None;
+    }
+    let tag = u16::from_be_bytes([frame[0], frame[1]]);
+    frame.drain(..TAG_SIZE);
+    Some((tag, frame))
+}
+
+/// Read the tag of a newly accepted channel
+async fn read_tag<L: StreamTypes<In = Vec<u8>>>(
+    send: L::SendSink,
+    mut recv: L::RecvStream,
+) -> Option<Tagged<L>> {
+    match recv.next().await {
+        Some(Ok(frame)) => match split_tag(frame) {
+            Some((tag, rest)) => Some((tag, (send, recv), rest)),
+            None => {
+                debug!("dropping channel with a first frame that is too short for a tag");
+                None
+            }
+        },
+        Some(Err(cause)) => {
+            debug!("dropping channel that failed before sending a tag: {cause}");
+            None
+        }
+        None => None,
+    }
+}
+
+/// State shared by a [MultiplexListener] and all of its routed listeners
+struct Shared<L: StreamTypes> {
+    inner: L,
+    routes: Mutex<Routes<L>>,
+    /// Accepted channels whose tag was not read yet, only polled by the listener that
+    /// currently accepts from the inner listener
+    pending: AsyncMutex<FuturesUnordered<BoxFuture<'static, Option<Tagged<L>>>>>,
+}
+
+enum Event<L: StreamTypes> {
+    Accepted(Result<Channel<L>, L::AcceptError>),
+    Tagged(Option<Tagged<L>>),
+}
+
+impl<L: Listener<In = Vec<u8>, Out = Vec<u8>>> Shared<L> {
+    /// Accept channels from the inner listener until one for `tag` arrives.
+    ///
+    /// Channels for other tags are handed to their routes on the way.
+    async fn accept_for(&self, tag: u16) -> Result<(Channel<L>, Vec<u8>), L::AcceptError> {
+        let mut pending = self.pending.lock().await;
+        loop {
+            let accepted = async { Event::<L>::Accepted(self.inner.accept().await) };
+            let tagged = async {
+                if pending.is_empty() {
+                    future::pending().await
+                } else {
+                    Event::Tagged(pending.next().await.flatten())
+                }
+            };
+            match future::or(tagged, accepted).await {
+                Event::Accepted(Ok((send, recv))) => {
+                    pending.push(Box::pin(read_tag::<L>(send, recv)));
+                }
+                Event::Accepted(Err(cause)) => return Err(cause),
+                Event::Tagged(Some((other, channel, first))) => {
+                    if other == tag {
+                        return Ok((channel, first));
+                    }
+                    let mut routes = self.routes.lock().unwrap();
+                    if let Some((_, sender)) = routes.get_mut(&other) {
+                        if let Err(cause) = sender.try_send((channel, first)) {
+                            if cause.is_full() {
+                                debug!(
+                                    "dropping channel for service tag {other}, its route is full"
+                                );
+                            }
+                        }
+                    } else {
+                        debug!("dropping channel for service tag {other} without a route");
+                    }
+                }
+                Event::Tagged(None) => {}
+            }
+        }
+    }
+}
+
+/// A listener that serves several services over the connections of an inner byte
+/// frame listener, see the [module docs](self)
+pub struct MultiplexListener<L: StreamTypes, C = BincodeCodec> {
+    shared: Arc<Shared<L>>,
+    codec: C,
+}
+
+impl<L: StreamTypes> MultiplexListener<L> {
+    /// Create a new multiplex listener using [BincodeCodec]
+    pub fn new(inner: L) -> Self {
+        Self::with_codec(inner, BincodeCodec)
+    }
+}
+
+impl<L: StreamTypes, C: Codec> MultiplexListener<L, C> {
+    /// Create a new multiplex listener using the given codec for the messages
+    pub fn with_codec(inner: L, codec: C) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                inner,
+                routes: Default::default(),
+                pending: Default::default(),
+            }),
+            codec,
+        }
+    }
+
+    /// Create the listener for the service `S`.
+    ///
+    /// Fails if another service with the same [ServiceTag::TAG] is already routed.
+    pub fn route<S: ServiceTag>(&self) -> Result<RoutedListener<S, L, C>, RouteError> {
+        let mut routes = self.shared.routes.lock().unwrap();
+        if let Some(&(existing, _)) = routes.get(&S::TAG) {
+            return Err(RouteError {
+                tag: S::TAG,
+                existing,
+            });
+        }
+        // the sender has a slot of its own in addition to the buffer
+        let (sender, receiver) = mpsc::channel(ROUTE_BUFFER - 1);
+        routes.insert(S::TAG, (std::any::type_name::<S>(), sender));
+        Ok(RoutedListener {
+            route: Arc::new(Route {
+                shared: self.shared.clone(),
+                tag: S::TAG,
+                channels: AsyncMutex::new(receiver),
+            }),
+            codec: self.codec.clone(),
+            _p: PhantomData,
+        })
+    }
+}
+
+impl<L: StreamTypes + Debug, C: Debug> Debug for MultiplexListener<L, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tags = self
+            .shared
+            .routes
+            .lock()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        f.debug_struct("MultiplexListener")
+            .field("inner", &self.shared.inner)
+            .field("codec", &self.codec)
+            .field("tags", &tags)
+            .finish()
+    }
+}
+
+/// The route of a [RoutedListener], removed once all clones of it are dropped
+struct Route<L: StreamTypes> {
+    shared: Arc<Shared<L>>,
+    tag: u16,
+    /// Channels for this route that another routed listener accepted
+    channels: AsyncMutex<mpsc::Receiver<(Channel<L>, Vec<u8>)>>,
+}
+
+impl<L: StreamTypes> Drop for Route<L> {
+    fn drop(&mut self) {
+        self.shared.routes.lock().unwrap().remove(&self.tag);
+    }
+}
+
+/// The listener for a single service of a [MultiplexListener]
+pub struct RoutedListener<S, L: StreamTypes, C = BincodeCodec> {
+    route: Arc<Route<L>>,
+    codec: C,
+    _p: PhantomData<S>,
+}
+
+impl<S, L: StreamTypes, C: Clone> Clone for RoutedListener<S, L, C> {
+    fn clone(&self) -> Self {
+        Self {
+            route: self.route.clone(),
+            codec: self.codec.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<S, L: StreamTypes + Debug, C: Debug> Debug for RoutedListener<S, L, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RoutedListener")
+            .field("inner", &self.route.shared.inner)
+            .field("codec", &self.codec)
+            .field("tag", &self.route.tag)
+            .finish()
+    }
+}
+
+impl<S, L, C> ConnectionErrors for RoutedListener<S, L, C>
+where
+    S: ServiceTag,
+    L: StreamTypes,
+    C: Codec,
+{
+    type SendError = MultiplexSendError<L::SendError>;
+    type RecvError = MultiplexRecvError<L::RecvError>;
+    type OpenError = L::OpenError;
+    type AcceptError = L::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        matches!(error, MultiplexSendError::Inner(e) if L::is_remote_closed(e))
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        matches!(error, MultiplexRecvError::Inner(e) if L::is_clean_close(e))
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        matches!(error, MultiplexRecvError::Inner(e) if L::is_unknown_message(e))
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        matches!(error, MultiplexRecvError::Inner(e) if L::is_reset(e))
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        matches!(error, MultiplexRecvError::Inner(e) if L::is_cancelled(e))
+    }
+}
+
+impl<S, L, C> StreamTypes for RoutedListener<S, L, C>
+where
+    S: ServiceTag,
+    L: StreamTypes<In = Vec<u8>, Out = Vec<u8>>,
+    C: Codec,
+{
+    type In = S::Req;
+    type Out = S::Res;
+    type RecvStream = RecvStream<L::RecvStream, S::Req, C>;
+    type SendSink = SendSink<L::SendSink, S::Res, C>;
+}
+
+impl<S, L, C> Listener for RoutedListener<S, L, C>
+where
+    S: ServiceTag,
+    L: Listener<In = Vec<u8>, Out = Vec<u8>>,
+    C: Codec,
+{
+    fn accept(
+        &self,
+    ) -> impl Future<Output = Result<(Self::SendSink, Self::RecvStream), Self::AcceptError>> + Send
+    {
+        async move {
+            let mut channels = self.route.channels.lock().await;
+            let routed = async {
+                match channels.next().await {
+                    Some(channel) => Ok(channel),
+                    // the sender is in the routes for as long as the route exists
+                    None => future::pending().await,
+                }
+            };
+            let accepted = self.route.shared.accept_for(self.route.tag);
+            let ((send, recv), first) = future::or(routed, accepted).await?;
+            Ok((
+                SendSink::new(send, self.codec.clone(), None),
+                RecvStream::new(recv, self.codec.clone(), Some(first)),
+            ))
+        }
+    }
+
+    fn local_addr(&self) -> &[LocalAddr] {
+        self.route.shared.inner.local_addr()
+    }
+
+    fn remote_info(recv: &Self::RecvStream) -> Option<Arc<RemoteInfo>> {
+        L::remote_info(&recv.inner)
+    }
+}
+
+/// A connector that opens channels for several services on an inner byte frame
+/// connector, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct MultiplexConnector<C, Co = BincodeCodec> {
+    inner: C,
+    codec: Co,
+}
+
+impl<C> MultiplexConnector<C> {
+    /// Create a new multiplex connector using [BincodeCodec]
+    pub fn new(inner: C) -> Self {
+        Self::with_codec(inner, BincodeCodec)
+    }
+}
+
+impl<C, Co: Codec> MultiplexConnector<C, Co> {
+    /// Create a new multiplex connector using the given codec for the messages
+    pub fn with_codec(inner: C, codec: Co) -> Self {
+        Self { inner, codec }
+    }
+
+    /// Create the connector for the service `S`, which shares the inner connector
+    pub fn route<S: ServiceTag>(&self) -> RoutedConnector<S, C, Co>
+    where
+        C: Clone,
+    {
+        RoutedConnector {
+            inner: self.inner.clone(),
+            codec: self.codec.clone(),
+            _p: PhantomData,
+        }
+    }
+
+    /// Get the inner connector
+    pub fn into_inner(self) -> C {
+        self.inner
+    }
+}
+
+/// The connector for a single service of a [MultiplexConnector]
+///
+/// Every channel it opens starts with the tag of `S`.
+pub struct RoutedConnector<S, C, Co = BincodeCodec> {
+    inner: C,
+    codec: Co,
+    _p: PhantomData<S>,
+}
+
+impl<S, C: Clone, Co: Clone> Clone for RoutedConnector<S, C, Co> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            codec: self.codec.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<S: ServiceTag, C: Debug, Co: Debug> Debug for RoutedConnector<S, C, Co> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RoutedConnector")
+            .field("inner", &self.inner)
+            .field("codec", &self.codec)
+            .field("tag", &S::TAG)
+            .finish()
+    }
+}
+
+impl<S, C, Co> ConnectionErrors for RoutedConnector<S, C, Co>
+where
+    S: ServiceTag,
+    C: ConnectionErrors,
+    Co: Codec,
+{
+    type SendError = MultiplexSendError<C::SendError>;
+    type RecvError = MultiplexRecvError<C::RecvError>;
+    type OpenError = C::OpenError;
+    type AcceptError = C::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        matches!(error, MultiplexSendError::Inner(e) if C::is_remote_closed(e))
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        matches!(error, MultiplexRecvError::Inner(e) if C::is_clean_close(e))
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        matches!(error, MultiplexRecvError::Inner(e) if C::is_unknown_message(e))
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        matches!(error, MultiplexRecvError::Inner(e) if C::is_reset(e))
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        matches!(error, MultiplexRecvError::Inner(e) if C::is_cancelled(e))
+    }
+}
+
+impl<S, C, Co> StreamTypes for RoutedConnector<S, C, Co>
+where
+    S: ServiceTag,
+    C: StreamTypes<In = Vec<u8>, Out = Vec<u8>>,
+    Co: Codec,
+{
+    type In = S::Res;
+    type Out = S::Req;
+    type RecvStream = RecvStream<C::RecvStream, S::Res, Co>;
+    type SendSink = SendSink<C::SendSink, S::Req, Co>;
+}
+
+impl<S, C, Co> Connector for RoutedConnector<S, C, Co>
+where
+    S: ServiceTag,
+    C: Connector<In = Vec<u8>, Out = Vec<u8>>,
+    Co: Codec,
+{
+    fn open(
+        &self,
+    ) -> impl Future<Output = Result<(Self::SendSink, Self::RecvStream), Self::OpenError>> + Send
+    {
+        let inner = self.inner.open();
+        let codec = self.codec.clone();
+        async move {
+            let (send, recv) = inner.await?;
+            Ok((
+                SendSink::new(send, codec.clone(), Some(S::TAG)),
+                RecvStream::new(recv, codec, None),
+            ))
+        }
+    }
+
+    fn stats(&self) -> Option<ConnectionStats> {
+        self.inner.stats()
+    }
+
+    fn ping(&self) -> impl Future<Output = Result<Duration, PingError>> + Send {
+        self.inner.ping()
+    }
+}
+
+/// A stream that deserializes incoming frames of a multiplexed channel
+pub struct RecvStream<S, In, C> {
+    inner: S,
+    codec: C,
+    /// The rest of the first frame, after the tag
+    first: Option<Vec<u8>>,
+    _p: PhantomData<In>,
+}
+
+impl<S, In, C> RecvStream<S, In, C> {
+    fn new(inner: S, codec: C, first: Option<Vec<u8>>) -> Self {
+        Self {
+            inner,
+            codec,
+            first,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<S, In, C> Debug for RecvStream<S, In, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecvStream").finish_non_exhaustive()
+    }
+}
+
+impl<S, In, C, E> Stream for RecvStream<S, In, C>
+where
+    S: Stream<Item = Result<Vec<u8>, E>> + Unpin,
+    In: serde::de::DeserializeOwned + Unpin,
+    C: Codec,
+    E: RpcError,
+{
+    type Item = Result<In, MultiplexRecvError<E>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let frame = match this.first.take() {
+            Some(frame) => frame,
+            None => match this.inner.poll_next(cx) {
+                Poll::Ready(Some(Ok(frame))) => frame,
+                Poll::Ready(Some(Err(e))) => {
+                    return Poll::Ready(Some(Err(MultiplexRecvError::Inner(e))))
+                }
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            },
+        };
+        let item = this.codec.decode(frame.into());
+        Poll::Ready(Some(item.map_err(MultiplexRecvError::Decode)))
+    }
+}
+
+/// A sink that serializes outgoing messages of a multiplexed channel
+pub struct SendSink<S, Out, C> {
+    inner: S,
+    codec: C,
+    /// The tag to put in front of the first frame, on the client side
+    tag: Option<u16>,
+    _p: PhantomData<Out>,
+}
+
+impl<S, Out, C> SendSink<S, Out, C> {
+    fn new(inner: S, codec: C, tag: Option<u16>) -> Self {
+        Self {
+            inner,
+            codec,
+            tag,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<S, Out, C> Debug for SendSink<S, Out, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendSink").finish_non_exhaustive()
+    }
+}
+
+impl<S, Out, C> Sink<Out> for SendSink<S, Out, C>
+where
+    S: Sink<Vec<u8>> + Unpin,
+    Out: serde::Serialize + Unpin,
+    C: Codec,
+{
+    type Error = MultiplexSendError<S::Error>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut()
+            .inner
+            .poll_ready_unpin(cx)
+            .map_err(MultiplexSendError::Inner)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Out) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        let data = this
+            .codec
+            .encode(&item)
+            .map_err(MultiplexSendError::Encode)?;
+        let frame = match this.tag.take() {
+            Some(tag) => {
+                let mut frame = Vec::with_capacity(TAG_SIZE + data.len());
+                frame.extend_from_slice(&tag.to_be_bytes());
+                frame.extend_from_slice(&data);
+                frame
+            }
+            None => data.into(),
+        };
+        this.inner
+            .start_send_unpin(frame)
+            .map_err(MultiplexSendError::Inner)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut()
+            .inner
+            .poll_flush_unpin(cx)
+            .map_err(MultiplexSendError::Inner)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut()
+            .inner
+            .poll_close_unpin(cx)
+            .map_err(MultiplexSendError::Inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tag_roundtrip() {
+        let frame = [0x12, 0x34, 1, 2, 3].to_vec();
+        assert_eq!(split_tag(frame), Some((0x1234, vec![1, 2, 3])));
+        assert_eq!(split_tag(vec![7]), None);
+    }
+}
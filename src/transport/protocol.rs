@@ -0,0 +1,232 @@
+//! The wire format of the byte based transports, and a [RawProtocol] to speak it
+//!
+//! This describes what goes over a channel of the [quinn](super::quinn),
+//! [iroh-net](super::iroh_net), [tcp](super::tcp) and [uds](super::uds) transports, so
+//! clients and servers can be written in other languages. The test vectors in
+//! `tests/protocol.rs` show complete interactions byte by byte.
+//!
+//! # Channels
+//!
+//! Every interaction uses its own channel, which is an ordered, reliable byte stream in
+//! each direction that can be closed in one direction while the other stays open: a
+//! bidi stream for quinn and iroh-net, a yamux substream for tcp and a connection for
+//! uds. The client opens the channel, the server never opens one for a call.
+//!
+//! # Frames
+//!
+//! Both directions are a sequence of frames. A frame is the length of the payload as a
+//! big endian `u32`, followed by the payload. The transports reject frames larger than
+//! their maximum frame size, 16 MiB by default.
+//!
+//! The client sends the service request type `S::Req`, the server sends the service
+//! response type `S::Res`, one message per frame. The payload is the message encoded
+//! with the [Codec] of the transport, [BincodeCodec] by default, which is bincode with
+//! fixed size integers in little endian. In that encoding:
+//!
+//! - an enum is the index of the variant as a `u32`, followed by its fields
+//! - a struct is its fields in order, a unit struct is empty
+//! - integers have their full size, a `bool` is one byte
+//! - a string, byte array or sequence is its length as a `u64`, followed by the items
+//! - an option is a `0` byte for `None`, or a `1` byte followed by the value
+//!
+//! # Patterns
+//!
+//! The first frame from the client is the request, which determines the pattern. Then:
+//!
+//! - **rpc**: the server sends one response and closes its side.
+//! - **server streaming**: the server sends any number of responses and closes its
+//!   side. The client keeps its side open, closing it cancels the call.
+//! - **client streaming**: the client sends any number of updates and closes its side.
+//!   The server sends one response and closes its side.
+//! - **bidi streaming**: the client sends updates and the server sends responses at the
+//!   same time. Closing the client side ends the updates, closing the server side ends
+//!   the responses.
+//!
+//! Updates are also of the request type, responses of the response type. A server that
+//! closes its side before the response of an rpc or client streaming call failed to
+//! handle it, for example because the request was unknown.
+//!
+//! Extensions that both sides have to opt into, like the substream reuse of
+//! [QuinnConnector::with_substream_pool](super::quinn::QuinnConnector::with_substream_pool),
+//! are not part of this format.
+use std::{error, fmt, io};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    backtrace::ErrorTrace,
+    codec::{BincodeCodec, Codec},
+};
+
+/// The maximum frame size the transports use by default
+pub(crate) const MAX_FRAME_LENGTH: usize = 1024 * 1024 * 16;
+
+/// A frame exceeds the maximum frame size
+///
+/// Transports that report errors as [io::Error] use this as the inner error, with
+/// [io::ErrorKind::InvalidData].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameTooLarge {
+    /// Size of the frame
+    pub size: usize,
+    /// Maximum frame size
+    pub limit: usize,
+    /// Where the error was constructed, see [super::backtrace]
+    pub backtrace: ErrorTrace,
+}
+
+impl fmt::Display for FrameTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "frame of {} bytes exceeds the limit of {} bytes",
+            self.size, self.limit
+        )
+    }
+}
+
+impl error::Error for FrameTooLarge {}
+
+impl From<FrameTooLarge> for io::Error {
+    fn from(value: FrameTooLarge) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, value)
+    }
+}
+
+/// The [FrameTooLarge] error of an io error, if any
+pub fn frame_too_large(error: &io::Error) -> Option<&FrameTooLarge> {
+    error.get_ref()?.downcast_ref()
+}
+
+/// Reads frames with a big endian u32 length prefix
+///
+/// The length prefix is checked against the limit before anything is buffered, so a
+/// peer can not make us allocate more than the limit. Complete frames are split off the
+/// read buffer, so the [Codec] gets them without a copy.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct LengthPrefixed {
+    pub(super) limit: usize,
+}
+
+impl LengthPrefixed {
+    /// Take the first frame off `src`, or `None` if it is not complete yet
+    pub(super) fn decode(&self, src: &mut BytesMut) -> io::Result<Option<BytesMut>> {
+        if src.len() < 4 {
+            return Ok(None);
+        }
+        let size = u32::from_be_bytes([src[0], src[1], src[2], src[3]]) as usize;
+        if size > self.limit {
+            return Err(FrameTooLarge {
+                size,
+                limit: self.limit,
+                backtrace: ErrorTrace::capture(),
+            }
+            .into());
+        }
+        if src.len() < 4 + size {
+            src.reserve(4 + size - src.len());
+            return Ok(None);
+        }
+        src.advance(4);
+        Ok(Some(src.split_to(size)))
+    }
+}
+
+/// Encoder and decoder of frames, without a transport
+///
+/// This produces and consumes exactly the bytes that the transports send over a channel,
+/// see the [module docs](self). It only works on buffers, so it can be used with any
+/// byte stream, or to check an implementation in another language.
+///
+/// ```
+/// use bytes::BytesMut;
+/// use quic_rpc::transport::protocol::RawProtocol;
+///
+/// let protocol = RawProtocol::new();
+/// let mut buf = BytesMut::new();
+/// protocol.encode(&(7u8, 258u16), &mut buf).unwrap();
+/// assert_eq!(&buf[..], [0, 0, 0, 3, 7, 2, 1]);
+/// let msg = protocol.decode::<(u8, u16)>(&mut buf).unwrap();
+/// assert_eq!(msg, Some((7, 258)));
+/// assert!(buf.is_empty());
+/// ```
+#[derive(Debug, Clone)]
+pub struct RawProtocol<C = BincodeCodec> {
+    codec: C,
+    max_frame_length: usize,
+}
+
+impl Default for RawProtocol {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RawProtocol {
+    /// Frames with the default [BincodeCodec]
+    pub fn new() -> Self {
+        Self::with_codec(BincodeCodec)
+    }
+}
+
+impl<C: Codec> RawProtocol<C> {
+    /// Frames with the given codec, which has to be the codec of the remote
+    pub fn with_codec(codec: C) -> Self {
+        Self {
+            codec,
+            max_frame_length: MAX_FRAME_LENGTH,
+        }
+    }
+
+    /// Set the maximum frame size, larger frames fail with [FrameTooLarge].
+    ///
+    /// The default is the default of the transports, 16 MiB.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_length = max_frame_size.min(u32::MAX as usize);
+        self
+    }
+
+    /// Append a frame with the encoded `msg` to `buf`
+    pub fn encode<M: Serialize>(&self, msg: &M, buf: &mut BytesMut) -> io::Result<()> {
+        let payload = self.codec.encode(msg)?;
+        self.encode_raw(&payload, buf)
+    }
+
+    /// Append a frame with a payload that is already encoded to `buf`
+    pub fn encode_raw(&self, payload: &[u8], buf: &mut BytesMut) -> io::Result<()> {
+        if payload.len() > self.max_frame_length {
+            return Err(FrameTooLarge {
+                size: payload.len(),
+                limit: self.max_frame_length,
+                backtrace: ErrorTrace::capture(),
+            }
+            .into());
+        }
+        buf.reserve(4 + payload.len());
+        buf.put_u32(payload.len() as u32);
+        buf.extend_from_slice(payload);
+        Ok(())
+    }
+
+    /// Take the first frame off `buf` and decode it
+    ///
+    /// Returns `None` if `buf` does not contain a complete frame yet, then more data has
+    /// to be appended before trying again.
+    pub fn decode<M: DeserializeOwned>(&self, buf: &mut BytesMut) -> io::Result<Option<M>> {
+        match self.decode_raw(buf)? {
+            Some(payload) => self.codec.decode(payload).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Take the first frame off `buf`, without decoding its payload
+    ///
+    /// Returns `None` if `buf` does not contain a complete frame yet.
+    pub fn decode_raw(&self, buf: &mut BytesMut) -> io::Result<Option<Bytes>> {
+        let framing = LengthPrefixed {
+            limit: self.max_frame_length,
+        };
+        Ok(framing.decode(buf)?.map(BytesMut::freeze))
+    }
+}
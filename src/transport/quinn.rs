@@ -1,39 +1,787 @@
 //! QUIC transport implementation based on [quinn](https://crates.io/crates/quinn)
 use std::{
+    collections::{HashMap, VecDeque},
     fmt, io,
     marker::PhantomData,
-    net::SocketAddr,
+    net::{IpAddr, SocketAddr},
     pin::Pin,
     result,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, OnceLock, PoisonError, Weak,
+    },
     task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
 };
-use futures::channel::oneshot;
+use bytes::Bytes;
+use futures::{channel::oneshot, task::AtomicWaker};
 use futures_lite::{Future, Stream, StreamExt};
 use futures_sink::Sink;
-use futures_util::FutureExt;
-use glib::JoinHandle;
-use pin_project::pin_project;
+use futures_util::{future::BoxFuture, FutureExt};
 use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::{broadcast, Notify};
+use tokio::task::JoinHandle;
 use tracing::{debug_span, Instrument};
 
 use super::{
-    util::{FramedBincodeRead, FramedBincodeWrite},
-    StreamTypes,
+    backtrace::ErrorTrace,
+    boxed::{box_recv_stream, box_send_sink},
+    codec::{BincodeCodec, Codec},
+    lifecycle::{ConnectionObserver, LifecycleEvent, LifecycleObserver},
+    protocol::MAX_FRAME_LENGTH,
+    util::{self, FramedCodecRead, FramedCodecWrite},
+    RawStreamTypes, StreamTypes,
 };
 use crate::{
-    transport::{ConnectionErrors, Connector, Listener, LocalAddr},
+    metrics::ConnectionCounters,
+    transport::{
+        ConnectionErrors, ConnectionStats, Connector, DatagramError, DatagramSocket, Listener,
+        LocalAddr, PingError, PriorityError, RemoteInfo,
+    },
     RpcMessage,
 };
 
-const MAX_FRAME_LENGTH: usize = 1024 * 1024 * 16;
+pub use super::{
+    protocol::{frame_too_large, FrameTooLarge},
+    util::{RawRecvStream, RawSendSink, CANCEL_CODE},
+};
+
+/// Number of [ConnectionEvent]s buffered for each subscriber
+const EVENTS_CAPACITY: usize = 16;
+
+/// Number of received datagrams buffered by a [QuinnConnector], see
+/// [QuinnConnector::datagrams]
+const DATAGRAMS_CAPACITY: usize = 64;
+
+/// Keep-alive settings for a [QuinnConnector]
+///
+/// There are two independent mechanisms:
+///
+/// - QUIC-level keep-alive, where quinn sends PING frames when the connection is idle.
+///   This keeps NAT and firewall mappings open, and is invisible to the application.
+/// - An application-level heartbeat, where the connector periodically sends a heartbeat
+///   on a separate unidirectional stream and the listener echoes it back. This is never
+///   visible to the rpc messages, but allows detecting dead peers using
+///   [QuinnConnector::last_alive]. The listener has to answer heartbeats, see
+///   [QuinnListener::with_heartbeat_responder], and allow the connector to open at
+///   least one unidirectional stream.
+#[derive(Debug, Clone, Default)]
+pub struct KeepAliveConfig {
+    keep_alive_interval: Option<Duration>,
+    heartbeat_interval: Option<Duration>,
+}
+
+impl KeepAliveConfig {
+    /// Send QUIC PING frames when no other data has been sent for this long.
+    ///
+    /// This should be lower than the idle timeout of both the connection and any NATs
+    /// or firewalls between the peers. The default is `None`, no keep-alive.
+    pub fn keep_alive_interval(mut self, value: Option<Duration>) -> Self {
+        self.keep_alive_interval = value;
+        self
+    }
+
+    /// Send an application-level heartbeat at this interval.
+    ///
+    /// The default is `None`, no heartbeat.
+    pub fn heartbeat_interval(mut self, value: Option<Duration>) -> Self {
+        self.heartbeat_interval = value;
+        self
+    }
+}
+
+/// quinn's default for [TransportSettings::max_idle_timeout]
+const DEFAULT_MAX_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// QUIC transport parameters for a [QuinnListener] or a [QuinnConnector]
+///
+/// Settings that are not set keep quinn's defaults. The settings are validated when
+/// the [quinn::TransportConfig] is created, see [TransportSettings::transport_config].
+///
+/// The stream limits apply to the streams the remote may open, so for rpc channels
+/// they are only relevant for the listener: each channel is a bidirectional stream
+/// opened by the connector.
+#[derive(Debug, Clone)]
+pub struct TransportSettings {
+    keep_alive: KeepAliveConfig,
+    max_idle_timeout: Option<Duration>,
+    max_concurrent_bidi_streams: Option<u32>,
+    max_concurrent_uni_streams: Option<u32>,
+    stream_receive_window: Option<u32>,
+    send_window: Option<u64>,
+}
+
+impl Default for TransportSettings {
+    fn default() -> Self {
+        Self {
+            keep_alive: KeepAliveConfig::default(),
+            max_idle_timeout: Some(DEFAULT_MAX_IDLE_TIMEOUT),
+            max_concurrent_bidi_streams: None,
+            max_concurrent_uni_streams: None,
+            stream_receive_window: None,
+            send_window: None,
+        }
+    }
+}
+
+impl TransportSettings {
+    /// Keep-alive and heartbeat settings.
+    ///
+    /// The heartbeat is only sent by a [QuinnConnector]. The QUIC-level keep-alive
+    /// interval has to be lower than [TransportSettings::max_idle_timeout].
+    pub fn keep_alive(mut self, value: KeepAliveConfig) -> Self {
+        self.keep_alive = value;
+        self
+    }
+
+    /// Close the connection if nothing was received from the remote for this long.
+    ///
+    /// The effective timeout is the lower of the two values of both peers. `None`
+    /// disables the timeout. The default is 30 seconds.
+    pub fn max_idle_timeout(mut self, value: Option<Duration>) -> Self {
+        self.max_idle_timeout = value;
+        self
+    }
+
+    /// Maximum number of bidirectional streams, and therefore rpc channels, the remote
+    /// may have open at the same time.
+    pub fn max_concurrent_bidi_streams(mut self, value: u32) -> Self {
+        self.max_concurrent_bidi_streams = Some(value);
+        self
+    }
+
+    /// Maximum number of unidirectional streams the remote may have open at the same
+    /// time.
+    ///
+    /// A listener needs to allow at least one for heartbeats, see [KeepAliveConfig].
+    pub fn max_concurrent_uni_streams(mut self, value: u32) -> Self {
+        self.max_concurrent_uni_streams = Some(value);
+        self
+    }
+
+    /// Maximum number of bytes the remote may send on a single stream before it is
+    /// read.
+    pub fn stream_receive_window(mut self, value: u32) -> Self {
+        self.stream_receive_window = Some(value);
+        self
+    }
+
+    /// Maximum number of bytes sent but not yet acknowledged, over all streams of a
+    /// connection.
+    pub fn send_window(mut self, value: u64) -> Self {
+        self.send_window = Some(value);
+        self
+    }
+
+    /// Validate the settings and create a transport config from them.
+    ///
+    /// This is useful to configure endpoints that are not created by this module.
+    pub fn transport_config(&self) -> Result<quinn::TransportConfig, TransportConfigError> {
+        if let (Some(keep_alive), Some(idle_timeout)) =
+            (self.keep_alive.keep_alive_interval, self.max_idle_timeout)
+        {
+            if keep_alive >= idle_timeout {
+                return Err(TransportConfigError::KeepAliveNotBelowIdleTimeout {
+                    keep_alive,
+                    idle_timeout,
+                });
+            }
+        }
+        let idle_timeout = self
+            .max_idle_timeout
+            .map(quinn::IdleTimeout::try_from)
+            .transpose()
+            .map_err(|_| TransportConfigError::IdleTimeoutTooLarge)?;
+        let mut transport = quinn::TransportConfig::default();
+        transport
+            .keep_alive_interval(self.keep_alive.keep_alive_interval)
+            .max_idle_timeout(idle_timeout);
+        if let Some(value) = self.max_concurrent_bidi_streams {
+            transport.max_concurrent_bidi_streams(value.into());
+        }
+        if let Some(value) = self.max_concurrent_uni_streams {
+            transport.max_concurrent_uni_streams(value.into());
+        }
+        if let Some(value) = self.stream_receive_window {
+            transport.stream_receive_window(value.into());
+        }
+        if let Some(value) = self.send_window {
+            transport.send_window(value);
+        }
+        Ok(transport)
+    }
+}
+
+/// Error when creating a transport config from [TransportSettings]
+#[derive(Debug)]
+pub enum TransportConfigError {
+    /// The keep-alive interval is not lower than the idle timeout, so the connection
+    /// would time out before a keep-alive is sent
+    KeepAliveNotBelowIdleTimeout {
+        /// The configured keep-alive interval
+        keep_alive: Duration,
+        /// The configured idle timeout
+        idle_timeout: Duration,
+    },
+    /// The idle timeout is too large to be encoded in the transport parameters
+    IdleTimeoutTooLarge,
+    /// Creating the listener failed
+    Io(io::Error),
+}
+
+impl From<io::Error> for TransportConfigError {
+    fn from(e: io::Error) -> Self {
+        TransportConfigError::Io(e)
+    }
+}
+
+impl fmt::Display for TransportConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for TransportConfigError {}
+
+/// Create a transport config that only differs from the default in the keep-alive interval
+fn keep_alive_transport_config(keep_alive_interval: Option<Duration>) -> quinn::TransportConfig {
+    let mut transport = quinn::TransportConfig::default();
+    transport.keep_alive_interval(keep_alive_interval);
+    transport
+}
+
+/// The last time a heartbeat was echoed by the remote
+#[derive(Debug, Clone, Default)]
+struct LastAlive(Arc<Mutex<Option<Instant>>>);
+
+impl LastAlive {
+    fn touch(&self) {
+        *self.0.lock().unwrap() = Some(Instant::now());
+    }
+
+    fn get(&self) -> Option<Instant> {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// The connection the client currently uses
+#[derive(Debug, Clone, Default)]
+struct CurrentConnection(Arc<Mutex<Option<quinn::Connection>>>);
+
+impl CurrentConnection {
+    fn set(&self, connection: quinn::Connection) {
+        *self.0.lock().unwrap() = Some(connection);
+    }
+
+    /// The current connection, unless it is closed
+    fn get(&self) -> Option<quinn::Connection> {
+        let guard = self.0.lock().unwrap();
+        let connection = guard.as_ref()?;
+        connection
+            .close_reason()
+            .is_none()
+            .then(|| connection.clone())
+    }
+
+    /// Refer to `connection`, which must be the current one, without keeping it alive
+    fn downgrade(&self, connection: &quinn::Connection) -> WeakConnection {
+        WeakConnection {
+            current: Arc::downgrade(&self.0),
+            id: connection.stable_id(),
+        }
+    }
+
+    fn stats(&self) -> Option<ConnectionStats> {
+        let guard = self.0.lock().unwrap();
+        let connection = guard.as_ref()?;
+        if connection.close_reason().is_some() {
+            return None;
+        }
+        let stats = connection.stats();
+        Some(ConnectionStats {
+            rtt: stats.path.rtt,
+            cwnd: stats.path.cwnd,
+            lost_packets: stats.path.lost_packets,
+            sent_bytes: stats.udp_tx.bytes,
+            recv_bytes: stats.udp_rx.bytes,
+        })
+    }
+}
+
+/// A connection of a [CurrentConnection], that is gone once the connector is dropped or
+/// uses another connection
+#[derive(Debug)]
+struct WeakConnection {
+    current: Weak<Mutex<Option<quinn::Connection>>>,
+    id: usize,
+}
+
+impl WeakConnection {
+    fn upgrade(&self) -> Option<quinn::Connection> {
+        let current = self.current.upgrade()?;
+        let guard = current.lock().unwrap();
+        guard
+            .as_ref()
+            .filter(|connection| connection.stable_id() == self.id)
+            .cloned()
+    }
+}
+
+/// How often the path of a connection is checked for changes, see [ConnectionEvent::Migrated]
+const PATH_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A change of the connection of a [QuinnConnector], see [QuinnConnector::events]
+///
+/// These are about the connection as a whole. Errors of single channels are still
+/// reported by the channels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ConnectionEvent {
+    /// The first connection to the remote was established
+    Connected {
+        /// Address of the remote
+        remote: SocketAddr,
+    },
+    /// A new connection was established after the previous one was lost
+    Reconnected {
+        /// Address of the remote
+        remote: SocketAddr,
+    },
+    /// The path of the connection changed, e.g. because the local network changed
+    ///
+    /// The connection stays open, but its congestion state was reset, so throughput
+    /// may be low for a while.
+    Migrated {
+        /// Address of the remote
+        remote: SocketAddr,
+        /// Local IP address used for the connection, if known
+        local_ip: Option<IpAddr>,
+    },
+    /// The connection was lost. A connector created with [QuinnConnector::new] reconnects
+    /// when the next channel is opened.
+    Lost(quinn::ConnectionError),
+    /// Resolving a [Remote] failed, so no connection was made. Opening the channel that
+    /// triggered the attempt fails with [quinn::ConnectionError::Reset].
+    ResolveFailed(ResolveError),
+}
+
+/// Report the changes of a connection until it is closed, or the connector is dropped
+///
+/// quinn does not report path changes, so they are detected by checking the addresses
+/// every [PATH_CHECK_INTERVAL]. The connection is only kept alive until the next check.
+async fn watch_connection(connection: WeakConnection, events: broadcast::Sender<ConnectionEvent>) {
+    let mut current = None;
+    while let Some(connection) = connection.upgrade() {
+        let (remote, local_ip) = (connection.remote_address(), connection.local_ip());
+        if current.is_some_and(|current| current != (remote, local_ip)) {
+            events
+                .send(ConnectionEvent::Migrated { remote, local_ip })
+                .ok();
+        }
+        current = Some((remote, local_ip));
+        let tick = tokio::time::sleep(PATH_CHECK_INTERVAL).map(|_| None);
+        if let Some(reason) = futures_lite::future::or(connection.closed().map(Some), tick).await {
+            events.send(ConnectionEvent::Lost(reason)).ok();
+            return;
+        }
+    }
+}
+
+/// A heartbeat on the echo stream, see [echo]
+const HEARTBEAT: u8 = 0;
+/// A ping on the echo stream, see [echo]
+const PING: u8 = 1;
+/// How long [Connector::ping] waits for the answer by default
+const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A ping waiting to be sent, answered with the round trip time
+type PingRequest = oneshot::Sender<Duration>;
+
+/// What the connector sends on the echo stream of each connection
+#[derive(Debug, Clone)]
+struct EchoConfig {
+    /// Heartbeat interval, and where to record when a heartbeat was echoed
+    heartbeat: Option<(Duration, LastAlive)>,
+    /// Pings requested by [Connector::ping]
+    pings: flume::Receiver<PingRequest>,
+}
+
+/// Send heartbeats and pings to the remote, and wait for them to be echoed back
+///
+/// Both share one unidirectional stream in each direction. The remote echoes all bytes
+/// in order, so the n-th [PING] that comes back answers the n-th ping that was sent.
+/// Runs until the connection is closed, pings that are not answered by then fail.
+async fn echo(connection: quinn::Connection, config: EchoConfig) {
+    let EchoConfig { heartbeat, pings } = config;
+    let pending = Mutex::new(VecDeque::<(Instant, PingRequest)>::new());
+    let send = async {
+        let mut send = connection.open_uni().await?;
+        let mut ticker = heartbeat
+            .as_ref()
+            .map(|(interval, _)| tokio::time::interval(*interval));
+        loop {
+            let tick = async {
+                match ticker.as_mut() {
+                    Some(ticker) => ticker.tick().await,
+                    None => futures_lite::future::pending().await,
+                };
+                None
+            };
+            let ping = async {
+                match pings.recv_async().await {
+                    Ok(ping) => Some(ping),
+                    // the connector is gone, only heartbeats are left
+                    Err(_) => futures_lite::future::pending().await,
+                }
+            };
+            let byte = match futures_lite::future::or(tick, ping).await {
+                Some(ping) => {
+                    pending.lock().unwrap().push_back((Instant::now(), ping));
+                    PING
+                }
+                None => HEARTBEAT,
+            };
+            send.write_all(&[byte]).await?;
+        }
+    };
+    let recv = async {
+        let mut recv = connection.accept_uni().await?;
+        let mut buf = [0u8; 64];
+        while let Some(n) = recv.read(&mut buf).await? {
+            if let Some((_, last_alive)) = &heartbeat {
+                if n > 0 {
+                    last_alive.touch();
+                }
+            }
+            for _ in buf[..n].iter().filter(|byte| **byte == PING) {
+                if let Some((sent, ping)) = pending.lock().unwrap().pop_front() {
+                    ping.send(sent.elapsed()).ok();
+                }
+            }
+        }
+        anyhow::Ok(())
+    };
+    let res: anyhow::Result<()> = futures_lite::future::race(send, recv).await;
+    tracing::debug!("Echo finished: {:?}", res);
+}
+
+/// Forward the channels opened by the remote to [Connector::accept_push]
+///
+/// Runs until the connection is closed, or no connector is left to accept them.
+async fn accept_pushes(connection: quinn::Connection, pushes: flume::Sender<SocketInner>) {
+    loop {
+        let pair = match connection.accept_bi().await {
+            Ok(pair) => pair,
+            Err(e) => {
+                tracing::debug!("Stopped accepting pushed substreams: {}", e);
+                break;
+            }
+        };
+        tracing::debug!("Accepted pushed substream {}", pair.0.id());
+        if pushes.send_async(pair).await.is_err() {
+            break;
+        }
+    }
+}
+
+/// Starts [accept_pushes] on the connections of a connector, once it accepts pushes
+///
+/// Most clients never accept pushes, so their connections do without the task.
+#[derive(Debug, Clone)]
+struct LazyPushes(Arc<Mutex<PushState>>);
+
+#[derive(Debug)]
+struct PushState {
+    /// Where [accept_pushes] forwards the channels
+    ///
+    /// A connector that does not reconnect hands it to the task of its only connection,
+    /// so that [Connector::accept_push] returns `None` once that connection is closed.
+    sender: Option<flume::Sender<SocketInner>>,
+    /// Whether the connector makes new connections
+    reconnects: bool,
+    /// Set by the first call to [Connector::accept_push]
+    wanted: bool,
+    /// The stable id of the last connection the task was started for
+    started: Option<usize>,
+}
+
+impl LazyPushes {
+    fn new(sender: flume::Sender<SocketInner>, reconnects: bool) -> Self {
+        Self(Arc::new(Mutex::new(PushState {
+            sender: Some(sender),
+            reconnects,
+            wanted: false,
+            started: None,
+        })))
+    }
+
+    /// Accept pushes on a new connection, if the connector accepts them
+    ///
+    /// The connection must be the current one already, see [LazyPushes::want].
+    fn start(&self, connection: &quinn::Connection) {
+        let mut state = self.0.lock().unwrap();
+        if state.wanted {
+            state.spawn(connection);
+        }
+    }
+
+    /// Accept pushes on the current connection and all later ones
+    fn want(&self, current: &CurrentConnection) {
+        let mut state = self.0.lock().unwrap();
+        state.wanted = true;
+        // read under the lock, so that a concurrent `start` either sees `wanted` or set the
+        // connection before
+        let connection = current.0.lock().unwrap().clone();
+        if let Some(connection) = connection {
+            state.spawn(&connection);
+        }
+    }
+}
+
+impl PushState {
+    fn spawn(&mut self, connection: &quinn::Connection) {
+        let id = connection.stable_id();
+        if self.started == Some(id) {
+            return;
+        }
+        let sender = match self.reconnects {
+            true => self.sender.clone(),
+            false => self.sender.take(),
+        };
+        let Some(sender) = sender else {
+            return;
+        };
+        self.started = Some(id);
+        tokio::spawn(accept_pushes(connection.clone(), sender));
+    }
+}
+
+/// Forward the datagrams sent by the remote to [QuinnConnector::datagrams]
+///
+/// Runs until the connection is closed, or no connector is left to receive them. While
+/// the buffer is full, datagrams are dropped, just like the network may drop them.
+async fn recv_datagrams(connection: quinn::Connection, datagrams: flume::Sender<Bytes>) {
+    loop {
+        let data = match connection.read_datagram().await {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::debug!("Stopped receiving datagrams: {}", e);
+                break;
+            }
+        };
+        match datagrams.try_send(data) {
+            Ok(()) => {}
+            Err(flume::TrySendError::Full(_)) => tracing::trace!("Dropped datagram"),
+            Err(flume::TrySendError::Disconnected(_)) => break,
+        }
+    }
+}
+
+impl DatagramSocket for quinn::Connection {
+    fn send_datagram(&self, data: Bytes) -> Result<(), DatagramError> {
+        let size = data.len();
+        quinn::Connection::send_datagram(self, data).map_err(|e| match e {
+            quinn::SendDatagramError::UnsupportedByPeer | quinn::SendDatagramError::Disabled => {
+                DatagramError::Unsupported
+            }
+            quinn::SendDatagramError::TooLarge => DatagramError::TooLarge {
+                size,
+                max: quinn::Connection::max_datagram_size(self).unwrap_or(0),
+            },
+            quinn::SendDatagramError::ConnectionLost(_) => DatagramError::Closed,
+        })
+    }
+
+    fn max_datagram_size(&self) -> Option<usize> {
+        quinn::Connection::max_datagram_size(self)
+    }
+
+    fn read_datagram(&self) -> BoxFuture<'static, Option<Bytes>> {
+        let connection = self.clone();
+        Box::pin(async move { connection.read_datagram().await.ok() })
+    }
+}
+
+/// What a reconnecting [QuinnConnector] starts for each new connection
+#[derive(Debug, Clone)]
+struct ConnectionTasks {
+    echo: EchoConfig,
+    current: CurrentConnection,
+    events: broadcast::Sender<ConnectionEvent>,
+    /// Channels opened by the remote, see [Connector::accept_push]
+    pushes: LazyPushes,
+    /// Datagrams sent by the remote, see [QuinnConnector::datagrams]
+    datagrams: flume::Sender<Bytes>,
+}
+
+impl ConnectionTasks {
+    fn start(&self, connection: &quinn::Connection) {
+        self.current.set(connection.clone());
+        tokio::spawn(watch_connection(
+            self.current.downgrade(connection),
+            self.events.clone(),
+        ));
+        tokio::spawn(echo(connection.clone(), self.echo.clone()));
+        self.pushes.start(connection);
+        tokio::spawn(recv_datagrams(connection.clone(), self.datagrams.clone()));
+    }
+}
+
+/// Echo heartbeats and pings sent by [echo] back to the remote
+///
+/// Runs until the connection is closed.
+async fn heartbeat_responder(connection: quinn::Connection) {
+    let res = async {
+        let mut recv = connection.accept_uni().await?;
+        let mut send = connection.open_uni().await?;
+        let mut buf = [0u8; 64];
+        while let Some(n) = recv.read(&mut buf).await? {
+            send.write_all(&buf[..n]).await?;
+        }
+        anyhow::Ok(())
+    }
+    .await;
+    tracing::debug!("Heartbeat responder finished: {:?}", res);
+}
+
+/// Server side eviction of idle connections, see [QuinnListener::with_idle_eviction]
+///
+/// Unlike [TransportSettings::max_idle_timeout], this is about the rpc channels of a
+/// connection: a connection that is kept alive by QUIC-level keep-alive or heartbeats
+/// is still evicted if it has no substreams for long enough.
+#[derive(Debug, Clone)]
+pub struct IdleEviction {
+    idle_timeout: Duration,
+    code: quinn::VarInt,
+    counters: Arc<ConnectionCounters>,
+}
+
+impl IdleEviction {
+    /// Evict connections that had no substream in use for `idle_timeout`.
+    pub fn new(idle_timeout: Duration) -> Self {
+        Self {
+            idle_timeout,
+            code: 0u32.into(),
+            counters: Default::default(),
+        }
+    }
+
+    /// The QUIC error code to close evicted connections with.
+    ///
+    /// The default is 0.
+    pub fn code(mut self, code: quinn::VarInt) -> Self {
+        self.code = code;
+        self
+    }
+
+    /// Count evicted connections in `counters`, e.g. the
+    /// [ServerMetrics::connections](crate::metrics::ServerMetrics::connections) of a server.
+    pub fn counters(mut self, counters: Arc<ConnectionCounters>) -> Self {
+        self.counters = counters;
+        self
+    }
+}
+
+/// The idle eviction of a listener, shared with its connection handlers
+#[derive(Debug, Default)]
+struct EvictionState {
+    config: Mutex<Option<IdleEviction>>,
+    /// Notified when the config changes
+    changed: Notify,
+}
+
+impl EvictionState {
+    fn set(&self, config: Option<IdleEviction>) {
+        *self.config.lock().unwrap_or_else(PoisonError::into_inner) = config;
+        self.changed.notify_waiters();
+    }
+
+    /// Wait until `activity` was idle for the configured timeout
+    async fn idle(&self, activity: &ConnectionActivity) -> IdleEviction {
+        loop {
+            // created before checking, so that no notification is missed
+            let changed = self.changed.notified();
+            let released = activity.released.notified();
+            let config = self
+                .config
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .clone();
+            match (config, activity.idle_since()) {
+                (Some(config), Some(since)) => {
+                    let remaining = config.idle_timeout.saturating_sub(since.elapsed());
+                    if remaining.is_zero() {
+                        return config;
+                    }
+                    futures_lite::future::or(tokio::time::sleep(remaining), changed).await;
+                }
+                // wait for the substreams to be dropped
+                (Some(_), None) => futures_lite::future::or(released, changed).await,
+                (None, _) => changed.await,
+            }
+        }
+    }
+}
+
+/// The substreams of a connection that are in use, see [QuinnListener::with_idle_eviction]
+///
+/// This also keeps the connection, to open push channels on it, see
+/// [Listener::connection].
+#[derive(Debug)]
+struct ConnectionActivity {
+    connection: quinn::Connection,
+    /// The [quinn::Connection::stable_id] of the connection
+    id: usize,
+    /// The number of substreams in use, and when the last one was dropped
+    state: Mutex<(usize, Instant)>,
+    /// Notified when the last substream in use is dropped
+    released: Notify,
+}
+
+impl ConnectionActivity {
+    fn new(connection: quinn::Connection) -> Self {
+        Self {
+            id: connection.stable_id(),
+            connection,
+            state: Mutex::new((0, Instant::now())),
+            released: Notify::new(),
+        }
+    }
+
+    fn acquire(&self) {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner).0 += 1;
+    }
+
+    fn release(&self) {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.0 -= 1;
+        if state.0 == 0 {
+            state.1 = Instant::now();
+            self.released.notify_waiters();
+        }
+    }
+
+    /// Since when no substream is in use, or `None` if one is
+    fn idle_since(&self) -> Option<Instant> {
+        let (open, since) = *self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        (open == 0).then_some(since)
+    }
+}
 
 #[derive(Debug)]
 struct ListenerInner {
     endpoint: Option<quinn::Endpoint>,
     task: Option<JoinHandle<()>>,
     local_addr: [LocalAddr; 1],
-    receiver: flume::Receiver<SocketInner>,
+    receiver: flume::Receiver<Incoming>,
+    /// Substreams that carry another call, see [QuinnConnector::with_substream_pool]
+    reused: (flume::Sender<Substream>, flume::Receiver<Substream>),
+    streams: StreamRegistry,
+    eviction: Arc<EvictionState>,
+    /// Reports connections, see [Listener::observe_connections]
+    lifecycle: Arc<ConnectionObserver>,
+    /// Whether heartbeats are answered, see [QuinnListener::with_heartbeat_responder]
+    heartbeats: Arc<AtomicBool>,
 }
 
 impl Drop for ListenerInner {
@@ -60,9 +808,13 @@ impl Drop for ListenerInner {
 }
 
 /// A listener using a quinn connection
+///
+/// Messages are serialized using the codec `C`, which defaults to [BincodeCodec].
 #[derive(Debug)]
-pub struct QuinnListener<In: RpcMessage, Out: RpcMessage> {
+pub struct QuinnListener<In: RpcMessage, Out: RpcMessage, C: Codec = BincodeCodec> {
     inner: Arc<ListenerInner>,
+    codec: C,
+    max_frame_size: usize,
     _p: PhantomData<(In, Out)>,
 }
 
@@ -70,29 +822,82 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnListener<In, Out> {
     /// handles RPC requests from a connection
     ///
     /// to cleanly shutdown the handler, drop the receiver side of the sender.
-    async fn connection_handler(connection: quinn::Connection, sender: flume::Sender<SocketInner>) {
+    async fn connection_handler(
+        connection: quinn::Connection,
+        sender: flume::Sender<Incoming>,
+        eviction: Arc<EvictionState>,
+        lifecycle: Arc<ConnectionObserver>,
+        heartbeats: Arc<AtomicBool>,
+    ) {
+        // heartbeats use unidirectional streams, so they never show up as substreams.
+        // The responder finishes when the connection is closed.
+        if heartbeats.load(Ordering::Acquire) {
+            tokio::spawn(heartbeat_responder(connection.clone()));
+        }
+        let id = connection.stable_id() as u64;
+        lifecycle.emit(|| LifecycleEvent::ConnectionOpened {
+            time: SystemTime::now(),
+            connection: id,
+            remote: connection.remote_address(),
+        });
+        let remote = Arc::new(remote_info(&connection));
+        let activity = Arc::new(ConnectionActivity::new(connection.clone()));
         loop {
             tracing::debug!("Awaiting incoming bidi substream on existing connection...");
-            let bidi_stream = match connection.accept_bi().await {
-                Ok(bidi_stream) => bidi_stream,
-                Err(quinn::ConnectionError::ApplicationClosed(e)) => {
+            let accept = connection.accept_bi().map(Ok);
+            let idle = eviction.idle(&activity).map(Err);
+            let bidi_stream = match futures_lite::future::or(accept, idle).await {
+                Ok(Ok(bidi_stream)) => bidi_stream,
+                Ok(Err(quinn::ConnectionError::ApplicationClosed(e))) => {
                     tracing::debug!("Peer closed the connection {:?}", e);
                     break;
                 }
-                Err(e) => {
+                Ok(Err(e)) => {
                     tracing::debug!("Error accepting stream: {}", e);
                     break;
                 }
+                Err(config) => {
+                    tracing::info!(
+                        "Evicting connection from {} after being idle for {:?}",
+                        connection.remote_address(),
+                        config.idle_timeout
+                    );
+                    connection.close(config.code, b"idle");
+                    config.counters.evicted.inc();
+                    break;
+                }
             };
             tracing::debug!("Sending substream to be handled... {}", bidi_stream.0.id());
-            if sender.send_async(bidi_stream).await.is_err() {
+            activity.acquire();
+            if sender
+                .send_async((bidi_stream, Some(remote.clone()), Some(activity.clone())))
+                .await
+                .is_err()
+            {
                 tracing::debug!("Receiver dropped");
                 break;
             }
         }
+        lifecycle.emit(|| LifecycleEvent::ConnectionClosed {
+            time: SystemTime::now(),
+            connection: id,
+            remote: connection.remote_address(),
+            error: match connection.close_reason() {
+                None
+                | Some(quinn::ConnectionError::ApplicationClosed(_))
+                | Some(quinn::ConnectionError::LocallyClosed) => None,
+                Some(cause) => Some(cause.to_string()),
+            },
+        });
     }
 
-    async fn endpoint_handler(endpoint: quinn::Endpoint, sender: flume::Sender<SocketInner>) {
+    async fn endpoint_handler(
+        endpoint: quinn::Endpoint,
+        sender: flume::Sender<Incoming>,
+        eviction: Arc<EvictionState>,
+        lifecycle: Arc<ConnectionObserver>,
+        heartbeats: Arc<AtomicBool>,
+    ) {
         loop {
             tracing::debug!("Waiting for incoming connection...");
             let connecting = match endpoint.accept().await {
@@ -112,7 +917,13 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnListener<In, Out> {
                 conection.remote_address()
             );
             tracing::debug!("Spawning connection handler...");
-            tokio::spawn(Self::connection_handler(conection, sender.clone()));
+            tokio::spawn(Self::connection_handler(
+                conection,
+                sender.clone(),
+                eviction.clone(),
+                lifecycle.clone(),
+                heartbeats.clone(),
+            ));
         }
     }
 
@@ -125,18 +936,93 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnListener<In, Out> {
     pub fn new(endpoint: quinn::Endpoint) -> io::Result<Self> {
         let local_addr = endpoint.local_addr()?;
         let (sender, receiver) = flume::bounded(16);
-        let task = tokio::spawn(Self::endpoint_handler(endpoint.clone(), sender));
+        let eviction = Arc::new(EvictionState::default());
+        let lifecycle = Arc::new(ConnectionObserver::default());
+        let heartbeats = Arc::new(AtomicBool::new(false));
+        let task = tokio::spawn(Self::endpoint_handler(
+            endpoint.clone(),
+            sender,
+            eviction.clone(),
+            lifecycle.clone(),
+            heartbeats.clone(),
+        ));
         Ok(Self {
             inner: Arc::new(ListenerInner {
                 endpoint: Some(endpoint),
                 task: Some(task),
                 local_addr: [LocalAddr::Socket(local_addr)],
                 receiver,
+                reused: flume::unbounded(),
+                streams: Default::default(),
+                eviction,
+                lifecycle,
+                heartbeats,
             }),
+            codec: BincodeCodec,
+            max_frame_size: MAX_FRAME_LENGTH,
             _p: PhantomData,
         })
     }
 
+    /// Create a new server channel, given a quinn endpoint and the server config to use
+    /// for incoming connections.
+    ///
+    /// This is like [QuinnListener::new], but enables QUIC-level keep-alive for incoming
+    /// connections, and answers the application-level heartbeats of a [QuinnConnector],
+    /// see [QuinnListener::with_heartbeat_responder].
+    ///
+    /// The keep-alive interval is set on the transport config of `server_config`, which
+    /// keeps all its other parameters. Fails with [io::ErrorKind::InvalidInput] if the
+    /// transport config is shared with another server config, since it can not be
+    /// changed then.
+    pub fn with_keep_alive(
+        endpoint: quinn::Endpoint,
+        mut server_config: quinn::ServerConfig,
+        keep_alive_interval: Option<Duration>,
+    ) -> io::Result<Self> {
+        let Some(transport) = Arc::get_mut(&mut server_config.transport) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the transport config of the server config is shared",
+            ));
+        };
+        transport.keep_alive_interval(keep_alive_interval);
+        endpoint.set_server_config(Some(server_config));
+        Ok(Self::new(endpoint)?.with_heartbeat_responder(true))
+    }
+
+    /// Create a new server channel, given a quinn endpoint and the server config to use
+    /// for incoming connections, with custom transport parameters.
+    ///
+    /// The transport config of `server_config` is replaced with one created from
+    /// `settings`. The heartbeat interval of the settings is ignored, heartbeats sent by a
+    /// [QuinnConnector] are answered, see [QuinnListener::with_heartbeat_responder].
+    pub fn with_transport_settings(
+        endpoint: quinn::Endpoint,
+        mut server_config: quinn::ServerConfig,
+        settings: TransportSettings,
+    ) -> Result<Self, TransportConfigError> {
+        server_config.transport = Arc::new(settings.transport_config()?);
+        endpoint.set_server_config(Some(server_config));
+        Ok(Self::new(endpoint)?.with_heartbeat_responder(true))
+    }
+
+    /// Create a new server channel that requires clients to authenticate with a
+    /// certificate.
+    ///
+    /// This replaces the server config of `endpoint` with one created by
+    /// [server_config_with_client_auth].
+    pub fn with_client_auth(
+        endpoint: quinn::Endpoint,
+        cert_chain: Vec<quinn::rustls::pki_types::CertificateDer<'static>>,
+        key: quinn::rustls::pki_types::PrivateKeyDer<'static>,
+        client_auth: impl Into<ClientAuth>,
+    ) -> Result<Self, TlsConfigError> {
+        let server_config = server_config_with_client_auth(cert_chain, key, client_auth)?;
+        endpoint.set_server_config(Some(server_config));
+        Ok(Self::new(endpoint)?)
+    }
+
     /// Create a new server channel, given just a source of incoming connections
     ///
     /// This is useful if you want to manage the quinn endpoint yourself,
@@ -146,10 +1032,24 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnListener<In, Out> {
         local_addr: SocketAddr,
     ) -> Self {
         let (sender, receiver) = flume::bounded(16);
-        let task = tokio::spawn(async move {
-            // just grab all connections and spawn a handler for each one
-            while let Ok(connection) = incoming.recv_async().await {
-                tokio::spawn(Self::connection_handler(connection, sender.clone()));
+        let eviction = Arc::new(EvictionState::default());
+        let lifecycle = Arc::new(ConnectionObserver::default());
+        let heartbeats = Arc::new(AtomicBool::new(false));
+        let task = tokio::spawn({
+            let eviction = eviction.clone();
+            let lifecycle = lifecycle.clone();
+            let heartbeats = heartbeats.clone();
+            async move {
+                // just grab all connections and spawn a handler for each one
+                while let Ok(connection) = incoming.recv_async().await {
+                    tokio::spawn(Self::connection_handler(
+                        connection,
+                        sender.clone(),
+                        eviction.clone(),
+                        lifecycle.clone(),
+                        heartbeats.clone(),
+                    ));
+                }
             }
         });
         Self {
@@ -158,7 +1058,14 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnListener<In, Out> {
                 task: Some(task),
                 local_addr: [LocalAddr::Socket(local_addr)],
                 receiver,
+                reused: flume::unbounded(),
+                streams: Default::default(),
+                eviction,
+                lifecycle,
+                heartbeats,
             }),
+            codec: BincodeCodec,
+            max_frame_size: MAX_FRAME_LENGTH,
             _p: PhantomData,
         }
     }
@@ -167,76 +1074,538 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnListener<In, Out> {
     ///
     /// This is useful if you want to manage the quinn endpoint yourself,
     /// use multiple endpoints, or use an endpoint for multiple protocols.
+    ///
+    /// The substreams carry no information about their connection, so
+    /// [Listener::remote_info] returns `None` for them.
     pub fn handle_substreams(
-        receiver: flume::Receiver<SocketInner>,
+        substreams: flume::Receiver<SocketInner>,
         local_addr: SocketAddr,
     ) -> Self {
+        let (sender, receiver) = flume::bounded(16);
+        let task = tokio::spawn(async move {
+            while let Ok(substream) = substreams.recv_async().await {
+                if sender.send_async((substream, None, None)).await.is_err() {
+                    break;
+                }
+            }
+        });
         Self {
             inner: Arc::new(ListenerInner {
                 endpoint: None,
-                task: None,
+                task: Some(task),
                 local_addr: [LocalAddr::Socket(local_addr)],
                 receiver,
+                reused: flume::unbounded(),
+                streams: Default::default(),
+                eviction: Default::default(),
+                lifecycle: Default::default(),
+                heartbeats: Default::default(),
             }),
+            codec: BincodeCodec,
+            max_frame_size: MAX_FRAME_LENGTH,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> QuinnListener<In, Out, C> {
+    /// Use a different [Codec] to serialize messages.
+    ///
+    /// The connectors talking to this listener need to use the same codec.
+    pub fn with_codec<C2: Codec>(self, codec: C2) -> QuinnListener<In, Out, C2> {
+        QuinnListener {
+            inner: self.inner,
+            codec,
+            max_frame_size: self.max_frame_size,
             _p: PhantomData,
         }
     }
+
+    /// Set the maximum size of a single serialized message, in both directions.
+    ///
+    /// A received frame whose length prefix exceeds this is rejected with a
+    /// [FrameTooLarge] error before anything is buffered, and the offending substream
+    /// is stopped. Sending a larger message fails with [FrameTooLarge] before anything
+    /// is written. The connection stays usable in both cases.
+    ///
+    /// The default is 16 MiB.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Close connections that have no substream in use for a while.
+    ///
+    /// A connection is idle while none of its substreams is in use, see
+    /// [QuinnListener::active_streams]. Once it was idle for the configured timeout, it
+    /// is closed with the configured error code, which is logged and counted. This
+    /// applies to all clones of the listener, including connections that are already
+    /// open. Substreams passed to [QuinnListener::handle_substreams] have no connection,
+    /// so they are never evicted.
+    ///
+    /// The default is `None`, idle connections are only closed by the
+    /// [TransportSettings::max_idle_timeout] of quinn.
+    pub fn with_idle_eviction(self, config: Option<IdleEviction>) -> Self {
+        self.inner.eviction.set(config);
+        self
+    }
+
+    /// Answer the application-level heartbeats and pings of a [QuinnConnector].
+    ///
+    /// The responder accepts the unidirectional streams of every connection, so it must
+    /// not be enabled if the application uses unidirectional streams on the connections
+    /// of this listener. It is disabled by default, except for listeners created with
+    /// [QuinnListener::with_keep_alive] or [QuinnListener::with_transport_settings].
+    /// Only applies to connections that are accepted afterwards.
+    pub fn with_heartbeat_responder(self, enabled: bool) -> Self {
+        self.inner.heartbeats.store(enabled, Ordering::Release);
+        self
+    }
+
+    /// The substreams accepted by this listener that are still in use, in the order in
+    /// which they were accepted.
+    ///
+    /// A substream is in use until both halves are dropped. Substreams that were
+    /// converted using `into_inner` or [RawStreamTypes::into_raw] are no longer tracked.
+    pub fn active_streams(&self) -> Vec<ActiveStream> {
+        self.inner
+            .streams
+            .live()
+            .into_iter()
+            .map(|control| control.info.clone())
+            .collect()
+    }
+
+    /// Reset a substream returned by [QuinnListener::active_streams] with the given error
+    /// code.
+    ///
+    /// Quinn streams can only be reset by their owner, so each half is reset the next
+    /// time it is used by the handler. A handler that is waiting on either half is woken
+    /// up, and gets a [io::ErrorKind::ConnectionReset] error. Once the send half was
+    /// reset, the remote sees the stream as reset. If `code` is [CANCEL_CODE], the remote
+    /// sees the stream as cancelled, see [ConnectionErrors::is_cancelled].
+    ///
+    /// Returns `false` if the substream is no longer in use.
+    pub fn reset_stream(&self, stream: &ActiveStream, code: quinn::VarInt) -> bool {
+        self.reset_streams(code, |active| {
+            active.connection == stream.connection && active.id == stream.id
+        }) > 0
+    }
+
+    /// Reset all substreams for which `filter` returns true, e.g. to shed load by
+    /// resetting the ones that are older than a threshold.
+    ///
+    /// See [QuinnListener::reset_stream]. Returns the number of substreams that were reset.
+    pub fn reset_streams(
+        &self,
+        code: quinn::VarInt,
+        mut filter: impl FnMut(&ActiveStream) -> bool,
+    ) -> usize {
+        let mut count = 0;
+        for control in self.inner.streams.live() {
+            if filter(&control.info) && control.reset(code) {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Reset all substreams that are in use, see [QuinnListener::reset_stream].
+    ///
+    /// Returns the number of substreams that were reset.
+    pub fn reset_all(&self, code: quinn::VarInt) -> usize {
+        self.reset_streams(code, |_| true)
+    }
 }
 
-impl<In: RpcMessage, Out: RpcMessage> Clone for QuinnListener<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Clone for QuinnListener<In, Out, C> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            codec: self.codec.clone(),
+            max_frame_size: self.max_frame_size,
             _p: PhantomData,
         }
     }
 }
 
-impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for QuinnListener<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> ConnectionErrors for QuinnListener<In, Out, C> {
     type SendError = io::Error;
     type RecvError = io::Error;
     type OpenError = quinn::ConnectionError;
     type AcceptError = quinn::ConnectionError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        util::is_stopped(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        util::is_clean_close(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        super::codec::unknown_message(error).is_some()
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        util::is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        util::is_cancelled(error)
+    }
 }
 
-impl<In: RpcMessage, Out: RpcMessage> StreamTypes for QuinnListener<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> StreamTypes for QuinnListener<In, Out, C> {
     type In = In;
     type Out = Out;
-    type SendSink = self::SendSink<Out>;
-    type RecvStream = self::RecvStream<In>;
+    type SendSink = self::SendSink<Out, C>;
+    type RecvStream = self::RecvStream<In, C>;
+
+    fn set_priority(send: &Self::SendSink, priority: i32) -> Result<(), PriorityError> {
+        send.set_priority(priority)
+    }
+
+    fn send_closed(send: &Self::SendSink) -> impl Future<Output = ()> + Send + 'static {
+        util::send_closed(&send.0)
+    }
+
+    fn cancel(send: &mut Self::SendSink) {
+        send.cancel()
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> RawStreamTypes for QuinnListener<In, Out, C> {
+    type RawRecvStream = self::RawRecvStream<quinn::RecvStream>;
+    type RawSendSink = self::RawSendSink<quinn::SendStream>;
+
+    fn into_raw(
+        mut send: Self::SendSink,
+        mut recv: Self::RecvStream,
+    ) -> (Self::RawSendSink, Self::RawRecvStream) {
+        (send.take_framed().into_raw(), recv.take_framed().into_raw())
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Listener for QuinnListener<In, Out, C> {
+    /// Substreams that a [QuinnConnector] reuses, see
+    /// [QuinnConnector::with_substream_pool], are accepted again for every call.
+    async fn accept(&self) -> Result<(Self::SendSink, Self::RecvStream), AcceptError> {
+        let fresh = async {
+            let ((send, recv), remote, activity) = self
+                .inner
+                .receiver
+                .recv_async()
+                .await
+                .map_err(|_| quinn::ConnectionError::LocallyClosed)?;
+            let substream = Substream {
+                send: RawSendSink::new(send, self.max_frame_size),
+                recv: RawRecvStream::new(recv, self.max_frame_size),
+                remote,
+                activity,
+            };
+            // whether the substream is reused is known once its first frame is read
+            Ok::<_, AcceptError>((substream, None))
+        };
+        let reused = async {
+            let substream = self
+                .inner
+                .reused
+                .1
+                .recv_async()
+                .await
+                .map_err(|_| quinn::ConnectionError::LocallyClosed)?;
+            Ok((substream, Some(true)))
+        };
+        let (substream, reusable) = futures_lite::future::or(reused, fresh).await?;
+        let Substream {
+            send,
+            recv,
+            remote,
+            activity,
+        } = substream;
+        let info = ActiveStream {
+            connection: activity.as_ref().map(|activity| activity.id),
+            id: send.get_ref().id(),
+            accepted: Instant::now(),
+        };
+        let control = self.inner.streams.register(info, activity.clone());
+        let reuse = Reuse::new(
+            reusable,
+            Recycle::Listener {
+                requeue: self.inner.reused.0.clone(),
+                remote: remote.clone(),
+                activity,
+            },
+        );
+        let send = SendSink(
+            util::SharedSend::new(send.with_codec(self.codec.clone())),
+            Some(control.clone()),
+            Default::default(),
+            Some(SendReuse {
+                reuse: reuse.clone(),
+                end_queued: false,
+            }),
+        );
+        let recv = RecvStream(
+            Some(recv.with_codec(self.codec.clone())),
+            remote,
+            Some(control),
+            Some(RecvReuse {
+                reuse,
+                reusable,
+                ended: false,
+            }),
+        );
+        Ok((send, recv))
+    }
+
+    fn local_addr(&self) -> &[LocalAddr] {
+        &self.inner.local_addr
+    }
+
+    fn remote_info(recv: &Self::RecvStream) -> Option<Arc<RemoteInfo>> {
+        recv.1.clone()
+    }
+
+    /// The id of a connection is its [quinn::Connection::stable_id]. The observer applies
+    /// to all clones of the listener, including connections that are already open.
+    fn observe_connections(&self, observer: LifecycleObserver) {
+        self.inner.lifecycle.set(observer);
+    }
+
+    /// Push channels are bidi streams opened by the listener. They count as in use for
+    /// [QuinnListener::with_idle_eviction], but are not listed by
+    /// [QuinnListener::active_streams].
+    ///
+    /// Returns `None` for substreams passed to [QuinnListener::handle_substreams].
+    fn connection(&self, recv: &Self::RecvStream) -> Option<super::Connection<In, Out>> {
+        let activity = recv.2.as_ref()?.activity.clone()?;
+        let codec = self.codec.clone();
+        let max_frame_size = self.max_frame_size;
+        let connection = activity.connection.clone();
+        let open = super::Connection::new(move || {
+            let activity = activity.clone();
+            let codec = codec.clone();
+            async move {
+                let (send, recv) = activity.connection.open_bi().await?;
+                activity.acquire();
+                let info = ActiveStream {
+                    connection: Some(activity.id),
+                    id: send.id(),
+                    accepted: Instant::now(),
+                };
+                let control = Arc::new(StreamControl::new(info, Some(activity)));
+                let mut send = SendSink::<Out, C>::new(send, codec.clone(), max_frame_size);
+                send.1 = Some(control.clone());
+                let mut recv = RecvStream::<In, C>::new(recv, codec, max_frame_size);
+                recv.2 = Some(control);
+                anyhow::Ok((box_send_sink::<Self>(send), box_recv_stream::<Self>(recv)))
+            }
+        });
+        Some(open.with_datagrams(connection))
+    }
+}
+
+type SocketInner = (quinn::SendStream, quinn::RecvStream);
+
+/// A substream accepted by a listener, with the info about its connection and the
+/// activity of the connection, which the substream counts as in use
+type Incoming = (
+    SocketInner,
+    Option<Arc<RemoteInfo>>,
+    Option<Arc<ConnectionActivity>>,
+);
+
+/// A substream accepted by a [QuinnListener] that is still in use
+///
+/// See [QuinnListener::active_streams].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveStream {
+    /// The [quinn::Connection::stable_id] of the connection of the substream
+    ///
+    /// This is `None` for substreams passed to [QuinnListener::handle_substreams].
+    pub connection: Option<usize>,
+    /// The id of the substream within its connection
+    pub id: quinn::StreamId,
+    /// When the substream was accepted
+    pub accepted: Instant,
+}
+
+impl ActiveStream {
+    /// How long ago the substream was accepted
+    pub fn age(&self) -> Duration {
+        self.accepted.elapsed()
+    }
+}
+
+/// Shared by the two halves of a substream accepted by a [QuinnListener]
+#[derive(Debug)]
+struct StreamControl {
+    info: ActiveStream,
+    /// Released when both halves are dropped
+    activity: Option<Arc<ConnectionActivity>>,
+    /// The error code, once a reset was requested
+    reset: OnceLock<quinn::VarInt>,
+    send_waker: AtomicWaker,
+    recv_waker: AtomicWaker,
+}
+
+impl StreamControl {
+    fn new(info: ActiveStream, activity: Option<Arc<ConnectionActivity>>) -> Self {
+        Self {
+            info,
+            activity,
+            reset: OnceLock::new(),
+            send_waker: AtomicWaker::new(),
+            recv_waker: AtomicWaker::new(),
+        }
+    }
+
+    /// Request a reset, returns `false` if one was requested before
+    fn reset(&self, code: quinn::VarInt) -> bool {
+        if self.reset.set(code).is_err() {
+            return false;
+        }
+        self.send_waker.wake();
+        self.recv_waker.wake();
+        true
+    }
+
+    /// The requested reset, after registering `waker` to be woken up by the next one
+    fn poll_reset(&self, waker: &AtomicWaker, cx: &Context<'_>) -> Option<quinn::VarInt> {
+        waker.register(cx.waker());
+        self.reset.get().copied()
+    }
+}
+
+impl Drop for StreamControl {
+    fn drop(&mut self) {
+        if let Some(activity) = &self.activity {
+            activity.release();
+        }
+    }
+}
+
+/// The substreams accepted by a [QuinnListener] that have not been dropped yet
+#[derive(Debug, Default)]
+struct StreamRegistry(Mutex<Vec<Weak<StreamControl>>>);
+
+impl StreamRegistry {
+    fn register(
+        &self,
+        info: ActiveStream,
+        activity: Option<Arc<ConnectionActivity>>,
+    ) -> Arc<StreamControl> {
+        let control = Arc::new(StreamControl::new(info, activity));
+        let mut streams = self.0.lock().unwrap();
+        streams.retain(|stream| stream.strong_count() > 0);
+        streams.push(Arc::downgrade(&control));
+        control
+    }
+
+    fn live(&self) -> Vec<Arc<StreamControl>> {
+        let mut streams = self.0.lock().unwrap();
+        streams.retain(|stream| stream.strong_count() > 0);
+        streams.iter().filter_map(Weak::upgrade).collect()
+    }
+}
+
+/// The error of a substream that was reset using [QuinnListener::reset_stream]
+fn reset_locally() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::ConnectionReset,
+        ResetLocally {
+            backtrace: ErrorTrace::capture(),
+        },
+    )
+}
+
+/// The payload of the error of [reset_locally]
+#[derive(Debug)]
+pub(crate) struct ResetLocally {
+    pub(crate) backtrace: ErrorTrace,
 }
 
-impl<In: RpcMessage, Out: RpcMessage> Listener for QuinnListener<In, Out> {
-    async fn accept(&self) -> Result<(Self::SendSink, Self::RecvStream), AcceptError> {
-        let (send, recv) = self
-            .inner
-            .receiver
-            .recv_async()
-            .await
-            .map_err(|_| quinn::ConnectionError::LocallyClosed)?;
-        Ok((SendSink::new(send), RecvStream::new(recv)))
+impl fmt::Display for ResetLocally {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("substream was reset locally")
     }
+}
 
-    fn local_addr(&self) -> &[LocalAddr] {
-        &self.inner.local_addr
+impl std::error::Error for ResetLocally {}
+
+/// Collect the info about the remote of an incoming connection
+fn remote_info(connection: &quinn::Connection) -> RemoteInfo {
+    let certificates = connection
+        .peer_identity()
+        .and_then(|identity| {
+            identity
+                .downcast::<Vec<quinn::rustls::pki_types::CertificateDer<'static>>>()
+                .ok()
+        })
+        .map(|certs| certs.iter().map(|cert| cert.to_vec()).collect())
+        .unwrap_or_default();
+    RemoteInfo {
+        addr: Some(connection.remote_address()),
+        certificates,
+        identity: None,
     }
 }
 
-type SocketInner = (quinn::SendStream, quinn::RecvStream);
-
 #[derive(Debug)]
 struct ClientConnectionInner {
-    /// The quinn endpoint, we just keep a clone of this for information
+    /// The quinn endpoint, to close it on drop unless it is shared
     endpoint: Option<quinn::Endpoint>,
     /// The task that handles creating new connections
     task: Option<JoinHandle<()>>,
     /// The channel to receive new connections
     sender: flume::Sender<oneshot::Sender<Result<SocketInner, quinn::ConnectionError>>>,
+    /// The last time a heartbeat was echoed by the remote
+    last_alive: LastAlive,
+    /// Pings to send on the echo stream of the current connection
+    pings: flume::Sender<PingRequest>,
+    /// The connection currently used to open substreams
+    connection: CurrentConnection,
+    /// Changes of the connection, see [QuinnConnector::events]
+    events: broadcast::Sender<ConnectionEvent>,
+    /// Channels opened by the remote, see [Connector::accept_push]
+    pushes: flume::Receiver<SocketInner>,
+    /// Starts accepting them on the connections
+    accept_pushes: LazyPushes,
+    /// Datagrams sent by the remote, see [QuinnConnector::datagrams]
+    datagrams: flume::Receiver<Bytes>,
+    /// Set by [QuinnConnector::close], no new connections are made from then on
+    closed: AtomicBool,
+    /// The connector made the connections itself, so they are closed on drop
+    owns_connection: bool,
+}
+
+impl ClientConnectionInner {
+    /// Close the current connection, and stop making new ones
+    fn close(&self, code: quinn::VarInt, reason: &[u8]) {
+        self.closed.store(true, Ordering::Release);
+        if let Some(task) = &self.task {
+            task.abort();
+        }
+        if let Some(connection) = self.connection.get() {
+            connection.close(code, reason);
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
 }
 
 impl Drop for ClientConnectionInner {
     fn drop(&mut self) {
         tracing::debug!("Dropping client connection");
+        // the background tasks keep the connection alive, so close it explicitly. A
+        // connection passed to from_connection may still be used by the caller.
+        if let Some(connection) = self.connection.get().filter(|_| self.owns_connection) {
+            connection.close(0u32.into(), b"client connection dropped");
+        }
         if let Some(endpoint) = self.endpoint.take() {
             endpoint.close(0u32.into(), b"client connection dropped");
             if let Ok(handle) = tokio::runtime::Handle::try_current() {
@@ -259,9 +1628,270 @@ impl Drop for ClientConnectionInner {
     }
 }
 
+/// How long to wait before resolving again after resolving failed
+const RESOLVE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Error of the resolver of a [Remote]
+///
+/// Clones are equal to each other, see [ConnectionEvent::ResolveFailed].
+#[derive(Debug, Clone)]
+pub struct ResolveError(Arc<dyn std::error::Error + Send + Sync>);
+
+impl ResolveError {
+    /// Create a resolve error from any error or message
+    pub fn new(cause: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Self(cause.into().into())
+    }
+}
+
+impl PartialEq for ResolveError {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for ResolveError {}
+
+impl From<io::Error> for ResolveError {
+    fn from(e: io::Error) -> Self {
+        Self::new(e)
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to resolve: {}", self.0)
+    }
+}
+
+impl std::error::Error for ResolveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&*self.0)
+    }
+}
+
+type Resolver =
+    Arc<dyn Fn(&str) -> BoxFuture<'static, Result<Vec<SocketAddr>, ResolveError>> + Send + Sync>;
+
+/// The server a [QuinnConnector] connects to
+///
+/// This is either a fixed address, or a host that is resolved to addresses every time the
+/// connector connects, e.g. using DNS, SRV records or a service registry. The server name
+/// for TLS is passed to the connector separately, so it does not have to match the host.
+///
+/// It is passed to the `*_with_remote` constructors of [QuinnConnector], e.g.
+/// [QuinnConnector::new_with_remote]. A [SocketAddr] converts into a fixed remote.
+#[derive(Clone)]
+pub struct Remote(RemoteInner);
+
+#[derive(Clone)]
+enum RemoteInner {
+    Addr(SocketAddr),
+    Resolve { host: String, resolver: Resolver },
+}
+
+impl Remote {
+    /// Always connect to `addr`
+    pub fn addr(addr: SocketAddr) -> Self {
+        Self(RemoteInner::Addr(addr))
+    }
+
+    /// Resolve `host` using `resolver` on every connect
+    ///
+    /// The addresses are tried in the order returned by the resolver, until a connection
+    /// succeeds. If resolving fails or returns no addresses, the connector tries again
+    /// after a short delay. Happy eyeballs can be implemented in the resolver by
+    /// interleaving IPv6 and IPv4 addresses.
+    pub fn resolve<F, Fut>(host: impl Into<String>, resolver: F) -> Self
+    where
+        F: Fn(&str) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<Vec<SocketAddr>, ResolveError>> + Send + 'static,
+    {
+        Self(RemoteInner::Resolve {
+            host: host.into(),
+            resolver: Arc::new(move |host| resolver(host).boxed()),
+        })
+    }
+
+    /// Key of the shared connection, see [QuinnConnector::with_endpoint]
+    fn key(&self) -> String {
+        match &self.0 {
+            RemoteInner::Addr(addr) => addr.to_string(),
+            RemoteInner::Resolve { host, .. } => host.clone(),
+        }
+    }
+}
+
+impl From<SocketAddr> for Remote {
+    fn from(addr: SocketAddr) -> Self {
+        Self::addr(addr)
+    }
+}
+
+impl fmt::Debug for Remote {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.0 {
+            RemoteInner::Addr(addr) => f.debug_tuple("Remote").field(addr).finish(),
+            RemoteInner::Resolve { host, .. } => f
+                .debug_struct("Remote")
+                .field("host", host)
+                .finish_non_exhaustive(),
+        }
+    }
+}
+
+/// A quinn endpoint that is shared by the connectors created with
+/// [QuinnConnector::with_endpoint]
+///
+/// A process that talks to many servers only needs this one endpoint, and thus one UDP
+/// socket. Connectors for the same server address and name share a single connection.
+/// Unlike a connector created with [QuinnConnector::new], these connectors do not close
+/// the endpoint when they are dropped.
+#[derive(Debug, Clone)]
+pub struct SharedEndpoint(Arc<SharedEndpointInner>);
+
+#[derive(Debug)]
+struct SharedEndpointInner {
+    endpoint: quinn::Endpoint,
+    /// The connection to each server, by remote and name, as long as a connector uses it
+    connections: Mutex<HashMap<(String, String), Weak<ClientConnectionInner>>>,
+}
+
+impl SharedEndpoint {
+    /// Share `endpoint` between connectors
+    pub fn new(endpoint: quinn::Endpoint) -> Self {
+        Self(Arc::new(SharedEndpointInner {
+            endpoint,
+            connections: Default::default(),
+        }))
+    }
+
+    /// The underlying quinn endpoint
+    pub fn endpoint(&self) -> &quinn::Endpoint {
+        &self.0.endpoint
+    }
+}
+
+impl From<quinn::Endpoint> for SharedEndpoint {
+    fn from(endpoint: quinn::Endpoint) -> Self {
+        Self::new(endpoint)
+    }
+}
+
+/// How long a substream is kept for reuse by default, see [SubstreamPool::idle_ttl]
+const DEFAULT_IDLE_TTL: Duration = Duration::from_secs(10);
+
+/// Reuse of substreams by a connector, see [QuinnConnector::with_substream_pool]
+#[derive(Debug, Clone)]
+pub struct SubstreamPool {
+    max_idle: usize,
+    idle_ttl: Duration,
+}
+
+impl SubstreamPool {
+    /// Keep up to `max_idle` substreams open for later calls once their call is done.
+    pub fn new(max_idle: usize) -> Self {
+        Self {
+            max_idle,
+            idle_ttl: DEFAULT_IDLE_TTL,
+        }
+    }
+
+    /// How long a substream is kept while it waits for its next call.
+    ///
+    /// This also bounds how long the rest of a dropped call is read, before its
+    /// substream is closed instead of reused. The default is 10 seconds.
+    pub fn idle_ttl(mut self, value: Duration) -> Self {
+        self.idle_ttl = value;
+        self
+    }
+}
+
+/// The substreams of a connector that wait for their next call
+#[derive(Debug)]
+struct IdleStreams {
+    config: SubstreamPool,
+    streams: Mutex<Vec<IdleStream>>,
+}
+
+#[derive(Debug)]
+struct IdleStream {
+    send: RawSendSink<quinn::SendStream>,
+    recv: RawRecvStream<quinn::RecvStream>,
+    /// The [quinn::Connection::stable_id] of the connection of the substream
+    connection: usize,
+    since: Instant,
+}
+
+impl IdleStreams {
+    fn put(
+        &self,
+        send: RawSendSink<quinn::SendStream>,
+        recv: RawRecvStream<quinn::RecvStream>,
+        connection: usize,
+    ) {
+        let mut streams = self.streams.lock().unwrap();
+        streams.retain(|stream| stream.since.elapsed() < self.config.idle_ttl);
+        if streams.len() < self.config.max_idle {
+            streams.push(IdleStream {
+                send,
+                recv,
+                connection,
+                since: Instant::now(),
+            });
+        }
+    }
+
+    /// Take the most recently used substream that can carry a call on `connection`
+    ///
+    /// Substreams that can not are dropped on the way, which closes them.
+    fn take(
+        &self,
+        connection: &quinn::Connection,
+    ) -> Option<(
+        RawSendSink<quinn::SendStream>,
+        RawRecvStream<quinn::RecvStream>,
+    )> {
+        let mut streams = self.streams.lock().unwrap();
+        while let Some(mut stream) = streams.pop() {
+            if stream.is_usable(connection, self.config.idle_ttl) {
+                return Some((stream.send, stream.recv));
+            }
+        }
+        None
+    }
+}
+
+impl IdleStream {
+    fn is_usable(&mut self, connection: &quinn::Connection, idle_ttl: Duration) -> bool {
+        if self.connection != connection.stable_id()
+            || connection.close_reason().is_some()
+            || self.since.elapsed() >= idle_ttl
+        {
+            return false;
+        }
+        // the remote must neither have stopped the substream, nor sent anything outside
+        // of a call
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        self.send.get_mut().stopped().now_or_never().is_none()
+            && Pin::new(&mut self.recv).poll_peek(&mut cx).is_pending()
+    }
+}
+
 /// A connection using a quinn connection
-pub struct QuinnConnector<In: RpcMessage, Out: RpcMessage> {
+///
+/// Messages are serialized using the codec `C`, which defaults to [BincodeCodec].
+///
+/// Clones share the connection, which stays open until the last clone is dropped or
+/// [QuinnConnector::close] is called on any of them. A connection passed to
+/// [QuinnConnector::from_connection] is only closed by [QuinnConnector::close].
+pub struct QuinnConnector<In: RpcMessage, Out: RpcMessage, C: Codec = BincodeCodec> {
     inner: Arc<ClientConnectionInner>,
+    codec: C,
+    max_frame_size: usize,
+    /// Substreams kept for reuse, see [QuinnConnector::with_substream_pool]
+    pool: Option<Arc<IdleStreams>>,
+    ping_timeout: Duration,
     _p: PhantomData<(In, Out)>,
 }
 
@@ -312,14 +1942,17 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnConnector<In, Out> {
     /// It will try to keep a connection open at all times.
     async fn reconnect_handler_inner(
         endpoint: quinn::Endpoint,
-        addr: SocketAddr,
+        remote: Remote,
         name: String,
+        client_config: Option<quinn::ClientConfig>,
+        tasks: ConnectionTasks,
         requests: flume::Receiver<oneshot::Sender<Result<SocketInner, quinn::ConnectionError>>>,
     ) {
         let reconnect = ReconnectHandler {
             endpoint,
+            client_config,
             state: ConnectionState::NotConnected,
-            addr,
+            remote,
             name,
         };
         futures_lite::pin!(reconnect);
@@ -329,7 +1962,7 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnConnector<In, Out> {
         let mut pending_request: Option<
             oneshot::Sender<Result<SocketInner, quinn::ConnectionError>>,
         > = None;
-        let mut connection = None;
+        let mut connection: Option<quinn::Connection> = None;
 
         enum Racer {
             Reconnect(Result<quinn::Connection, ReconnectErr>),
@@ -363,6 +1996,13 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnConnector<In, Out> {
                 tracing::trace!("tick: connection result");
                 match conn_result {
                     Ok(new_connection) => {
+                        let remote = new_connection.remote_address();
+                        let event = match connection {
+                            None => ConnectionEvent::Connected { remote },
+                            Some(_) => ConnectionEvent::Reconnected { remote },
+                        };
+                        tasks.events.send(event).ok();
+                        tasks.start(&new_connection);
                         connection = Some(new_connection);
                     }
                     Err(e) => {
@@ -379,6 +2019,13 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnConnector<In, Out> {
                                 tracing::warn!(%e, "failed to connect");
                                 e
                             }
+                            ReconnectErr::Resolve(e) => {
+                                // like a ConnectError, reported as a reset. The cause is
+                                // only available as an event.
+                                tracing::warn!(%e, "error resolving remote");
+                                tasks.events.send(ConnectionEvent::ResolveFailed(e)).ok();
+                                quinn::ConnectionError::Reset
+                            }
                         };
                         if let Some(request) = pending_request.take() {
                             if request.send(Err(connection_err)).is_err() {
@@ -429,52 +2076,548 @@ impl<In: RpcMessage, Out: RpcMessage> QuinnConnector<In, Out> {
 
     async fn reconnect_handler(
         endpoint: quinn::Endpoint,
-        addr: SocketAddr,
+        remote: Remote,
         name: String,
+        client_config: Option<quinn::ClientConfig>,
+        tasks: ConnectionTasks,
         requests: flume::Receiver<oneshot::Sender<Result<SocketInner, quinn::ConnectionError>>>,
     ) {
-        Self::reconnect_handler_inner(endpoint, addr, name, requests).await;
+        Self::reconnect_handler_inner(endpoint, remote, name, client_config, tasks, requests).await;
         tracing::info!("Reconnect handler finished");
     }
 
     /// Create a new channel
+    ///
+    /// The connection stays open when the connector is dropped, since the caller may
+    /// still use it. Use [QuinnConnector::close] to close it.
     pub fn from_connection(connection: quinn::Connection) -> Self {
         let (sender, receiver) = flume::bounded(16);
-        let task = glib::spawn_async(Self::single_connection_handler(connection, receiver));
+        let current = CurrentConnection::default();
+        current.set(connection.clone());
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+        tokio::spawn(watch_connection(
+            current.downgrade(&connection),
+            events.clone(),
+        ));
+        let (pings, receiver_pings) = flume::bounded(16);
+        let echo_config = EchoConfig {
+            heartbeat: None,
+            pings: receiver_pings,
+        };
+        tokio::spawn(echo(connection.clone(), echo_config));
+        let (push_sender, pushes) = flume::bounded(16);
+        let accept_pushes = LazyPushes::new(push_sender, false);
+        let (datagram_sender, datagrams) = flume::bounded(DATAGRAMS_CAPACITY);
+        tokio::spawn(recv_datagrams(connection.clone(), datagram_sender));
+        let task = tokio::spawn(Self::single_connection_handler(connection, receiver));
         Self {
             inner: Arc::new(ClientConnectionInner {
                 endpoint: None,
                 task: Some(task),
                 sender,
+                last_alive: LastAlive::default(),
+                pings,
+                connection: current,
+                events,
+                pushes,
+                accept_pushes,
+                datagrams,
+                closed: AtomicBool::new(false),
+                owns_connection: false,
             }),
+            codec: BincodeCodec,
+            max_frame_size: MAX_FRAME_LENGTH,
+            pool: None,
+            ping_timeout: DEFAULT_PING_TIMEOUT,
             _p: PhantomData,
         }
     }
 
-    /// Create a new channel
-    pub fn new(endpoint: quinn::Endpoint, addr: SocketAddr, name: String) -> Self {
+    /// Create a connector that reconnects to `remote` as needed
+    ///
+    /// With `close_endpoint`, the endpoint is closed when the last clone of the connector
+    /// is dropped.
+    fn spawn(
+        endpoint: quinn::Endpoint,
+        remote: Remote,
+        name: String,
+        client_config: Option<quinn::ClientConfig>,
+        heartbeat_interval: Option<Duration>,
+        close_endpoint: bool,
+    ) -> Self {
+        let last_alive = LastAlive::default();
+        let (pings, receiver_pings) = flume::bounded(16);
+        let echo = EchoConfig {
+            heartbeat: heartbeat_interval.map(|interval| (interval, last_alive.clone())),
+            pings: receiver_pings,
+        };
         let (sender, receiver) = flume::bounded(16);
+        let current = CurrentConnection::default();
+        let (events, _) = broadcast::channel(EVENTS_CAPACITY);
+        let (push_sender, pushes) = flume::bounded(16);
+        let accept_pushes = LazyPushes::new(push_sender, true);
+        let (datagram_sender, datagrams) = flume::bounded(DATAGRAMS_CAPACITY);
+        let tasks = ConnectionTasks {
+            echo,
+            current: current.clone(),
+            events: events.clone(),
+            pushes: accept_pushes.clone(),
+            datagrams: datagram_sender,
+        };
         let task = tokio::spawn(Self::reconnect_handler(
             endpoint.clone(),
-            addr,
+            remote,
             name,
+            client_config,
+            tasks,
             receiver,
         ));
         Self {
             inner: Arc::new(ClientConnectionInner {
-                endpoint: Some(endpoint),
+                endpoint: close_endpoint.then_some(endpoint),
                 task: Some(task),
                 sender,
+                last_alive,
+                pings,
+                connection: current,
+                events,
+                pushes,
+                accept_pushes,
+                datagrams,
+                closed: AtomicBool::new(false),
+                owns_connection: true,
             }),
+            codec: BincodeCodec,
+            max_frame_size: MAX_FRAME_LENGTH,
+            pool: None,
+            ping_timeout: DEFAULT_PING_TIMEOUT,
+            _p: PhantomData,
+        }
+    }
+
+    /// Create a new channel
+    pub fn new(endpoint: quinn::Endpoint, addr: SocketAddr, name: String) -> Self {
+        Self::new_with_remote(endpoint, Remote::addr(addr), name)
+    }
+
+    /// Like [QuinnConnector::new], but connects to a [Remote], which may resolve a host
+    /// on every connect
+    ///
+    /// `name` is the server name for TLS.
+    pub fn new_with_remote(endpoint: quinn::Endpoint, remote: Remote, name: String) -> Self {
+        Self::spawn(endpoint, remote, name, None, None, true)
+    }
+
+    /// Create a new channel on a shared endpoint
+    ///
+    /// All connectors created from the same [SharedEndpoint] for the same `addr` and
+    /// `name` use one connection, which stays open while any of them is alive, so
+    /// opening substreams on any of them does not reconnect. Connections are made using
+    /// the default client config of the endpoint, and the endpoint stays open when the
+    /// connectors are dropped.
+    /// A connection closed using [QuinnConnector::close] is not shared with connectors
+    /// created afterwards.
+    pub fn with_endpoint(endpoint: &SharedEndpoint, addr: SocketAddr, name: String) -> Self {
+        Self::with_endpoint_with_remote(endpoint, Remote::addr(addr), name)
+    }
+
+    /// Like [QuinnConnector::with_endpoint], but connects to a [Remote], which may
+    /// resolve a host on every connect
+    ///
+    /// Remotes that resolve a host share a connection if the host is the same, the
+    /// resolver of the first connector is used.
+    pub fn with_endpoint_with_remote(
+        endpoint: &SharedEndpoint,
+        remote: Remote,
+        name: String,
+    ) -> Self {
+        let mut connections = endpoint.0.connections.lock().unwrap();
+        connections.retain(|_, inner| inner.strong_count() > 0);
+        let key = (remote.key(), name);
+        let shared = connections.get(&key).and_then(Weak::upgrade);
+        if let Some(inner) = shared.filter(|inner| !inner.is_closed()) {
+            return Self {
+                inner,
+                codec: BincodeCodec,
+                max_frame_size: MAX_FRAME_LENGTH,
+                pool: None,
+                ping_timeout: DEFAULT_PING_TIMEOUT,
+                _p: PhantomData,
+            };
+        }
+        let this = Self::spawn(
+            endpoint.0.endpoint.clone(),
+            remote,
+            key.1.clone(),
+            None,
+            None,
+            false,
+        );
+        connections.insert(key, Arc::downgrade(&this.inner));
+        this
+    }
+
+    /// Create a new channel that authenticates with a client certificate
+    ///
+    /// Connections to the remote are made using a client config created by
+    /// [client_config_with_cert] instead of the default client config of the endpoint.
+    /// To combine this with keep-alive, pass such a config to
+    /// [QuinnConnector::with_keep_alive].
+    pub fn with_client_cert(
+        endpoint: quinn::Endpoint,
+        addr: SocketAddr,
+        name: String,
+        roots: quinn::rustls::RootCertStore,
+        cert_chain: Vec<quinn::rustls::pki_types::CertificateDer<'static>>,
+        key: quinn::rustls::pki_types::PrivateKeyDer<'static>,
+    ) -> Result<Self, TlsConfigError> {
+        Self::with_client_cert_with_remote(
+            endpoint,
+            Remote::addr(addr),
+            name,
+            roots,
+            cert_chain,
+            key,
+        )
+    }
+
+    /// Like [QuinnConnector::with_client_cert], but connects to a [Remote], which may
+    /// resolve a host on every connect
+    pub fn with_client_cert_with_remote(
+        endpoint: quinn::Endpoint,
+        remote: Remote,
+        name: String,
+        roots: quinn::rustls::RootCertStore,
+        cert_chain: Vec<quinn::rustls::pki_types::CertificateDer<'static>>,
+        key: quinn::rustls::pki_types::PrivateKeyDer<'static>,
+    ) -> Result<Self, TlsConfigError> {
+        let client_config = client_config_with_cert(roots, cert_chain, key)?;
+        Ok(Self::spawn(
+            endpoint,
+            remote,
+            name,
+            Some(client_config),
+            None,
+            true,
+        ))
+    }
+
+    /// Create a new channel with keep-alive settings
+    ///
+    /// Connections to the remote are made using `client_config` instead of the default
+    /// client config of the endpoint. quinn does not allow reading the transport config
+    /// of a client config, so if a QUIC-level keep-alive interval is configured, the
+    /// transport config is replaced with a default one that has this interval. Use
+    /// [QuinnConnector::with_transport_settings] to combine keep-alive with other
+    /// transport parameters. Without a keep-alive interval, the transport config of
+    /// `client_config` is kept.
+    pub fn with_keep_alive(
+        endpoint: quinn::Endpoint,
+        addr: SocketAddr,
+        name: String,
+        client_config: quinn::ClientConfig,
+        keep_alive: KeepAliveConfig,
+    ) -> Self {
+        Self::with_keep_alive_with_remote(
+            endpoint,
+            Remote::addr(addr),
+            name,
+            client_config,
+            keep_alive,
+        )
+    }
+
+    /// Like [QuinnConnector::with_keep_alive], but connects to a [Remote], which may
+    /// resolve a host on every connect
+    pub fn with_keep_alive_with_remote(
+        endpoint: quinn::Endpoint,
+        remote: Remote,
+        name: String,
+        mut client_config: quinn::ClientConfig,
+        keep_alive: KeepAliveConfig,
+    ) -> Self {
+        if keep_alive.keep_alive_interval.is_some() {
+            client_config.transport_config(Arc::new(keep_alive_transport_config(
+                keep_alive.keep_alive_interval,
+            )));
+        }
+        Self::spawn(
+            endpoint,
+            remote,
+            name,
+            Some(client_config),
+            keep_alive.heartbeat_interval,
+            true,
+        )
+    }
+
+    /// Create a new channel with custom transport parameters
+    ///
+    /// Connections to the remote are made using `client_config` instead of the default
+    /// client config of the endpoint. Its transport config is replaced with one created
+    /// from `settings`, which also configure the heartbeat.
+    pub fn with_transport_settings(
+        endpoint: quinn::Endpoint,
+        addr: SocketAddr,
+        name: String,
+        client_config: quinn::ClientConfig,
+        settings: TransportSettings,
+    ) -> Result<Self, TransportConfigError> {
+        Self::with_transport_settings_with_remote(
+            endpoint,
+            Remote::addr(addr),
+            name,
+            client_config,
+            settings,
+        )
+    }
+
+    /// Like [QuinnConnector::with_transport_settings], but connects to a [Remote], which
+    /// may resolve a host on every connect
+    pub fn with_transport_settings_with_remote(
+        endpoint: quinn::Endpoint,
+        remote: Remote,
+        name: String,
+        mut client_config: quinn::ClientConfig,
+        settings: TransportSettings,
+    ) -> Result<Self, TransportConfigError> {
+        client_config.transport_config(Arc::new(settings.transport_config()?));
+        Ok(Self::spawn(
+            endpoint,
+            remote,
+            name,
+            Some(client_config),
+            settings.keep_alive.heartbeat_interval,
+            true,
+        ))
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> QuinnConnector<In, Out, C> {
+    /// Use a different [Codec] to serialize messages.
+    ///
+    /// The listener this connects to needs to use the same codec.
+    pub fn with_codec<C2: Codec>(self, codec: C2) -> QuinnConnector<In, Out, C2> {
+        QuinnConnector {
+            inner: self.inner,
+            codec,
+            max_frame_size: self.max_frame_size,
+            pool: self.pool,
+            ping_timeout: self.ping_timeout,
             _p: PhantomData,
         }
     }
+
+    /// Set the maximum size of a single serialized message, in both directions.
+    ///
+    /// See [QuinnListener::with_max_frame_size].
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Reuse substreams for more than one rpc call, to save the cost of opening them.
+    ///
+    /// Only the substreams of unary calls are reused, see [Connector::open_rpc], all
+    /// other patterns still get a fresh substream per call. Once a call is done, its
+    /// substream is kept open for a later call on this connector or its clones, instead
+    /// of being finished. In each direction, an empty
+    /// frame ends the call, and a substream is only reused once both sides ended the call
+    /// cleanly. Before reuse, a substream is checked to be on the current connection, to
+    /// have been idle for less than the TTL, and to not have been stopped by the remote.
+    ///
+    /// The listener needs to support reused substreams, which a [QuinnListener] does.
+    /// Since dropping a call on a reused substream does not stop it, the server can not
+    /// tell, see [StreamTypes::send_closed], so the handler of a dropped rpc call runs to
+    /// completion. Substreams converted using `into_inner` or
+    /// [RawStreamTypes::into_raw] are not reused.
+    pub fn with_substream_pool(mut self, config: SubstreamPool) -> Self {
+        self.pool = Some(Arc::new(IdleStreams {
+            config,
+            streams: Default::default(),
+        }));
+        self
+    }
+
+    /// How long [Connector::ping] waits for the answer, including the time to connect.
+    ///
+    /// The default is 10 seconds.
+    pub fn with_ping_timeout(mut self, timeout: Duration) -> Self {
+        self.ping_timeout = timeout;
+        self
+    }
+
+    /// The number of substreams that wait for their next call, see
+    /// [QuinnConnector::with_substream_pool].
+    pub fn idle_substreams(&self) -> usize {
+        self.pool
+            .as_ref()
+            .map_or(0, |pool| pool.streams.lock().unwrap().len())
+    }
+
+    /// Wrap the halves of a substream that is reused
+    /// Open a substream, which is taken from and returned to the pool if `reuse` is set
+    async fn open_substream(
+        &self,
+        reuse: bool,
+    ) -> Result<(SendSink<Out, C>, RecvStream<In, C>), quinn::ConnectionError> {
+        if self.inner.is_closed() {
+            return Err(quinn::ConnectionError::LocallyClosed);
+        }
+        let pool = self.pool.as_ref().filter(|_| reuse);
+        if let (Some(pool), Some(connection)) = (pool, self.inner.connection.get()) {
+            if let Some((send, recv)) = pool.take(&connection) {
+                return Ok(self.reused(pool, connection.stable_id(), send, recv));
+            }
+        }
+        let (sender, receiver) = oneshot::channel();
+        self.inner
+            .sender
+            .send_async(sender)
+            .await
+            .map_err(|_| quinn::ConnectionError::LocallyClosed)?;
+        let (send, recv) = receiver
+            .await
+            .map_err(|_| quinn::ConnectionError::LocallyClosed)??;
+        if let (Some(pool), Some(connection)) = (pool, self.inner.connection.get()) {
+            let mut send = RawSendSink::new(send, self.max_frame_size);
+            // an empty first frame tells the listener that the substream is reused
+            Pin::new(&mut send)
+                .start_send(Bytes::new())
+                .expect("an empty frame is never too large");
+            let recv = RawRecvStream::new(recv, self.max_frame_size);
+            return Ok(self.reused(pool, connection.stable_id(), send, recv));
+        }
+        Ok((
+            SendSink::new(send, self.codec.clone(), self.max_frame_size),
+            RecvStream::new(recv, self.codec.clone(), self.max_frame_size),
+        ))
+    }
+
+    fn reused(
+        &self,
+        pool: &Arc<IdleStreams>,
+        connection: usize,
+        send: RawSendSink<quinn::SendStream>,
+        recv: RawRecvStream<quinn::RecvStream>,
+    ) -> (SendSink<Out, C>, RecvStream<In, C>) {
+        let reuse = Reuse::new(
+            Some(true),
+            Recycle::Pool {
+                pool: Arc::downgrade(pool),
+                connection,
+            },
+        );
+        let send = SendSink(
+            util::SharedSend::new(send.with_codec(self.codec.clone())),
+            None,
+            Default::default(),
+            Some(SendReuse {
+                reuse: reuse.clone(),
+                end_queued: false,
+            }),
+        );
+        let recv = RecvStream(
+            Some(recv.with_codec(self.codec.clone())),
+            None,
+            None,
+            Some(RecvReuse {
+                reuse,
+                reusable: Some(true),
+                ended: false,
+            }),
+        );
+        (send, recv)
+    }
+
+    /// The last time the remote echoed an application-level heartbeat.
+    ///
+    /// This is `None` if heartbeats are not enabled, see [KeepAliveConfig], or no
+    /// heartbeat has been echoed yet. If this is older than a few heartbeat intervals,
+    /// the remote is likely dead.
+    pub fn last_alive(&self) -> Option<Instant> {
+        self.inner.last_alive.get()
+    }
+
+    /// Subscribe to changes of the connection to the remote.
+    ///
+    /// Only changes after subscribing are reported. Use [Connector::stats] to check
+    /// whether there is a connection right now. If the subscriber falls behind by more
+    /// than a few events, the oldest ones are skipped. The stream ends once all clones
+    /// of this connector are dropped. If the connection stays open, e.g. because it was
+    /// passed to [QuinnConnector::from_connection], this is noticed when the path of the
+    /// connection is checked next, about a second later.
+    pub fn events(&self) -> impl Stream<Item = ConnectionEvent> + Send + 'static {
+        let receiver = self.inner.events.subscribe();
+        futures_lite::stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((event, receiver)),
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        tracing::debug!("Skipped {n} connection events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    /// Send an unreliable datagram to the remote, on the current connection.
+    ///
+    /// Datagrams are not part of any channel. They may be lost, duplicated or reordered,
+    /// and are never retransmitted, which suits data that is stale by the time it could
+    /// be resent, like telemetry. The bytes are sent as they are; to send a message,
+    /// encode it using a [Codec] first. Returns [DatagramError::Closed] if there is no
+    /// connection right now, this does not wait for a reconnect.
+    ///
+    /// Datagrams have to be enabled on both sides, which they are by default, see
+    /// [quinn::TransportConfig::datagram_receive_buffer_size].
+    pub fn send_datagram(&self, data: impl Into<Bytes>) -> Result<(), DatagramError> {
+        let connection = self.inner.connection.get().ok_or(DatagramError::Closed)?;
+        DatagramSocket::send_datagram(&connection, data.into())
+    }
+
+    /// The largest datagram that can be sent right now.
+    ///
+    /// This depends on the path to the remote, so it can change over the lifetime of
+    /// the connection. Returns `None` if there is no connection, or the remote does not
+    /// support datagrams.
+    pub fn max_datagram_size(&self) -> Option<usize> {
+        self.inner.connection.get()?.max_datagram_size()
+    }
+
+    /// The datagrams sent by the remote, on this and any later connection.
+    ///
+    /// Each datagram is returned by only one of the streams, if there are several. Up to
+    /// a few datagrams are buffered while no stream is polled, later ones are dropped.
+    /// The stream ends when all clones of this connector are dropped.
+    pub fn datagrams(&self) -> impl Stream<Item = Bytes> + Send + 'static {
+        self.inner.datagrams.clone().into_stream()
+    }
+
+    /// Close the connection for all clones of this connector.
+    ///
+    /// The remote gets a CONNECTION_CLOSE with the application error `code` and `reason`,
+    /// and all substreams on the connection fail, including those opened by other clones.
+    /// The connector does not reconnect afterwards, opening a substream fails with
+    /// [quinn::ConnectionError::LocallyClosed]. Without calling this, the connection is
+    /// closed once the last clone is dropped, unless it was passed to
+    /// [QuinnConnector::from_connection].
+    pub fn close(&self, code: quinn::VarInt, reason: &[u8]) {
+        self.inner.close(code, reason)
+    }
+
+    /// Returns true if [QuinnConnector::close] has been called on any clone.
+    pub fn is_closed(&self) -> bool {
+        self.inner.is_closed()
+    }
 }
 
 struct ReconnectHandler {
     endpoint: quinn::Endpoint,
+    client_config: Option<quinn::ClientConfig>,
     state: ConnectionState,
-    addr: SocketAddr,
+    remote: Remote,
     name: String,
 }
 
@@ -486,13 +2629,49 @@ impl ReconnectHandler {
     pub fn connected(&self) -> bool {
         matches!(self.state, ConnectionState::Connected(_))
     }
+
+    /// Start connecting to the first of `addrs` that can be connected to
+    fn connect_next(&mut self, mut addrs: VecDeque<SocketAddr>) -> Result<(), ReconnectErr> {
+        let mut last_err = None;
+        while let Some(addr) = addrs.pop_front() {
+            let connecting = match &self.client_config {
+                Some(config) => self.endpoint.connect_with(config.clone(), addr, &self.name),
+                None => self.endpoint.connect(addr, &self.name),
+            };
+            match connecting {
+                Ok(connecting) => {
+                    self.state = ConnectionState::Connecting(connecting, addrs);
+                    return Ok(());
+                }
+                Err(e) => {
+                    tracing::debug!(%addr, %e, "failed to connect, trying next address");
+                    last_err = Some(e);
+                }
+            }
+        }
+        self.state = ConnectionState::NotConnected;
+        Err(ReconnectErr::Connect(
+            last_err.unwrap_or(quinn::ConnectError::EndpointStopping),
+        ))
+    }
+
+    /// Wait before resolving again, and fail with `e`
+    fn resolve_failed(&mut self, e: ResolveError) -> Poll<Result<quinn::Connection, ReconnectErr>> {
+        let retry = Box::pin(tokio::time::sleep(RESOLVE_RETRY_DELAY));
+        self.state = ConnectionState::ResolveFailed(retry);
+        Poll::Ready(Err(ReconnectErr::Resolve(e)))
+    }
 }
 
 enum ConnectionState {
     /// There is no active connection. An attempt to connect will be made.
     NotConnected,
-    /// Connecting to the remote.
-    Connecting(quinn::Connecting),
+    /// Resolving the remote to addresses.
+    Resolving(BoxFuture<'static, Result<Vec<SocketAddr>, ResolveError>>),
+    /// Resolving failed, waiting before resolving again.
+    ResolveFailed(BoxFuture<'static, ()>),
+    /// Connecting to the remote, with the addresses to try if this fails.
+    Connecting(quinn::Connecting, VecDeque<SocketAddr>),
     /// A connection is already established. In this state, no more connection attempts are made.
     Connected(quinn::Connection),
     /// Intermediate state while processing.
@@ -512,6 +2691,7 @@ impl ConnectionState {
 enum ReconnectErr {
     Connect(quinn::ConnectError),
     Connection(quinn::ConnectionError),
+    Resolve(ResolveError),
 }
 
 impl Future for ReconnectHandler {
@@ -519,33 +2699,68 @@ impl Future for ReconnectHandler {
 
     fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         match self.state.poison() {
-            ConnectionState::NotConnected => match self.endpoint.connect(self.addr, &self.name) {
-                Ok(connecting) => {
-                    self.state = ConnectionState::Connecting(connecting);
-                    self.poll(cx)
+            ConnectionState::NotConnected => match &self.remote.0 {
+                RemoteInner::Addr(addr) => {
+                    let addrs = VecDeque::from([*addr]);
+                    match self.connect_next(addrs) {
+                        Ok(()) => self.poll(cx),
+                        Err(e) => Poll::Ready(Err(e)),
+                    }
                 }
-                Err(e) => {
-                    self.state = ConnectionState::NotConnected;
-                    Poll::Ready(Err(ReconnectErr::Connect(e)))
+                RemoteInner::Resolve { host, resolver } => {
+                    self.state = ConnectionState::Resolving(resolver(host));
+                    self.poll(cx)
                 }
             },
-            ConnectionState::Connecting(mut connecting) => match Pin::new(&mut connecting).poll(cx)
-            {
-                Poll::Ready(res) => match res {
-                    Ok(connection) => {
-                        self.state = ConnectionState::Connected(connection.clone());
-                        Poll::Ready(Ok(connection))
-                    }
-                    Err(e) => {
-                        self.state = ConnectionState::NotConnected;
-                        Poll::Ready(Err(ReconnectErr::Connection(e)))
-                    }
+            ConnectionState::Resolving(mut resolving) => match resolving.as_mut().poll(cx) {
+                Poll::Ready(Ok(addrs)) if addrs.is_empty() => {
+                    self.resolve_failed(ResolveError::new("no addresses"))
+                }
+                Poll::Ready(Ok(addrs)) => match self.connect_next(addrs.into()) {
+                    Ok(()) => self.poll(cx),
+                    Err(e) => Poll::Ready(Err(e)),
                 },
+                Poll::Ready(Err(e)) => self.resolve_failed(e),
+                Poll::Pending => {
+                    self.state = ConnectionState::Resolving(resolving);
+                    Poll::Pending
+                }
+            },
+            ConnectionState::ResolveFailed(mut retry) => match retry.as_mut().poll(cx) {
+                Poll::Ready(()) => {
+                    self.state = ConnectionState::NotConnected;
+                    self.poll(cx)
+                }
                 Poll::Pending => {
-                    self.state = ConnectionState::Connecting(connecting);
+                    self.state = ConnectionState::ResolveFailed(retry);
                     Poll::Pending
                 }
             },
+            ConnectionState::Connecting(mut connecting, addrs) => {
+                match Pin::new(&mut connecting).poll(cx) {
+                    Poll::Ready(res) => match res {
+                        Ok(connection) => {
+                            self.state = ConnectionState::Connected(connection.clone());
+                            Poll::Ready(Ok(connection))
+                        }
+                        Err(e) if !addrs.is_empty() => {
+                            tracing::debug!(%e, "failed to connect, trying next address");
+                            match self.connect_next(addrs) {
+                                Ok(()) => self.poll(cx),
+                                Err(e) => Poll::Ready(Err(e)),
+                            }
+                        }
+                        Err(e) => {
+                            self.state = ConnectionState::NotConnected;
+                            Poll::Ready(Err(ReconnectErr::Connection(e)))
+                        }
+                    },
+                    Poll::Pending => {
+                        self.state = ConnectionState::Connecting(connecting, addrs);
+                        Poll::Pending
+                    }
+                }
+            }
             ConnectionState::Connected(connection) => {
                 self.state = ConnectionState::Connected(connection.clone());
                 Poll::Ready(Ok(connection))
@@ -607,145 +2822,702 @@ impl<'a, T> Stream for Receiver<'a, T> {
     }
 }
 
-impl<In: RpcMessage, Out: RpcMessage> fmt::Debug for QuinnConnector<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> fmt::Debug for QuinnConnector<In, Out, C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ClientChannel")
             .field("inner", &self.inner)
+            .field("codec", &self.codec)
+            .field("max_frame_size", &self.max_frame_size)
+            .field("pool", &self.pool)
+            .field("ping_timeout", &self.ping_timeout)
             .finish()
     }
 }
 
-impl<In: RpcMessage, Out: RpcMessage> Clone for QuinnConnector<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Clone for QuinnConnector<In, Out, C> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
+            codec: self.codec.clone(),
+            max_frame_size: self.max_frame_size,
+            pool: self.pool.clone(),
+            ping_timeout: self.ping_timeout,
             _p: PhantomData,
         }
     }
 }
 
-impl<In: RpcMessage, Out: RpcMessage> ConnectionErrors for QuinnConnector<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> ConnectionErrors for QuinnConnector<In, Out, C> {
     type SendError = io::Error;
     type RecvError = io::Error;
     type OpenError = quinn::ConnectionError;
     type AcceptError = quinn::ConnectionError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        util::is_stopped(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        util::is_clean_close(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        super::codec::unknown_message(error).is_some()
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        util::is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        util::is_cancelled(error)
+    }
 }
 
-impl<In: RpcMessage, Out: RpcMessage> StreamTypes for QuinnConnector<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> StreamTypes for QuinnConnector<In, Out, C> {
     type In = In;
     type Out = Out;
-    type SendSink = self::SendSink<Out>;
-    type RecvStream = self::RecvStream<In>;
+    type SendSink = self::SendSink<Out, C>;
+    type RecvStream = self::RecvStream<In, C>;
+
+    fn set_priority(send: &Self::SendSink, priority: i32) -> Result<(), PriorityError> {
+        send.set_priority(priority)
+    }
+
+    fn send_closed(send: &Self::SendSink) -> impl Future<Output = ()> + Send + 'static {
+        util::send_closed(&send.0)
+    }
+
+    fn cancel(send: &mut Self::SendSink) {
+        send.cancel()
+    }
 }
 
-impl<In: RpcMessage, Out: RpcMessage> Connector for QuinnConnector<In, Out> {
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> RawStreamTypes for QuinnConnector<In, Out, C> {
+    type RawRecvStream = self::RawRecvStream<quinn::RecvStream>;
+    type RawSendSink = self::RawSendSink<quinn::SendStream>;
+
+    fn into_raw(
+        mut send: Self::SendSink,
+        mut recv: Self::RecvStream,
+    ) -> (Self::RawSendSink, Self::RawRecvStream) {
+        (send.take_framed().into_raw(), recv.take_framed().into_raw())
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Connector for QuinnConnector<In, Out, C> {
     async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
-        let (sender, receiver) = oneshot::channel();
-        self.inner
-            .sender
-            .send_async(sender)
-            .await
-            .map_err(|_| quinn::ConnectionError::LocallyClosed)?;
-        let (send, recv) = receiver
+        self.open_substream(false).await
+    }
+
+    /// Open a substream for a rpc call, which is reused if the connector has a
+    /// [SubstreamPool]
+    async fn open_rpc(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        self.open_substream(true).await
+    }
+
+    fn stats(&self) -> Option<ConnectionStats> {
+        self.inner.connection.stats()
+    }
+
+    /// Send a ping on the stream that is also used for heartbeats.
+    ///
+    /// Like heartbeats, this needs the listener to answer them, see [KeepAliveConfig].
+    /// Fails with [PingError::Timeout] if there is no answer in time, see
+    /// [QuinnConnector::with_ping_timeout].
+    async fn ping(&self) -> Result<Duration, PingError> {
+        let ping = async {
+            let (sender, receiver) = oneshot::channel();
+            self.inner
+                .pings
+                .send_async(sender)
+                .await
+                .map_err(|_| PingError::Closed)?;
+            receiver.await.map_err(|_| PingError::Closed)
+        };
+        tokio::time::timeout(self.ping_timeout, ping)
             .await
-            .map_err(|_| quinn::ConnectionError::LocallyClosed)??;
-        Ok((SendSink::new(send), RecvStream::new(recv)))
+            .map_err(|_| PingError::Timeout)?
+    }
+
+    /// Accept a bidi stream opened by the listener using
+    /// [Connection::open_push](super::Connection::open_push).
+    ///
+    /// A connector that reconnects keeps accepting pushes on each new connection, so
+    /// this only returns `None` once all clones of the connector are dropped. For a
+    /// connector created with [QuinnConnector::from_connection], this returns `None`
+    /// once the connection is closed.
+    ///
+    /// The connector only starts accepting the pushed channels of its connections with
+    /// the first call. Until then, they are held back by the stream limit of the
+    /// connection, just like pushes that are not accepted fast enough.
+    async fn accept_push(&self) -> Option<(Self::SendSink, Self::RecvStream)> {
+        self.inner.accept_pushes.want(&self.inner.connection);
+        let (send, recv) = self.inner.pushes.recv_async().await.ok()?;
+        Some((
+            SendSink::new(send, self.codec.clone(), self.max_frame_size),
+            RecvStream::new(recv, self.codec.clone(), self.max_frame_size),
+        ))
+    }
+}
+
+/// Shared by the two halves of a call on a substream that carries more than one call
+///
+/// See [QuinnConnector::with_substream_pool]. On such a substream, an empty frame ends a
+/// call in its direction, instead of the end of the stream. The halves hand back their
+/// framing when they are dropped, including anything that is buffered, and once both
+/// are back, the substream is prepared for the next call.
+#[derive(Debug)]
+struct Reuse {
+    state: Mutex<ReuseState>,
+    recycle: Recycle,
+}
+
+#[derive(Debug)]
+struct ReuseState {
+    /// Whether the substream is reused, `None` until the listener read its first frame
+    reusable: Option<bool>,
+    /// A half was dropped without handing back its framing, or failed
+    broken: bool,
+    /// The halves that were not handed back yet
+    halves: u8,
+    send: Option<RawSendSink<quinn::SendStream>>,
+    recv: Option<RawRecvStream<quinn::RecvStream>>,
+    /// The remote ended the call
+    ended: bool,
+}
+
+/// What to do with a substream once both halves of a call were handed back
+#[derive(Debug)]
+enum Recycle {
+    /// Put it into the pool of a connector
+    Pool {
+        pool: Weak<IdleStreams>,
+        /// The [quinn::Connection::stable_id] of the connection of the substream
+        connection: usize,
+    },
+    /// Hand it to the listener again, once the next call arrives
+    Listener {
+        requeue: flume::Sender<Substream>,
+        remote: Option<Arc<RemoteInfo>>,
+        activity: Option<Arc<ConnectionActivity>>,
+    },
+}
+
+impl Reuse {
+    fn new(reusable: Option<bool>, recycle: Recycle) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(ReuseState {
+                reusable,
+                broken: false,
+                halves: 2,
+                send: None,
+                recv: None,
+                ended: false,
+            }),
+            recycle,
+        })
+    }
+
+    fn reusable(&self) -> Option<bool> {
+        self.state.lock().unwrap().reusable
+    }
+
+    fn set_reusable(&self, reusable: bool) {
+        self.state.lock().unwrap().reusable = Some(reusable);
+    }
+
+    /// Hand back the send half of a call.
+    ///
+    /// The empty frame that ends the call is flushed before the half counts as handed
+    /// back, so the remote sees the end of the call while the receive half is in use.
+    fn release_send(self: Arc<Self>, mut send: RawSendSink<quinn::SendStream>, end_queued: bool) {
+        if self.reusable() != Some(true)
+            || (!end_queued && Pin::new(&mut send).start_send(Bytes::new()).is_err())
+        {
+            drop(send);
+            self.discard();
+            return;
+        }
+        let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+        match Pin::new(&mut send).poll_flush(&mut cx) {
+            Poll::Ready(Ok(())) => self.release(|state| state.send = Some(send)),
+            Poll::Ready(Err(_)) => {
+                drop(send);
+                self.discard();
+            }
+            Poll::Pending => {
+                tokio::spawn(async move {
+                    let flushed =
+                        std::future::poll_fn(|cx| Pin::new(&mut send).poll_flush(cx)).await;
+                    match flushed {
+                        Ok(()) => self.release(|state| state.send = Some(send)),
+                        Err(_) => self.discard(),
+                    }
+                });
+            }
+        }
+    }
+
+    /// Hand back the receive half of a call, `ended` if it read the end of the call
+    fn release_recv(&self, recv: RawRecvStream<quinn::RecvStream>, ended: bool) {
+        if self.reusable() == Some(true) {
+            self.release(|state| {
+                state.recv = Some(recv);
+                state.ended = ended;
+            });
+        } else {
+            drop(recv);
+            self.discard();
+        }
+    }
+
+    /// Count a half as handed back without its framing, so the substream is not reused
+    fn discard(&self) {
+        self.release(|state| state.broken = true);
+    }
+
+    /// Count a half as handed back, after `hand_back` stored its framing
+    fn release(&self, hand_back: impl FnOnce(&mut ReuseState)) {
+        let mut state = self.state.lock().unwrap();
+        hand_back(&mut state);
+        state.halves -= 1;
+        if state.halves > 0 {
+            return;
+        }
+        let reusable = state.reusable == Some(true) && !state.broken;
+        let (send, recv) = (state.send.take(), state.recv.take());
+        let ended = state.ended;
+        drop(state);
+        if let (true, Some(send), Some(recv)) = (reusable, send, recv) {
+            self.recycle.start(send, recv, ended);
+        }
+    }
+}
+
+impl Recycle {
+    /// Prepare a substream for the next call, once the remote ended the current one
+    fn start(
+        &self,
+        send: RawSendSink<quinn::SendStream>,
+        recv: RawRecvStream<quinn::RecvStream>,
+        ended: bool,
+    ) {
+        let mut end = EndOfCall { recv, ended };
+        match self {
+            Recycle::Pool { pool, connection } => {
+                let connection = *connection;
+                // the end of the call usually arrived together with the last response
+                let mut cx = Context::from_waker(futures::task::noop_waker_ref());
+                match end.poll_end(&mut cx) {
+                    Poll::Ready(true) => {
+                        if let Some(pool) = pool.upgrade() {
+                            pool.put(send, end.recv, connection);
+                        }
+                    }
+                    Poll::Ready(false) => {}
+                    Poll::Pending => {
+                        let pool = pool.clone();
+                        tokio::spawn(async move {
+                            let Some(ttl) = pool.upgrade().map(|pool| pool.config.idle_ttl) else {
+                                return;
+                            };
+                            let ended = std::future::poll_fn(|cx| end.poll_end(cx));
+                            let ended = tokio::time::timeout(ttl, ended).await;
+                            if let (Ok(true), Some(pool)) = (ended, pool.upgrade()) {
+                                pool.put(send, end.recv, connection);
+                            }
+                        });
+                    }
+                }
+            }
+            Recycle::Listener {
+                requeue,
+                remote,
+                activity,
+            } => {
+                let requeue = requeue.clone();
+                let remote = remote.clone();
+                let activity = activity.clone();
+                tokio::spawn(async move {
+                    if !std::future::poll_fn(|cx| end.poll_end(cx)).await {
+                        return;
+                    }
+                    // the substream is idle until the next call arrives
+                    let mut recv = end.recv;
+                    let next = std::future::poll_fn(|cx| Pin::new(&mut recv).poll_peek(cx));
+                    if !matches!(next.await, Ok(true)) {
+                        return;
+                    }
+                    if let Some(activity) = &activity {
+                        activity.acquire();
+                    }
+                    let substream = Substream {
+                        send,
+                        recv,
+                        remote,
+                        activity,
+                    };
+                    if let Err(flume::SendError(substream)) = requeue.send_async(substream).await {
+                        if let Some(activity) = &substream.activity {
+                            activity.release();
+                        }
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Skips the rest of a call on the receive side of a substream that is reused
+struct EndOfCall {
+    recv: RawRecvStream<quinn::RecvStream>,
+    ended: bool,
+}
+
+impl EndOfCall {
+    /// Resolves to `false` if the substream ended or failed before the call did
+    fn poll_end(&mut self, cx: &mut Context<'_>) -> Poll<bool> {
+        while !self.ended {
+            match std::task::ready!(Pin::new(&mut self.recv).poll_next(cx)) {
+                Some(Ok(frame)) => self.ended = frame.is_empty(),
+                Some(Err(_)) | None => return Poll::Ready(false),
+            }
+        }
+        Poll::Ready(true)
+    }
+}
+
+/// A substream accepted by a listener again, because it carries another call
+#[derive(Debug)]
+struct Substream {
+    send: RawSendSink<quinn::SendStream>,
+    recv: RawRecvStream<quinn::RecvStream>,
+    remote: Option<Arc<RemoteInfo>>,
+    activity: Option<Arc<ConnectionActivity>>,
+}
+
+/// The send half of a call on a substream that can be reused
+#[derive(Debug)]
+struct SendReuse {
+    reuse: Arc<Reuse>,
+    /// The empty frame that ends the call was queued
+    end_queued: bool,
+}
+
+/// The receive half of a call on a substream that can be reused
+#[derive(Debug)]
+struct RecvReuse {
+    reuse: Arc<Reuse>,
+    /// Whether the substream is reused, `None` until the listener read its first frame
+    reusable: Option<bool>,
+    /// The remote ended the call
+    ended: bool,
+}
+
+impl RecvReuse {
+    /// Poll for the next message of the call, skipping the frames that mark the start
+    /// and the end of calls
+    fn poll_next<In: DeserializeOwned, C: Codec>(
+        &mut self,
+        mut framed: Pin<&mut FramedCodecRead<quinn::RecvStream, In, C>>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<In>>> {
+        loop {
+            if self.ended {
+                return Poll::Ready(None);
+            }
+            match std::task::ready!(framed.as_mut().poll_next_frame(cx)) {
+                Some(Ok(frame)) if frame.is_empty() && self.reusable != Some(false) => {
+                    if self.reusable.is_none() {
+                        // the connector announces that it reuses the substream
+                        self.reusable = Some(true);
+                        self.reuse.set_reusable(true);
+                    } else {
+                        self.ended = true;
+                    }
+                }
+                Some(Ok(frame)) => {
+                    if self.reusable.is_none() {
+                        self.reusable = Some(false);
+                        self.reuse.set_reusable(false);
+                    }
+                    return Poll::Ready(Some(framed.decode(frame)));
+                }
+                Some(Err(cause)) => return Poll::Ready(Some(Err(cause))),
+                None => return Poll::Ready(None),
+            }
+        }
     }
 }
 
-/// A sink that wraps a quinn SendStream with length delimiting and bincode
+/// A sink that wraps a quinn SendStream with length delimiting and a [Codec]
+///
+/// Closing the sink finishes the stream, and only completes once the remote has
+/// acknowledged all data, so the last frames are not lost if the connection is closed
+/// right after. On a substream that is reused, see [QuinnConnector::with_substream_pool],
+/// closing the sink ends the call instead, and only waits until that is flushed.
 ///
 /// If you want to send bytes directly, use [SendSink::into_inner] to get the
 /// underlying [quinn::SendStream].
-#[pin_project]
-pub struct SendSink<Out>(#[pin] FramedBincodeWrite<quinn::SendStream, Out>);
-
-impl<Out> fmt::Debug for SendSink<Out> {
+pub struct SendSink<Out, C = BincodeCodec>(
+    /// Only empty once taken when the sink is consumed
+    util::SharedSend<FramedCodecWrite<quinn::SendStream, Out, C>>,
+    Option<Arc<StreamControl>>,
+    util::Acknowledged,
+    Option<SendReuse>,
+);
+
+impl<Out, C> fmt::Debug for SendSink<Out, C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("SendSink").finish()
     }
 }
 
-impl<Out: Serialize> SendSink<Out> {
-    fn new(inner: quinn::SendStream) -> Self {
-        let inner = FramedBincodeWrite::new(inner, MAX_FRAME_LENGTH);
-        Self(inner)
+impl<Out: Serialize, C: Codec> SendSink<Out, C> {
+    fn new(inner: quinn::SendStream, codec: C, max_frame_size: usize) -> Self {
+        let inner = FramedCodecWrite::new(inner, codec, max_frame_size);
+        Self(util::SharedSend::new(inner), None, Default::default(), None)
     }
 }
 
-impl<Out> SendSink<Out> {
+impl<Out, C> SendSink<Out, C> {
     /// Get the underlying [quinn::SendStream], which implements
     /// [tokio::io::AsyncWrite] and can be used to send bytes directly.
-    pub fn into_inner(self) -> quinn::SendStream {
-        self.0.into_inner()
+    ///
+    /// The substream is no longer reused after this.
+    pub fn into_inner(mut self) -> quinn::SendStream {
+        self.take_framed().into_inner()
+    }
+
+    /// Set the priority of the stream, see [StreamTypes::set_priority]
+    ///
+    /// This is [quinn::SendStream::set_priority]. Fails if the stream is already closed.
+    pub fn set_priority(&self, priority: i32) -> Result<(), PriorityError> {
+        self.0
+            .with(|framed| framed.get_ref().set_priority(priority))
+            .map_err(|_| PriorityError::Closed)
+    }
+
+    fn take_framed(&mut self) -> FramedCodecWrite<quinn::SendStream, Out, C> {
+        self.stop_reuse();
+        self.0.take().expect("only taken when consumed")
+    }
+
+    /// Do not reuse the substream, e.g. after an error
+    fn stop_reuse(&mut self) {
+        if let Some(reuse) = self.3.take() {
+            reuse.reuse.discard();
+        }
+    }
+
+    /// Stop reusing the substream if `res` is an error
+    fn check<T>(&mut self, res: io::Result<T>) -> io::Result<T> {
+        if res.is_err() {
+            self.stop_reuse();
+        }
+        res
+    }
+
+    /// Reset the stream with [CANCEL_CODE], see [StreamTypes::cancel]
+    fn cancel(&mut self) {
+        self.stop_reuse();
+        self.0.with(|framed| {
+            Pin::new(framed)
+                .get_pin_mut()
+                .get_mut()
+                .reset(CANCEL_CODE)
+                .ok()
+        });
+    }
+
+    /// Reset the stream if this was requested using [QuinnListener::reset_stream]
+    fn check_reset(&mut self, cx: Option<&Context<'_>>) -> io::Result<()> {
+        let Some(control) = &self.1 else {
+            return Ok(());
+        };
+        let code = match cx {
+            Some(cx) => control.poll_reset(&control.send_waker, cx),
+            None => control.reset.get().copied(),
+        };
+        match code {
+            Some(code) => {
+                self.0
+                    .with(|framed| Pin::new(framed).get_pin_mut().get_mut().reset(code).ok());
+                self.stop_reuse();
+                Err(reset_locally())
+            }
+            None => Ok(()),
+        }
     }
 }
 
-impl<Out: Serialize> Sink<Out> for SendSink<Out> {
+impl<Out: Serialize + Unpin, C: Codec> Sink<Out> for SendSink<Out, C> {
     type Error = io::Error;
 
     fn poll_ready(
         self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_ready(cx)
+        let this = self.get_mut();
+        this.check_reset(Some(cx))?;
+        let res = std::task::ready!(this.0.with(|framed| Pin::new(framed).poll_ready(cx)));
+        Poll::Ready(this.check(res))
     }
 
     fn start_send(self: Pin<&mut Self>, item: Out) -> Result<(), Self::Error> {
-        Pin::new(&mut self.project().0).start_send(item)
+        let this = self.get_mut();
+        this.check_reset(None)?;
+        let res = this.0.with(|framed| Pin::new(framed).start_send(item));
+        this.check(res)
     }
 
     fn poll_flush(
         self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_flush(cx)
+        let this = self.get_mut();
+        this.check_reset(Some(cx))?;
+        let res = std::task::ready!(this.0.with(|framed| Pin::new(framed).poll_flush(cx)));
+        Poll::Ready(this.check(res))
     }
 
     fn poll_close(
         self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_close(cx)
+        let this = self.get_mut();
+        this.check_reset(Some(cx))?;
+        let end_queued = match &mut this.3 {
+            Some(reuse) if reuse.reuse.reusable() == Some(true) => {
+                Some(std::mem::replace(&mut reuse.end_queued, true))
+            }
+            _ => None,
+        };
+        if let Some(end_queued) = end_queued {
+            // end the call, but keep the substream open for the next one
+            if !end_queued {
+                let res = this
+                    .0
+                    .with(|framed| Pin::new(framed).start_send_frame(Bytes::new()));
+                this.check(res)?;
+            }
+            let res = std::task::ready!(this.0.with(|framed| Pin::new(framed).poll_flush(cx)));
+            return Poll::Ready(this.check(res));
+        }
+        let acknowledged = &mut this.2;
+        this.0.with(|framed| {
+            std::task::ready!(Pin::new(&mut *framed).poll_close(cx))?;
+            let stream = Pin::new(framed).get_pin_mut().get_mut();
+            acknowledged.poll(stream, cx)
+        })
+    }
+}
+
+impl<Out, C> Drop for SendSink<Out, C> {
+    fn drop(&mut self) {
+        if let (Some(reuse), Some(inner)) = (self.3.take(), self.0.take()) {
+            reuse.reuse.release_send(inner.into_raw(), reuse.end_queued);
+        }
     }
 }
 
-/// A stream that wraps a quinn RecvStream with length delimiting and bincode
+/// A stream that wraps a quinn RecvStream with length delimiting and a [Codec]
 ///
 /// If you want to receive bytes directly, use [RecvStream::into_inner] to get
 /// the underlying [quinn::RecvStream].
-#[pin_project]
-pub struct RecvStream<In>(#[pin] FramedBincodeRead<quinn::RecvStream, In>);
-
-impl<In> fmt::Debug for RecvStream<In> {
+pub struct RecvStream<In, C = BincodeCodec>(
+    /// Only `None` once taken when the stream is consumed
+    Option<FramedCodecRead<quinn::RecvStream, In, C>>,
+    Option<Arc<RemoteInfo>>,
+    Option<Arc<StreamControl>>,
+    Option<RecvReuse>,
+);
+
+impl<In, C> fmt::Debug for RecvStream<In, C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("RecvStream").finish()
     }
 }
 
-impl<In: DeserializeOwned> RecvStream<In> {
-    fn new(inner: quinn::RecvStream) -> Self {
-        let inner = FramedBincodeRead::new(inner, MAX_FRAME_LENGTH);
-        Self(inner)
+impl<In: DeserializeOwned, C: Codec> RecvStream<In, C> {
+    fn new(inner: quinn::RecvStream, codec: C, max_frame_size: usize) -> Self {
+        let inner = RawRecvStream::new(inner, max_frame_size).with_codec(codec);
+        Self(Some(inner), None, None, None)
     }
 }
 
-impl<In> RecvStream<In> {
+impl<In, C> RecvStream<In, C> {
     /// Get the underlying [quinn::RecvStream], which implements
     /// [tokio::io::AsyncRead] and can be used to receive bytes directly.
-    pub fn into_inner(self) -> quinn::RecvStream {
-        self.0.into_inner()
+    ///
+    /// The substream is no longer reused after this.
+    pub fn into_inner(mut self) -> quinn::RecvStream {
+        self.take_framed().into_inner()
+    }
+
+    fn take_framed(&mut self) -> FramedCodecRead<quinn::RecvStream, In, C> {
+        self.stop_reuse();
+        self.0.take().expect("only taken when consumed")
+    }
+
+    /// Do not reuse the substream, e.g. after an error
+    fn stop_reuse(&mut self) {
+        if let Some(reuse) = self.3.take() {
+            reuse.reuse.discard();
+        }
     }
 }
 
-impl<In: DeserializeOwned> Stream for RecvStream<In> {
+impl<In: DeserializeOwned + Unpin, C: Codec> Stream for RecvStream<In, C> {
     type Item = result::Result<In, io::Error>;
 
     fn poll_next(
         self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        Pin::new(&mut self.project().0).poll_next(cx)
+        let this = self.get_mut();
+        let inner = this.0.as_mut().expect("only taken when consumed");
+        if let Some(control) = &this.2 {
+            if let Some(code) = control.poll_reset(&control.recv_waker, cx) {
+                Pin::new(inner).get_pin_mut().get_mut().stop(code).ok();
+                this.stop_reuse();
+                return Poll::Ready(Some(Err(reset_locally())));
+            }
+        }
+        let res = match &mut this.3 {
+            Some(reuse) => reuse.poll_next(Pin::new(&mut *inner), cx),
+            None => Pin::new(&mut *inner).poll_next(cx),
+        };
+        if let Poll::Ready(Some(Err(cause))) = &res {
+            if frame_too_large(cause).is_some() {
+                // stop just this substream, so the peer does not keep sending
+                Pin::new(inner)
+                    .get_pin_mut()
+                    .get_mut()
+                    .stop(0u8.into())
+                    .ok();
+            }
+        }
+        // only a call that the remote ended properly leaves the substream reusable
+        let reusable = match (&this.3, &res) {
+            (Some(reuse), _) if reuse.reusable == Some(false) => false,
+            (Some(reuse), Poll::Ready(None)) => reuse.ended,
+            (_, Poll::Ready(Some(Err(_)))) => false,
+            _ => true,
+        };
+        if !reusable {
+            this.stop_reuse();
+        }
+        res
+    }
+}
+
+impl<In, C> Drop for RecvStream<In, C> {
+    fn drop(&mut self) {
+        if let (Some(reuse), Some(inner)) = (self.3.take(), self.0.take()) {
+            reuse.reuse.release_recv(inner.into_raw(), reuse.ended);
+        }
     }
 }
 
@@ -792,6 +3564,126 @@ impl fmt::Display for CreateChannelError {
 
 impl std::error::Error for CreateChannelError {}
 
+/// How a [QuinnListener] verifies the certificates of its clients
+///
+/// See [server_config_with_client_auth].
+#[derive(Debug, Clone)]
+pub enum ClientAuth {
+    /// Require a client certificate issued by one of these roots
+    Roots(Arc<quinn::rustls::RootCertStore>),
+    /// Verify client certificates using a custom verifier
+    Verifier(Arc<dyn quinn::rustls::server::danger::ClientCertVerifier>),
+}
+
+impl From<quinn::rustls::RootCertStore> for ClientAuth {
+    fn from(roots: quinn::rustls::RootCertStore) -> Self {
+        Self::Roots(Arc::new(roots))
+    }
+}
+
+impl From<Arc<dyn quinn::rustls::server::danger::ClientCertVerifier>> for ClientAuth {
+    fn from(verifier: Arc<dyn quinn::rustls::server::danger::ClientCertVerifier>) -> Self {
+        Self::Verifier(verifier)
+    }
+}
+
+/// Error when creating a TLS config for mutual authentication
+#[derive(Debug)]
+pub enum TlsConfigError {
+    /// The certificates or the key were rejected by rustls
+    Rustls(quinn::rustls::Error),
+    /// The client certificate verifier could not be built from the roots
+    Verifier(quinn::rustls::server::VerifierBuilderError),
+    /// The resulting TLS config can not be used for QUIC
+    NoInitialCipherSuite(quinn::crypto::rustls::NoInitialCipherSuite),
+    /// Creating the listener failed
+    Io(io::Error),
+}
+
+impl From<quinn::rustls::Error> for TlsConfigError {
+    fn from(e: quinn::rustls::Error) -> Self {
+        TlsConfigError::Rustls(e)
+    }
+}
+
+impl From<quinn::rustls::server::VerifierBuilderError> for TlsConfigError {
+    fn from(e: quinn::rustls::server::VerifierBuilderError) -> Self {
+        TlsConfigError::Verifier(e)
+    }
+}
+
+impl From<quinn::crypto::rustls::NoInitialCipherSuite> for TlsConfigError {
+    fn from(e: quinn::crypto::rustls::NoInitialCipherSuite) -> Self {
+        TlsConfigError::NoInitialCipherSuite(e)
+    }
+}
+
+impl From<io::Error> for TlsConfigError {
+    fn from(e: io::Error) -> Self {
+        TlsConfigError::Io(e)
+    }
+}
+
+impl fmt::Display for TlsConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for TlsConfigError {}
+
+/// The crypto provider used for the TLS configs created by this module
+fn crypto_provider() -> Arc<quinn::rustls::crypto::CryptoProvider> {
+    Arc::new(quinn::rustls::crypto::ring::default_provider())
+}
+
+/// Create a server config that requires clients to present a valid certificate.
+///
+/// The server authenticates itself using `cert_chain` and `key`. Connections from
+/// clients without a certificate, or with a certificate rejected by `client_auth`,
+/// fail during the handshake. The verified client certificates are available to
+/// handlers via [Listener::remote_info].
+pub fn server_config_with_client_auth(
+    cert_chain: Vec<quinn::rustls::pki_types::CertificateDer<'static>>,
+    key: quinn::rustls::pki_types::PrivateKeyDer<'static>,
+    client_auth: impl Into<ClientAuth>,
+) -> Result<quinn::ServerConfig, TlsConfigError> {
+    let provider = crypto_provider();
+    let verifier = match client_auth.into() {
+        ClientAuth::Roots(roots) => {
+            quinn::rustls::server::WebPkiClientVerifier::builder_with_provider(
+                roots,
+                provider.clone(),
+            )
+            .build()?
+        }
+        ClientAuth::Verifier(verifier) => verifier,
+    };
+    let crypto = quinn::rustls::ServerConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&quinn::rustls::version::TLS13])?
+        .with_client_cert_verifier(verifier)
+        .with_single_cert(cert_chain, key)?;
+    let crypto = quinn::crypto::rustls::QuicServerConfig::try_from(crypto)?;
+    Ok(quinn::ServerConfig::with_crypto(Arc::new(crypto)))
+}
+
+/// Create a client config that authenticates with a client certificate.
+///
+/// The server is verified against `roots`, and the client authenticates itself using
+/// `cert_chain` and `key`.
+pub fn client_config_with_cert(
+    roots: quinn::rustls::RootCertStore,
+    cert_chain: Vec<quinn::rustls::pki_types::CertificateDer<'static>>,
+    key: quinn::rustls::pki_types::PrivateKeyDer<'static>,
+) -> Result<quinn::ClientConfig, TlsConfigError> {
+    let crypto = quinn::rustls::ClientConfig::builder_with_provider(crypto_provider())
+        .with_protocol_versions(&[&quinn::rustls::version::TLS13])?
+        .with_root_certificates(roots)
+        .with_client_auth_cert(cert_chain, key)?;
+    let crypto = quinn::crypto::rustls::QuicClientConfig::try_from(crypto)?;
+    Ok(quinn::ClientConfig::new(Arc::new(crypto)))
+}
+
 /// Get the handshake data from a quinn connection that uses rustls.
 pub fn get_handshake_data(
     connection: &quinn::Connection,
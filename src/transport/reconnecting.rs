@@ -0,0 +1,249 @@
+//! Connector that transparently re-creates its inner connector when opening fails.
+//!
+//! This is useful for transports where a connector is bound to a single underlying
+//! connection, and a failed [`Connector::open`] means that the connection is gone.
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use futures::{future::BoxFuture, FutureExt};
+
+use super::{ConnectionErrors, ConnectionStats, Connector, PingError, StreamTypes};
+
+type MakeConnector<C> =
+    Box<dyn FnMut() -> BoxFuture<'static, Result<C, <C as ConnectionErrors>::OpenError>> + Send>;
+
+/// Backoff policy for [`ReconnectingConnector`] and
+/// [`RpcClient::rpc_with_retry`](crate::RpcClient::rpc_with_retry).
+///
+/// The delay between attempts starts at `initial_delay` and is multiplied by
+/// `multiplier` after each failed attempt, up to `max_delay`.
+#[derive(Debug, Clone)]
+pub struct BackoffPolicy {
+    pub(crate) initial_delay: Duration,
+    pub(crate) max_delay: Duration,
+    pub(crate) multiplier: u32,
+    pub(crate) max_attempts: usize,
+}
+
+impl BackoffPolicy {
+    /// Set the delay before the first retry.
+    pub fn initial_delay(mut self, value: Duration) -> Self {
+        self.initial_delay = value;
+        self
+    }
+
+    /// Set the maximum delay between two attempts.
+    pub fn max_delay(mut self, value: Duration) -> Self {
+        self.max_delay = value;
+        self
+    }
+
+    /// Set the factor by which the delay grows after each failed attempt.
+    pub fn multiplier(mut self, value: u32) -> Self {
+        self.multiplier = value.max(1);
+        self
+    }
+
+    /// Set the maximum number of attempts for a single open or call, including the first one.
+    pub fn max_attempts(mut self, value: usize) -> Self {
+        self.max_attempts = value.max(1);
+        self
+    }
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(10),
+            multiplier: 2,
+            max_attempts: 5,
+        }
+    }
+}
+
+struct State<C: ConnectionErrors> {
+    /// The current connector, if any
+    current: Option<C>,
+    /// Incremented every time the current connector is replaced
+    generation: u64,
+    /// Function to create a fresh connector
+    make: MakeConnector<C>,
+}
+
+struct Inner<C: ConnectionErrors> {
+    state: Mutex<State<C>>,
+    backoff: BackoffPolicy,
+}
+
+/// A connector that lazily (re)creates its inner connector.
+///
+/// When [`Connector::open`] on the inner connector fails, the inner connector is
+/// discarded and a new one is created using the provided function, retrying
+/// according to the [`BackoffPolicy`].
+///
+/// Channels that were already opened are not affected. Only new calls to
+/// [`Connector::open`] trigger reconnection.
+pub struct ReconnectingConnector<C: ConnectionErrors> {
+    inner: Arc<Inner<C>>,
+}
+
+impl<C: ConnectionErrors> ReconnectingConnector<C> {
+    /// Create a new reconnecting connector.
+    ///
+    /// `make` is called to create the inner connector on the first open, and again
+    /// whenever opening a channel on the current inner connector fails.
+    pub fn new<F, Fut>(backoff: BackoffPolicy, mut make: F) -> Self
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<C, C::OpenError>> + Send + 'static,
+    {
+        Self {
+            inner: Arc::new(Inner {
+                state: Mutex::new(State {
+                    current: None,
+                    generation: 0,
+                    make: Box::new(move || make().boxed()),
+                }),
+                backoff,
+            }),
+        }
+    }
+
+    /// Get the current inner connector, creating one if there is none.
+    ///
+    /// The lock is not held while the connector is created, so a slow connect does not
+    /// block other callers. If another caller installed a connector in the meantime,
+    /// that one is used and the new one is dropped.
+    async fn current(&self) -> Result<(C, u64), C::OpenError> {
+        let make = {
+            let mut state = self.state();
+            if let Some(current) = &state.current {
+                return Ok((current.clone(), state.generation));
+            }
+            (state.make)()
+        };
+        let created = make.await?;
+        let mut state = self.state();
+        if let Some(current) = &state.current {
+            return Ok((current.clone(), state.generation));
+        }
+        state.current = Some(created.clone());
+        state.generation += 1;
+        Ok((created, state.generation))
+    }
+
+    /// Discard the inner connector, unless it has already been replaced.
+    fn invalidate(&self, generation: u64) {
+        let mut state = self.state();
+        if state.generation == generation {
+            state.current = None;
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State<C>> {
+        self.inner
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<C: ConnectionErrors> Clone for ReconnectingConnector<C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<C: ConnectionErrors> fmt::Debug for ReconnectingConnector<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReconnectingConnector")
+            .field("backoff", &self.inner.backoff)
+            .finish()
+    }
+}
+
+impl<C: ConnectionErrors> ConnectionErrors for ReconnectingConnector<C> {
+    type SendError = C::SendError;
+    type RecvError = C::RecvError;
+    type OpenError = C::OpenError;
+    type AcceptError = C::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        C::is_remote_closed(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        C::is_clean_close(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        C::is_unknown_message(error)
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        C::is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        C::is_cancelled(error)
+    }
+}
+
+impl<C: StreamTypes> StreamTypes for ReconnectingConnector<C> {
+    type In = C::In;
+    type Out = C::Out;
+    type RecvStream = C::RecvStream;
+    type SendSink = C::SendSink;
+}
+
+impl<C: Connector> Connector for ReconnectingConnector<C> {
+    async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let backoff = &self.inner.backoff;
+        let mut delay = backoff.initial_delay;
+        let mut attempt = 1;
+        loop {
+            let cause = match self.current().await {
+                Ok((current, generation)) => match current.open().await {
+                    Ok(channel) => return Ok(channel),
+                    Err(cause) => {
+                        self.invalidate(generation);
+                        cause
+                    }
+                },
+                Err(cause) => cause,
+            };
+            if attempt >= backoff.max_attempts {
+                return Err(cause);
+            }
+            tracing::debug!(%cause, attempt, "open failed, reconnecting");
+            if !delay.is_zero() {
+                glib::timeout_future(delay).await;
+            }
+            delay = delay
+                .checked_mul(backoff.multiplier)
+                .unwrap_or(backoff.max_delay)
+                .min(backoff.max_delay);
+            attempt += 1;
+        }
+    }
+
+    /// Statistics of the current inner connector.
+    ///
+    /// Returns `None` while there is no inner connector.
+    fn stats(&self) -> Option<ConnectionStats> {
+        self.state().current.as_ref()?.stats()
+    }
+
+    /// Ping the current inner connector, creating it if there is none.
+    async fn ping(&self) -> Result<Duration, PingError> {
+        let (current, _) = self.current().await.map_err(|_| PingError::Closed)?;
+        current.ping().await
+    }
+}
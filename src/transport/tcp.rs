@@ -0,0 +1,974 @@
+//! TCP transport, with optional TLS
+//!
+//! For networks where QUIC is not an option, e.g. because UDP is blocked. Messages use
+//! the same length prefixed framing as the [quinn](super::quinn) transport, serialized
+//! with a [Codec].
+//!
+//! # Connections
+//!
+//! A [TcpConnector] keeps a single TCP connection to the server and multiplexes all
+//! channels over it using [yamux]. Each call to [TcpConnector::open] opens a new
+//! substream, just like a quinn bidi stream, so concurrent channels do not need a
+//! connection each and a slow channel never blocks the others. The connection is made
+//! on the first call to [TcpConnector::open], and made again on the next call after it
+//! was lost.
+//!
+//! A [TcpListener] accepts any number of connections and yields the substreams of all of
+//! them from [TcpListener::accept].
+//!
+//! TLS is off by default and can be turned on with [TcpConnectorBuilder::tls] and
+//! [TcpListenerBuilder::tls]. Both sides have to agree.
+//!
+//! # Authentication
+//!
+//! Clients can be authenticated with a handshake that runs once per connection, after
+//! the TLS handshake, see [TcpConnectorBuilder::auth] and [TcpListenerBuilder::auth].
+//! The channels of a connection whose token was rejected are never yielded by
+//! [TcpListener::accept]. The identity of an accepted client is available from
+//! [Listener::remote_info], together with its address.
+//!
+//! # Closing
+//!
+//! Dropping or closing the send side of a channel half-closes the substream, which ends
+//! the receive side on the remote with `None`. This allows client streaming and bidi
+//! streaming interactions to finish the updates while still waiting for the response.
+//!
+//! Dropping both sides of a channel before the remote finished sending resets the
+//! substream. Sending on a reset substream fails with an error for which
+//! [ConnectionErrors::is_remote_closed] is true. Unlike with quinn, receiving on a reset
+//! substream just ends with `None` once the data that was sent before is received, since
+//! yamux does not tell a reset apart from the end of the stream.
+//!
+//! Dropping the connector closes its connection. Dropping the listener stops accepting
+//! and closes all connections accepted by it.
+use std::{
+    collections::VecDeque,
+    fmt,
+    future::Future,
+    io,
+    marker::PhantomData,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, PoisonError,
+    },
+    task::{Context, Poll},
+    time::SystemTime,
+};
+
+use futures_lite::{Stream, StreamExt};
+use futures_sink::Sink;
+use pin_project::pin_project;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt},
+    net::{self, TcpStream},
+    sync::oneshot,
+    task::{JoinHandle, JoinSet},
+};
+use tokio_rustls::{
+    rustls::{pki_types::ServerName, ClientConfig, ServerConfig},
+    TlsAcceptor, TlsConnector,
+};
+use tokio_util::compat::{Compat, FuturesAsyncReadCompatExt, TokioAsyncReadCompatExt};
+use tracing::{debug, trace, warn};
+
+use super::{
+    auth::{client_handshake, server_handshake, ClientAuth, ServerAuth},
+    codec::{BincodeCodec, Codec},
+    lifecycle::{ConnectionObserver, LifecycleEvent, LifecycleObserver},
+    protocol::MAX_FRAME_LENGTH,
+    util::{FramedCodecRead, FramedCodecWrite},
+    ConnectionErrors, Connector, Listener, LocalAddr, RawStreamTypes, RemoteInfo, StreamTypes,
+};
+use crate::RpcMessage;
+
+pub use super::util::{RawRecvStream, RawSendSink};
+
+/// The reading half of a substream
+///
+/// Dropping this together with the [WriteHalf] resets the substream, see [WriteHalf].
+pub struct ReadHalf {
+    inner: tokio::io::ReadHalf<Compat<yamux::Stream>>,
+    /// Set when this is dropped, shared with the [WriteHalf]
+    dropped: Arc<AtomicBool>,
+}
+
+impl fmt::Debug for ReadHalf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReadHalf").finish()
+    }
+}
+
+impl AsyncRead for ReadHalf {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl Drop for ReadHalf {
+    fn drop(&mut self) {
+        self.dropped.store(true, Ordering::Release);
+    }
+}
+
+/// A request to open a new substream on a connection
+type OpenRequest = oneshot::Sender<io::Result<yamux::Stream>>;
+
+/// An inbound substream, with the info about its connection
+type Inbound = (yamux::Stream, Arc<RemoteInfo>);
+
+#[derive(Debug)]
+struct ListenerInner {
+    task: JoinHandle<()>,
+    local_addr: [LocalAddr; 1],
+    receiver: flume::Receiver<Inbound>,
+    /// Reports connections, see [Listener::observe_connections]
+    lifecycle: Arc<ConnectionObserver>,
+}
+
+impl Drop for ListenerInner {
+    fn drop(&mut self) {
+        tracing::debug!("Dropping listener");
+        // this also aborts the tasks of all accepted connections
+        self.task.abort();
+    }
+}
+
+/// A builder for a [TcpListener]
+#[derive(Debug, Clone, Default)]
+pub struct TcpListenerBuilder {
+    tls: Option<Arc<ServerConfig>>,
+    auth: Option<ServerAuth>,
+}
+
+impl TcpListenerBuilder {
+    /// Accept only TLS connections, using the given server config.
+    pub fn tls(mut self, config: Arc<ServerConfig>) -> Self {
+        self.tls = Some(config);
+        self
+    }
+
+    /// Accept plain TCP connections. This is the default.
+    pub fn no_tls(mut self) -> Self {
+        self.tls = None;
+        self
+    }
+
+    /// Authenticate every new connection with a handshake, see [super::auth].
+    ///
+    /// Connections whose handshake fails are closed. The clients need to be configured
+    /// with [TcpConnectorBuilder::auth].
+    pub fn auth(mut self, auth: ServerAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Bind to the given address and start accepting connections.
+    pub async fn bind<In: RpcMessage, Out: RpcMessage>(
+        self,
+        addr: SocketAddr,
+    ) -> io::Result<TcpListener<In, Out>> {
+        let listener = net::TcpListener::bind(addr).await?;
+        self.build(listener)
+    }
+
+    /// Start accepting connections, given a bound tokio listener.
+    pub fn build<In: RpcMessage, Out: RpcMessage>(
+        self,
+        listener: net::TcpListener,
+    ) -> io::Result<TcpListener<In, Out>> {
+        let local_addr = listener.local_addr()?;
+        let acceptor = self.tls.map(TlsAcceptor::from);
+        let (sender, receiver) = flume::unbounded();
+        let lifecycle = Arc::new(ConnectionObserver::default());
+        let task = tokio::spawn(accept_loop(
+            listener,
+            acceptor,
+            self.auth,
+            sender,
+            lifecycle.clone(),
+        ));
+        Ok(TcpListener {
+            inner: Arc::new(ListenerInner {
+                task,
+                local_addr: [LocalAddr::Socket(local_addr)],
+                receiver,
+                lifecycle,
+            }),
+            codec: BincodeCodec,
+            max_frame_size: MAX_FRAME_LENGTH,
+            _p: PhantomData,
+        })
+    }
+}
+
+/// A listener that accepts TCP connections and yields their substreams as channels
+///
+/// Messages are serialized using the codec `C`, which defaults to [BincodeCodec].
+#[derive(Debug)]
+pub struct TcpListener<In: RpcMessage, Out: RpcMessage, C: Codec = BincodeCodec> {
+    inner: Arc<ListenerInner>,
+    codec: C,
+    max_frame_size: usize,
+    _p: PhantomData<(In, Out)>,
+}
+
+impl<In: RpcMessage, Out: RpcMessage> TcpListener<In, Out> {
+    /// Create a builder, to configure TLS.
+    pub fn builder() -> TcpListenerBuilder {
+        TcpListenerBuilder::default()
+    }
+
+    /// Create a new listener without TLS, bound to the given address.
+    pub async fn bind(addr: SocketAddr) -> io::Result<Self> {
+        Self::builder().bind(addr).await
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> TcpListener<In, Out, C> {
+    /// Use a different [Codec] to serialize messages.
+    ///
+    /// The clients need to use the same codec, see [TcpConnector::with_codec].
+    pub fn with_codec<C2: Codec>(self, codec: C2) -> TcpListener<In, Out, C2> {
+        TcpListener {
+            inner: self.inner,
+            codec,
+            max_frame_size: self.max_frame_size,
+            _p: PhantomData,
+        }
+    }
+
+    /// Set the maximum size of a single serialized message, in both directions.
+    ///
+    /// Defaults to 16 MiB.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Clone for TcpListener<In, Out, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            codec: self.codec.clone(),
+            max_frame_size: self.max_frame_size,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> ConnectionErrors for TcpListener<In, Out, C> {
+    type SendError = io::Error;
+    type RecvError = io::Error;
+    type OpenError = io::Error;
+    type AcceptError = io::Error;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        is_remote_closed(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        super::codec::unknown_message(error).is_some()
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> StreamTypes for TcpListener<In, Out, C> {
+    type In = In;
+    type Out = Out;
+    type SendSink = self::SendSink<Out, C>;
+    type RecvStream = self::RecvStream<In, C>;
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> RawStreamTypes for TcpListener<In, Out, C> {
+    type RawRecvStream = self::RawRecvStream<ReadHalf>;
+    type RawSendSink = self::RawSendSink<WriteHalf>;
+
+    fn into_raw(
+        send: Self::SendSink,
+        recv: Self::RecvStream,
+    ) -> (Self::RawSendSink, Self::RawRecvStream) {
+        (send.0.into_raw(), recv.0.into_raw())
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Listener for TcpListener<In, Out, C> {
+    async fn accept(&self) -> Result<(Self::SendSink, Self::RecvStream), io::Error> {
+        let (stream, info) = self
+            .inner
+            .receiver
+            .recv_async()
+            .await
+            .map_err(|_| io::Error::other("listener task stopped"))?;
+        trace!("Accepted substream");
+        let (send, mut recv) = channel(stream, self.codec.clone(), self.max_frame_size);
+        recv.1 = Some(info);
+        Ok((send, recv))
+    }
+
+    fn local_addr(&self) -> &[LocalAddr] {
+        &self.inner.local_addr
+    }
+
+    fn remote_info(recv: &Self::RecvStream) -> Option<Arc<RemoteInfo>> {
+        recv.1.clone()
+    }
+
+    /// Connections are numbered in the order in which they were accepted, and reported
+    /// once they are authenticated. The observer applies to all clones of the listener,
+    /// including connections that are already open.
+    fn observe_connections(&self, observer: LifecycleObserver) {
+        self.inner.lifecycle.set(observer);
+    }
+}
+
+/// Accept connections and run a [Driver] for each of them
+async fn accept_loop(
+    listener: net::TcpListener,
+    acceptor: Option<TlsAcceptor>,
+    auth: Option<ServerAuth>,
+    sender: flume::Sender<Inbound>,
+    lifecycle: Arc<ConnectionObserver>,
+) {
+    // owning the connection tasks here means they are aborted together with this task
+    let mut connections = JoinSet::new();
+    for id in 0u64.. {
+        while connections.try_join_next().is_some() {}
+        let (stream, addr) = match listener.accept().await {
+            Ok(res) => res,
+            Err(cause) => {
+                warn!("error accepting connection: {cause}");
+                continue;
+            }
+        };
+        debug!(%addr, "accepted connection");
+        let acceptor = acceptor.clone();
+        let auth = auth.clone();
+        let sender = sender.clone();
+        let lifecycle = lifecycle.clone();
+        connections.spawn(async move {
+            stream.set_nodelay(true).ok();
+            match acceptor {
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => serve_connection(stream, addr, auth, sender, id, lifecycle).await,
+                    Err(cause) => warn!(%addr, "tls handshake failed: {cause}"),
+                },
+                None => serve_connection(stream, addr, auth, sender, id, lifecycle).await,
+            }
+        });
+    }
+}
+
+/// Authenticate an accepted connection if configured, and run its [Driver]
+async fn serve_connection<T: AsyncRead + AsyncWrite + Unpin>(
+    mut stream: T,
+    addr: SocketAddr,
+    auth: Option<ServerAuth>,
+    sender: flume::Sender<Inbound>,
+    id: u64,
+    lifecycle: Arc<ConnectionObserver>,
+) {
+    let identity = match &auth {
+        Some(auth) => match server_handshake(&mut stream, auth).await {
+            Ok(identity) => Some(identity),
+            Err(cause) => {
+                warn!(%addr, "auth handshake failed: {cause}");
+                stream.shutdown().await.ok();
+                return;
+            }
+        },
+        None => None,
+    };
+    let info = Arc::new(RemoteInfo {
+        addr: Some(addr),
+        certificates: Vec::new(),
+        identity,
+    });
+    lifecycle.emit(|| LifecycleEvent::ConnectionOpened {
+        time: SystemTime::now(),
+        connection: id,
+        remote: addr,
+    });
+    let error = match Driver::new(stream, yamux::Mode::Server, None, Some((sender, info))).await {
+        Ok(()) => {
+            debug!(%addr, "connection closed");
+            None
+        }
+        Err(cause) => {
+            debug!(%addr, "connection lost: {cause}");
+            Some(cause.to_string())
+        }
+    };
+    lifecycle.emit(|| LifecycleEvent::ConnectionClosed {
+        time: SystemTime::now(),
+        connection: id,
+        remote: addr,
+        error,
+    });
+}
+
+/// A builder for a [TcpConnector]
+#[derive(Debug, Clone)]
+pub struct TcpConnectorBuilder {
+    addr: SocketAddr,
+    tls: Option<(Arc<ClientConfig>, ServerName<'static>)>,
+    auth: Option<ClientAuth>,
+}
+
+impl TcpConnectorBuilder {
+    /// Connect using TLS, with the given client config.
+    ///
+    /// `server_name` is the name the server certificate is checked against.
+    pub fn tls(mut self, config: Arc<ClientConfig>, server_name: ServerName<'static>) -> Self {
+        self.tls = Some((config, server_name));
+        self
+    }
+
+    /// Connect using plain TCP. This is the default.
+    pub fn no_tls(mut self) -> Self {
+        self.tls = None;
+        self
+    }
+
+    /// Authenticate every new connection with a handshake, see [super::auth].
+    ///
+    /// If the server rejects the token, opening the channel that made the connection
+    /// fails with an error for which [auth_error](super::auth::auth_error) returns the
+    /// reason. The next channel makes a new connection, with a new token.
+    pub fn auth(mut self, auth: ClientAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Create the connector.
+    ///
+    /// This does not connect yet, the connection is made when the first channel is opened.
+    pub fn build<In: RpcMessage, Out: RpcMessage>(self) -> TcpConnector<In, Out> {
+        TcpConnector {
+            inner: Arc::new(ConnectorInner {
+                addr: self.addr,
+                tls: self
+                    .tls
+                    .map(|(config, server_name)| (TlsConnector::from(config), server_name)),
+                auth: self.auth,
+                state: Mutex::new(ConnectionState::Idle),
+                connected: event_listener::Event::new(),
+            }),
+            codec: BincodeCodec,
+            max_frame_size: MAX_FRAME_LENGTH,
+            _p: PhantomData,
+        }
+    }
+}
+
+/// The connection of a [TcpConnector]
+enum ConnectionState {
+    /// No connection, the next open makes one
+    Idle,
+    /// An open is making a connection, the others wait for it
+    Connecting,
+    /// Requests to the [Driver] of the current connection
+    ///
+    /// Dropping this closes the connection.
+    Connected(flume::Sender<OpenRequest>),
+}
+
+struct ConnectorInner {
+    addr: SocketAddr,
+    tls: Option<(TlsConnector, ServerName<'static>)>,
+    auth: Option<ClientAuth>,
+    /// The lock is never held across an await point
+    state: Mutex<ConnectionState>,
+    /// Notified when an open stops [ConnectionState::Connecting]
+    connected: event_listener::Event,
+}
+
+/// Resets [ConnectionState::Connecting] if making the connection fails or is cancelled
+struct ConnectingGuard<'a>(&'a ConnectorInner);
+
+impl Drop for ConnectingGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap_or_else(PoisonError::into_inner);
+        if matches!(*state, ConnectionState::Connecting) {
+            *state = ConnectionState::Idle;
+        }
+        drop(state);
+        self.0.connected.notify(usize::MAX);
+    }
+}
+
+impl ConnectorInner {
+    /// Get the current connection, or make a new one if there is none
+    ///
+    /// Only one open makes a connection at a time, concurrent opens wait for it.
+    async fn connection(&self) -> io::Result<flume::Sender<OpenRequest>> {
+        loop {
+            let connected = {
+                let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+                match &*state {
+                    ConnectionState::Connected(requests) if !requests.is_disconnected() => {
+                        return Ok(requests.clone());
+                    }
+                    // register before releasing the lock, so the notification is not missed
+                    ConnectionState::Connecting => self.connected.listen(),
+                    _ => {
+                        *state = ConnectionState::Connecting;
+                        break;
+                    }
+                }
+            };
+            connected.await;
+        }
+        let guard = ConnectingGuard(self);
+        let requests = self.connect().await?;
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner) =
+            ConnectionState::Connected(requests.clone());
+        drop(guard);
+        Ok(requests)
+    }
+
+    /// Make a new connection and spawn its [Driver]
+    async fn connect(&self) -> io::Result<flume::Sender<OpenRequest>> {
+        debug!(addr = %self.addr, "connecting");
+        let stream = TcpStream::connect(self.addr).await?;
+        stream.set_nodelay(true)?;
+        let (sender, receiver) = flume::unbounded();
+        let receiver = receiver.into_stream();
+        match &self.tls {
+            Some((connector, server_name)) => {
+                let stream = connector.connect(server_name.clone(), stream).await?;
+                self.start(stream, receiver).await?;
+            }
+            None => self.start(stream, receiver).await?,
+        }
+        Ok(sender)
+    }
+
+    /// Authenticate a new connection if configured, and spawn its [Driver]
+    async fn start<T: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+        &self,
+        mut stream: T,
+        requests: flume::r#async::RecvStream<'static, OpenRequest>,
+    ) -> io::Result<()> {
+        if let Some(auth) = &self.auth {
+            client_handshake(&mut stream, auth).await?;
+        }
+        let driver = Driver::new(stream, yamux::Mode::Client, Some(requests), None);
+        tokio::spawn(run_client(driver, self.addr));
+        Ok(())
+    }
+}
+
+async fn run_client<T: AsyncRead + AsyncWrite + Unpin>(driver: Driver<T>, addr: SocketAddr) {
+    match driver.await {
+        Ok(()) => debug!(%addr, "connection closed"),
+        Err(cause) => debug!(%addr, "connection lost: {cause}"),
+    }
+}
+
+/// A connector that multiplexes channels over a single TCP connection
+///
+/// Messages are serialized using the codec `C`, which defaults to [BincodeCodec].
+pub struct TcpConnector<In: RpcMessage, Out: RpcMessage, C: Codec = BincodeCodec> {
+    inner: Arc<ConnectorInner>,
+    codec: C,
+    max_frame_size: usize,
+    _p: PhantomData<(In, Out)>,
+}
+
+impl<In: RpcMessage, Out: RpcMessage> TcpConnector<In, Out> {
+    /// Create a builder for a connector to the given address, to configure TLS.
+    pub fn builder(addr: SocketAddr) -> TcpConnectorBuilder {
+        TcpConnectorBuilder {
+            addr,
+            tls: None,
+            auth: None,
+        }
+    }
+
+    /// Create a new connector without TLS for the given address.
+    ///
+    /// This does not connect yet, the connection is made when the first channel is opened.
+    pub fn new(addr: SocketAddr) -> Self {
+        Self::builder(addr).build()
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> TcpConnector<In, Out, C> {
+    /// Use a different [Codec] to serialize messages.
+    ///
+    /// The server needs to use the same codec, see [TcpListener::with_codec].
+    pub fn with_codec<C2: Codec>(self, codec: C2) -> TcpConnector<In, Out, C2> {
+        TcpConnector {
+            inner: self.inner,
+            codec,
+            max_frame_size: self.max_frame_size,
+            _p: PhantomData,
+        }
+    }
+
+    /// Set the maximum size of a single serialized message, in both directions.
+    ///
+    /// See [TcpListener::with_max_frame_size].
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> fmt::Debug for TcpConnector<In, Out, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TcpConnector")
+            .field("addr", &self.inner.addr)
+            .field("tls", &self.inner.tls.is_some())
+            .field("auth", &self.inner.auth.is_some())
+            .field("codec", &self.codec)
+            .field("max_frame_size", &self.max_frame_size)
+            .finish()
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Clone for TcpConnector<In, Out, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            codec: self.codec.clone(),
+            max_frame_size: self.max_frame_size,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> ConnectionErrors for TcpConnector<In, Out, C> {
+    type SendError = io::Error;
+    type RecvError = io::Error;
+    type OpenError = io::Error;
+    type AcceptError = io::Error;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        is_remote_closed(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        super::codec::unknown_message(error).is_some()
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> StreamTypes for TcpConnector<In, Out, C> {
+    type In = In;
+    type Out = Out;
+    type SendSink = self::SendSink<Out, C>;
+    type RecvStream = self::RecvStream<In, C>;
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> RawStreamTypes for TcpConnector<In, Out, C> {
+    type RawRecvStream = self::RawRecvStream<ReadHalf>;
+    type RawSendSink = self::RawSendSink<WriteHalf>;
+
+    fn into_raw(
+        send: Self::SendSink,
+        recv: Self::RecvStream,
+    ) -> (Self::RawSendSink, Self::RawRecvStream) {
+        (send.0.into_raw(), recv.0.into_raw())
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Connector for TcpConnector<In, Out, C> {
+    async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let requests = self.inner.connection().await?;
+        let (sender, receiver) = oneshot::channel();
+        requests
+            .send_async(sender)
+            .await
+            .map_err(|_| connection_closed())?;
+        let stream = receiver.await.map_err(|_| connection_closed())??;
+        Ok(channel(stream, self.codec.clone(), self.max_frame_size))
+    }
+}
+
+/// Drives a yamux connection
+///
+/// A yamux connection only makes progress while it is polled, so every connection has a
+/// task that runs this. On the client side it opens substreams on request, on the server
+/// side it hands out the inbound substreams. Dropping the other end of either channel
+/// closes the connection.
+struct Driver<T> {
+    connection: yamux::Connection<Compat<T>>,
+    requests: Option<flume::r#async::RecvStream<'static, OpenRequest>>,
+    pending: VecDeque<OpenRequest>,
+    /// Where to send inbound substreams, and the info about the connection to send with them
+    inbound: Option<(flume::Sender<Inbound>, Arc<RemoteInfo>)>,
+    closing: bool,
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> Driver<T> {
+    fn new(
+        io: T,
+        mode: yamux::Mode,
+        requests: Option<flume::r#async::RecvStream<'static, OpenRequest>>,
+        inbound: Option<(flume::Sender<Inbound>, Arc<RemoteInfo>)>,
+    ) -> Self {
+        Self {
+            connection: yamux::Connection::new(io.compat(), yamux::Config::default(), mode),
+            requests,
+            pending: VecDeque::new(),
+            inbound,
+            closing: false,
+        }
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> Future for Driver<T> {
+    type Output = Result<(), yamux::ConnectionError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = &mut *self;
+        if let Some(requests) = this.requests.as_mut().filter(|_| !this.closing) {
+            loop {
+                match requests.poll_next(cx) {
+                    Poll::Ready(Some(request)) => this.pending.push_back(request),
+                    Poll::Ready(None) => {
+                        trace!("connector dropped");
+                        this.closing = true;
+                        break;
+                    }
+                    Poll::Pending => break,
+                }
+            }
+        }
+        if this.closing {
+            return this.connection.poll_close(cx);
+        }
+        while !this.pending.is_empty() {
+            let Poll::Ready(res) = this.connection.poll_new_outbound(cx) else {
+                break;
+            };
+            let request = this.pending.pop_front().expect("not empty");
+            if request.send(res.map_err(io::Error::other)).is_err() {
+                trace!("requester dropped");
+            }
+        }
+        loop {
+            match this.connection.poll_next_inbound(cx) {
+                Poll::Ready(Some(Ok(stream))) => match &this.inbound {
+                    Some((inbound, info)) => {
+                        if inbound.send((stream, info.clone())).is_err() {
+                            trace!("listener dropped");
+                            this.closing = true;
+                            return this.connection.poll_close(cx);
+                        }
+                    }
+                    // dropping the substream resets it
+                    None => trace!("rejecting inbound substream"),
+                },
+                Poll::Ready(Some(Err(cause))) => return Poll::Ready(Err(cause)),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+fn connection_closed() -> io::Error {
+    io::Error::new(io::ErrorKind::NotConnected, "connection closed")
+}
+
+/// Split a substream into the two halves of a channel
+fn channel<In, Out, C>(
+    stream: yamux::Stream,
+    codec: C,
+    max_frame_size: usize,
+) -> (SendSink<Out, C>, RecvStream<In, C>)
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: Codec,
+{
+    let (read, write) = tokio::io::split(stream.compat());
+    let dropped = Arc::new(AtomicBool::new(false));
+    let write = WriteHalf {
+        inner: Some(write),
+        read_dropped: dropped.clone(),
+    };
+    let read = ReadHalf {
+        inner: read,
+        dropped,
+    };
+    (
+        SendSink(FramedCodecWrite::new(write, codec.clone(), max_frame_size)),
+        RecvStream(FramedCodecRead::new(read, codec, max_frame_size), None),
+    )
+}
+
+/// Whether a write error is because the remote closed or reset the substream
+fn is_remote_closed(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::WriteZero | io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// The writing half of a substream
+///
+/// Dropping this without shutting it down half-closes the substream in the background.
+/// If the [ReadHalf] is dropped as well, e.g. because the whole channel is dropped, the
+/// substream is reset instead, unless the remote already finished sending. Writing to a
+/// reset substream fails on the remote, like writing to a stopped quinn stream.
+pub struct WriteHalf {
+    inner: Option<tokio::io::WriteHalf<Compat<yamux::Stream>>>,
+    /// Set when the [ReadHalf] of the same substream is dropped
+    read_dropped: Arc<AtomicBool>,
+}
+
+impl fmt::Debug for WriteHalf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WriteHalf").finish()
+    }
+}
+
+impl WriteHalf {
+    fn get_pin_mut(&mut self) -> io::Result<Pin<&mut tokio::io::WriteHalf<Compat<yamux::Stream>>>> {
+        match self.inner.as_mut() {
+            Some(inner) => Ok(Pin::new(inner)),
+            None => Err(io::Error::new(
+                io::ErrorKind::BrokenPipe,
+                "substream closed",
+            )),
+        }
+    }
+}
+
+impl AsyncWrite for WriteHalf {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_pin_mut()?.poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_pin_mut()?.poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.inner.is_none() {
+            return Poll::Ready(Ok(()));
+        }
+        let res = futures_lite::ready!(self.get_pin_mut()?.poll_shutdown(cx));
+        self.inner = None;
+        Poll::Ready(res)
+    }
+}
+
+impl Drop for WriteHalf {
+    fn drop(&mut self) {
+        let Some(mut inner) = self.inner.take() else {
+            return;
+        };
+        // yamux resets a substream that is dropped without closing it, and finishes it
+        // if the remote already did
+        if self.read_dropped.load(Ordering::Acquire) {
+            return;
+        }
+        let read_dropped = self.read_dropped.clone();
+        let close = async move {
+            // a channel drops its read half right after this one, so give it a chance
+            futures_lite::future::yield_now().await;
+            if read_dropped.load(Ordering::Acquire) {
+                return;
+            }
+            let res = std::future::poll_fn(|cx| Pin::new(&mut inner).poll_shutdown(cx)).await;
+            if let Err(cause) = res {
+                trace!("error closing substream: {cause}");
+            }
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(close);
+            }
+            // the connection is driven by its own task, which drains the queue
+            Err(_) => {
+                std::thread::spawn(move || futures::executor::block_on(close));
+            }
+        }
+    }
+}
+
+/// A sink that wraps the writing half of a substream with length prefixing and a
+/// [Codec]
+///
+/// Dropping this half-closes the substream, so the remote sees the end of the stream.
+#[pin_project]
+pub struct SendSink<Out, C = BincodeCodec>(#[pin] FramedCodecWrite<WriteHalf, Out, C>);
+
+impl<Out, C> fmt::Debug for SendSink<Out, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendSink").finish()
+    }
+}
+
+impl<Out, C> SendSink<Out, C> {
+    /// Get the writing half of the underlying substream, which implements
+    /// [tokio::io::AsyncWrite] and can be used to send bytes directly.
+    pub fn into_inner(self) -> WriteHalf {
+        self.0.into_inner()
+    }
+}
+
+impl<Out: Serialize, C: Codec> Sink<Out> for SendSink<Out, C> {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().0.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Out) -> Result<(), Self::Error> {
+        self.project().0.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().0.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().0.poll_close(cx)
+    }
+}
+
+/// A stream that wraps the reading half of a substream with length prefixing and a
+/// [Codec]
+#[pin_project]
+pub struct RecvStream<In, C = BincodeCodec>(
+    #[pin] FramedCodecRead<ReadHalf, In, C>,
+    /// The info about the connection, for substreams accepted by a [TcpListener]
+    Option<Arc<RemoteInfo>>,
+);
+
+impl<In, C> fmt::Debug for RecvStream<In, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecvStream").finish()
+    }
+}
+
+impl<In, C> RecvStream<In, C> {
+    /// Get the reading half of the underlying substream, which implements
+    /// [tokio::io::AsyncRead] and can be used to receive bytes directly.
+    pub fn into_inner(self) -> ReadHalf {
+        self.0.into_inner()
+    }
+}
+
+impl<In: DeserializeOwned, C: Codec> Stream for RecvStream<In, C> {
+    type Item = Result<In, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().0.poll_next(cx)
+    }
+}
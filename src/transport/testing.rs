@@ -0,0 +1,81 @@
+//! Helpers to test the wire format of messages
+//!
+//! The [quinn](super::quinn), [iroh-net](super::iroh_net), [tcp](super::tcp) and
+//! [uds](super::uds) transports send every message as a frame with a big endian u32
+//! length prefix, followed by the message encoded with a [Codec]. The functions in this
+//! module run a message through exactly the same framing, without a connection, so the
+//! bytes can be compared against a known good encoding to catch accidental breaking
+//! changes to the wire format:
+//!
+//! ```
+//! use quic_rpc::transport::testing::{decode_frame, encode_frame};
+//!
+//! // the length prefix, followed by the fixed size bincode encoding
+//! let bytes = encode_frame(&(7u8, 258u16));
+//! assert_eq!(bytes, [0, 0, 0, 3, 7, 2, 1]);
+//! assert_eq!(decode_frame::<(u8, u16)>(&bytes).unwrap(), (7, 258));
+//! ```
+//!
+//! What is on the wire is the request or response type of the service, not the message
+//! type itself, so that is what should be encoded.
+use std::io;
+
+use futures_lite::StreamExt;
+use futures_util::SinkExt;
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::{
+    codec::{BincodeCodec, Codec},
+    protocol::MAX_FRAME_LENGTH,
+    util::{FramedCodecRead, FramedCodecWrite},
+};
+
+/// Encode a message as a single frame, with the default [BincodeCodec].
+///
+/// # Panics
+///
+/// If the message can not be encoded or is larger than the default maximum frame size.
+pub fn encode_frame<M: Serialize>(msg: &M) -> Vec<u8> {
+    encode_frame_with_codec(&BincodeCodec, msg)
+}
+
+/// Encode a message as a single frame, with the given codec.
+///
+/// # Panics
+///
+/// If the message can not be encoded or is larger than the default maximum frame size.
+pub fn encode_frame_with_codec<M: Serialize, C: Codec>(codec: &C, msg: &M) -> Vec<u8> {
+    let mut write = FramedCodecWrite::new(Vec::new(), codec.clone(), MAX_FRAME_LENGTH);
+    futures::executor::block_on(write.send(msg)).expect("unable to encode frame");
+    write.into_inner()
+}
+
+/// Decode a message from a single frame, with the default [BincodeCodec].
+///
+/// Fails if the data is not exactly one frame, or the frame can not be decoded.
+pub fn decode_frame<M: DeserializeOwned>(data: &[u8]) -> io::Result<M> {
+    decode_frame_with_codec(&BincodeCodec, data)
+}
+
+/// Decode a message from a single frame, with the given codec.
+///
+/// Fails if the data is not exactly one frame, or the frame can not be decoded.
+pub fn decode_frame_with_codec<M: DeserializeOwned, C: Codec>(
+    codec: &C,
+    data: &[u8],
+) -> io::Result<M> {
+    let mut read = FramedCodecRead::<_, M, _>::new(data, codec.clone(), MAX_FRAME_LENGTH);
+    futures::executor::block_on(async {
+        let msg = read
+            .next()
+            .await
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))??;
+        if read.next().await.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "data after the end of the frame",
+            ));
+        }
+        Ok(msg)
+    })
+}
@@ -0,0 +1,401 @@
+//! Transport that tells the server in which span the client made a call.
+//!
+//! [TracedConnector] sends a [TraceContext] describing the current span of the client as
+//! an extra frame ahead of the first message of each channel. [TracedListener] strips
+//! this frame, so the rpc layer never sees it, and records the context on the server
+//! side span of the interaction, so the logs of both sides of a call can be correlated.
+//!
+//! This is not distributed tracing: the id of the span is only unique within the client
+//! process, and the server span does not become a child of the client span. The context
+//! is also not forwarded to the channels the server opens while handling the call.
+//!
+//! Both sides have to use the traced wrappers, since the inner transport carries
+//! [Frame]s instead of the plain messages.
+use std::{
+    fmt::{self, Debug, Display},
+    marker::PhantomData,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use futures_lite::{Future, Stream};
+use futures_sink::Sink;
+use futures_util::SinkExt;
+use pin_project::pin_project;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ConnectionErrors, ConnectionStats, Connector, Listener, LocalAddr, PingError, RemoteInfo,
+    StreamTypes,
+};
+use crate::{RpcError, RpcMessage};
+
+/// Serialized context of the span in which a client opened a channel
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TraceContext {
+    /// Name of the span
+    pub name: Option<String>,
+    /// Id of the span, only unique within the client process
+    pub id: Option<u64>,
+}
+
+impl TraceContext {
+    /// Capture the context of the current span
+    pub fn current() -> Self {
+        let span = tracing::Span::current();
+        Self {
+            name: span.metadata().map(|m| m.name().to_owned()),
+            id: span.id().map(|id| id.into_u64()),
+        }
+    }
+}
+
+impl Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.name, self.id) {
+            (Some(name), Some(id)) => write!(f, "{name}#{id}"),
+            (Some(name), None) => write!(f, "{name}"),
+            (None, Some(id)) => write!(f, "#{id}"),
+            (None, None) => write!(f, "none"),
+        }
+    }
+}
+
+/// A message on the inner transport of a traced connection
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Frame<T> {
+    /// Tracing context, sent before the first message
+    Context(TraceContext),
+    /// A message
+    Msg(T),
+}
+
+/// A connector that sends the tracing context of the client when opening a channel
+#[derive(Debug)]
+pub struct TracedConnector<In, Out, C> {
+    inner: C,
+    _p: PhantomData<(In, Out)>,
+}
+
+impl<In, Out, C> TracedConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: Connector<In = Frame<In>, Out = Frame<Out>>,
+{
+    /// Create a new traced connector
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In, Out, C: Clone> Clone for TracedConnector<In, Out, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In, Out, C> ConnectionErrors for TracedConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: ConnectionErrors,
+{
+    type SendError = C::SendError;
+    type RecvError = C::RecvError;
+    type OpenError = OpenError<C::OpenError, C::SendError>;
+    type AcceptError = C::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        C::is_remote_closed(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        C::is_clean_close(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        C::is_unknown_message(error)
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        C::is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        C::is_cancelled(error)
+    }
+}
+
+impl<In, Out, C> StreamTypes for TracedConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: StreamTypes<In = Frame<In>, Out = Frame<Out>>,
+{
+    type In = In;
+    type Out = Out;
+    type SendSink = SendSink<C::SendSink>;
+    type RecvStream = RecvStream<C::RecvStream>;
+}
+
+impl<In, Out, C> Connector for TracedConnector<In, Out, C>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: Connector<In = Frame<In>, Out = Frame<Out>>,
+{
+    async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let context = TraceContext::current();
+        let (mut send, recv) = self.inner.open().await.map_err(OpenError::Open)?;
+        send.send(Frame::Context(context))
+            .await
+            .map_err(OpenError::Send)?;
+        Ok((SendSink(send), RecvStream::new(recv)))
+    }
+
+    fn stats(&self) -> Option<ConnectionStats> {
+        self.inner.stats()
+    }
+
+    fn ping(&self) -> impl Future<Output = Result<Duration, PingError>> + Send {
+        self.inner.ping()
+    }
+}
+
+/// A listener that strips the tracing context sent by a [TracedConnector]
+#[derive(Debug)]
+pub struct TracedListener<In, Out, L> {
+    inner: L,
+    _p: PhantomData<(In, Out)>,
+}
+
+impl<In, Out, L> TracedListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: Listener<In = Frame<In>, Out = Frame<Out>>,
+{
+    /// Create a new traced listener
+    pub fn new(inner: L) -> Self {
+        Self {
+            inner,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In, Out, L: Clone> Clone for TracedListener<In, Out, L> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In, Out, L> ConnectionErrors for TracedListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: ConnectionErrors,
+{
+    type SendError = L::SendError;
+    type RecvError = L::RecvError;
+    type OpenError = L::OpenError;
+    type AcceptError = L::AcceptError;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        L::is_remote_closed(error)
+    }
+
+    fn is_clean_close(error: &Self::RecvError) -> bool {
+        L::is_clean_close(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        L::is_unknown_message(error)
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        L::is_reset(error)
+    }
+
+    fn is_cancelled(error: &Self::RecvError) -> bool {
+        L::is_cancelled(error)
+    }
+}
+
+impl<In, Out, L> StreamTypes for TracedListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: StreamTypes<In = Frame<In>, Out = Frame<Out>>,
+{
+    type In = In;
+    type Out = Out;
+    type SendSink = SendSink<L::SendSink>;
+    type RecvStream = RecvStream<L::RecvStream>;
+}
+
+impl<In, Out, L> Listener for TracedListener<In, Out, L>
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    L: Listener<In = Frame<In>, Out = Frame<Out>>,
+{
+    async fn accept(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::AcceptError> {
+        let (send, recv) = self.inner.accept().await?;
+        Ok((SendSink(send), RecvStream::new(recv)))
+    }
+
+    fn local_addr(&self) -> &[LocalAddr] {
+        self.inner.local_addr()
+    }
+
+    fn remote_info(recv: &Self::RecvStream) -> Option<Arc<RemoteInfo>> {
+        L::remote_info(&recv.inner)
+    }
+}
+
+/// Error when opening a traced channel
+#[derive(Debug)]
+pub enum OpenError<O, S> {
+    /// Opening the inner channel failed
+    Open(O),
+    /// Sending the tracing context failed
+    Send(S),
+}
+
+impl<O: Debug, S: Debug> Display for OpenError<O, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(self, f)
+    }
+}
+
+impl<O: RpcError, S: RpcError> std::error::Error for OpenError<O, S> {}
+
+/// Send sink of a traced channel, wrapping each message in a [Frame::Msg]
+#[derive(Debug)]
+#[pin_project]
+pub struct SendSink<S>(#[pin] S);
+
+impl<S, T> Sink<T> for SendSink<S>
+where
+    S: Sink<Frame<T>>,
+{
+    type Error = S::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().0.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        self.project().0.start_send(Frame::Msg(item))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().0.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().0.poll_close(cx)
+    }
+}
+
+/// Receive stream of a traced channel, stripping [Frame::Context]
+///
+/// The context is recorded as the `remote` field of the current span whenever the
+/// stream is polled, which is the span of the interaction when handling it on the
+/// server with the `tracing-spans` feature enabled.
+#[derive(Debug)]
+#[pin_project]
+pub struct RecvStream<S> {
+    #[pin]
+    inner: S,
+    context: Option<TraceContext>,
+}
+
+impl<S> RecvStream<S> {
+    fn new(inner: S) -> Self {
+        Self {
+            inner,
+            context: None,
+        }
+    }
+
+    /// The tracing context sent by the remote, if it has been received yet
+    pub fn context(&self) -> Option<&TraceContext> {
+        self.context.as_ref()
+    }
+}
+
+impl<S, T, E> Stream for RecvStream<S>
+where
+    S: Stream<Item = Result<Frame<T>, E>>,
+{
+    type Item = Result<T, E>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(context) = this.context.as_ref() {
+                tracing::Span::current().record("remote", tracing::field::display(context));
+            }
+            match this.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(Frame::Context(context)))) => {
+                    *this.context = Some(context);
+                }
+                Poll::Ready(Some(Ok(Frame::Msg(msg)))) => return Poll::Ready(Some(Ok(msg))),
+                Poll::Ready(Some(Err(cause))) => return Poll::Ready(Some(Err(cause))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "flume-transport")]
+mod tests {
+    use futures_lite::StreamExt;
+    use testresult::TestResult;
+    use tracing::Instrument;
+
+    use super::*;
+    use crate::transport::flume;
+
+    #[tokio::test]
+    async fn context_is_stripped() -> TestResult<()> {
+        let (listener, connector) = flume::channel::<Frame<u64>, Frame<u64>>(1);
+        let listener = TracedListener::<u64, u64, _>::new(listener);
+        let connector = TracedConnector::<u64, u64, _>::new(connector);
+        let client = async move {
+            let (mut send, _recv) = connector.open().await?;
+            send.send(42).await?;
+            TestResult::Ok(())
+        }
+        .instrument(tracing::info_span!("client"));
+        let server = async move {
+            let (_send, mut recv) = listener.accept().await?;
+            assert_eq!(recv.next().await.transpose()?, Some(42));
+            assert_eq!(
+                recv.context().and_then(|c| c.name.as_deref()),
+                Some("client")
+            );
+            TestResult::Ok(())
+        };
+        let (client, server) = tokio::join!(client, server);
+        client?;
+        server?;
+        Ok(())
+    }
+}
@@ -0,0 +1,483 @@
+//! Unix domain socket transport
+//!
+//! For communication between processes on the same host. Messages use the same length
+//! prefixed framing as the [quinn](super::quinn) transport, serialized with a [Codec].
+//!
+//! # Connections
+//!
+//! A unix socket is a single ordered byte stream and can not be multiplexed without an
+//! additional framing layer. Instead, every channel is a separate connection:
+//! [UdsConnector::open] connects to the socket, and [UdsListener::accept] yields one
+//! channel per accepted connection. Connecting to a unix socket does not involve a
+//! handshake, so this is cheap, and a slow channel never blocks the others.
+//!
+//! # Closing
+//!
+//! Dropping or closing the send side of a channel shuts down the writing half of the
+//! connection, which ends the receive side on the remote with `None`. This allows client
+//! streaming and bidi streaming interactions to finish the updates while still waiting
+//! for the response. The connection is closed once both sides are dropped.
+//!
+//! Closing a connection before all data sent by the remote was received resets it.
+//! Receiving on the remote then fails with an error for which
+//! [ConnectionErrors::is_reset] is true.
+//!
+//! # Authentication
+//!
+//! Clients can be authenticated with a handshake, see [UdsConnector::with_auth] and
+//! [UdsListener::with_auth]. Since every channel is a connection, the handshake runs for
+//! every channel. The listener runs the handshakes of several connections concurrently,
+//! so a slow client does not hold up the others, and yields only the channels whose
+//! token was accepted. The identity of the client is available from
+//! [Listener::remote_info].
+use std::{
+    fmt, io,
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use futures_lite::Stream;
+use futures_sink::Sink;
+use pin_project::pin_project;
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{
+        unix::{OwnedReadHalf, OwnedWriteHalf},
+        UnixListener, UnixStream,
+    },
+    sync::Mutex,
+    task::JoinSet,
+};
+use tracing::{trace, warn};
+
+use super::{
+    auth::{client_handshake, server_handshake, ClientAuth, Identity, ServerAuth},
+    codec::{BincodeCodec, Codec},
+    protocol::MAX_FRAME_LENGTH,
+    util::{FramedCodecRead, FramedCodecWrite},
+    ConnectionErrors, Connector, Listener, LocalAddr, RawStreamTypes, RemoteInfo, StreamTypes,
+};
+use crate::RpcMessage;
+
+pub use super::util::{RawRecvStream, RawSendSink};
+
+#[derive(Debug)]
+struct ListenerInner {
+    listener: UnixListener,
+    local_addr: [LocalAddr; 1],
+    /// Handshakes of accepted connections that are still running
+    handshakes: Mutex<JoinSet<io::Result<(UnixStream, Identity)>>>,
+}
+
+/// A listener that accepts connections on a unix domain socket
+///
+/// Each accepted connection is a single channel.
+#[derive(Debug)]
+pub struct UdsListener<In: RpcMessage, Out: RpcMessage, C: Codec = BincodeCodec> {
+    inner: Arc<ListenerInner>,
+    codec: C,
+    max_frame_size: usize,
+    auth: Option<ServerAuth>,
+    _p: PhantomData<(In, Out)>,
+}
+
+impl<In: RpcMessage, Out: RpcMessage> UdsListener<In, Out> {
+    /// Create a new listener bound to the given path.
+    ///
+    /// This fails if the path already exists. The socket file is not removed when the
+    /// listener is dropped.
+    pub fn bind(path: impl AsRef<Path>) -> io::Result<Self> {
+        let listener = UnixListener::bind(path)?;
+        Self::new(listener)
+    }
+
+    /// Create a new listener, given a bound unix listener.
+    pub fn new(listener: UnixListener) -> io::Result<Self> {
+        let local_addr = match listener.local_addr()?.as_pathname() {
+            Some(path) => LocalAddr::Path(path.to_owned()),
+            None => LocalAddr::Mem,
+        };
+        Ok(Self {
+            inner: Arc::new(ListenerInner {
+                listener,
+                local_addr: [local_addr],
+                handshakes: Mutex::new(JoinSet::new()),
+            }),
+            codec: BincodeCodec,
+            max_frame_size: MAX_FRAME_LENGTH,
+            auth: None,
+            _p: PhantomData,
+        })
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> UdsListener<In, Out, C> {
+    /// Use a different [Codec] to serialize messages.
+    ///
+    /// The clients need to use the same codec, see [UdsConnector::with_codec].
+    pub fn with_codec<C2: Codec>(self, codec: C2) -> UdsListener<In, Out, C2> {
+        UdsListener {
+            inner: self.inner,
+            codec,
+            max_frame_size: self.max_frame_size,
+            auth: self.auth,
+            _p: PhantomData,
+        }
+    }
+
+    /// Set the maximum size of a single serialized message, in both directions.
+    ///
+    /// Defaults to 16 MiB.
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Authenticate every connection with a handshake, see [super::auth].
+    ///
+    /// Connections whose handshake fails are closed. The clients need to be configured
+    /// with [UdsConnector::with_auth].
+    pub fn with_auth(mut self, auth: ServerAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Accept connections and run their handshakes, until one of them succeeds
+    async fn accept_authenticated(&self, auth: &ServerAuth) -> io::Result<(UnixStream, Identity)> {
+        let mut handshakes = self.inner.handshakes.lock().await;
+        loop {
+            let accepted = async { Ok(self.inner.listener.accept().await) };
+            let done = async {
+                match handshakes.join_next().await {
+                    Some(res) => Err(res),
+                    None => std::future::pending().await,
+                }
+            };
+            match futures_lite::future::or(accepted, done).await {
+                Ok(accepted) => {
+                    let (mut stream, _addr) = accepted?;
+                    let auth = auth.clone();
+                    handshakes.spawn(async move {
+                        match server_handshake(&mut stream, &auth).await {
+                            Ok(identity) => Ok((stream, identity)),
+                            Err(cause) => {
+                                stream.shutdown().await.ok();
+                                Err(cause)
+                            }
+                        }
+                    });
+                }
+                Err(Ok(Ok(authenticated))) => return Ok(authenticated),
+                Err(Ok(Err(cause))) => warn!("auth handshake failed: {cause}"),
+                Err(Err(cause)) => warn!("auth handshake task failed: {cause}"),
+            }
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Clone for UdsListener<In, Out, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            codec: self.codec.clone(),
+            max_frame_size: self.max_frame_size,
+            auth: self.auth.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> ConnectionErrors for UdsListener<In, Out, C> {
+    type SendError = io::Error;
+    type RecvError = io::Error;
+    type OpenError = io::Error;
+    type AcceptError = io::Error;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        is_remote_closed(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        super::codec::unknown_message(error).is_some()
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        error.kind() == io::ErrorKind::ConnectionReset
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> StreamTypes for UdsListener<In, Out, C> {
+    type In = In;
+    type Out = Out;
+    type SendSink = self::SendSink<Out, C>;
+    type RecvStream = self::RecvStream<In, C>;
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> RawStreamTypes for UdsListener<In, Out, C> {
+    type RawRecvStream = self::RawRecvStream<OwnedReadHalf>;
+    type RawSendSink = self::RawSendSink<OwnedWriteHalf>;
+
+    fn into_raw(
+        send: Self::SendSink,
+        recv: Self::RecvStream,
+    ) -> (Self::RawSendSink, Self::RawRecvStream) {
+        (send.0.into_raw(), recv.0.into_raw())
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Listener for UdsListener<In, Out, C> {
+    async fn accept(&self) -> Result<(Self::SendSink, Self::RecvStream), io::Error> {
+        let (stream, info) = match &self.auth {
+            Some(auth) => {
+                let (stream, identity) = self.accept_authenticated(auth).await?;
+                let info = RemoteInfo {
+                    identity: Some(identity),
+                    ..Default::default()
+                };
+                (stream, Some(Arc::new(info)))
+            }
+            None => (self.inner.listener.accept().await?.0, None),
+        };
+        trace!("Accepted unix socket connection");
+        let (send, mut recv) = channel(stream, self.codec.clone(), self.max_frame_size);
+        recv.1 = info;
+        Ok((send, recv))
+    }
+
+    fn local_addr(&self) -> &[LocalAddr] {
+        &self.inner.local_addr
+    }
+
+    fn remote_info(recv: &Self::RecvStream) -> Option<Arc<RemoteInfo>> {
+        recv.1.clone()
+    }
+}
+
+/// A connector that opens a new unix domain socket connection for each channel
+pub struct UdsConnector<In: RpcMessage, Out: RpcMessage, C: Codec = BincodeCodec> {
+    path: Arc<Path>,
+    codec: C,
+    max_frame_size: usize,
+    auth: Option<ClientAuth>,
+    _p: PhantomData<(In, Out)>,
+}
+
+impl<In: RpcMessage, Out: RpcMessage> UdsConnector<In, Out> {
+    /// Create a new connector for the socket at the given path.
+    ///
+    /// This does not connect yet, a connection is made for every channel.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into().into(),
+            codec: BincodeCodec,
+            max_frame_size: MAX_FRAME_LENGTH,
+            auth: None,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> UdsConnector<In, Out, C> {
+    /// Use a different [Codec] to serialize messages.
+    ///
+    /// The server needs to use the same codec, see [UdsListener::with_codec].
+    pub fn with_codec<C2: Codec>(self, codec: C2) -> UdsConnector<In, Out, C2> {
+        UdsConnector {
+            path: self.path,
+            codec,
+            max_frame_size: self.max_frame_size,
+            auth: self.auth,
+            _p: PhantomData,
+        }
+    }
+
+    /// Set the maximum size of a single serialized message, in both directions.
+    ///
+    /// See [UdsListener::with_max_frame_size].
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Authenticate every connection with a handshake, see [super::auth].
+    ///
+    /// If the server rejects the token, opening the channel fails with an error for
+    /// which [auth_error](super::auth::auth_error) returns the reason.
+    pub fn with_auth(mut self, auth: ClientAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> fmt::Debug for UdsConnector<In, Out, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UdsConnector")
+            .field("path", &self.path)
+            .field("codec", &self.codec)
+            .field("max_frame_size", &self.max_frame_size)
+            .field("auth", &self.auth.is_some())
+            .finish()
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Clone for UdsConnector<In, Out, C> {
+    fn clone(&self) -> Self {
+        Self {
+            path: self.path.clone(),
+            codec: self.codec.clone(),
+            max_frame_size: self.max_frame_size,
+            auth: self.auth.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> ConnectionErrors for UdsConnector<In, Out, C> {
+    type SendError = io::Error;
+    type RecvError = io::Error;
+    type OpenError = io::Error;
+    type AcceptError = io::Error;
+
+    fn is_remote_closed(error: &Self::SendError) -> bool {
+        is_remote_closed(error)
+    }
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        super::codec::unknown_message(error).is_some()
+    }
+
+    fn is_reset(error: &Self::RecvError) -> bool {
+        error.kind() == io::ErrorKind::ConnectionReset
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> StreamTypes for UdsConnector<In, Out, C> {
+    type In = In;
+    type Out = Out;
+    type SendSink = self::SendSink<Out, C>;
+    type RecvStream = self::RecvStream<In, C>;
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> RawStreamTypes for UdsConnector<In, Out, C> {
+    type RawRecvStream = self::RawRecvStream<OwnedReadHalf>;
+    type RawSendSink = self::RawSendSink<OwnedWriteHalf>;
+
+    fn into_raw(
+        send: Self::SendSink,
+        recv: Self::RecvStream,
+    ) -> (Self::RawSendSink, Self::RawRecvStream) {
+        (send.0.into_raw(), recv.0.into_raw())
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Connector for UdsConnector<In, Out, C> {
+    async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let mut stream = UnixStream::connect(&*self.path).await?;
+        if let Some(auth) = &self.auth {
+            client_handshake(&mut stream, auth).await?;
+        }
+        Ok(channel(stream, self.codec.clone(), self.max_frame_size))
+    }
+}
+
+/// Split a connection into the two halves of a channel
+fn channel<In, Out, C>(
+    stream: UnixStream,
+    codec: C,
+    max_frame_size: usize,
+) -> (SendSink<Out, C>, RecvStream<In, C>)
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: Codec,
+{
+    let (read, write) = stream.into_split();
+    (
+        SendSink(FramedCodecWrite::new(write, codec.clone(), max_frame_size)),
+        RecvStream(FramedCodecRead::new(read, codec, max_frame_size), None),
+    )
+}
+
+/// Whether a write error is because the remote closed the connection
+fn is_remote_closed(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+    )
+}
+
+/// A sink that wraps the writing half of a unix socket with length prefixing and a
+/// [Codec]
+///
+/// Dropping this shuts down the writing half, so the remote sees the end of the stream.
+#[pin_project]
+pub struct SendSink<Out, C = BincodeCodec>(#[pin] FramedCodecWrite<OwnedWriteHalf, Out, C>);
+
+impl<Out, C> fmt::Debug for SendSink<Out, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendSink").finish()
+    }
+}
+
+impl<Out, C> SendSink<Out, C> {
+    /// Get the writing half of the underlying socket, which implements
+    /// [tokio::io::AsyncWrite] and can be used to send bytes directly.
+    pub fn into_inner(self) -> OwnedWriteHalf {
+        self.0.into_inner()
+    }
+}
+
+impl<Out: Serialize, C: Codec> Sink<Out> for SendSink<Out, C> {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().0.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Out) -> Result<(), Self::Error> {
+        self.project().0.start_send(item)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().0.poll_flush(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.project().0.poll_close(cx)
+    }
+}
+
+/// A stream that wraps the reading half of a unix socket with length prefixing and a
+/// [Codec]
+#[pin_project]
+pub struct RecvStream<In, C = BincodeCodec>(
+    #[pin] FramedCodecRead<OwnedReadHalf, In, C>,
+    /// The info about the client, for connections accepted by a [UdsListener] with auth
+    Option<Arc<RemoteInfo>>,
+);
+
+impl<In, C> fmt::Debug for RecvStream<In, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecvStream").finish()
+    }
+}
+
+impl<In, C> RecvStream<In, C> {
+    /// Get the reading half of the underlying socket, which implements
+    /// [tokio::io::AsyncRead] and can be used to receive bytes directly.
+    pub fn into_inner(self) -> OwnedReadHalf {
+        self.0.into_inner()
+    }
+}
+
+impl<In: DeserializeOwned, C: Codec> Stream for RecvStream<In, C> {
+    type Item = Result<In, io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().0.poll_next(cx)
+    }
+}
@@ -1,134 +1,872 @@
 use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, IoSlice},
+    marker::PhantomData,
     pin::Pin,
-    task::{self, Poll},
+    task::{self, ready, Poll},
 };
 
-use bincode::Options;
-use futures_lite::{AsyncRead, AsyncWrite, Stream};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures_lite::Stream;
 use futures_sink::Sink;
 use pin_project::pin_project;
 use serde::{de::DeserializeOwned, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::io::poll_read_buf;
 
-type BincodeEncoding =
-    bincode::config::WithOtherIntEncoding<bincode::DefaultOptions, bincode::config::FixintEncoding>;
-/// Wrapper that wraps a bidirectional binary stream in a length delimited codec and bincode with fast fixint encoding
-/// to get a bidirectional stream of rpc Messages
+use super::{
+    backtrace::ErrorTrace,
+    codec::Codec,
+    protocol::{FrameTooLarge, LengthPrefixed},
+};
+
+/// Smallest amount of data [FrameReader] tries to read at once, and what it shrinks back to
+/// when idle
+const MIN_READ_SIZE: usize = 8 * 1024;
+
+/// Largest amount of data an adaptive [FrameReader] makes room for before the size of
+/// the next frame is known
+#[cfg(any(test, feature = "quinn-transport"))]
+const MAX_READ_SIZE: usize = 64 * 1024;
+
+/// Reads frames with a big endian u32 length prefix from a binary stream
+///
+/// Once the length prefix of a frame is buffered, this makes room for the whole frame
+/// before the next read. Before that, it reads up to [MIN_READ_SIZE] bytes at once.
+///
+/// An [adaptive](FrameReader::adaptive) reader instead makes room for as much data as
+/// the recent frames had, up to [MAX_READ_SIZE]. So a large frame, or several small ones,
+/// usually take a single read, instead of a small one for the length prefix. The read
+/// size halves every time the stream is idle between frames, so an idle stream does
+/// not keep a large buffer.
+#[pin_project]
+struct FrameReader<T> {
+    #[pin]
+    inner: T,
+    framing: LengthPrefixed,
+    buffer: BytesMut,
+    /// How much data to make room for before a read, when the next frame size is unknown
+    read_size: usize,
+    /// What `read_size` grows to at most
+    max_read_size: usize,
+    /// The inner stream ended
+    eof: bool,
+}
+
+impl<T> FrameReader<T> {
+    fn new(inner: T, limit: usize) -> Self {
+        Self {
+            inner,
+            framing: LengthPrefixed { limit },
+            buffer: BytesMut::new(),
+            read_size: MIN_READ_SIZE,
+            max_read_size: MIN_READ_SIZE,
+            eof: false,
+        }
+    }
+
+    /// A reader that grows the read size with the frames it sees
+    #[cfg(any(test, feature = "quinn-transport"))]
+    fn adaptive(inner: T, limit: usize) -> Self {
+        Self {
+            max_read_size: MAX_READ_SIZE,
+            ..Self::new(inner, limit)
+        }
+    }
+
+    fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut T> {
+        self.project().inner
+    }
+}
+
+impl<T: AsyncRead> Stream for FrameReader<T> {
+    type Item = io::Result<BytesMut>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match this.framing.decode(this.buffer) {
+                Ok(Some(frame)) => {
+                    let size = (frame.len() + 4)
+                        .next_power_of_two()
+                        .min(*this.max_read_size);
+                    *this.read_size = (*this.read_size).max(size);
+                    return Poll::Ready(Some(Ok(frame)));
+                }
+                Ok(None) => {}
+                Err(cause) => {
+                    // the framing is lost, so this is the end of the stream
+                    *this.eof = true;
+                    this.buffer.clear();
+                    return Poll::Ready(Some(Err(cause)));
+                }
+            }
+            if *this.eof {
+                if this.buffer.is_empty() {
+                    return Poll::Ready(None);
+                }
+                this.buffer.clear();
+                return Poll::Ready(Some(Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "bytes remaining on stream",
+                ))));
+            }
+            if this.buffer.len() < 4 {
+                // the size of the next frame is unknown, so expect one like the recent ones.
+                // Otherwise decode already made room for the rest of the frame.
+                this.buffer.reserve(*this.read_size - this.buffer.len());
+            }
+            match poll_read_buf(this.inner.as_mut(), cx, this.buffer) {
+                Poll::Ready(Ok(0)) => *this.eof = true,
+                Poll::Ready(Ok(_)) => {}
+                Poll::Ready(Err(cause)) => return Poll::Ready(Some(Err(cause))),
+                Poll::Pending => {
+                    if this.buffer.is_empty() {
+                        // idle between frames
+                        *this.read_size = (*this.read_size / 2).max(MIN_READ_SIZE);
+                        if this.buffer.capacity() > 2 * *this.read_size {
+                            *this.buffer = BytesMut::new();
+                        }
+                    }
+                    return Poll::Pending;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "quinn-transport")]
+impl<T: AsyncRead> FrameReader<T> {
+    /// Wait until a complete frame is buffered, without taking it.
+    ///
+    /// Returns `false` if the stream ended first. A frame that is too large counts as
+    /// complete, so the next [Stream::poll_next] reports it.
+    fn poll_peek(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<bool>> {
+        let mut this = self.project();
+        loop {
+            if this.buffer.len() >= 4 {
+                let size = u32::from_be_bytes([
+                    this.buffer[0],
+                    this.buffer[1],
+                    this.buffer[2],
+                    this.buffer[3],
+                ]) as usize;
+                if size > this.framing.limit || this.buffer.len() >= 4 + size {
+                    return Poll::Ready(Ok(true));
+                }
+            }
+            if *this.eof {
+                return Poll::Ready(Ok(false));
+            }
+            this.buffer.reserve(*this.read_size);
+            if ready!(poll_read_buf(this.inner.as_mut(), cx, this.buffer))? == 0 {
+                *this.eof = true;
+            }
+        }
+    }
+}
+
+/// Frames up to this size are copied into a buffer together with their length prefix,
+/// larger ones are written directly from the buffer the [Codec] produced
+const COPY_THRESHOLD: usize = 4096;
+
+/// Once this many bytes are buffered, [FrameWriter] writes them before accepting more
+const BACKPRESSURE_BOUNDARY: usize = 128 * 1024;
+
+/// Maximum number of chunks written with a single vectored write
+const MAX_IO_SLICES: usize = 64;
+
+/// Writes frames with a big endian u32 length prefix to a binary sink
+///
+/// Unlike a [FramedWrite](tokio_util::codec::FramedWrite), this does not copy large frames
+/// into an intermediate buffer. The length prefix and the frame are written using
+/// vectored writes, so the only copy of a large message is the one produced by the [Codec].
 #[pin_project]
-pub struct FramedBincodeRead<T, In>(
+struct FrameWriter<T> {
     #[pin]
-    serde_transport::SymmetricallyFramed<
-        tokio_util::codec::FramedRead<T, tokio_util::codec::LengthDelimitedCodec>,
-        In,
-        tokio_serde::formats::SymmetricalBincode<In, BincodeEncoding>,
-    >,
-);
-
-impl<T: AsyncRead, In: DeserializeOwned> FramedBincodeRead<T, In> {
-    /// Wrap a socket in a length delimited codec and bincode with fast fixint encoding
-    pub fn new(inner: T, max_frame_length: usize) -> Self {
-        // configure length delimited codec with max frame length
-        let framing = LengthDelimitedCodec::builder()
-            .max_frame_length(max_frame_length)
-            .new_codec();
-        // create the actual framing. This turns the AsyncRead/AsyncWrite into a Stream/Sink of Bytes/BytesMut
-        let framed = tokio_util::codec::FramedRead::new(inner, framing);
-        // configure bincode with fixint encoding
-        let bincode_options: BincodeEncoding =
-            bincode::DefaultOptions::new().with_fixint_encoding();
-        let bincode = tokio_serde::formats::Bincode::from(bincode_options);
-        // create the actual framing. This turns the Stream/Sink of Bytes/BytesMut into a Stream/Sink of In/Out
-        let framed = tokio_serde::Framed::new(framed, bincode);
-        Self(framed)
-    }
-}
-
-impl<T, In> FramedBincodeRead<T, In> {
+    inner: T,
+    limit: usize,
+    /// Length prefixes and small frames that were not moved to `chunks` yet
+    buffer: BytesMut,
+    /// Data that still has to be written, in order, followed by `buffer`
+    chunks: VecDeque<Bytes>,
+    /// Number of bytes in `chunks` and `buffer`
+    buffered: usize,
+}
+
+impl<T> FrameWriter<T> {
+    fn new(inner: T, limit: usize) -> Self {
+        Self {
+            inner,
+            limit,
+            buffer: BytesMut::new(),
+            chunks: VecDeque::new(),
+            buffered: 0,
+        }
+    }
+
+    fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut T> {
+        self.project().inner
+    }
+}
+
+impl<T: AsyncWrite> FrameWriter<T> {
+    /// Write buffered data until at most `target` bytes are left
+    fn poll_write_buffered(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+        target: usize,
+    ) -> Poll<io::Result<()>> {
+        let mut this = self.project();
+        if *this.buffered <= target {
+            return Poll::Ready(Ok(()));
+        }
+        if !this.buffer.is_empty() {
+            this.chunks.push_back(this.buffer.split().freeze());
+        }
+        while *this.buffered > target {
+            let mut slices = [IoSlice::new(&[]); MAX_IO_SLICES];
+            let count = slices
+                .iter_mut()
+                .zip(this.chunks.iter())
+                .map(|(slice, chunk)| *slice = IoSlice::new(chunk))
+                .count();
+            let mut written = ready!(this
+                .inner
+                .as_mut()
+                .poll_write_vectored(cx, &slices[..count]))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            *this.buffered -= written;
+            while written > 0 {
+                let chunk = this.chunks.front_mut().expect("written data was buffered");
+                if written < chunk.len() {
+                    chunk.advance(written);
+                    break;
+                }
+                written -= chunk.len();
+                this.chunks.pop_front();
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite> Sink<Bytes> for FrameWriter<T> {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        self.poll_write_buffered(cx, BACKPRESSURE_BOUNDARY)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> io::Result<()> {
+        let this = self.project();
+        let size = item.len();
+        if size > *this.limit {
+            return Err(FrameTooLarge {
+                size,
+                limit: *this.limit,
+                backtrace: ErrorTrace::capture(),
+            }
+            .into());
+        }
+        this.buffer.put_u32(size as u32);
+        if size <= COPY_THRESHOLD {
+            this.buffer.extend_from_slice(&item);
+        } else {
+            this.chunks.push_back(this.buffer.split().freeze());
+            this.chunks.push_back(item);
+        }
+        *this.buffered += 4 + size;
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_write_buffered(cx, 0))?;
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<io::Result<()>> {
+        ready!(self.as_mut().poll_write_buffered(cx, 0))?;
+        self.project().inner.poll_shutdown(cx)
+    }
+}
+
+/// Wrapper that wraps a binary stream in a length prefixed framing and a [Codec]
+/// to get a stream of rpc Messages
+#[pin_project]
+pub struct FramedCodecRead<T, In, C> {
+    #[pin]
+    inner: FrameReader<T>,
+    codec: C,
+    _p: PhantomData<In>,
+}
+
+impl<T: AsyncRead, In: DeserializeOwned, C: Codec> FramedCodecRead<T, In, C> {
+    /// Wrap a socket in a length prefixed framing and the given [Codec]
+    ///
+    /// Frames larger than `max_frame_length` are rejected with [FrameTooLarge].
+    pub fn new(inner: T, codec: C, max_frame_length: usize) -> Self {
+        // create the actual framing. This turns the AsyncRead into a Stream of BytesMut
+        let inner = FrameReader::new(inner, max_frame_length.min(u32::MAX as usize));
+        Self {
+            inner,
+            codec,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<T, In, C> FramedCodecRead<T, In, C> {
     /// Get the underlying binary stream
     ///
     /// This can be useful if you want to drop the framing and use the underlying stream directly
     /// after exchanging some messages.
     pub fn into_inner(self) -> T {
-        self.0.into_inner().into_inner()
+        self.inner.into_inner()
+    }
+
+    /// Get the underlying binary stream, e.g. to reset it after an error
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut T> {
+        self.project().inner.get_pin_mut()
+    }
+
+    /// Drop the [Codec] but keep the framing, including frames that are already buffered
+    pub fn into_raw(self) -> RawRecvStream<T> {
+        RawRecvStream(self.inner)
     }
 }
 
-impl<T: AsyncRead, In: DeserializeOwned> Stream for FramedBincodeRead<T, In> {
+#[cfg(feature = "quinn-transport")]
+impl<T: AsyncRead, In: DeserializeOwned, C: Codec> FramedCodecRead<T, In, C> {
+    /// Poll for the next frame, without decoding it
+    pub(crate) fn poll_next_frame(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<io::Result<Bytes>>> {
+        self.project()
+            .inner
+            .poll_next(cx)
+            .map(|frame| frame.map(|frame| frame.map(BytesMut::freeze)))
+    }
+
+    /// Decode a frame returned by [FramedCodecRead::poll_next_frame]
+    pub(crate) fn decode(&self, frame: Bytes) -> io::Result<In> {
+        self.codec.decode(frame)
+    }
+}
+
+impl<T: AsyncRead, In: DeserializeOwned, C: Codec> Stream for FramedCodecRead<T, In, C> {
     type Item = Result<In, std::io::Error>;
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
-        Pin::new(&mut self.project().0).poll_next(cx)
+        let this = self.project();
+        match this.inner.poll_next(cx) {
+            Poll::Ready(Some(Ok(frame))) => Poll::Ready(Some(this.codec.decode(frame.freeze()))),
+            Poll::Ready(Some(Err(cause))) => Poll::Ready(Some(Err(cause))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
     }
 }
 
-/// Wrapper that wraps a bidirectional binary stream in a length delimited codec and bincode with fast fixint encoding
-/// to get a bidirectional stream of rpc Messages
+/// Wrapper that wraps a binary sink in a length prefixed framing and a [Codec]
+/// to get a sink of rpc Messages
 #[pin_project]
-pub struct FramedBincodeWrite<T, Out>(
+pub struct FramedCodecWrite<T, Out, C> {
     #[pin]
-    tokio_serde::SymmetricallyFramed<
-        tokio_util::codec::FramedWrite<T, tokio_util::codec::LengthDelimitedCodec>,
-        Out,
-        tokio_serde::formats::SymmetricalBincode<Out, BincodeEncoding>,
-    >,
-);
-
-impl<T: AsyncWrite, Out: Serialize> FramedBincodeWrite<T, Out> {
-    /// Wrap a socket in a length delimited codec and bincode with fast fixint encoding
-    pub fn new(inner: T, max_frame_length: usize) -> Self {
-        // configure length delimited codec with max frame length
-        let framing = LengthDelimitedCodec::builder()
-            .max_frame_length(max_frame_length)
-            .new_codec();
-        // create the actual framing. This turns the AsyncRead/AsyncWrite into a Stream/Sink of Bytes/BytesMut
-        let framed = tokio_util::codec::FramedWrite::new(inner, framing);
-        // configure bincode with fixint encoding
-        let bincode_options: BincodeEncoding =
-            bincode::DefaultOptions::new().with_fixint_encoding();
-        let bincode = tokio_serde::formats::SymmetricalBincode::from(bincode_options);
-        // create the actual framing. This turns the Stream/Sink of Bytes/BytesMut into a Stream/Sink of In/Out
-        let framed = tokio_serde::SymmetricallyFramed::new(framed, bincode);
-        Self(framed)
-    }
-}
-
-impl<T, Out> FramedBincodeWrite<T, Out> {
+    inner: FrameWriter<T>,
+    codec: C,
+    _p: PhantomData<Out>,
+}
+
+impl<T: AsyncWrite, Out: Serialize, C: Codec> FramedCodecWrite<T, Out, C> {
+    /// Wrap a socket in a length prefixed framing and the given [Codec]
+    ///
+    /// Frames larger than `max_frame_length` are rejected with [FrameTooLarge].
+    pub fn new(inner: T, codec: C, max_frame_length: usize) -> Self {
+        // create the actual framing. This turns the AsyncWrite into a Sink of Bytes
+        let inner = FrameWriter::new(inner, max_frame_length.min(u32::MAX as usize));
+        Self {
+            inner,
+            codec,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<T, Out, C> FramedCodecWrite<T, Out, C> {
     /// Get the underlying binary stream
     ///
     /// This can be useful if you want to drop the framing and use the underlying stream directly
     /// after exchanging some messages.
     pub fn into_inner(self) -> T {
-        self.0.into_inner().into_inner()
+        self.inner.into_inner()
+    }
+
+    /// Get a reference to the underlying binary sink
+    pub fn get_ref(&self) -> &T {
+        self.inner.get_ref()
+    }
+
+    /// Get the underlying binary sink, e.g. to reset it
+    pub fn get_pin_mut(self: Pin<&mut Self>) -> Pin<&mut T> {
+        self.project().inner.get_pin_mut()
+    }
+
+    /// Drop the [Codec] but keep the framing, including frames that are not flushed yet
+    pub fn into_raw(self) -> RawSendSink<T> {
+        RawSendSink(self.inner)
+    }
+}
+
+#[cfg(feature = "quinn-transport")]
+impl<T: AsyncWrite, Out, C> FramedCodecWrite<T, Out, C> {
+    /// Queue a frame as it is, without the [Codec]
+    pub(crate) fn start_send_frame(self: Pin<&mut Self>, frame: Bytes) -> io::Result<()> {
+        self.project().inner.start_send(frame)
     }
 }
 
-impl<T: AsyncWrite, Out: Serialize> Sink<Out> for FramedBincodeWrite<T, Out> {
+impl<T: AsyncWrite, Out: Serialize, C: Codec> Sink<Out> for FramedCodecWrite<T, Out, C> {
     type Error = std::io::Error;
 
     fn poll_ready(
         self: Pin<&mut Self>,
         cx: &mut task::Context<'_>,
     ) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_ready(cx)
+        self.project().inner.poll_ready(cx)
     }
 
     fn start_send(self: Pin<&mut Self>, item: Out) -> Result<(), Self::Error> {
-        Pin::new(&mut self.project().0).start_send(item)
+        let this = self.project();
+        let frame = this.codec.encode(&item)?;
+        this.inner.start_send(frame)
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.project().inner.poll_close(cx)
+    }
+}
+
+/// A stream of raw frames, using the same length prefixed framing as [FramedCodecRead]
+///
+/// Each item is the serialized form of one message, as the remote [Codec] produced it.
+#[pin_project]
+pub struct RawRecvStream<T>(#[pin] FrameReader<T>);
+
+impl<T> fmt::Debug for RawRecvStream<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawRecvStream").finish()
+    }
+}
+
+impl<T> RawRecvStream<T> {
+    /// Get the underlying binary stream
+    pub fn into_inner(self) -> T {
+        self.0.into_inner()
+    }
+
+    /// Read frames of up to `max_frame_length` bytes from `inner`, with an
+    /// [adaptive](FrameReader::adaptive) read size
+    #[cfg(feature = "quinn-transport")]
+    pub(crate) fn new(inner: T, max_frame_length: usize) -> Self {
+        Self(FrameReader::adaptive(
+            inner,
+            max_frame_length.min(u32::MAX as usize),
+        ))
+    }
+
+    /// Decode the frames using `codec`, keeping frames that are already buffered
+    #[cfg(feature = "quinn-transport")]
+    pub(crate) fn with_codec<In, C>(self, codec: C) -> FramedCodecRead<T, In, C> {
+        FramedCodecRead {
+            inner: self.0,
+            codec,
+            _p: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "quinn-transport")]
+impl<T: AsyncRead> RawRecvStream<T> {
+    /// Wait until a complete frame is buffered, without taking it.
+    ///
+    /// Returns `false` if the stream ended first.
+    pub(crate) fn poll_peek(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<io::Result<bool>> {
+        self.project().0.poll_peek(cx)
+    }
+}
+
+impl<T: AsyncRead> Stream for RawRecvStream<T> {
+    type Item = Result<Bytes, std::io::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut task::Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.project().0.poll_next(cx) {
+            Poll::Ready(Some(Ok(frame))) => Poll::Ready(Some(Ok(frame.freeze()))),
+            Poll::Ready(Some(Err(cause))) => Poll::Ready(Some(Err(cause))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// A sink of raw frames, using the same length prefixed framing as [FramedCodecWrite]
+///
+/// Each item must be the serialized form of one message, as the remote [Codec] expects it.
+#[pin_project]
+pub struct RawSendSink<T>(#[pin] FrameWriter<T>);
+
+impl<T> fmt::Debug for RawSendSink<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RawSendSink").finish()
+    }
+}
+
+impl<T> RawSendSink<T> {
+    /// Get the underlying binary stream
+    pub fn into_inner(self) -> T {
+        self.0.into_inner()
+    }
+
+    /// Write frames of up to `max_frame_length` bytes to `inner`
+    #[cfg(feature = "quinn-transport")]
+    pub(crate) fn new(inner: T, max_frame_length: usize) -> Self {
+        Self(FrameWriter::new(
+            inner,
+            max_frame_length.min(u32::MAX as usize),
+        ))
+    }
+
+    /// Get a reference to the underlying binary sink
+    #[cfg(feature = "quinn-transport")]
+    pub(crate) fn get_ref(&self) -> &T {
+        self.0.get_ref()
+    }
+
+    /// Get a mutable reference to the underlying binary sink
+    #[cfg(feature = "quinn-transport")]
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        self.0.get_mut()
+    }
+
+    /// Encode the frames using `codec`, keeping frames that are not flushed yet
+    #[cfg(feature = "quinn-transport")]
+    pub(crate) fn with_codec<Out, C>(self, codec: C) -> FramedCodecWrite<T, Out, C> {
+        FramedCodecWrite {
+            inner: self.0,
+            codec,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<T: AsyncWrite> Sink<Bytes> for RawSendSink<T> {
+    type Error = std::io::Error;
+
+    fn poll_ready(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.project().0.poll_ready(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
+        self.project().0.start_send(item)
     }
 
     fn poll_flush(
         self: Pin<&mut Self>,
         cx: &mut task::Context<'_>,
     ) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_flush(cx)
+        self.project().0.poll_flush(cx)
     }
 
     fn poll_close(
         self: Pin<&mut Self>,
         cx: &mut task::Context<'_>,
     ) -> Poll<Result<(), Self::Error>> {
-        Pin::new(&mut self.project().0).poll_close(cx)
+        self.project().0.poll_close(cx)
+    }
+}
+
+/// Whether a read error is because the remote closed the connection cleanly
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+pub(crate) fn is_clean_close(error: &io::Error) -> bool {
+    error
+        .get_ref()
+        .and_then(|cause| cause.downcast_ref::<quinn::ReadError>())
+        .is_some_and(|cause| {
+            matches!(
+                cause,
+                quinn::ReadError::ConnectionLost(quinn::ConnectionError::ApplicationClosed(_))
+            )
+        })
+}
+
+/// Whether a read error is because the remote reset the stream
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+pub(crate) fn is_reset(error: &io::Error) -> bool {
+    error
+        .get_ref()
+        .and_then(|cause| cause.downcast_ref::<quinn::ReadError>())
+        .is_some_and(|cause| matches!(cause, quinn::ReadError::Reset(_)))
+}
+
+/// The error code with which a stream is reset by
+/// [StreamTypes::cancel](super::StreamTypes::cancel)
+///
+/// A stream that the remote reset with this code counts as cancelled, see
+/// [ConnectionErrors::is_cancelled](super::ConnectionErrors::is_cancelled).
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+pub const CANCEL_CODE: quinn::VarInt = quinn::VarInt::from_u32(0x7270_6378);
+
+/// Whether a read error is because the remote cancelled the stream
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+pub(crate) fn is_cancelled(error: &io::Error) -> bool {
+    error
+        .get_ref()
+        .and_then(|cause| cause.downcast_ref::<quinn::ReadError>())
+        .is_some_and(|cause| matches!(cause, quinn::ReadError::Reset(code) if *code == CANCEL_CODE))
+}
+
+/// Whether a write error is because the remote stopped the receiving side of the stream
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+pub(crate) fn is_stopped(error: &io::Error) -> bool {
+    error
+        .get_ref()
+        .and_then(|cause| cause.downcast_ref::<quinn::WriteError>())
+        .is_some_and(|cause| matches!(cause, quinn::WriteError::Stopped(_)))
+}
+
+/// The framed send side of a quinn stream, shared by its sink and the futures of
+/// [send_closed]
+///
+/// The futures only hold a weak reference, so they do not keep the stream open once the
+/// sink is dropped. The lock is only held while the sink or a future is polled.
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+pub(crate) struct SharedSend<T>(std::sync::Arc<std::sync::Mutex<Option<T>>>);
+
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+impl<T> SharedSend<T> {
+    pub(crate) fn new(inner: T) -> Self {
+        Self(std::sync::Arc::new(std::sync::Mutex::new(Some(inner))))
+    }
+
+    /// Run `f` with the inner value, which must not have been taken
+    pub(crate) fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        let mut inner = self
+            .0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        f(inner.as_mut().expect("only taken when consumed"))
+    }
+
+    /// Take the inner value, `None` if it was taken before
+    pub(crate) fn take(&self) -> Option<T> {
+        self.0
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .take()
     }
 }
 
-// fn assert_sink<T>(_: &impl Sink<T>) {}
-// fn assert_stream<T>(_: &impl Stream<Item = T>) {}
+/// Resolves once the remote stopped the stream of `send`, or the connection is lost
+///
+/// See [StreamTypes::send_closed](super::StreamTypes::send_closed).
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+pub(crate) fn send_closed<Out, C>(
+    send: &SharedSend<FramedCodecWrite<quinn::SendStream, Out, C>>,
+) -> impl std::future::Future<Output = ()> + Send + 'static
+where
+    Out: Send + Unpin + 'static,
+    C: Send + Unpin + 'static,
+{
+    let send = std::sync::Arc::downgrade(&send.0);
+    std::future::poll_fn(move |cx| {
+        // the sink is gone, so the handler is done anyway
+        let Some(shared) = send.upgrade() else {
+            return Poll::Pending;
+        };
+        let mut inner = shared
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        // taken for raw access, which is not watched
+        let Some(framed) = inner.as_mut() else {
+            return Poll::Pending;
+        };
+        let stream = Pin::new(framed).get_pin_mut().get_mut();
+        let res = ready!(std::future::Future::poll(
+            std::pin::pin!(stream.stopped()),
+            cx
+        ));
+        match res {
+            // this side finished the stream and the remote read all of it
+            Ok(None) => Poll::Pending,
+            Ok(Some(_)) | Err(_) => Poll::Ready(()),
+        }
+    })
+}
+
+/// Waits until the remote acknowledged all data of a finished quinn stream.
+///
+/// Finishing a quinn stream only queues the end of the stream, so a sink that is
+/// closed and then dropped together with its connection could lose the last frames.
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+#[derive(Debug, Default)]
+pub(crate) struct Acknowledged {
+    /// Whether all data was acknowledged
+    done: bool,
+}
+
+#[cfg(any(feature = "quinn-transport", feature = "iroh-net-transport"))]
+impl Acknowledged {
+    /// Poll for the acknowledgement, after `stream` has been finished.
+    ///
+    /// Fails with [quinn::WriteError::Stopped] if the remote stopped the stream before
+    /// reading all data.
+    pub(crate) fn poll(
+        &mut self,
+        stream: &mut quinn::SendStream,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.done {
+            return Poll::Ready(Ok(()));
+        }
+        // the future only registers the waker with the connection, so it can be
+        // recreated on every poll
+        let res = ready!(std::future::Future::poll(
+            std::pin::pin!(stream.stopped()),
+            cx
+        ));
+        self.done = true;
+        Poll::Ready(match res {
+            Ok(None) => Ok(()),
+            Ok(Some(code)) => Err(quinn::WriteError::Stopped(code).into()),
+            Err(cause) => Err(io::Error::new(io::ErrorKind::ConnectionAborted, cause)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::StreamExt;
+    use futures_util::SinkExt;
+
+    use tokio::io::ReadBuf;
+
+    use super::*;
+    use crate::transport::{codec::BincodeCodec, protocol::frame_too_large};
+
+    #[test]
+    fn small_and_large_frames() {
+        let messages = [10, 10_000, 20, COPY_THRESHOLD, 100_000]
+            .map(|size| vec![size as u8; size])
+            .to_vec();
+        let data = futures::executor::block_on(async {
+            let mut write = FramedCodecWrite::new(Vec::new(), BincodeCodec, 1 << 20);
+            for message in &messages {
+                write.feed(message.clone()).await.unwrap();
+            }
+            write.flush().await.unwrap();
+            write.into_inner()
+        });
+        let read = FramedCodecRead::<_, Vec<u8>, _>::new(&data[..], BincodeCodec, 1 << 20);
+        let received = futures::executor::block_on(read.map(Result::unwrap).collect::<Vec<_>>());
+        assert_eq!(received, messages);
+    }
+
+    /// Counts the reads from an in-memory stream
+    struct CountReads<'a> {
+        data: &'a [u8],
+        reads: usize,
+    }
+
+    impl AsyncRead for CountReads<'_> {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut task::Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let this = self.get_mut();
+            this.reads += 1;
+            Pin::new(&mut this.data).poll_read(cx, buf)
+        }
+    }
+
+    /// Count the reads it takes to receive `frames` frames of `size` bytes
+    fn count_reads(
+        size: usize,
+        frames: usize,
+        reader: fn(CountReads) -> FrameReader<CountReads>,
+    ) -> usize {
+        let data = futures::executor::block_on(async {
+            let mut write = FramedCodecWrite::new(Vec::new(), BincodeCodec, 1 << 20);
+            for _ in 0..frames {
+                write.feed(vec![1u8; size]).await.unwrap();
+            }
+            write.flush().await.unwrap();
+            write.into_inner()
+        });
+        let mut read = reader(CountReads {
+            data: &data,
+            reads: 0,
+        });
+        let received = futures::executor::block_on((&mut read).map(Result::unwrap).count());
+        assert_eq!(received, frames);
+        read.into_inner().reads
+    }
+
+    #[test]
+    fn adaptive_reader_takes_fewer_reads() {
+        const FRAMES: usize = 64;
+        // frames larger than the fixed read size, but within the adaptive one
+        for size in [20_000, 60_000] {
+            let fixed = count_reads(size, FRAMES, |inner| FrameReader::new(inner, 1 << 20));
+            let adaptive = count_reads(size, FRAMES, |inner| FrameReader::adaptive(inner, 1 << 20));
+            assert!(
+                adaptive < fixed * 3 / 4,
+                "{adaptive} adaptive reads, {fixed} fixed reads"
+            );
+        }
+    }
+
+    #[test]
+    fn frame_too_large_error() {
+        let mut write = FramedCodecWrite::new(Vec::new(), BincodeCodec, 100);
+        let res = futures::executor::block_on(write.send(vec![0u8; 200]));
+        let error = res.unwrap_err();
+        assert_eq!(frame_too_large(&error).map(|e| e.limit), Some(100));
+    }
+}
@@ -0,0 +1,598 @@
+//! WebSocket transport implementation based on [tokio-tungstenite](https://crates.io/crates/tokio-tungstenite)
+//!
+//! This makes it possible to talk to a quic-rpc server from environments where only
+//! WebSockets are available, e.g. a browser or a proxy that only forwards http.
+//!
+//! Every channel is a separate WebSocket connection, and every message is sent as a
+//! single binary WebSocket message, a data frame. It starts with the tag byte 0,
+//! followed by the message serialized with a [Codec], so messages that serialize to
+//! zero bytes are fine. Text messages are accepted as well and decoded as a single
+//! message without a tag, which is useful together with a text based codec such as
+//! JSON.
+//!
+//! # Closing
+//!
+//! Dropping or closing the send side of a channel sends a close frame, a binary
+//! message that consists of the tag byte 1 only. It ends the receive side on the
+//! remote with `None`. This allows client streaming and bidi streaming interactions to
+//! finish the updates while still waiting for the response. Once both sides are done
+//! sending, the connection is closed with the closing handshake of WebSocket.
+//!
+//! A WebSocket close from the remote also ends the receive side with `None` instead
+//! of an error, so a server that shuts down cleanly terminates client streams. A
+//! connection that is dropped without a close ends the receive side with a
+//! [RecvError::Ws]. A binary message with any other tag is reported as
+//! [RecvError::InvalidFrame].
+//!
+//! # wasm
+//!
+//! On wasm32 targets, only the [WsConnector] is available, using the WebSocket of the
+//! browser through [tokio-tungstenite-wasm](https://crates.io/crates/tokio-tungstenite-wasm).
+//! The browser WebSocket is not `Send`, so each connection is driven by a task spawned
+//! with `wasm_bindgen_futures::spawn_local`, and the halves of the channel talk to it
+//! through channels like on native targets.
+use std::{
+    fmt, io,
+    marker::PhantomData,
+    pin::Pin,
+    result,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::{channel::oneshot, Future, SinkExt, StreamExt};
+use futures_lite::Stream;
+use futures_sink::Sink;
+#[cfg(not(target_arch = "wasm32"))]
+use tokio::{
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    task::JoinHandle,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use tokio_tungstenite::{
+    tungstenite::{Error as WsError, Message},
+    WebSocketStream,
+};
+#[cfg(target_arch = "wasm32")]
+use tokio_tungstenite_wasm::{Error as WsError, Message};
+#[cfg(not(target_arch = "wasm32"))]
+use tracing::debug;
+use tracing::trace;
+
+use super::{
+    codec::{BincodeCodec, Codec},
+    ConnectionErrors, Connector, StreamTypes,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use super::{Listener, LocalAddr};
+use crate::RpcMessage;
+
+/// Number of messages that are buffered in each direction of a channel
+const BUFFER_SIZE: usize = 32;
+
+/// Tag of a data frame, which is followed by a serialized message
+const DATA: u8 = 0;
+
+/// Tag of a close frame, after which the remote sends no more data frames
+const CLOSE: u8 = 1;
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+struct ListenerInner {
+    task: JoinHandle<()>,
+    local_addr: [LocalAddr; 1],
+    receiver: flume::Receiver<WebSocketStream<TcpStream>>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for ListenerInner {
+    fn drop(&mut self) {
+        debug!("Dropping websocket listener");
+        self.task.abort();
+    }
+}
+
+/// A listener that accepts WebSocket connections
+///
+/// Each accepted WebSocket connection is a single channel.
+///
+/// Creating this spawns a tokio task which accepts connections and performs the
+/// WebSocket handshake. Once the last clone is dropped, no new connections will be
+/// accepted.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug)]
+pub struct WsListener<In: RpcMessage, Out: RpcMessage, C: Codec = BincodeCodec> {
+    inner: Arc<ListenerInner>,
+    codec: C,
+    _p: PhantomData<(In, Out)>,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<In: RpcMessage, Out: RpcMessage> WsListener<In, Out> {
+    /// Create a new listener bound to the given address.
+    pub async fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        Self::new(listener)
+    }
+
+    /// Create a new listener, given a bound tcp listener.
+    pub fn new(listener: TcpListener) -> io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (sender, receiver) = flume::bounded(16);
+        let task = tokio::spawn(Self::listener_handler(listener, sender));
+        Ok(Self {
+            inner: Arc::new(ListenerInner {
+                task,
+                local_addr: [LocalAddr::Socket(local_addr)],
+                receiver,
+            }),
+            codec: BincodeCodec,
+            _p: PhantomData,
+        })
+    }
+
+    async fn listener_handler(
+        listener: TcpListener,
+        sender: flume::Sender<WebSocketStream<TcpStream>>,
+    ) {
+        loop {
+            let (stream, addr) = match listener.accept().await {
+                Ok(x) => x,
+                Err(e) => {
+                    tracing::warn!("Error accepting tcp connection: {}", e);
+                    continue;
+                }
+            };
+            debug!("Tcp connection from {}", addr);
+            let sender = sender.clone();
+            // do the handshake in a separate task so a slow client does not block others
+            tokio::spawn(async move {
+                match tokio_tungstenite::accept_async(stream).await {
+                    Ok(ws) => {
+                        sender.send_async(ws).await.ok();
+                    }
+                    Err(e) => {
+                        tracing::warn!("WebSocket handshake with {} failed: {}", addr, e);
+                    }
+                }
+            });
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> WsListener<In, Out, C> {
+    /// Use a different [Codec] to serialize messages.
+    ///
+    /// The clients need to use the same codec, see [WsConnector::with_codec].
+    pub fn with_codec<C2: Codec>(self, codec: C2) -> WsListener<In, Out, C2> {
+        WsListener {
+            inner: self.inner,
+            codec,
+            _p: PhantomData,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Clone for WsListener<In, Out, C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            codec: self.codec.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> ConnectionErrors for WsListener<In, Out, C> {
+    type SendError = self::SendError;
+    type RecvError = self::RecvError;
+    type OpenError = AcceptError;
+    type AcceptError = AcceptError;
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        matches!(
+            error,
+            RecvError::DeserializeError(e) if super::codec::unknown_message(e).is_some()
+        )
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> StreamTypes for WsListener<In, Out, C> {
+    type In = In;
+    type Out = Out;
+    type RecvStream = self::RecvStream<In, C>;
+    type SendSink = self::SendSink<Out, C>;
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Listener for WsListener<In, Out, C> {
+    async fn accept(&self) -> Result<(Self::SendSink, Self::RecvStream), AcceptError> {
+        let ws = self
+            .inner
+            .receiver
+            .recv_async()
+            .await
+            .map_err(|_| AcceptError::RemoteDropped)?;
+        let (halves, handler) = channel(ws, self.codec.clone());
+        tokio::spawn(handler);
+        Ok(halves)
+    }
+
+    fn local_addr(&self) -> &[LocalAddr] {
+        &self.inner.local_addr
+    }
+}
+
+/// A connector that opens a new WebSocket connection for each channel
+pub struct WsConnector<In: RpcMessage, Out: RpcMessage, C: Codec = BincodeCodec> {
+    url: Arc<str>,
+    codec: C,
+    _p: PhantomData<(In, Out)>,
+}
+
+impl<In: RpcMessage, Out: RpcMessage> WsConnector<In, Out> {
+    /// Create a new connector for the given url, e.g. `ws://127.0.0.1:3000`.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into().into(),
+            codec: BincodeCodec,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> WsConnector<In, Out, C> {
+    /// Use a different [Codec] to serialize messages.
+    ///
+    /// The server needs to use the same codec, see [WsListener::with_codec].
+    pub fn with_codec<C2: Codec>(self, codec: C2) -> WsConnector<In, Out, C2> {
+        WsConnector {
+            url: self.url,
+            codec,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> fmt::Debug for WsConnector<In, Out, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WsConnector")
+            .field("url", &self.url)
+            .field("codec", &self.codec)
+            .finish()
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Clone for WsConnector<In, Out, C> {
+    fn clone(&self) -> Self {
+        Self {
+            url: self.url.clone(),
+            codec: self.codec.clone(),
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> ConnectionErrors for WsConnector<In, Out, C> {
+    type SendError = self::SendError;
+    type RecvError = self::RecvError;
+    type OpenError = OpenError;
+    type AcceptError = AcceptError;
+
+    fn is_unknown_message(error: &Self::RecvError) -> bool {
+        matches!(
+            error,
+            RecvError::DeserializeError(e) if super::codec::unknown_message(e).is_some()
+        )
+    }
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> StreamTypes for WsConnector<In, Out, C> {
+    type In = In;
+    type Out = Out;
+    type RecvStream = self::RecvStream<In, C>;
+    type SendSink = self::SendSink<Out, C>;
+}
+
+impl<In: RpcMessage, Out: RpcMessage, C: Codec> Connector for WsConnector<In, Out, C> {
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let (ws, _response) = tokio_tungstenite::connect_async(&*self.url)
+            .await
+            .map_err(OpenError)?;
+        let (halves, handler) = channel(ws, self.codec.clone());
+        tokio::spawn(handler);
+        Ok(halves)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+        let (opened_tx, opened_rx) = oneshot::channel();
+        let url = self.url.clone();
+        let codec = self.codec.clone();
+        // the websocket of the browser is not Send, so it stays on a local task
+        wasm_bindgen_futures::spawn_local(async move {
+            match tokio_tungstenite_wasm::connect(&*url).await {
+                Ok(ws) => {
+                    let (halves, handler) = channel(ws, codec);
+                    opened_tx.send(Ok(halves)).ok();
+                    handler.await;
+                }
+                Err(cause) => {
+                    opened_tx.send(Err(OpenError(cause))).ok();
+                }
+            }
+        });
+        opened_rx
+            .await
+            .map_err(|_| OpenError(WsError::ConnectionClosed))?
+    }
+}
+
+/// Return the two halves of a channel, and the future that drives the websocket
+fn channel<In, Out, C, W>(
+    ws: W,
+    codec: C,
+) -> (
+    (SendSink<Out, C>, RecvStream<In, C>),
+    impl Future<Output = ()>,
+)
+where
+    In: RpcMessage,
+    Out: RpcMessage,
+    C: Codec,
+    W: Stream<Item = result::Result<Message, WsError>> + Sink<Message> + Unpin,
+{
+    let (out_tx, out_rx) = flume::bounded(BUFFER_SIZE);
+    let (in_tx, in_rx) = flume::bounded(BUFFER_SIZE);
+    let halves = (
+        SendSink::new(out_tx, codec.clone()),
+        RecvStream::new(in_rx, codec),
+    );
+    (halves, connection_handler(ws, out_rx, in_tx))
+}
+
+/// Forward messages between the websocket and the two halves of the channel
+///
+/// When the send half is dropped, a close frame is sent to tell the remote that no
+/// more messages will follow. Once that is done and the remote has done the same, the
+/// closing handshake is performed. A received WebSocket close terminates the receive
+/// half.
+async fn connection_handler<W>(
+    ws: W,
+    out_rx: flume::Receiver<Message>,
+    in_tx: flume::Sender<result::Result<Bytes, RecvError>>,
+) where
+    W: Stream<Item = result::Result<Message, WsError>> + Sink<Message> + Unpin,
+{
+    let (mut ws_send, mut ws_recv) = ws.split();
+    // dropped once we don't expect any more messages from the remote
+    let (in_done_tx, in_done_rx) = oneshot::channel::<()>();
+    let forward_in = async move {
+        let mut in_done_tx = Some(in_done_tx);
+        let mut in_tx = Some(in_tx);
+        while let Some(msg) = ws_recv.next().await {
+            let item = match msg {
+                Ok(Message::Binary(data)) => match data.first() {
+                    Some(&DATA) => Ok(Bytes::from(data).slice(1..)),
+                    Some(&CLOSE) => {
+                        trace!("WebSocket remote finished sending");
+                        in_tx = None;
+                        in_done_tx = None;
+                        continue;
+                    }
+                    _ => Err(RecvError::InvalidFrame),
+                },
+                Ok(Message::Text(text)) => Ok(Bytes::from(text)),
+                Ok(Message::Close(frame)) => {
+                    trace!("WebSocket closed by remote: {:?}", frame);
+                    break;
+                }
+                // pings are answered by tungstenite
+                #[cfg(not(target_arch = "wasm32"))]
+                Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_)) => continue,
+                Err(WsError::ConnectionClosed) => break,
+                Err(cause) => {
+                    if let Some(tx) = in_tx.take() {
+                        tx.send_async(Err(RecvError::Ws(cause))).await.ok();
+                    }
+                    break;
+                }
+            };
+            let Some(tx) = &in_tx else {
+                continue;
+            };
+            if tx.send_async(item).await.is_err() {
+                // nobody is listening anymore, but we still need to keep
+                // reading to see the close.
+                trace!("WebSocket receiver dropped");
+                in_tx = None;
+                in_done_tx = None;
+            }
+        }
+        drop(in_done_tx);
+    };
+    let forward_out = async move {
+        while let Ok(msg) = out_rx.recv_async().await {
+            if ws_send.send(msg).await.is_err() {
+                return;
+            }
+        }
+        // the send half was dropped, tell the remote
+        if ws_send.send(Message::Binary(vec![CLOSE])).await.is_err() {
+            return;
+        }
+        in_done_rx.await.ok();
+        ws_send.close().await.ok();
+    };
+    futures::future::join(forward_in, forward_out).await;
+}
+
+/// Receive stream for websocket channels
+pub struct RecvStream<In: RpcMessage, C: Codec = BincodeCodec> {
+    recv: flume::r#async::RecvStream<'static, result::Result<Bytes, RecvError>>,
+    codec: C,
+    _p: PhantomData<In>,
+}
+
+impl<In: RpcMessage, C: Codec> RecvStream<In, C> {
+    fn new(recv: flume::Receiver<result::Result<Bytes, RecvError>>, codec: C) -> Self {
+        Self {
+            recv: recv.into_stream(),
+            codec,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<In: RpcMessage, C: Codec> fmt::Debug for RecvStream<In, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecvStream").finish_non_exhaustive()
+    }
+}
+
+impl<In: RpcMessage, C: Codec> Stream for RecvStream<In, C> {
+    type Item = result::Result<In, RecvError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let bytes = match Pin::new(&mut self.recv).poll_next(cx) {
+            Poll::Ready(Some(Ok(bytes))) => bytes,
+            Poll::Ready(Some(Err(cause))) => return Poll::Ready(Some(Err(cause))),
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => return Poll::Pending,
+        };
+        let item = self
+            .codec
+            .decode(bytes)
+            .map_err(RecvError::DeserializeError);
+        Poll::Ready(Some(item))
+    }
+}
+
+/// Send sink for websocket channels
+pub struct SendSink<Out: RpcMessage, C: Codec = BincodeCodec> {
+    sink: flume::r#async::SendSink<'static, Message>,
+    codec: C,
+    _p: PhantomData<Out>,
+}
+
+impl<Out: RpcMessage, C: Codec> SendSink<Out, C> {
+    fn new(sender: flume::Sender<Message>, codec: C) -> Self {
+        Self {
+            sink: sender.into_sink(),
+            codec,
+            _p: PhantomData,
+        }
+    }
+}
+
+impl<Out: RpcMessage, C: Codec> fmt::Debug for SendSink<Out, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SendSink").finish_non_exhaustive()
+    }
+}
+
+impl<Out: RpcMessage, C: Codec> Sink<Out> for SendSink<Out, C> {
+    type Error = SendError;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SendError>> {
+        Pin::new(&mut self.sink)
+            .poll_ready(cx)
+            .map_err(|_| SendError::ReceiverDropped)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: Out) -> Result<(), SendError> {
+        let data = self
+            .codec
+            .encode(&item)
+            .map_err(SendError::SerializeError)?;
+        let mut frame = Vec::with_capacity(1 + data.len());
+        frame.push(DATA);
+        frame.extend_from_slice(&data);
+        Pin::new(&mut self.sink)
+            .start_send(Message::Binary(frame))
+            .map_err(|_| SendError::ReceiverDropped)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SendError>> {
+        Pin::new(&mut self.sink)
+            .poll_flush(cx)
+            .map_err(|_| SendError::ReceiverDropped)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), SendError>> {
+        Pin::new(&mut self.sink)
+            .poll_close(cx)
+            .map_err(|_| SendError::ReceiverDropped)
+    }
+}
+
+/// Send error for websocket channels.
+#[derive(Debug)]
+pub enum SendError {
+    /// Error when serializing the message.
+    SerializeError(io::Error),
+    /// The connection has been closed.
+    ReceiverDropped,
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl std::error::Error for SendError {}
+
+/// Receive error for websocket channels.
+#[derive(Debug)]
+pub enum RecvError {
+    /// Error when deserializing the message.
+    DeserializeError(io::Error),
+    /// WebSocket error, e.g. the connection was reset without a close.
+    Ws(WsError),
+    /// A binary message that is neither a data frame nor a close frame.
+    InvalidFrame,
+}
+
+impl fmt::Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+/// OpenError for websocket channels.
+///
+/// Contains the error from the WebSocket handshake.
+#[derive(Debug)]
+pub struct OpenError(pub WsError);
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for OpenError {}
+
+/// AcceptError for websocket channels.
+#[derive(Debug)]
+pub enum AcceptError {
+    /// The task accepting connections has stopped
+    RemoteDropped,
+}
+
+impl fmt::Display for AcceptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl std::error::Error for AcceptError {}
@@ -0,0 +1,278 @@
+#![cfg(feature = "flume-transport")]
+use std::{sync::Arc, time::Duration};
+
+use derive_more::{From, TryInto};
+use futures::{channel::mpsc, future::BoxFuture, FutureExt, StreamExt};
+use quic_rpc::{
+    message::RpcMsg,
+    server::{LimitPolicy, Priority, RpcChannel, RpcServerError},
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Semaphore;
+use tokio_util::task::AbortOnDropHandle;
+
+#[derive(Debug, Clone)]
+struct JobService;
+
+impl Service for JobService {
+    type Req = JobRequest;
+    type Res = JobResponse;
+}
+
+/// Run a job of the given kind
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Job {
+    /// Runs until the test lets it finish
+    Block,
+    Bulk,
+    Lookup,
+}
+
+impl RpcMsg<JobService> for Job {
+    type Response = Done;
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Done;
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum JobRequest {
+    Job(Job),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum JobResponse {
+    Done(Done),
+}
+
+type JobListener = flume::FlumeListener<JobRequest, JobResponse>;
+
+type JobHandlerResult = Result<(), RpcServerError<JobListener>>;
+
+fn spawn(task: BoxFuture<'static, ()>) {
+    tokio::spawn(task);
+}
+
+/// Answer jobs and report each job when it starts, [Job::Block] waits for a permit of
+/// `gate`
+fn job_handler(
+    started_tx: mpsc::UnboundedSender<Job>,
+    gate: Arc<Semaphore>,
+) -> impl Fn(JobRequest, RpcChannel<JobService, JobListener>) -> BoxFuture<'static, JobHandlerResult>
+       + Send
+       + Sync
+       + 'static {
+    move |JobRequest::Job(job), chan| {
+        let started_tx = started_tx.clone();
+        let gate = gate.clone();
+        async move {
+            chan.rpc(job, (), |_, job| async move {
+                started_tx.unbounded_send(job).ok();
+                if job == Job::Block {
+                    gate.acquire().await.unwrap().forget();
+                }
+                Done
+            })
+            .await
+        }
+        .boxed()
+    }
+}
+
+/// Once the limit is reached, new channels wait until a running handler finishes
+#[tokio::test]
+async fn max_concurrent_wait() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<JobService, _>::new(server)
+        .with_spawner(spawn)
+        .with_max_concurrent(2);
+    let (started_tx, mut started) = mpsc::unbounded();
+    let gate = Arc::new(Semaphore::new(0));
+    let _server_handle = AbortOnDropHandle::new(tokio::spawn(
+        server
+            .clone()
+            .accept_loop(job_handler(started_tx, gate.clone())),
+    ));
+    let client = RpcClient::<JobService, _>::new(client);
+    let call = |job| {
+        tokio::spawn({
+            let client = client.clone();
+            async move { client.rpc(job).await }
+        })
+    };
+
+    let blocks = [call(Job::Block), call(Job::Block)];
+    assert_eq!(started.next().await, Some(Job::Block));
+    assert_eq!(started.next().await, Some(Job::Block));
+    assert_eq!(server.available_permits(), Some(0));
+    let lookup = call(Job::Lookup);
+
+    // the lookup gets the permit of the first block job that finishes
+    gate.add_permits(1);
+    assert_eq!(started.next().await, Some(Job::Lookup));
+    lookup.await??;
+    gate.add_permits(1);
+    for call in blocks {
+        call.await??;
+    }
+    Ok(())
+}
+
+/// Once the limit is reached, new channels are closed right away with
+/// [LimitPolicy::Reject]
+#[tokio::test]
+async fn max_concurrent_reject() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<JobService, _>::new(server)
+        .with_spawner(spawn)
+        .with_max_concurrent_policy(1, LimitPolicy::Reject);
+    let (started_tx, mut started) = mpsc::unbounded();
+    let gate = Arc::new(Semaphore::new(0));
+    let _server_handle = AbortOnDropHandle::new(tokio::spawn(
+        server.accept_loop(job_handler(started_tx, gate.clone())),
+    ));
+    let client = RpcClient::<JobService, _>::new(client);
+
+    let block = tokio::spawn({
+        let client = client.clone();
+        async move { client.rpc(Job::Block).await }
+    });
+    assert_eq!(started.next().await, Some(Job::Block));
+    assert!(client.rpc(Job::Lookup).await.is_err());
+
+    gate.add_permits(1);
+    block.await??;
+    Ok(())
+}
+
+/// After a shutdown, no new channels are accepted, but running handlers may finish
+///
+/// The accept loop is spawned with glib, so the test runs on a glib main context.
+#[test]
+fn shutdown_drains_handlers() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let context = glib::MainContext::new();
+    context.with_thread_default(|| {
+        context.block_on(async {
+            let (server, client) = flume::channel(1);
+            let (started_tx, mut started) = mpsc::unbounded();
+            let gate = Arc::new(Semaphore::new(0));
+            let handle = RpcServer::<JobService, _>::new(server)
+                .spawn_accept_loop_with_shutdown(job_handler(started_tx, gate.clone()));
+            let client = RpcClient::<JobService, _>::new(client);
+
+            let block = client.rpc(Job::Block);
+            let shutdown = async {
+                assert_eq!(started.next().await, Some(Job::Block));
+                let shutdown = handle.shutdown(None);
+                futures::pin_mut!(shutdown);
+                // the block job is still running, so the shutdown waits for it
+                assert!(futures::poll!(&mut shutdown).is_pending());
+                gate.add_permits(1);
+                shutdown.await;
+            };
+            let (res, ()) = futures::join!(block, shutdown);
+            res?;
+            // the listener is gone
+            assert!(client.rpc(Job::Lookup).await.is_err());
+            anyhow::Ok(())
+        })
+    })?
+}
+
+/// Handlers that are still running after the shutdown deadline are aborted
+#[test]
+fn shutdown_deadline_aborts_handlers() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let context = glib::MainContext::new();
+    context.with_thread_default(|| {
+        context.block_on(async {
+            let (server, client) = flume::channel(1);
+            let (started_tx, mut started) = mpsc::unbounded();
+            let gate = Arc::new(Semaphore::new(0));
+            let handle = RpcServer::<JobService, _>::new(server)
+                .spawn_accept_loop_with_shutdown(job_handler(started_tx, gate.clone()));
+            let client = RpcClient::<JobService, _>::new(client);
+
+            let block = client.rpc(Job::Block);
+            let shutdown = async {
+                assert_eq!(started.next().await, Some(Job::Block));
+                handle.shutdown(Some(Duration::ZERO)).await;
+            };
+            // the gate is never opened
+            let (res, ()) = futures::join!(block, shutdown);
+            assert!(res.is_err());
+            anyhow::Ok(())
+        })
+    })?
+}
+
+/// Once the limit is reached, a channel with a higher priority gets the next permit,
+/// even if it arrived later
+#[tokio::test]
+async fn priority_high_first() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let (classified_tx, mut classified) = mpsc::unbounded();
+    let server = RpcServer::<JobService, _>::new(server)
+        .with_spawner(spawn)
+        .with_max_concurrent(1)
+        .with_priority(0, move |JobRequest::Job(job)| {
+            classified_tx.unbounded_send(*job).ok();
+            match job {
+                Job::Lookup => Priority::High,
+                Job::Block | Job::Bulk => Priority::Low,
+            }
+        });
+    let (started_tx, mut started) = mpsc::unbounded();
+    let gate = Arc::new(Semaphore::new(0));
+    let _server_handle = AbortOnDropHandle::new(tokio::spawn(
+        server.accept_loop(job_handler(started_tx, gate.clone())),
+    ));
+    let client = RpcClient::<JobService, _>::new(client);
+    let call = |job| {
+        tokio::spawn({
+            let client = client.clone();
+            async move { client.rpc(job).await }
+        })
+    };
+
+    // the only permit is taken
+    let block = call(Job::Block);
+    assert_eq!(classified.next().await, Some(Job::Block));
+    assert_eq!(started.next().await, Some(Job::Block));
+    // both wait for it, the bulk job arrived first
+    let bulk = call(Job::Bulk);
+    assert_eq!(classified.next().await, Some(Job::Bulk));
+    let lookup = call(Job::Lookup);
+    assert_eq!(classified.next().await, Some(Job::Lookup));
+
+    gate.add_permits(1);
+    assert_eq!(started.next().await, Some(Job::Lookup));
+    assert_eq!(started.next().await, Some(Job::Bulk));
+    for call in [block, bulk, lookup] {
+        call.await??;
+    }
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "reserved permits must be fewer than the concurrency limit")]
+fn priority_reserves_whole_limit() {
+    let (server, _client) = flume::channel(1);
+    let _server = RpcServer::<JobService, _>::new(server)
+        .with_priority(2, |_| Priority::Normal)
+        .with_max_concurrent(2);
+}
+
+#[test]
+#[should_panic(expected = "the per peer limit must be at least one")]
+fn max_concurrent_per_peer_zero() {
+    let (server, _client) = flume::channel(1);
+    let _server =
+        RpcServer::<JobService, _>::new(server).with_max_concurrent_per_peer(0, |info| info.addr);
+}
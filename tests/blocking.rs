@@ -0,0 +1,36 @@
+#![cfg(all(feature = "flume-transport", feature = "blocking"))]
+use quic_rpc::{blocking::BlockingRpcClient, transport::flume, RpcServer};
+
+mod math;
+use math::*;
+
+#[test]
+fn blocking_client() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    // the server needs a runtime of its own
+    let runtime = tokio::runtime::Runtime::new()?;
+    let (server, client) = flume::channel(1);
+    let _server = runtime.block_on(async { ComputeService::server(RpcServer::new(server)) });
+
+    let client = BlockingRpcClient::<ComputeService, _>::new(|| client)?;
+    assert_eq!(client.rpc(Sqr(1234))?, SqrResponse(1522756));
+
+    let items = client
+        .server_streaming(Fibonacci(10))?
+        .map(|item| item.map(|item| item.0))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(items, vec![0, 1, 1, 2, 3, 5, 8, 13, 21, 34]);
+
+    // clones share the runtime, and can be used from other threads
+    let handles = (0..4u64)
+        .map(|i| {
+            let client = client.clone();
+            std::thread::spawn(move || client.rpc(Sqr(i)))
+        })
+        .collect::<Vec<_>>();
+    for (i, handle) in handles.into_iter().enumerate() {
+        let res = handle.join().expect("thread panicked")?;
+        assert_eq!(res, SqrResponse((i * i) as u128));
+    }
+    Ok(())
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "broadcast")]
+use futures::StreamExt;
+use quic_rpc::broadcast::{Broadcaster, LagPolicy, Lagged};
+
+/// every subscriber gets every item that was sent after it subscribed
+#[tokio::test]
+async fn broadcast_fan_out() {
+    let events = Broadcaster::new(4, LagPolicy::DropOldest);
+    // nobody listens yet
+    assert_eq!(events.send(0u64), 0);
+    let first = events.subscribe();
+    let second = events.subscribe();
+    assert_eq!(events.receiver_count(), 2);
+    for i in 1..4 {
+        assert_eq!(events.send(i), 2);
+    }
+    // the streams end once the broadcaster is gone
+    drop(events);
+    let expected = vec![Ok(1), Ok(2), Ok(3)];
+    assert_eq!(first.collect::<Vec<_>>().await, expected);
+    assert_eq!(second.collect::<Vec<_>>().await, expected);
+}
+
+/// a subscriber that falls behind misses the oldest items and keeps going
+#[tokio::test]
+async fn broadcast_lag_drop_oldest() {
+    let events = Broadcaster::new(2, LagPolicy::DropOldest);
+    let subscription = events.subscribe();
+    for i in 0..4u64 {
+        events.send(i);
+    }
+    drop(events);
+    assert_eq!(
+        subscription.collect::<Vec<_>>().await,
+        vec![Err(Lagged(2)), Ok(2), Ok(3)]
+    );
+}
+
+/// a subscriber that falls behind is told how many items it missed, then disconnected
+#[tokio::test]
+async fn broadcast_lag_disconnect() {
+    let events = Broadcaster::new(2, LagPolicy::Disconnect);
+    let subscription = events.subscribe();
+    for i in 0..4u64 {
+        events.send(i);
+    }
+    assert_eq!(subscription.collect::<Vec<_>>().await, vec![Err(Lagged(2))]);
+    // the broadcaster itself is still usable
+    let subscription = events.subscribe();
+    events.send(4);
+    drop(events);
+    assert_eq!(subscription.collect::<Vec<_>>().await, vec![Ok(4)]);
+}
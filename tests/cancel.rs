@@ -0,0 +1,111 @@
+#![cfg(feature = "flume-transport")]
+use std::time::Duration;
+
+use futures::{channel::mpsc, SinkExt, StreamExt};
+use quic_rpc::{client::CallError, transport::flume, RpcClient};
+
+mod hang;
+use hang::*;
+
+/// Spawns a flume server whose handlers hang forever, which is stopped when the returned
+/// handle is dropped
+fn serve() -> (
+    RpcClient<HangService, flume::FlumeConnector<HangResponse, HangRequest>>,
+    mpsc::UnboundedReceiver<Event>,
+    impl Sized,
+) {
+    let (listener, connector) = flume::channel(1);
+    let (events, handle) = hang::serve(listener);
+    (RpcClient::new(connector), events, handle)
+}
+
+#[tokio::test]
+async fn cancel_rpc() -> anyhow::Result<()> {
+    let (client, mut events, _server) = serve();
+    let call = tokio::spawn(async move { client.rpc(HangRpc).await });
+    assert_eq!(next_event(&mut events).await?, Event::Started);
+    call.abort();
+    assert_eq!(next_event(&mut events).await?, Event::Dropped);
+    Ok(())
+}
+
+/// a call that times out drops its channel, so the server drops the handler as well
+///
+/// The timeout is a glib timer, so the call runs on a glib main context.
+#[test]
+fn cancel_rpc_timeout() -> anyhow::Result<()> {
+    let context = glib::MainContext::new();
+    context.with_thread_default(|| {
+        context.block_on(async {
+            let (client, mut events, _server) = serve();
+            let res = client
+                .rpc_with_timeout(HangRpc, Duration::from_millis(50))
+                .await;
+            assert!(
+                matches!(res, Err(CallError::Timeout { sent: true })),
+                "{res:?}"
+            );
+            let timeout = Duration::from_secs(5);
+            let event = glib::future_with_timeout(timeout, events.next()).await?;
+            assert_eq!(event, Some(Event::Started));
+            let event = glib::future_with_timeout(timeout, events.next()).await?;
+            assert_eq!(event, Some(Event::Dropped));
+            anyhow::Ok(())
+        })
+    })?
+}
+
+#[tokio::test]
+async fn cancel_server_streaming() -> anyhow::Result<()> {
+    let (client, mut events, _server) = serve();
+    let mut items = client.server_streaming(HangServerStreaming).await?;
+    let next = tokio::time::timeout(Duration::from_millis(100), items.next()).await;
+    assert!(next.is_err());
+    assert_eq!(next_event(&mut events).await?, Event::Started);
+    drop(items);
+    assert_eq!(next_event(&mut events).await?, Event::Dropped);
+    Ok(())
+}
+
+#[tokio::test]
+async fn cancel_client_streaming() -> anyhow::Result<()> {
+    let (client, mut events, _server) = serve();
+    let (mut send, recv) = client.client_streaming(HangClientStreaming).await?;
+    send.send(HangUpdate).await?;
+    assert_eq!(next_event(&mut events).await?, Event::Started);
+    drop(send);
+    drop(recv);
+    assert_eq!(next_event(&mut events).await?, Event::Dropped);
+    Ok(())
+}
+
+#[tokio::test]
+async fn cancel_bidi_streaming() -> anyhow::Result<()> {
+    let (client, mut events, _server) = serve();
+    let (mut send, mut recv) = client.bidi(HangBidi).await?;
+    send.send(HangUpdate).await?;
+    let next = tokio::time::timeout(Duration::from_millis(100), recv.next()).await;
+    assert!(next.is_err());
+    assert_eq!(next_event(&mut events).await?, Event::Started);
+    drop(send);
+    drop(recv);
+    assert_eq!(next_event(&mut events).await?, Event::Dropped);
+    Ok(())
+}
+
+#[tokio::test]
+async fn cancel_not_on_end_of_updates() -> anyhow::Result<()> {
+    let (client, mut events, _server) = serve();
+    let (send, mut recv) = client.client_streaming(HangClientStreaming).await?;
+    assert_eq!(next_event(&mut events).await?, Event::Started);
+    // finishing the updates is not a cancellation, the response is still awaited
+    drop(send);
+    let res = tokio::time::timeout(Duration::from_millis(100), &mut recv).await;
+    assert!(res.is_err());
+    let event = tokio::time::timeout(Duration::from_millis(100), events.next()).await;
+    assert!(event.is_err());
+    // but dropping the response is
+    drop(recv);
+    assert_eq!(next_event(&mut events).await?, Event::Dropped);
+    Ok(())
+}
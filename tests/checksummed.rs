@@ -0,0 +1,128 @@
+#![cfg(feature = "flume-transport")]
+use derive_more::{From, TryInto};
+use futures::{SinkExt, StreamExt};
+use quic_rpc::{
+    client::{CallError, UpdateError},
+    message::{ClientStreaming, ClientStreamingAbortMsg, ClientStreamingMsg, Msg},
+    pattern::{
+        checksummed::{crc32, ChecksumMismatch, ChecksummedChunk},
+        client_streaming::AbortableResponse,
+    },
+    server::RpcServerError,
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+struct UploadService;
+
+impl Service for UploadService {
+    type Req = UploadRequest;
+    type Res = UploadResponse;
+}
+
+/// Upload data, the response is its size
+#[derive(Debug, Serialize, Deserialize)]
+struct Upload;
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum UploadRequest {
+    Upload(Upload),
+    Chunk(ChecksummedChunk),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum UploadResponse {
+    Size(usize),
+    Uploaded(AbortableResponse<usize, ChecksumMismatch>),
+}
+
+impl Msg<UploadService> for Upload {
+    type Pattern = ClientStreaming;
+}
+
+impl ClientStreamingMsg<UploadService> for Upload {
+    type Update = ChecksummedChunk;
+    type Response = usize;
+}
+
+impl ClientStreamingAbortMsg<UploadService> for Upload {
+    type Abort = ChecksumMismatch;
+}
+
+/// Spawns a server that counts the uploaded bytes, which is stopped when the returned
+/// handle is dropped
+fn serve() -> (
+    RpcClient<UploadService, flume::FlumeConnector<UploadResponse, UploadRequest>>,
+    impl Sized,
+) {
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::<UploadService, _>::new(listener);
+    let handle = server.spawn_accept_loop(|req, chan| async move {
+        match req {
+            UploadRequest::Upload(msg) => {
+                chan.client_streaming_checksummed(msg, (), |_, _, mut chunks| async move {
+                    let mut size = 0;
+                    while let Some(data) = chunks.next().await {
+                        size += data.len();
+                    }
+                    size
+                })
+                .await
+            }
+            UploadRequest::Chunk(_) => Err(RpcServerError::UnexpectedStartMessage),
+        }
+    });
+    (RpcClient::new(connector), handle)
+}
+
+#[test]
+fn crc32_check_value() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    assert!(ChecksummedChunk::new(b"123456789".to_vec()).verify());
+}
+
+#[tokio::test]
+async fn checksummed_upload() -> anyhow::Result<()> {
+    let (client, _server) = serve();
+    let (mut send, size) = client.client_streaming_abortable(Upload).await?;
+    for i in 0..10u8 {
+        send.send(ChecksummedChunk::new(vec![i; 100])).await?;
+    }
+    drop(send);
+    assert_eq!(size.await?, 1000);
+    Ok(())
+}
+
+/// a corrupted chunk aborts the upload with its index
+#[tokio::test]
+async fn checksummed_upload_mismatch() -> anyhow::Result<()> {
+    let (client, _server) = serve();
+    let (mut send, size) = client.client_streaming_abortable(Upload).await?;
+    let mismatch = ChecksumMismatch { index: 3 };
+    let mut sent = 0;
+    let err = loop {
+        let mut chunk = ChecksummedChunk::new(vec![sent as u8; 100]);
+        if sent == 3 {
+            chunk.data[17] ^= 0x10;
+        }
+        if let Err(cause) = send.send(chunk).await {
+            break cause;
+        }
+        sent += 1;
+        assert!(sent < 1000, "upload was not aborted");
+    };
+    assert!(sent > 3);
+    match err {
+        UpdateError::Aborted(reason) => assert_eq!(reason, mismatch),
+        err => panic!("unexpected error {err:?}"),
+    }
+    match size.await {
+        Err(CallError::App(reason)) => assert_eq!(reason, mismatch),
+        res => panic!("unexpected result {res:?}"),
+    }
+    assert_eq!(mismatch.to_string(), "checksum mismatch in chunk 3");
+    Ok(())
+}
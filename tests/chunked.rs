@@ -0,0 +1,132 @@
+#![cfg(feature = "flume-transport")]
+use derive_more::{From, TryInto};
+use futures_lite::{stream, StreamExt};
+use quic_rpc::{
+    client::CallError,
+    message::{Msg, ServerStreaming, ServerStreamingMsg},
+    pattern::chunked::{Chunk, Progress},
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+struct BlobService;
+
+impl Service for BlobService {
+    type Req = BlobRequest;
+    type Res = BlobResponse;
+}
+
+/// Request a blob of the given size
+#[derive(Debug, Serialize, Deserialize)]
+struct Blob(usize);
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum BlobRequest {
+    Blob(Blob),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum BlobResponse {
+    Chunk(Chunk),
+}
+
+impl Msg<BlobService> for Blob {
+    type Pattern = ServerStreaming;
+}
+
+impl ServerStreamingMsg<BlobService> for Blob {
+    type Response = Chunk;
+}
+
+fn blob(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i % 251) as u8).collect()
+}
+
+/// Spawns a server that sends blobs in chunks of 1000 bytes, which is stopped when the
+/// returned handle is dropped
+fn serve() -> (
+    RpcClient<BlobService, flume::FlumeConnector<BlobResponse, BlobRequest>>,
+    impl Sized,
+) {
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::<BlobService, _>::new(listener);
+    let handle =
+        server.spawn_accept_loop(|req, chan| async move {
+            match req {
+                BlobRequest::Blob(msg) => {
+                    chan.server_streaming_chunked(msg, (), 1000, |_, Blob(size)| async move {
+                        blob(size)
+                    })
+                    .await
+                }
+            }
+        });
+    (RpcClient::new(connector), handle)
+}
+
+#[tokio::test]
+async fn chunked_progress() -> anyhow::Result<()> {
+    let (client, _server) = serve();
+    let chunks = client.server_streaming_chunked(Blob(10_500)).await?;
+    assert_eq!(chunks.size(), 10_500);
+    let mut progress = Vec::new();
+    let data = chunks
+        .collect_with_progress(|p| progress.push(p.transferred))
+        .await?;
+    assert_eq!(data, blob(10_500));
+    let expected: Vec<u64> = (0..=10).map(|i| i * 1000).chain([10_500]).collect();
+    assert_eq!(progress, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn chunked_empty() -> anyhow::Result<()> {
+    let (client, _server) = serve();
+    let chunks = client.server_streaming_chunked(Blob(0)).await?;
+    let mut progress = Vec::new();
+    let data = chunks.collect_with_progress(|p| progress.push(p)).await?;
+    assert!(data.is_empty());
+    assert_eq!(
+        progress,
+        vec![Progress {
+            transferred: 0,
+            size: 0
+        }]
+    );
+    assert!(progress[0].is_done());
+    Ok(())
+}
+
+/// a server that sends more data than announced, or a second size, violates the pattern
+#[tokio::test]
+async fn chunked_protocol_violation() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::<BlobService, _>::new(listener);
+    let _server = server.spawn_accept_loop(|req, chan| async move {
+        match req {
+            BlobRequest::Blob(msg) => {
+                chan.server_streaming(msg, (), |_, Blob(size)| {
+                    let frames = match size {
+                        0 => vec![Chunk::Size(2), Chunk::Data(vec![1, 2, 3].into())],
+                        _ => vec![Chunk::Size(2), Chunk::Size(2)],
+                    };
+                    stream::iter(frames)
+                })
+                .await
+            }
+        }
+    });
+    let client = RpcClient::<BlobService, _>::new(connector);
+    for size in [0, 1] {
+        let mut chunks = client.server_streaming_chunked(Blob(size)).await?;
+        let res = chunks.next().await;
+        assert!(
+            matches!(res, Some(Err(CallError::ProtocolViolation))),
+            "{res:?}"
+        );
+        assert!(chunks.next().await.is_none());
+    }
+    Ok(())
+}
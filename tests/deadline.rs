@@ -0,0 +1,113 @@
+#![cfg(feature = "flume-transport")]
+use std::time::{Duration, Instant};
+
+use derive_more::{From, TryInto};
+use futures::{channel::mpsc, SinkExt, StreamExt};
+use quic_rpc::{
+    message::RpcMsg,
+    server::RpcServerError,
+    transport::{
+        flume,
+        metadata::{Frame, MetadataConnector, MetadataListener},
+        Connector,
+    },
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+struct SleepService;
+
+impl Service for SleepService {
+    type Req = SleepRequest;
+    type Res = SleepResponse;
+}
+
+/// sleep for the given number of milliseconds
+#[derive(Debug, Serialize, Deserialize)]
+struct Sleep(u64);
+
+impl RpcMsg<SleepService> for Sleep {
+    type Response = SleepResponse;
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum SleepRequest {
+    Sleep(Sleep),
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+struct SleepResponse;
+
+/// The deadline is a glib timer, so the test runs on a glib main context.
+#[test]
+fn deadline_propagation() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let context = glib::MainContext::new();
+    context.with_thread_default(|| {
+        context.block_on(async {
+            let (server, client) = flume::channel::<Frame<SleepRequest>, Frame<SleepResponse>>(1);
+            let server = MetadataListener::new(server);
+            let client = MetadataConnector::new(client);
+
+            let server = RpcServer::<SleepService, _>::new(server).with_deadline_cancel();
+            let (deadlines_tx, mut deadlines_rx) = mpsc::unbounded();
+            let (done_tx, mut done_rx) = mpsc::unbounded();
+            let server_handle = glib::spawn_future(async move {
+                let mut results = Vec::new();
+                for _ in 0..2 {
+                    let (req, chan) = server.accept().await?.read_first().await?;
+                    let SleepRequest::Sleep(req) = req;
+                    deadlines_tx.unbounded_send(chan.deadline()).ok();
+                    let done_tx = done_tx.clone();
+                    let res = chan
+                        .rpc(req, (), |_, req| async move {
+                            let mut done = Done(done_tx, false);
+                            glib::timeout_future(Duration::from_millis(req.0)).await;
+                            done.1 = true;
+                            SleepResponse
+                        })
+                        .await;
+                    results.push(res);
+                }
+                anyhow::Ok(results)
+            });
+            let client = RpcClient::<SleepService, _>::new(client);
+
+            // the time left of the timeout is the deadline of the server
+            let start = Instant::now();
+            let res = client
+                .rpc_with_timeout(Sleep(0), Duration::from_secs(10))
+                .await?;
+            assert_eq!(res, SleepResponse);
+            assert_eq!(done_rx.next().await, Some(true));
+            let deadline = deadlines_rx.next().await.flatten().unwrap();
+            assert!(deadline > start + Duration::from_secs(9));
+            assert!(deadline <= Instant::now() + Duration::from_secs(10));
+
+            // the server gives up on the request once the deadline has passed, even though
+            // the client keeps the channel open
+            let (mut send, mut recv) = client.as_ref().open().await?;
+            send.set_deadline(Duration::from_millis(100))?;
+            send.send(Sleep(60_000).into()).await?;
+            let res = glib::future_with_timeout(Duration::from_secs(5), recv.next()).await?;
+            assert!(res.is_none());
+            assert_eq!(done_rx.next().await, Some(false));
+            assert!(deadlines_rx.next().await.flatten().is_some());
+
+            let results = server_handle.await.expect("server task")?;
+            assert!(results[0].is_ok());
+            assert!(matches!(results[1], Err(RpcServerError::HandlerTimeout)));
+            anyhow::Ok(())
+        })
+    })?
+}
+
+/// Sends whether the handler completed, when the handler is done or dropped
+struct Done(mpsc::UnboundedSender<bool>, bool);
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        self.0.unbounded_send(self.1).ok();
+    }
+}
@@ -0,0 +1,201 @@
+#![cfg(all(feature = "macros", feature = "flume-transport"))]
+use async_stream::stream;
+use futures_lite::{Stream, StreamExt};
+use futures_util::SinkExt;
+use quic_rpc::{
+    message::{BidiStreaming, ClientStreaming, Msg, Rpc, ServerStreaming},
+    reflection::{MessageDescriptor, MessagePattern, Reflect},
+    transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Square(u64);
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Squared(u64);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Count(u64);
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Counted(u64);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Sum;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SumUpdate(u64);
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Summed(u64);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Scale(u64);
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScaleUpdate(u64);
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Scaled(u64);
+
+quic_rpc::define_service! {
+    Service = MathService;
+    Request = MathRequest;
+    Response = MathResponse;
+
+    Square: Rpc -> Squared;
+    Count: ServerStreaming -> Counted;
+    Sum: ClientStreaming<SumUpdate> -> Summed;
+    Scale: BidiStreaming<ScaleUpdate> -> Scaled;
+}
+
+/// Compile time check that the types implement the traits of their pattern
+fn assert_msg<M, P>()
+where
+    M: Msg<MathService, Pattern = P>,
+{
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Handler;
+
+impl Handler {
+    async fn square(self, Square(n): Square) -> Squared {
+        Squared(n * n)
+    }
+
+    fn count(self, Count(n): Count) -> impl Stream<Item = Counted> {
+        futures_lite::stream::iter((0..n).map(Counted))
+    }
+
+    async fn sum(self, _req: Sum, updates: impl Stream<Item = SumUpdate>) -> Summed {
+        Summed(updates.fold(0, |acc, SumUpdate(n)| acc + n).await)
+    }
+
+    fn scale(
+        self,
+        Scale(factor): Scale,
+        updates: impl Stream<Item = ScaleUpdate>,
+    ) -> impl Stream<Item = Scaled> {
+        stream! {
+            tokio::pin!(updates);
+            while let Some(ScaleUpdate(n)) = updates.next().await {
+                yield Scaled(n * factor);
+            }
+        }
+    }
+}
+
+/// Spawns a server, which is stopped when the returned handle is dropped
+fn serve() -> (
+    RpcClient<MathService, flume::FlumeConnector<MathResponse, MathRequest>>,
+    impl Sized,
+) {
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::<MathService, _>::new(listener);
+    let handle = server.spawn_accept_loop(|req, chan| async move {
+        quic_rpc::handle_request!(chan, req, Handler, {
+            MathRequest::Square => rpc(Handler::square),
+            MathRequest::Count => server_streaming(Handler::count),
+            MathRequest::Sum => client_streaming(Handler::sum),
+            MathRequest::Scale => bidi_streaming(Handler::scale),
+            MathRequest::SumUpdate | MathRequest::ScaleUpdate => unexpected,
+        })
+    });
+    (RpcClient::new(connector), handle)
+}
+
+#[test]
+fn define_service_types() {
+    assert_msg::<Square, Rpc>();
+    assert_msg::<Count, ServerStreaming>();
+    assert_msg::<Sum, ClientStreaming>();
+    assert_msg::<Scale, BidiStreaming>();
+
+    // requests and updates share the request enum
+    let req: <MathService as Service>::Req = SumUpdate(1).into();
+    assert!(matches!(req, MathRequest::SumUpdate(SumUpdate(1))));
+    let res: <MathService as Service>::Res = Scaled(2).into();
+    assert_eq!(Scaled::try_from(res).ok(), Some(Scaled(2)));
+    let res = MathResponse::from(Squared(3));
+    assert!(Counted::try_from(res).is_err());
+}
+
+#[test]
+fn define_service_descriptor() {
+    let descriptor = MathService::descriptor();
+    assert_eq!(descriptor.name, "MathService");
+    let message = |name: &str, pattern, update: Option<&str>, response: &str| MessageDescriptor {
+        name: name.into(),
+        pattern,
+        update: update.map(Into::into),
+        response: response.into(),
+    };
+    assert_eq!(
+        descriptor.messages,
+        vec![
+            message("Square", MessagePattern::Rpc, None, "Squared"),
+            message("Count", MessagePattern::ServerStreaming, None, "Counted"),
+            message(
+                "Sum",
+                MessagePattern::ClientStreaming,
+                Some("SumUpdate"),
+                "Summed"
+            ),
+            message(
+                "Scale",
+                MessagePattern::BidiStreaming,
+                Some("ScaleUpdate"),
+                "Scaled"
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn define_service_rpc() -> anyhow::Result<()> {
+    let (client, _server) = serve();
+    assert_eq!(client.rpc(Square(12)).await?, Squared(144));
+    Ok(())
+}
+
+#[tokio::test]
+async fn define_service_server_streaming() -> anyhow::Result<()> {
+    let (client, _server) = serve();
+    let items: Vec<_> = client
+        .server_streaming(Count(3))
+        .await?
+        .try_collect()
+        .await?;
+    assert_eq!(items, vec![Counted(0), Counted(1), Counted(2)]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn define_service_client_streaming() -> anyhow::Result<()> {
+    let (client, _server) = serve();
+    let (mut send, recv) = client.client_streaming(Sum).await?;
+    for i in 1..=4 {
+        send.send(SumUpdate(i)).await?;
+    }
+    drop(send);
+    assert_eq!(recv.await?, Summed(10));
+    Ok(())
+}
+
+#[tokio::test]
+async fn define_service_bidi_streaming() -> anyhow::Result<()> {
+    let (client, _server) = serve();
+    let (mut send, recv) = client.bidi(Scale(3)).await?;
+    tokio::spawn(async move {
+        for i in 1..=3 {
+            send.send(ScaleUpdate(i)).await?;
+        }
+        anyhow::Ok(())
+    });
+    let items: Vec<_> = recv.try_collect().await?;
+    assert_eq!(items, vec![Scaled(3), Scaled(6), Scaled(9)]);
+    Ok(())
+}
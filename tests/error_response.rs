@@ -0,0 +1,237 @@
+#![cfg(feature = "flume-transport")]
+use std::time::Duration;
+
+use derive_more::{From, TryInto};
+use futures::{SinkExt, StreamExt};
+use quic_rpc::{
+    client::CallError,
+    message::{ClientStreaming, ClientStreamingMsg, Msg},
+    server::{RateLimit, RpcServerError},
+    transport::{flume, ConnectionErrors},
+    Listener, RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+struct CountService;
+
+impl Service for CountService {
+    type Req = CountRequest;
+    type Res = CountResponse;
+}
+
+/// The same messages, but [Count] takes [Garbage] as updates
+#[derive(Debug, Clone)]
+struct MismatchedService;
+
+impl Service for MismatchedService {
+    type Req = CountRequest;
+    type Res = CountResponse;
+}
+
+/// Count the updates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Count;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Item;
+
+/// An update that [CountService] does not expect
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Garbage;
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum CountRequest {
+    Count(Count),
+    Item(Item),
+    Garbage(Garbage),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum CountResponse {
+    Count(Result<usize, String>),
+}
+
+impl Msg<CountService> for Count {
+    type Pattern = ClientStreaming;
+}
+
+impl ClientStreamingMsg<CountService> for Count {
+    type Update = Item;
+    type Response = Result<usize, String>;
+}
+
+impl Msg<MismatchedService> for Count {
+    type Pattern = ClientStreaming;
+}
+
+impl ClientStreamingMsg<MismatchedService> for Count {
+    type Update = Garbage;
+    type Response = Result<usize, String>;
+}
+
+fn on_error<C: ConnectionErrors>(
+    req: &CountRequest,
+) -> Option<impl FnOnce(&RpcServerError<C>) -> Option<CountResponse>> {
+    match req {
+        CountRequest::Count(_) => Some(|cause: &RpcServerError<C>| match cause {
+            RpcServerError::UnexpectedUpdateMessage | RpcServerError::RateLimited => {
+                Some(CountResponse::Count(Err(cause.to_string())))
+            }
+            _ => None,
+        }),
+        CountRequest::Item(_) | CountRequest::Garbage(_) => None,
+    }
+}
+
+/// Spawns a server, which is stopped when the returned handle is dropped
+fn serve<C: Listener<CountService>>(server: RpcServer<CountService, C>) -> impl Sized {
+    server.spawn_accept_loop(|req, chan| async move {
+        match req {
+            CountRequest::Count(req) => {
+                chan.client_streaming(
+                    req,
+                    (),
+                    |_, _, items| async move { Ok(items.count().await) },
+                )
+                .await
+            }
+            CountRequest::Item(_) | CountRequest::Garbage(_) => {
+                Err(RpcServerError::UnexpectedStartMessage)
+            }
+        }
+    })
+}
+
+#[tokio::test]
+async fn error_response_for_unexpected_update() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::new(listener).with_error_response(on_error);
+    let _server = serve(server);
+    // a successful call is not affected
+    let client = RpcClient::<CountService, _>::new(connector.clone());
+    let (mut send, recv) = client.client_streaming(Count).await?;
+    send.send(Item).await?;
+    send.send(Item).await?;
+    drop(send);
+    assert_eq!(recv.await?, Ok(2));
+    // the handler fails, and the client gets the response of the hook
+    let client = RpcClient::<MismatchedService, _>::new(connector);
+    let (mut send, recv) = client.client_streaming(Count).await?;
+    send.send(Garbage).await?;
+    let res = recv.await?;
+    assert_eq!(res, Err("unexpected update message".to_string()));
+    Ok(())
+}
+
+/// a channel over the rate limit gets the response of the hook instead of an early close
+#[tokio::test]
+async fn error_response_rate_limited() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::new(listener)
+        .with_rate_limit(RateLimit::new(1, Duration::from_secs(3600)), |_| Some(()))
+        .with_error_response(on_error);
+    let _server = serve(server);
+    let client = RpcClient::<CountService, _>::new(connector);
+    let (send, recv) = client.client_streaming(Count).await?;
+    drop(send);
+    assert_eq!(recv.await?, Ok(0));
+    let (send, recv) = client.client_streaming(Count).await?;
+    drop(send);
+    let res = recv.await?;
+    assert_eq!(res, Err("rate limit of the peer exceeded".to_string()));
+    Ok(())
+}
+
+/// the hook is kept when the listener is wrapped to observe it
+#[tokio::test]
+async fn error_response_with_lifecycle_observer() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::new(listener)
+        .with_error_response(on_error)
+        .with_lifecycle_observer(|_| {});
+    let _server = serve(server);
+    let client = RpcClient::<MismatchedService, _>::new(connector);
+    let (mut send, recv) = client.client_streaming(Count).await?;
+    send.send(Garbage).await?;
+    let res = recv.await?;
+    assert_eq!(res, Err("unexpected update message".to_string()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn no_error_response_without_hook() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel(1);
+    let _server = serve(RpcServer::new(listener));
+    let client = RpcClient::<MismatchedService, _>::new(connector);
+    let (mut send, recv) = client.client_streaming(Count).await?;
+    send.send(Garbage).await?;
+    assert!(matches!(recv.await, Err(CallError::EarlyClose)));
+    Ok(())
+}
+
+/// the hook is kept for a boxed server, and still gets the errors of the transport
+#[tokio::test]
+async fn error_response_boxed_server() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::<CountService, _>::new(listener)
+        .with_error_response(on_error)
+        .boxed();
+    let _server = serve(server);
+    let client = RpcClient::<MismatchedService, _>::new(connector);
+    let (mut send, recv) = client.client_streaming(Count).await?;
+    send.send(Garbage).await?;
+    let res = recv.await?;
+    assert_eq!(res, Err("unexpected update message".to_string()));
+    Ok(())
+}
+
+/// the hook is kept for a boxed channel
+#[tokio::test]
+async fn error_response_boxed_channel() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::<CountService, _>::new(listener).with_error_response(on_error);
+    let _server = server.spawn_accept_loop(|req, chan| async move {
+        let CountRequest::Count(req) = req else {
+            return Err(RpcServerError::UnexpectedStartMessage);
+        };
+        chan.boxed()
+            .client_streaming(
+                req,
+                (),
+                |_, _, items| async move { Ok(items.count().await) },
+            )
+            .await
+    });
+    let client = RpcClient::<MismatchedService, _>::new(connector);
+    let (mut send, recv) = client.client_streaming(Count).await?;
+    send.send(Garbage).await?;
+    let res = recv.await?;
+    assert_eq!(res, Err("unexpected update message".to_string()));
+    Ok(())
+}
+
+/// the hook is kept for a mapped channel, and sends the response of the outer service
+#[tokio::test]
+async fn error_response_mapped_channel() -> anyhow::Result<()> {
+    let (listener, connector) = flume::channel(1);
+    let server = RpcServer::<CountService, _>::new(listener).with_error_response(on_error);
+    let _server = server.spawn_accept_loop(|req, chan| async move {
+        let CountRequest::Count(req) = req else {
+            return Err(RpcServerError::UnexpectedStartMessage);
+        };
+        chan.map::<CountService>()
+            .client_streaming(
+                req,
+                (),
+                |_, _, items| async move { Ok(items.count().await) },
+            )
+            .await
+    });
+    let client = RpcClient::<MismatchedService, _>::new(connector);
+    let (mut send, recv) = client.client_streaming(Count).await?;
+    send.send(Garbage).await?;
+    let res = recv.await?;
+    assert_eq!(res, Err("unexpected update message".to_string()));
+    Ok(())
+}
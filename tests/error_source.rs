@@ -0,0 +1,85 @@
+#![cfg(feature = "flume-transport")]
+use std::error::Error;
+
+use quic_rpc::{
+    client::CallError,
+    server::RpcServerError,
+    transport::{flume, Connector},
+    RpcClient, RpcServer,
+};
+
+mod math;
+use math::*;
+
+/// The messages of an error and its sources, outermost first
+fn chain(error: &dyn Error) -> Vec<String> {
+    let mut chain = vec![error.to_string()];
+    let mut source = error.source();
+    while let Some(cause) = source {
+        chain.push(cause.to_string());
+        source = cause.source();
+    }
+    chain
+}
+
+#[tokio::test]
+async fn call_error_source() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    drop(server);
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let res = client.rpc(Sqr(2)).await;
+    let Err(err @ CallError::Open(flume::OpenError::RemoteDropped)) = res else {
+        panic!("{res:?}");
+    };
+    assert_eq!(
+        chain(&err),
+        [
+            "failed to open a substream".to_string(),
+            flume::OpenError::RemoteDropped.to_string(),
+        ]
+    );
+    // the source shows up when the error is reported with anyhow
+    let report = format!("{:#}", anyhow::Error::from(err));
+    assert!(report.ends_with(&flume::OpenError::RemoteDropped.to_string()), "{report}");
+    Ok(())
+}
+
+/// the transport errors of a boxed connector are `anyhow::Error`s, which are sources too
+#[tokio::test]
+async fn boxed_call_error_source() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    drop(server);
+    let client = RpcClient::<ComputeService, _>::new(client.boxed());
+    let res = client.rpc(Sqr(2)).await;
+    let Err(err @ CallError::Open(_)) = res else {
+        panic!("{res:?}");
+    };
+    assert_eq!(
+        chain(&err),
+        [
+            "failed to open a substream".to_string(),
+            flume::OpenError::RemoteDropped.to_string(),
+        ]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn server_error_source() -> anyhow::Result<()> {
+    let (server, client) = flume::channel(1);
+    drop(client);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let Err(err @ RpcServerError::Accept(flume::AcceptError::RemoteDropped)) =
+        server.accept().await
+    else {
+        panic!("accepted a channel without a client");
+    };
+    let source = err.source().expect("no source");
+    assert_eq!(
+        source.to_string(),
+        flume::AcceptError::RemoteDropped.to_string()
+    );
+    // the transport error has no source of its own
+    assert!(source.source().is_none());
+    Ok(())
+}
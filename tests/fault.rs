@@ -0,0 +1,347 @@
+#![cfg(feature = "flume-transport")]
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use quic_rpc::{
+    client::CallError,
+    pattern::rpc::RetryPolicy,
+    transport::{
+        fault::{self, FaultInjector, FaultPolicy, OpenFault, RecvFault, Repeat, SendFault},
+        flume::{self, FlumeConnector},
+    },
+    RpcClient, RpcServer,
+};
+
+mod math;
+use math::*;
+
+type Client =
+    RpcClient<ComputeService, FaultInjector<FlumeConnector<ComputeResponse, ComputeRequest>>>;
+
+/// Run `test` on a glib main context
+///
+/// The servers are spawned with glib, and the delays of the fault injector and the
+/// timeouts of the client are glib timers.
+fn run(test: impl Future<Output = anyhow::Result<()>>) -> anyhow::Result<()> {
+    let context = glib::MainContext::new();
+    context.with_thread_default(|| context.block_on(test))?
+}
+
+/// A compute server, which is stopped when the returned handle is dropped, and a client
+/// that injects the faults of `policy`
+fn setup(policy: FaultPolicy) -> (impl Sized, Client) {
+    let (server, client) = flume::channel(1);
+    let server_handle = ComputeService::server(RpcServer::new(server));
+    let client = RpcClient::new(FaultInjector::new(client, policy));
+    (server_handle, client)
+}
+
+#[test]
+fn fault_fail_open() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    run(async {
+        let policy = FaultPolicy::default().open(OpenFault::Fail, Repeat::Times(2));
+        let (_server_handle, client) = setup(policy);
+        let res = client.rpc(Sqr(2)).await;
+        assert!(
+            matches!(res, Err(CallError::Open(fault::OpenError::Injected))),
+            "{res:?}"
+        );
+        // the second open fails as well, the retry succeeds
+        let retry = RetryPolicy::default().initial_delay(Duration::ZERO);
+        let res = client.rpc_with_retry(Sqr(2), retry).await?;
+        assert_eq!(res, SqrResponse(4));
+        Ok(())
+    })
+}
+
+#[test]
+fn fault_delay() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    run(async {
+        let delay = Duration::from_millis(50);
+        let policy = FaultPolicy::default()
+            .open(OpenFault::Delay(delay), Repeat::Times(1))
+            .send(SendFault::Delay(delay), Repeat::Forever);
+        let (_server_handle, client) = setup(policy);
+        let res = client.rpc_with_timeout(Sqr(2), delay / 5).await;
+        assert!(
+            matches!(res, Err(CallError::Timeout { sent: false })),
+            "{res:?}"
+        );
+        let start = Instant::now();
+        let res = client.rpc(Sqr(2)).await?;
+        assert_eq!(res, SqrResponse(4));
+        assert!(start.elapsed() >= delay);
+        Ok(())
+    })
+}
+
+#[test]
+fn fault_drop() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    run(async {
+        let timeout = Duration::from_millis(50);
+        let policy = FaultPolicy::default()
+            .send(SendFault::Drop, Repeat::Times(1))
+            .recv(RecvFault::Pass, Repeat::Times(1))
+            .recv(RecvFault::Drop, Repeat::Times(1));
+        let (_server_handle, client) = setup(policy);
+        // the request is dropped
+        let res = client.rpc_with_timeout(Sqr(2), timeout).await;
+        assert!(
+            matches!(res, Err(CallError::Timeout { sent: true })),
+            "{res:?}"
+        );
+        // the response is received
+        let res = client.rpc_with_timeout(Sqr(2), timeout).await?;
+        assert_eq!(res, SqrResponse(4));
+        // the response is dropped
+        let res = client.rpc_with_timeout(Sqr(2), timeout).await;
+        assert!(
+            matches!(res, Err(CallError::Timeout { sent: true })),
+            "{res:?}"
+        );
+        Ok(())
+    })
+}
+
+#[test]
+fn fault_corrupt() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    run(async {
+        let policy = FaultPolicy::default().recv(RecvFault::Corrupt, Repeat::Times(1));
+        let (_server_handle, client) = setup(policy);
+        let res = client.rpc(Sqr(2)).await;
+        assert!(
+            matches!(res, Err(CallError::Recv(fault::RecvError::Corrupt))),
+            "{res:?}"
+        );
+        let err = res.unwrap_err();
+        assert_eq!(err.to_string(), "failed to receive a response");
+        let source = std::error::Error::source(&err).expect("no source");
+        assert_eq!(source.to_string(), "Corrupt");
+        let res = client.rpc(Sqr(2)).await?;
+        assert_eq!(res, SqrResponse(4));
+        Ok(())
+    })
+}
+
+#[test]
+fn fault_reset() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    run(async {
+        let policy = FaultPolicy::default()
+            .send(SendFault::Reset, Repeat::Times(1))
+            .recv(RecvFault::Reset, Repeat::Times(1));
+        let (_server_handle, client) = setup(policy.clone());
+        let res = client.rpc(Sqr(2)).await;
+        assert!(
+            matches!(res, Err(CallError::Send(fault::SendError::Reset))),
+            "{res:?}"
+        );
+        // a reset of the receive side is an early close
+        let res = client.rpc(Sqr(2)).await;
+        assert!(matches!(res, Err(CallError::EarlyClose)), "{res:?}");
+        // faults can be added while the injector is in use
+        policy.recv(RecvFault::Reset, Repeat::Times(1));
+        let res = client.rpc(Sqr(2)).await;
+        assert!(matches!(res, Err(CallError::EarlyClose)), "{res:?}");
+        let res = client.rpc(Sqr(2)).await?;
+        assert_eq!(res, SqrResponse(4));
+        Ok(())
+    })
+}
+
+/// a resumable stream continues after a reset, without duplicate or skipped items
+#[test]
+fn fault_resume_server_streaming() -> anyhow::Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    use derive_more::{From, TryInto};
+    use futures::TryStreamExt;
+    use quic_rpc::{
+        message::{Msg, ServerStreaming, ServerStreamingMsg},
+        pattern::server_streaming::{ResumableResponse, Resume},
+        server::RpcServerError,
+        Service,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct Count(u64);
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum Request {
+        Count(Count),
+        Resume(Resume),
+    }
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum Response {
+        Value(u64),
+        Item(ResumableResponse<u64>),
+    }
+    #[derive(Debug, Clone)]
+    struct CountService;
+    impl Service for CountService {
+        type Req = Request;
+        type Res = Response;
+    }
+    impl Msg<CountService> for Count {
+        type Pattern = ServerStreaming;
+    }
+    impl ServerStreamingMsg<CountService> for Count {
+        type Response = u64;
+    }
+
+    tracing_subscriber::fmt::try_init().ok();
+    run(async {
+        let (server, client) = flume::channel(1);
+        let resumes = Arc::new(Mutex::new(Vec::new()));
+        let server = RpcServer::<CountService, _>::new(server);
+        let handler_resumes = resumes.clone();
+        let _server_handle = server.spawn_accept_loop(move |req, chan| {
+            let resumes = handler_resumes.clone();
+            async move {
+                match req {
+                    Request::Count(req) => {
+                        chan.server_streaming_resumable(
+                            req,
+                            resumes,
+                            |resumes, Count(n), resume| {
+                                resumes.lock().unwrap().push(resume);
+                                futures::stream::iter(resume.next_seq()..n)
+                            },
+                        )
+                        .await
+                    }
+                    Request::Resume(_) => Err(RpcServerError::UnexpectedStartMessage),
+                }
+            }
+        });
+
+        // the 6th response is lost with a reset of the stream
+        let policy = FaultPolicy::default()
+            .recv(RecvFault::Pass, Repeat::Times(5))
+            .recv(RecvFault::Reset, Repeat::Times(1));
+        let client = RpcClient::<CountService, _>::new(FaultInjector::new(client, policy));
+        let retry = RetryPolicy::default().initial_delay(Duration::ZERO);
+        let items: Vec<u64> = client
+            .server_streaming_resumable(Count(20), retry)
+            .await?
+            .try_collect()
+            .await?;
+        assert_eq!(items, (0..20).collect::<Vec<_>>());
+        assert_eq!(
+            *resumes.lock().unwrap(),
+            [Resume { last: None }, Resume { last: Some(4) }]
+        );
+        Ok(())
+    })
+}
+
+/// a retried idempotent request carries the same key, so the server handles it once
+#[test]
+fn fault_retry_idempotent() -> anyhow::Result<()> {
+    use std::{
+        collections::HashMap,
+        sync::{Arc, Mutex},
+    };
+
+    use derive_more::{From, TryInto};
+    use quic_rpc::{
+        message::RpcMsg,
+        pattern::rpc::{IdempotencyKey, Idempotent},
+        Service,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct Add(u64);
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum Request {
+        Add(Add),
+        Idempotent(Idempotent<Add>),
+    }
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum Response {
+        Total(u64),
+    }
+    #[derive(Debug, Clone)]
+    struct TotalService;
+    impl Service for TotalService {
+        type Req = Request;
+        type Res = Response;
+    }
+    impl RpcMsg<TotalService> for Add {
+        type Response = u64;
+    }
+
+    /// The total, and the answers to the requests that were handled
+    #[derive(Debug, Default)]
+    struct Total {
+        total: u64,
+        answered: HashMap<IdempotencyKey, u64>,
+    }
+
+    tracing_subscriber::fmt::try_init().ok();
+    run(async {
+        let (server, client) = flume::channel(1);
+        let total = Arc::new(Mutex::new(Total::default()));
+        let server = RpcServer::<TotalService, _>::new(server);
+        let handler_total = total.clone();
+        let _server_handle = server.spawn_accept_loop(move |req, chan| {
+            let total = handler_total.clone();
+            async move {
+                match req {
+                    Request::Add(req) => {
+                        chan.rpc(req, total, |total, Add(n)| async move {
+                            let mut total = total.lock().unwrap();
+                            total.total += n;
+                            total.total
+                        })
+                        .await
+                    }
+                    Request::Idempotent(req) => {
+                        chan.rpc(
+                            req,
+                            total,
+                            |total, Idempotent { key, msg: Add(n) }| async move {
+                                let mut total = total.lock().unwrap();
+                                if let Some(answer) = total.answered.get(&key) {
+                                    return *answer;
+                                }
+                                total.total += n;
+                                let answer = total.total;
+                                total.answered.insert(key, answer);
+                                answer
+                            },
+                        )
+                        .await
+                    }
+                }
+            }
+        });
+
+        // the first response is lost after the server handled the request
+        let policy = FaultPolicy::default().recv(RecvFault::Reset, Repeat::Times(1));
+        let client = RpcClient::<TotalService, _>::new(FaultInjector::new(client, policy));
+        let retry = RetryPolicy::default().initial_delay(Duration::ZERO);
+        let req = Idempotent::new(Add(5));
+        assert_ne!(req.key, Idempotent::new(Add(5)).key);
+        let res = client.rpc_with_retry(req, retry.clone()).await?;
+        assert_eq!(res, 5);
+        {
+            let total = total.lock().unwrap();
+            assert_eq!(total.total, 5);
+            assert_eq!(total.answered.len(), 1);
+        }
+        // a new request with the same payload has a new key
+        let res = client
+            .rpc_with_retry(Idempotent::new(Add(5)), retry)
+            .await?;
+        assert_eq!(res, 10);
+        Ok(())
+    })
+}
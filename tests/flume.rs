@@ -21,6 +21,72 @@ async fn flume_channel_bench() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// same as flume_channel_bench, but with boxed client and server, to compare the overhead
+#[tokio::test]
+async fn flume_channel_boxed_bench() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<ComputeService, _>::new(server).boxed();
+    let _server_handle = AbortOnDropHandle::new(tokio::spawn(ComputeService::server(server)));
+    let client = RpcClient::<ComputeService, _>::new(client).boxed();
+    bench(client, 1000000).await?;
+    Ok(())
+}
+
+/// boxing the client and server does not add much to the latency of a sequential rpc
+/// with a small message
+///
+/// The accept loop spawns its handlers on glib, so the calls run on a glib main context.
+#[test]
+fn flume_channel_boxed_overhead() -> anyhow::Result<()> {
+    use std::time::{Duration, Instant};
+
+    use quic_rpc::Connector;
+
+    /// The average time of a sequential rpc, after a warm up
+    async fn latency<C: Connector<ComputeService>>(
+        client: RpcClient<ComputeService, C>,
+    ) -> anyhow::Result<Duration> {
+        const N: u64 = 10_000;
+        for i in 0..1000 {
+            client.rpc(Sqr(i)).await?;
+        }
+        let t0 = Instant::now();
+        for i in 0..N {
+            assert_eq!(
+                client.rpc(Sqr(i)).await?,
+                SqrResponse(i as u128 * i as u128)
+            );
+        }
+        Ok(t0.elapsed() / N as u32)
+    }
+
+    tracing_subscriber::fmt::try_init().ok();
+    let context = glib::MainContext::new();
+    context.with_thread_default(|| {
+        context.block_on(async {
+            let (server, client) = flume::channel(1);
+            let server = RpcServer::<ComputeService, _>::new(server);
+            let _server_handle = ComputeService::server(server);
+            let unboxed = latency(RpcClient::<ComputeService, _>::new(client)).await?;
+
+            let (server, client) = flume::channel(1);
+            let server = RpcServer::<ComputeService, _>::new(server).boxed();
+            let _server_handle = ComputeService::server(server);
+            let boxed = latency(RpcClient::<ComputeService, _>::new(client).boxed()).await?;
+
+            println!("rpc latency unboxed {unboxed:?}, boxed {boxed:?}");
+            // generous, so a busy machine does not make this fail
+            assert!(
+                boxed <= unboxed * 2 + Duration::from_micros(50),
+                "boxed {boxed:?} vs unboxed {unboxed:?}"
+            );
+            anyhow::Ok(())
+        })
+    })?
+}
+
 #[tokio::test]
 async fn flume_channel_mapped_bench() -> anyhow::Result<()> {
     use derive_more::{From, TryInto};
@@ -101,3 +167,1429 @@ async fn flume_channel_smoke() -> anyhow::Result<()> {
     smoke_test(client).await?;
     Ok(())
 }
+
+/// batching responses does not change what the client receives
+#[tokio::test]
+async fn flume_channel_response_batch() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<ComputeService, _>::new(server).with_response_batch(8);
+    let _server_handle = ComputeService::server(server);
+    smoke_test(client).await?;
+    Ok(())
+}
+
+/// a client that does not read its responses holds on to the memory budget, which stops
+/// the handlers of other channels until it is released
+#[tokio::test]
+async fn flume_memory_budget() -> anyhow::Result<()> {
+    use std::time::Duration;
+
+    use futures_lite::StreamExt;
+    use quic_rpc::budget::MemoryBudget;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    // enough for a single response
+    let budget = MemoryBudget::new(20);
+    let server = RpcServer::<ComputeService, _>::new(server).with_memory_budget(budget.clone());
+    let _server_handle = ComputeService::server(server);
+    let client = RpcClient::<ComputeService, _>::new(client.with_stream_capacity(1));
+
+    // the first response fills the stream, the second one waits for the client
+    let stalled = client.server_streaming(Fibonacci(50)).await?;
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while budget.used() == 0 {
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+    })
+    .await?;
+    assert!(budget.used() <= budget.limit());
+
+    let mut items = client.server_streaming(Fibonacci(10)).await?;
+    let next = tokio::time::timeout(Duration::from_millis(100), items.next()).await;
+    assert!(next.is_err());
+
+    drop(stalled);
+    let items: Vec<_> = items.map(|x| x.map(|x| x.0)).try_collect().await?;
+    assert_eq!(items, vec![0, 1, 1, 2, 3, 5, 8, 13, 21, 34]);
+    assert_eq!(budget.used(), 0);
+    Ok(())
+}
+
+/// the target bound to the server is passed to every handler
+#[tokio::test]
+async fn flume_channel_with_target() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<ComputeService, _>::new(server).with_target(ComputeService);
+    let _server_handle = server.spawn_accept_loop_with_target(ComputeService::handle_rpc_request);
+    smoke_test(client).await?;
+    Ok(())
+}
+
+/// channels beyond the rate limit of a peer are rejected
+#[tokio::test]
+async fn flume_rate_limit() -> anyhow::Result<()> {
+    use std::time::Duration;
+
+    use quic_rpc::{client::CallError, server::RateLimit};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    // flume channels have no remote address, so all of them count for the same peer
+    let server = RpcServer::<ComputeService, _>::new(server)
+        .with_rate_limit(RateLimit::new(3, Duration::from_secs(3600)), |_| Some(()));
+    let _server_handle = ComputeService::server(server);
+    let client = RpcClient::<ComputeService, _>::new(client);
+    for i in 0..3 {
+        assert_eq!(
+            client.rpc(Sqr(i)).await?,
+            SqrResponse(i as u128 * i as u128)
+        );
+    }
+    let res = client.rpc(Sqr(3)).await;
+    assert!(matches!(res, Err(CallError::EarlyClose)), "{res:?}");
+    Ok(())
+}
+
+/// the reconnecting connector replaces the inner connector when opening fails
+#[tokio::test]
+async fn flume_channel_reconnecting() -> anyhow::Result<()> {
+    use quic_rpc::transport::reconnecting::{BackoffPolicy, ReconnectingConnector};
+    use std::time::Duration;
+
+    tracing_subscriber::fmt::try_init().ok();
+    // a connector whose listener is gone, so every open fails
+    let (_, dead) = flume::channel(1);
+    let (server, live) = flume::channel(1);
+
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let _server_handle = AbortOnDropHandle::new(tokio::spawn(ComputeService::server(server)));
+    let mut connectors = vec![dead, live].into_iter();
+    let backoff = BackoffPolicy::default()
+        .initial_delay(Duration::ZERO)
+        .max_attempts(2);
+    let client = ReconnectingConnector::new(backoff, move || {
+        let next = connectors.next();
+        async move { Ok::<_, flume::OpenError>(next.expect("no more connectors")) }
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let res = client.rpc(Sqr(4)).await?;
+    assert_eq!(res, SqrResponse(16));
+    Ok(())
+}
+
+/// the reconnecting connector waits for the backoff delay, growing by the multiplier,
+/// between attempts
+///
+/// The delay is a glib timer, so the call runs on a glib main context.
+#[test]
+fn flume_channel_reconnecting_backoff() -> anyhow::Result<()> {
+    use std::time::{Duration, Instant};
+
+    use quic_rpc::transport::reconnecting::{BackoffPolicy, ReconnectingConnector};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let context = glib::MainContext::new();
+    context.with_thread_default(|| {
+        context.block_on(async {
+            // connectors whose listener is gone, so every open fails
+            let dead = (0..3).map(|_| flume::channel(1).1);
+            let (server, live) = flume::channel(1);
+
+            let server = RpcServer::<ComputeService, _>::new(server);
+            let _server_handle = ComputeService::server(server);
+            let mut connectors = dead.chain([live]).collect::<Vec<_>>().into_iter();
+            let backoff = BackoffPolicy::default()
+                .initial_delay(Duration::from_millis(50))
+                .multiplier(2)
+                .max_attempts(4);
+            let client = ReconnectingConnector::new(backoff, move || {
+                let next = connectors.next();
+                async move { Ok::<_, flume::OpenError>(next.expect("no more connectors")) }
+            });
+            let client = RpcClient::<ComputeService, _>::new(client);
+            let start = Instant::now();
+            let res = client.rpc(Sqr(4)).await?;
+            assert_eq!(res, SqrResponse(16));
+            // 50ms, 100ms and 200ms before the second, third and fourth attempt
+            assert!(start.elapsed() >= Duration::from_millis(350));
+            anyhow::Ok(())
+        })
+    })?
+}
+
+/// a slow consumer stops a fast server streaming producer once the channel is full
+#[tokio::test]
+async fn flume_stream_capacity() -> anyhow::Result<()> {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use futures::StreamExt;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let client = client.with_stream_capacity(2);
+
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let produced = Arc::new(AtomicUsize::new(0));
+    let produced2 = produced.clone();
+    let _server_handle = AbortOnDropHandle::new(tokio::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let ComputeRequest::Fibonacci(req) = req else {
+            panic!("unexpected request {req:?}");
+        };
+        chan.server_streaming(req, (), move |_, _| {
+            futures::stream::repeat(FibonacciResponse(0)).inspect(move |_| {
+                produced2.fetch_add(1, Ordering::SeqCst);
+            })
+        })
+        .await
+    }));
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let mut stream = client.server_streaming(Fibonacci(0)).await?;
+    for received in 1..=10 {
+        // waiting for an item lets the producer run until the channel is full
+        assert!(stream.next().await.is_some());
+        // the buffered items, plus the ones held by the sink and the handler
+        assert!(produced.load(Ordering::SeqCst) <= received + 4);
+    }
+    Ok(())
+}
+
+/// the server only sends as many responses ahead as the client granted credits
+#[tokio::test]
+async fn flume_server_streaming_credits() -> anyhow::Result<()> {
+    use std::sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    };
+
+    use derive_more::{From, TryInto};
+    use futures::{StreamExt, TryStreamExt};
+    use quic_rpc::{
+        message::{Msg, ServerStreaming, ServerStreamingMsg},
+        pattern::server_streaming::{Credit, CreditWindow},
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Count(u64);
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum Request {
+        Count(Count),
+        Credit(Credit),
+    }
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum Response {
+        Value(u64),
+    }
+    #[derive(Debug, Clone)]
+    struct CountService;
+    impl Service for CountService {
+        type Req = Request;
+        type Res = Response;
+    }
+    impl Msg<CountService> for Count {
+        type Pattern = ServerStreaming;
+    }
+    impl ServerStreamingMsg<CountService> for Count {
+        type Response = u64;
+    }
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<CountService, _>::new(server);
+    let produced = Arc::new(AtomicU64::new(0));
+    let handler_produced = produced.clone();
+    let _server_handle = server.spawn_accept_loop(move |req, chan| {
+        let produced = handler_produced.clone();
+        async move {
+            match req {
+                Request::Count(req) => {
+                    chan.server_streaming_with_credits(req, produced, |produced, Count(n)| {
+                        futures::stream::iter(0..n).inspect(move |_| {
+                            produced.fetch_add(1, Ordering::SeqCst);
+                        })
+                    })
+                    .await
+                }
+                Request::Credit(_) => Err(RpcServerError::UnexpectedStartMessage),
+            }
+        }
+    });
+    let client = RpcClient::<CountService, _>::new(client);
+    let window = CreditWindow::default().initial(4).refill(2);
+
+    // a slow consumer holds the server back, even though the channel has room
+    let mut stream = client
+        .server_streaming_with_credits(Count(u64::MAX), window)
+        .await?;
+    for received in 1..=20 {
+        assert!(stream.next().await.transpose()?.is_some());
+        assert!(produced.load(Ordering::SeqCst) <= received + 4);
+    }
+    drop(stream);
+
+    // all responses arrive, with credits granted along the way
+    let items: Vec<u64> = client
+        .server_streaming_with_credits(Count(100), window)
+        .await?
+        .try_collect()
+        .await?;
+    assert_eq!(items, (0..100).collect::<Vec<_>>());
+    Ok(())
+}
+
+/// dropping the response stream on the client side fires the cancellation signal
+#[tokio::test]
+async fn flume_server_streaming_cancel() -> anyhow::Result<()> {
+    use futures::{channel::oneshot, StreamExt};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let (cancelled_tx, cancelled_rx) = oneshot::channel();
+    let server_handle = tokio::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let ComputeRequest::Fibonacci(req) = req else {
+            panic!("unexpected request {req:?}");
+        };
+        chan.server_streaming_with_cancel(req, (), move |_, req, cancelled| {
+            tokio::spawn(async move {
+                cancelled.await;
+                cancelled_tx.send(()).ok();
+            });
+            futures::stream::repeat(FibonacciResponse(req.0 as u128))
+        })
+        .await
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let mut stream = client.server_streaming(Fibonacci(1)).await?;
+    assert!(stream.next().await.is_some());
+    drop(stream);
+    tokio::time::timeout(std::time::Duration::from_secs(5), cancelled_rx).await??;
+    server_handle.await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn flume_server_streaming_handle_cancel() -> anyhow::Result<()> {
+    use futures::{channel::oneshot, StreamExt};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let (cancelled_tx, cancelled_rx) = oneshot::channel();
+    let server_handle = tokio::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let ComputeRequest::Fibonacci(req) = req else {
+            panic!("unexpected request {req:?}");
+        };
+        chan.server_streaming_with_cancel(req, (), move |_, req, cancelled| {
+            tokio::spawn(async move {
+                cancelled.await;
+                cancelled_tx.send(()).ok();
+            });
+            futures::stream::repeat(FibonacciResponse(req.0 as u128))
+        })
+        .await
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let (handle, mut stream) = client.server_streaming_with_handle(Fibonacci(1)).await?;
+    assert!(stream.next().await.is_some());
+    // the response stream is still alive, the handle alone cancels the call
+    handle.cancel().await?;
+    tokio::time::timeout(std::time::Duration::from_secs(5), cancelled_rx).await??;
+    server_handle.await??;
+    while stream.next().await.is_some() {}
+    Ok(())
+}
+
+/// responses pushed by several tasks are all sent, and pushing fails once the client is gone
+#[tokio::test]
+async fn flume_server_streaming_with_sender() -> anyhow::Result<()> {
+    use futures::{StreamExt, TryStreamExt};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let (closed_tx, mut closed_rx) = tokio::sync::mpsc::unbounded_channel();
+    let server_handle = tokio::spawn(async move {
+        for _ in 0..2 {
+            let (req, chan) = server.accept().await?.read_first().await?;
+            let ComputeRequest::Fibonacci(req) = req else {
+                panic!("unexpected request {req:?}");
+            };
+            let closed_tx = closed_tx.clone();
+            chan.server_streaming_with_sender(req, (), move |_, Fibonacci(n), sender| async move {
+                for worker in 0..2 {
+                    let sender = sender.clone();
+                    let closed_tx = closed_tx.clone();
+                    tokio::spawn(async move {
+                        for i in 0..n {
+                            let item = FibonacciResponse((worker * n + i) as u128);
+                            if sender.send(item).await.is_err() {
+                                closed_tx.send(worker).ok();
+                                return;
+                            }
+                        }
+                    });
+                }
+            })
+            .await?;
+        }
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let stream = client.server_streaming(Fibonacci(10)).await?;
+    let mut items = stream
+        .map(|item| item.map(|FibonacciResponse(x)| x))
+        .try_collect::<Vec<_>>()
+        .await?;
+    items.sort();
+    assert_eq!(items, (0..20).collect::<Vec<_>>());
+
+    // these workers do not stop by themselves, only once the client is gone
+    let mut stream = client.server_streaming(Fibonacci(u64::MAX / 2)).await?;
+    assert!(stream.next().await.is_some());
+    drop(stream);
+    server_handle.await??;
+    let mut closed = Vec::new();
+    for _ in 0..2 {
+        let worker = tokio::time::timeout(std::time::Duration::from_secs(5), closed_rx.recv());
+        closed.push(worker.await?.expect("worker reports"));
+    }
+    closed.sort();
+    assert_eq!(closed, vec![0, 1]);
+    Ok(())
+}
+
+/// a handler that sends its responses in a loop, and stops once the client is gone
+#[tokio::test]
+async fn flume_server_streaming_generator() -> anyhow::Result<()> {
+    use futures::{StreamExt, TryStreamExt};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let (stopped_tx, stopped_rx) = tokio::sync::oneshot::channel::<()>();
+    let server_handle = tokio::spawn(async move {
+        let mut stopped_tx = Some(stopped_tx);
+        for _ in 0..2 {
+            let (req, chan) = server.accept().await?.read_first().await?;
+            let ComputeRequest::Fibonacci(req) = req else {
+                panic!("unexpected request {req:?}");
+            };
+            let stopped_tx = stopped_tx.take();
+            chan.server_streaming_generator(req, (), move |_, Fibonacci(n), sender| async move {
+                // dropped once the handler stops
+                let _stopped_tx = stopped_tx;
+                let (mut a, mut b) = (0u128, 1u128);
+                for _ in 0..n {
+                    tokio::task::yield_now().await;
+                    sender.send(FibonacciResponse(a)).await?;
+                    (a, b) = (b, a.wrapping_add(b));
+                }
+                anyhow::Ok(())
+            })
+            .await?;
+        }
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let mut stream = client.server_streaming(Fibonacci(u64::MAX)).await?;
+    assert!(stream.next().await.is_some());
+    drop(stream);
+
+    let stream = client.server_streaming(Fibonacci(6)).await?;
+    let items = stream
+        .map(|item| item.map(|FibonacciResponse(x)| x))
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(items, vec![0, 1, 1, 2, 3, 5]);
+    server_handle.await??;
+    // the first handler was stopped, even though it would send forever
+    tokio::time::timeout(std::time::Duration::from_secs(5), stopped_rx)
+        .await?
+        .ok();
+    Ok(())
+}
+
+/// an error returned by a generator handler is reported by the server
+#[tokio::test]
+async fn flume_server_streaming_generator_error() -> anyhow::Result<()> {
+    use futures::{StreamExt, TryStreamExt};
+    use quic_rpc::pattern::server_streaming::GeneratorError;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let server_handle = tokio::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let ComputeRequest::Fibonacci(req) = req else {
+            panic!("unexpected request {req:?}");
+        };
+        let res = chan
+            .server_streaming_generator(req, (), move |_, Fibonacci(n), sender| async move {
+                sender.send(FibonacciResponse(0)).await?;
+                sender.send(FibonacciResponse(1)).await?;
+                anyhow::ensure!(n <= 2, "too many numbers: {n}");
+                Ok(())
+            })
+            .await;
+        let Err(GeneratorError::Handler(cause)) = res else {
+            panic!("unexpected result {res:?}");
+        };
+        assert_eq!(cause.to_string(), "too many numbers: 3");
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let stream = client.server_streaming(Fibonacci(3)).await?;
+    let items = stream
+        .map(|item| item.map(|FibonacciResponse(x)| x))
+        .try_collect::<Vec<_>>()
+        .await?;
+    assert_eq!(items, vec![0, 1]);
+    server_handle.await??;
+    Ok(())
+}
+
+/// the header is sent once before the responses of a server streaming call
+#[tokio::test]
+async fn flume_server_streaming_with_header() -> anyhow::Result<()> {
+    use derive_more::{From, TryInto};
+    use futures::StreamExt;
+    use quic_rpc::message::{Msg, ServerStreaming, ServerStreamingHeaderMsg, ServerStreamingMsg};
+    use serde::{Deserialize, Serialize};
+
+    tracing_subscriber::fmt::try_init().ok();
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct List(u64);
+    #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+    struct ListHeader {
+        count: u64,
+    }
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum ListRequest {
+        List(List),
+    }
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum ListResponse {
+        Header(ListHeader),
+        Item(u64),
+    }
+    #[derive(Debug, Clone)]
+    struct ListService;
+    impl Service for ListService {
+        type Req = ListRequest;
+        type Res = ListResponse;
+    }
+    impl Msg<ListService> for List {
+        type Pattern = ServerStreaming;
+    }
+    impl ServerStreamingMsg<ListService> for List {
+        type Response = u64;
+    }
+    impl ServerStreamingHeaderMsg<ListService> for List {
+        type Header = ListHeader;
+    }
+
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ListService, _>::new(server);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        let ListRequest::List(req) = req;
+        chan.server_streaming_with_header(req, (), |_, List(n)| async move {
+            (ListHeader { count: n }, futures::stream::iter(0..n))
+        })
+        .await
+    });
+    let client = RpcClient::<ListService, _>::new(client);
+    let (header, items) = client.server_streaming_with_header(List(3)).await?;
+    assert_eq!(header, ListHeader { count: 3 });
+    let items = items.map(|item| item.unwrap()).collect::<Vec<_>>().await;
+    assert_eq!(items, vec![0, 1, 2]);
+    Ok(())
+}
+
+/// a stream of updates can be forwarded into the update sink, which closes it at the end
+#[tokio::test]
+async fn flume_client_streaming_forward() -> anyhow::Result<()> {
+    use futures::{SinkExt, StreamExt};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let _server_handle = AbortOnDropHandle::new(tokio::spawn(ComputeService::server(server)));
+    let client = RpcClient::<ComputeService, _>::new(client);
+    for capacity in [0, 4] {
+        let (send, recv) = client.client_streaming_with_capacity(Sum, capacity).await?;
+        let updates = futures::stream::iter(1..=10).map(anyhow::Ok);
+        // map plain numbers to updates with a sink combinator
+        let send = send.with(|n| futures::future::ok(SumUpdate(n)));
+        updates.forward(send).await?;
+        assert_eq!(recv.await?, SumResponse(55));
+    }
+    Ok(())
+}
+
+/// closing the update sink ends the update stream on the server, while responses still arrive
+#[tokio::test]
+async fn flume_bidi_half_close() -> anyhow::Result<()> {
+    use futures::{SinkExt, StreamExt};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let _server_handle = AbortOnDropHandle::new(tokio::spawn(ComputeService::server(server)));
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let (mut send, recv) = client.bidi(Multiply(2)).await?;
+    for i in 1..=3 {
+        send.send(MultiplyUpdate(i)).await?;
+    }
+    send.close().await?;
+    let responses = recv.map(|x| x.map(|x| x.0)).collect::<Vec<_>>().await;
+    let responses = responses.into_iter().collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(responses, vec![2, 4, 6]);
+    Ok(())
+}
+
+/// updates beyond the limit of an update stream either wait, or terminate the call
+#[tokio::test]
+async fn flume_update_limit() -> anyhow::Result<()> {
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt};
+    use quic_rpc::server::UpdateOverflow;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let server_handle = tokio::spawn(async move {
+        // with backpressure, the reader stops at the limit and the handler still gets
+        // all updates
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let ComputeRequest::Multiply(req) = req else {
+            panic!("unexpected request {req:?}");
+        };
+        chan.bidi_streaming(req, (), |_, Multiply(n), updates| {
+            let mut updates = updates.with_update_limit(4, UpdateOverflow::Backpressure);
+            async_stream::stream! {
+                while updates.buffered_len() < 4 {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                assert_eq!(updates.buffered_len(), 4);
+                while let Some(MultiplyUpdate(m)) = updates.next().await {
+                    yield MultiplyResponse(n as u128 * m as u128);
+                }
+            }
+        })
+        .await?;
+        // with an error, a flood of updates terminates the call
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let ComputeRequest::Multiply(req) = req else {
+            panic!("unexpected request {req:?}");
+        };
+        let res = chan
+            .bidi_streaming(req, (), |_, _, updates| {
+                let updates = updates.with_update_limit(4, UpdateOverflow::Error);
+                async_stream::stream! {
+                    let _updates = updates;
+                    std::future::pending::<()>().await;
+                    yield MultiplyResponse(0);
+                }
+            })
+            .await;
+        anyhow::Ok(res)
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+
+    let (mut send, recv) = client.bidi(Multiply(2)).await?;
+    for i in 1..=10 {
+        send.send(MultiplyUpdate(i)).await?;
+    }
+    send.close().await?;
+    let responses = recv.map(|x| x.map(|x| x.0)).collect::<Vec<_>>().await;
+    let responses = responses.into_iter().collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(responses, (1..=10).map(|i| i * 2).collect::<Vec<_>>());
+
+    let (mut send, mut recv) = client.bidi(Multiply(2)).await?;
+    for i in 1..=10 {
+        send.send(MultiplyUpdate(i)).await?;
+    }
+    let res = tokio::time::timeout(Duration::from_secs(5), server_handle).await???;
+    assert!(
+        matches!(res, Err(RpcServerError::TooManyUpdates)),
+        "{res:?}"
+    );
+    assert!(recv.next().await.is_none());
+    Ok(())
+}
+
+/// the server metrics count handled requests per pattern
+#[tokio::test]
+async fn flume_server_metrics() -> anyhow::Result<()> {
+    use futures::StreamExt;
+    use quic_rpc::metrics::{Pattern, ServerMetrics};
+    use std::sync::Arc;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let metrics = Arc::new(ServerMetrics::default());
+    let server = RpcServer::<ComputeService, _>::new(server).with_metrics(metrics.clone());
+    let _server_handle = AbortOnDropHandle::new(tokio::spawn(ComputeService::server(server)));
+    let client = RpcClient::<ComputeService, _>::new(client);
+    for i in 0..3 {
+        client.rpc(Sqr(i)).await?;
+    }
+    let stream = client.server_streaming(Fibonacci(10)).await?;
+    assert_eq!(stream.count().await, 10);
+    // the handler finishes after the client has seen the end of the stream
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert_eq!(metrics.pattern(Pattern::Rpc).requests.load(), 3);
+    assert_eq!(metrics.pattern(Pattern::ServerStreaming).requests.load(), 1);
+    assert_eq!(metrics.pattern(Pattern::Rpc).errors.load(), 0);
+    assert_eq!(metrics.in_flight.load(), 0);
+    Ok(())
+}
+
+/// a slow server slows down a client producing updates
+#[tokio::test]
+async fn flume_update_backpressure() -> anyhow::Result<()> {
+    use futures::{SinkExt, StreamExt};
+    use quic_rpc::client::TrySendError;
+    use std::{
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let received = Arc::new(AtomicU64::new(0));
+    let server_received = received.clone();
+    let server_handle = tokio::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let ComputeRequest::Sum(req) = req else {
+            panic!("unexpected request {req:?}");
+        };
+        chan.client_streaming(req, server_received, |received, _, updates| async move {
+            tokio::pin!(updates);
+            let mut sum = 0u128;
+            while let Some(SumUpdate(n)) = updates.next().await {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+                received.fetch_add(1, Ordering::SeqCst);
+                sum += n as u128;
+            }
+            SumResponse(sum)
+        })
+        .await
+    });
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let capacity = 4;
+    let (mut send, recv) = client.client_streaming_with_capacity(Sum, capacity).await?;
+    // updates can only be buffered in the update sink, in the substream of the flume
+    // transport and in the handler
+    let slack = capacity as u64 + 128 + 2;
+    let n = 300;
+    for i in 0..n {
+        send.send(SumUpdate(i)).await?;
+        assert!(received.load(Ordering::SeqCst) + slack > i);
+    }
+    // the buffer fills up quickly, since the server takes a while for each update
+    let mut full = None;
+    for i in n..n + 10 {
+        match send.try_send(SumUpdate(i)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(update)) => {
+                full = Some(update.0);
+                break;
+            }
+            Err(TrySendError::Send(cause)) => return Err(cause.into()),
+        }
+    }
+    let full = full.expect("sink never got full");
+    assert!(full <= n + capacity as u64 + 1);
+    send.close().await?;
+    let res = recv.await?;
+    assert_eq!(res, SumResponse((0..full as u128).sum()));
+    server_handle.await??;
+    Ok(())
+}
+
+/// a server that responds to a client streaming request early stops reading updates
+#[tokio::test]
+async fn flume_client_streaming_early_response() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    early_response_test(server, client).await
+}
+
+/// rpc_with_retry retries failed opens, with a fresh substream for each attempt
+///
+/// The delay between attempts is a glib timer, so the call runs on a glib main context.
+#[test]
+fn flume_rpc_with_retry() -> anyhow::Result<()> {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    };
+
+    use quic_rpc::{
+        pattern::rpc::RetryPolicy,
+        transport::{ConnectionErrors, Connector, StreamTypes},
+    };
+
+    /// A connector where the first `fail` opens fail
+    #[derive(Debug, Clone)]
+    struct Flaky {
+        inner: flume::FlumeConnector<ComputeResponse, ComputeRequest>,
+        opens: Arc<AtomicUsize>,
+        fail: usize,
+    }
+
+    impl ConnectionErrors for Flaky {
+        type SendError = flume::SendError;
+        type RecvError = flume::RecvError;
+        type OpenError = flume::OpenError;
+        type AcceptError = flume::AcceptError;
+    }
+
+    impl StreamTypes for Flaky {
+        type In = ComputeResponse;
+        type Out = ComputeRequest;
+        type SendSink = flume::SendSink<ComputeRequest>;
+        type RecvStream = flume::RecvStream<ComputeResponse>;
+    }
+
+    impl Connector for Flaky {
+        async fn open(&self) -> Result<(Self::SendSink, Self::RecvStream), Self::OpenError> {
+            if self.opens.fetch_add(1, Ordering::SeqCst) < self.fail {
+                return Err(flume::OpenError::RemoteDropped);
+            }
+            self.inner.open().await
+        }
+    }
+
+    tracing_subscriber::fmt::try_init().ok();
+    let context = glib::MainContext::new();
+    context.with_thread_default(|| {
+        context.block_on(async {
+            let (server, client) = flume::channel(1);
+
+            let server = RpcServer::<ComputeService, _>::new(server);
+            let _server_handle = ComputeService::server(server);
+            let opens = Arc::new(AtomicUsize::new(0));
+            let client = RpcClient::<ComputeService, _>::new(Flaky {
+                inner: client,
+                opens: opens.clone(),
+                fail: 2,
+            });
+            let policy = RetryPolicy::default()
+                .initial_delay(Duration::from_millis(1))
+                .max_attempts(3);
+            let res = client.rpc_with_retry(Sqr(12), policy).await?;
+            assert_eq!(res, SqrResponse(144));
+            assert_eq!(opens.load(Ordering::SeqCst), 3);
+            anyhow::Ok(())
+        })
+    })?
+}
+
+/// the item timeout resets on every item, and fires when the server stalls
+///
+/// The item timer is a glib timer, so the call runs on a glib main context.
+#[test]
+fn flume_server_streaming_item_timeout() -> anyhow::Result<()> {
+    use std::time::Duration;
+
+    use futures::StreamExt;
+    use quic_rpc::pattern::server_streaming::ItemError;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let context = glib::MainContext::new();
+    context.with_thread_default(|| {
+        context.block_on(async {
+            let (server, client) = flume::channel(1);
+
+            let server = RpcServer::<ComputeService, _>::new(server);
+            let _server_handle = glib::spawn_future(async move {
+                let (req, chan) = server.accept().await?.read_first().await?;
+                let ComputeRequest::Fibonacci(req) = req else {
+                    panic!("unexpected request {req:?}");
+                };
+                chan.server_streaming(req, (), |_, _| {
+                    // three items, 100ms apart, then stall
+                    futures::stream::unfold(0u128, |i| async move {
+                        glib::timeout_future(Duration::from_millis(100)).await;
+                        Some((FibonacciResponse(i), i + 1))
+                    })
+                    .take(3)
+                    .chain(futures::stream::pending())
+                })
+                .await?;
+                anyhow::Ok(())
+            });
+            let client = RpcClient::<ComputeService, _>::new(client);
+            let mut items = client
+                .server_streaming_with_item_timeout(Fibonacci(10), Duration::from_millis(250))
+                .await?;
+            // the whole call takes longer than the item timeout
+            for i in 0..3 {
+                let FibonacciResponse(item) = items.next().await.expect("item")?;
+                assert_eq!(item, i);
+            }
+            assert!(matches!(items.next().await, Some(Err(ItemError::Timeout))));
+            assert!(items.next().await.is_none());
+            anyhow::Ok(())
+        })
+    })?
+}
+
+/// a bidi call that stays active outlives its idle timeout, an idle one ends
+///
+/// The idle timer is a glib timer, so the call runs on a glib main context.
+#[test]
+fn flume_bidi_idle_timeout() -> anyhow::Result<()> {
+    use std::time::Duration;
+
+    use futures::{SinkExt, StreamExt};
+    use quic_rpc::client::{CallError, UpdateError};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let context = glib::MainContext::new();
+    context.with_thread_default(|| {
+        context.block_on(async {
+            let (server, client) = flume::channel(1);
+
+            let server = RpcServer::<ComputeService, _>::new(server);
+            let _server_handle = ComputeService::server(server);
+            let client = RpcClient::<ComputeService, _>::new(client);
+            let idle = Duration::from_millis(250);
+            let (mut send, mut recv) = client.bidi_with_idle_timeout(Multiply(2), idle).await?;
+            // the whole call takes longer than the idle timeout
+            for i in 0..6 {
+                glib::timeout_future(Duration::from_millis(100)).await;
+                send.send(MultiplyUpdate(i)).await?;
+                let MultiplyResponse(item) = recv.next().await.expect("item")?;
+                assert_eq!(item, 2 * i as u128);
+            }
+            // no activity on either side
+            let res = glib::future_with_timeout(Duration::from_secs(5), recv.next()).await?;
+            assert!(matches!(res, Some(Err(CallError::Idle))));
+            assert!(recv.next().await.is_none());
+            assert!(matches!(
+                send.send(MultiplyUpdate(1)).await,
+                Err(UpdateError::Idle)
+            ));
+            anyhow::Ok(())
+        })
+    })?
+}
+
+/// a batch of updates is sent while the responses arrive, an empty batch has none
+#[tokio::test]
+async fn flume_batch_server_streaming() -> anyhow::Result<()> {
+    use futures::TryStreamExt;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<ComputeService, _>::new(server);
+    let _server_handle = ComputeService::server(server);
+    let client = RpcClient::<ComputeService, _>::new(client);
+    // more updates than the channels buffer, so sending and receiving have to overlap
+    let updates = (0..100).map(MultiplyUpdate);
+    let items: Vec<_> = client
+        .batch_server_streaming(Multiply(3), updates)
+        .await?
+        .map_ok(|MultiplyResponse(item)| item)
+        .try_collect()
+        .await?;
+    assert_eq!(items, (0..100).map(|i| 3 * i).collect::<Vec<_>>());
+    let items: Vec<_> = client
+        .batch_server_streaming(Multiply(3), [])
+        .await?
+        .try_collect()
+        .await?;
+    assert!(items.is_empty());
+    Ok(())
+}
+
+/// a balanced connector spreads requests over its backends in turn
+#[tokio::test]
+async fn flume_balanced_round_robin() -> anyhow::Result<()> {
+    use quic_rpc::{
+        metrics::{Pattern, ServerMetrics},
+        transport::balanced::{BalancedConnector, Strategy},
+    };
+    use std::sync::Arc;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let mut connectors = Vec::new();
+    let mut metrics = Vec::new();
+    let mut handles = Vec::new();
+    for _ in 0..3 {
+        let (server, client) = flume::channel(1);
+        let m = Arc::new(ServerMetrics::default());
+        let server = RpcServer::<ComputeService, _>::new(server).with_metrics(m.clone());
+        handles.push(AbortOnDropHandle::new(tokio::spawn(
+            ComputeService::server(server),
+        )));
+        connectors.push(client);
+        metrics.push(m);
+    }
+    let client = RpcClient::<ComputeService, _>::new(BalancedConnector::new(
+        connectors,
+        Strategy::RoundRobin,
+    ));
+    for i in 0..6 {
+        assert_eq!(
+            client.rpc(Sqr(i)).await?,
+            SqrResponse(i as u128 * i as u128)
+        );
+    }
+    for m in &metrics {
+        assert_eq!(m.pattern(Pattern::Rpc).requests.load(), 2);
+    }
+    Ok(())
+}
+
+/// a balanced connector picks the backend with the fewest open channels
+#[tokio::test]
+async fn flume_balanced_least_in_flight() -> anyhow::Result<()> {
+    use quic_rpc::transport::{
+        balanced::{BalancedConnector, Strategy},
+        Connector,
+    };
+
+    tracing_subscriber::fmt::try_init().ok();
+    // the channels are never accepted, so the listeners buffer them
+    let (_server_a, a) = flume::channel::<ComputeRequest, ComputeResponse>(8);
+    let (_server_b, b) = flume::channel::<ComputeRequest, ComputeResponse>(8);
+    let client = BalancedConnector::new(vec![a, b], Strategy::LeastInFlight);
+    let first = client.open().await?;
+    let second = client.open().await?;
+    let third = client.open().await?;
+    assert_eq!(client.in_flight(), vec![2, 1]);
+    // a channel counts until both of its halves are dropped
+    let (second_send, second_recv) = second;
+    drop(second_send);
+    assert_eq!(client.in_flight(), vec![2, 1]);
+    drop(second_recv);
+    assert_eq!(client.in_flight(), vec![2, 0]);
+    // round robin would pick the busier backend for the last one
+    let fourth = client.open().await?;
+    let fifth = client.open().await?;
+    assert_eq!(client.in_flight(), vec![2, 2]);
+    drop((first, third, fourth, fifth));
+    assert_eq!(client.in_flight(), vec![0, 0]);
+    Ok(())
+}
+
+/// a balanced connector skips backends that can not open a channel
+#[tokio::test]
+async fn flume_balanced_failover() -> anyhow::Result<()> {
+    use quic_rpc::{
+        metrics::{Pattern, ServerMetrics},
+        transport::balanced::{BalancedConnector, Strategy},
+    };
+    use std::sync::Arc;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (gone, gone_client) = flume::channel(1);
+    drop(gone);
+    let (server, client) = flume::channel(1);
+    let metrics = Arc::new(ServerMetrics::default());
+    let server = RpcServer::<ComputeService, _>::new(server).with_metrics(metrics.clone());
+    let _server_handle = AbortOnDropHandle::new(tokio::spawn(ComputeService::server(server)));
+    let client = RpcClient::<ComputeService, _>::new(BalancedConnector::new(
+        vec![gone_client, client],
+        Strategy::RoundRobin,
+    ));
+    for i in 0..4 {
+        assert_eq!(
+            client.rpc(Sqr(i)).await?,
+            SqrResponse(i as u128 * i as u128)
+        );
+    }
+    assert_eq!(metrics.pattern(Pattern::Rpc).requests.load(), 4);
+    Ok(())
+}
+
+/// handlers of the accept loop run on the configured spawner
+#[tokio::test]
+async fn flume_custom_spawner() -> anyhow::Result<()> {
+    use futures::future::BoxFuture;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let spawned = Arc::new(AtomicUsize::new(0));
+    let server = RpcServer::<ComputeService, _>::new(server).with_spawner({
+        let spawned = spawned.clone();
+        move |task: BoxFuture<'static, ()>| {
+            spawned.fetch_add(1, Ordering::SeqCst);
+            tokio::spawn(task);
+        }
+    });
+    let _server_handle =
+        AbortOnDropHandle::new(tokio::spawn(server.accept_loop(|req, chan| {
+            ComputeService::handle_rpc_request(ComputeService, req, chan)
+        })));
+    let client = RpcClient::<ComputeService, _>::new(client);
+    for i in 0..3 {
+        assert_eq!(
+            client.rpc(Sqr(i)).await?,
+            SqrResponse(i as u128 * i as u128)
+        );
+    }
+    assert_eq!(spawned.load(Ordering::SeqCst), 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn flume_server_streaming_collect() -> anyhow::Result<()> {
+    use quic_rpc::client::ResponseStreamExt;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let _server_handle = ComputeService::server(RpcServer::new(server));
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let fib: [u128; 10] = [0, 1, 1, 2, 3, 5, 8, 13, 21, 34];
+
+    let items = client
+        .server_streaming(Fibonacci(10))
+        .await?
+        .collect_vec()
+        .await;
+    let items = items
+        .into_iter()
+        .map(|item| item.map(|FibonacciResponse(x)| x))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(items, fib);
+
+    let items = client
+        .server_streaming(Fibonacci(10))
+        .await?
+        .try_collect_vec()
+        .await?;
+    assert_eq!(items.len(), fib.len());
+
+    let sum = client
+        .server_streaming(Fibonacci(10))
+        .await?
+        .fold_ok(0, |sum, FibonacciResponse(x)| sum + x)
+        .await?;
+    assert_eq!(sum, fib.iter().sum::<u128>());
+
+    // the first error ends the aggregation
+    let items = futures::stream::iter([Ok(1), Err("first"), Ok(2), Err("second")]);
+    assert_eq!(items.clone().try_collect_vec().await, Err("first"));
+    assert_eq!(items.clone().fold_ok(0, |a, b| a + b).await, Err("first"));
+    assert_eq!(items.collect_vec().await.len(), 4);
+    Ok(())
+}
+
+/// the server acks each update on the substream of the request, before the response
+#[tokio::test]
+async fn flume_client_streaming_with_acks() -> anyhow::Result<()> {
+    use derive_more::{From, TryInto};
+    use futures::{SinkExt, StreamExt};
+    use quic_rpc::{
+        message::{ClientStreaming, ClientStreamingMsg, Msg},
+        pattern::client_streaming::AckedResponse,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Upload;
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Chunk(Vec<u8>);
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum UploadRequest {
+        Upload(Upload),
+        Chunk(Chunk),
+    }
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum UploadResponse {
+        Size(usize),
+        Acked(AckedResponse<usize>),
+    }
+    #[derive(Debug, Clone)]
+    struct UploadService;
+    impl Service for UploadService {
+        type Req = UploadRequest;
+        type Res = UploadResponse;
+    }
+    impl Msg<UploadService> for Upload {
+        type Pattern = ClientStreaming;
+    }
+    impl ClientStreamingMsg<UploadService> for Upload {
+        type Update = Chunk;
+        type Response = usize;
+    }
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<UploadService, _>::new(server);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        let UploadRequest::Upload(req) = req else {
+            return Err(RpcServerError::UnexpectedStartMessage);
+        };
+        chan.client_streaming_with_acks(req, (), |_, _, chunks, acks| async move {
+            let mut size = 0;
+            let mut chunks = chunks.enumerate();
+            while let Some((seq, Chunk(data))) = chunks.next().await {
+                size += data.len();
+                // only every other chunk is checkpointed
+                if seq % 2 == 1 {
+                    acks.ack(seq as u64);
+                }
+            }
+            size
+        })
+        .await
+    });
+
+    let client = RpcClient::<UploadService, _>::new(client);
+    let (mut send, acks, size) = client.client_streaming_with_acks(Upload).await?;
+    let upload = async move {
+        for i in 0..5 {
+            send.send(Chunk(vec![0; i])).await?;
+        }
+        send.close().await?;
+        anyhow::Ok(())
+    };
+    let (res, acks) = tokio::join!(upload, acks.collect::<Vec<_>>());
+    res?;
+    assert_eq!(acks, [1, 3]);
+    assert_eq!(size.await?, 10);
+    Ok(())
+}
+
+/// the server aborts an upload, and the client reads the reason from the update sink
+#[tokio::test]
+async fn flume_client_streaming_abortable() -> anyhow::Result<()> {
+    use derive_more::{From, TryInto};
+    use futures::{SinkExt, StreamExt};
+    use quic_rpc::{
+        client::{CallError, UpdateError},
+        message::{ClientStreaming, ClientStreamingAbortMsg, ClientStreamingMsg, Msg},
+        pattern::client_streaming::AbortableResponse,
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Upload;
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Chunk(Vec<u8>);
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    enum Rejected {
+        QuotaExceeded { limit: usize },
+    }
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum UploadRequest {
+        Upload(Upload),
+        Chunk(Chunk),
+    }
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum UploadResponse {
+        Size(usize),
+        Upload(AbortableResponse<usize, Rejected>),
+    }
+    #[derive(Debug, Clone)]
+    struct UploadService;
+    impl Service for UploadService {
+        type Req = UploadRequest;
+        type Res = UploadResponse;
+    }
+    impl Msg<UploadService> for Upload {
+        type Pattern = ClientStreaming;
+    }
+    impl ClientStreamingMsg<UploadService> for Upload {
+        type Update = Chunk;
+        type Response = usize;
+    }
+    impl ClientStreamingAbortMsg<UploadService> for Upload {
+        type Abort = Rejected;
+    }
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<UploadService, _>::new(server);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        let UploadRequest::Upload(req) = req else {
+            return Err(RpcServerError::UnexpectedStartMessage);
+        };
+        chan.client_streaming_abortable(req, (), |_, _, mut chunks| async move {
+            let mut size = 0;
+            let mut count = 0;
+            while let Some(Chunk(data)) = chunks.next().await {
+                size += data.len();
+                count += 1;
+                if count == 5 {
+                    return Err(Rejected::QuotaExceeded { limit: 5 });
+                }
+            }
+            Ok(size)
+        })
+        .await
+    });
+
+    let client = RpcClient::<UploadService, _>::new(client);
+    let (mut send, size) = client.client_streaming_abortable(Upload).await?;
+    let mut sent = 0;
+    let err = loop {
+        if let Err(cause) = send.send(Chunk(vec![0; 16])).await {
+            break cause;
+        }
+        sent += 1;
+        assert!(sent < 1000, "upload was not aborted");
+    };
+    assert!(sent >= 5);
+    let reason = Rejected::QuotaExceeded { limit: 5 };
+    match err {
+        UpdateError::Aborted(r) => assert_eq!(r, reason),
+        err => panic!("unexpected error {err:?}"),
+    }
+    match size.await {
+        Err(CallError::App(r)) => assert_eq!(r, reason),
+        res => panic!("unexpected result {res:?}"),
+    }
+    Ok(())
+}
+
+/// a client gets the messages of a service from the server
+#[tokio::test]
+async fn flume_describe() -> anyhow::Result<()> {
+    use derive_more::{From, TryInto};
+    use quic_rpc::{
+        message::RpcMsg,
+        reflection::{Describe, MessageDescriptor, MessagePattern, Reflect, ServiceDescriptor},
+    };
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Ping;
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Pong;
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum Request {
+        Ping(Ping),
+        Describe(Describe),
+    }
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    enum Response {
+        Pong(Pong),
+        Descriptor(ServiceDescriptor),
+    }
+    #[derive(Debug, Clone)]
+    struct PingService;
+    impl Service for PingService {
+        type Req = Request;
+        type Res = Response;
+    }
+    impl RpcMsg<PingService> for Ping {
+        type Response = Pong;
+    }
+    impl RpcMsg<PingService> for Describe {
+        type Response = ServiceDescriptor;
+    }
+    impl Reflect for PingService {
+        fn descriptor() -> ServiceDescriptor {
+            let message = |name: &str, response: &str| MessageDescriptor {
+                name: name.into(),
+                pattern: MessagePattern::Rpc,
+                update: None,
+                response: response.into(),
+            };
+            ServiceDescriptor {
+                name: "PingService".into(),
+                messages: vec![
+                    message("Ping", "Pong"),
+                    message("Describe", "ServiceDescriptor"),
+                ],
+            }
+        }
+    }
+
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<PingService, _>::new(server);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        match req {
+            Request::Ping(req) => chan.rpc(req, (), |_, _| async { Pong }).await,
+            Request::Describe(req) => chan.describe(req).await,
+        }
+    });
+    let client = RpcClient::<PingService, _>::new(client);
+    let descriptor = client.describe().await?;
+    assert_eq!(descriptor, PingService::descriptor());
+    assert_eq!(
+        descriptor.schema_hash(),
+        PingService::descriptor().schema_hash()
+    );
+    // the hash changes with the messages
+    let mut changed = descriptor.clone();
+    changed.messages[0].pattern = MessagePattern::ServerStreaming;
+    assert_ne!(changed.schema_hash(), descriptor.schema_hash());
+    Ok(())
+}
+
+/// messages are encrypted between the wrappers, and a client with another key is rejected
+#[cfg(feature = "encrypted-transport")]
+#[tokio::test]
+async fn flume_encrypted() -> anyhow::Result<()> {
+    use quic_rpc::transport::encrypted::{EncryptedConnector, EncryptedListener, EncryptionKey};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let key = EncryptionKey::generate();
+    let (server, client) = flume::channel::<Vec<u8>, Vec<u8>>(1);
+    let server = EncryptedListener::<ComputeRequest, ComputeResponse, _>::new(server, key.clone());
+    let _server_handle = ComputeService::server(RpcServer::new(server));
+
+    let connector = EncryptedConnector::<ComputeResponse, ComputeRequest, _>::new(client, key);
+    smoke_test(connector.clone()).await?;
+    let client = RpcClient::<ComputeService, _>::new(connector.clone());
+    for i in 0..10u64 {
+        assert_eq!(
+            client.rpc(Sqr(i)).await?,
+            SqrResponse(i as u128 * i as u128)
+        );
+    }
+
+    let other = EncryptedConnector::<ComputeResponse, ComputeRequest, _>::new(
+        connector.into_inner(),
+        EncryptionKey::generate(),
+    );
+    let client = RpcClient::<ComputeService, _>::new(other);
+    assert!(client.rpc(Sqr(2)).await.is_err());
+    Ok(())
+}
@@ -0,0 +1,129 @@
+#![cfg(feature = "flume-transport")]
+use std::time::Duration;
+
+use derive_more::{From, TryInto};
+use futures::{channel::mpsc, StreamExt};
+use quic_rpc::{
+    client::CallError, message::RpcMsg, metrics::Pattern, server::RpcServerError, transport::flume,
+    RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+struct SleepService;
+
+impl Service for SleepService {
+    type Req = SleepRequest;
+    type Res = SleepResponse;
+}
+
+/// sleep for the given number of milliseconds
+#[derive(Debug, Serialize, Deserialize)]
+struct Sleep(u64);
+
+impl RpcMsg<SleepService> for Sleep {
+    type Response = Slept;
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+struct Slept;
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum SleepRequest {
+    Sleep(Sleep),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum SleepResponse {
+    Slept(Slept),
+}
+
+/// The handler timer is a glib timer, so the test runs on a glib main context.
+#[test]
+fn handler_timeout() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let context = glib::MainContext::new();
+    context.with_thread_default(|| {
+        context.block_on(async {
+            let (server, client) = flume::channel(1);
+
+            let server = RpcServer::<SleepService, _>::new(server)
+                .with_handler_timeout(Duration::from_millis(100));
+            let (done_tx, mut done_rx) = mpsc::unbounded();
+            let server_handle = glib::spawn_future(async move {
+                let mut results = Vec::new();
+                for _ in 0..2 {
+                    let (req, chan) = server.accept().await?.read_first().await?;
+                    let SleepRequest::Sleep(req) = req;
+                    let done_tx = done_tx.clone();
+                    let res = chan
+                        .rpc(req, (), |_, req| async move {
+                            let mut done = Done(done_tx, false);
+                            glib::timeout_future(Duration::from_millis(req.0)).await;
+                            done.1 = true;
+                            Slept
+                        })
+                        .await;
+                    results.push(res);
+                }
+                anyhow::Ok(results)
+            });
+            let client = RpcClient::<SleepService, _>::new(client);
+
+            // completes within the limit
+            assert_eq!(client.rpc(Sleep(0)).await?, Slept);
+            assert_eq!(done_rx.next().await, Some(true));
+
+            // the handler is cancelled once the limit has passed. flume can not cancel the
+            // channel, so the client sees it closing early
+            let res = glib::future_with_timeout(Duration::from_secs(5), client.rpc(Sleep(60_000)))
+                .await?;
+            assert!(matches!(res, Err(CallError::EarlyClose)));
+            assert_eq!(done_rx.next().await, Some(false));
+
+            let results = server_handle.await.expect("server task")?;
+            assert!(results[0].is_ok());
+            assert!(matches!(results[1], Err(RpcServerError::HandlerTimeout)));
+            anyhow::Ok(())
+        })
+    })?
+}
+
+/// The limit of a pattern wins over the one for all patterns, in any order
+#[test]
+fn pattern_timeout_overrides_default() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let context = glib::MainContext::new();
+    context.with_thread_default(|| {
+        context.block_on(async {
+            let (server, client) = flume::channel(1);
+
+            let server = RpcServer::<SleepService, _>::new(server)
+                .with_pattern_timeout(Pattern::Rpc, None)
+                .with_handler_timeout(Duration::from_millis(10));
+            let server_handle = glib::spawn_future(async move {
+                let (req, chan) = server.accept().await?.read_first().await?;
+                let SleepRequest::Sleep(req) = req;
+                chan.rpc(req, (), |_, req| async move {
+                    glib::timeout_future(Duration::from_millis(req.0)).await;
+                    Slept
+                })
+                .await?;
+                anyhow::Ok(())
+            });
+            let client = RpcClient::<SleepService, _>::new(client);
+            assert_eq!(client.rpc(Sleep(100)).await?, Slept);
+            server_handle.await.expect("server task")?;
+            anyhow::Ok(())
+        })
+    })?
+}
+
+/// Sends whether the handler completed, when the handler is done or dropped
+struct Done(mpsc::UnboundedSender<bool>, bool);
+
+impl Drop for Done {
+    fn drop(&mut self) {
+        self.0.unbounded_send(self.1).ok();
+    }
+}
@@ -0,0 +1,172 @@
+#![cfg(any(
+    feature = "flume-transport",
+    feature = "quinn-transport",
+    feature = "iroh-net-transport",
+))]
+#![allow(dead_code)]
+use std::time::Duration;
+
+use async_stream::stream;
+use derive_more::{From, TryInto};
+use futures::{channel::mpsc, StreamExt};
+use quic_rpc::{
+    message::{
+        BidiStreaming, BidiStreamingMsg, ClientStreaming, ClientStreamingMsg, Msg, RpcMsg,
+        ServerStreaming, ServerStreamingMsg,
+    },
+    server::RpcServerError,
+    Listener, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+use tokio_util::task::AbortOnDropHandle;
+
+#[derive(Debug, Clone)]
+pub struct HangService;
+
+impl Service for HangService {
+    type Req = HangRequest;
+    type Res = HangResponse;
+}
+
+/// A call for every pattern, none of them ever completes
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HangRpc;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HangServerStreaming;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HangClientStreaming;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HangBidi;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HangUpdate;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Hung;
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+pub enum HangRequest {
+    HangRpc(HangRpc),
+    HangServerStreaming(HangServerStreaming),
+    HangClientStreaming(HangClientStreaming),
+    HangBidi(HangBidi),
+    HangUpdate(HangUpdate),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+pub enum HangResponse {
+    Hung(Hung),
+}
+
+impl RpcMsg<HangService> for HangRpc {
+    type Response = Hung;
+}
+
+impl Msg<HangService> for HangServerStreaming {
+    type Pattern = ServerStreaming;
+}
+
+impl ServerStreamingMsg<HangService> for HangServerStreaming {
+    type Response = Hung;
+}
+
+impl Msg<HangService> for HangClientStreaming {
+    type Pattern = ClientStreaming;
+}
+
+impl ClientStreamingMsg<HangService> for HangClientStreaming {
+    type Update = HangUpdate;
+    type Response = Hung;
+}
+
+impl Msg<HangService> for HangBidi {
+    type Pattern = BidiStreaming;
+}
+
+impl BidiStreamingMsg<HangService> for HangBidi {
+    type Update = HangUpdate;
+    type Response = Hung;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Event {
+    Started,
+    Dropped,
+}
+
+/// Reports when a handler starts, and when it is dropped
+pub struct Guard(mpsc::UnboundedSender<Event>);
+
+impl Guard {
+    pub fn new(events: mpsc::UnboundedSender<Event>) -> Self {
+        events.unbounded_send(Event::Started).ok();
+        Self(events)
+    }
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        self.0.unbounded_send(Event::Dropped).ok();
+    }
+}
+
+/// Spawns a server on `listener` whose handlers hang forever, which is stopped when the
+/// returned handle is dropped
+pub fn serve<L: Listener<HangService>>(
+    listener: L,
+) -> (mpsc::UnboundedReceiver<Event>, AbortOnDropHandle<()>) {
+    let (events_tx, events_rx) = mpsc::unbounded();
+    let server = RpcServer::<HangService, _>::new(listener);
+    let handle = server.spawn_accept_loop(move |req, chan| {
+        let events = events_tx.clone();
+        async move {
+            match req {
+                HangRequest::HangRpc(msg) => {
+                    chan.rpc(msg, events, |events, _| async move {
+                        let _guard = Guard::new(events);
+                        std::future::pending().await
+                    })
+                    .await
+                }
+                HangRequest::HangServerStreaming(msg) => {
+                    chan.server_streaming(msg, events, |events, _| {
+                        stream! {
+                            let _guard = Guard::new(events);
+                            std::future::pending::<()>().await;
+                            yield Hung;
+                        }
+                    })
+                    .await
+                }
+                HangRequest::HangClientStreaming(msg) => {
+                    chan.client_streaming(msg, events, |events, _, _updates| async move {
+                        let _guard = Guard::new(events);
+                        std::future::pending().await
+                    })
+                    .await
+                }
+                HangRequest::HangBidi(msg) => {
+                    chan.bidi_streaming(msg, events, |events, _, _updates| {
+                        stream! {
+                            let _guard = Guard::new(events);
+                            std::future::pending::<()>().await;
+                            yield Hung;
+                        }
+                    })
+                    .await
+                }
+                HangRequest::HangUpdate(_) => Err(RpcServerError::UnexpectedStartMessage),
+            }
+        }
+    });
+    (events_rx, handle)
+}
+
+/// Waits for the next handler event, failing if there is none within a few seconds
+pub async fn next_event(events: &mut mpsc::UnboundedReceiver<Event>) -> anyhow::Result<Event> {
+    let event = tokio::time::timeout(Duration::from_secs(5), events.next()).await?;
+    event.ok_or_else(|| anyhow::anyhow!("server stopped"))
+}
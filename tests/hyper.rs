@@ -5,6 +5,7 @@ use ::hyper::Uri;
 use derive_more::{From, TryInto};
 use flume::Receiver;
 use quic_rpc::{
+    client::CallError,
     declare_rpc,
     server::RpcServerError,
     transport::hyper::{self, HyperConnector, HyperListener, RecvError},
@@ -238,9 +239,7 @@ async fn hyper_channel_errors() -> anyhow::Result<()> {
     let res = client.rpc(BigRequest(vec![0; 20_000_000])).await;
     assert_matches!(
         res,
-        Err(quic_rpc::pattern::rpc::Error::Send(
-            hyper::SendError::SizeError(_)
-        ))
+        Err(CallError::Send(hyper::SendError::FrameTooLarge { .. }))
     );
     assert_server_result!(Err(RpcServerError::EarlyClose));
 
@@ -248,34 +247,27 @@ async fn hyper_channel_errors() -> anyhow::Result<()> {
     let res = client.rpc(NoSerRequest(NoSer)).await;
     assert_matches!(
         res,
-        Err(quic_rpc::pattern::rpc::Error::Send(
-            hyper::SendError::SerializeError(_)
-        ))
+        Err(CallError::Send(hyper::SendError::SerializeError(..)))
     );
     assert_server_result!(Err(RpcServerError::EarlyClose));
 
     // not deserializable - should fail on the server side
     let res = client.rpc(NoDeserRequest(NoDeser)).await;
-    assert_matches!(res, Err(quic_rpc::pattern::rpc::Error::EarlyClose));
+    assert_matches!(res, Err(CallError::EarlyClose));
     assert_server_result!(Err(RpcServerError::RecvError(
-        hyper::RecvError::DeserializeError(_)
+        hyper::RecvError::DeserializeError(..)
     )));
 
     // response not serializable - should fail on the server side
     let res = client.rpc(NoSerResponseRequest).await;
-    assert_matches!(res, Err(quic_rpc::pattern::rpc::Error::EarlyClose));
+    assert_matches!(res, Err(CallError::EarlyClose));
     assert_server_result!(Err(RpcServerError::SendError(
-        hyper::SendError::SerializeError(_)
+        hyper::SendError::SerializeError(..)
     )));
 
     // response not deserializable - should succeed on the server side fail on the client side
     let res = client.rpc(NoDeserResponseRequest).await;
-    assert_matches!(
-        res,
-        Err(quic_rpc::pattern::rpc::Error::RecvError(
-            RecvError::DeserializeError(_)
-        ))
-    );
+    assert_matches!(res, Err(CallError::Recv(RecvError::DeserializeError(..))));
     assert_server_result!(Ok(()));
 
     // response small - should succeed
@@ -285,12 +277,144 @@ async fn hyper_channel_errors() -> anyhow::Result<()> {
 
     // response big - should fail
     let res = client.rpc(BigResponseRequest(20_000_000)).await;
-    assert_matches!(res, Err(quic_rpc::pattern::rpc::Error::EarlyClose));
-    assert_server_result!(Err(RpcServerError::SendError(hyper::SendError::SizeError(
-        _
-    ))));
+    assert_matches!(res, Err(CallError::EarlyClose));
+    assert_server_result!(Err(RpcServerError::SendError(
+        hyper::SendError::FrameTooLarge { .. }
+    )));
 
     println!("terminating server");
     server_handle.abort();
     Ok(())
 }
+
+#[cfg(feature = "json-codec")]
+#[tokio::test]
+async fn hyper_channel_json_codec() -> anyhow::Result<()> {
+    use quic_rpc::transport::{codec::JsonCodec, hyper::ChannelConfig};
+
+    let addr: SocketAddr = "127.0.0.1:3003".parse()?;
+    let uri: Uri = "http://127.0.0.1:3003".parse()?;
+    let channel = HyperListener::serve_with_codec(&addr, ChannelConfig::default(), JsonCodec)?;
+    let _server_handle = ComputeService::server(RpcServer::new(channel));
+    let client = HyperConnector::new(uri).with_codec(JsonCodec);
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let res = client.rpc(Sqr(1234)).await?;
+    assert_eq!(res, SqrResponse(1234 * 1234));
+    Ok(())
+}
+
+/// oversized frames are rejected in both directions, without taking down the connection
+#[tokio::test]
+async fn hyper_frame_too_large() -> anyhow::Result<()> {
+    use futures::{SinkExt, StreamExt};
+    use quic_rpc::transport::{hyper::ChannelConfig, Connector, Listener};
+
+    let addr: SocketAddr = "127.0.0.1:3004".parse()?;
+    let uri: Uri = "http://127.0.0.1:3004".parse()?;
+    let small = ChannelConfig::default().max_payload_size(8192)?;
+    let listener = HyperListener::<Vec<u8>, Vec<u8>>::serve_with_config(&addr, small.clone())?;
+    let server = tokio::spawn(async move {
+        // the first stream sends a frame that is too large
+        {
+            let (_send, mut recv) = listener.accept().await?;
+            let res = recv.next().await;
+            assert!(
+                matches!(res, Some(Err(RecvError::FrameTooLarge { limit: 8192, .. }))),
+                "unexpected result {res:?}"
+            );
+        }
+        // the second stream on the same connection still works
+        let (mut send, mut recv) = listener.accept().await?;
+        let msg = recv.next().await.transpose()?.expect("message");
+        send.send(msg).await?;
+        anyhow::Ok(())
+    });
+
+    let client = HyperConnector::<Vec<u8>, Vec<u8>>::new(uri.clone());
+    let (mut send, mut recv) = client.open().await?;
+    send.send(vec![0; 10_000]).await?;
+    assert!(recv.next().await.is_none());
+    let (mut send, mut recv) = client.open().await?;
+    send.send(vec![1; 100]).await?;
+    assert_eq!(recv.next().await.transpose()?, Some(vec![1; 100]));
+    server.await??;
+
+    // sending is rejected before anything is written
+    let client = HyperConnector::<Vec<u8>, Vec<u8>>::with_config(uri, small);
+    let (mut send, _recv) = client.open().await?;
+    let res = send.send(vec![0; 10_000]).await;
+    assert!(
+        matches!(
+            res,
+            Err(hyper::SendError::FrameTooLarge {
+                size: 10_008,
+                limit: 8192,
+                ..
+            })
+        ),
+        "unexpected result {res:?}"
+    );
+    Ok(())
+}
+
+/// errors that are not about (de)serialization have a backtrace too
+#[cfg(feature = "backtrace")]
+#[tokio::test]
+async fn hyper_error_backtrace() -> anyhow::Result<()> {
+    use futures::SinkExt;
+    use quic_rpc::transport::{backtrace::ErrorBacktrace, hyper::ChannelConfig, Connector};
+
+    let addr: SocketAddr = "127.0.0.1:3006".parse()?;
+    let uri: Uri = "http://127.0.0.1:3006".parse()?;
+    let small = ChannelConfig::default().max_payload_size(8192)?;
+    let _listener = HyperListener::<Vec<u8>, Vec<u8>>::serve_with_config(&addr, small.clone())?;
+    let client = HyperConnector::<Vec<u8>, Vec<u8>>::with_config(uri, small);
+    let (mut send, _recv) = client.open().await?;
+    let cause = send.send(vec![0; 10_000]).await.unwrap_err();
+    assert!(matches!(cause, hyper::SendError::FrameTooLarge { .. }));
+    let backtrace = cause.backtrace().expect("no backtrace");
+    assert!(backtrace.to_string().contains("hyper_error_backtrace"));
+    Ok(())
+}
+
+/// the listener can be fed by a service mounted on a route of an existing server
+#[tokio::test]
+async fn hyper_service_mounted() -> anyhow::Result<()> {
+    use std::convert::Infallible;
+
+    use ::hyper::{
+        service::{make_service_fn, service_fn, Service},
+        Body, Client, Request, Response, Server,
+    };
+    use quic_rpc::transport::hyper::ChannelConfig;
+
+    let addr: SocketAddr = "127.0.0.1:3005".parse()?;
+    let (listener, service) = HyperListener::service(ChannelConfig::default());
+    let _server_handle = ComputeService::server(RpcServer::new(listener));
+    let make_service = make_service_fn(move |_| {
+        let service = service.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let mut service = service.clone();
+                async move {
+                    if req.uri().path() == "/rpc" {
+                        service.call(req).await
+                    } else {
+                        Ok(Response::new(Body::from("ok")))
+                    }
+                }
+            }))
+        }
+    });
+    let server = Server::bind(&addr).http2_only(true).serve(make_service);
+    let _http_handle = AbortOnDropHandle::new(tokio::spawn(server));
+
+    let uri: Uri = "http://127.0.0.1:3005/rpc".parse()?;
+    smoke_test(HyperConnector::new(uri)).await?;
+
+    let client = Client::builder().http2_only(true).build_http::<Body>();
+    let res = client.get("http://127.0.0.1:3005/health".parse()?).await?;
+    let body = ::hyper::body::to_bytes(res.into_body()).await?;
+    assert_eq!(&body[..], b"ok");
+    Ok(())
+}
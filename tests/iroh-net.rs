@@ -6,6 +6,7 @@ use testresult::TestResult;
 
 use crate::transport::iroh_net::{IrohNetConnector, IrohNetListener};
 
+mod hang;
 mod math;
 use math::*;
 use tokio_util::task::AbortOnDropHandle;
@@ -159,3 +160,34 @@ async fn server_away_and_back() -> TestResult<()> {
     server_handle.abort();
     Ok(())
 }
+
+/// Dropping a streaming call cancels its handler, although its updates just end as if
+/// the client had finished them.
+#[tokio::test]
+async fn iroh_net_cancel_streaming() -> TestResult<()> {
+    use futures::SinkExt;
+    use hang::{next_event, Event, HangBidi, HangClientStreaming, HangService, HangUpdate};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_node_addr,
+    } = Endpoints::new().await?;
+    let (mut events, _server) = hang::serve(IrohNetListener::new(server)?);
+    let client = IrohNetConnector::new(client, server_node_addr, ALPN.into());
+    let client = RpcClient::<HangService, _>::new(client);
+
+    let (mut send, recv) = client.client_streaming(HangClientStreaming).await?;
+    send.send(HangUpdate).await?;
+    assert_eq!(next_event(&mut events).await?, Event::Started);
+    drop((send, recv));
+    assert_eq!(next_event(&mut events).await?, Event::Dropped);
+
+    let (mut send, recv) = client.bidi(HangBidi).await?;
+    send.send(HangUpdate).await?;
+    assert_eq!(next_event(&mut events).await?, Event::Started);
+    drop((send, recv));
+    assert_eq!(next_event(&mut events).await?, Event::Dropped);
+    Ok(())
+}
@@ -0,0 +1,68 @@
+#![cfg(feature = "flume-transport")]
+use std::sync::{Arc, Mutex};
+
+use quic_rpc::{
+    client::CallError,
+    server::{Layer, Rejection},
+    transport::{flume, RemoteInfo},
+    RpcClient, RpcServer,
+};
+
+mod math;
+use math::*;
+
+/// Records the number of every [Sqr] request, and rejects the ones in `reject`
+#[derive(Debug, Clone, Default)]
+struct Recorder {
+    seen: Arc<Mutex<Vec<u64>>>,
+    reject: Option<u64>,
+}
+
+impl Recorder {
+    fn seen(&self) -> Vec<u64> {
+        self.seen.lock().unwrap().clone()
+    }
+}
+
+impl Layer<ComputeService> for Recorder {
+    fn wrap(
+        &self,
+        req: &ComputeRequest,
+        _info: &RemoteInfo,
+    ) -> Result<(), Rejection<ComputeService>> {
+        if let ComputeRequest::Sqr(Sqr(n)) = req {
+            self.seen.lock().unwrap().push(*n);
+            if self.reject == Some(*n) {
+                return Err(Rejection::close());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// every first request passes the layers in order before it is dispatched, and the
+/// first rejection stops the request
+#[tokio::test]
+async fn layer_wraps_dispatch() -> anyhow::Result<()> {
+    let first = Recorder {
+        reject: Some(13),
+        ..Default::default()
+    };
+    let second = Recorder::default();
+    let (server, client) = flume::channel(1);
+    let server = RpcServer::<ComputeService, _>::new(server)
+        .with_layer(first.clone())
+        .with_layer(second.clone());
+    assert!(format!("{server:?}").contains("Layers(2)"));
+    let _server = ComputeService::server(server);
+    let client = RpcClient::<ComputeService, _>::new(client);
+
+    assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+    let res = client.rpc(Sqr(13)).await;
+    assert!(matches!(res, Err(CallError::EarlyClose)), "{res:?}");
+    assert_eq!(client.rpc(Sqr(4)).await?, SqrResponse(16));
+
+    assert_eq!(first.seen(), [3, 13, 4]);
+    assert_eq!(second.seen(), [3, 4]);
+    Ok(())
+}
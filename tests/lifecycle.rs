@@ -0,0 +1,95 @@
+#![cfg(feature = "flume-transport")]
+use std::time::Duration;
+
+use futures::SinkExt;
+use quic_rpc::{
+    transport::{
+        flume,
+        lifecycle::{LifecycleConnector, LifecycleEvent, LifecycleListener, LifecycleObserver},
+        Connector, Listener,
+    },
+    RpcClient, RpcServer,
+};
+use tokio::sync::mpsc;
+
+mod math;
+use math::*;
+
+/// An observer that records the events, and the receiver of the recorded events
+fn recorder() -> (
+    impl Fn(LifecycleEvent) + Send + Sync + 'static,
+    mpsc::UnboundedReceiver<LifecycleEvent>,
+) {
+    let (events, recv) = mpsc::unbounded_channel();
+    let observer = move |event| {
+        events.send(event).ok();
+    };
+    (observer, recv)
+}
+
+async fn next_event(events: &mut mpsc::UnboundedReceiver<LifecycleEvent>) -> LifecycleEvent {
+    tokio::time::timeout(Duration::from_secs(5), events.recv())
+        .await
+        .expect("no event")
+        .expect("observer dropped")
+}
+
+/// an rpc call is reported as a stream that is opened and closed on both sides
+#[tokio::test]
+async fn lifecycle_rpc() -> anyhow::Result<()> {
+    let (server_observer, mut server_events) = recorder();
+    let (client_observer, mut client_events) = recorder();
+    let (server, client) = flume::channel(1);
+    let server =
+        RpcServer::<ComputeService, _>::new(server).with_lifecycle_observer(server_observer);
+    let _server = ComputeService::server(server);
+    let client =
+        RpcClient::<ComputeService, _>::new(client).with_lifecycle_observer(client_observer);
+    assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+
+    for events in [&mut client_events, &mut server_events] {
+        let LifecycleEvent::StreamOpened { stream, remote, .. } = next_event(events).await else {
+            panic!("stream was not opened");
+        };
+        // flume knows nothing about the remote
+        assert_eq!(remote, None);
+        match next_event(events).await {
+            LifecycleEvent::StreamClosed { stream: closed, .. } => assert_eq!(closed, stream),
+            event => panic!("unexpected event {event:?}"),
+        }
+    }
+    Ok(())
+}
+
+/// a stream on which sending failed is reported as reset
+#[tokio::test]
+async fn lifecycle_reset() -> anyhow::Result<()> {
+    let (observer, mut events) = recorder();
+    let (listener, connector) = flume::channel::<u64, u64>(1);
+    let connector = LifecycleConnector::new(connector, LifecycleObserver::new(observer));
+    let listener = LifecycleListener::new(listener, LifecycleObserver::new(|_| {}));
+    let (mut send, recv) = connector.open().await?;
+    let stream = send.stream_id();
+    assert_eq!(recv.stream_id(), stream);
+    // the server drops the channel right away
+    drop(listener.accept().await?);
+    assert!(send.send(1).await.is_err());
+    drop((send, recv));
+
+    match next_event(&mut events).await {
+        LifecycleEvent::StreamOpened { stream: opened, .. } => assert_eq!(opened, stream),
+        event => panic!("unexpected event {event:?}"),
+    }
+    match next_event(&mut events).await {
+        LifecycleEvent::StreamReset {
+            stream: reset,
+            error,
+            ..
+        } => {
+            assert_eq!(reset, stream);
+            assert!(!error.is_empty());
+        }
+        event => panic!("unexpected event {event:?}"),
+    }
+    Ok(())
+}
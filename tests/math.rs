@@ -3,6 +3,9 @@
     feature = "hyper-transport",
     feature = "quinn-transport",
     feature = "iroh-net-transport",
+    feature = "ws-transport",
+    feature = "tcp-transport",
+    feature = "uds-transport",
 ))]
 #![allow(dead_code)]
 use std::{
@@ -29,7 +32,7 @@ use thousands::Separable;
 use tokio_util::task::AbortOnDropHandle;
 
 /// compute the square of a number
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Sqr(pub u64);
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -49,7 +52,7 @@ pub struct SumResponse(pub u128);
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Fibonacci(pub u64);
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FibonacciResponse(pub u128);
 
 /// multiply a stream of numbers, returning a stream
@@ -170,6 +173,19 @@ impl ComputeService {
         server.spawn_accept_loop(|req, chan| Self::handle_rpc_request(ComputeService, req, chan))
     }
 
+    /// Like [Self::server], but handles [Sqr] with `rpc_pipeline`, so clients can send
+    /// them with `RpcClient::rpc_pipeline`
+    pub fn server_pipelined<C: Listener<ComputeService>>(
+        server: RpcServer<ComputeService, C>,
+    ) -> AbortOnDropHandle<()> {
+        server.spawn_accept_loop(|req, chan| async move {
+            match req {
+                ComputeRequest::Sqr(msg) => chan.rpc_pipeline(msg, ComputeService, Self::sqr).await,
+                req => Self::handle_rpc_request(ComputeService, req, chan).await,
+            }
+        })
+    }
+
     pub async fn handle_rpc_request<E>(
         self,
         req: ComputeRequest,
@@ -279,7 +295,7 @@ pub async fn smoke_test<C: Connector<ComputeService>>(client: C) -> anyhow::Resu
         for i in 1..=3 {
             send.send(SumUpdate(i)).await?;
         }
-        Ok::<_, C::SendError>(())
+        Ok::<_, quic_rpc::client::UpdateError<C>>(())
     });
     let res = recv.await?;
     tracing::debug!("got response {:?}", res);
@@ -299,7 +315,7 @@ pub async fn smoke_test<C: Connector<ComputeService>>(client: C) -> anyhow::Resu
         for i in 1..=3 {
             send.send(MultiplyUpdate(i)).await?;
         }
-        Ok::<_, C::SendError>(())
+        Ok::<_, quic_rpc::client::UpdateError<C>>(())
     });
     let res: Vec<_> = recv.map(|x| x.map(|x| x.0)).try_collect().await?;
     tracing::debug!("got response {:?}", res);
@@ -309,13 +325,63 @@ pub async fn smoke_test<C: Connector<ComputeService>>(client: C) -> anyhow::Resu
     Ok(())
 }
 
+/// A server that responds to a client streaming request early stops reading updates,
+/// which the client sees as [UpdateError::ServerClosed](quic_rpc::client::UpdateError)
+pub async fn early_response_test<L, C>(listener: L, client: C) -> anyhow::Result<()>
+where
+    L: Listener<ComputeService>,
+    C: Connector<ComputeService>,
+{
+    use quic_rpc::client::UpdateError;
+
+    let server = RpcServer::<ComputeService, _>::new(listener);
+    let server_handle = tokio::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let ComputeRequest::Sum(req) = req else {
+            panic!("unexpected request {req:?}");
+        };
+        chan.client_streaming(req, (), |_, _, updates| async move {
+            // stop reading after 3 updates
+            let updates = updates.take(3);
+            tokio::pin!(updates);
+            let mut sum = 0u128;
+            while let Some(SumUpdate(n)) = updates.next().await {
+                sum += n as u128;
+            }
+            SumResponse(sum)
+        })
+        .await?;
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<ComputeService, C>::new(client);
+    let (mut send, recv) = client.client_streaming(Sum).await?;
+    for i in 1..=3 {
+        send.send(SumUpdate(i)).await?;
+    }
+    assert_eq!(recv.await?, SumResponse(6));
+    server_handle.await??;
+    // transports that learn about the closed side asynchronously accept a few more
+    let res = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            if let Err(cause) = send.send(SumUpdate(4)).await {
+                break cause;
+            }
+        }
+    })
+    .await?;
+    match res {
+        UpdateError::ServerClosed(_) => {}
+        res => panic!("unexpected result {res:?}"),
+    }
+    Ok(())
+}
+
 fn clear_line() {
     print!("\r{}\r", " ".repeat(80));
 }
 
 pub async fn bench<C>(client: RpcClient<ComputeService, C>, n: u64) -> anyhow::Result<()>
 where
-    C::SendError: std::error::Error,
     C: Connector<ComputeService>,
 {
     // individual RPCs
@@ -361,8 +427,10 @@ where
         let (send, recv) = client.bidi(Multiply(2)).await?;
         let handle = tokio::task::spawn(async move {
             let requests = futures_lite::stream::iter((0..n).map(MultiplyUpdate));
-            futures_util::StreamExt::forward(requests.map(Ok), send).await?;
-            anyhow::Result::<()>::Ok(())
+            let res: anyhow::Result<()> = futures_util::StreamExt::forward(requests.map(Ok), send)
+                .await
+                .map_err(Into::into);
+            res
         });
         let mut sum = 0;
         tokio::pin!(recv);
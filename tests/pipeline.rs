@@ -0,0 +1,91 @@
+#![cfg(feature = "flume-transport")]
+use std::{sync::Arc, time::Duration};
+
+use derive_more::{From, TryInto};
+use futures::StreamExt;
+use quic_rpc::{
+    message::RpcMsg, pattern::rpc::Tagged, transport::flume, RpcClient, RpcServer, Service,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone)]
+struct EchoService;
+
+impl Service for EchoService {
+    type Req = EchoRequest;
+    type Res = EchoResponse;
+}
+
+/// echo the value, once the handler is released if `wait` is set
+#[derive(Debug, Serialize, Deserialize)]
+struct Echo {
+    value: u64,
+    wait: bool,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Echoed(u64);
+
+impl RpcMsg<EchoService> for Echo {
+    type Response = Echoed;
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum EchoRequest {
+    Echo(Echo),
+    TaggedEcho(Tagged<Echo>),
+}
+
+#[derive(Debug, Serialize, Deserialize, From, TryInto)]
+enum EchoResponse {
+    Echoed(Echoed),
+    TaggedEchoed(Tagged<Echoed>),
+}
+
+#[tokio::test]
+async fn pipeline_unordered_completion() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<EchoService, _>::new(server);
+    let server_handle = tokio::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let EchoRequest::TaggedEcho(req) = req else {
+            panic!("unexpected request {req:?}");
+        };
+        // the first request waits until the second one is done
+        let released = Arc::new(Notify::new());
+        chan.rpc_pipeline_unordered(req, released, |released, req| async move {
+            if req.wait {
+                released.notified().await;
+            } else {
+                released.notify_one();
+            }
+            Echoed(req.value)
+        })
+        .await?;
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<EchoService, _>::new(client);
+
+    let msgs = [
+        Echo {
+            value: 10,
+            wait: true,
+        },
+        Echo {
+            value: 20,
+            wait: false,
+        },
+    ];
+    let responses = client.rpc_pipeline_unordered(msgs).await?;
+    let responses = tokio::time::timeout(Duration::from_secs(5), responses.collect::<Vec<_>>())
+        .await?
+        .into_iter()
+        .map(|(index, res)| Ok((index, res?)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    assert_eq!(responses, vec![(1, Echoed(20)), (0, Echoed(10))]);
+    server_handle.await??;
+    Ok(())
+}
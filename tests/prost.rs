@@ -0,0 +1,179 @@
+#![cfg(all(feature = "tcp-transport", feature = "prost-codec"))]
+use prost::Message;
+use quic_rpc::{
+    message::RpcMsg,
+    prost_message, prost_oneof,
+    transport::{
+        codec::{Codec, ProstCodec},
+        tcp::{TcpConnector, TcpListener},
+        Listener, LocalAddr,
+    },
+    RpcClient, RpcServer, Service,
+};
+
+/// Messages as prost-build generates them for
+///
+/// ```protobuf
+/// message Add { uint64 a = 1; uint64 b = 2; }
+/// message Neg { sint64 value = 1; }
+/// message Sum { uint64 value = 1; }
+/// message Negated { sint64 value = 1; }
+/// message Request { oneof req { Add add = 1; Neg neg = 2; } }
+/// message Response { oneof res { Sum sum = 1; Negated negated = 2; } }
+/// ```
+mod pb {
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Add {
+        #[prost(uint64, tag = "1")]
+        pub a: u64,
+        #[prost(uint64, tag = "2")]
+        pub b: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Neg {
+        #[prost(sint64, tag = "1")]
+        pub value: i64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Sum {
+        #[prost(uint64, tag = "1")]
+        pub value: u64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Negated {
+        #[prost(sint64, tag = "1")]
+        pub value: i64,
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Request {
+        #[prost(oneof = "request::Req", tags = "1, 2")]
+        pub req: Option<request::Req>,
+    }
+
+    pub mod request {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Req {
+            #[prost(message, tag = "1")]
+            Add(super::Add),
+            #[prost(message, tag = "2")]
+            Neg(super::Neg),
+        }
+    }
+
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Response {
+        #[prost(oneof = "response::Res", tags = "1, 2")]
+        pub res: Option<response::Res>,
+    }
+
+    pub mod response {
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum Res {
+            #[prost(message, tag = "1")]
+            Sum(super::Sum),
+            #[prost(message, tag = "2")]
+            Negated(super::Negated),
+        }
+    }
+}
+
+use pb::{request::Req, Add, Neg, Negated, Request, Response, Sum};
+
+prost_message!(Request, Response);
+prost_oneof!(Request, pb::request::Req, req { Add(Add), Neg(Neg) });
+prost_oneof!(Response, pb::response::Res, res { Sum(Sum), Negated(Negated) });
+
+#[derive(Debug, Clone)]
+struct ProtoCalculator;
+
+impl Service for ProtoCalculator {
+    type Req = Request;
+    type Res = Response;
+}
+
+impl RpcMsg<ProtoCalculator> for Add {
+    type Response = Sum;
+}
+
+impl RpcMsg<ProtoCalculator> for Neg {
+    type Response = Negated;
+}
+
+#[test]
+fn prost_codec_wire_format() -> anyhow::Result<()> {
+    let codec = ProstCodec;
+    let req = Request::from(Add { a: 1, b: 300 });
+    let data = codec.encode(&req)?;
+    // exactly what other protobuf libraries write for the message, the transport adds
+    // the length of the frame
+    assert_eq!(data, req.encode_to_vec());
+    assert_eq!(codec.decode::<Request>(data)?, req);
+
+    // a frame that is not a message is rejected
+    let mut data = req.encode_to_vec();
+    data.push(0);
+    assert!(codec.decode::<Request>(data.into()).is_err());
+
+    // only prost messages can be encoded
+    assert!(codec.encode(&1u32).is_err());
+    Ok(())
+}
+
+/// the calls go over a transport that encodes them with the codec
+#[tokio::test]
+async fn prost_tcp_round_trip() -> anyhow::Result<()> {
+    let listener = TcpListener::<Request, Response>::bind("127.0.0.1:0".parse()?)
+        .await?
+        .with_codec(ProstCodec);
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    let client = TcpConnector::<Response, Request>::new(addr).with_codec(ProstCodec);
+    let server = RpcServer::<ProtoCalculator, _>::new(listener);
+    let server_handle = tokio::spawn(async move {
+        for _ in 0..2 {
+            let (req, chan) = server.accept().await?.read_first().await?;
+            match req.req {
+                Some(Req::Add(add)) => {
+                    chan.rpc(add, (), |(), add| async move {
+                        Sum {
+                            value: add.a + add.b,
+                        }
+                    })
+                    .await?
+                }
+                Some(Req::Neg(neg)) => {
+                    chan.rpc(
+                        neg,
+                        (),
+                        |(), neg| async move { Negated { value: -neg.value } },
+                    )
+                    .await?
+                }
+                None => anyhow::bail!("empty request"),
+            }
+        }
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<ProtoCalculator, _>::new(client);
+    let sum = client.rpc(Add { a: 1, b: 2 }).await?;
+    assert_eq!(sum, Sum { value: 3 });
+    let negated = client.rpc(Neg { value: 7 }).await?;
+    assert_eq!(negated, Negated { value: -7 });
+    server_handle.await??;
+
+    // the conversions of the oneof fail for the other messages
+    let req = Request::from(Neg { value: 1 });
+    let req = Add::try_from(req).unwrap_err();
+    assert_eq!(req.req, Some(Req::Neg(Neg { value: 1 })));
+    assert!(Sum::try_from(Response { res: None }).is_err());
+    assert_eq!(
+        Negated::try_from(Response::from(Negated { value: 2 })).ok(),
+        Some(Negated { value: 2 })
+    );
+    Ok(())
+}
@@ -0,0 +1,158 @@
+#![cfg(all(feature = "uds-transport", unix))]
+//! Test vectors for the wire format, see [quic_rpc::transport::protocol]
+//!
+//! Each test writes the literal bytes of one interaction of the `ComputeService` to a
+//! plain unix socket, and checks the literal bytes the server sends back. A client in
+//! another language has to produce and accept exactly these bytes.
+use std::path::Path;
+
+use bytes::BytesMut;
+use quic_rpc::{
+    transport::{protocol::RawProtocol, uds::UdsListener},
+    RpcServer,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::UnixStream,
+};
+
+mod math;
+use math::*;
+
+/// Send the frames of the client, and return everything the server sent until it
+/// closed its side.
+///
+/// With `close`, the client closes its side after sending, otherwise only once the server
+/// is done.
+async fn exchange(path: &Path, client: &[&[u8]], close: bool) -> anyhow::Result<Vec<u8>> {
+    let mut stream = UnixStream::connect(path).await?;
+    for frame in client {
+        stream.write_all(frame).await?;
+    }
+    if close {
+        stream.shutdown().await?;
+    }
+    let mut received = Vec::new();
+    stream.read_to_end(&mut received).await?;
+    Ok(received)
+}
+
+/// Run the compute service on a unix socket in `dir`
+fn serve(dir: &Path) -> anyhow::Result<(std::path::PathBuf, impl Sized)> {
+    let path = dir.join("rpc.sock");
+    let listener = UdsListener::bind(&path)?;
+    let handle = ComputeService::server(RpcServer::new(listener));
+    Ok((path, handle))
+}
+
+#[test]
+fn protocol_frames() -> anyhow::Result<()> {
+    let protocol = RawProtocol::new();
+    let mut buf = BytesMut::new();
+    // length 12, variant 0 (Sqr), 3 as a u64
+    protocol.encode(&ComputeRequest::from(Sqr(3)), &mut buf)?;
+    assert_eq!(&buf[..], [0, 0, 0, 12, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0]);
+    let req: ComputeRequest = protocol.decode(&mut buf)?.expect("complete frame");
+    assert!(matches!(req, ComputeRequest::Sqr(Sqr(3))));
+
+    // length 4, variant 1 (Sum) without fields
+    protocol.encode(&ComputeRequest::from(Sum), &mut buf)?;
+    assert_eq!(&buf[..], [0, 0, 0, 4, 1, 0, 0, 0]);
+
+    // frames are only taken off the buffer once they are complete
+    let mut partial = BytesMut::from(&buf[..6]);
+    assert!(protocol.decode_raw(&mut partial)?.is_none());
+    assert_eq!(partial.len(), 6);
+    let payload = protocol.decode_raw(&mut buf)?.expect("complete frame");
+    assert_eq!(&payload[..], [1, 0, 0, 0]);
+    assert!(buf.is_empty());
+
+    // frames larger than the limit are rejected, before they are complete
+    let protocol = RawProtocol::new().with_max_frame_size(8);
+    let mut buf = BytesMut::from(&[0u8, 0, 0, 9][..]);
+    assert!(protocol.decode_raw(&mut buf).is_err());
+    assert!(protocol.encode_raw(&[0; 9], &mut buf).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn protocol_rpc() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (path, _server) = serve(dir.path())?;
+    let request: &[u8] = &[
+        0, 0, 0, 12, // length
+        0, 0, 0, 0, // Sqr
+        3, 0, 0, 0, 0, 0, 0, 0, // 3u64
+    ];
+    let response = exchange(&path, &[request], false).await?;
+    #[rustfmt::skip]
+    let expected = [
+        0, 0, 0, 20, // length
+        0, 0, 0, 0, // SqrResponse
+        9, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // 9u128
+    ];
+    assert_eq!(response, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn protocol_server_streaming() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (path, _server) = serve(dir.path())?;
+    let request: &[u8] = &[
+        0, 0, 0, 12, // length
+        3, 0, 0, 0, // Fibonacci
+        4, 0, 0, 0, 0, 0, 0, 0, // 4u64
+    ];
+    let responses = exchange(&path, &[request], false).await?;
+    #[rustfmt::skip]
+    let expected = [
+        0, 0, 0, 20, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // FibonacciResponse(0)
+        0, 0, 0, 20, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // FibonacciResponse(1)
+        0, 0, 0, 20, 2, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // FibonacciResponse(1)
+        0, 0, 0, 20, 2, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // FibonacciResponse(2)
+    ];
+    assert_eq!(responses, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn protocol_client_streaming() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (path, _server) = serve(dir.path())?;
+    #[rustfmt::skip]
+    let frames: [&[u8]; 3] = [
+        &[0, 0, 0, 4, 1, 0, 0, 0], // Sum
+        &[0, 0, 0, 12, 2, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0], // SumUpdate(5)
+        &[0, 0, 0, 12, 2, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0], // SumUpdate(7)
+    ];
+    // closing the client side ends the updates
+    let response = exchange(&path, &frames, true).await?;
+    #[rustfmt::skip]
+    let expected = [
+        0, 0, 0, 20, 1, 0, 0, 0, 12, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // SumResponse(12)
+    ];
+    assert_eq!(response, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn protocol_bidi_streaming() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir()?;
+    let (path, _server) = serve(dir.path())?;
+    #[rustfmt::skip]
+    let frames: [&[u8]; 3] = [
+        &[0, 0, 0, 12, 4, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0, 0], // Multiply(3)
+        &[0, 0, 0, 12, 5, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0], // MultiplyUpdate(2)
+        &[0, 0, 0, 12, 5, 0, 0, 0, 5, 0, 0, 0, 0, 0, 0, 0], // MultiplyUpdate(5)
+    ];
+    // a response for every update, the responses end with the updates
+    let responses = exchange(&path, &frames, true).await?;
+    #[rustfmt::skip]
+    let expected = [
+        0, 0, 0, 20, 3, 0, 0, 0, 6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // MultiplyResponse(6)
+        0, 0, 0, 20, 3, 0, 0, 0, 15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // MultiplyResponse(15)
+    ];
+    assert_eq!(responses, expected);
+    Ok(())
+}
@@ -2,12 +2,17 @@
 use std::{
     net::{Ipv4Addr, SocketAddr, SocketAddrV4},
     sync::Arc,
+    time::Duration,
 };
 
 use quic_rpc::{
     transport::{
         self,
-        quinn::{QuinnConnector, QuinnListener},
+        quinn::{
+            ConnectionEvent, IdleEviction, KeepAliveConfig, QuinnConnector, QuinnListener, Remote,
+            ResolveError, SharedEndpoint, SubstreamPool, TransportConfigError, TransportSettings,
+        },
+        PingError,
     },
     RpcClient, RpcServer,
 };
@@ -16,6 +21,7 @@ use quinn::{
     rustls, ClientConfig, Endpoint, ServerConfig,
 };
 
+mod hang;
 mod math;
 use math::*;
 use testresult::TestResult;
@@ -101,6 +107,15 @@ fn configure_server() -> anyhow::Result<(ServerConfig, Vec<u8>)> {
     Ok((server_config, cert_der.to_vec()))
 }
 
+/// Like [configure_server], but allows the unidirectional stream used for heartbeats
+fn configure_heartbeat_server() -> anyhow::Result<(ServerConfig, Vec<u8>)> {
+    let (mut server_config, server_cert) = configure_server()?;
+    Arc::get_mut(&mut server_config.transport)
+        .unwrap()
+        .max_concurrent_uni_streams(1_u8.into());
+    Ok((server_config, server_cert))
+}
+
 pub struct Endpoints {
     client: Endpoint,
     server: Endpoint,
@@ -206,3 +221,1432 @@ async fn server_away_and_back() -> TestResult<()> {
     server_handle.abort();
     Ok(())
 }
+
+/// Test that heartbeats are echoed by the listener without interfering with rpc calls.
+#[tokio::test]
+async fn quinn_keep_alive_heartbeat() -> TestResult<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let server_addr: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12348));
+    let (server, _) = make_server_endpoint(server_addr)?;
+    // the keep-alive is set on this config, which has to be the only user of it
+    let (server_config, server_cert) = configure_heartbeat_server()?;
+    let interval = Some(Duration::from_millis(50));
+    let listener = QuinnListener::with_keep_alive(server, server_config, interval)?;
+    let _server_handle = ComputeService::server(RpcServer::new(listener));
+
+    let client = Endpoint::client("0.0.0.0:0".parse()?)?;
+    let keep_alive = KeepAliveConfig::default()
+        .keep_alive_interval(interval)
+        .heartbeat_interval(interval);
+    let client_connection = QuinnConnector::with_keep_alive(
+        client,
+        server_addr,
+        "localhost".into(),
+        configure_client(&[&server_cert])?,
+        keep_alive,
+    );
+    assert!(client_connection.last_alive().is_none());
+    let client = RpcClient::new(client_connection.clone());
+    let SqrResponse(response) = client.rpc(Sqr(4)).await?;
+    assert_eq!(response, 16);
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let last_alive = client_connection.last_alive().expect("heartbeat echoed");
+    assert!(last_alive.elapsed() < Duration::from_millis(200));
+    // heartbeats must not be visible to the rpc layer
+    let SqrResponse(response) = client.rpc(Sqr(3)).await?;
+    assert_eq!(response, 9);
+    Ok(())
+}
+
+/// Connection stats are available once the client is connected.
+#[tokio::test]
+async fn quinn_connection_stats() -> TestResult<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12349)?;
+    let _server_handle = run_server(server);
+    let client = QuinnConnector::new(client, server_addr, "localhost".into());
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let SqrResponse(response) = client.rpc(Sqr(4)).await?;
+    assert_eq!(response, 16);
+    let stats = client.stats().expect("connected");
+    assert!(stats.rtt > Duration::ZERO);
+    assert!(stats.cwnd > 0);
+    assert!(stats.sent_bytes > 0);
+    assert!(stats.recv_bytes > 0);
+    Ok(())
+}
+
+/// Oversized frames are rejected in both directions, and only affect their own substream.
+#[tokio::test]
+async fn quinn_frame_too_large() -> TestResult<()> {
+    use futures::{SinkExt, StreamExt};
+    use quic_rpc::transport::{quinn::frame_too_large, Connector, Listener};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12350)?;
+    let listener = QuinnListener::<Vec<u8>, Vec<u8>>::new(server)?.with_max_frame_size(8192);
+    let server = tokio::spawn(async move {
+        // the first substream sends a frame that is too large
+        {
+            let (_send, mut recv) = listener.accept().await?;
+            let cause = recv.next().await.expect("error").unwrap_err();
+            let cause = frame_too_large(&cause).expect("frame too large");
+            assert_eq!(cause.limit, 8192);
+        }
+        // the second substream on the same connection still works
+        let (mut send, mut recv) = listener.accept().await?;
+        let msg = recv.next().await.transpose()?.expect("message");
+        send.send(msg).await?;
+        TestResult::Ok(())
+    });
+
+    let connector =
+        QuinnConnector::<Vec<u8>, Vec<u8>>::new(client, server_addr, "localhost".into());
+    let (mut send, _recv) = connector.open().await?;
+    send.send(vec![0; 10_000]).await?;
+    let (mut send, mut recv) = connector.open().await?;
+    send.send(vec![1; 100]).await?;
+    assert_eq!(recv.next().await.transpose()?, Some(vec![1; 100]));
+    server.await??;
+
+    // sending is rejected before anything is written
+    let connector = connector.with_max_frame_size(8192);
+    let (mut send, _recv) = connector.open().await?;
+    let cause = send.send(vec![0; 10_000]).await.unwrap_err();
+    let cause = frame_too_large(&cause).expect("frame too large");
+    assert_eq!((cause.size, cause.limit), (10_008, 8192));
+    Ok(())
+}
+
+/// Errors of the transport capture a backtrace, without changing their payload
+#[cfg(feature = "backtrace")]
+#[tokio::test]
+async fn quinn_error_backtrace() -> TestResult<()> {
+    use futures::SinkExt;
+    use quic_rpc::transport::{
+        backtrace::ErrorBacktrace,
+        quinn::{frame_too_large, FrameTooLarge},
+        Connector,
+    };
+
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12371)?;
+    let _listener = QuinnListener::<Vec<u8>, Vec<u8>>::new(server)?;
+    let connector =
+        QuinnConnector::<Vec<u8>, Vec<u8>>::new(client, server_addr, "localhost".into())
+            .with_max_frame_size(8192);
+    let (mut send, _recv) = connector.open().await?;
+    let cause = send.send(vec![0; 10_000]).await.unwrap_err();
+    // the payload is the error of the crate, which carries the backtrace
+    let payload = cause
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<FrameTooLarge>());
+    assert_eq!(payload, frame_too_large(&cause));
+    let backtrace = cause.backtrace().expect("no backtrace");
+    assert!(backtrace.to_string().contains("quinn_error_backtrace"));
+    assert!(format!("{cause:?}").contains("backtrace"));
+    Ok(())
+}
+
+/// The listener reports its connections, and the streams with the address of the client
+#[tokio::test]
+async fn quinn_lifecycle_observer() -> TestResult<()> {
+    use quic_rpc::transport::lifecycle::LifecycleEvent;
+
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12372)?;
+    let (events, mut recv) = tokio::sync::mpsc::unbounded_channel();
+    let server = RpcServer::<ComputeService, _>::new(QuinnListener::new(server)?)
+        .with_lifecycle_observer(move |event| {
+            events.send(event).ok();
+        });
+    let _server = ComputeService::server(server);
+    let connector = QuinnConnector::new(client, server_addr, "localhost".into());
+    let client = RpcClient::<ComputeService, _>::new(connector);
+    assert_eq!(client.rpc(Sqr(4)).await?, SqrResponse(16));
+
+    async fn next(
+        recv: &mut tokio::sync::mpsc::UnboundedReceiver<LifecycleEvent>,
+    ) -> LifecycleEvent {
+        tokio::time::timeout(Duration::from_secs(5), recv.recv())
+            .await
+            .expect("no event")
+            .expect("observer dropped")
+    }
+    let LifecycleEvent::ConnectionOpened { remote, .. } = next(&mut recv).await else {
+        panic!("connection was not opened");
+    };
+    let LifecycleEvent::StreamOpened {
+        stream,
+        remote: stream_remote,
+        ..
+    } = next(&mut recv).await
+    else {
+        panic!("stream was not opened");
+    };
+    assert_eq!(stream_remote, Some(remote));
+    match next(&mut recv).await {
+        LifecycleEvent::StreamClosed { stream: closed, .. } => assert_eq!(closed, stream),
+        event => panic!("unexpected event {event:?}"),
+    }
+    Ok(())
+}
+
+/// With drain_on_close, a clean close of the client connection ends the updates
+/// instead of aborting the handler.
+#[tokio::test]
+async fn quinn_bidi_drain_on_close() -> TestResult<()> {
+    use futures::{SinkExt, StreamExt};
+    use quic_rpc::server::UpdatesEnd;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12351)?;
+    let server = RpcServer::<ComputeService, _>::new(QuinnListener::new(server)?);
+    let (end_tx, end_rx) = tokio::sync::oneshot::channel();
+    let server_handle = tokio::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let ComputeRequest::Multiply(req) = req else {
+            panic!("unexpected request {req:?}");
+        };
+        let res = chan
+            .bidi_streaming(req, (), move |_, Multiply(factor), updates| {
+                let mut updates = updates.drain_on_close();
+                async_stream::stream! {
+                    while let Some(MultiplyUpdate(x)) = updates.next().await {
+                        yield MultiplyResponse(factor as u128 * x as u128);
+                    }
+                    end_tx.send(updates.end()).ok();
+                    // a final response after the updates ended
+                    yield MultiplyResponse(0);
+                }
+            })
+            .await;
+        TestResult::Ok(res)
+    });
+
+    let endpoint = client.clone();
+    let client = QuinnConnector::new(client, server_addr, "localhost".into());
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let (mut send, mut recv) = client.bidi(Multiply(2)).await?;
+    send.send(MultiplyUpdate(3)).await?;
+    let MultiplyResponse(res) = recv.next().await.expect("response")?;
+    assert_eq!(res, 6);
+    // close the connection while the update sink is still open
+    endpoint.close(0u32.into(), b"done");
+    assert_eq!(end_rx.await?, Some(UpdatesEnd::Closed));
+    // the final response can not be delivered anymore, but the handler did run to the end
+    let res = server_handle.await??;
+    assert!(res.is_err(), "{res:?}");
+    drop(send);
+    Ok(())
+}
+
+/// the handler can see the address of the client, also through a boxed listener
+#[tokio::test]
+async fn quinn_remote_info() -> TestResult<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12352)?;
+    let client_port = client.local_addr()?.port();
+    let server = RpcServer::<ComputeService, _>::new(QuinnListener::new(server)?).boxed();
+    let server_handle = tokio::spawn(async move {
+        let accepting = server.accept().await?;
+        let addr = accepting.remote_info().and_then(|info| info.addr);
+        let (req, chan) = accepting.read_first().await?;
+        let ComputeRequest::Sqr(req) = req else {
+            panic!("unexpected request {req:?}");
+        };
+        let info = chan.remote_info().cloned();
+        chan.rpc(req, (), |_, Sqr(x)| async move {
+            SqrResponse(x as u128 * x as u128)
+        })
+        .await?;
+        TestResult::Ok((addr, info))
+    });
+    let client = QuinnConnector::new(client, server_addr, "localhost".into());
+    let client = RpcClient::<ComputeService, _>::new(client);
+    client.rpc(Sqr(2)).await?;
+    let (addr, info) = server_handle.await??;
+    let info = info.expect("remote info");
+    assert_eq!(addr, info.addr);
+    assert_eq!(info.addr.map(|addr| addr.port()), Some(client_port));
+    // no client authentication configured
+    assert!(info.certificates.is_empty());
+    Ok(())
+}
+
+/// A certificate and key issued by a test CA
+struct Issued {
+    cert: rustls::pki_types::CertificateDer<'static>,
+    key: rustls::pki_types::PrivateKeyDer<'static>,
+}
+
+fn issue(
+    name: &str,
+    issuer: Option<(&rcgen::Certificate, &rcgen::KeyPair)>,
+) -> anyhow::Result<Issued> {
+    let key = rcgen::KeyPair::generate()?;
+    let params = rcgen::CertificateParams::new(vec![name.into()])?;
+    let cert = match issuer {
+        Some((ca, ca_key)) => params.signed_by(&key, ca, ca_key)?,
+        None => params.self_signed(&key)?,
+    };
+    Ok(Issued {
+        cert: cert.der().clone(),
+        key: rustls::pki_types::PrivatePkcs8KeyDer::from(key.serialize_der()).into(),
+    })
+}
+
+/// only clients with a certificate issued by the CA can connect, and the handler sees it
+#[tokio::test]
+async fn quinn_mtls() -> TestResult<()> {
+    use quic_rpc::transport::quinn::client_config_with_cert;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let ca_key = rcgen::KeyPair::generate()?;
+    let mut ca_params = rcgen::CertificateParams::new(Vec::<String>::new())?;
+    ca_params.is_ca = rcgen::IsCa::Ca(rcgen::BasicConstraints::Unconstrained);
+    let ca = ca_params.self_signed(&ca_key)?;
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(ca.der().clone())?;
+    let server_cert = issue("localhost", Some((&ca, &ca_key)))?;
+    let client_cert = issue("client", Some((&ca, &ca_key)))?;
+    let unknown_cert = issue("client", None)?;
+
+    let server_addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12353));
+    let (server, _) = make_server_endpoint(server_addr)?;
+    let listener = QuinnListener::with_client_auth(
+        server,
+        vec![server_cert.cert],
+        server_cert.key,
+        roots.clone(),
+    )?;
+    let server = RpcServer::<ComputeService, _>::new(listener);
+    let server_handle = tokio::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let ComputeRequest::Sqr(req) = req else {
+            panic!("unexpected request {req:?}");
+        };
+        let info = chan.remote_info().cloned();
+        chan.rpc(req, (), |_, Sqr(x)| async move {
+            SqrResponse(x as u128 * x as u128)
+        })
+        .await?;
+        TestResult::Ok(info)
+    });
+
+    // a client certificate from an unknown issuer is rejected during the handshake
+    let endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
+    let config = client_config_with_cert(roots.clone(), vec![unknown_cert.cert], unknown_cert.key)?;
+    let err = match endpoint
+        .connect_with(config, server_addr, "localhost")?
+        .await
+    {
+        Ok(connection) => connection.closed().await,
+        Err(cause) => cause,
+    };
+    assert!(
+        matches!(
+            err,
+            quinn::ConnectionError::TransportError(_) | quinn::ConnectionError::ConnectionClosed(_)
+        ),
+        "unexpected error {err:?}"
+    );
+
+    // a client certificate issued by the CA is accepted
+    let endpoint = Endpoint::client("0.0.0.0:0".parse()?)?;
+    let client = QuinnConnector::with_client_cert(
+        endpoint,
+        server_addr,
+        "localhost".into(),
+        roots,
+        vec![client_cert.cert.clone()],
+        client_cert.key,
+    )?;
+    let client = RpcClient::<ComputeService, _>::new(client);
+    assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+    let info = server_handle.await??.expect("remote info");
+    assert_eq!(info.certificates, vec![client_cert.cert.to_vec()]);
+    Ok(())
+}
+
+/// an old and a new version of a service, where the new one has an additional request
+mod versioned {
+    use derive_more::{From, TryInto};
+    use quic_rpc::{message::RpcMsg, Service};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Echo(pub u64);
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Shout(pub String);
+
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    pub enum OldRequest {
+        Echo(Echo),
+    }
+
+    /// new variants are only appended
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    pub enum NewRequest {
+        Echo(Echo),
+        Shout(Shout),
+    }
+
+    #[derive(Debug, Serialize, Deserialize, From, TryInto)]
+    pub enum Response {
+        Echo(u64),
+        Shout(String),
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct OldService;
+
+    impl Service for OldService {
+        type Req = OldRequest;
+        type Res = Response;
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct NewService;
+
+    impl Service for NewService {
+        type Req = NewRequest;
+        type Res = Response;
+    }
+
+    impl RpcMsg<OldService> for Echo {
+        type Response = u64;
+    }
+
+    impl RpcMsg<NewService> for Echo {
+        type Response = u64;
+    }
+
+    impl RpcMsg<NewService> for Shout {
+        type Response = String;
+    }
+}
+
+/// an old server skips unknown requests from a newer client, and keeps serving it
+#[tokio::test]
+async fn quinn_skip_unknown_request() -> TestResult<()> {
+    use quic_rpc::transport::codec::{BincodeCodec, UnknownVariantPolicy, VersionedCodec};
+    use versioned::*;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12354)?;
+    let codec = VersionedCodec::new(BincodeCodec, 1)
+        .with_unknown_variant_policy(UnknownVariantPolicy::Skip);
+    let listener = QuinnListener::<OldRequest, Response>::new(server)?.with_codec(codec);
+    let server = RpcServer::<OldService, _>::new(listener);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        match req {
+            OldRequest::Echo(req) => chan.rpc(req, (), |_, Echo(x)| async move { x }).await,
+        }
+    });
+    let client =
+        QuinnConnector::<Response, NewRequest>::new(client, server_addr, "localhost".into())
+            .with_codec(VersionedCodec::new(BincodeCodec, 2));
+    let client = RpcClient::<NewService, _>::new(client);
+    let res = client.rpc(Shout("hello".into())).await;
+    // the server drops the channel without a response
+    assert!(res.is_err(), "unexpected result {res:?}");
+    assert_eq!(client.rpc(Echo(7)).await?, 7);
+    Ok(())
+}
+
+/// The connector reports when its connection is established and when it is lost.
+#[tokio::test]
+async fn quinn_connection_events() -> TestResult<()> {
+    use futures::StreamExt;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12355)?;
+    let _server_handle = run_server(server.clone());
+    let connector = QuinnConnector::new(client, server_addr, "localhost".into());
+    let mut events = std::pin::pin!(connector.events());
+    let client = RpcClient::<ComputeService, _>::new(connector);
+    let SqrResponse(response) = client.rpc(Sqr(4)).await?;
+    assert_eq!(response, 16);
+    let event = tokio::time::timeout(Duration::from_secs(1), events.next()).await?;
+    assert_eq!(
+        event,
+        Some(ConnectionEvent::Connected {
+            remote: server_addr
+        })
+    );
+
+    server.close(0u32.into(), b"bye");
+    let event = tokio::time::timeout(Duration::from_secs(1), events.next()).await?;
+    assert!(
+        matches!(event, Some(ConnectionEvent::Lost(_))),
+        "unexpected event {event:?}"
+    );
+    Ok(())
+}
+
+/// the events end when the connector is dropped, even if the connection stays open
+#[tokio::test]
+async fn quinn_connection_events_end() -> TestResult<()> {
+    use futures::StreamExt;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12375)?;
+    let server_connection = tokio::spawn(async move {
+        let incoming = server.accept().await.expect("server endpoint closed");
+        anyhow::Ok(incoming.await?)
+    });
+    let connection = client.connect(server_addr, "localhost")?.await?;
+    let _server_connection = server_connection.await??;
+    let connector =
+        QuinnConnector::<ComputeResponse, ComputeRequest>::from_connection(connection.clone());
+    let mut events = std::pin::pin!(connector.events());
+    drop(connector);
+    let event = tokio::time::timeout(Duration::from_secs(5), events.next()).await?;
+    assert_eq!(event, None);
+    assert!(connection.close_reason().is_none());
+    Ok(())
+}
+
+/// Transport settings are validated, and valid settings are used for both sides.
+#[tokio::test]
+async fn quinn_transport_settings() -> TestResult<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let keep_alive = KeepAliveConfig::default().keep_alive_interval(Some(Duration::from_secs(5)));
+    let res = TransportSettings::default()
+        .keep_alive(keep_alive.clone())
+        .max_idle_timeout(Some(Duration::from_secs(5)))
+        .transport_config();
+    assert!(matches!(
+        res,
+        Err(TransportConfigError::KeepAliveNotBelowIdleTimeout { .. })
+    ));
+
+    let server_addr: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12356));
+    let (server_config, server_cert) = configure_server()?;
+    let settings = TransportSettings::default()
+        .keep_alive(keep_alive)
+        .max_idle_timeout(Some(Duration::from_secs(10)))
+        .max_concurrent_bidi_streams(1024)
+        .stream_receive_window(1024 * 1024)
+        .send_window(8 * 1024 * 1024);
+    let server = Endpoint::server(server_config.clone(), server_addr)?;
+    let listener = QuinnListener::with_transport_settings(server, server_config, settings.clone())?;
+    let _server_handle = ComputeService::server(RpcServer::new(listener));
+    let client = Endpoint::client("0.0.0.0:0".parse()?)?;
+    let connector = QuinnConnector::with_transport_settings(
+        client,
+        server_addr,
+        "localhost".into(),
+        configure_client(&[&server_cert])?,
+        settings,
+    )?;
+    let client = RpcClient::<ComputeService, _>::new(connector);
+    let calls = (0..64u64).map(|i| client.rpc(Sqr(i)));
+    let res = futures::future::try_join_all(calls).await?;
+    let expected: Vec<_> = (0..64u128).map(|i| SqrResponse(i * i)).collect();
+    assert_eq!(res, expected);
+    Ok(())
+}
+
+/// Pings are answered by the listener without involving the rpc handlers.
+#[tokio::test]
+async fn quinn_ping() -> TestResult<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let server_addr: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12357));
+    let (server, _) = make_server_endpoint(server_addr)?;
+    let (server_config, server_cert) = configure_heartbeat_server()?;
+    let listener = QuinnListener::with_keep_alive(server, server_config, None)?;
+    let _server_handle = ComputeService::server(RpcServer::new(listener));
+
+    let client = make_client_endpoint("0.0.0.0:0".parse()?, &[&server_cert])?;
+    let connector = QuinnConnector::new(client, server_addr, "localhost".into());
+    let client = RpcClient::<ComputeService, _>::new(connector).boxed();
+    for _ in 0..3 {
+        let rtt = tokio::time::timeout(Duration::from_secs(5), client.ping()).await??;
+        assert!(rtt < Duration::from_secs(1));
+    }
+    let SqrResponse(response) = client.rpc(Sqr(4)).await?;
+    assert_eq!(response, 16);
+    Ok(())
+}
+
+/// A ping to a listener that does not answer heartbeats times out.
+#[tokio::test]
+async fn quinn_ping_timeout() -> TestResult<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server_config, server_cert) = configure_heartbeat_server()?;
+    let server = Endpoint::server(server_config, "127.0.0.1:0".parse()?)?;
+    let server_addr = server.local_addr()?;
+    let _server_handle = ComputeService::server(RpcServer::new(QuinnListener::new(server)?));
+
+    let client = make_client_endpoint("0.0.0.0:0".parse()?, &[&server_cert])?;
+    let connector = QuinnConnector::new(client, server_addr, "localhost".into())
+        .with_ping_timeout(Duration::from_millis(200));
+    let client = RpcClient::<ComputeService, _>::new(connector).boxed();
+    assert_eq!(client.ping().await, Err(PingError::Timeout));
+    let SqrResponse(response) = client.rpc(Sqr(4)).await?;
+    assert_eq!(response, 16);
+    Ok(())
+}
+
+/// The listener resets substreams that are still in use, waking up their handlers.
+#[tokio::test]
+async fn quinn_reset_active_streams() -> TestResult<()> {
+    use futures::{SinkExt, StreamExt};
+    use transport::{ConnectionErrors, Connector, Listener};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12358)?;
+    let listener = QuinnListener::<ComputeRequest, ComputeResponse>::new(server)?;
+    let connector = QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client,
+        server_addr,
+        "localhost".into(),
+    );
+    let (mut client_send, mut client_recv) = connector.open().await?;
+    // the substream only shows up at the listener once something was sent
+    client_send.send(Sqr(2).into()).await?;
+    let (mut server_send, mut server_recv) = listener.accept().await?;
+    let first = server_recv.next().await.transpose()?;
+    assert!(matches!(first, Some(ComputeRequest::Sqr(Sqr(2)))));
+
+    let active = listener.active_streams();
+    assert_eq!(active.len(), 1);
+    assert!(active[0].connection.is_some());
+
+    // the handler is waiting for the next request when the reset happens
+    let handler = tokio::spawn(async move {
+        let next = server_recv.next().await;
+        let sent = server_send.send(SqrResponse(4).into()).await;
+        (next, sent)
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let old = |stream: &transport::quinn::ActiveStream| stream.age() > Duration::from_secs(60);
+    assert_eq!(listener.reset_streams(7u32.into(), old), 0);
+    assert_eq!(listener.reset_all(7u32.into()), 1);
+    let (next, sent) = tokio::time::timeout(Duration::from_secs(5), handler).await??;
+    assert!(matches!(next, Some(Err(e)) if e.kind() == std::io::ErrorKind::ConnectionReset));
+    assert_eq!(
+        sent.unwrap_err().kind(),
+        std::io::ErrorKind::ConnectionReset
+    );
+    assert!(listener.active_streams().is_empty());
+    assert!(!listener.reset_stream(&active[0], 7u32.into()));
+
+    // the remote sees the substream as reset
+    let res = tokio::time::timeout(Duration::from_secs(5), client_recv.next()).await?;
+    let Some(Err(e)) = res else {
+        panic!("unexpected result {res:?}");
+    };
+    assert!(QuinnConnector::<ComputeResponse, ComputeRequest>::is_reset(
+        &e
+    ));
+    Ok(())
+}
+
+/// A server that stops reading updates stops the substream, which the client sees as
+/// a closed server side, like with the flume transport.
+#[tokio::test]
+async fn quinn_client_streaming_early_response() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints { client, server, .. } = make_endpoints(0)?;
+    let server_addr = server.local_addr()?;
+    let listener = QuinnListener::new(server)?;
+    let connector = QuinnConnector::new(client, server_addr, "localhost".into());
+    early_response_test(listener, connector).await
+}
+
+/// A reset substream fails an rpc call with an early close.
+#[tokio::test]
+async fn quinn_rpc_reset_early_close() -> TestResult<()> {
+    use quic_rpc::client::CallError;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints { client, server, .. } = make_endpoints(0)?;
+    let server_addr = server.local_addr()?;
+    let listener = QuinnListener::<ComputeRequest, ComputeResponse>::new(server)?;
+    let server = RpcServer::<ComputeService, _>::new(listener.clone());
+    // the handler never responds, so the substream stays in use until it is reset
+    let _server_handle = server.spawn_accept_loop(|_req, _chan| async move {
+        std::future::pending::<()>().await;
+        anyhow::Ok(())
+    });
+    let connector = QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client,
+        server_addr,
+        "localhost".into(),
+    );
+    let client = RpcClient::<ComputeService, _>::new(connector);
+    let call = tokio::spawn(async move { client.rpc(Sqr(2)).await });
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while listener.active_streams().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+    assert_eq!(listener.reset_all(7u32.into()), 1);
+    let res = tokio::time::timeout(Duration::from_secs(5), call).await??;
+    assert!(matches!(res, Err(CallError::EarlyClose)), "{res:?}");
+    Ok(())
+}
+
+/// A reset substream ends the responses of a bidi call with an early close, like it does
+/// for an rpc call.
+#[tokio::test]
+async fn quinn_bidi_reset_early_close() -> TestResult<()> {
+    use futures::StreamExt;
+    use quic_rpc::client::CallError;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints { client, server, .. } = make_endpoints(0)?;
+    let server_addr = server.local_addr()?;
+    let listener = QuinnListener::<ComputeRequest, ComputeResponse>::new(server)?;
+    let server = RpcServer::<ComputeService, _>::new(listener.clone());
+    // the handler never responds, so the substream stays in use until it is reset
+    let _server_handle = server.spawn_accept_loop(|_req, _chan| async move {
+        std::future::pending::<()>().await;
+        anyhow::Ok(())
+    });
+    let connector = QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client,
+        server_addr,
+        "localhost".into(),
+    );
+    let client = RpcClient::<ComputeService, _>::new(connector);
+    let (_updates, mut responses) = client.bidi(Multiply(2)).await?;
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while listener.active_streams().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+    assert_eq!(listener.reset_all(7u32.into()), 1);
+    let res = tokio::time::timeout(Duration::from_secs(5), responses.next()).await?;
+    assert!(matches!(res, Some(Err(CallError::EarlyClose))), "{res:?}");
+    Ok(())
+}
+
+/// Dropping the send side half-closes a stream, the same for all transports, see
+/// `tcp_half_close`
+#[tokio::test]
+async fn quinn_half_close() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints { client, server, .. } = make_endpoints(0)?;
+    let server_addr = server.local_addr()?;
+    let listener = QuinnListener::<u64, u64>::new(server)?;
+    let connector = QuinnConnector::<u64, u64>::new(client, server_addr, "localhost".into());
+    util::check_half_close(connector, listener).await
+}
+
+/// Dropping both sides resets a stream, the same for all transports, see `tcp_reset`
+#[tokio::test]
+async fn quinn_reset() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints { client, server, .. } = make_endpoints(0)?;
+    let server_addr = server.local_addr()?;
+    let listener = QuinnListener::<u64, u64>::new(server)?;
+    let connector = QuinnConnector::<u64, u64>::new(client, server_addr, "localhost".into());
+    util::check_reset(connector, listener).await
+}
+
+/// Closing the update sink waits until the server has received all updates, so closing
+/// the connection right after does not lose any of them.
+#[tokio::test]
+async fn quinn_close_delivers_updates() -> TestResult<()> {
+    use futures::{SinkExt, StreamExt};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints { client, server, .. } = make_endpoints(0)?;
+    let server_addr = server.local_addr()?;
+    let server = RpcServer::<ComputeService, _>::new(QuinnListener::new(server)?);
+    let (sum_tx, sum_rx) = tokio::sync::oneshot::channel();
+    let _server_handle = AbortOnDropHandle::new(tokio::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let ComputeRequest::Sum(req) = req else {
+            panic!("unexpected request {req:?}");
+        };
+        chan.client_streaming(req, (), move |_, _, updates| async move {
+            let mut updates = updates.drain_on_close();
+            let mut sum = 0u128;
+            while let Some(SumUpdate(x)) = updates.next().await {
+                sum += x as u128;
+            }
+            sum_tx.send(sum).ok();
+            SumResponse(sum)
+        })
+        .await?;
+        TestResult::Ok(())
+    }));
+
+    let endpoint = client.clone();
+    let client = QuinnConnector::new(client, server_addr, "localhost".into());
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let (mut send, _res) = client.client_streaming(Sum).await?;
+    let n = 1000u64;
+    for i in 0..n {
+        send.feed(SumUpdate(i)).await?;
+    }
+    send.close_with_timeout(Duration::from_secs(5)).await?;
+    // close the connection without waiting for the response
+    endpoint.close(0u32.into(), b"done");
+    let sum = tokio::time::timeout(Duration::from_secs(5), sum_rx).await??;
+    assert_eq!(sum, (0..n as u128).sum::<u128>());
+    Ok(())
+}
+
+/// Dropping a streaming call cancels its handler, although its updates just end as if
+/// the client had finished them.
+#[tokio::test]
+async fn quinn_cancel_streaming() -> TestResult<()> {
+    use futures::SinkExt;
+    use hang::{next_event, Event, HangBidi, HangClientStreaming, HangService, HangUpdate};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints { client, server, .. } = make_endpoints(0)?;
+    let server_addr = server.local_addr()?;
+    let (mut events, _server) = hang::serve(QuinnListener::new(server)?);
+    let client = QuinnConnector::new(client, server_addr, "localhost".into());
+    let client = RpcClient::<HangService, _>::new(client);
+
+    let (mut send, recv) = client.client_streaming(HangClientStreaming).await?;
+    send.send(HangUpdate).await?;
+    assert_eq!(next_event(&mut events).await?, Event::Started);
+    drop((send, recv));
+    assert_eq!(next_event(&mut events).await?, Event::Dropped);
+
+    let (mut send, recv) = client.bidi(HangBidi).await?;
+    send.send(HangUpdate).await?;
+    assert_eq!(next_event(&mut events).await?, Event::Started);
+    drop((send, recv));
+    assert_eq!(next_event(&mut events).await?, Event::Dropped);
+    Ok(())
+}
+
+/// Connections without substreams in use are evicted once they were idle for long enough.
+#[tokio::test]
+async fn quinn_idle_eviction() -> TestResult<()> {
+    use futures::{SinkExt, StreamExt};
+    use quic_rpc::metrics::ServerMetrics;
+    use transport::{Connector, Listener};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12360)?;
+    let metrics = Arc::new(ServerMetrics::default());
+    let listener = QuinnListener::<ComputeRequest, ComputeResponse>::new(server)?;
+    let connector = QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client,
+        server_addr,
+        "localhost".into(),
+    );
+    let mut events = std::pin::pin!(connector.events());
+    let (mut client_send, mut client_recv) = connector.open().await?;
+    client_send.send(Sqr(2).into()).await?;
+    let (mut server_send, mut server_recv) = listener.accept().await?;
+
+    // evict as soon as the connection is idle, which also applies to open connections
+    let eviction = IdleEviction::new(Duration::ZERO)
+        .code(42u32.into())
+        .counters(metrics.connections.clone());
+    let _listener = listener.clone().with_idle_eviction(Some(eviction));
+
+    // a substream in use keeps the connection open
+    assert!(matches!(
+        server_recv.next().await,
+        Some(Ok(ComputeRequest::Sqr(Sqr(2))))
+    ));
+    server_send.send(SqrResponse(4).into()).await?;
+    assert!(matches!(
+        client_recv.next().await,
+        Some(Ok(ComputeResponse::SqrResponse(SqrResponse(4))))
+    ));
+    assert_eq!(metrics.connections.evicted.load(), 0);
+
+    drop((server_send, server_recv));
+    let lost = async {
+        loop {
+            match events.next().await {
+                Some(ConnectionEvent::Lost(reason)) => break Some(reason),
+                Some(_) => continue,
+                None => break None,
+            }
+        }
+    };
+    let reason = tokio::time::timeout(Duration::from_secs(5), lost).await?;
+    assert!(
+        matches!(
+            &reason,
+            Some(quinn::ConnectionError::ApplicationClosed(close)) if close.error_code == 42u32.into()
+        ),
+        "{reason:?}"
+    );
+    assert_eq!(metrics.connections.evicted.load(), 1);
+    Ok(())
+}
+
+/// the server pushes a channel to the client next to the calls of the client
+#[tokio::test]
+async fn quinn_server_push() -> TestResult<()> {
+    use futures::{SinkExt, StreamExt};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12361)?;
+    let (answers, mut answers_recv) = tokio::sync::mpsc::unbounded_channel();
+    let server = RpcServer::<ComputeService, _>::new(QuinnListener::new(server)?);
+    let _server_handle = server.spawn_accept_loop(move |req, chan| {
+        let answers = answers.clone();
+        async move {
+            let connection = chan.connection().cloned();
+            ComputeService.handle_rpc_request(req, chan).await?;
+            // after answering the call, push a message and wait for the client to answer
+            let connection = connection.expect("quinn supports push");
+            let (mut send, mut recv) = connection.open_push().await?;
+            send.send(SqrResponse(9).into()).await?;
+            if let Some(answer) = recv.next().await {
+                answers.send(answer?).ok();
+            }
+            anyhow::Ok(())
+        }
+    });
+    let connector = QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client,
+        server_addr,
+        "localhost".into(),
+    );
+    let client = RpcClient::<ComputeService, _>::new(connector);
+    let mut pushes = std::pin::pin!(client.accept_push());
+
+    // the pushed channel does not interfere with the call
+    assert_eq!(client.rpc(Sqr(2)).await?, SqrResponse(4));
+    let mut push = tokio::time::timeout(Duration::from_secs(5), pushes.next())
+        .await?
+        .expect("push channel");
+    let pushed = push.recv.next().await.transpose()?;
+    assert!(
+        matches!(pushed, Some(ComputeResponse::SqrResponse(SqrResponse(9)))),
+        "{pushed:?}"
+    );
+    push.send.send(Sqr(3).into()).await?;
+    let answer = tokio::time::timeout(Duration::from_secs(5), answers_recv.recv()).await?;
+    assert!(
+        matches!(answer, Some(ComputeRequest::Sqr(Sqr(3)))),
+        "{answer:?}"
+    );
+
+    // calls keep working while a push channel is open
+    assert_eq!(client.rpc(Sqr(4)).await?, SqrResponse(16));
+    Ok(())
+}
+
+/// pushing works through the boxed listener and connector
+#[tokio::test]
+async fn quinn_server_push_boxed() -> TestResult<()> {
+    use futures::{SinkExt, StreamExt};
+    use transport::{Connector, Listener};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12376)?;
+    let server = RpcServer::<ComputeService>::new(QuinnListener::new(server)?.boxed());
+    let _server_handle = server.spawn_accept_loop(move |req, chan| async move {
+        let connection = chan.connection().cloned();
+        ComputeService.handle_rpc_request(req, chan).await?;
+        let connection = connection.expect("the boxed listener forwards the connection");
+        let (mut send, _recv) = connection.open_push().await?;
+        send.send(SqrResponse(9).into()).await?;
+        anyhow::Ok(())
+    });
+    let connector = QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client,
+        server_addr,
+        "localhost".into(),
+    );
+    let client = RpcClient::<ComputeService>::new(connector.boxed());
+    let mut pushes = std::pin::pin!(client.accept_push());
+
+    assert_eq!(client.rpc(Sqr(2)).await?, SqrResponse(4));
+    let mut push = tokio::time::timeout(Duration::from_secs(5), pushes.next())
+        .await?
+        .expect("push channel");
+    let pushed = push.recv.next().await.transpose()?;
+    assert!(
+        matches!(pushed, Some(ComputeResponse::SqrResponse(SqrResponse(9)))),
+        "{pushed:?}"
+    );
+    Ok(())
+}
+
+/// both ends can set the priority of a stream, also through a boxed connector
+#[tokio::test]
+async fn quinn_stream_priority() -> TestResult<()> {
+    use futures::{SinkExt, StreamExt};
+    use transport::Connector;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12362)?;
+    let (results, mut results_recv) = tokio::sync::mpsc::unbounded_channel();
+    let server = RpcServer::<ComputeService, _>::new(QuinnListener::new(server)?);
+    let _server_handle = server.spawn_accept_loop(move |req, chan| {
+        results.send(chan.set_priority(1)).ok();
+        ComputeService.handle_rpc_request(req, chan)
+    });
+    let connector = QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client,
+        server_addr,
+        "localhost".into(),
+    );
+    let client = RpcClient::<ComputeService, _>::new(connector.boxed());
+    let (mut updates, mut responses) = client.bidi(Multiply(2)).await?;
+    updates.set_priority(10)?;
+    updates.send(MultiplyUpdate(3)).await?;
+    let response = responses.next().await.transpose()?;
+    assert!(
+        matches!(response, Some(MultiplyResponse(6))),
+        "{response:?}"
+    );
+    assert_eq!(results_recv.recv().await, Some(Ok(())));
+    Ok(())
+}
+
+/// connectors on a shared endpoint use one connection per server
+#[tokio::test]
+async fn quinn_shared_endpoint() -> TestResult<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let addr1: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12363));
+    let addr2: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12364));
+    let (server1, cert1) = make_server_endpoint(addr1)?;
+    let (server2, cert2) = make_server_endpoint(addr2)?;
+    let _server1_handle = run_server(server1.clone());
+    let _server2_handle = run_server(server2.clone());
+    let endpoint = make_client_endpoint("0.0.0.0:0".parse()?, &[&cert1, &cert2])?;
+    let endpoint = SharedEndpoint::new(endpoint);
+
+    let connector = |addr| {
+        let connector = QuinnConnector::<ComputeResponse, ComputeRequest>::with_endpoint(
+            &endpoint,
+            addr,
+            "localhost".into(),
+        );
+        RpcClient::<ComputeService, _>::new(connector)
+    };
+    let client1 = connector(addr1);
+    let client2 = connector(addr1);
+    let client3 = connector(addr2);
+    for client in [&client1, &client2, &client3] {
+        assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+    }
+    assert_eq!(server1.open_connections(), 1);
+    assert_eq!(server2.open_connections(), 1);
+    assert_eq!(endpoint.endpoint().open_connections(), 2);
+
+    // the endpoint and the shared connection outlive a dropped connector
+    drop(client1);
+    assert_eq!(client2.rpc(Sqr(4)).await?, SqrResponse(16));
+    assert_eq!(server1.open_connections(), 1);
+    Ok(())
+}
+
+/// datagrams are sent next to the calls, in both directions
+#[tokio::test]
+async fn quinn_datagrams() -> TestResult<()> {
+    use futures::StreamExt;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12365)?;
+    let server = RpcServer::<ComputeService, _>::new(QuinnListener::new(server)?);
+    let _server_handle = server.spawn_accept_loop(move |req, chan| async move {
+        let connection = chan
+            .connection()
+            .cloned()
+            .expect("quinn supports datagrams");
+        ComputeService.handle_rpc_request(req, chan).await?;
+        // echo a single datagram back to the client
+        let datagrams = connection.datagrams();
+        tokio::pin!(datagrams);
+        if let Some(data) = datagrams.next().await {
+            connection.send_datagram(data)?;
+        }
+        anyhow::Ok(())
+    });
+    let connector = QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client,
+        server_addr,
+        "localhost".into(),
+    );
+    // no connection yet, and sending does not wait for one
+    assert_eq!(
+        connector.send_datagram(&b"early"[..]),
+        Err(transport::DatagramError::Closed)
+    );
+    let mut datagrams = connector.datagrams();
+    let client = RpcClient::<ComputeService, _>::new(connector.clone());
+    assert_eq!(client.rpc(Sqr(2)).await?, SqrResponse(4));
+
+    // a datagram larger than the path allows is rejected right away
+    let max = connector
+        .max_datagram_size()
+        .expect("datagrams are enabled");
+    let res = connector.send_datagram(vec![0u8; max + 1]);
+    assert_eq!(
+        res,
+        Err(transport::DatagramError::TooLarge { size: max + 1, max })
+    );
+
+    // datagrams may be lost, so send until one is echoed
+    let echoed = tokio::time::timeout(Duration::from_secs(5), async {
+        loop {
+            connector.send_datagram(&b"telemetry"[..])?;
+            let next = tokio::time::timeout(Duration::from_millis(100), datagrams.next());
+            if let Ok(data) = next.await {
+                return anyhow::Ok(data);
+            }
+        }
+    })
+    .await??;
+    assert_eq!(echoed.as_deref(), Some(&b"telemetry"[..]));
+
+    // calls keep working next to datagrams
+    assert_eq!(client.rpc(Sqr(4)).await?, SqrResponse(16));
+    Ok(())
+}
+
+/// Waits until the connector has `n` substreams waiting for their next call
+async fn idle_substreams(
+    connector: &QuinnConnector<ComputeResponse, ComputeRequest>,
+    n: usize,
+) -> anyhow::Result<()> {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while connector.idle_substreams() != n {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await?;
+    Ok(())
+}
+
+/// calls on a connector with a substream pool reuse the substreams of earlier calls
+#[tokio::test]
+async fn quinn_substream_pool() -> TestResult<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12366)?;
+    let listener = QuinnListener::new(server)?;
+    let streams = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let server = RpcServer::<ComputeService, _>::new(listener.clone());
+    let _server_handle = server.spawn_accept_loop({
+        let streams = streams.clone();
+        move |req, chan| {
+            let active = listener.active_streams();
+            streams
+                .lock()
+                .unwrap()
+                .extend(active.into_iter().map(|stream| stream.id));
+            ComputeService.handle_rpc_request(req, chan)
+        }
+    });
+    let connector = QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client,
+        server_addr,
+        "localhost".into(),
+    )
+    .with_substream_pool(SubstreamPool::new(4).idle_ttl(Duration::from_millis(500)));
+    let client = RpcClient::<ComputeService, _>::new(connector.clone());
+    for i in 0..5 {
+        assert_eq!(client.rpc(Sqr(i)).await?, SqrResponse((i * i) as u128));
+        idle_substreams(&connector, 1).await?;
+    }
+    let ids = std::mem::take(&mut *streams.lock().unwrap());
+    assert_eq!(ids.len(), 5);
+    assert!(ids.iter().all(|id| *id == ids[0]));
+    let pooled = ids[0];
+
+    // the other patterns get substreams of their own, which are not pooled
+    smoke_test(connector.clone()).await?;
+    idle_substreams(&connector, 1).await?;
+    let ids = std::mem::take(&mut *streams.lock().unwrap());
+    assert!(ids.iter().any(|id| *id != pooled));
+
+    // a substream that was idle for longer than the ttl is not reused
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+    let ids2 = std::mem::take(&mut *streams.lock().unwrap());
+    assert_eq!(ids2.len(), 1);
+    assert_ne!(ids2[0], pooled);
+    Ok(())
+}
+
+/// a rpc call that is dropped before its response still leaves the substream reusable
+#[tokio::test]
+async fn quinn_substream_pool_dropped_call() -> TestResult<()> {
+    use futures::SinkExt;
+    use transport::Connector;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12367)?;
+    let _server_handle = run_server(server);
+    let connector = QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client,
+        server_addr,
+        "localhost".into(),
+    )
+    .with_substream_pool(SubstreamPool::new(1));
+    let (mut send, recv) = connector.open_rpc().await?;
+    send.send(Sqr(4).into()).await?;
+    drop((send, recv));
+    // the response is skipped before the substream is put back
+    idle_substreams(&connector, 1).await?;
+    let client = RpcClient::<ComputeService, _>::new(connector.clone());
+    assert_eq!(client.rpc(Sqr(5)).await?, SqrResponse(25));
+    idle_substreams(&connector, 1).await?;
+    Ok(())
+}
+
+/// the connection of a connector stays open until the last clone is dropped, unless one
+/// of them closes it, which also fails the substreams of the others. A connection passed
+/// to from_connection is left open.
+#[tokio::test]
+async fn quinn_connector_close() -> TestResult<()> {
+    use futures::{SinkExt, StreamExt};
+    use transport::Connector;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12368)?;
+    let accept = || {
+        let server = server.clone();
+        async move {
+            let incoming = server.accept().await.expect("server endpoint closed");
+            anyhow::Ok(incoming.await?)
+        }
+    };
+
+    let server_connection = tokio::spawn(accept());
+    let connection = client.connect(server_addr, "localhost")?.await?;
+    let server_connection = server_connection.await??;
+    let connector =
+        QuinnConnector::<ComputeResponse, ComputeRequest>::from_connection(connection.clone());
+    let other = connector.clone();
+    drop(connector);
+    drop(other);
+    // the caller can still use the connection
+    let mut send = connection.open_uni().await?;
+    send.write_all(b"still open").await?;
+    send.finish()?;
+    let mut recv = server_connection.accept_uni().await?;
+    assert_eq!(recv.read_to_end(100).await?, b"still open");
+    assert!(server_connection.close_reason().is_none());
+    connection.close(0u32.into(), b"done");
+
+    let server_connection = tokio::spawn(accept());
+    let connector = QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client.clone(),
+        server_addr,
+        "localhost".into(),
+    );
+    let other = connector.clone();
+    connector.open().await?;
+    let server_connection = server_connection.await??;
+    drop(connector);
+    // the other clone still uses the same connection
+    let (mut send, _recv) = other.open().await?;
+    send.send(Sqr(2).into()).await?;
+    server_connection.accept_bi().await?;
+    drop(other);
+    let reason = tokio::time::timeout(Duration::from_secs(5), server_connection.closed()).await?;
+    assert!(
+        matches!(&reason, quinn::ConnectionError::ApplicationClosed(_)),
+        "{reason:?}"
+    );
+
+    let server_connection = tokio::spawn(accept());
+    let connector = QuinnConnector::<ComputeResponse, ComputeRequest>::new(
+        client,
+        server_addr,
+        "localhost".into(),
+    );
+    let other = connector.clone();
+    let (mut send, mut recv) = other.open().await?;
+    send.send(Sqr(2).into()).await?;
+    let server_connection = server_connection.await??;
+    connector.close(7u32.into(), b"shutting down");
+    assert!(other.is_closed());
+    // the substream of the other clone fails, and no new connection is made
+    let res = tokio::time::timeout(Duration::from_secs(5), recv.next()).await?;
+    assert!(matches!(res, Some(Err(_))));
+    assert!(matches!(
+        other.open().await,
+        Err(quinn::ConnectionError::LocallyClosed)
+    ));
+    let reason = tokio::time::timeout(Duration::from_secs(5), server_connection.closed()).await?;
+    assert!(
+        matches!(
+            &reason,
+            quinn::ConnectionError::ApplicationClosed(close)
+                if close.error_code == 7u32.into() && &close.reason[..] == b"shutting down"
+        ),
+        "{reason:?}"
+    );
+    Ok(())
+}
+
+/// a resolved remote is connected to using the first address that works, and the server
+/// name for TLS is independent of the resolved host
+#[tokio::test]
+async fn quinn_resolver_tries_addresses_in_order() -> TestResult<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12369)?;
+    let _server_handle = run_server(server);
+    // a server with a certificate the client does not trust, the handshake fails
+    let bad_addr: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 12370));
+    let (bad_server, _) = make_server_endpoint(bad_addr)?;
+    let _bad_server_handle = run_server(bad_server.clone());
+
+    let (resolved_tx, mut resolved_rx) = tokio::sync::mpsc::unbounded_channel();
+    let remote = Remote::resolve("compute.internal", move |host: &str| {
+        resolved_tx.send(host.to_string()).ok();
+        async move { Ok::<_, ResolveError>(vec![bad_addr, server_addr]) }
+    });
+    let connector = QuinnConnector::new_with_remote(client, remote, "localhost".into());
+    let client = RpcClient::<ComputeService, _>::new(connector);
+    let res = tokio::time::timeout(Duration::from_secs(5), client.rpc(Sqr(4))).await??;
+    assert_eq!(res, SqrResponse(16));
+    assert_eq!(
+        resolved_rx.recv().await.as_deref(),
+        Some("compute.internal")
+    );
+    assert_eq!(bad_server.open_connections(), 0);
+    Ok(())
+}
+
+/// the error of a failed resolve is reported as an event, opening the channel fails
+#[tokio::test]
+async fn quinn_resolve_error_event() -> TestResult<()> {
+    use futures::StreamExt;
+    use quic_rpc::client::CallError;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints { client, .. } = make_endpoints(12374)?;
+    let remote = Remote::resolve("compute.internal", |_: &str| async {
+        Err::<Vec<SocketAddr>, _>(ResolveError::new("no such host"))
+    });
+    let connector = QuinnConnector::new_with_remote(client, remote, "localhost".into());
+    let mut events = std::pin::pin!(connector.events());
+    let client = RpcClient::<ComputeService, _>::new(connector);
+    let res = tokio::time::timeout(Duration::from_secs(5), client.rpc(Sqr(4))).await?;
+    assert!(
+        matches!(res, Err(CallError::Open(quinn::ConnectionError::Reset))),
+        "{res:?}"
+    );
+    let event = tokio::time::timeout(Duration::from_secs(1), events.next()).await?;
+    let Some(ConnectionEvent::ResolveFailed(cause)) = event else {
+        panic!("unexpected event {event:?}");
+    };
+    assert_eq!(cause.to_string(), "failed to resolve: no such host");
+    Ok(())
+}
+
+/// a handler that runs longer than the handler timeout is cancelled, also through a boxed
+/// listener, and the client can tell
+#[tokio::test]
+async fn quinn_handler_timeout() -> TestResult<()> {
+    use quic_rpc::{client::CallError, server::RpcServerError};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let Endpoints {
+        client,
+        server,
+        server_addr,
+    } = make_endpoints(12373)?;
+    let server = RpcServer::<ComputeService, _>::new(QuinnListener::new(server)?)
+        .boxed()
+        .with_handler_timeout(Duration::from_millis(100));
+    let server_handle = tokio::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        let ComputeRequest::Sqr(req) = req else {
+            panic!("unexpected request {req:?}");
+        };
+        let res = chan
+            .rpc(req, (), |_, _| std::future::pending::<SqrResponse>())
+            .await;
+        TestResult::Ok(matches!(res, Err(RpcServerError::HandlerTimeout)))
+    });
+    let client = QuinnConnector::new(client, server_addr, "localhost".into());
+    let client = RpcClient::<ComputeService, _>::new(client);
+    let res = tokio::time::timeout(Duration::from_secs(5), client.rpc(Sqr(2))).await?;
+    assert!(matches!(res, Err(CallError::Cancelled)), "{res:?}");
+    assert!(server_handle.await??);
+    Ok(())
+}
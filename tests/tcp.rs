@@ -0,0 +1,553 @@
+#![cfg(feature = "tcp-transport")]
+use std::{sync::Arc, time::Duration};
+
+use quic_rpc::{
+    client::CallError,
+    declare_rpc,
+    transport::{
+        auth::{auth_error, AuthError, AuthToken, ClientAuth, Identity, ServerAuth},
+        lifecycle::LifecycleEvent,
+        multiplex::{MultiplexConnector, MultiplexListener, ServiceTag},
+        tcp::{TcpConnector, TcpListener},
+        testing::{decode_frame, encode_frame},
+        Listener, LocalAddr,
+    },
+    RpcClient, RpcServer, Service,
+};
+use quinn::rustls;
+use serde::{Deserialize, Serialize};
+use tokio_util::task::AbortOnDropHandle;
+
+mod math;
+use math::*;
+mod util;
+
+#[tokio::test]
+async fn tcp_channel_smoke() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let listener = TcpListener::bind("127.0.0.1:0".parse()?).await?;
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    let _server_handle = ComputeService::server(RpcServer::new(listener));
+    let connector = TcpConnector::new(addr);
+    smoke_test(connector.clone()).await?;
+
+    // concurrent calls are substreams of the same connection
+    let client = RpcClient::<ComputeService, _>::new(connector);
+    let calls = (0..16u64).map(|i| client.rpc(Sqr(i)));
+    let res = futures::future::try_join_all(calls).await?;
+    let expected: Vec<_> = (0..16u128).map(|i| SqrResponse(i * i)).collect();
+    assert_eq!(res, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn tcp_channel_tls_smoke() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()])?;
+    let key = rustls::pki_types::PrivatePkcs8KeyDer::from(cert.key_pair.serialize_der());
+    let server_config = rustls::ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(vec![cert.cert.der().clone()], key.into())?;
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert.cert.der().clone())?;
+    let client_config = rustls::ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+
+    let listener = TcpListener::<ComputeRequest, ComputeResponse>::builder()
+        .tls(Arc::new(server_config))
+        .bind("127.0.0.1:0".parse()?)
+        .await?;
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    let _server_handle = ComputeService::server(RpcServer::new(listener));
+    let connector = TcpConnector::<ComputeResponse, ComputeRequest>::builder(addr)
+        .tls(Arc::new(client_config), "localhost".try_into()?)
+        .build();
+    smoke_test(connector).await?;
+    Ok(())
+}
+
+/// the wire format of a request, as a golden byte sequence
+#[test]
+fn tcp_wire_format() -> anyhow::Result<()> {
+    let golden = [0, 0, 0, 12, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0];
+    assert_eq!(encode_frame(&ComputeRequest::from(Sqr(2))), golden);
+    let req: ComputeRequest = decode_frame(&golden)?;
+    assert!(matches!(req, ComputeRequest::Sqr(Sqr(2))), "{req:?}");
+    // a truncated frame or data after the frame is rejected
+    assert!(decode_frame::<ComputeRequest>(&golden[..10]).is_err());
+    assert!(decode_frame::<ComputeRequest>(&[&golden[..], &[0]].concat()).is_err());
+    Ok(())
+}
+
+/// a connection that opens many channels does not starve another one
+#[tokio::test]
+async fn tcp_max_concurrent_per_peer() -> anyhow::Result<()> {
+    use futures::{stream::FuturesUnordered, StreamExt};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let listener = TcpListener::bind("127.0.0.1:0".parse()?).await?;
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    let server = RpcServer::<ComputeService, _>::new(listener)
+        .with_max_concurrent(4)
+        .with_max_concurrent_per_peer(2, |info| info.addr);
+    let _server_handle = server.spawn_accept_loop(|req, chan| async move {
+        // squaring 0 never completes
+        if let ComputeRequest::Sqr(Sqr(0)) = req {
+            futures::future::pending::<()>().await;
+        }
+        ComputeService.handle_rpc_request(req, chan).await
+    });
+
+    // every connection has its own address
+    let greedy = RpcClient::<ComputeService, _>::new(TcpConnector::new(addr));
+    let mut greedy_calls = (0..32)
+        .map(|_| {
+            let greedy = greedy.clone();
+            AbortOnDropHandle::new(tokio::spawn(async move { greedy.rpc(Sqr(0)).await }))
+        })
+        .collect::<FuturesUnordered<_>>();
+    // two channels run and eight wait, all further ones are closed by the server
+    for _ in 0..22 {
+        let res = greedy_calls
+            .next()
+            .await
+            .expect("greedy calls never complete")?;
+        assert!(res.is_err());
+    }
+
+    let client = RpcClient::<ComputeService, _>::new(TcpConnector::new(addr));
+    for i in 1..10 {
+        let res = tokio::time::timeout(Duration::from_secs(1), client.rpc(Sqr(i))).await??;
+        assert_eq!(res, SqrResponse(i as u128 * i as u128));
+    }
+    Ok(())
+}
+
+/// a proxy forwards raw frames between two connections, without deserializing them
+#[tokio::test]
+async fn tcp_raw_proxy() -> anyhow::Result<()> {
+    use futures::StreamExt;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let backend = TcpListener::bind("127.0.0.1:0".parse()?).await?;
+    let LocalAddr::Socket(backend_addr) = backend.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    let _server_handle = ComputeService::server(RpcServer::new(backend));
+    let upstream = RpcClient::<ComputeService, _>::new(TcpConnector::new(backend_addr));
+
+    let proxy = TcpListener::bind("127.0.0.1:0".parse()?).await?;
+    let LocalAddr::Socket(proxy_addr) = proxy.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    let proxy = RpcServer::<ComputeService, _>::new(proxy);
+    let _proxy_handle = AbortOnDropHandle::new(tokio::spawn(async move {
+        loop {
+            let Ok(accepting) = proxy.accept().await else {
+                continue;
+            };
+            let upstream = upstream.clone();
+            tokio::spawn(async move {
+                let (send, recv) = accepting.into_raw()?;
+                let (up_send, up_recv) = upstream.open_raw().await?;
+                let requests = recv.forward(up_send);
+                let responses = up_recv.forward(send);
+                futures::future::try_join(requests, responses).await?;
+                anyhow::Ok(())
+            });
+        }
+    }));
+
+    smoke_test(TcpConnector::new(proxy_addr)).await?;
+    Ok(())
+}
+
+/// a raw channel is closed if the peer exceeded the rate limit of the server
+#[tokio::test]
+async fn tcp_raw_rate_limit() -> anyhow::Result<()> {
+    use futures::SinkExt;
+    use quic_rpc::server::{RateLimit, RpcServerError};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let listener = TcpListener::bind("127.0.0.1:0".parse()?).await?;
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    let server = RpcServer::<ComputeService, _>::new(listener)
+        .with_rate_limit(RateLimit::new(1, Duration::from_secs(3600)), |_| Some(()));
+    let client = RpcClient::<ComputeService, _>::new(TcpConnector::new(addr));
+    for admitted in [true, false] {
+        let (mut send, _recv) = client.open_raw().await?;
+        // the substream shows up on the server with its first frame
+        send.send(bytes::Bytes::from_static(b"request")).await?;
+        let res = server.accept().await?.into_raw();
+        if admitted {
+            assert!(res.is_ok());
+        } else {
+            assert!(matches!(res, Err(RpcServerError::RateLimited)));
+        }
+    }
+    Ok(())
+}
+
+/// dropping the send side half-closes a substream, like a quinn stream
+#[tokio::test]
+async fn tcp_half_close() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let listener = TcpListener::<u64, u64>::bind("127.0.0.1:0".parse()?).await?;
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    util::check_half_close(TcpConnector::new(addr), listener).await
+}
+
+/// dropping both sides resets a substream, like a quinn stream
+#[tokio::test]
+async fn tcp_reset() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let listener = TcpListener::<u64, u64>::bind("127.0.0.1:0".parse()?).await?;
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    util::check_reset(TcpConnector::new(addr), listener).await
+}
+
+/// a server that stops reading updates resets the substream, which the client sees as a
+/// closed server side, like with the flume transport
+#[tokio::test]
+async fn tcp_client_streaming_early_response() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let listener = TcpListener::bind("127.0.0.1:0".parse()?).await?;
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    early_response_test(listener, TcpConnector::new(addr)).await
+}
+
+/// a handler that panics drops the substream, which the client sees as an early close
+///
+/// yamux reports a reset substream as the end of the stream, so this is not a reset for
+/// the client, see `tcp_client_streaming_early_response` for what the reset does.
+#[tokio::test]
+async fn tcp_handler_panic() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let listener = TcpListener::bind("127.0.0.1:0".parse()?).await?;
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    let server = RpcServer::<ComputeService, _>::new(listener);
+    let _server_handle = server.spawn_accept_loop(|req, _chan| async move {
+        if let ComputeRequest::Sqr(Sqr(0)) = req {
+            panic!("handler panicked");
+        }
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<ComputeService, _>::new(TcpConnector::new(addr));
+    let res = tokio::time::timeout(Duration::from_secs(5), client.rpc(Sqr(0))).await?;
+    assert!(matches!(res, Err(CallError::EarlyClose)), "{res:?}");
+    Ok(())
+}
+
+/// the errors of the individual patterns convert to a [CallError], where a receive error
+/// of a reset substream counts as an early close, like in the client methods
+#[test]
+fn tcp_pattern_errors_into_call_error() {
+    use std::io;
+
+    use quic_rpc::pattern::{
+        bidi_streaming, client_streaming, fallible, rpc, server_streaming, try_server_streaming,
+    };
+
+    type C = TcpConnector<ComputeResponse, ComputeRequest>;
+    let reset = || io::Error::from(io::ErrorKind::ConnectionReset);
+    let other = || io::Error::from(io::ErrorKind::InvalidData);
+
+    let e: CallError<C> = rpc::Error::<C>::RecvError(reset()).into();
+    assert!(matches!(e, CallError::EarlyClose), "{e:?}");
+    let e: CallError<C> = rpc::Error::<C>::RecvError(other()).into();
+    assert!(matches!(e, CallError::Recv(_)), "{e:?}");
+    let e: CallError<C> = rpc::Error::<C>::Timeout { sent: true }.into();
+    assert!(matches!(e, CallError::Timeout { sent: true }), "{e:?}");
+
+    let e: CallError<C> = server_streaming::Error::<C>::Open(other()).into();
+    assert!(matches!(e, CallError::Open(_)), "{e:?}");
+    let e: CallError<C> = server_streaming::ItemError::<C>::RecvError(reset()).into();
+    assert!(matches!(e, CallError::EarlyClose), "{e:?}");
+    let e: CallError<C> = server_streaming::ItemError::<C>::Timeout.into();
+    assert!(matches!(e, CallError::Timeout { sent: true }), "{e:?}");
+
+    let e: CallError<C> = client_streaming::Error::<C>::Send(other()).into();
+    assert!(matches!(e, CallError::Send(_)), "{e:?}");
+    let e: CallError<C> = client_streaming::ItemError::<C>::RecvError(reset()).into();
+    assert!(matches!(e, CallError::EarlyClose), "{e:?}");
+
+    let e: CallError<C> = bidi_streaming::ItemError::<C>::RecvError(reset()).into();
+    assert!(matches!(e, CallError::EarlyClose), "{e:?}");
+    let e: CallError<C> = bidi_streaming::ItemError::<C>::DowncastError.into();
+    assert!(matches!(e, CallError::Downcast), "{e:?}");
+
+    let e: CallError<C, String> = fallible::Error::<C, String>::Recv(reset()).into();
+    assert!(matches!(e, CallError::EarlyClose), "{e:?}");
+    let e: CallError<C, String> = fallible::Error::<C, String>::Application("no".into()).into();
+    assert!(matches!(e, CallError::App(cause) if cause == "no"));
+
+    let e: CallError<C, String> = try_server_streaming::Error::<C, String>::EarlyClose.into();
+    assert!(matches!(e, CallError::EarlyClose), "{e:?}");
+    let e: CallError<C, String> =
+        try_server_streaming::ItemError::<C, String>::Recv(reset()).into();
+    assert!(matches!(e, CallError::EarlyClose), "{e:?}");
+}
+
+/// the connections of a tcp listener are reported to a lifecycle observer
+#[tokio::test]
+async fn tcp_lifecycle_connections() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let listener = TcpListener::bind("127.0.0.1:0".parse()?).await?;
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    let (events, mut recv) = tokio::sync::mpsc::unbounded_channel();
+    let server = RpcServer::new(listener).with_lifecycle_observer(move |event| {
+        if let LifecycleEvent::ConnectionOpened { .. } | LifecycleEvent::ConnectionClosed { .. } =
+            event
+        {
+            events.send(event).ok();
+        }
+    });
+    let _server_handle = ComputeService::server(server);
+    let connector = TcpConnector::new(addr);
+    let client = RpcClient::<ComputeService, _>::new(connector);
+    assert_eq!(client.rpc(Sqr(3)).await?, SqrResponse(9));
+
+    let timeout = Duration::from_secs(5);
+    let event = tokio::time::timeout(timeout, recv.recv()).await?;
+    let Some(LifecycleEvent::ConnectionOpened { connection, .. }) = event else {
+        panic!("connection was not opened");
+    };
+    // dropping the client closes its connection
+    drop(client);
+    match tokio::time::timeout(timeout, recv.recv()).await? {
+        Some(LifecycleEvent::ConnectionClosed {
+            connection: closed, ..
+        }) => assert_eq!(closed, connection),
+        event => panic!("unexpected event {event:?}"),
+    }
+    Ok(())
+}
+
+/// a client whose token is rejected never reaches a handler, an accepted one has an identity
+#[tokio::test]
+async fn tcp_auth_handshake() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let verify = ServerAuth::new(|token: AuthToken| async move {
+        if token.0 == b"secret" {
+            Ok(Identity("alice".into()))
+        } else {
+            Err(AuthError::new("unknown token"))
+        }
+    });
+    let listener = TcpListener::<ComputeRequest, ComputeResponse>::builder()
+        .auth(verify)
+        .bind("127.0.0.1:0".parse()?)
+        .await?;
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    let (identities, mut identities_recv) = tokio::sync::mpsc::unbounded_channel();
+    let server = RpcServer::<ComputeService, _>::new(listener);
+    let _server_handle = server.spawn_accept_loop(move |req, chan| {
+        let identities = identities.clone();
+        async move {
+            let identity = chan.remote_info().and_then(|info| info.identity.clone());
+            identities.send(identity).ok();
+            ComputeService.handle_rpc_request(req, chan).await
+        }
+    });
+
+    let token =
+        |token: &'static [u8]| ClientAuth::new(move || async move { AuthToken(token.to_vec()) });
+    let rejected = TcpConnector::<ComputeResponse, ComputeRequest>::builder(addr)
+        .auth(token(b"wrong"))
+        .build();
+    let client = RpcClient::<ComputeService, _>::new(rejected);
+    let res = client.rpc(Sqr(2)).await;
+    match &res {
+        Err(CallError::Open(cause)) => {
+            assert_eq!(auth_error(cause), Some(&AuthError::new("unknown token")))
+        }
+        _ => panic!("{res:?}"),
+    }
+
+    let accepted = TcpConnector::<ComputeResponse, ComputeRequest>::builder(addr)
+        .auth(token(b"secret"))
+        .build();
+    let client = RpcClient::<ComputeService, _>::new(accepted);
+    assert_eq!(client.rpc(Sqr(2)).await?, SqrResponse(4));
+    // only the accepted client got to the handler
+    let identity = identities_recv.recv().await;
+    assert_eq!(identity, Some(Some(Identity("alice".into()))));
+    assert!(identities_recv.try_recv().is_err());
+    Ok(())
+}
+
+/// opening a channel fails if the server never answers the handshake
+#[tokio::test]
+async fn tcp_auth_client_timeout() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    // a server that accepts connections, but never runs the handshake
+    let silent = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = silent.local_addr()?;
+    let _server_handle = AbortOnDropHandle::new(tokio::spawn(async move {
+        let mut connections = Vec::new();
+        while let Ok((stream, _)) = silent.accept().await {
+            connections.push(stream);
+        }
+    }));
+
+    let auth = ClientAuth::new(|| async { AuthToken(b"secret".to_vec()) })
+        .timeout(Duration::from_millis(100));
+    let connector = TcpConnector::<ComputeResponse, ComputeRequest>::builder(addr)
+        .auth(auth)
+        .build();
+    let client = RpcClient::<ComputeService, _>::new(connector);
+    let res = tokio::time::timeout(Duration::from_secs(5), client.rpc(Sqr(2))).await?;
+    match &res {
+        Err(CallError::Open(cause)) => assert_eq!(cause.kind(), std::io::ErrorKind::TimedOut),
+        _ => panic!("{res:?}"),
+    }
+    Ok(())
+}
+
+impl ServiceTag for ComputeService {
+    const TAG: u16 = 1;
+}
+
+#[derive(Debug, Clone)]
+struct EchoService;
+
+impl Service for EchoService {
+    type Req = Echo;
+    type Res = String;
+}
+
+impl ServiceTag for EchoService {
+    const TAG: u16 = 2;
+}
+
+/// a service that the server does not route
+#[derive(Debug, Clone)]
+struct UnroutedService;
+
+impl Service for UnroutedService {
+    type Req = Echo;
+    type Res = String;
+}
+
+impl ServiceTag for UnroutedService {
+    const TAG: u16 = 3;
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Echo(String);
+
+declare_rpc!(EchoService, Echo, String);
+declare_rpc!(UnroutedService, Echo, String);
+
+/// two services with their own servers share one connection
+#[tokio::test]
+async fn tcp_multiplex() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let listener = TcpListener::<Vec<u8>, Vec<u8>>::bind("127.0.0.1:0".parse()?).await?;
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    let listener = MultiplexListener::new(listener);
+    let compute = listener.route::<ComputeService>()?;
+    let echo = listener.route::<EchoService>()?;
+    // the tag of a routed service can not be used again
+    let res = listener.route::<ComputeService>();
+    assert!(matches!(&res, Err(err) if err.tag == 1), "{res:?}");
+    let _compute_handle = ComputeService::server(RpcServer::new(compute));
+    let echo = RpcServer::<EchoService, _>::new(echo);
+    let _echo_handle = echo.spawn_accept_loop(|req, chan| {
+        chan.rpc(req, EchoService, |_, Echo(text)| async move { text })
+    });
+
+    let connector = MultiplexConnector::new(TcpConnector::<Vec<u8>, Vec<u8>>::new(addr));
+    smoke_test(connector.route::<ComputeService>()).await?;
+    let client = RpcClient::<EchoService, _>::new(connector.route::<EchoService>());
+    assert_eq!(client.rpc(Echo("hello".into())).await?, "hello");
+    // a channel for a service without a route is dropped
+    let client = RpcClient::<UnroutedService, _>::new(connector.route::<UnroutedService>());
+    let res = client.rpc(Echo("hello".into())).await;
+    assert!(matches!(res, Err(CallError::EarlyClose)), "{res:?}");
+    Ok(())
+}
+
+/// channels for a service whose listener is not accepting are kept up to a limit
+#[tokio::test]
+async fn tcp_multiplex_route_buffer() -> anyhow::Result<()> {
+    use futures::{stream::FuturesUnordered, SinkExt, StreamExt};
+    use quic_rpc::transport::Connector;
+
+    tracing_subscriber::fmt::try_init().ok();
+    let listener = TcpListener::<Vec<u8>, Vec<u8>>::bind("127.0.0.1:0".parse()?).await?;
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        panic!("not a socket address");
+    };
+    let listener = MultiplexListener::new(listener);
+    let compute = listener.route::<ComputeService>()?;
+    let echo = listener.route::<EchoService>()?;
+    // only the compute listener accepts, and hands the echo channels to their route
+    let _compute_handle = AbortOnDropHandle::new(tokio::spawn(async move {
+        compute.accept().await.ok();
+    }));
+
+    let connector = MultiplexConnector::new(TcpConnector::<Vec<u8>, Vec<u8>>::new(addr));
+    let connector = connector.route::<EchoService>();
+    let mut channels = Vec::new();
+    for i in 0..20 {
+        let (mut send, recv) = connector.open().await?;
+        send.send(Echo(i.to_string())).await?;
+        channels.push((send, recv));
+    }
+    // 16 channels are kept for the echo listener, the others are dropped
+    let mut ended = channels
+        .iter_mut()
+        .enumerate()
+        .map(|(i, (_, recv))| async move {
+            recv.next().await;
+            i
+        })
+        .collect::<FuturesUnordered<_>>();
+    let mut dropped = Vec::new();
+    for _ in 0..4 {
+        let i = tokio::time::timeout(Duration::from_secs(5), ended.next()).await?;
+        dropped.extend(i);
+    }
+    drop(ended);
+
+    let mut kept = Vec::new();
+    for _ in 0..16 {
+        let (_send, mut recv) =
+            tokio::time::timeout(Duration::from_secs(5), echo.accept()).await??;
+        let Some(Ok(Echo(text))) = recv.next().await else {
+            panic!("no first message");
+        };
+        kept.push(text.parse::<usize>()?);
+    }
+    kept.extend(dropped);
+    kept.sort();
+    assert_eq!(kept, (0..20).collect::<Vec<_>>());
+    Ok(())
+}
@@ -2,8 +2,12 @@
 use derive_more::{From, TryInto};
 use futures_lite::{Stream, StreamExt};
 use quic_rpc::{
+    client::CallError,
     message::Msg,
-    pattern::try_server_streaming::{StreamCreated, TryServerStreaming, TryServerStreamingMsg},
+    pattern::{
+        fallible::{Fallible, FallibleMsg},
+        try_server_streaming::{StreamCreated, TryServerStreaming, TryServerStreamingMsg},
+    },
     server::RpcServerError,
     transport::flume,
     RpcClient, RpcServer, Service,
@@ -33,16 +37,33 @@ impl TryServerStreamingMsg<TryService> for StreamN {
     type CreateError = String;
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Div(u64, u64);
+
+impl Msg<TryService> for Div {
+    type Pattern = Fallible;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DivisionByZero;
+
+impl FallibleMsg<TryService> for Div {
+    type Response = u64;
+    type AppError = DivisionByZero;
+}
+
 /// request enum
 #[derive(Debug, Serialize, Deserialize, From, TryInto)]
 pub enum TryRequest {
     StreamN(StreamN),
+    Div(Div),
 }
 
 #[derive(Debug, Serialize, Deserialize, From, TryInto, Clone)]
 pub enum TryResponse {
     StreamN(std::result::Result<u64, String>),
     StreamNError(std::result::Result<StreamCreated, String>),
+    Div(std::result::Result<u64, DivisionByZero>),
 }
 
 #[derive(Clone)]
@@ -67,6 +88,10 @@ impl Handler {
         };
         Ok(stream)
     }
+
+    async fn div(self, req: Div) -> std::result::Result<u64, DivisionByZero> {
+        req.0.checked_div(req.1).ok_or(DivisionByZero)
+    }
 }
 
 #[tokio::test]
@@ -84,6 +109,9 @@ async fn try_server_streaming() -> anyhow::Result<()> {
                     chan.try_server_streaming(req, handler, Handler::try_stream_n)
                         .await?;
                 }
+                TryRequest::Div(req) => {
+                    chan.rpc_fallible(req, handler, Handler::div).await?;
+                }
             }
         }
         #[allow(unreachable_code)]
@@ -101,3 +129,28 @@ async fn try_server_streaming() -> anyhow::Result<()> {
     }
     Ok(())
 }
+
+#[tokio::test]
+async fn rpc_fallible() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (server, client) = flume::channel(1);
+
+    let server = RpcServer::<TryService, _>::new(server);
+    let _server_handle = tokio::task::spawn(async move {
+        loop {
+            let (req, chan) = server.accept().await?.read_first().await?;
+            if let TryRequest::Div(req) = req {
+                chan.rpc_fallible(req, Handler, Handler::div).await?;
+            }
+        }
+        #[allow(unreachable_code)]
+        anyhow::Ok(())
+    });
+    let client = RpcClient::<TryService, _>::new(client);
+    assert_eq!(client.rpc_fallible(Div(6, 3)).await?, 3);
+    match client.rpc_fallible(Div(6, 0)).await {
+        Err(CallError::App(DivisionByZero)) => {}
+        res => panic!("unexpected result {res:?}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,108 @@
+#![cfg(all(feature = "uds-transport", unix))]
+use quic_rpc::{
+    client::CallError,
+    transport::{
+        auth::{auth_error, AuthError, AuthToken, ClientAuth, Identity, ServerAuth},
+        uds::{UdsConnector, UdsListener},
+        Listener, LocalAddr,
+    },
+    RpcClient, RpcServer,
+};
+
+mod math;
+use math::*;
+
+#[tokio::test]
+async fn uds_channel_smoke() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("rpc.sock");
+    let listener = UdsListener::bind(&path)?;
+    assert!(matches!(&listener.local_addr()[0], LocalAddr::Path(p) if *p == path));
+    let _server_handle = ComputeService::server(RpcServer::new(listener));
+    smoke_test(UdsConnector::new(path)).await?;
+    Ok(())
+}
+
+/// a client whose token is rejected never reaches a handler, an accepted one has an identity
+#[tokio::test]
+async fn uds_auth_handshake() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("rpc.sock");
+    let verify = ServerAuth::new(|token: AuthToken| async move {
+        if token.0 == b"secret" {
+            Ok(Identity("alice".into()))
+        } else {
+            Err(AuthError::new("unknown token"))
+        }
+    });
+    let listener = UdsListener::<ComputeRequest, ComputeResponse>::bind(&path)?.with_auth(verify);
+    let (identities, mut identities_recv) = tokio::sync::mpsc::unbounded_channel();
+    let server = RpcServer::<ComputeService, _>::new(listener);
+    let _server_handle = server.spawn_accept_loop(move |req, chan| {
+        let identities = identities.clone();
+        async move {
+            let identity = chan.remote_info().and_then(|info| info.identity.clone());
+            identities.send(identity).ok();
+            ComputeService.handle_rpc_request(req, chan).await
+        }
+    });
+
+    let token =
+        |token: &'static [u8]| ClientAuth::new(move || async move { AuthToken(token.to_vec()) });
+    let rejected =
+        UdsConnector::<ComputeResponse, ComputeRequest>::new(&path).with_auth(token(b"wrong"));
+    let client = RpcClient::<ComputeService, _>::new(rejected);
+    let res = client.rpc(Sqr(2)).await;
+    match &res {
+        Err(CallError::Open(cause)) => {
+            assert_eq!(auth_error(cause), Some(&AuthError::new("unknown token")))
+        }
+        _ => panic!("{res:?}"),
+    }
+
+    let accepted =
+        UdsConnector::<ComputeResponse, ComputeRequest>::new(&path).with_auth(token(b"secret"));
+    let client = RpcClient::<ComputeService, _>::new(accepted);
+    assert_eq!(client.rpc(Sqr(2)).await?, SqrResponse(4));
+    // only the accepted client got to the handler
+    let identity = identities_recv.recv().await;
+    assert_eq!(identity, Some(Some(Identity("alice".into()))));
+    assert!(identities_recv.try_recv().is_err());
+    Ok(())
+}
+
+/// a handler that drops a channel with an update it did not receive resets the connection,
+/// which the client sees as an early close
+#[tokio::test]
+async fn uds_reset_early_close() -> anyhow::Result<()> {
+    use futures::{SinkExt, StreamExt};
+
+    tracing_subscriber::fmt::try_init().ok();
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("rpc.sock");
+    let listener = UdsListener::<ComputeRequest, ComputeResponse>::bind(&path)?;
+    let (started, started_recv) = tokio::sync::oneshot::channel();
+    let (sent, sent_recv) = tokio::sync::oneshot::channel::<()>();
+    let server = RpcServer::<ComputeService, _>::new(listener);
+    let server_handle = tokio::spawn(async move {
+        let (req, chan) = server.accept().await?.read_first().await?;
+        assert!(matches!(req, ComputeRequest::Multiply(_)), "{req:?}");
+        started.send(()).ok();
+        // the update is still waiting in the socket when the channel is dropped
+        sent_recv.await?;
+        drop(chan);
+        anyhow::Ok(())
+    });
+
+    let client = RpcClient::<ComputeService, _>::new(UdsConnector::new(&path));
+    let (mut updates, mut responses) = client.bidi(Multiply(2)).await?;
+    started_recv.await?;
+    updates.send(MultiplyUpdate(1)).await?;
+    sent.send(()).ok();
+    server_handle.await??;
+    let res = tokio::time::timeout(std::time::Duration::from_secs(5), responses.next()).await?;
+    assert!(matches!(res, Some(Err(CallError::EarlyClose))), "{res:?}");
+    Ok(())
+}
@@ -1,5 +1,8 @@
 use anyhow::Context;
-use quic_rpc::{server::RpcServerError, transport::Connector};
+use quic_rpc::{
+    server::RpcServerError,
+    transport::{Connector, Listener},
+};
 
 #[allow(unused)]
 pub async fn check_termination_anyhow<C: Connector>(
@@ -18,3 +21,71 @@ pub async fn check_termination_anyhow<C: Connector>(
     }
     Ok(())
 }
+
+/// The transport errors are [quic_rpc::RpcError]s, which convert into anyhow but
+/// don't necessarily implement [std::error::Error].
+fn into_anyhow(error: impl Into<anyhow::Error>) -> anyhow::Error {
+    error.into()
+}
+
+/// Dropping the send side of a channel half-closes it: the remote receives the end of
+/// the stream, and can still send in the other direction.
+#[allow(unused)]
+pub async fn check_half_close<C, L>(connector: C, listener: L) -> anyhow::Result<()>
+where
+    C: Connector<In = u64, Out = u64>,
+    L: Listener<In = u64, Out = u64>,
+{
+    use futures::{SinkExt, StreamExt};
+
+    let (mut client_send, mut client_recv) = connector.open().await.map_err(into_anyhow)?;
+    client_send.send(1).await.map_err(into_anyhow)?;
+    drop(client_send);
+    let (mut server_send, mut server_recv) = listener.accept().await.map_err(into_anyhow)?;
+    assert_eq!(
+        server_recv.next().await.transpose().map_err(into_anyhow)?,
+        Some(1)
+    );
+    assert!(server_recv.next().await.is_none());
+    server_send.send(2).await.map_err(into_anyhow)?;
+    drop(server_send);
+    assert_eq!(
+        client_recv.next().await.transpose().map_err(into_anyhow)?,
+        Some(2)
+    );
+    assert!(client_recv.next().await.is_none());
+    Ok(())
+}
+
+/// Dropping both sides of a channel before the remote finished sending resets it, so
+/// sending on the remote fails with an error for which `is_remote_closed` is true.
+#[allow(unused)]
+pub async fn check_reset<C, L>(connector: C, listener: L) -> anyhow::Result<()>
+where
+    C: Connector<In = u64, Out = u64>,
+    L: Listener<In = u64, Out = u64>,
+{
+    use futures::{SinkExt, StreamExt};
+
+    let (mut client_send, client_recv) = connector.open().await.map_err(into_anyhow)?;
+    client_send.send(1).await.map_err(into_anyhow)?;
+    let (mut server_send, mut server_recv) = listener.accept().await.map_err(into_anyhow)?;
+    assert_eq!(
+        server_recv.next().await.transpose().map_err(into_anyhow)?,
+        Some(1)
+    );
+    drop(client_send);
+    drop(client_recv);
+    // sends succeed until the reset arrives
+    let error = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+        loop {
+            if let Err(cause) = server_send.send(2).await {
+                break cause;
+            }
+            tokio::task::yield_now().await;
+        }
+    })
+    .await?;
+    assert!(L::is_remote_closed(&error), "{error:?}");
+    Ok(())
+}
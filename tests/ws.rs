@@ -0,0 +1,76 @@
+#![cfg(feature = "ws-transport")]
+use futures::SinkExt;
+use futures_lite::StreamExt;
+use quic_rpc::{
+    transport::{
+        ws::{WsConnector, WsListener},
+        Connector, Listener, LocalAddr,
+    },
+    RpcClient, RpcServer,
+};
+use tokio_util::task::AbortOnDropHandle;
+
+mod math;
+use math::*;
+
+async fn run_server() -> anyhow::Result<(String, AbortOnDropHandle<()>)> {
+    let listener = WsListener::bind("127.0.0.1:0").await?;
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        anyhow::bail!("listener not bound to a socket");
+    };
+    let server = RpcServer::new(listener);
+    Ok((format!("ws://{addr}"), ComputeService::server(server)))
+}
+
+#[tokio::test]
+async fn ws_channel_smoke() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (url, _server_handle) = run_server().await?;
+    let client = WsConnector::new(url);
+    smoke_test(client).await?;
+    Ok(())
+}
+
+/// A server that is done with a streaming response closes the websocket cleanly,
+/// which must end the stream on the client with `None` instead of an error.
+#[tokio::test]
+async fn ws_server_streaming_clean_close() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let (url, _server_handle) = run_server().await?;
+    let client = RpcClient::<ComputeService, _>::new(WsConnector::new(url));
+    for _ in 0..3 {
+        let mut s = client.server_streaming(Fibonacci(5)).await?;
+        let mut items = Vec::new();
+        while let Some(item) = s.next().await {
+            items.push(item?.0);
+        }
+        assert_eq!(items, vec![0, 1, 1, 2, 3]);
+    }
+    Ok(())
+}
+
+/// Messages that serialize to zero bytes are data, only the close frame of the sender
+/// ends the stream, while the other direction stays open.
+#[tokio::test]
+async fn ws_zero_byte_messages() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::try_init().ok();
+    let listener = WsListener::<(), ()>::bind("127.0.0.1:0").await?;
+    let LocalAddr::Socket(addr) = listener.local_addr()[0] else {
+        anyhow::bail!("listener not bound to a socket");
+    };
+    let connector = WsConnector::<(), ()>::new(format!("ws://{addr}"));
+    let (mut client_send, mut client_recv) = connector.open().await?;
+    let (mut server_send, mut server_recv) = listener.accept().await?;
+    client_send.send(()).await?;
+    client_send.send(()).await?;
+    drop(client_send);
+    assert!(matches!(server_recv.next().await, Some(Ok(()))));
+    assert!(matches!(server_recv.next().await, Some(Ok(()))));
+    assert!(server_recv.next().await.is_none());
+
+    server_send.send(()).await?;
+    drop(server_send);
+    assert!(matches!(client_recv.next().await, Some(Ok(()))));
+    assert!(client_recv.next().await.is_none());
+    Ok(())
+}